@@ -22,10 +22,14 @@ __author__ = "James Henry <jamesrahenry@henrynet.ca>"
 # Import Rust core components (will be available after maturin build)
 try:
     from yori._core import PolicyEngine, Cache
+    # In-process Rust proxy (start/stop, runtime mode, listener status);
+    # aliased to avoid clashing with the FastAPI ProxyServer below
+    from yori._core import ProxyServer as CoreProxyServer
 except ImportError:
     # Rust module not built yet - provide stubs for development
     PolicyEngine = None  # type: ignore
     Cache = None  # type: ignore
+    CoreProxyServer = None  # type: ignore
 
 # Import Python components
 from yori.config import YoriConfig
@@ -37,6 +41,7 @@ from yori.consent import ConsentValidator, validate_enforcement_consent
 __all__ = [
     "PolicyEngine",
     "Cache",
+    "CoreProxyServer",
     "YoriConfig",
     "ProxyServer",
     "EnforcementDecision",
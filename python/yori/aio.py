@@ -0,0 +1,86 @@
+"""
+Awaitable wrappers around the Rust core for the FastAPI event loop
+
+The Rust bindings release the GIL while they work (policy evaluation,
+reloads, audit queries), so running them in a worker thread keeps the
+event loop serving other requests instead of stalling until they return.
+
+Example:
+    >>> from yori import aio
+    >>> result = await aio.evaluate(engine, {"user": "alice", "endpoint": "api.openai.com"})
+"""
+
+import asyncio
+from typing import Any, Dict
+
+
+async def evaluate(engine: Any, input_data: Dict[str, Any], explain: bool = False) -> Any:
+    """
+    Evaluate a policy input without blocking the event loop
+
+    Args:
+        engine: yori._core.PolicyEngine
+        input_data: Policy input dictionary
+        explain: Include the evaluation trace
+
+    Returns:
+        yori._core.PolicyResult, as from PolicyEngine.evaluate
+    """
+    return await asyncio.to_thread(engine.evaluate, input_data, explain)
+
+
+async def load_policies(engine: Any) -> int:
+    """
+    Reload policy files without blocking the event loop
+
+    Args:
+        engine: yori._core.PolicyEngine
+
+    Returns:
+        Number of policies loaded
+    """
+    return await asyncio.to_thread(engine.load_policies)
+
+
+async def query_audit(logger: Any, **filters: Any) -> Dict[str, Any]:
+    """
+    Fetch a page of audit events without blocking the event loop
+
+    Args:
+        logger: yori._core.AuditLogger
+        **filters: Keyword arguments of AuditLogger.query
+
+    Returns:
+        Page dictionary with events, total, limit and offset
+    """
+    return await asyncio.to_thread(lambda: logger.query(**filters))
+
+
+async def count_audit(logger: Any, **filters: Any) -> int:
+    """
+    Count audit events without blocking the event loop
+
+    Args:
+        logger: yori._core.AuditLogger
+        **filters: Keyword arguments of AuditLogger.count
+
+    Returns:
+        Number of matching events
+    """
+    return await asyncio.to_thread(lambda: logger.count(**filters))
+
+
+async def stop_proxy(proxy: Any) -> bool:
+    """
+    Stop the Rust proxy, waiting for in-flight requests to drain
+
+    Args:
+        proxy: yori._core.ProxyServer
+
+    Returns:
+        Whether the proxy was running
+    """
+    return await asyncio.to_thread(proxy.stop)
+
+
+__all__ = ["evaluate", "load_policies", "query_audit", "count_audit", "stop_proxy"]
@@ -5,6 +5,7 @@ Enhanced audit logging for Phase 2 enforcement mode.
 Captures blocks, overrides, allowlist bypasses, and enforcement events.
 """
 
+import hashlib
 import json
 import sqlite3
 from datetime import datetime
@@ -14,18 +15,48 @@ import logging
 
 logger = logging.getLogger(__name__)
 
+# prev_hash of the first event in a hash chain (matches yori_core)
+GENESIS_HASH = "0" * 64
+
+# Columns holding the chain itself, left out of the hashed content
+_CHAIN_COLUMNS = ("prev_hash", "event_hash")
+
+
+def event_hash(prev_hash: str, record: Dict[str, Any]) -> str:
+    """
+    Hash of one audit event given the previous event's hash.
+
+    Must stay byte-for-byte identical to yori_core's integrity module:
+    SHA-256 over the previous hash, then one ``name=json`` line per non-NULL
+    column sorted by name (blobs as hex), chain columns excluded.
+    """
+    digest = hashlib.sha256()
+    digest.update(prev_hash.encode() + b"\n")
+    for name in sorted(record):
+        value = record[name]
+        if name in _CHAIN_COLUMNS or value is None:
+            continue
+        if isinstance(value, bytes):
+            value = value.hex()
+        line = f"{name}={json.dumps(value, ensure_ascii=False)}\n"
+        digest.update(line.encode())
+    return digest.hexdigest()
+
 
 class EnforcementAuditLogger:
     """Handles enforcement-specific audit logging to SQLite"""
 
-    def __init__(self, database_path: Path):
+    def __init__(self, database_path: Path, hash_chain: bool = False):
         """
         Initialize enforcement audit logger.
 
         Args:
             database_path: Path to SQLite audit database
+            hash_chain: Chain each event to the previous one with a SHA-256
+                hash, as yori_core does (see AuditLogger.verify_integrity)
         """
         self.database_path = database_path
+        self.hash_chain = hash_chain
         self._ensure_database_exists()
 
     def _ensure_database_exists(self):
@@ -38,6 +69,26 @@ class EnforcementAuditLogger:
         conn.row_factory = sqlite3.Row
         return conn
 
+    def _chain_event(self, conn: sqlite3.Connection, event_id: int):
+        """Link a just-inserted event into the hash chain (same transaction)"""
+        columns = {row["name"] for row in conn.execute("PRAGMA table_info(audit_events)")}
+        for column in _CHAIN_COLUMNS:
+            if column not in columns:
+                conn.execute(f"ALTER TABLE audit_events ADD COLUMN {column} TEXT")
+
+        previous = conn.execute(
+            "SELECT event_hash FROM audit_events "
+            "WHERE id < ? AND event_hash IS NOT NULL ORDER BY id DESC LIMIT 1",
+            (event_id,),
+        ).fetchone()
+        prev_hash = previous["event_hash"] if previous else GENESIS_HASH
+
+        row = conn.execute("SELECT * FROM audit_events WHERE id = ?", (event_id,)).fetchone()
+        conn.execute(
+            "UPDATE audit_events SET prev_hash = ?, event_hash = ? WHERE id = ?",
+            (prev_hash, event_hash(prev_hash, dict(row)), event_id),
+        )
+
     def log_enforcement_event(
         self,
         event_type: str,
@@ -117,8 +168,10 @@ class EnforcementAuditLogger:
                     request_id,
                 ),
             )
-            conn.commit()
             event_id = cursor.lastrowid
+            if self.hash_chain:
+                self._chain_event(conn, event_id)
+            conn.commit()
 
         logger.info(
             f"Enforcement event logged: {event_type} - {enforcement_action} "
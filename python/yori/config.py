@@ -5,7 +5,7 @@ Loads configuration from YAML files and provides type-safe access.
 """
 
 from pathlib import Path
-from typing import List, Literal, Optional
+from typing import List, Literal, Optional, Union
 from pydantic import BaseModel, Field
 import yaml
 
@@ -15,10 +15,28 @@ from yori.models import EnforcementConfig
 class EndpointConfig(BaseModel):
     """Configuration for an LLM endpoint"""
 
-    domain: str = Field(..., description="Domain name (e.g., api.openai.com)")
+    domain: str = Field(
+        ..., description="Domain name or wildcard pattern (e.g., api.openai.com, *.openai.azure.com)"
+    )
     enabled: bool = Field(True, description="Whether to intercept this endpoint")
 
 
+PiiDetector = Literal["email", "phone", "credit_card", "ssn", "address"]
+
+
+class PiiRule(BaseModel):
+    """A built-in PII detector with overrides, or a custom pattern"""
+
+    name: str = Field(..., description="Built-in detector, or the custom rule's name")
+    pattern: Optional[str] = Field(
+        default=None, description="Regular expression (required for custom rules)"
+    )
+    replacement: Optional[str] = Field(
+        default=None, description="Placeholder (default: the detector's, or [NAME])"
+    )
+    enabled: bool = Field(True, description="Whether the rule runs")
+
+
 class AuditConfig(BaseModel):
     """Audit logging configuration"""
 
@@ -26,6 +44,22 @@ class AuditConfig(BaseModel):
         default=Path("/var/db/yori/audit.db"), description="SQLite database path"
     )
     retention_days: int = Field(default=365, description="How long to keep audit logs")
+    encryption_key: Optional[Path] = Field(
+        default=None,
+        description="Vault key file used to encrypt the audit DB with SQLCipher (unset = plaintext)",
+    )
+    maintenance_window: str = Field(
+        default="03:00-05:00",
+        description="Local quiet hours (HH:MM-HH:MM) for vacuum, ANALYZE and WAL checkpoints",
+    )
+    redact_pii: List[Union[PiiDetector, PiiRule]] = Field(
+        default_factory=lambda: ["email", "phone", "credit_card", "ssn", "address"],
+        description="PII rules run on prompt previews before they are stored (empty = off)",
+    )
+    hash_chain: bool = Field(
+        default=False,
+        description="Chain audit events with SHA-256 hashes so edits and deletions can be detected",
+    )
 
 
 class PolicyConfig(BaseModel):
@@ -35,6 +69,29 @@ class PolicyConfig(BaseModel):
         default=Path("/usr/local/etc/yori/policies"), description="Directory containing .rego files"
     )
     default: str = Field(default="home_default.rego", description="Default policy file")
+    strategy: Literal["deny-overrides", "allow-overrides", "first-match", "priority-ordered"] = (
+        Field(
+            default="deny-overrides",
+            description="How decisions of several policy packages combine",
+        )
+    )
+    default_decision: Literal["allow", "deny"] = Field(
+        default="allow",
+        description="Decision for requests no policy decides (deny = fail closed in enforce mode)",
+    )
+    require_signed: bool = Field(
+        default=False,
+        description="Refuse policy bundles not signed by one of trusted_keys",
+    )
+    trusted_keys: List[str] = Field(
+        default_factory=list,
+        description="Hex-encoded ed25519 public keys of policy signers (see `yori policy sign`)",
+    )
+    bundle_url: Optional[str] = Field(
+        default=None,
+        description="OPA-style bundle (.tar.gz) to keep the policy directory in sync with",
+    )
+    bundle_interval_secs: int = Field(default=300, description="Time between bundle checks")
 
 
 class ProxyConfig(BaseModel):
@@ -63,7 +120,14 @@ class YoriConfig(BaseModel):
             EndpointConfig(domain="api.openai.com", enabled=True),
             EndpointConfig(domain="api.anthropic.com", enabled=True),
             EndpointConfig(domain="gemini.google.com", enabled=True),
+            EndpointConfig(domain="generativelanguage.googleapis.com", enabled=True),
             EndpointConfig(domain="api.mistral.ai", enabled=True),
+            EndpointConfig(domain="*.openai.azure.com", enabled=True),
+            EndpointConfig(domain="bedrock-runtime.*.amazonaws.com", enabled=True),
+            EndpointConfig(domain="openrouter.ai", enabled=True),
+            EndpointConfig(domain="api.groq.com", enabled=True),
+            EndpointConfig(domain="api.deepseek.com", enabled=True),
+            EndpointConfig(domain="api.together.xyz", enabled=True),
         ]
     )
 
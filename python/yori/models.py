@@ -5,7 +5,7 @@ Defines Pydantic models for configuration and runtime data structures.
 """
 
 from datetime import datetime, time
-from typing import List, Optional, Literal
+from typing import Any, List, Optional, Literal
 from pydantic import BaseModel, Field
 from ipaddress import IPv4Address, IPv6Address
 
@@ -79,6 +79,49 @@ class EnforcementConfig(BaseModel):
     admin_token_hash: Optional[str] = Field(None, description="SHA-256 hash of admin token for emergency override")
 
 
+class RuleLocation(BaseModel):
+    """Where a Rego rule that contributed to a decision is defined"""
+
+    package: str = Field(..., description="Rego package (e.g., 'yori.bedtime')")
+    rule: str = Field(..., description="Rule name (e.g., 'deny')")
+    file: str = Field(..., description="Policy file, relative to the policy directory")
+    line: int = Field(..., description="1-based line of the rule head")
+
+    @property
+    def anchor(self) -> str:
+        """'file:line' reference used for dashboard deep links"""
+        return f"{self.file}:{self.line}"
+
+
+class MatchedInput(BaseModel):
+    """An input field referenced by a rule that fired"""
+
+    path: str = Field(..., description="Dotted input path (e.g., 'input.user.group')")
+    value: Any = Field(None, description="Value of the field in the evaluated input")
+
+
+class EvaluatedRule(BaseModel):
+    """A decision rule evaluated while explaining a decision"""
+
+    package: str = Field(..., description="Rego package (e.g., 'yori.bedtime')")
+    rule: str = Field(..., description="Rule name (e.g., 'deny')")
+    value: Any = Field(None, description="Value the rule produced (None if undefined)")
+
+
+class PolicyTrace(BaseModel):
+    """How a decision was reached, from evaluate(..., explain=True)"""
+
+    evaluated: List[EvaluatedRule] = Field(
+        default_factory=list, description="Every decision rule evaluated, in order"
+    )
+    consulted_inputs: List[MatchedInput] = Field(
+        default_factory=list, description="Input fields the evaluated rules looked at"
+    )
+    prints: List[str] = Field(
+        default_factory=list, description="Output of print() calls, as 'file:line: message'"
+    )
+
+
 class PolicyResult(BaseModel):
     """Result from policy evaluation"""
 
@@ -86,6 +129,15 @@ class PolicyResult(BaseModel):
     policy_name: str = Field(..., description="Name of the policy that was evaluated")
     reason: Optional[str] = Field(None, description="Reason for the decision")
     violations: List[str] = Field(default_factory=list, description="List of policy violations")
+    rules: List[RuleLocation] = Field(
+        default_factory=list, description="Rules that produced the decision"
+    )
+    matched_inputs: List[MatchedInput] = Field(
+        default_factory=list, description="Input fields the deciding rules looked at"
+    )
+    trace: Optional[PolicyTrace] = Field(
+        None, description="Evaluation trace, when the decision was explained"
+    )
 
 
 class EnforcementDecision(BaseModel):
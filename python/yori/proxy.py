@@ -50,7 +50,9 @@ class ProxyServer:
         self.audit_logger: Optional[EnforcementAuditLogger] = None
         try:
             audit_db_path = self.config.audit.database
-            self.audit_logger = EnforcementAuditLogger(audit_db_path)
+            self.audit_logger = EnforcementAuditLogger(
+                audit_db_path, hash_chain=self.config.audit.hash_chain
+            )
             logger.info(f"Audit logger initialized: {audit_db_path}")
         except Exception as e:
             logger.error(f"Failed to initialize audit logger: {e}")
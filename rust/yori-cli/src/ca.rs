@@ -0,0 +1,108 @@
+//! Interception CA generation
+//!
+//! The proxy terminates TLS for LLM endpoints with leaf certificates signed
+//! by this CA, so every client device has to trust `ca.crt`. The key never
+//! leaves the router and is written with mode 0600.
+
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, Utc};
+use rcgen::{
+    date_time_ymd, BasicConstraints, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where the proxy and canary probe expect the CA
+pub const DEFAULT_CA_DIR: &str = "/usr/local/etc/yori/certs";
+
+/// Ten years; reissuing means re-trusting on every device
+pub const DEFAULT_VALIDITY_DAYS: i64 = 3650;
+
+/// Create `ca.crt` and `ca.key` in `out_dir`, returning their paths
+///
+/// Refuses to overwrite an existing CA unless `force` is set, since every
+/// client would have to trust the replacement.
+pub fn generate(out_dir: &Path, days: i64, force: bool) -> Result<(PathBuf, PathBuf)> {
+    if days <= 0 {
+        bail!("CA lifetime must be at least one day");
+    }
+    let cert_path = out_dir.join("ca.crt");
+    let key_path = out_dir.join("ca.key");
+    if !force && (cert_path.exists() || key_path.exists()) {
+        bail!(
+            "a CA already exists in {} (use --force to replace it)",
+            out_dir.display()
+        );
+    }
+
+    let mut params = CertificateParams::default();
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "YORI Local CA");
+    name.push(DnType::OrganizationName, "YORI");
+    params.distinguished_name = name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    let now = Utc::now();
+    let expires = now + Duration::days(days);
+    params.not_before = date_time_ymd(now.year(), now.month() as u8, now.day() as u8);
+    params.not_after = date_time_ymd(expires.year(), expires.month() as u8, expires.day() as u8);
+
+    let key = KeyPair::generate().context("failed to generate CA key")?;
+    let cert = params
+        .self_signed(&key)
+        .context("failed to sign CA certificate")?;
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+    write_private(&key_path, key.serialize_pem().as_bytes())?;
+    fs::write(&cert_path, cert.pem())
+        .with_context(|| format!("failed to write {}", cert_path.display()))?;
+    Ok((cert_path, key_path))
+}
+
+/// Write a file readable only by its owner
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_refuses_to_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = generate(dir.path(), 30, false).unwrap();
+        assert!(fs::read_to_string(&cert)
+            .unwrap()
+            .starts_with("-----BEGIN CERTIFICATE-----"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&key).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+        }
+
+        assert!(generate(dir.path(), 30, false).is_err());
+        assert!(generate(dir.path(), 30, true).is_ok());
+    }
+}
@@ -0,0 +1,362 @@
+//! `yori.conf` loading and validation
+//!
+//! Only the sections the CLI and core need are modelled; everything else
+//! (enforcement, allowlists, ...) is owned by the Python layer and ignored
+//! here. Defaults mirror `python/yori/config.py`.
+
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Locations searched when `--config` isn't given, in order
+const DEFAULT_PATHS: &[&str] = &[
+    "/usr/local/etc/yori/yori.conf",
+    "/etc/yori/yori.conf",
+    "yori.conf",
+];
+
+/// Operation modes accepted by the proxy
+const MODES: &[&str] = &["observe", "advisory", "enforce"];
+
+/// First existing default config path
+pub fn default_path() -> Option<PathBuf> {
+    DEFAULT_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    pub mode: String,
+    pub listen: String,
+    pub endpoints: Vec<EndpointSection>,
+    pub proxy: ProxySection,
+    pub audit: AuditSection,
+    pub policies: PolicySection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EndpointSection {
+    pub domain: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ProxySection {
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditSection {
+    pub database: PathBuf,
+    pub retention_days: i64,
+    pub encryption_key: Option<PathBuf>,
+    pub maintenance_window: String,
+    pub redact_pii: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PolicySection {
+    pub directory: PathBuf,
+    pub default: String,
+    /// How several packages' decisions combine
+    pub strategy: String,
+    /// Decision for requests no policy decides ("allow" or "deny")
+    pub default_decision: String,
+    /// Refuse policy bundles not signed by one of `trusted_keys`
+    pub require_signed: bool,
+    /// Hex-encoded ed25519 public keys of policy signers
+    pub trusted_keys: Vec<String>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        FileConfig {
+            mode: "observe".to_string(),
+            listen: "[::]:8443".to_string(),
+            endpoints: Vec::new(),
+            proxy: ProxySection::default(),
+            audit: AuditSection::default(),
+            policies: PolicySection::default(),
+        }
+    }
+}
+
+impl Default for ProxySection {
+    fn default() -> Self {
+        ProxySection {
+            tls_cert: Some(PathBuf::from("/usr/local/etc/yori/yori.crt")),
+            tls_key: Some(PathBuf::from("/usr/local/etc/yori/yori.key")),
+        }
+    }
+}
+
+impl Default for AuditSection {
+    fn default() -> Self {
+        AuditSection {
+            database: PathBuf::from("/var/db/yori/audit.db"),
+            retention_days: 365,
+            encryption_key: None,
+            maintenance_window: "03:00-05:00".to_string(),
+            redact_pii: yori_core::PiiKind::ALL
+                .iter()
+                .map(|kind| kind.as_str().to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Default for PolicySection {
+    fn default() -> Self {
+        PolicySection {
+            directory: PathBuf::from("/usr/local/etc/yori/policies"),
+            default: "home_default.rego".to_string(),
+            strategy: yori_core::CombinationStrategy::default()
+                .as_str()
+                .to_string(),
+            default_decision: yori_core::DefaultDecision::default().as_str().to_string(),
+            require_signed: false,
+            trusted_keys: Vec::new(),
+        }
+    }
+}
+
+/// Problems found by [`FileConfig::validate`]
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// The gateway won't start (or will misbehave) with these
+    pub errors: Vec<String>,
+
+    /// Likely mistakes that don't stop the gateway
+    pub warnings: Vec<String>,
+}
+
+impl FileConfig {
+    /// Parse a YAML config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        // An empty file is a valid all-defaults config
+        if text.trim().is_empty() {
+            return Ok(FileConfig::default());
+        }
+        serde_yaml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Check values the YAML schema alone can't
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let errors = &mut report.errors;
+        let warnings = &mut report.warnings;
+
+        if !MODES.contains(&self.mode.as_str()) {
+            errors.push(format!(
+                "mode {:?} must be one of {}",
+                self.mode,
+                MODES.join(", ")
+            ));
+        }
+        if self.listen.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "listen {:?} is not a host:port address",
+                self.listen
+            ));
+        }
+
+        for endpoint in &self.endpoints {
+            let domain = endpoint.domain.trim();
+            if domain.is_empty() || domain.contains(['/', ':', ' ']) {
+                errors.push(format!(
+                    "endpoint domain {:?} must be a host name or wildcard pattern",
+                    endpoint.domain
+                ));
+            }
+        }
+        if !self.endpoints.is_empty() && !self.endpoints.iter().any(|e| e.enabled) {
+            warnings.push("all endpoints are disabled; no traffic will be intercepted".to_string());
+        }
+
+        if self.audit.retention_days <= 0 {
+            errors.push("audit.retention_days must be positive".to_string());
+        }
+        if let Some(parent) = self.audit.database.parent() {
+            if !parent.as_os_str().is_empty() && !parent.is_dir() {
+                errors.push(format!(
+                    "audit.database directory {} does not exist",
+                    parent.display()
+                ));
+            }
+        }
+        if let Some(key) = &self.audit.encryption_key {
+            if !key.exists() {
+                warnings.push(format!(
+                    "audit.encryption_key {} does not exist yet (it is created on first start)",
+                    key.display()
+                ));
+            }
+        }
+        if parse_window(&self.audit.maintenance_window).is_none() {
+            errors.push(format!(
+                "audit.maintenance_window {:?} must look like HH:MM-HH:MM",
+                self.audit.maintenance_window
+            ));
+        }
+
+        for detector in &self.audit.redact_pii {
+            if let Err(e) = detector.parse::<yori_core::PiiKind>() {
+                errors.push(format!("audit.redact_pii: {:#}", e));
+            }
+        }
+        if self.audit.redact_pii.is_empty() {
+            warnings.push(
+                "audit.redact_pii is empty; prompt previews are stored unredacted".to_string(),
+            );
+        }
+
+        if let Err(e) = self
+            .policies
+            .strategy
+            .parse::<yori_core::CombinationStrategy>()
+        {
+            errors.push(format!("policies.strategy: {:#}", e));
+        }
+        match self.policies.default_decision.parse() {
+            Ok(yori_core::DefaultDecision::Allow) if self.mode == "enforce" => warnings.push(
+                "policies.default_decision is allow in enforce mode; requests no policy \
+                 decides are let through (set it to deny to fail closed)"
+                    .to_string(),
+            ),
+            Ok(_) => {}
+            Err(e) => errors.push(format!("policies.default_decision: {:#}", e)),
+        }
+        match yori_core::RuleIndex::build(&self.policies.directory) {
+            Ok(index) if index.files().is_empty() => warnings.push(format!(
+                "no .rego files in {}",
+                self.policies.directory.display()
+            )),
+            Ok(index) => {
+                if !index.files().contains(&self.policies.default) {
+                    errors.push(format!(
+                        "default policy {} not found in {}",
+                        self.policies.default,
+                        self.policies.directory.display()
+                    ));
+                }
+            }
+            Err(e) => errors.push(format!("policies: {:#}", e)),
+        }
+        if self.policies.require_signed {
+            match yori_core::BundleVerifier::new(&self.policies.trusted_keys) {
+                Ok(verifier) => {
+                    if let Err(e) = verifier.verify(&self.policies.directory) {
+                        errors.push(format!("policies: {:#} (run `yori policy sign`)", e));
+                    }
+                }
+                Err(e) => errors.push(format!("policies.trusted_keys: {:#}", e)),
+            }
+        }
+
+        for (name, path) in [
+            ("tls_cert", &self.proxy.tls_cert),
+            ("tls_key", &self.proxy.tls_key),
+        ] {
+            if let Some(path) = path {
+                if !path.exists() {
+                    warnings.push(format!(
+                        "proxy.{} {} does not exist; the proxy will run without TLS",
+                        name,
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// Parse a "HH:MM-HH:MM" quiet-hours window
+fn parse_window(window: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = window.split_once('-')?;
+    Some((
+        NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+        NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("home_default.rego"), "package yori.home\n").unwrap();
+        let path = dir.path().join("yori.conf");
+        std::fs::write(
+            &path,
+            format!(
+                "mode: enforce\nlisten: \"0.0.0.0:8443\"\n\
+                 endpoints:\n  - domain: \"*.openai.azure.com\"\n\
+                 audit:\n  database: {db}\n\
+                 policies:\n  directory: {dir}\n",
+                db = dir.path().join("audit.db").display(),
+                dir = dir.path().display(),
+            ),
+        )
+        .unwrap();
+        let config = FileConfig::load(&path).unwrap();
+        let report = config.validate();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("policies.default_decision")));
+
+        let broken = FileConfig {
+            mode: "block-everything".to_string(),
+            listen: "8443".to_string(),
+            audit: AuditSection {
+                maintenance_window: "3am".to_string(),
+                ..config.audit
+            },
+            policies: PolicySection {
+                strategy: "most-specific".to_string(),
+                ..PolicySection::default()
+            },
+            ..FileConfig::default()
+        };
+        let report = broken.validate();
+        assert!(report.errors.iter().any(|e| e.starts_with("mode")));
+        assert!(report.errors.iter().any(|e| e.starts_with("listen")));
+        assert!(report
+            .errors
+            .iter()
+            .any(|e| e.contains("maintenance_window")));
+        assert!(report
+            .errors
+            .iter()
+            .any(|e| e.starts_with("policies.strategy")));
+    }
+
+    #[test]
+    fn test_unknown_sections_are_ignored() {
+        let config: FileConfig =
+            serde_yaml::from_str("enforcement:\n  enabled: true\nmode: advisory\n").unwrap();
+        assert_eq!(config.mode, "advisory");
+        assert_eq!(config.policies.default, "home_default.rego");
+    }
+}
@@ -0,0 +1,592 @@
+//! `yori` - headless administration for YORI
+//!
+//! Everything here works directly on the files the gateway uses (policy
+//! directory, audit database, `yori.conf`), so a router can be managed over
+//! SSH without the Python stack or the web UI.
+//!
+//! ```text
+//! yori policy test request.json      # dry-run a policy decision
+//! yori policy sign --key signing.key  # sign the policy directory
+//! yori audit query --since 24h       # recent traffic
+//! yori audit export --format csv     # full history for a spreadsheet
+//! yori audit verify                  # check the tamper-evident hash chain
+//! yori quota --daily-tokens 50000    # today's usage per device
+//! yori cache                         # prompt de-duplication cache
+//! yori config validate               # check yori.conf before a restart
+//! yori ca generate                   # create the interception CA
+//! yori keys set openai < key.txt      # store a provider API key
+//! ```
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use yori_core::{
+    ApiKeyStore, AuditConfig, AuditLogger, AuditQuery, PolicyEngine, PolicySigner, Vault,
+    DEFAULT_API_KEY_STORE, DEFAULT_VAULT_PATH,
+};
+
+mod ca;
+mod config;
+
+use config::FileConfig;
+
+#[derive(Parser)]
+#[command(
+    name = "yori",
+    version,
+    about = "Zero-trust LLM governance - admin CLI"
+)]
+struct Cli {
+    /// Path to yori.conf (default: first of /usr/local/etc/yori/yori.conf,
+    /// /etc/yori/yori.conf, ./yori.conf)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Test and list policies
+    #[command(subcommand)]
+    Policy(PolicyCommand),
+
+    /// Query and export the audit log
+    #[command(subcommand)]
+    Audit(AuditCommand),
+
+    /// Show today's token usage per device
+    Quota {
+        /// Daily token allowance to report remaining tokens against
+        #[arg(long)]
+        daily_tokens: Option<u64>,
+    },
+
+    /// Show prompt de-duplication cache statistics
+    Cache,
+
+    /// Check configuration files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Manage the interception certificate authority
+    #[command(subcommand)]
+    Ca(CaCommand),
+
+    /// Manage provider API keys injected by the proxy
+    Keys {
+        /// Sealed API key store
+        #[arg(long, global = true, default_value = DEFAULT_API_KEY_STORE)]
+        store: PathBuf,
+
+        /// Vault key file the store is sealed with (created if missing)
+        #[arg(long, global = true, default_value = DEFAULT_VAULT_PATH)]
+        vault_key: PathBuf,
+
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyCommand {
+    /// Evaluate sample input against the policies (exit status 2 if denied)
+    Test {
+        /// JSON input document ("-" for stdin)
+        input: PathBuf,
+
+        /// Policy directory (default: from config)
+        #[arg(long)]
+        policy_dir: Option<PathBuf>,
+
+        /// Include a trace of every rule evaluated, the input fields they
+        /// read and the policies' print() output
+        #[arg(long)]
+        explain: bool,
+    },
+
+    /// List loaded policies
+    List {
+        /// Policy directory (default: from config)
+        #[arg(long)]
+        policy_dir: Option<PathBuf>,
+    },
+
+    /// Write bundle.manifest and bundle.sig for the policy directory and
+    /// print the public key to add to policies.trusted_keys
+    Sign {
+        /// Ed25519 signing key file (created if missing; keep it off the router)
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Policy directory (default: from config)
+        #[arg(long)]
+        policy_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Show recent audit events
+    Query {
+        #[command(flatten)]
+        filter: AuditFilter,
+
+        /// Print one JSON object per line instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export audit events as JSON, JSONL, CSV or Parquet
+    Export {
+        #[command(flatten)]
+        filter: AuditFilter,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Write to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Check the audit hash chain (exit status 1 if a record was tampered with)
+    Verify {
+        /// Audit database (default: from config)
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct AuditFilter {
+    /// Only events newer than this (e.g. "30m", "24h", "7d" or an RFC 3339 time)
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Only events from this client IP
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Only events from this tenant
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Only events with this policy result (allow, alert, block)
+    #[arg(long)]
+    result: Option<String>,
+
+    /// Maximum number of events
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Audit database (default: from config)
+    #[arg(long)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// One pretty-printed JSON array (loads every event into memory)
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    Csv,
+    /// Requires yori-core's `parquet` feature
+    Parquet,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate yori.conf (exit status 1 on errors)
+    Validate,
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Store a provider's API key, read from stdin
+    Set {
+        /// Provider id ("openai", "anthropic", "gemini", ...)
+        provider: String,
+    },
+
+    /// List providers with a stored key
+    List,
+
+    /// Remove a provider's API key
+    Remove { provider: String },
+}
+
+#[derive(Subcommand)]
+enum CaCommand {
+    /// Generate a new CA certificate and key
+    Generate {
+        /// Output directory for ca.crt and ca.key
+        #[arg(long, default_value = ca::DEFAULT_CA_DIR)]
+        out_dir: PathBuf,
+
+        /// Certificate lifetime in days
+        #[arg(long, default_value_t = ca::DEFAULT_VALIDITY_DAYS)]
+        days: i64,
+
+        /// Replace an existing CA (clients must trust the new one)
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    let config_path = cli.config.or_else(config::default_path);
+    let config = match &config_path {
+        Some(path) => FileConfig::load(path)?,
+        None => FileConfig::default(),
+    };
+
+    match cli.command {
+        Command::Policy(PolicyCommand::Test {
+            input,
+            policy_dir,
+            explain,
+        }) => {
+            let engine = PolicyEngine::open(policy_dir.unwrap_or(config.policies.directory))?;
+            engine.set_strategy(config.policies.strategy.parse()?);
+            engine.set_default_decision(config.policies.default_decision.parse()?);
+            let input: serde_json::Value =
+                serde_json::from_str(&read_input(&input)?).context("input is not valid JSON")?;
+            let result = match explain {
+                true => engine.explain_json(&input)?,
+                false => engine.evaluate_json(&input)?,
+            };
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            Ok(if result["allow"].as_bool() == Some(true) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(2)
+            })
+        }
+        Command::Policy(PolicyCommand::List { policy_dir }) => {
+            let engine = PolicyEngine::open(policy_dir.unwrap_or(config.policies.directory))?;
+            for name in engine.policy_names() {
+                println!("{}", name);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Policy(PolicyCommand::Sign { key, policy_dir }) => {
+            let policy_dir = policy_dir.unwrap_or(config.policies.directory);
+            let signer = PolicySigner::open_or_create(&key)?;
+            let files = signer.sign(&policy_dir)?;
+            eprintln!("Signed {} files in {}", files, policy_dir.display());
+            println!("{}", signer.public_key());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Audit(AuditCommand::Query { filter, json }) => {
+            let logger = open_audit(&config, filter.db.clone())?;
+            let events = logger.query(&filter.to_query(Some(50))?)?;
+            if json {
+                for event in &events {
+                    println!("{}", serde_json::to_string(event)?);
+                }
+            } else {
+                print_events(&events);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Audit(AuditCommand::Export {
+            filter,
+            format,
+            output,
+        }) => {
+            let logger = open_audit(&config, filter.db.clone())?;
+            let query = filter.to_query(None)?;
+            let mut out: Box<dyn Write + Send> = match &output {
+                Some(path) => Box::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("failed to create {}", path.display()))?,
+                ),
+                None => Box::new(std::io::stdout()),
+            };
+            let rows = match format {
+                ExportFormat::Json => {
+                    let events = logger.query(&query)?;
+                    serde_json::to_writer_pretty(&mut out, &events)?;
+                    writeln!(out)?;
+                    events.len() as u64
+                }
+                ExportFormat::Jsonl => {
+                    logger
+                        .export(&query, yori_core::ExportFormat::Jsonl, out)?
+                        .rows
+                }
+                ExportFormat::Csv => {
+                    logger
+                        .export(&query, yori_core::ExportFormat::Csv, out)?
+                        .rows
+                }
+                ExportFormat::Parquet => {
+                    logger
+                        .export(&query, yori_core::ExportFormat::Parquet, out)?
+                        .rows
+                }
+            };
+            if let Some(path) = output {
+                eprintln!("Exported {} events to {}", rows, path.display());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Audit(AuditCommand::Verify { db, json }) => {
+            let logger = open_audit(&config, db)?;
+            let report = logger.verify_integrity()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("Verified:  {} events", report.verified);
+                if report.unchained > 0 {
+                    println!(
+                        "Unchained: {} events (logged before hash_chain was enabled)",
+                        report.unchained
+                    );
+                }
+                if let Some(head) = &report.head {
+                    println!("Head:      {}", head);
+                }
+            }
+            match &report.first_tampered {
+                Some(record) => {
+                    eprintln!("Tampered record {}: {}", record.id, record.reason);
+                    Ok(ExitCode::FAILURE)
+                }
+                None => {
+                    if report.verified == 0 && !json {
+                        println!("No chained events (is audit.hash_chain enabled?)");
+                    }
+                    Ok(ExitCode::SUCCESS)
+                }
+            }
+        }
+        Command::Quota { daily_tokens } => {
+            let logger = open_audit(&config, None)?;
+            let usage = logger.device_usage_since(local_midnight()?)?;
+            println!(
+                "{:<12} {:<18} {:>9} {:>12} {:>7} {:>12}",
+                "TENANT", "DEVICE", "REQUESTS", "TOKENS", "BLOCKS", "REMAINING"
+            );
+            for device in usage {
+                let remaining = daily_tokens.map_or("unlimited".to_string(), |quota| {
+                    quota.saturating_sub(device.tokens).to_string()
+                });
+                println!(
+                    "{:<12} {:<18} {:>9} {:>12} {:>7} {:>12}",
+                    device.tenant,
+                    device.device,
+                    device.requests,
+                    device.tokens,
+                    device.blocks,
+                    remaining
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Cache => {
+            let logger = open_audit(&config, None)?;
+            let stats = logger.prompt_cache_stats()?;
+            println!("Canonical prompts: {}", stats.canonical_prompts);
+            println!("Prompts logged:    {}", stats.occurrences);
+            println!("Hit rate:          {:.1}%", stats.hit_rate() * 100.0);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Config(ConfigCommand::Validate) => {
+            let Some(path) = config_path else {
+                bail!("no configuration file found; pass --config");
+            };
+            let report = config.validate();
+            for warning in &report.warnings {
+                println!("warning: {}", warning);
+            }
+            for error in &report.errors {
+                println!("error: {}", error);
+            }
+            if report.errors.is_empty() {
+                println!("✓ {} is valid", path.display());
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Command::Ca(CaCommand::Generate {
+            out_dir,
+            days,
+            force,
+        }) => {
+            let (cert, key) = ca::generate(&out_dir, days, force)?;
+            println!("✓ CA certificate: {}", cert.display());
+            println!("✓ CA private key: {}", key.display());
+            println!("Install the certificate on client devices to trust YORI.");
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Keys {
+            store,
+            vault_key,
+            command,
+        } => {
+            let vault = Vault::open_or_create(&vault_key)?;
+            let keys = ApiKeyStore::open(&store, &vault)?;
+            match command {
+                KeysCommand::Set { provider } => {
+                    let mut api_key = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut api_key)
+                        .context("failed to read the API key from stdin")?;
+                    keys.set(&provider, &api_key)?;
+                    println!("✓ API key for {} stored in {}", provider, store.display());
+                }
+                KeysCommand::List => {
+                    for provider in keys.providers() {
+                        println!("{}", provider);
+                    }
+                }
+                KeysCommand::Remove { provider } => {
+                    if !keys.remove(&provider)? {
+                        bail!("no API key stored for {}", provider);
+                    }
+                    println!("✓ API key for {} removed", provider);
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+impl AuditFilter {
+    fn to_query(&self, default_limit: Option<usize>) -> Result<AuditQuery> {
+        Ok(AuditQuery {
+            since: self
+                .since
+                .as_deref()
+                .map(|s| parse_since(s, Utc::now()))
+                .transpose()?,
+            tenant: self.tenant.clone(),
+            client_ip: self.device.clone(),
+            policy_result: self.result.clone(),
+            limit: self.limit.or(default_limit),
+            ..AuditQuery::default()
+        })
+    }
+}
+
+fn open_audit(config: &FileConfig, db: Option<PathBuf>) -> Result<AuditLogger> {
+    let database = db.unwrap_or_else(|| config.audit.database.clone());
+    if !database.exists() {
+        bail!("audit database {} does not exist", database.display());
+    }
+    AuditLogger::open(AuditConfig {
+        database,
+        encryption_key: config.audit.encryption_key.clone(),
+        // Read-only use; skip loading the fingerprint index
+        dedup_prompts: false,
+        ..AuditConfig::default()
+    })
+}
+
+/// Read a file, or stdin for "-"
+fn read_input(path: &PathBuf) -> Result<String> {
+    let mut text = String::new();
+    if path.as_os_str() == "-" {
+        std::io::stdin().read_to_string(&mut text)?;
+    } else {
+        text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+    }
+    Ok(text)
+}
+
+/// Parse a relative age ("90s", "30m", "24h", "7d") or an RFC 3339 time
+fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let split = value.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("invalid --since value {:?}", value))?;
+    let age = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => bail!("invalid --since unit in {:?} (use s, m, h or d)", value),
+    };
+    Ok(now - age)
+}
+
+fn local_midnight() -> Result<DateTime<Utc>> {
+    Local
+        .from_local_datetime(&Local::now().date_naive().and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .context("local midnight does not exist")
+}
+
+type Event = serde_json::Map<String, serde_json::Value>;
+
+fn print_events(events: &[Event]) {
+    let field = |event: &Event, name: &str| match &event[name] {
+        serde_json::Value::Null => "-".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    println!(
+        "{:<25} {:<16} {:<24} {:<7} {:>6} {:>7}",
+        "TIME", "DEVICE", "ENDPOINT", "RESULT", "STATUS", "TOKENS"
+    );
+    for event in events {
+        println!(
+            "{:<25} {:<16} {:<24} {:<7} {:>6} {:>7}",
+            field(event, "timestamp"),
+            field(event, "client_ip"),
+            field(event, "endpoint"),
+            field(event, "policy_result"),
+            field(event, "response_status"),
+            field(event, "response_tokens"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        assert_eq!(parse_since("24h", now).unwrap(), now - Duration::hours(24));
+        assert_eq!(parse_since("7d", now).unwrap(), now - Duration::days(7));
+        assert_eq!(
+            parse_since("2026-02-28T08:00:00+00:00", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 2, 28, 8, 0, 0).unwrap()
+        );
+        assert!(parse_since("3w", now).is_err());
+        assert!(parse_since("", now).is_err());
+    }
+}
@@ -0,0 +1,643 @@
+//! REST admin API
+//!
+//! The OPNsense plugin and shell scripts manage a running gateway over a
+//! small JSON API instead of importing the Python bindings:
+//!
+//! ```text
+//! GET  /api/health                  liveness, mode and uptime (no token needed)
+//! GET  /healthz                     liveness probe (no token needed)
+//! GET  /readyz?upstreams=false      readiness probe (no token needed)
+//! GET  /api/policies                loaded policies and how they combine
+//! POST /api/policies/reload         reload the policy directory
+//! POST /api/config/reload           re-read yori.toml, returning what changed
+//! GET  /api/mode                    current mode and local-only switch
+//! PUT  /api/mode                    {"mode": "enforce", "local_only": false}
+//! GET  /api/audit/events?since=...  audit events, newest first (paged)
+//! GET  /api/audit/stream?type=block audit events as they are logged (SSE)
+//! GET  /api/audit/costs?daily=true  estimated cost per user, device, model or day
+//! GET  /api/cache/stats             decision and prompt cache counters
+//! ```
+//!
+//! With a token configured every route but the health probes requires
+//! `Authorization: Bearer <token>`. Errors are `{"error": "..."}` with a
+//! 4xx/5xx status.
+//!
+//! `/api/audit/stream` takes the live-tail filters (`tenant`, `client_ip`,
+//! `endpoint`, `type`, `status`) and stays open, sending each new event as
+//! a Server-Sent Event, so the dashboard doesn't poll `/api/audit/events`.
+//!
+//! `/healthz` answers 200 whenever the admin listener's runtime is
+//! responsive. `/readyz` answers 503 unless a policy engine is attached and
+//! the audit database answers a query within a few seconds, so monit or a
+//! watchdog can restart a wedged gateway. It also reports which upstream
+//! providers accept a TCP connection; an unreachable upstream doesn't make
+//! the gateway unready, since restarting it won't bring the WAN back.
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::{Query, RawQuery, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use crate::audit::AuditQuery;
+use crate::cost::CostGrouping;
+use crate::livetail::LiveFilter;
+use crate::proxy::{ProxyMode, ProxyServer};
+use crate::reload::ConfigReloader;
+
+/// Address the admin API listens on unless configured otherwise (loopback
+/// only: the plugin runs on the router itself)
+pub const DEFAULT_ADMIN_ADDR: &str = "127.0.0.1:8444";
+
+/// Events returned by `/api/audit/events` without a `limit`
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
+/// Most events one `/api/audit/events` call returns
+const MAX_EVENT_LIMIT: usize = 1000;
+
+/// Time a readiness check gets before it counts as failed
+const READY_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What the admin routes operate on
+#[derive(Clone)]
+pub struct AdminState {
+    proxy: Arc<ProxyServer>,
+    token: Option<Arc<str>>,
+    reloader: Option<Arc<ConfigReloader>>,
+    started_at: DateTime<Utc>,
+}
+
+impl AdminState {
+    /// Manage `proxy` (and the policy engine and audit log attached to it)
+    pub fn new(proxy: Arc<ProxyServer>) -> Self {
+        AdminState {
+            proxy,
+            token: None,
+            reloader: None,
+            started_at: Utc::now(),
+        }
+    }
+
+    /// Require `Authorization: Bearer <token>` on every route but health
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into().into());
+        self
+    }
+
+    /// Serve `/api/config/reload` with `reloader`
+    pub fn with_reloader(mut self, reloader: Arc<ConfigReloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+}
+
+/// Error response: `{"error": message}` with a status
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    fn unavailable(what: &str) -> Self {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("no {} attached to the proxy", what),
+        )
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult = std::result::Result<Json<Value>, ApiError>;
+
+/// Run database and policy work off the async workers
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> std::result::Result<T, ApiError> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result.map_err(ApiError::from),
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+/// Admin routes over `state`
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/api/policies", get(list_policies))
+        .route("/api/policies/reload", post(reload_policies))
+        .route("/api/config/reload", post(reload_config))
+        .route("/api/mode", get(get_mode).put(set_mode))
+        .route("/api/audit/events", get(audit_events))
+        .route("/api/audit/stream", get(audit_stream))
+        .route("/api/audit/costs", get(audit_costs))
+        .route("/api/cache/stats", get(cache_stats))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .route("/api/health", get(health))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
+
+/// Serve the admin API on `addr` until the task is dropped
+pub async fn serve(addr: SocketAddr, state: AdminState) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Admin API listening on {}", listener.local_addr()?);
+    serve_listener(listener, state).await
+}
+
+/// Serve on an already bound listener
+pub async fn serve_listener(listener: TcpListener, state: AdminState) -> Result<()> {
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+async fn require_token(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let presented = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+        if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+            return ApiError::new(StatusCode::UNAUTHORIZED, "missing or invalid admin token")
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Compare without leaking how many leading bytes matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn health(State(state): State<AdminState>) -> Json<Value> {
+    let proxy = &state.proxy;
+    let limits = proxy.connection_limit_stats();
+    Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "mode": proxy.mode().as_str(),
+        "local_only": proxy.is_local_only(),
+        "active_connections": proxy.active_connections(),
+        "queued_connections": limits.queued,
+        "refused_connections": limits.refused,
+        "policies": proxy.policy_engine().map(|engine| engine.policy_names().len()),
+        "audit": proxy.audit_logger().is_some(),
+        "audit_spool": proxy.audit_spool().stats(),
+        "uptime_seconds": (Utc::now() - state.started_at).num_seconds().max(0),
+    }))
+}
+
+async fn healthz(State(state): State<AdminState>) -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "uptime_seconds": (Utc::now() - state.started_at).num_seconds().max(0),
+    }))
+}
+
+/// Query string of `GET /readyz`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadyParams {
+    #[serde(default = "default_true")]
+    upstreams: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+async fn readyz(State(state): State<AdminState>, Query(params): Query<ReadyParams>) -> Response {
+    let proxy = &state.proxy;
+    let policy_engine = match proxy.policy_engine() {
+        Some(engine) => json!({"ok": true, "policies": engine.policy_names().len()}),
+        None => json!({"ok": false, "error": "no policy engine attached"}),
+    };
+    let audit = match proxy.audit_logger() {
+        Some(audit) => match timeout(READY_CHECK_TIMEOUT, blocking(move || audit.ping())).await {
+            Ok(Ok(())) => json!({"ok": true}),
+            Ok(Err(e)) => json!({"ok": false, "error": e.message}),
+            Err(_) => json!({"ok": false, "error": "audit database did not answer in time"}),
+        },
+        None => json!({"ok": false, "error": "no audit log attached"}),
+    };
+    let ready = policy_engine["ok"] == json!(true) && audit["ok"] == json!(true);
+
+    let mut checks = json!({"policy_engine": policy_engine, "audit": audit});
+    if params.upstreams {
+        let hosts = proxy.upstream_hosts();
+        let probes = hosts.iter().map(|host| reachable(host));
+        let results = futures::future::join_all(probes).await;
+        let (up, down): (Vec<_>, Vec<_>) = hosts.iter().zip(results).partition(|(_, ok)| *ok);
+        let open_circuits: Vec<String> = proxy
+            .tripped_circuits()
+            .into_iter()
+            .map(|(endpoint, _)| endpoint)
+            .collect();
+        checks["upstreams"] = json!({
+            "ok": hosts.is_empty() || !up.is_empty(),
+            "reachable": up.into_iter().map(|(host, _)| host).collect::<Vec<_>>(),
+            "unreachable": down.into_iter().map(|(host, _)| host).collect::<Vec<_>>(),
+            "open_circuits": open_circuits,
+        });
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(json!({"ready": ready, "checks": checks}))).into_response()
+}
+
+/// Whether `host` (port 443 unless given) accepts a TCP connection in time
+async fn reachable(host: &str) -> bool {
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:443", host)
+    };
+    matches!(
+        timeout(READY_CHECK_TIMEOUT, TcpStream::connect(addr)).await,
+        Ok(Ok(_))
+    )
+}
+
+async fn list_policies(State(state): State<AdminState>) -> ApiResult {
+    let engine = state
+        .proxy
+        .policy_engine()
+        .ok_or_else(|| ApiError::unavailable("policy engine"))?;
+    Ok(Json(json!({
+        "policies": engine.policy_names(),
+        "strategy": engine.strategy().as_str(),
+        "default_decision": engine.default_decision().as_str(),
+    })))
+}
+
+async fn reload_policies(State(state): State<AdminState>) -> ApiResult {
+    let engine = state
+        .proxy
+        .policy_engine()
+        .ok_or_else(|| ApiError::unavailable("policy engine"))?;
+    let loaded = blocking(move || engine.reload()).await?;
+    tracing::info!("Policies reloaded through the admin API: {} loaded", loaded);
+    Ok(Json(json!({ "loaded": loaded })))
+}
+
+async fn reload_config(State(state): State<AdminState>) -> ApiResult {
+    let reloader = state
+        .reloader
+        .clone()
+        .ok_or_else(|| ApiError::unavailable("config reloader"))?;
+    let proxy = Arc::clone(&state.proxy);
+    let report = blocking(move || reloader.reload(&proxy)).await?;
+    Ok(Json(json!(report)))
+}
+
+fn mode_json(proxy: &ProxyServer) -> Json<Value> {
+    Json(json!({
+        "mode": proxy.mode().as_str(),
+        "local_only": proxy.is_local_only(),
+    }))
+}
+
+async fn get_mode(State(state): State<AdminState>) -> Json<Value> {
+    mode_json(&state.proxy)
+}
+
+/// Body of `PUT /api/mode`; absent fields are left unchanged
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModeChange {
+    mode: Option<String>,
+    local_only: Option<bool>,
+}
+
+async fn set_mode(State(state): State<AdminState>, Json(change): Json<ModeChange>) -> ApiResult {
+    // Parse before changing anything so a bad mode leaves both untouched
+    let mode = change
+        .mode
+        .as_deref()
+        .map(str::parse::<ProxyMode>)
+        .transpose()
+        .map_err(|e| ApiError::bad_request(format!("{:#}", e)))?;
+    if let Some(mode) = mode {
+        state.proxy.set_mode(mode);
+    }
+    if let Some(enabled) = change.local_only {
+        state.proxy.set_local_only(enabled);
+    }
+    Ok(mode_json(&state.proxy))
+}
+
+/// Query string of `GET /api/audit/events`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventParams {
+    since: Option<String>,
+    until: Option<String>,
+    tenant: Option<String>,
+    client_ip: Option<String>,
+    endpoint: Option<String>,
+    event_type: Option<String>,
+    policy_result: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+fn parse_time(
+    name: &str,
+    value: Option<&str>,
+) -> std::result::Result<Option<DateTime<Utc>>, ApiError> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| ApiError::bad_request(format!("{}: {} ({:?})", name, e, v)))
+        })
+        .transpose()
+}
+
+async fn audit_events(
+    State(state): State<AdminState>,
+    Query(params): Query<EventParams>,
+) -> ApiResult {
+    let audit = state
+        .proxy
+        .audit_logger()
+        .ok_or_else(|| ApiError::unavailable("audit log"))?;
+    let query = AuditQuery {
+        since: parse_time("since", params.since.as_deref())?,
+        until: parse_time("until", params.until.as_deref())?,
+        tenant: params.tenant,
+        client_ip: params.client_ip,
+        endpoint: params.endpoint,
+        event_type: params.event_type,
+        policy_result: params.policy_result,
+        limit: Some(
+            params
+                .limit
+                .unwrap_or(DEFAULT_EVENT_LIMIT)
+                .min(MAX_EVENT_LIMIT),
+        ),
+        offset: params.offset,
+    };
+    let (events, total) =
+        blocking(move || Ok((audit.query(&query)?, audit.count(&query)?))).await?;
+    Ok(Json(json!({ "events": events, "total": total })))
+}
+
+async fn audit_stream(
+    State(state): State<AdminState>,
+    RawQuery(query): RawQuery,
+) -> std::result::Result<Response, ApiError> {
+    let audit = state
+        .proxy
+        .audit_logger()
+        .ok_or_else(|| ApiError::unavailable("audit log"))?;
+    let filter = LiveFilter::from_query(query.as_deref().unwrap_or(""));
+    let events = futures::StreamExt::map(audit.live_tail().sse_stream(filter), |chunk| {
+        Ok::<_, Infallible>(chunk)
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+            (header::HeaderName::from_static("x-accel-buffering"), "no"),
+        ],
+        Body::from_stream(events),
+    )
+        .into_response())
+}
+
+/// Query string of `GET /api/audit/costs`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostParams {
+    group_by: Option<String>,
+    #[serde(default)]
+    daily: bool,
+    since: Option<String>,
+    until: Option<String>,
+    tenant: Option<String>,
+    client_ip: Option<String>,
+    endpoint: Option<String>,
+}
+
+async fn audit_costs(
+    State(state): State<AdminState>,
+    Query(params): Query<CostParams>,
+) -> ApiResult {
+    let audit = state
+        .proxy
+        .audit_logger()
+        .ok_or_else(|| ApiError::unavailable("audit log"))?;
+    let grouping = match params.group_by.as_deref() {
+        Some(name) => name
+            .parse::<CostGrouping>()
+            .map_err(|e| ApiError::bad_request(e.to_string()))?,
+        None => CostGrouping::Device,
+    };
+    let query = AuditQuery {
+        since: parse_time("since", params.since.as_deref())?,
+        until: parse_time("until", params.until.as_deref())?,
+        tenant: params.tenant,
+        client_ip: params.client_ip,
+        endpoint: params.endpoint,
+        ..AuditQuery::default()
+    };
+    let daily = params.daily;
+    let groups = blocking(move || audit.cost_summary(&query, grouping, daily)).await?;
+    let total: f64 = groups.iter().map(|g| g.cost_usd).sum();
+    Ok(Json(json!({
+        "group_by": grouping.as_str(),
+        "groups": groups,
+        "total_usd": total,
+    })))
+}
+
+async fn cache_stats(State(state): State<AdminState>) -> ApiResult {
+    let decisions = state.proxy.policy_engine().map(|engine| {
+        let stats = engine.cache_stats();
+        json!({
+            "entries": stats.entries,
+            "hits": stats.hits,
+            "misses": stats.misses,
+            "hit_rate": stats.hit_rate(),
+        })
+    });
+    let prompts = match state.proxy.audit_logger() {
+        Some(audit) => {
+            let stats = blocking(move || audit.prompt_cache_stats()).await?;
+            Some(json!({
+                "canonical_prompts": stats.canonical_prompts,
+                "occurrences": stats.occurrences,
+                "hit_rate": stats.hit_rate(),
+            }))
+        }
+        None => None,
+    };
+    Ok(Json(json!({ "decisions": decisions, "prompts": prompts })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditConfig, AuditLogger};
+    use crate::policy::PolicyEngine;
+    use crate::proxy::ProxyConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn start(state: AdminState) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(listener, state));
+        addr
+    }
+
+    /// Send one request and return the status line and body
+    async fn call(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &str,
+        body: &str,
+    ) -> (u16, Value) {
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: yori\r\nConnection: close\r\n{headers}\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap_or("");
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_mode_and_token() {
+        let proxy = Arc::new(ProxyServer::new(ProxyConfig::default()));
+        let addr = start(AdminState::new(Arc::clone(&proxy)).with_token("s3cret")).await;
+
+        let (status, health) = call(addr, "GET", "/api/health", "", "").await;
+        assert_eq!((status, health["mode"].as_str()), (200, Some("observe")));
+        assert_eq!(health["audit_spool"]["dropped"], json!(0));
+
+        let (status, _) = call(addr, "GET", "/api/mode", "", "").await;
+        assert_eq!(status, 401);
+        let auth = "Authorization: Bearer s3cret\r\n";
+        let (status, mode) = call(
+            addr,
+            "PUT",
+            "/api/mode",
+            auth,
+            r#"{"mode": "enforce", "local_only": true}"#,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(mode, json!({"mode": "enforce", "local_only": true}));
+        assert_eq!(proxy.mode(), ProxyMode::Enforce);
+
+        let (status, error) = call(addr, "PUT", "/api/mode", auth, r#"{"mode": "loud"}"#).await;
+        assert_eq!(status, 400);
+        assert!(error["error"].as_str().unwrap().contains("loud"));
+        assert_eq!(proxy.mode(), ProxyMode::Enforce);
+
+        let (status, _) = call(addr, "GET", "/api/policies", auth, "").await;
+        assert_eq!(status, 503);
+        let (status, _) = call(addr, "GET", "/api/audit/stream", auth, "").await;
+        assert_eq!(status, 503);
+
+        let (status, _) = call(addr, "GET", "/healthz", "", "").await;
+        assert_eq!(status, 200);
+        let (status, ready) = call(addr, "GET", "/readyz?upstreams=false", "", "").await;
+        assert_eq!((status, ready["ready"].as_bool()), (503, Some(false)));
+        assert_eq!(ready["checks"]["audit"]["ok"], json!(false));
+    }
+
+    #[tokio::test]
+    async fn test_policies_audit_and_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("home.rego"), "package yori.home\n").unwrap();
+        let proxy = Arc::new(ProxyServer::new(ProxyConfig::default()));
+        proxy.set_policy_engine(Arc::new(PolicyEngine::open(dir.path()).unwrap()));
+        let audit = AuditLogger::open(AuditConfig {
+            database: ":memory:".into(),
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        proxy.set_audit_logger(Arc::new(audit));
+        let addr = start(AdminState::new(proxy)).await;
+
+        let (status, policies) = call(addr, "GET", "/api/policies", "", "").await;
+        assert_eq!(status, 200);
+        assert_eq!(policies["policies"], json!(["home"]));
+
+        std::fs::write(dir.path().join("bedtime.rego"), "package yori.bedtime\n").unwrap();
+        let (status, reloaded) = call(addr, "POST", "/api/policies/reload", "", "").await;
+        assert_eq!((status, reloaded["loaded"].as_u64()), (200, Some(2)));
+
+        let (status, events) = call(addr, "GET", "/api/audit/events?limit=5", "", "").await;
+        assert_eq!((status, events["total"].as_u64()), (200, Some(0)));
+        let (status, _) = call(addr, "GET", "/api/audit/events?since=yesterday", "", "").await;
+        assert_eq!(status, 400);
+
+        let (status, costs) = call(addr, "GET", "/api/audit/costs?group_by=user", "", "").await;
+        assert_eq!((status, costs["total_usd"].as_f64()), (200, Some(0.0)));
+        let (status, _) = call(addr, "GET", "/api/audit/costs?group_by=pet", "", "").await;
+        assert_eq!(status, 400);
+
+        let (status, stats) = call(addr, "GET", "/api/cache/stats", "", "").await;
+        assert_eq!(status, 200);
+        assert_eq!(stats["decisions"]["hits"], json!(0));
+        assert_eq!(stats["prompts"]["occurrences"], json!(0));
+
+        let (status, ready) = call(addr, "GET", "/readyz?upstreams=false", "", "").await;
+        assert_eq!((status, ready["ready"].as_bool()), (200, Some(true)));
+        assert_eq!(ready["checks"]["policy_engine"]["policies"], json!(2));
+        assert!(ready["checks"].get("upstreams").is_none());
+    }
+}
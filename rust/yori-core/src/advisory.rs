@@ -0,0 +1,196 @@
+//! Advisory-mode warnings on forwarded responses
+//!
+//! Advisory mode forwards requests a policy would deny, so families can see
+//! what enforce mode will do before turning it on. Without a signal on the
+//! response itself, nobody notices until the blocks start. Each such
+//! response carries:
+//!
+//! ```text
+//! X-YORI-Policy: bedtime
+//! X-YORI-Warning: This request would be blocked: after 9pm on school nights
+//! ```
+//!
+//! With `notice` on, the same warning is also prepended to the completion
+//! text of non-streaming responses, so it shows up in the chat window:
+//!
+//! ```text
+//! [YORI] This request would be blocked: after 9pm on school nights
+//!
+//! Sure! Here's the essay outline...
+//! ```
+//!
+//! The rest of the provider's JSON is left intact. Streamed responses get
+//! the headers only.
+
+use serde_json::Value;
+
+use crate::providers::{completion_pointers, Provider};
+
+/// Policy that would have blocked the request
+pub const HEADER_POLICY: &str = "X-YORI-Policy";
+
+/// Why the request would have been blocked
+pub const HEADER_WARNING: &str = "X-YORI-Warning";
+
+/// Longest header value sent; longer reasons are cut short
+const MAX_HEADER_CHARS: usize = 200;
+
+/// Advisory warning settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdvisoryConfig {
+    /// Add the `X-YORI-Policy`/`X-YORI-Warning` headers
+    pub headers: bool,
+
+    /// Prepend the warning to the completion text
+    pub notice: bool,
+
+    /// Warning template (`{reason}`, `{policy}`)
+    pub message: String,
+}
+
+impl Default for AdvisoryConfig {
+    fn default() -> Self {
+        AdvisoryConfig {
+            headers: true,
+            notice: false,
+            message: "This request would be blocked: {reason}".to_string(),
+        }
+    }
+}
+
+/// Warns about requests advisory mode let through
+#[derive(Debug, Clone, Default)]
+pub struct AdvisoryNotice {
+    config: AdvisoryConfig,
+}
+
+impl AdvisoryNotice {
+    /// Create from configuration
+    pub fn new(config: AdvisoryConfig) -> Self {
+        AdvisoryNotice { config }
+    }
+
+    /// Warning text for a decision
+    pub fn warning(&self, policy: &str, reason: &str) -> String {
+        self.config
+            .message
+            .replace("{reason}", reason)
+            .replace("{policy}", policy)
+    }
+
+    /// Headers to add to the forwarded response (empty when disabled)
+    pub fn headers(&self, policy: &str, reason: &str) -> Vec<(String, String)> {
+        if !self.config.headers {
+            return Vec::new();
+        }
+        vec![
+            (HEADER_POLICY.to_string(), header_value(policy)),
+            (
+                HEADER_WARNING.to_string(),
+                header_value(&self.warning(policy, reason)),
+            ),
+        ]
+    }
+
+    /// Response body with the warning prepended to the first completion
+    /// text
+    ///
+    /// None when notices are off or the body has no completion text to
+    /// annotate (errors, embeddings, non-JSON bodies).
+    pub fn annotate(
+        &self,
+        provider: Provider,
+        body: &[u8],
+        policy: &str,
+        reason: &str,
+    ) -> Option<Vec<u8>> {
+        if !self.config.notice {
+            return None;
+        }
+        let mut json: Value = serde_json::from_slice(body).ok()?;
+        let pointer = completion_pointers(provider, &json).into_iter().next()?;
+        let Some(Value::String(text)) = json.pointer_mut(&pointer) else {
+            return None;
+        };
+        *text = format!("[YORI] {}\n\n{}", self.warning(policy, reason), text);
+        serde_json::to_vec(&json).ok()
+    }
+}
+
+/// Printable ASCII only (other characters become `?`), cut to
+/// [`MAX_HEADER_CHARS`]
+fn header_value(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c == ' ' || c.is_ascii_graphic() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .take(MAX_HEADER_CHARS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_headers() {
+        let notice = AdvisoryNotice::default();
+        assert_eq!(
+            notice.headers("bedtime", "after 9pm\non school nights"),
+            [
+                (HEADER_POLICY.to_string(), "bedtime".to_string()),
+                (
+                    HEADER_WARNING.to_string(),
+                    "This request would be blocked: after 9pm?on school nights".to_string()
+                ),
+            ]
+        );
+        let quiet = AdvisoryNotice::new(AdvisoryConfig {
+            headers: false,
+            ..AdvisoryConfig::default()
+        });
+        assert!(quiet.headers("bedtime", "late").is_empty());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let body = json!({
+            "choices": [{"message": {"role": "assistant", "content": "Sure!"}}],
+            "usage": {"total_tokens": 12}
+        })
+        .to_string();
+        assert!(AdvisoryNotice::default()
+            .annotate(Provider::OpenAI, body.as_bytes(), "bedtime", "late")
+            .is_none());
+
+        let notice = AdvisoryNotice::new(AdvisoryConfig {
+            notice: true,
+            message: "{policy}: {reason}".to_string(),
+            ..AdvisoryConfig::default()
+        });
+        let annotated = notice
+            .annotate(Provider::OpenAI, body.as_bytes(), "bedtime", "late")
+            .unwrap();
+        let json: Value = serde_json::from_slice(&annotated).unwrap();
+        assert_eq!(
+            json["choices"][0]["message"]["content"],
+            "[YORI] bedtime: late\n\nSure!"
+        );
+        assert_eq!(json["usage"]["total_tokens"], 12);
+
+        let anthropic = json!({"content": [{"type": "text", "text": "Hi"}]}).to_string();
+        let annotated = notice
+            .annotate(Provider::Anthropic, anthropic.as_bytes(), "bedtime", "late")
+            .unwrap();
+        let json: Value = serde_json::from_slice(&annotated).unwrap();
+        assert_eq!(json["content"][0]["text"], "[YORI] bedtime: late\n\nHi");
+        assert!(notice
+            .annotate(Provider::OpenAI, b"{\"error\": {}}", "bedtime", "late")
+            .is_none());
+    }
+}
@@ -0,0 +1,697 @@
+//! Push notifications for blocked requests and errors
+//!
+//! Parents want to hear about a block when it happens, not the next time
+//! they open the dashboard. The [`Alerter`] follows the live-tail channel
+//! and posts every blocked request, proxy error or secret found in a
+//! request (see [`crate::dlp`]) to the configured targets: a generic JSON
+//! webhook, an ntfy.sh topic, a Discord webhook or a Slack incoming
+//! webhook.
+//!
+//! A device stuck in a retry loop can produce hundreds of identical blocks
+//! a minute, so alerts are de-duplicated: the first event for a device,
+//! endpoint and policy goes out, repeats within the window are counted
+//! and reported with the next alert for that key ("… and 37 more like
+//! this"). A global per-minute cap bounds the total regardless.
+//!
+//! Prompt previews are left out of notifications unless
+//! `include_prompt` is set; they would otherwise end up on third-party
+//! servers.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::audit::{AuditEvent, AuditEventType};
+use crate::livetail::{LiveEvent, LiveTail};
+
+/// CA bundles tried when none is configured (FreeBSD/OPNsense, then Linux)
+pub const DEFAULT_CA_BUNDLES: &[&str] = &[
+    "/usr/local/share/certs/ca-root-nss.crt",
+    "/etc/ssl/cert.pem",
+    "/etc/ssl/certs/ca-certificates.crt",
+];
+
+/// Default window in which repeats of an alert are suppressed
+pub const DEFAULT_DEDUP_WINDOW_SECS: u64 = 600;
+
+/// Default cap on alerts sent per minute across all keys
+pub const DEFAULT_MAX_ALERTS_PER_MINUTE: u32 = 10;
+
+/// Discord rejects messages longer than this
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// Status line and headers read from a webhook response
+const MAX_RESPONSE_HEAD: usize = 4096;
+
+/// How a target expects its notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    /// `{"title", "message", "event", "suppressed"}` JSON
+    Webhook,
+
+    /// ntfy.sh topic URL: plain-text body, title/priority/tags headers
+    Ntfy,
+
+    /// Discord webhook: `{"content"}`
+    Discord,
+
+    /// Slack incoming webhook: `{"text"}`
+    Slack,
+}
+
+impl AlertKind {
+    /// Name used in configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Webhook => "webhook",
+            AlertKind::Ntfy => "ntfy",
+            AlertKind::Discord => "discord",
+            AlertKind::Slack => "slack",
+        }
+    }
+}
+
+impl std::str::FromStr for AlertKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "webhook" | "json" => Ok(AlertKind::Webhook),
+            "ntfy" => Ok(AlertKind::Ntfy),
+            "discord" => Ok(AlertKind::Discord),
+            "slack" => Ok(AlertKind::Slack),
+            other => bail!(
+                "unknown alert target kind {:?} (expected webhook, ntfy, discord or slack)",
+                other
+            ),
+        }
+    }
+}
+
+/// Where alerts are sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertTarget {
+    /// Payload format
+    pub kind: AlertKind,
+
+    /// Webhook or topic URL (http:// or https://)
+    pub url: String,
+
+    /// Sent as `Authorization: Bearer <token>` (e.g., a private ntfy topic)
+    pub token: Option<String>,
+}
+
+/// Alerting configuration
+#[derive(Debug, Clone)]
+pub struct AlertConfig {
+    /// Targets every alert is posted to
+    pub targets: Vec<AlertTarget>,
+
+    /// Event types that raise an alert
+    pub event_types: Vec<AuditEventType>,
+
+    /// Repeats of an alert (same type, device, endpoint and policy) within
+    /// this window are counted instead of sent
+    pub dedup_window: Duration,
+
+    /// Alerts sent per minute at most, across all keys
+    pub max_per_minute: u32,
+
+    /// Include the prompt preview in notifications
+    pub include_prompt: bool,
+
+    /// PEM bundle of trusted CAs for https targets (None = first of
+    /// [`DEFAULT_CA_BUNDLES`] that exists)
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Connect-and-post timeout per target
+    pub timeout: Duration,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        AlertConfig {
+            targets: Vec::new(),
+            event_types: vec![
+                AuditEventType::RequestBlocked,
+                AuditEventType::Error,
+                AuditEventType::SensitiveData,
+            ],
+            dedup_window: Duration::from_secs(DEFAULT_DEDUP_WINDOW_SECS),
+            max_per_minute: DEFAULT_MAX_ALERTS_PER_MINUTE,
+            include_prompt: false,
+            ca_bundle: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A notification ready to be formatted for a target
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    /// One-line summary (e.g., "Blocked: sam-ipad → api.openai.com")
+    pub title: String,
+
+    /// Details: policy, reason and suppressed repeats
+    pub message: String,
+
+    /// The audit event, as in the live-tail stream
+    pub event: Value,
+
+    /// Repeats suppressed since the previous alert for this key
+    pub suppressed: u64,
+
+    /// Whether the event was a block (as opposed to an error or a
+    /// sensitive-data notice)
+    pub blocked: bool,
+}
+
+impl Alert {
+    /// Build the alert for an audit event
+    pub fn from_event(event: &AuditEvent, suppressed: u64, include_prompt: bool) -> Self {
+        let device = event.client_device.as_deref().unwrap_or(&event.client_ip);
+        let blocked = !matches!(
+            event.event_type,
+            AuditEventType::Error | AuditEventType::SensitiveData
+        );
+        let title = match event.event_type {
+            AuditEventType::Error => format!("YORI error: {} → {}", device, event.endpoint),
+            AuditEventType::SensitiveData => {
+                format!("Sensitive data: {} → {}", device, event.endpoint)
+            }
+            _ => format!("Blocked: {} → {}", device, event.endpoint),
+        };
+
+        let mut lines = Vec::new();
+        if let Some(policy) = &event.policy_name {
+            lines.push(format!("Policy: {}", policy));
+        }
+        if let Some(reason) = &event.policy_reason {
+            lines.push(format!("Reason: {}", reason));
+        }
+        if let Some(status) = event.response_status {
+            lines.push(format!("Status: {}", status));
+        }
+        if include_prompt {
+            if let Some(prompt) = &event.prompt_preview {
+                lines.push(format!("Prompt: {}", prompt));
+            }
+        }
+        if suppressed > 0 {
+            lines.push(format!("… and {} more like this", suppressed));
+        }
+
+        let mut json = event.to_json();
+        if !include_prompt {
+            json["prompt_preview"] = Value::Null;
+        }
+        Alert {
+            title,
+            message: lines.join("\n"),
+            event: json,
+            suppressed,
+            blocked,
+        }
+    }
+
+    /// Content type, extra headers and body for a target kind
+    pub fn payload(&self, kind: AlertKind) -> (&'static str, Vec<(&'static str, String)>, String) {
+        match kind {
+            AlertKind::Webhook => (
+                "application/json",
+                Vec::new(),
+                json!({
+                    "title": self.title,
+                    "message": self.message,
+                    "event": self.event,
+                    "suppressed": self.suppressed,
+                })
+                .to_string(),
+            ),
+            AlertKind::Ntfy => (
+                "text/plain; charset=utf-8",
+                vec![
+                    // Header values must be ASCII
+                    ("Title", self.title.replace('→', "->")),
+                    (
+                        "Priority",
+                        if self.blocked { "high" } else { "default" }.to_string(),
+                    ),
+                    (
+                        "Tags",
+                        if self.blocked { "no_entry" } else { "warning" }.to_string(),
+                    ),
+                ],
+                self.message.clone(),
+            ),
+            AlertKind::Discord => {
+                let content: String = format!("**{}**\n{}", self.title, self.message)
+                    .chars()
+                    .take(DISCORD_MAX_CHARS)
+                    .collect();
+                (
+                    "application/json",
+                    Vec::new(),
+                    json!({ "content": content }).to_string(),
+                )
+            }
+            AlertKind::Slack => (
+                "application/json",
+                Vec::new(),
+                json!({ "text": format!("*{}*\n{}", self.title, self.message) }).to_string(),
+            ),
+        }
+    }
+}
+
+/// Window state of one alert key
+#[derive(Debug)]
+struct DedupEntry {
+    /// When the last alert for the key went out (None = never, over the cap)
+    sent_at: Option<Instant>,
+    suppressed: u64,
+}
+
+/// Suppresses repeats per key and caps the overall rate
+#[derive(Debug)]
+struct Throttle {
+    window: Duration,
+    max_per_minute: u32,
+    keys: HashMap<String, DedupEntry>,
+    minute_start: Instant,
+    sent_this_minute: u32,
+}
+
+impl Throttle {
+    fn new(window: Duration, max_per_minute: u32) -> Self {
+        Throttle {
+            window,
+            max_per_minute,
+            keys: HashMap::new(),
+            minute_start: Instant::now(),
+            sent_this_minute: 0,
+        }
+    }
+
+    /// Whether an alert for `key` may go out now, with the number of
+    /// repeats suppressed since the last one
+    fn admit(&mut self, key: &str, now: Instant) -> Option<u64> {
+        if now.duration_since(self.minute_start) >= Duration::from_secs(60) {
+            self.minute_start = now;
+            self.sent_this_minute = 0;
+        }
+        let window = self.window;
+        let in_window = |entry: &DedupEntry| {
+            entry
+                .sent_at
+                .is_some_and(|t| now.duration_since(t) < window)
+        };
+        // Keys with pending repeats are kept so the count reaches the next alert
+        self.keys
+            .retain(|_, entry| in_window(entry) || entry.suppressed > 0);
+
+        let over_cap = self.sent_this_minute >= self.max_per_minute;
+        let entry = self.keys.entry(key.to_string()).or_insert(DedupEntry {
+            sent_at: None,
+            suppressed: 0,
+        });
+        if in_window(entry) || over_cap {
+            entry.suppressed += 1;
+            return None;
+        }
+        entry.sent_at = Some(now);
+        self.sent_this_minute += 1;
+        Some(std::mem::take(&mut entry.suppressed))
+    }
+}
+
+/// Posts alerts for audit events to the configured targets
+pub struct Alerter {
+    config: AlertConfig,
+    throttle: Mutex<Throttle>,
+    tls: Option<TlsConnector>,
+}
+
+impl Alerter {
+    /// Create an alerter, loading the CA bundle if any target uses https
+    pub fn new(config: AlertConfig) -> Result<Self> {
+        for target in &config.targets {
+            parse_url(&target.url)
+                .with_context(|| format!("invalid {} alert URL", target.kind.as_str()))?;
+        }
+        let needs_tls = config.targets.iter().any(|t| t.url.starts_with("https://"));
+        let tls = match needs_tls {
+            true => Some(load_tls(config.ca_bundle.as_ref())?),
+            false => None,
+        };
+        Ok(Alerter {
+            throttle: Mutex::new(Throttle::new(config.dedup_window, config.max_per_minute)),
+            config,
+            tls,
+        })
+    }
+
+    /// The alert to send for an event, if it is alert-worthy and not
+    /// suppressed as a repeat
+    pub fn check(&self, event: &AuditEvent) -> Option<Alert> {
+        if !self.config.event_types.contains(&event.event_type) {
+            return None;
+        }
+        let key = format!(
+            "{}|{}|{}|{}",
+            event.event_type.as_str(),
+            event.client_ip,
+            event.endpoint,
+            event.policy_name.as_deref().unwrap_or("")
+        );
+        let suppressed = self.throttle.lock().unwrap().admit(&key, Instant::now())?;
+        Some(Alert::from_event(
+            event,
+            suppressed,
+            self.config.include_prompt,
+        ))
+    }
+
+    /// Post an alert to every target; failures are logged, not returned
+    pub async fn send(&self, alert: &Alert) {
+        for target in &self.config.targets {
+            let result = tokio::time::timeout(self.config.timeout, self.post(target, alert)).await;
+            match result {
+                Ok(Ok(status)) if (200..300).contains(&status) => {}
+                Ok(Ok(status)) => tracing::warn!(
+                    "{} alert to {} answered HTTP {}",
+                    target.kind.as_str(),
+                    target.url,
+                    status
+                ),
+                Ok(Err(e)) => tracing::warn!(
+                    "{} alert to {} failed: {:#}",
+                    target.kind.as_str(),
+                    target.url,
+                    e
+                ),
+                Err(_) => {
+                    tracing::warn!("{} alert to {} timed out", target.kind.as_str(), target.url)
+                }
+            }
+        }
+    }
+
+    /// Alert on audit events published to `tail` until the channel closes
+    pub fn watch(self: Arc<Self>, tail: &LiveTail) -> tokio::task::JoinHandle<()> {
+        let mut receiver = tail.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        if let LiveEvent::Audit(event) = event.as_ref() {
+                            if let Some(alert) = self.check(event) {
+                                self.send(&alert).await;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Alerting fell behind; {} events not checked", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+
+    async fn post(&self, target: &AlertTarget, alert: &Alert) -> Result<u16> {
+        let url = parse_url(&target.url)?;
+        let (content_type, headers, body) = alert.payload(target.kind);
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: yori-alerts\r\n\
+             Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            url.path,
+            url.host,
+            content_type,
+            body.len()
+        );
+        if let Some(token) = &target.token {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let tcp = TcpStream::connect((url.host.as_str(), url.port))
+            .await
+            .with_context(|| format!("failed to connect to {}:{}", url.host, url.port))?;
+        if !url.tls {
+            return exchange(tcp, request.as_bytes()).await;
+        }
+        let connector = self.tls.as_ref().context("no CA bundle loaded")?;
+        let server_name = ServerName::try_from(url.host.as_str())
+            .with_context(|| format!("invalid host name {}", url.host))?;
+        let tls = connector.connect(server_name, tcp).await?;
+        exchange(tls, request.as_bytes()).await
+    }
+}
+
+/// Write a request and return the response status
+pub(crate) async fn exchange(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    request: &[u8],
+) -> Result<u16> {
+    stream.write_all(request).await?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 512];
+    while !head.windows(2).any(|w| w == b"\r\n") && head.len() < MAX_RESPONSE_HEAD {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let status_line = String::from_utf8_lossy(&head);
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| {
+            format!(
+                "malformed response {:?}",
+                status_line.lines().next().unwrap_or("")
+            )
+        })
+}
+
+/// The parts of a target URL the client needs
+#[derive(Debug, PartialEq)]
+pub(crate) struct TargetUrl {
+    pub(crate) tls: bool,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) path: String,
+}
+
+pub(crate) fn parse_url(url: &str) -> Result<TargetUrl> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        bail!("{:?} must start with http:// or https://", url);
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    // IPv6 literals are bracketed: [::1]:8080
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => bail!("{:?} has an unterminated IPv6 address", url),
+        },
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("invalid port in {:?}", url))?,
+        None if tls => 443,
+        None => 80,
+    };
+    if host.is_empty() {
+        bail!("{:?} has no host", url);
+    }
+    Ok(TargetUrl {
+        tls,
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+/// TLS connector trusting the configured or system CA bundle
+pub(crate) fn load_tls(bundle: Option<&PathBuf>) -> Result<TlsConnector> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(load_roots(bundle)?)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Certificates of the configured or system CA bundle
+pub(crate) fn load_roots(bundle: Option<&PathBuf>) -> Result<RootCertStore> {
+    let path = match bundle {
+        Some(path) => path.clone(),
+        None => DEFAULT_CA_BUNDLES
+            .iter()
+            .map(PathBuf::from)
+            .find(|p| p.exists())
+            .context("no system CA bundle found; set the alerts CA bundle")?,
+    };
+    let pem = std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut roots = RootCertStore::empty();
+    for der in rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))? {
+        // Bundles often carry a few certificates rustls can't parse
+        let _ = roots.add(&rustls::Certificate(der));
+    }
+    if roots.is_empty() {
+        bail!("no usable certificates in {}", path.display());
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{test_request, RequestContext};
+    use tokio::net::TcpListener;
+
+    fn blocked(client_ip: &str) -> AuditEvent {
+        let ctx = RequestContext {
+            client_ip: client_ip.to_string(),
+            client_device: Some("sam-ipad".to_string()),
+            ..test_request()
+        };
+        AuditEvent::from_request(AuditEventType::RequestBlocked, &ctx)
+            .with_policy("bedtime", "block", "past bedtime")
+            .with_prompt("help me with my essay", 200)
+    }
+
+    #[test]
+    fn test_repeats_are_suppressed_and_counted() {
+        let mut throttle = Throttle::new(Duration::from_secs(600), 2);
+        let start = Instant::now();
+        assert_eq!(throttle.admit("sam", start), Some(0));
+        for _ in 0..499 {
+            assert_eq!(throttle.admit("sam", start + Duration::from_secs(1)), None);
+        }
+        assert_eq!(throttle.admit("tv", start), Some(0));
+        // Cap of two per minute reached
+        assert_eq!(throttle.admit("laptop", start), None);
+
+        let later = start + Duration::from_secs(601);
+        assert_eq!(throttle.admit("sam", later), Some(499));
+        assert_eq!(throttle.admit("laptop", later), Some(1));
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let alert = Alert::from_event(&blocked("192.168.1.50"), 3, false);
+        assert_eq!(alert.title, "Blocked: sam-ipad → api.openai.com");
+        assert!(alert.message.contains("Reason: past bedtime"));
+        assert!(alert.message.ends_with("… and 3 more like this"));
+        assert!(!alert.message.contains("essay"));
+
+        let (_, _, body) = alert.payload(AlertKind::Webhook);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["event"]["prompt_preview"], Value::Null);
+        assert_eq!(body["suppressed"], 3);
+
+        let (content_type, headers, body) = alert.payload(AlertKind::Ntfy);
+        assert!(content_type.starts_with("text/plain"));
+        assert!(headers.contains(&("Title", "Blocked: sam-ipad -> api.openai.com".to_string())));
+        assert!(body.starts_with("Policy: bedtime"));
+
+        let (_, _, body) = alert.payload(AlertKind::Slack);
+        assert!(body.contains("*Blocked: sam-ipad"));
+        let with_prompt = Alert::from_event(&blocked("192.168.1.50"), 0, true);
+        assert!(with_prompt
+            .message
+            .contains("Prompt: help me with my essay"));
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = parse_url("https://ntfy.sh/yori-alerts").unwrap();
+        assert_eq!(
+            url,
+            TargetUrl {
+                tls: true,
+                host: "ntfy.sh".to_string(),
+                port: 443,
+                path: "/yori-alerts".to_string(),
+            }
+        );
+        assert_eq!(parse_url("http://[::1]:8080").unwrap().port, 8080);
+        assert_eq!(parse_url("http://[::1]:8080").unwrap().host, "::1");
+        assert_eq!(parse_url("https://[::1]/hook").unwrap().port, 443);
+        assert!(parse_url("ftp://example.com").is_err());
+        assert!("teams".parse::<AlertKind>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !String::from_utf8_lossy(&received).contains("\"suppressed\"") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed before the body arrived");
+                received.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        let alerter = Arc::new(
+            Alerter::new(AlertConfig {
+                targets: vec![AlertTarget {
+                    kind: AlertKind::Webhook,
+                    url: format!("http://{}/hooks/yori", addr),
+                    token: Some("t0ken".to_string()),
+                }],
+                ..AlertConfig::default()
+            })
+            .unwrap(),
+        );
+        let tail = LiveTail::default();
+        let watcher = Arc::clone(&alerter).watch(&tail);
+        tail.publish(LiveEvent::Audit(Box::new(blocked("192.168.1.50"))));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hooks/yori HTTP/1.1"));
+        assert!(request.contains("Authorization: Bearer t0ken"));
+        assert!(request.contains("past bedtime"));
+        // A repeat is suppressed rather than sent
+        assert!(alerter.check(&blocked("192.168.1.50")).is_none());
+        watcher.abort();
+    }
+}
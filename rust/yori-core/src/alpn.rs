@@ -0,0 +1,346 @@
+//! HTTP/2 and ALPN negotiation for intercepted and upstream connections
+//!
+//! Most provider SDKs (and every gRPC-flavoured one) offer `h2` in their
+//! ClientHello and some refuse to fall back. The intercepting listener
+//! offers `h2` ahead of `http/1.1`, and each terminated connection is
+//! served with whichever protocol the handshake settled on
+//! ([`serve_connection`]). Upstream connections negotiate on their own
+//! ([`connect_upstream`]), so an HTTP/1.1 client can be forwarded over h2
+//! and the other way round; [`prepare_upstream_request`] rewrites the
+//! request for the upstream side's framing.
+
+use anyhow::{Context, Result};
+use hyper::body::{Body, Incoming};
+use hyper::client::conn::{http1 as client_http1, http2 as client_http2};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, HOST};
+use hyper::server::conn::{http1 as server_http1, http2 as server_http2};
+use hyper::service::Service;
+use hyper::{Request, Response, Uri};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::error::Error as StdError;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+/// ALPN identifier of HTTP/2
+pub const ALPN_H2: &[u8] = b"h2";
+
+/// ALPN identifier of HTTP/1.1
+pub const ALPN_HTTP11: &[u8] = b"http/1.1";
+
+/// Headers that only describe a single HTTP/1 connection (RFC 9113 §8.2.2)
+const HOP_HEADERS: [&str; 6] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "te",
+];
+
+/// ALPN protocols to offer, most preferred first
+pub fn alpn_protocols(http2: bool) -> Vec<Vec<u8>> {
+    let mut protocols = Vec::with_capacity(2);
+    if http2 {
+        protocols.push(ALPN_H2.to_vec());
+    }
+    protocols.push(ALPN_HTTP11.to_vec());
+    protocols
+}
+
+/// HTTP version a connection speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    Http1,
+    Http2,
+}
+
+impl HttpVersion {
+    /// Version picked by ALPN (no ALPN = HTTP/1.1)
+    pub fn from_alpn(protocol: Option<&[u8]>) -> Self {
+        match protocol {
+            Some(ALPN_H2) => HttpVersion::Http2,
+            _ => HttpVersion::Http1,
+        }
+    }
+
+    /// Version name for logs and the audit trail
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Http1 => "HTTP/1.1",
+            HttpVersion::Http2 => "HTTP/2",
+        }
+    }
+}
+
+/// Serve one TLS-terminated client connection with the negotiated protocol
+pub async fn serve_connection<I, S, B>(io: I, version: HttpVersion, service: S) -> hyper::Result<()>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let io = TokioIo::new(io);
+    match version {
+        HttpVersion::Http1 => {
+            server_http1::Builder::new()
+                .serve_connection(io, service)
+                .await
+        }
+        HttpVersion::Http2 => {
+            server_http2::Builder::new(TokioExecutor::new())
+                .serve_connection(io, service)
+                .await
+        }
+    }
+}
+
+/// Request sender for one upstream connection
+pub enum UpstreamSender<B> {
+    Http1(client_http1::SendRequest<B>),
+    Http2(client_http2::SendRequest<B>),
+}
+
+impl<B> UpstreamSender<B>
+where
+    B: Body + Unpin + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Start an HTTP session over an established stream
+    ///
+    /// The connection is driven by a spawned task that ends when the
+    /// sender is dropped or the upstream closes.
+    pub async fn handshake<I>(io: I, version: HttpVersion) -> Result<Self>
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let io = TokioIo::new(io);
+        match version {
+            HttpVersion::Http1 => {
+                let (sender, conn) = client_http1::handshake(io)
+                    .await
+                    .context("HTTP/1.1 handshake failed")?;
+                tokio::spawn(async move {
+                    if let Err(e) = conn.await {
+                        tracing::debug!("Upstream HTTP/1.1 connection closed: {}", e);
+                    }
+                });
+                Ok(UpstreamSender::Http1(sender))
+            }
+            HttpVersion::Http2 => {
+                let (sender, conn) = client_http2::handshake(TokioExecutor::new(), io)
+                    .await
+                    .context("HTTP/2 handshake failed")?;
+                tokio::spawn(async move {
+                    if let Err(e) = conn.await {
+                        tracing::debug!("Upstream HTTP/2 connection closed: {}", e);
+                    }
+                });
+                Ok(UpstreamSender::Http2(sender))
+            }
+        }
+    }
+
+    /// Version this connection speaks
+    pub fn version(&self) -> HttpVersion {
+        match self {
+            UpstreamSender::Http1(_) => HttpVersion::Http1,
+            UpstreamSender::Http2(_) => HttpVersion::Http2,
+        }
+    }
+
+    /// Send a request prepared with [`prepare_upstream_request`]
+    pub async fn send_request(&mut self, request: Request<B>) -> hyper::Result<Response<Incoming>> {
+        match self {
+            UpstreamSender::Http1(sender) => {
+                sender.ready().await?;
+                sender.send_request(request).await
+            }
+            UpstreamSender::Http2(sender) => {
+                sender.ready().await?;
+                sender.send_request(request).await
+            }
+        }
+    }
+}
+
+/// TLS client configuration for upstream providers, offering h2 when enabled
+pub fn upstream_tls_config(roots: RootCertStore, http2: bool) -> Arc<ClientConfig> {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols(http2);
+    Arc::new(config)
+}
+
+/// Open a TLS connection to an upstream and start HTTP on whatever
+/// protocol it agreed to
+pub async fn connect_upstream<B>(
+    connector: &TlsConnector,
+    host: &str,
+    port: u16,
+) -> Result<UpstreamSender<B>>
+where
+    B: Body + Unpin + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let tcp = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("failed to connect to {}:{}", host, port))?;
+    let name = ServerName::try_from(host).with_context(|| format!("invalid host {:?}", host))?;
+    let tls = connector
+        .connect(name, tcp)
+        .await
+        .with_context(|| format!("TLS handshake with {} failed", host))?;
+    let version = HttpVersion::from_alpn(tls.get_ref().1.alpn_protocol());
+    tracing::debug!("Upstream {} negotiated {}", host, version.as_str());
+    UpstreamSender::handshake(tls, version).await
+}
+
+/// Version negotiated on a TLS-terminated client connection
+pub fn negotiated_version(conn: &rustls::ServerConnection) -> HttpVersion {
+    HttpVersion::from_alpn(conn.alpn_protocol())
+}
+
+/// Rewrite a client request for the upstream connection's framing
+///
+/// HTTP/2 carries the target in the URI (`:authority`) and forbids
+/// connection-specific headers; HTTP/1.1 wants an origin-form URI and a
+/// `Host` header. Clients speak either, so both directions are handled.
+pub fn prepare_upstream_request<B>(
+    request: &mut Request<B>,
+    upstream: HttpVersion,
+    host: &str,
+) -> Result<()> {
+    strip_hop_headers(request.headers_mut());
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |p| p.as_str())
+        .to_string();
+    match upstream {
+        HttpVersion::Http1 => {
+            *request.uri_mut() = path.parse::<Uri>().context("invalid request path")?;
+            *request.version_mut() = hyper::Version::HTTP_11;
+            let value = HeaderValue::from_str(host).context("invalid host")?;
+            request.headers_mut().insert(HOST, value);
+        }
+        HttpVersion::Http2 => {
+            *request.uri_mut() = format!("https://{}{}", host, path)
+                .parse::<Uri>()
+                .context("invalid request target")?;
+            *request.version_mut() = hyper::Version::HTTP_2;
+            request.headers_mut().remove(HOST);
+        }
+    }
+    Ok(())
+}
+
+/// Drop connection-specific headers, including any named by `Connection`
+pub fn strip_hop_headers(headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = headers
+        .get_all(hyper::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in named {
+        headers.remove(name);
+    }
+    for name in HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certs::{ca_server_config, test_authority};
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_requests_rewritten_for_upstream_version() {
+        let mut request = Request::builder()
+            .uri("https://api.openai.com/v1/chat/completions?x=1")
+            .header("connection", "keep-alive, x-trace")
+            .header("x-trace", "1")
+            .header("authorization", "Bearer sk-test")
+            .body(())
+            .unwrap();
+        prepare_upstream_request(&mut request, HttpVersion::Http1, "api.openai.com").unwrap();
+        assert_eq!(request.uri(), "/v1/chat/completions?x=1");
+        assert_eq!(request.headers()[HOST], "api.openai.com");
+        assert!(!request.headers().contains_key("connection"));
+        assert!(!request.headers().contains_key("x-trace"));
+        assert!(request.headers().contains_key("authorization"));
+
+        prepare_upstream_request(&mut request, HttpVersion::Http2, "api.openai.com").unwrap();
+        assert_eq!(
+            request.uri(),
+            "https://api.openai.com/v1/chat/completions?x=1"
+        );
+        assert!(!request.headers().contains_key(HOST));
+    }
+
+    #[tokio::test]
+    async fn test_alpn_picks_h2_when_both_sides_offer_it() {
+        let ca = Arc::new(test_authority());
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.ca_der().to_vec()))
+            .unwrap();
+        let acceptor =
+            tokio_rustls::TlsAcceptor::from(ca_server_config(Arc::clone(&ca), true, None));
+
+        for (client_h2, expected) in [(true, HttpVersion::Http2), (false, HttpVersion::Http1)] {
+            let connector = TlsConnector::from(upstream_tls_config(roots.clone(), client_h2));
+            let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+            let name = ServerName::try_from("api.openai.com").unwrap();
+            let (server, client) = tokio::join!(
+                acceptor.accept(server_io),
+                connector.connect(name, client_io)
+            );
+            let server = server.unwrap();
+            client.unwrap();
+            assert_eq!(negotiated_version(server.get_ref().1), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_and_sends_over_h2() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve_connection(
+            server_io,
+            HttpVersion::Http2,
+            hyper::service::service_fn(|req: Request<Incoming>| async move {
+                let body = format!("{:?} {}", req.version(), req.uri().path());
+                Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(body))))
+            }),
+        ));
+
+        let mut sender = UpstreamSender::<Empty<Bytes>>::handshake(client_io, HttpVersion::Http2)
+            .await
+            .unwrap();
+        let mut request = Request::builder()
+            .uri("/v1/models")
+            .body(Empty::new())
+            .unwrap();
+        prepare_upstream_request(&mut request, sender.version(), "api.openai.com").unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/2.0 /v1/models");
+    }
+}
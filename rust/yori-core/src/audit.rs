@@ -0,0 +1,2832 @@
+//! Audit logging of LLM traffic to SQLite
+//!
+//! Every intercepted request produces one or more audit events that are
+//! written to the same `audit_events` table the Python layer and dashboard
+//! already use (see `sql/schema.sql`), so both sides share one database.
+//!
+//! # Prompt de-duplication
+//!
+//! When `AuditConfig::dedup_prompts` is enabled, prompt previews are
+//! fingerprinted during ingestion (see [`crate::dedup`]). The first copy of
+//! a prompt is stored in `audit_prompts`; near-duplicates only store a
+//! `prompt_ref` pointing at it instead of repeating the full preview.
+//!
+//! # Encryption at rest
+//!
+//! With the `sqlcipher` feature, setting `AuditConfig::encryption_key`
+//! encrypts the whole database with SQLCipher using a key derived from the
+//! [`crate::vault`] master key. Builds without the feature refuse to open
+//! an encrypted configuration rather than silently writing plaintext.
+//!
+//! # Live subscriptions
+//!
+//! Every written event is also broadcast on the logger's [`LiveTail`]
+//! channel, so the dashboard's live view follows new events instead of
+//! polling the table: Rust callers use [`AuditLogger::subscribe`], Python
+//! `ProxyServer.subscribe()`, and HTTP clients the admin API's
+//! `/api/audit/stream` (Server-Sent Events). Subscribers that fall behind
+//! skip ahead and are told how many events they missed.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::budget::{budget_reason, BudgetStatus, BUDGET_POLICY};
+use crate::cost::{CostGrouping, CostSummary};
+use crate::dedup::{self, PromptDeduplicator};
+use crate::dlp::{DlpScan, DLP_POLICY};
+use crate::explain::RuleLocation;
+use crate::export::ExportFormat;
+use crate::integrity;
+use crate::limits::{BodyKind, BodyTooLarge};
+use crate::livetail::{LiveEvent, LiveFilter, LiveTail};
+use crate::models::MODEL_POLICY;
+use crate::netaddr::normalize_client_ip;
+use crate::policy::{json_to_py, PolicyEngine};
+use crate::proxy::{RequestContext, ResponseContext};
+use crate::ratelimit::RateDecision;
+use crate::redact::{RedactionConfig, Redactor};
+use crate::replay::PolicyReplay;
+use crate::rollup::{self, RollupGrouping, RollupPeriod, RollupQuery, RollupReport, UsageRollup};
+use crate::tenant::DEFAULT_TENANT;
+use crate::vault::{Vault, AUDIT_DB_PURPOSE};
+
+/// Base audit schema, kept in sync with `sql/schema.sql`
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    client_ip TEXT NOT NULL,
+    client_device TEXT,
+    endpoint TEXT NOT NULL,
+    http_method TEXT NOT NULL,
+    http_path TEXT NOT NULL,
+    prompt_preview TEXT,
+    prompt_tokens INTEGER,
+    contains_sensitive BOOLEAN,
+    response_status INTEGER,
+    response_tokens INTEGER,
+    response_duration_ms INTEGER,
+    policy_name TEXT,
+    policy_result TEXT,
+    policy_reason TEXT,
+    user_agent TEXT,
+    request_id TEXT UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS idx_timestamp ON audit_events(timestamp);
+CREATE INDEX IF NOT EXISTS idx_client_ip ON audit_events(client_ip);
+CREATE INDEX IF NOT EXISTS idx_endpoint ON audit_events(endpoint);
+CREATE INDEX IF NOT EXISTS idx_policy_result ON audit_events(policy_result);
+
+CREATE TABLE IF NOT EXISTS audit_prompts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    simhash INTEGER NOT NULL,
+    preview TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    occurrences INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_prompts_last_seen ON audit_prompts(last_seen);
+";
+
+/// Type of audit event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventType {
+    /// Request intercepted and forwarded
+    Request,
+
+    /// Upstream response received
+    Response,
+
+    /// Request blocked by policy
+    RequestBlocked,
+
+    /// Proxy or upstream error
+    Error,
+
+    /// Policy set reloaded from disk (or a reload failed)
+    PolicyReload,
+
+    /// Request refused with 429 by the proxy rate limiter
+    RateLimited,
+
+    /// Upstream response withheld by a response-phase policy
+    ResponseBlocked,
+
+    /// Secrets found in a forwarded request body (alerted or redacted,
+    /// see [`crate::dlp`])
+    SensitiveData,
+}
+
+impl AuditEventType {
+    /// Value stored in the `event_type` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::Request => "request",
+            AuditEventType::Response => "response",
+            AuditEventType::RequestBlocked => "block",
+            AuditEventType::Error => "error",
+            AuditEventType::PolicyReload => "policy_reload",
+            AuditEventType::RateLimited => "rate_limited",
+            AuditEventType::ResponseBlocked => "response_block",
+            AuditEventType::SensitiveData => "sensitive_data",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditEventType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "request" => Ok(AuditEventType::Request),
+            "response" => Ok(AuditEventType::Response),
+            "block" => Ok(AuditEventType::RequestBlocked),
+            "error" => Ok(AuditEventType::Error),
+            "policy_reload" => Ok(AuditEventType::PolicyReload),
+            "rate_limited" => Ok(AuditEventType::RateLimited),
+            "response_block" => Ok(AuditEventType::ResponseBlocked),
+            "sensitive_data" => Ok(AuditEventType::SensitiveData),
+            other => bail!("unknown audit event type {:?}", other),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for AuditEventType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A single audit log record
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AuditEvent {
+    /// Unique event identifier (stored in the `request_id` column)
+    pub request_id: String,
+
+    /// When the event happened
+    pub timestamp: DateTime<Utc>,
+
+    /// Event type
+    pub event_type: AuditEventType,
+
+    /// Client IP address
+    pub client_ip: String,
+
+    /// Tenant (household) the client belongs to
+    pub tenant: String,
+
+    /// Device name (from DHCP or WireGuard peer), if known
+    pub client_device: Option<String>,
+
+    /// Owner of the device, if known
+    pub client_user: Option<String>,
+
+    /// Target endpoint (e.g., "api.openai.com")
+    pub endpoint: String,
+
+    /// Address the client dialed ("ip:port"), when the firewall reported
+    /// it (see [`crate::origdst`])
+    pub destination: Option<String>,
+
+    /// HTTP method
+    pub http_method: String,
+
+    /// Request path
+    pub http_path: String,
+
+    /// Prompt preview (truncated to `AuditConfig::prompt_preview_chars`)
+    pub prompt_preview: Option<String>,
+
+    /// Prompt token count
+    pub prompt_tokens: Option<u64>,
+
+    /// Whether PII was detected in the prompt
+    pub contains_sensitive: bool,
+
+    /// Upstream HTTP status
+    pub response_status: Option<u16>,
+
+    /// Completion token count
+    pub response_tokens: Option<u64>,
+
+    /// Upstream response time in milliseconds
+    pub response_duration_ms: Option<u64>,
+
+    /// Model the client asked for
+    pub requested_model: Option<String>,
+
+    /// Model that actually served the response
+    pub response_model: Option<String>,
+
+    /// Model the proxy substituted for the requested one before forwarding
+    /// (see [`crate::models`])
+    pub rewritten_model: Option<String>,
+
+    /// Why generation stopped (a "length" here means truncated output)
+    pub finish_reason: Option<String>,
+
+    /// Estimated cost in USD (see [`crate::cost`])
+    pub estimated_cost: Option<f64>,
+
+    /// Provider safety annotations on the response
+    #[serde(default)]
+    pub safety_flags: Vec<String>,
+
+    /// Policy that made the decision
+    pub policy_name: Option<String>,
+
+    /// Policy outcome ("allow", "alert", "block")
+    pub policy_result: Option<String>,
+
+    /// Human-readable policy explanation
+    pub policy_reason: Option<String>,
+
+    /// Request id of the original request when this one is a client retry
+    pub retry_of: Option<String>,
+
+    /// Conversation session the request belongs to (see [`crate::session`])
+    pub session_id: Option<String>,
+
+    /// Content categories of the prompt (see [`crate::classify`])
+    #[serde(default)]
+    pub categories: Vec<String>,
+
+    /// Rule that made the decision, as "file:line" relative to the policy
+    /// directory (lets the dashboard deep-link to the Rego source)
+    pub policy_location: Option<String>,
+
+    /// Client user agent
+    pub user_agent: Option<String>,
+}
+
+impl AuditEvent {
+    /// Create an event from a request context
+    pub fn from_request(event_type: AuditEventType, request: &RequestContext) -> Self {
+        AuditEvent {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            timestamp: request.timestamp,
+            event_type,
+            client_ip: normalize_client_ip(&request.client_ip),
+            tenant: request.tenant.clone(),
+            client_device: request.client_device.clone(),
+            client_user: request.identity.as_ref().and_then(|i| i.owner.clone()),
+            endpoint: request.endpoint.clone(),
+            destination: request.destination.map(|d| d.to_string()),
+            http_method: request.method.clone(),
+            http_path: request.path.clone(),
+            prompt_preview: request.prompt_preview.clone(),
+            prompt_tokens: None,
+            contains_sensitive: false,
+            response_status: None,
+            response_tokens: None,
+            response_duration_ms: None,
+            requested_model: None,
+            response_model: None,
+            rewritten_model: None,
+            finish_reason: None,
+            estimated_cost: None,
+            safety_flags: Vec::new(),
+            policy_name: None,
+            policy_result: None,
+            policy_reason: None,
+            policy_location: None,
+            retry_of: request.retry_of.clone(),
+            session_id: request.session_id.clone(),
+            categories: request.categories.clone(),
+            user_agent: request.user_agent.clone(),
+        }
+    }
+
+    /// Create an event recording a policy reload
+    ///
+    /// A failed reload (`error` set) means the previous policy set is still
+    /// in force.
+    pub fn policy_reload(policy_dir: &Path, policies: usize, error: Option<&str>) -> Self {
+        let ctx = RequestContext {
+            client_ip: "127.0.0.1".to_string(),
+            tenant: DEFAULT_TENANT.to_string(),
+            scope: None,
+            client_device: Some("yori".to_string()),
+            identity: None,
+            endpoint: "policy-engine".to_string(),
+            destination: None,
+            method: "RELOAD".to_string(),
+            path: policy_dir.display().to_string(),
+            user_agent: None,
+            prompt_preview: None,
+            timestamp: Utc::now(),
+            retry_of: None,
+            session_id: None,
+            categories: Vec::new(),
+            parsed: None,
+        };
+        let event = AuditEvent::from_request(AuditEventType::PolicyReload, &ctx);
+        match error {
+            None => event.with_policy(
+                "policy_reload",
+                "reloaded",
+                &format!("Loaded {} policies", policies),
+            ),
+            Some(error) => event.with_policy(
+                "policy_reload",
+                "failed",
+                &format!("Reload failed, previous policies kept: {}", error),
+            ),
+        }
+    }
+
+    /// Create an event for a request refused by the rate limiter
+    pub fn rate_limited(request: &RequestContext, decision: &RateDecision) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::RateLimited, request).with_policy(
+            "rate_limit",
+            "block",
+            &format!(
+                "Rate limit of {} requests/minute (burst {}) exceeded for {}; retry in {}s",
+                decision.limit,
+                decision.burst,
+                request.endpoint,
+                decision.reset_secs.max(1)
+            ),
+        );
+        event.response_status = Some(429);
+        event
+    }
+
+    /// Create an error event for a request the upstream failed (or its
+    /// open circuit refused); the client got a 502
+    pub fn upstream_error(request: &RequestContext, reason: &str) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::Error, request)
+            .with_policy("upstream", "error", reason);
+        event.response_status = Some(502);
+        event
+    }
+
+    /// Create an event for a request refused (413) or an upstream response
+    /// dropped (502) for exceeding its size limit
+    pub fn body_too_large(request: &RequestContext, error: &BodyTooLarge) -> Self {
+        let (event_type, status) = match error.kind {
+            BodyKind::Request => (AuditEventType::RequestBlocked, 413),
+            BodyKind::Response => (AuditEventType::Error, 502),
+        };
+        let mut event = AuditEvent::from_request(event_type, request).with_policy(
+            "size_limit",
+            "block",
+            &error.to_string(),
+        );
+        event.response_status = Some(status);
+        event
+    }
+
+    /// Create an event for an upstream response withheld by policy
+    ///
+    /// `response` is what the upstream sent; the client got a 403 instead.
+    pub fn response_blocked(
+        request: &RequestContext,
+        response: &ResponseContext,
+        policy: &str,
+        reason: &str,
+    ) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::ResponseBlocked, request)
+            .with_response(response)
+            .with_policy(policy, "block", reason);
+        event.response_status = Some(403);
+        event
+    }
+
+    /// Attach a prompt preview, truncated to `max_chars` characters
+    ///
+    /// PII is redacted by [`AuditLogger::log`] (per
+    /// [`AuditConfig::redaction`]) before the preview is stored.
+    pub fn with_prompt(mut self, prompt: &str, max_chars: usize) -> Self {
+        self.prompt_preview = Some(prompt.chars().take(max_chars).collect());
+        self
+    }
+
+    /// Attach upstream response details
+    pub fn with_response(mut self, response: &ResponseContext) -> Self {
+        self.response_status = Some(response.status);
+        self.response_duration_ms = Some(response.duration_ms);
+        self.response_tokens = response.tokens.map(|t| t as u64);
+        self.response_model = response.model.clone();
+        self.finish_reason = response.finish_reason.clone();
+        self.safety_flags = response.safety_flags.clone();
+        self.estimated_cost = response.cost_usd;
+        self
+    }
+
+    /// Record the model the client requested
+    pub fn with_requested_model(mut self, model: Option<&str>) -> Self {
+        self.requested_model = model.map(str::to_string);
+        self
+    }
+
+    /// Record that the requested model was replaced before forwarding
+    pub fn with_model_rewrite(mut self, from: &str, to: &str) -> Self {
+        self.requested_model = Some(from.to_string());
+        self.rewritten_model = Some(to.to_string());
+        self
+    }
+
+    /// Create an event for a request refused for its model (403)
+    pub fn model_blocked(request: &RequestContext, model: &str, reason: &str) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::RequestBlocked, request)
+            .with_requested_model(Some(model))
+            .with_policy(MODEL_POLICY, "block", reason);
+        event.response_status = Some(403);
+        event
+    }
+
+    /// Create a blocked event for a request refused by the hard budget cap
+    pub fn budget_exceeded(request: &RequestContext, subject: &str, status: &BudgetStatus) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::RequestBlocked, request)
+            .with_policy(BUDGET_POLICY, "block", &budget_reason(subject, status));
+        event.response_status = Some(403);
+        event
+    }
+
+    /// Create a blocked event for a request refused by a DLP rule (403)
+    pub fn dlp_blocked(request: &RequestContext, scan: &DlpScan) -> Self {
+        let mut event = AuditEvent::from_request(AuditEventType::RequestBlocked, request)
+            .with_policy(DLP_POLICY, "block", &scan.reason());
+        event.contains_sensitive = true;
+        event.response_status = Some(403);
+        event
+    }
+
+    /// Create an event for secrets forwarded as is or redacted
+    pub fn sensitive_data(request: &RequestContext, scan: &DlpScan) -> Self {
+        let action = scan.action().map_or("alert", |a| a.as_str());
+        let mut event = AuditEvent::from_request(AuditEventType::SensitiveData, request)
+            .with_policy(DLP_POLICY, action, &scan.reason());
+        event.contains_sensitive = true;
+        event
+    }
+
+    /// Whether the provider served a different model than requested
+    ///
+    /// Providers commonly answer an alias ("gpt-4o") with a dated snapshot
+    /// ("gpt-4o-2024-08-06"), which doesn't count as a mismatch; a different
+    /// variant ("gpt-4o-mini") does.
+    pub fn model_mismatch(&self) -> bool {
+        let requested = self
+            .rewritten_model
+            .as_ref()
+            .or(self.requested_model.as_ref());
+        match (requested, &self.response_model) {
+            (Some(requested), Some(served)) => {
+                let requested = requested.rsplit('/').next().unwrap_or(requested);
+                match served.strip_prefix(requested) {
+                    Some(suffix) => {
+                        let mut chars = suffix.chars();
+                        !(suffix.is_empty()
+                            || (chars.next() == Some('-')
+                                && chars.next().is_some_and(|c| c.is_ascii_digit())))
+                    }
+                    None => true,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Attach the policy decision
+    pub fn with_policy(mut self, name: &str, result: &str, reason: &str) -> Self {
+        self.policy_name = Some(name.to_string());
+        self.policy_result = Some(result.to_string());
+        self.policy_reason = Some(reason.to_string());
+        self
+    }
+
+    /// Attach the location of the rule that made the decision
+    pub fn with_rule_location(mut self, location: &RuleLocation) -> Self {
+        self.policy_location = Some(format!("{}:{}", location.file, location.line));
+        self
+    }
+
+    /// Parse the [`to_json`](Self::to_json) representation back (used by
+    /// the audit spool, see [`crate::spool`])
+    pub fn from_json(value: serde_json::Value) -> Result<Self> {
+        serde_json::from_value(value).context("invalid audit event JSON")
+    }
+
+    /// JSON representation used by the live-tail stream
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "request_id": self.request_id,
+            "timestamp": self.timestamp.to_rfc3339(),
+            "event_type": self.event_type.as_str(),
+            "client_ip": self.client_ip,
+            "tenant": self.tenant,
+            "client_device": self.client_device,
+            "client_user": self.client_user,
+            "endpoint": self.endpoint,
+            "destination": self.destination,
+            "http_method": self.http_method,
+            "http_path": self.http_path,
+            "prompt_preview": self.prompt_preview,
+            "prompt_tokens": self.prompt_tokens,
+            "contains_sensitive": self.contains_sensitive,
+            "response_status": self.response_status,
+            "response_tokens": self.response_tokens,
+            "response_duration_ms": self.response_duration_ms,
+            "requested_model": self.requested_model,
+            "response_model": self.response_model,
+            "rewritten_model": self.rewritten_model,
+            "finish_reason": self.finish_reason,
+            "estimated_cost": self.estimated_cost,
+            "safety_flags": self.safety_flags,
+            "policy_name": self.policy_name,
+            "policy_result": self.policy_result,
+            "policy_reason": self.policy_reason,
+            "policy_location": self.policy_location,
+            "retry_of": self.retry_of,
+            "session_id": self.session_id,
+            "categories": self.categories,
+            "user_agent": self.user_agent,
+        })
+    }
+}
+
+/// Audit logging configuration
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// SQLite database path (":memory:" for an ephemeral store)
+    pub database: PathBuf,
+
+    /// How long to keep audit logs
+    pub retention_days: u32,
+
+    /// Maximum characters of prompt text kept in previews
+    pub prompt_preview_chars: usize,
+
+    /// Store near-duplicate prompts as references to a canonical copy
+    pub dedup_prompts: bool,
+
+    /// Maximum SimHash distance for two prompts to count as duplicates (0-3)
+    pub dedup_max_distance: u32,
+
+    /// Vault key file used to encrypt the database (None = plaintext)
+    pub encryption_key: Option<PathBuf>,
+
+    /// PII detectors run on prompt previews before they are stored
+    pub redaction: RedactionConfig,
+
+    /// Chain each event to the previous one with a SHA-256 hash (see
+    /// [`crate::integrity`])
+    pub hash_chain: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            database: PathBuf::from("/var/db/yori/audit.db"),
+            retention_days: 365,
+            prompt_preview_chars: 200,
+            dedup_prompts: true,
+            dedup_max_distance: dedup::DEFAULT_MAX_DISTANCE,
+            encryption_key: None,
+            redaction: RedactionConfig::default(),
+            hash_chain: false,
+        }
+    }
+}
+
+/// Outcome of one [`AuditLogger::run_maintenance`] pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    /// A full VACUUM was run to switch the database to incremental auto-vacuum
+    pub converted_to_incremental: bool,
+
+    /// Free pages returned to the filesystem
+    pub pages_freed: u64,
+
+    /// WAL frames copied back into the database
+    pub frames_checkpointed: u64,
+
+    /// Wall-clock time of the pass
+    pub duration_ms: u64,
+}
+
+/// Outcome of one [`AuditLogger::prune_old_logs`] pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Events older than the retention window that were deleted
+    pub events_deleted: u64,
+
+    /// Canonical prompts no longer seen within the retention window
+    pub prompts_deleted: u64,
+
+    /// Wall-clock time of the pass
+    pub duration_ms: u64,
+}
+
+/// Rows deleted per statement while pruning, so logging isn't held up for
+/// the whole pass
+const PRUNE_BATCH_ROWS: i64 = 5_000;
+
+/// SQLite-backed audit logger
+pub struct AuditLogger {
+    config: AuditConfig,
+    conn: Mutex<Connection>,
+    dedup: Option<Mutex<PromptDeduplicator>>,
+    redactor: Redactor,
+    live: LiveTail,
+    /// Table columns, kept when events are hash-chained
+    chain_columns: Option<Vec<AuditColumn>>,
+}
+
+impl AuditLogger {
+    /// Open (or create) the audit database
+    pub fn open(config: AuditConfig) -> Result<Self> {
+        let conn = Connection::open(&config.database).with_context(|| {
+            format!(
+                "failed to open audit database {}",
+                config.database.display()
+            )
+        })?;
+        if let Some(key_path) = &config.encryption_key {
+            unlock(&conn, key_path)?;
+        }
+        // WAL keeps dashboard reads from blocking proxy writes; incremental
+        // auto-vacuum (new databases only) lets maintenance reclaim space in
+        // small steps instead of rewriting the whole file
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL")?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        conn.execute_batch(SCHEMA)
+            .context("failed to initialize audit schema")?;
+        ensure_column(&conn, "audit_events", "prompt_ref", "INTEGER")?;
+        ensure_column(
+            &conn,
+            "audit_events",
+            "tenant",
+            "TEXT NOT NULL DEFAULT 'default'",
+        )?;
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_tenant ON audit_events(tenant)")?;
+        ensure_column(&conn, "audit_events", "policy_location", "TEXT")?;
+        ensure_column(&conn, "audit_events", "retry_of", "TEXT")?;
+        ensure_column(&conn, "audit_events", "requested_model", "TEXT")?;
+        ensure_column(&conn, "audit_events", "response_model", "TEXT")?;
+        ensure_column(&conn, "audit_events", "rewritten_model", "TEXT")?;
+        ensure_column(&conn, "audit_events", "finish_reason", "TEXT")?;
+        ensure_column(&conn, "audit_events", "client_user", "TEXT")?;
+        ensure_column(&conn, "audit_events", "estimated_cost", "REAL")?;
+        // Comma-separated provider safety flags
+        ensure_column(&conn, "audit_events", "safety_flags", "TEXT")?;
+        ensure_column(&conn, "audit_events", "prev_hash", "TEXT")?;
+        ensure_column(&conn, "audit_events", "event_hash", "TEXT")?;
+        ensure_column(&conn, "audit_events", "session_id", "TEXT")?;
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_session_id ON audit_events(session_id)",
+        )?;
+        // Comma-separated content categories
+        ensure_column(&conn, "audit_events", "categories", "TEXT")?;
+        ensure_column(&conn, "audit_events", "destination", "TEXT")?;
+        rollup::ensure_schema(&conn).context("failed to initialize usage rollups")?;
+        let chain_columns = if config.hash_chain {
+            Some(table_columns(&conn)?)
+        } else {
+            None
+        };
+
+        let dedup = if config.dedup_prompts {
+            let mut index =
+                PromptDeduplicator::new(config.dedup_max_distance, dedup::DEFAULT_INDEX_CAPACITY);
+            let mut stmt = conn.prepare(
+                "SELECT id, simhash FROM audit_prompts ORDER BY last_seen DESC LIMIT ?1",
+            )?;
+            let rows = stmt.query_map([dedup::DEFAULT_INDEX_CAPACITY as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })?;
+            let mut recent = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+            recent.reverse();
+            for (id, fingerprint) in recent {
+                index.insert(fingerprint as u64, id);
+            }
+            drop(stmt);
+            Some(Mutex::new(index))
+        } else {
+            None
+        };
+
+        Ok(AuditLogger {
+            redactor: Redactor::new(&config.redaction).context("invalid PII redaction rule")?,
+            config,
+            conn: Mutex::new(conn),
+            dedup,
+            live: LiveTail::default(),
+            chain_columns,
+        })
+    }
+
+    /// Publish logged events to a shared live-tail channel (e.g., the
+    /// proxy's, so subscribers also see status changes) instead of the
+    /// logger's own
+    pub fn with_live_tail(mut self, live: LiveTail) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Channel logged events are published to
+    pub fn live_tail(&self) -> LiveTail {
+        self.live.clone()
+    }
+
+    /// Receive every event logged from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.live.subscribe()
+    }
+
+    /// Audit configuration in use
+    pub fn config(&self) -> &AuditConfig {
+        &self.config
+    }
+
+    /// Write an event to the audit database
+    ///
+    /// # Returns
+    ///
+    /// Row id of the inserted event
+    pub fn log(&self, event: &AuditEvent) -> Result<i64> {
+        let event = self.redact(event);
+        let event = event.as_ref();
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let (preview, prompt_ref) = match (&self.dedup, &event.prompt_preview) {
+            (Some(dedup), Some(preview)) => {
+                self.dedup_prompt(&tx, dedup, preview, event.timestamp)?
+            }
+            _ => (event.prompt_preview.clone(), None),
+        };
+
+        tx.execute(
+            "INSERT INTO audit_events (
+                timestamp, event_type, client_ip, client_device, endpoint,
+                http_method, http_path, prompt_preview, prompt_tokens, contains_sensitive,
+                response_status, response_tokens, response_duration_ms,
+                policy_name, policy_result, policy_reason, user_agent, request_id, prompt_ref, tenant,
+                policy_location, retry_of, requested_model, response_model, finish_reason, safety_flags,
+                rewritten_model, client_user, estimated_cost, session_id, categories, destination
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20,
+                      ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)",
+            params![
+                event.timestamp.to_rfc3339(),
+                event.event_type.as_str(),
+                event.client_ip,
+                event.client_device,
+                event.endpoint,
+                event.http_method,
+                event.http_path,
+                preview,
+                event.prompt_tokens.map(|t| t as i64),
+                event.contains_sensitive,
+                event.response_status,
+                event.response_tokens.map(|t| t as i64),
+                event.response_duration_ms.map(|t| t as i64),
+                event.policy_name,
+                event.policy_result,
+                event.policy_reason,
+                event.user_agent,
+                event.request_id,
+                prompt_ref,
+                event.tenant,
+                event.policy_location,
+                event.retry_of,
+                event.requested_model,
+                event.response_model,
+                event.finish_reason,
+                (!event.safety_flags.is_empty()).then(|| event.safety_flags.join(",")),
+                event.rewritten_model,
+                event.client_user,
+                event.estimated_cost,
+                event.session_id,
+                (!event.categories.is_empty()).then(|| event.categories.join(",")),
+                event.destination,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        if let Some(columns) = &self.chain_columns {
+            integrity::chain_event(&tx, columns, id)?;
+        }
+        tx.commit()?;
+
+        // Skip the copy when nobody is listening
+        if self.live.subscriber_count() > 0 {
+            self.live.publish(LiveEvent::Audit(Box::new(event.clone())));
+        }
+        Ok(id)
+    }
+
+    /// Redact PII from an event's prompt preview, marking the event as
+    /// sensitive if anything was found
+    fn redact<'a>(&self, event: &'a AuditEvent) -> Cow<'a, AuditEvent> {
+        let Some(preview) = event.prompt_preview.as_deref() else {
+            return Cow::Borrowed(event);
+        };
+        let redaction = self.redactor.redact(preview);
+        if redaction.found.is_empty() {
+            return Cow::Borrowed(event);
+        }
+        let mut event = event.clone();
+        event.prompt_preview = Some(redaction.text);
+        event.contains_sensitive = true;
+        Cow::Owned(event)
+    }
+
+    /// Resolve a prompt preview to a canonical prompt row
+    ///
+    /// Returns the preview to store inline (only for the first copy) and the
+    /// canonical prompt id to reference.
+    fn dedup_prompt(
+        &self,
+        conn: &Connection,
+        dedup: &Mutex<PromptDeduplicator>,
+        preview: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(Option<String>, Option<i64>)> {
+        let fingerprint = dedup::simhash(preview);
+        let mut index = dedup.lock().unwrap();
+
+        if let Some(id) = index.find(fingerprint) {
+            let updated = conn.execute(
+                "UPDATE audit_prompts SET occurrences = occurrences + 1, last_seen = ?1 WHERE id = ?2",
+                params![timestamp.to_rfc3339(), id],
+            )?;
+            if updated == 1 {
+                return Ok((None, Some(id)));
+            }
+        }
+
+        conn.execute(
+            "INSERT INTO audit_prompts (simhash, preview, first_seen, last_seen) VALUES (?1, ?2, ?3, ?3)",
+            params![fingerprint as i64, preview, timestamp.to_rfc3339()],
+        )?;
+        let id = conn.last_insert_rowid();
+        index.insert(fingerprint, id);
+        Ok((Some(preview.to_string()), Some(id)))
+    }
+
+    /// Run database upkeep: incremental vacuum, ANALYZE and a WAL checkpoint
+    ///
+    /// Meant to run during quiet hours (see [`crate::maintenance`]). At most
+    /// `vacuum_pages` free pages are reclaimed per run so a single pass
+    /// stays short on slow flash. A database created before incremental
+    /// auto-vacuum was enabled is converted with one full VACUUM first.
+    pub fn run_maintenance(&self, vacuum_pages: u32) -> Result<MaintenanceReport> {
+        let started = std::time::Instant::now();
+        let conn = self.conn.lock().unwrap();
+        let pragma =
+            |name: &str| conn.query_row(&format!("PRAGMA {name}"), [], |r| r.get::<_, i64>(0));
+
+        let mut converted = false;
+        if pragma("auto_vacuum")? != 2 {
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+            converted = true;
+        }
+
+        let free_before = pragma("freelist_count")?;
+        // Each step of the pragma frees one page, so drain it to completion
+        let mut stmt = conn.prepare(&format!("PRAGMA incremental_vacuum({vacuum_pages})"))?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
+        drop(rows);
+        drop(stmt);
+        let pages_freed = (free_before - pragma("freelist_count")?).max(0) as u64;
+
+        conn.execute_batch("ANALYZE")?;
+
+        // (busy, log frames, checkpointed frames); -1s outside WAL mode
+        let (_, _, checkpointed) = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, i64>(2)?,
+            ))
+        })?;
+
+        Ok(MaintenanceReport {
+            converted_to_incremental: converted,
+            pages_freed,
+            frames_checkpointed: checkpointed.max(0) as u64,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Delete events older than `retention_days`
+    ///
+    /// Canonical prompts last seen before the cutoff go with them. Rows are
+    /// deleted in batches, releasing the database between them, and the
+    /// freed pages are left for [`AuditLogger::run_maintenance`] to reclaim.
+    /// A hash chain stays verifiable since its oldest remaining event is
+    /// taken on trust (see [`crate::integrity`]).
+    pub fn prune_old_logs(&self) -> Result<PruneReport> {
+        let cutoff = Utc::now() - chrono::Duration::days(i64::from(self.config.retention_days));
+        self.prune_before(cutoff)
+    }
+
+    /// Delete events (and canonical prompts last seen) before `cutoff`
+    pub fn prune_before(&self, cutoff: DateTime<Utc>) -> Result<PruneReport> {
+        let started = std::time::Instant::now();
+        let cutoff = cutoff.to_rfc3339();
+        let delete_batch = |sql: &str| -> Result<u64> {
+            let mut deleted = 0;
+            loop {
+                let conn = self.conn.lock().unwrap();
+                let rows = conn
+                    .execute(sql, params![cutoff, PRUNE_BATCH_ROWS])
+                    .context("failed to prune audit log")?;
+                deleted += rows as u64;
+                if (rows as i64) < PRUNE_BATCH_ROWS {
+                    return Ok(deleted);
+                }
+            }
+        };
+
+        let events_deleted = delete_batch(
+            "DELETE FROM audit_events WHERE id IN \
+             (SELECT id FROM audit_events WHERE timestamp < ?1 ORDER BY id LIMIT ?2)",
+        )?;
+        let prompts_deleted = delete_batch(
+            "DELETE FROM audit_prompts WHERE id IN \
+             (SELECT id FROM audit_prompts WHERE last_seen < ?1 ORDER BY id LIMIT ?2)",
+        )?;
+
+        Ok(PruneReport {
+            events_deleted,
+            prompts_deleted,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Copy the write-ahead log back into the database file
+    ///
+    /// Events are committed as they're logged; this just leaves a
+    /// self-contained database behind when the proxy stops. Returns the
+    /// number of WAL frames checkpointed.
+    pub fn flush(&self) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let checkpointed: i64 =
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |r| r.get(2))?;
+        Ok(checkpointed.max(0) as u64)
+    }
+
+    /// Check that the database answers a query (for readiness probes)
+    pub fn ping(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM audit_events WHERE id = 0", [], |r| {
+            r.get::<_, i64>(0)
+        })?;
+        Ok(())
+    }
+
+    /// Look up the canonical prompt text for an event's `prompt_ref`
+    pub fn canonical_prompt(&self, prompt_ref: i64) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT preview FROM audit_prompts WHERE id = ?1",
+                [prompt_ref],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Fetch events matching a filter, newest first
+    ///
+    /// Each row is returned as a JSON object keyed by column name, so
+    /// columns added by later migrations show up without changes here.
+    pub fn query(
+        &self,
+        query: &AuditQuery,
+    ) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+        let (clause, args) = query.where_clause();
+        let mut sql =
+            format!("SELECT * FROM audit_events WHERE {clause} ORDER BY timestamp DESC, id DESC");
+        // SQLite only takes OFFSET after a LIMIT; -1 means no limit
+        match (query.limit, query.offset) {
+            (Some(limit), offset) => {
+                sql.push_str(&format!(" LIMIT {limit} OFFSET {}", offset.unwrap_or(0)))
+            }
+            (None, Some(offset)) => sql.push_str(&format!(" LIMIT -1 OFFSET {offset}")),
+            (None, None) => {}
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+            let mut record = serde_json::Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                record.insert(column.clone(), sql_to_json(row.get_ref(i)?));
+            }
+            Ok(record)
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Columns of the `audit_events` table, in table order
+    pub(crate) fn columns(&self) -> Result<Vec<AuditColumn>> {
+        table_columns(&self.conn.lock().unwrap())
+    }
+
+    /// Walk every event matching a filter, oldest first, `batch_rows` at a
+    /// time
+    ///
+    /// The database lock is only held while a batch is read, so a long
+    /// export doesn't stall the proxy's writes. `f` gets each batch's rows,
+    /// with values in [`columns`](Self::columns) order. The query's `limit`
+    /// caps the rows visited; its `offset` is ignored. Returns the number of
+    /// rows visited.
+    pub(crate) fn scan(
+        &self,
+        query: &AuditQuery,
+        batch_rows: usize,
+        mut f: impl FnMut(&[Vec<Value>]) -> Result<()>,
+    ) -> Result<u64> {
+        let columns = self.columns()?;
+        let names = columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let id_index = columns
+            .iter()
+            .position(|c| c.name == "id")
+            .context("audit_events has no id column")?;
+
+        let (clause, mut args) = query.where_clause();
+        let sql = format!(
+            "SELECT {names} FROM audit_events WHERE {clause} AND id > ?{} ORDER BY id LIMIT ?{}",
+            args.len() + 1,
+            args.len() + 2
+        );
+        args.push(Box::new(0i64));
+        args.push(Box::new(0i64));
+        let cursor = args.len() - 2;
+
+        let mut remaining = query.limit.unwrap_or(usize::MAX);
+        let mut visited = 0u64;
+        let mut last_id = 0i64;
+        while remaining > 0 {
+            let take = batch_rows.min(remaining);
+            args[cursor] = Box::new(last_id);
+            args[cursor + 1] = Box::new(take as i64);
+            let rows = {
+                let conn = self.conn.lock().unwrap();
+                let mut stmt = conn.prepare(&sql)?;
+                let rows = stmt
+                    .query_map(rusqlite::params_from_iter(args.iter()), |row| {
+                        (0..columns.len())
+                            .map(|i| row.get::<_, Value>(i))
+                            .collect::<rusqlite::Result<Vec<_>>>()
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                rows
+            };
+            let Some(last) = rows.last() else {
+                break;
+            };
+            if let Value::Integer(id) = last[id_index] {
+                last_id = id;
+            }
+            f(&rows)?;
+            visited += rows.len() as u64;
+            remaining -= rows.len();
+            if rows.len() < take {
+                break;
+            }
+        }
+        Ok(visited)
+    }
+
+    /// Number of events matching a filter, ignoring its limit and offset
+    pub fn count(&self, query: &AuditQuery) -> Result<u64> {
+        let (clause, args) = query.where_clause();
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM audit_events WHERE {clause}"),
+            rusqlite::params_from_iter(args.iter()),
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Per-device request, token and block counts since `since`
+    ///
+    /// Retries are left out, matching how the proxy counts quota usage.
+    pub fn device_usage_since(&self, since: DateTime<Utc>) -> Result<Vec<DeviceUsage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT tenant, client_ip,
+                    COUNT(*),
+                    COALESCE(SUM(response_tokens), 0),
+                    SUM(CASE WHEN policy_result = 'block' THEN 1 ELSE 0 END)
+             FROM audit_events
+             WHERE timestamp >= ?1 AND retry_of IS NULL
+             GROUP BY tenant, client_ip
+             ORDER BY 4 DESC",
+        )?;
+        let rows = stmt.query_map([since.to_rfc3339()], |row| {
+            Ok(DeviceUsage {
+                tenant: row.get(0)?,
+                device: row.get(1)?,
+                requests: row.get::<_, i64>(2)? as u64,
+                tokens: row.get::<_, i64>(3)? as u64,
+                blocks: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Estimated cost of the events matching a filter, grouped by user,
+    /// device, model or day (and, with `daily`, by UTC day within that)
+    ///
+    /// Only events with token counts or a cost are counted, retries left
+    /// out; groups come most expensive first. The query's limit and offset
+    /// are ignored.
+    pub fn cost_summary(
+        &self,
+        query: &AuditQuery,
+        grouping: CostGrouping,
+        daily: bool,
+    ) -> Result<Vec<CostSummary>> {
+        let (clause, args) = query.where_clause();
+        let key = grouping.sql_key();
+        let day = if daily {
+            CostGrouping::Day.sql_key()
+        } else {
+            "NULL"
+        };
+        let sql = format!(
+            "SELECT {key}, {day},
+                    COUNT(*),
+                    COALESCE(SUM(prompt_tokens), 0),
+                    COALESCE(SUM(response_tokens), 0),
+                    COALESCE(SUM(estimated_cost), 0.0),
+                    SUM(CASE WHEN estimated_cost IS NULL THEN 1 ELSE 0 END)
+             FROM audit_events
+             WHERE {clause} AND retry_of IS NULL
+               AND (estimated_cost IS NOT NULL OR response_tokens IS NOT NULL)
+             GROUP BY 1, 2
+             ORDER BY 6 DESC, 1, 2"
+        );
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+            Ok(CostSummary {
+                key: row.get(0)?,
+                day: row.get(1)?,
+                requests: row.get::<_, i64>(2)? as u64,
+                prompt_tokens: row.get::<_, i64>(3)? as u64,
+                response_tokens: row.get::<_, i64>(4)? as u64,
+                cost_usd: row.get(5)?,
+                unpriced: row.get::<_, i64>(6)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Fold events since each period's open bucket into the daily and
+    /// weekly usage rollups (see [`crate::rollup`])
+    pub fn refresh_rollups(&self, now: DateTime<Utc>) -> Result<RollupReport> {
+        let conn = self.conn.lock().unwrap();
+        rollup::refresh(&conn, now)
+    }
+
+    /// Daily or weekly usage from the rollup tables, oldest bucket first
+    pub fn usage_rollups(&self, query: &RollupQuery) -> Result<Vec<UsageRollup>> {
+        let conn = self.conn.lock().unwrap();
+        rollup::query(&conn, query)
+    }
+
+    /// Requests, tokens and estimated cost of each conversation session
+    /// among the events matching a filter
+    ///
+    /// Retries are left out. Sessions come most recent first; the query's
+    /// limit caps the number of sessions, its offset is ignored.
+    pub fn session_totals(&self, query: &AuditQuery) -> Result<Vec<SessionTotals>> {
+        let (clause, mut args) = query.where_clause();
+        args.push(Box::new(query.limit.map_or(-1, |limit| limit as i64)));
+        let sql = format!(
+            "SELECT session_id, MIN(tenant), MIN(client_ip), MIN(endpoint),
+                    MIN(timestamp), MAX(timestamp),
+                    COUNT(*),
+                    COALESCE(SUM(prompt_tokens), 0),
+                    COALESCE(SUM(response_tokens), 0),
+                    COALESCE(SUM(estimated_cost), 0.0)
+             FROM audit_events
+             WHERE {clause} AND session_id IS NOT NULL AND retry_of IS NULL
+             GROUP BY 1
+             ORDER BY 6 DESC
+             LIMIT ?{}",
+            args.len()
+        );
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+            Ok(SessionTotals {
+                session_id: row.get(0)?,
+                tenant: row.get(1)?,
+                device: row.get(2)?,
+                endpoint: row.get(3)?,
+                started: row.get(4)?,
+                last_seen: row.get(5)?,
+                requests: row.get::<_, i64>(6)? as u64,
+                prompt_tokens: row.get::<_, i64>(7)? as u64,
+                response_tokens: row.get::<_, i64>(8)? as u64,
+                cost_usd: row.get(9)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Totals over the events matching a filter, for summary cards
+    ///
+    /// The query's limit and offset are ignored.
+    pub fn stats(&self, query: &AuditQuery) -> Result<AuditStats> {
+        let (clause, args) = query.where_clause();
+        let sql = format!(
+            "SELECT {STATS_COLUMNS},
+                    COUNT(DISTINCT client_ip),
+                    MIN(timestamp),
+                    MAX(timestamp)
+             FROM audit_events
+             WHERE {clause}"
+        );
+        let conn = self.conn.lock().unwrap();
+        let stats = conn.query_row(&sql, rusqlite::params_from_iter(args.iter()), |row| {
+            Ok(AuditStats {
+                counts: stats_counts(row, 0)?,
+                clients: row.get::<_, i64>(5)? as u64,
+                oldest: row.get(6)?,
+                newest: row.get(7)?,
+            })
+        })?;
+        Ok(stats)
+    }
+
+    /// Totals over the events matching a filter, per endpoint, client or
+    /// day
+    ///
+    /// Endpoints and clients come busiest first, days in order. The
+    /// query's limit caps the number of groups; its offset is ignored.
+    pub fn grouped_stats(
+        &self,
+        query: &AuditQuery,
+        grouping: StatsGrouping,
+    ) -> Result<Vec<GroupStats>> {
+        let (clause, mut args) = query.where_clause();
+        let key = grouping.sql_key();
+        let order = match grouping {
+            StatsGrouping::Day => "1",
+            StatsGrouping::Endpoint | StatsGrouping::Client => "2 DESC, 1",
+        };
+        args.push(Box::new(query.limit.map_or(-1, |limit| limit as i64)));
+        let sql = format!(
+            "SELECT {key}, {STATS_COLUMNS}
+             FROM audit_events
+             WHERE {clause}
+             GROUP BY 1
+             ORDER BY {order}
+             LIMIT ?{}",
+            args.len()
+        );
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+            Ok(GroupStats {
+                key: row.get(0)?,
+                counts: stats_counts(row, 1)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Size and effectiveness of the prompt de-duplication cache
+    pub fn prompt_cache_stats(&self) -> Result<PromptCacheStats> {
+        let conn = self.conn.lock().unwrap();
+        let (canonical, occurrences): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(occurrences), 0) FROM audit_prompts",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        Ok(PromptCacheStats {
+            canonical_prompts: canonical as u64,
+            occurrences: occurrences as u64,
+        })
+    }
+}
+
+/// Filter for [`AuditLogger::query`]; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Only events at or after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only events before this time
+    pub until: Option<DateTime<Utc>>,
+
+    /// Only events from this tenant
+    pub tenant: Option<String>,
+
+    /// Only events from this client IP
+    pub client_ip: Option<String>,
+
+    /// Only events for this endpoint
+    pub endpoint: Option<String>,
+
+    /// Only events of this type ("request", "block", ..., see
+    /// [`AuditEventType::as_str`])
+    pub event_type: Option<String>,
+
+    /// Only events with this policy result ("allow", "block", ...)
+    pub policy_result: Option<String>,
+
+    /// Maximum number of events returned
+    pub limit: Option<usize>,
+
+    /// Number of matching events to skip (for paging)
+    pub offset: Option<usize>,
+}
+
+/// A column of the `audit_events` table
+#[derive(Debug, Clone)]
+pub(crate) struct AuditColumn {
+    /// Column name
+    pub name: String,
+
+    /// Declared SQL type, upper-cased ("INTEGER", "TEXT", ...)
+    #[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+    pub decl_type: String,
+}
+
+fn table_columns(conn: &Connection) -> Result<Vec<AuditColumn>> {
+    let mut stmt = conn.prepare("PRAGMA table_info(audit_events)")?;
+    let columns = stmt
+        .query_map([], |row| {
+            Ok(AuditColumn {
+                name: row.get(1)?,
+                decl_type: row.get::<_, String>(2)?.to_ascii_uppercase(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+/// JSON form of a stored value (blobs as hex)
+pub(crate) fn sql_to_json(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+        ValueRef::Blob(b) => hex::encode(b).into(),
+    }
+}
+
+impl AuditQuery {
+    /// SQL condition and its bound parameters
+    fn where_clause(&self) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let mut clause = String::from("1 = 1");
+        let mut args: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        let mut filter = |condition: &str, value: Box<dyn rusqlite::ToSql>| {
+            args.push(value);
+            clause.push_str(&format!(" AND {condition} ?{}", args.len()));
+        };
+        if let Some(since) = self.since {
+            filter("timestamp >=", Box::new(since.to_rfc3339()));
+        }
+        if let Some(until) = self.until {
+            filter("timestamp <", Box::new(until.to_rfc3339()));
+        }
+        if let Some(tenant) = &self.tenant {
+            filter("tenant =", Box::new(tenant.clone()));
+        }
+        if let Some(client_ip) = &self.client_ip {
+            filter("client_ip =", Box::new(normalize_client_ip(client_ip)));
+        }
+        if let Some(endpoint) = &self.endpoint {
+            filter("endpoint =", Box::new(endpoint.clone()));
+        }
+        if let Some(event_type) = &self.event_type {
+            filter("event_type =", Box::new(event_type.clone()));
+        }
+        if let Some(result) = &self.policy_result {
+            filter("policy_result =", Box::new(result.clone()));
+        }
+        (clause, args)
+    }
+}
+
+/// Aggregate columns shared by [`AuditLogger::stats`] and
+/// [`AuditLogger::grouped_stats`], read back with `stats_counts`
+const STATS_COLUMNS: &str = "COUNT(*),
+       SUM(CASE WHEN event_type IN ('block', 'response_block') THEN 1 ELSE 0 END),
+       SUM(CASE WHEN event_type = 'error' THEN 1 ELSE 0 END),
+       SUM(CASE WHEN event_type = 'rate_limited' THEN 1 ELSE 0 END),
+       COALESCE(SUM(response_tokens), 0)";
+
+fn stats_counts(row: &rusqlite::Row, first: usize) -> rusqlite::Result<StatsCounts> {
+    // SUM over no rows is NULL
+    let count = |i: usize| -> rusqlite::Result<u64> {
+        Ok(row.get::<_, Option<i64>>(first + i)?.unwrap_or(0) as u64)
+    };
+    Ok(StatsCounts {
+        total: count(0)?,
+        blocked: count(1)?,
+        errors: count(2)?,
+        rate_limited: count(3)?,
+        tokens: count(4)?,
+    })
+}
+
+/// How [`AuditLogger::grouped_stats`] groups events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsGrouping {
+    /// Upstream endpoint
+    Endpoint,
+
+    /// Client IP
+    Client,
+
+    /// UTC day
+    Day,
+}
+
+impl StatsGrouping {
+    /// Grouping name as used by the Python API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatsGrouping::Endpoint => "endpoint",
+            StatsGrouping::Client => "client",
+            StatsGrouping::Day => "day",
+        }
+    }
+
+    fn sql_key(&self) -> &'static str {
+        match self {
+            StatsGrouping::Endpoint => "endpoint",
+            StatsGrouping::Client => "client_ip",
+            StatsGrouping::Day => "substr(timestamp, 1, 10)",
+        }
+    }
+}
+
+impl std::str::FromStr for StatsGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "endpoint" => Ok(StatsGrouping::Endpoint),
+            "client" => Ok(StatsGrouping::Client),
+            "day" => Ok(StatsGrouping::Day),
+            _ => bail!(
+                "unknown grouping {:?} (expected endpoint, client or day)",
+                s
+            ),
+        }
+    }
+}
+
+/// Event counts of a set of audit events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct StatsCounts {
+    /// Events of every type
+    pub total: u64,
+
+    /// Requests and responses blocked by policy
+    pub blocked: u64,
+
+    /// Proxy and upstream errors
+    pub errors: u64,
+
+    /// Requests refused by the rate limiter
+    pub rate_limited: u64,
+
+    /// Response tokens
+    pub tokens: u64,
+}
+
+/// Summary of the audit log, from [`AuditLogger::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AuditStats {
+    #[serde(flatten)]
+    pub counts: StatsCounts,
+
+    /// Distinct client IPs
+    pub clients: u64,
+
+    /// Timestamp of the oldest event (RFC 3339)
+    pub oldest: Option<String>,
+
+    /// Timestamp of the newest event (RFC 3339)
+    pub newest: Option<String>,
+}
+
+/// Counts of one endpoint, client or day, from
+/// [`AuditLogger::grouped_stats`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct GroupStats {
+    /// Endpoint, client IP or UTC day ("2026-01-31")
+    pub key: String,
+
+    #[serde(flatten)]
+    pub counts: StatsCounts,
+}
+
+/// Aggregated usage of one device, from [`AuditLogger::device_usage_since`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DeviceUsage {
+    pub tenant: String,
+    pub device: String,
+    pub requests: u64,
+    pub tokens: u64,
+    pub blocks: u64,
+}
+
+/// Usage of one conversation session, from [`AuditLogger::session_totals`]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SessionTotals {
+    pub session_id: String,
+    pub tenant: String,
+    pub device: String,
+    pub endpoint: String,
+
+    /// RFC 3339 times of the first and latest request
+    pub started: String,
+    pub last_seen: String,
+
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub response_tokens: u64,
+
+    /// Estimated cost in USD of the priced requests
+    pub cost_usd: f64,
+}
+
+/// Prompt de-duplication cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct PromptCacheStats {
+    /// Distinct prompts stored in full
+    pub canonical_prompts: u64,
+
+    /// Prompts logged in total, including near-duplicates
+    pub occurrences: u64,
+}
+
+impl PromptCacheStats {
+    /// Fraction of logged prompts that reused a canonical copy
+    pub fn hit_rate(&self) -> f64 {
+        if self.occurrences == 0 {
+            return 0.0;
+        }
+        (self.occurrences - self.canonical_prompts) as f64 / self.occurrences as f64
+    }
+}
+
+/// Key an SQLCipher connection with the audit key from the vault at `key_path`
+///
+/// Must be called before any other statement on the connection. Fails if
+/// this build has no SQLCipher support or the key doesn't match.
+pub(crate) fn unlock(conn: &Connection, key_path: &Path) -> Result<()> {
+    let cipher: Option<String> = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+        .optional()?;
+    if cipher.is_none() {
+        bail!("audit encryption requested but yori-core was built without the sqlcipher feature");
+    }
+
+    let vault = Vault::open_or_create(key_path)?;
+    conn.pragma_update(None, "key", vault.sqlcipher_key(AUDIT_DB_PURPOSE).as_str())?;
+
+    // SQLCipher only checks the key on first read
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .context("audit database key is wrong or the database is not encrypted")?;
+    Ok(())
+}
+
+/// Copy a plaintext audit database into a new encrypted one
+///
+/// Used once when turning on encryption for an existing install; the
+/// plaintext file is left in place for the caller to remove securely.
+pub fn encrypt_database(plaintext: &Path, encrypted: &Path, key_path: &Path) -> Result<()> {
+    if encrypted.exists() {
+        bail!("{} already exists", encrypted.display());
+    }
+    let conn = Connection::open(plaintext)
+        .with_context(|| format!("failed to open {}", plaintext.display()))?;
+    let cipher: Option<String> = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+        .optional()?;
+    if cipher.is_none() {
+        bail!("yori-core was built without the sqlcipher feature");
+    }
+
+    let vault = Vault::open_or_create(key_path)?;
+    let key = vault.sqlcipher_key(AUDIT_DB_PURPOSE);
+    conn.execute(
+        &format!("ATTACH DATABASE ?1 AS encrypted KEY \"{}\"", key.as_str()),
+        [encrypted.to_string_lossy()],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    conn.execute_batch("DETACH DATABASE encrypted")?;
+    Ok(())
+}
+
+/// Add a column to an existing table if it isn't there yet
+///
+/// Databases created by older versions (or by the Python layer) predate
+/// some columns, and SQLite has no `ADD COLUMN IF NOT EXISTS`.
+pub(crate) fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+    }
+    Ok(())
+}
+
+/// Read-only access to the audit database from Python
+///
+/// ```python
+/// audit = yori_core.AuditLogger("/var/db/yori/audit.db")
+/// page = audit.query(client_ip="192.168.1.42", policy_result="block",
+///                    since="2026-01-01T00:00:00Z", limit=50, offset=50)
+/// page["total"], [e["endpoint"] for e in page["events"]]
+/// ```
+#[pyclass(name = "AuditLogger")]
+pub struct PyAuditLogger {
+    logger: AuditLogger,
+}
+
+/// Parse an RFC 3339 timestamp argument
+fn parse_time(name: &str, value: Option<&str>) -> PyResult<Option<DateTime<Utc>>> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| PyValueError::new_err(format!("invalid {} {:?}: {}", name, v, e)))
+        })
+        .transpose()
+}
+
+/// Filter built from the keyword arguments shared by the Python methods
+#[allow(clippy::too_many_arguments)]
+fn filter_query(
+    since: Option<&str>,
+    until: Option<&str>,
+    client_ip: Option<String>,
+    endpoint: Option<String>,
+    event_type: Option<String>,
+    policy_result: Option<String>,
+    tenant: Option<String>,
+) -> PyResult<AuditQuery> {
+    Ok(AuditQuery {
+        since: parse_time("since", since)?,
+        until: parse_time("until", until)?,
+        tenant,
+        client_ip,
+        endpoint,
+        event_type,
+        policy_result,
+        ..AuditQuery::default()
+    })
+}
+
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", e))
+}
+
+#[pymethods]
+impl PyAuditLogger {
+    /// Open an audit database
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - SQLite database path (default: /var/db/yori/audit.db)
+    /// * `encryption_key` - Vault key file if the database is encrypted
+    #[new]
+    #[pyo3(signature = (database=None, encryption_key=None))]
+    fn new(
+        py: Python,
+        database: Option<PathBuf>,
+        encryption_key: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let defaults = AuditConfig::default();
+        let config = AuditConfig {
+            database: database.unwrap_or(defaults.database.clone()),
+            encryption_key,
+            ..defaults
+        };
+        // Opening may run migrations or decrypt the database
+        let logger = py
+            .allow_threads(|| AuditLogger::open(config))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        Ok(PyAuditLogger { logger })
+    }
+
+    /// Fetch one page of audit events, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `since` / `until` - RFC 3339 bounds (`since` inclusive, `until` exclusive)
+    /// * `client_ip`, `endpoint`, `event_type`, `policy_result`, `tenant` - Exact matches
+    /// * `limit` - Page size (default: 100)
+    /// * `offset` - Matching events to skip (default: 0)
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `events` (list of dicts), `total` (matches across all
+    /// pages), `limit` and `offset`
+    #[pyo3(signature = (
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        event_type=None,
+        policy_result=None,
+        tenant=None,
+        limit=100,
+        offset=0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn query(
+        &self,
+        py: Python,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        event_type: Option<String>,
+        policy_result: Option<String>,
+        tenant: Option<String>,
+        limit: usize,
+        offset: usize,
+    ) -> PyResult<PyObject> {
+        let query = AuditQuery {
+            limit: Some(limit),
+            offset: Some(offset),
+            ..filter_query(
+                since,
+                until,
+                client_ip,
+                endpoint,
+                event_type,
+                policy_result,
+                tenant,
+            )?
+        };
+        let (events, total) = py
+            .allow_threads(|| -> Result<_> {
+                Ok((self.logger.query(&query)?, self.logger.count(&query)?))
+            })
+            .map_err(to_py_err)?;
+        let page = serde_json::json!({
+            "events": events,
+            "total": total,
+            "limit": limit,
+            "offset": offset,
+        });
+        json_to_py(py, &page)
+    }
+
+    /// Number of events matching the same filters as [`query`](Self::query)
+    #[pyo3(signature = (
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        event_type=None,
+        policy_result=None,
+        tenant=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn count(
+        &self,
+        py: Python,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        event_type: Option<String>,
+        policy_result: Option<String>,
+        tenant: Option<String>,
+    ) -> PyResult<u64> {
+        let query = filter_query(
+            since,
+            until,
+            client_ip,
+            endpoint,
+            event_type,
+            policy_result,
+            tenant,
+        )?;
+        py.allow_threads(|| self.logger.count(&query))
+            .map_err(to_py_err)
+    }
+
+    /// Write matching events to a file, oldest first, without loading them
+    /// all into memory
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Output file (replaced if it exists)
+    /// * `format` - "jsonl", "csv" or "parquet" (default: from the file extension)
+    /// * Filters as for [`query`](Self::query); `limit` caps the events written
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `rows`, `duration_ms`, `format` and `path`
+    #[pyo3(signature = (
+        path,
+        format=None,
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        event_type=None,
+        policy_result=None,
+        tenant=None,
+        limit=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn export(
+        &self,
+        py: Python,
+        path: PathBuf,
+        format: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        event_type: Option<String>,
+        policy_result: Option<String>,
+        tenant: Option<String>,
+        limit: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let format = match format {
+            Some(name) => name
+                .parse::<ExportFormat>()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            None => ExportFormat::from_path(&path).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "cannot tell the export format of {}; pass format=",
+                    path.display()
+                ))
+            })?,
+        };
+        let query = AuditQuery {
+            limit,
+            ..filter_query(
+                since,
+                until,
+                client_ip,
+                endpoint,
+                event_type,
+                policy_result,
+                tenant,
+            )?
+        };
+        let report = py
+            .allow_threads(|| self.logger.export_file(&query, format, &path))
+            .map_err(to_py_err)?;
+        let result = serde_json::json!({
+            "rows": report.rows,
+            "duration_ms": report.duration_ms,
+            "format": format.as_str(),
+            "path": path.display().to_string(),
+        });
+        json_to_py(py, &result)
+    }
+
+    /// Estimated cost of matching events per user, device, model or day
+    ///
+    /// # Arguments
+    ///
+    /// * `group_by` - "user", "device", "model" or "day" (default: "device")
+    /// * `daily` - Also split each group by UTC day (default: False)
+    /// * Filters as for [`query`](Self::query)
+    ///
+    /// # Returns
+    ///
+    /// List of dicts with `key`, `day`, `requests`, `prompt_tokens`,
+    /// `response_tokens`, `cost_usd` and `unpriced`, most expensive first
+    #[pyo3(signature = (
+        group_by="device",
+        daily=false,
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        tenant=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn cost_summary(
+        &self,
+        py: Python,
+        group_by: &str,
+        daily: bool,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        tenant: Option<String>,
+    ) -> PyResult<PyObject> {
+        let grouping = group_by
+            .parse::<CostGrouping>()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let query = filter_query(since, until, client_ip, endpoint, None, None, tenant)?;
+        let summary = py
+            .allow_threads(|| self.logger.cost_summary(&query, grouping, daily))
+            .map_err(to_py_err)?;
+        let summary = serde_json::to_value(&summary).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &summary)
+    }
+
+    /// Totals for the dashboard's summary cards
+    ///
+    /// # Arguments
+    ///
+    /// * `since` / `until` - RFC 3339 bounds (`since` inclusive, `until` exclusive)
+    /// * `client_ip`, `endpoint`, `tenant` - Exact matches
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `total`, `blocked`, `errors`, `rate_limited`,
+    /// `tokens`, `clients`, `oldest` and `newest`
+    #[pyo3(signature = (since=None, until=None, client_ip=None, endpoint=None, tenant=None))]
+    fn stats(
+        &self,
+        py: Python,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        tenant: Option<String>,
+    ) -> PyResult<PyObject> {
+        let query = filter_query(since, until, client_ip, endpoint, None, None, tenant)?;
+        let stats = py
+            .allow_threads(|| self.logger.stats(&query))
+            .map_err(to_py_err)?;
+        let stats = serde_json::to_value(&stats).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &stats)
+    }
+
+    /// Totals per endpoint, client or day
+    ///
+    /// # Arguments
+    ///
+    /// * `group_by` - "endpoint", "client" or "day"
+    /// * `since` / `until` - RFC 3339 bounds (`since` inclusive, `until` exclusive)
+    /// * `client_ip`, `endpoint`, `tenant` - Exact matches
+    /// * `limit` - Maximum number of groups (default: all)
+    ///
+    /// # Returns
+    ///
+    /// List of dicts with `key`, `total`, `blocked`, `errors`,
+    /// `rate_limited` and `tokens`; busiest first, days in order
+    #[pyo3(signature = (
+        group_by="endpoint",
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        tenant=None,
+        limit=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn grouped_stats(
+        &self,
+        py: Python,
+        group_by: &str,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        tenant: Option<String>,
+        limit: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let grouping = group_by
+            .parse::<StatsGrouping>()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let query = AuditQuery {
+            limit,
+            ..filter_query(since, until, client_ip, endpoint, None, None, tenant)?
+        };
+        let groups = py
+            .allow_threads(|| self.logger.grouped_stats(&query, grouping))
+            .map_err(to_py_err)?;
+        let groups = serde_json::to_value(&groups).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &groups)
+    }
+
+    /// Requests, tokens and cost per conversation session
+    ///
+    /// # Arguments
+    ///
+    /// * `since` / `until` - RFC 3339 bounds (`since` inclusive, `until` exclusive)
+    /// * `client_ip`, `endpoint`, `tenant` - Exact matches
+    /// * `limit` - Maximum number of sessions (default: all)
+    ///
+    /// # Returns
+    ///
+    /// List of dicts with `session_id`, `tenant`, `device`, `endpoint`,
+    /// `started`, `last_seen`, `requests`, `prompt_tokens`,
+    /// `response_tokens` and `cost_usd`; most recent first
+    #[pyo3(signature = (
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        tenant=None,
+        limit=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn session_totals(
+        &self,
+        py: Python,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        tenant: Option<String>,
+        limit: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let query = AuditQuery {
+            limit,
+            ..filter_query(since, until, client_ip, endpoint, None, None, tenant)?
+        };
+        let sessions = py
+            .allow_threads(|| self.logger.session_totals(&query))
+            .map_err(to_py_err)?;
+        let sessions = serde_json::to_value(&sessions).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &sessions)
+    }
+
+    /// Daily or weekly usage from the rollup tables
+    ///
+    /// Cheap over any time span: reads the rollups the maintenance
+    /// scheduler keeps, not the raw events.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - "day" or "week" (default: "day")
+    /// * `group_by` - "user", "device", "provider" or None for one total
+    ///   per bucket and tenant
+    /// * `since` / `until` - Bucket start dates ("2026-01-01"; `since`
+    ///   inclusive, `until` exclusive)
+    /// * `tenant` - Exact match
+    /// * `refresh` - Fold in the latest events first (default: False)
+    ///
+    /// # Returns
+    ///
+    /// List of dicts with `bucket`, `tenant`, `key`, `requests`, `blocks`,
+    /// `prompt_tokens`, `response_tokens` and `cost_usd`; oldest first
+    #[pyo3(signature = (
+        period="day",
+        group_by=None,
+        since=None,
+        until=None,
+        tenant=None,
+        refresh=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn usage_rollups(
+        &self,
+        py: Python,
+        period: &str,
+        group_by: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+        tenant: Option<String>,
+        refresh: bool,
+    ) -> PyResult<PyObject> {
+        let date = |name: &str, value: Option<&str>| {
+            value
+                .map(|v| {
+                    v.parse::<chrono::NaiveDate>().map_err(|e| {
+                        PyValueError::new_err(format!("invalid {} {:?}: {}", name, v, e))
+                    })
+                })
+                .transpose()
+        };
+        let query = RollupQuery {
+            period: period
+                .parse::<RollupPeriod>()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            since: date("since", since)?,
+            until: date("until", until)?,
+            tenant,
+            group_by: group_by
+                .map(str::parse::<RollupGrouping>)
+                .transpose()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        };
+        let rollups = py
+            .allow_threads(|| {
+                if refresh {
+                    self.logger.refresh_rollups(Utc::now())?;
+                }
+                self.logger.usage_rollups(&query)
+            })
+            .map_err(to_py_err)?;
+        let rollups = serde_json::to_value(&rollups).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &rollups)
+    }
+
+    /// Re-run recorded requests through a candidate policy set
+    ///
+    /// See [`crate::replay`] for how the input is rebuilt.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - PolicyEngine loaded with the candidate policies
+    /// * `since` / `until` - RFC 3339 bounds (`since` inclusive, `until` exclusive)
+    /// * `client_ip`, `endpoint`, `tenant` - Exact matches
+    /// * `limit` - Maximum number of events read (default: all)
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `replayed`, `skipped`, `unchanged`,
+    /// `newly_blocked_count`, `newly_allowed_count`, `newly_blocked` and
+    /// `newly_allowed` (lists of the changed requests), `errors` and
+    /// `first_error`
+    #[pyo3(signature = (
+        engine,
+        since=None,
+        until=None,
+        client_ip=None,
+        endpoint=None,
+        tenant=None,
+        limit=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn replay(
+        &self,
+        py: Python,
+        engine: PyRef<'_, PolicyEngine>,
+        since: Option<&str>,
+        until: Option<&str>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        tenant: Option<String>,
+        limit: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let query = AuditQuery {
+            limit,
+            ..filter_query(since, until, client_ip, endpoint, None, None, tenant)?
+        };
+        let engine = &*engine;
+        let report = py
+            .allow_threads(|| PolicyReplay::new(engine).replay(&self.logger, &query))
+            .map_err(to_py_err)?;
+        let report = serde_json::to_value(&report).map_err(|e| to_py_err(e.into()))?;
+        json_to_py(py, &report)
+    }
+
+    /// Delete events older than the configured retention window
+    ///
+    /// # Returns
+    ///
+    /// Number of events deleted
+    fn prune_old_logs(&self, py: Python) -> PyResult<u64> {
+        let report = py
+            .allow_threads(|| self.logger.prune_old_logs())
+            .map_err(to_py_err)?;
+        Ok(report.events_deleted)
+    }
+
+    /// Check the audit hash chain (see `audit.hash_chain`)
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `intact`, `verified`, `unchained`, `head` and
+    /// `first_tampered` (None, or `{"id", "reason"}`)
+    fn verify_integrity(&self, py: Python) -> PyResult<PyObject> {
+        let report = py
+            .allow_threads(|| self.logger.verify_integrity())
+            .map_err(to_py_err)?;
+        let mut result = serde_json::to_value(&report).map_err(|e| to_py_err(e.into()))?;
+        result["intact"] = report.is_intact().into();
+        json_to_py(py, &result)
+    }
+}
+
+/// Longest a Python wait for an event blocks before checking for Ctrl-C
+const SUBSCRIPTION_POLL: Duration = Duration::from_millis(250);
+
+/// Live audit events, from `ProxyServer.subscribe()`
+///
+/// Each message is `{"event": "audit", "data": {...event...}}`, or
+/// `{"event": "lagged", "data": {"missed": n}}` when the subscriber fell
+/// behind and skipped ahead (and `"status"` for proxy status changes).
+///
+/// ```python
+/// for message in proxy.subscribe(event_type="block"):
+///     print(message["data"]["endpoint"])
+///
+/// message = subscription.next(timeout=1.0)  # None if nothing arrived
+/// ```
+#[pyclass(name = "AuditSubscription")]
+pub struct PyAuditSubscription {
+    receiver: Mutex<broadcast::Receiver<Arc<LiveEvent>>>,
+    filter: LiveFilter,
+    runtime: tokio::runtime::Runtime,
+}
+
+/// Outcome of one wait on a subscription
+enum Received {
+    Message(serde_json::Value),
+    Timeout,
+    Closed,
+}
+
+impl PyAuditSubscription {
+    /// Follow `live`, passing messages that match `filter`
+    pub(crate) fn new(live: &LiveTail, filter: LiveFilter) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .context("failed to start the subscription runtime")?;
+        Ok(PyAuditSubscription {
+            receiver: Mutex::new(live.subscribe()),
+            filter,
+            runtime,
+        })
+    }
+
+    /// Wait up to `wait` for the next message passing the filter
+    fn recv(&self, wait: Duration) -> Received {
+        let mut receiver = self.receiver.lock().unwrap();
+        self.runtime.block_on(async {
+            let deadline = tokio::time::Instant::now() + wait;
+            loop {
+                let event = match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    Err(_) => return Received::Timeout,
+                    Ok(Err(broadcast::error::RecvError::Closed)) => return Received::Closed,
+                    Ok(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                        return Received::Message(serde_json::json!({
+                            "event": "lagged",
+                            "data": {"missed": missed},
+                        }))
+                    }
+                    Ok(Ok(event)) => event,
+                };
+                if self.filter.matches(&event) {
+                    return Received::Message(serde_json::json!({
+                        "event": event.name(),
+                        "data": event.to_json(),
+                    }));
+                }
+            }
+        })
+    }
+
+    /// Wait in short steps so Ctrl-C still interrupts a long wait
+    fn wait(&self, py: Python, timeout: Option<Duration>) -> PyResult<Received> {
+        let deadline = timeout.map(|t| std::time::Instant::now() + t);
+        loop {
+            let step = match deadline {
+                Some(deadline) => deadline
+                    .saturating_duration_since(std::time::Instant::now())
+                    .min(SUBSCRIPTION_POLL),
+                None => SUBSCRIPTION_POLL,
+            };
+            match py.allow_threads(|| self.recv(step)) {
+                Received::Timeout if deadline.is_none_or(|d| std::time::Instant::now() < d) => {
+                    py.check_signals()?
+                }
+                received => return Ok(received),
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl PyAuditSubscription {
+    /// Next message, waiting up to `timeout` seconds (None = until one
+    /// arrives)
+    ///
+    /// # Returns
+    ///
+    /// Message dictionary, or None on timeout or once the proxy is gone
+    #[pyo3(signature = (timeout=None))]
+    fn next(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        let timeout = timeout
+            .map(|t| Duration::try_from_secs_f64(t.max(0.0)))
+            .transpose()
+            .map_err(|e| PyValueError::new_err(format!("invalid timeout: {}", e)))?;
+        match self.wait(py, timeout)? {
+            Received::Message(message) => json_to_py(py, &message).map(Some),
+            Received::Timeout | Received::Closed => Ok(None),
+        }
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Block until the next message; iteration ends when the proxy is gone
+    fn __next__(&self, py: Python) -> PyResult<Option<PyObject>> {
+        match self.wait(py, None)? {
+            Received::Message(message) => json_to_py(py, &message).map(Some),
+            Received::Timeout | Received::Closed => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::proxy::test_request;
+
+    pub(crate) fn memory_logger() -> AuditLogger {
+        AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            ..AuditConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Make writes fail with SQLITE_READONLY, as on a remounted disk
+    pub(crate) fn set_read_only(logger: &AuditLogger, read_only: bool) {
+        let conn = logger.conn.lock().unwrap();
+        conn.pragma_update(None, "query_only", read_only).unwrap();
+    }
+
+    pub(crate) fn request(prompt: &str) -> AuditEvent {
+        let ctx = test_request();
+        AuditEvent::from_request(AuditEventType::Request, &ctx).with_prompt(prompt, 200)
+    }
+
+    #[test]
+    fn test_duplicate_prompts_reference_canonical() {
+        let logger = memory_logger();
+        logger.log(&request("What is 7 times 8?")).unwrap();
+        logger.log(&request("what is 7 times 8")).unwrap();
+        logger.log(&request("Tell me about volcanoes")).unwrap();
+
+        let conn = logger.conn.lock().unwrap();
+        let rows: Vec<(Option<String>, i64)> = conn
+            .prepare("SELECT prompt_preview, prompt_ref FROM audit_events ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        drop(conn);
+
+        assert_eq!(rows[0].0.as_deref(), Some("What is 7 times 8?"));
+        assert_eq!(rows[1].0, None);
+        assert_eq!(rows[0].1, rows[1].1);
+        assert_ne!(rows[0].1, rows[2].1);
+        assert_eq!(
+            logger.canonical_prompt(rows[1].1).unwrap().as_deref(),
+            Some("What is 7 times 8?")
+        );
+    }
+
+    #[test]
+    fn test_prompt_pii_redacted_before_storage() {
+        let logger = memory_logger();
+        logger
+            .log(&request(
+                "Email grandma at nana@example.com about the party",
+            ))
+            .unwrap();
+        logger.log(&request("What is 7 times 8?")).unwrap();
+
+        let conn = logger.conn.lock().unwrap();
+        let rows: Vec<(Option<String>, bool)> = conn
+            .prepare("SELECT prompt_preview, contains_sensitive FROM audit_events ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows[0],
+            (
+                Some("Email grandma at [EMAIL] about the party".to_string()),
+                true
+            )
+        );
+        assert_eq!(rows[1], (Some("What is 7 times 8?".to_string()), false));
+    }
+
+    #[test]
+    fn test_dedup_disabled_stores_previews_inline() {
+        let logger = AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            dedup_prompts: false,
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        logger.log(&request("same prompt")).unwrap();
+        logger.log(&request("same prompt")).unwrap();
+
+        let conn = logger.conn.lock().unwrap();
+        let inline: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_events WHERE prompt_preview IS NOT NULL",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(inline, 2);
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn test_encryption_requires_sqlcipher_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = AuditLogger::open(AuditConfig {
+            database: dir.path().join("audit.db"),
+            encryption_key: Some(dir.path().join("vault.key")),
+            ..AuditConfig::default()
+        });
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_database_needs_vault_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AuditConfig {
+            database: dir.path().join("audit.db"),
+            encryption_key: Some(dir.path().join("vault.key")),
+            ..AuditConfig::default()
+        };
+        let logger = AuditLogger::open(config.clone()).unwrap();
+        logger.log(&request("what is 7 times 8")).unwrap();
+        drop(logger);
+
+        // Without the key the file is unreadable; with it the data is back
+        let plain = Connection::open(&config.database).unwrap();
+        assert!(plain
+            .query_row("SELECT count(*) FROM audit_events", [], |r| r
+                .get::<_, i64>(0))
+            .is_err());
+        assert!(AuditLogger::open(config).is_ok());
+    }
+
+    #[test]
+    fn test_maintenance_reclaims_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = AuditLogger::open(AuditConfig {
+            database: dir.path().join("audit.db"),
+            dedup_prompts: false,
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        for i in 0..200 {
+            logger
+                .log(&request(&format!("prompt number {i} {}", "x".repeat(150))))
+                .unwrap();
+        }
+        logger
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM audit_events", [])
+            .unwrap();
+
+        let report = logger.run_maintenance(10_000).unwrap();
+        assert!(!report.converted_to_incremental);
+        assert!(report.pages_freed > 0);
+        assert_eq!(logger.run_maintenance(10_000).unwrap().pages_freed, 0);
+    }
+
+    #[test]
+    fn test_stats_totals_and_groups() {
+        let logger = memory_logger();
+        assert_eq!(
+            logger.stats(&AuditQuery::default()).unwrap(),
+            AuditStats::default()
+        );
+
+        logger.log(&request("hello")).unwrap();
+        let mut other = request("hi there");
+        other.client_ip = "192.168.1.60".to_string();
+        other.endpoint = "api.anthropic.com".to_string();
+        logger.log(&other).unwrap();
+        let mut blocked = request("how do I pick a lock");
+        blocked.event_type = AuditEventType::RequestBlocked;
+        logger.log(&blocked).unwrap();
+        let mut error = request("hello again");
+        error.event_type = AuditEventType::Error;
+        logger.log(&error).unwrap();
+
+        let stats = logger.stats(&AuditQuery::default()).unwrap();
+        assert_eq!(
+            (
+                stats.counts.total,
+                stats.counts.blocked,
+                stats.counts.errors
+            ),
+            (4, 1, 1)
+        );
+        assert_eq!(stats.clients, 2);
+        assert!(stats.oldest.is_some() && stats.oldest <= stats.newest);
+
+        let endpoints = logger
+            .grouped_stats(&AuditQuery::default(), StatsGrouping::Endpoint)
+            .unwrap();
+        assert_eq!(endpoints[0].key, "api.openai.com");
+        assert_eq!(endpoints[0].counts.total, 3);
+        let top = AuditQuery {
+            limit: Some(1),
+            ..AuditQuery::default()
+        };
+        let clients = logger.grouped_stats(&top, StatsGrouping::Client).unwrap();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].key, "192.168.1.50");
+        let days = logger
+            .grouped_stats(&AuditQuery::default(), StatsGrouping::Day)
+            .unwrap();
+        assert_eq!(days.len(), 1);
+        assert!("hourly".parse::<StatsGrouping>().is_err());
+    }
+
+    #[test]
+    fn test_prune_deletes_only_expired_rows() {
+        let logger = AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            retention_days: 30,
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        let mut old = request("what's the capital of France?");
+        old.timestamp = Utc::now() - chrono::Duration::days(45);
+        logger.log(&old).unwrap();
+        let mut recent = request("summarize this article");
+        recent.timestamp = Utc::now() - chrono::Duration::days(2);
+        logger.log(&recent).unwrap();
+        logger.log(&request("write a haiku")).unwrap();
+
+        let report = logger.prune_old_logs().unwrap();
+        assert_eq!((report.events_deleted, report.prompts_deleted), (1, 1));
+        assert_eq!(logger.count(&AuditQuery::default()).unwrap(), 2);
+        assert_eq!(logger.prune_old_logs().unwrap().events_deleted, 0);
+    }
+
+    #[test]
+    fn test_response_metadata_recorded() {
+        let logger = memory_logger();
+        let response = ResponseContext {
+            status: 200,
+            duration_ms: 850,
+            tokens: Some(4096),
+            model: Some("gpt-4o-mini-2024-07-18".to_string()),
+            finish_reason: Some("length".to_string()),
+            safety_flags: vec!["azure:violence".to_string()],
+            cost_usd: None,
+        };
+        let event = request("write a long story")
+            .with_requested_model(Some("gpt-4o"))
+            .with_response(&response);
+        assert!(event.model_mismatch());
+        assert!(!request("x")
+            .with_requested_model(Some("openai/gpt-4o-mini"))
+            .with_response(&response)
+            .model_mismatch());
+        // A downgrade by the proxy isn't a provider substitution
+        assert!(!request("x")
+            .with_model_rewrite("gpt-4o", "gpt-4o-mini")
+            .with_response(&response)
+            .model_mismatch());
+        logger.log(&event).unwrap();
+
+        let conn = logger.conn.lock().unwrap();
+        let (served, finish, flags): (String, String, String) = conn
+            .query_row(
+                "SELECT response_model, finish_reason, safety_flags FROM audit_events",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(served, "gpt-4o-mini-2024-07-18");
+        assert_eq!(finish, "length");
+        assert_eq!(flags, "azure:violence");
+    }
+
+    #[test]
+    fn test_query_and_device_usage() {
+        let logger = memory_logger();
+        let response = ResponseContext {
+            status: 200,
+            duration_ms: 100,
+            tokens: Some(300),
+            model: None,
+            finish_reason: None,
+            safety_flags: Vec::new(),
+            cost_usd: None,
+        };
+        logger
+            .log(&request("first").with_response(&response))
+            .unwrap();
+        logger
+            .log(&request("second").with_policy("bedtime", "block", "after 9pm"))
+            .unwrap();
+
+        let blocked = logger
+            .query(&AuditQuery {
+                policy_result: Some("block".to_string()),
+                ..AuditQuery::default()
+            })
+            .unwrap();
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0]["policy_name"], "bedtime");
+        assert_eq!(
+            logger
+                .query(&AuditQuery {
+                    limit: Some(1),
+                    ..AuditQuery::default()
+                })
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let usage = logger
+            .device_usage_since(Utc::now() - chrono::Duration::hours(1))
+            .unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(
+            (usage[0].requests, usage[0].tokens, usage[0].blocks),
+            (2, 300, 1)
+        );
+    }
+
+    #[test]
+    fn test_cost_summary_groups() {
+        let logger = memory_logger();
+        let response = |cost_usd| ResponseContext {
+            status: 200,
+            duration_ms: 100,
+            tokens: Some(1000),
+            model: Some("gpt-4o".to_string()),
+            finish_reason: None,
+            safety_flags: Vec::new(),
+            cost_usd,
+        };
+        let mut laptop = request("a").with_response(&response(Some(0.25)));
+        laptop.client_device = Some("timmys-laptop".to_string());
+        laptop.client_user = Some("timmy".to_string());
+        let mut tablet = request("b").with_response(&response(Some(0.5)));
+        tablet.client_device = Some("timmys-tablet".to_string());
+        tablet.client_user = Some("timmy".to_string());
+        for event in [laptop, tablet, request("c").with_response(&response(None))] {
+            logger.log(&event).unwrap();
+        }
+        // Blocked requests have no usage and aren't counted
+        logger
+            .log(&request("d").with_policy("bedtime", "block", "after 9pm"))
+            .unwrap();
+
+        let all = AuditQuery::default();
+        let users = logger
+            .cost_summary(&all, CostGrouping::User, false)
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(
+            (users[0].key.as_str(), users[0].requests, users[0].cost_usd),
+            ("timmy", 2, 0.75)
+        );
+        assert_eq!(
+            (users[1].key.as_str(), users[1].unpriced),
+            ("192.168.1.50", 1)
+        );
+
+        let daily = logger
+            .cost_summary(&all, CostGrouping::Model, true)
+            .unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(
+            daily[0].day,
+            Some(Utc::now().format("%Y-%m-%d").to_string())
+        );
+        assert_eq!(daily[0].response_tokens, 3000);
+    }
+
+    #[test]
+    fn test_session_totals() {
+        let logger = memory_logger();
+        let turn = |session: Option<&str>, cost_usd| {
+            let mut event = request("turn").with_response(&ResponseContext {
+                status: 200,
+                duration_ms: 100,
+                tokens: Some(500),
+                model: Some("gpt-4o".to_string()),
+                finish_reason: None,
+                safety_flags: Vec::new(),
+                cost_usd: Some(cost_usd),
+            });
+            event.session_id = session.map(str::to_string);
+            event
+        };
+        let mut retry = turn(Some("chat-1"), 0.25);
+        retry.retry_of = Some("first".to_string());
+        for event in [
+            turn(Some("chat-1"), 0.25),
+            turn(Some("chat-1"), 0.5),
+            turn(Some("chat-2"), 1.0),
+            retry,
+            turn(None, 2.0),
+        ] {
+            logger.log(&event).unwrap();
+        }
+
+        let sessions = logger.session_totals(&AuditQuery::default()).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let chat = sessions.iter().find(|s| s.session_id == "chat-1").unwrap();
+        assert_eq!(
+            (chat.requests, chat.response_tokens, chat.cost_usd),
+            (2, 1000, 0.75)
+        );
+        assert_eq!(chat.device, "192.168.1.50");
+    }
+
+    #[test]
+    fn test_query_filters_and_pagination() {
+        let logger = memory_logger();
+        for i in 0..5 {
+            logger.log(&request(&format!("prompt {i}"))).unwrap();
+        }
+        let mut blocked = request("blocked").with_policy("bedtime", "block", "after 9pm");
+        blocked.event_type = AuditEventType::RequestBlocked;
+        blocked.client_ip = "192.168.1.77".to_string();
+        blocked.endpoint = "api.anthropic.com".to_string();
+        logger.log(&blocked).unwrap();
+
+        let all = AuditQuery::default();
+        assert_eq!(logger.count(&all).unwrap(), 6);
+        let page = |offset| AuditQuery {
+            limit: Some(4),
+            offset: Some(offset),
+            ..AuditQuery::default()
+        };
+        assert_eq!(logger.query(&page(0)).unwrap().len(), 4);
+        assert_eq!(logger.query(&page(4)).unwrap().len(), 2);
+        // Offset without a limit returns the rest
+        let rest = AuditQuery {
+            offset: Some(5),
+            ..AuditQuery::default()
+        };
+        assert_eq!(logger.query(&rest).unwrap().len(), 1);
+
+        let filtered = AuditQuery {
+            client_ip: Some("192.168.1.77".to_string()),
+            endpoint: Some("api.anthropic.com".to_string()),
+            event_type: Some(AuditEventType::RequestBlocked.as_str().to_string()),
+            policy_result: Some("block".to_string()),
+            until: Some(Utc::now() + chrono::Duration::minutes(1)),
+            ..AuditQuery::default()
+        };
+        assert_eq!(logger.count(&filtered).unwrap(), 1);
+        let future = AuditQuery {
+            since: Some(Utc::now() + chrono::Duration::minutes(1)),
+            ..AuditQuery::default()
+        };
+        assert!(logger.query(&future).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_receives_logged_events() {
+        let logger = memory_logger();
+        logger.log(&request("before anyone listened")).unwrap();
+        let mut events = logger.subscribe();
+        logger.log(&request("live")).unwrap();
+
+        let event = events.try_recv().unwrap();
+        let LiveEvent::Audit(event) = event.as_ref() else {
+            panic!("expected an audit event, got {:?}", event);
+        };
+        assert_eq!(event.prompt_preview.as_deref(), Some("live"));
+        assert!(events.try_recv().is_err());
+    }
+}
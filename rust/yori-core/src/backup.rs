@@ -0,0 +1,528 @@
+//! Full state backup and restore
+//!
+//! Produces a single `.tar.gz` archive holding everything needed to move
+//! YORI to new router hardware or recover from SD-card death: policies and
+//! data documents, configuration files, persisted state (grants, quota
+//! counters) and, optionally, the audit database.
+//!
+//! # Archive format
+//!
+//! ```text
+//! manifest.json        format version, creation time, per-file SHA-256
+//! policies/...         contents of the policy directory (recursive)
+//! config/...           top-level files of the config directory
+//! state/...            contents of the state directory (recursive)
+//! audit/audit.db       consistent snapshot of the audit database (optional)
+//! ```
+//!
+//! Restores verify the whole archive against the manifest before a single
+//! file is written, so a truncated or tampered backup never half-applies.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Current archive format version
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+const AUDIT_ENTRY: &str = "audit/audit.db";
+
+/// Description of a backup archive's contents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Archive format version
+    pub format_version: u32,
+
+    /// yori-core version that wrote the archive
+    pub yori_version: String,
+
+    /// When the backup was taken
+    pub created_at: DateTime<Utc>,
+
+    /// Whether the audit database is included
+    pub includes_audit: bool,
+
+    /// Archive path -> SHA-256 (hex) of every file
+    pub files: BTreeMap<String, String>,
+}
+
+/// Locations of the state that gets backed up (and restored to)
+#[derive(Debug, Clone)]
+pub struct BackupPaths {
+    /// Policy directory (.rego and data documents)
+    pub policy_dir: PathBuf,
+
+    /// Configuration directory (only top-level files are included)
+    pub config_dir: PathBuf,
+
+    /// Persisted runtime state (grants, quota counters)
+    pub state_dir: PathBuf,
+
+    /// Audit database
+    pub audit_db: PathBuf,
+
+    /// Vault key unlocking an encrypted audit database
+    ///
+    /// The key itself is never written to the archive; the audit snapshot
+    /// stays encrypted and restoring it requires the same vault key.
+    pub vault_key: Option<PathBuf>,
+}
+
+impl Default for BackupPaths {
+    fn default() -> Self {
+        BackupPaths {
+            policy_dir: PathBuf::from("/usr/local/etc/yori/policies"),
+            config_dir: PathBuf::from("/usr/local/etc/yori"),
+            state_dir: PathBuf::from("/var/db/yori/state"),
+            audit_db: PathBuf::from("/var/db/yori/audit.db"),
+            vault_key: None,
+        }
+    }
+}
+
+/// Write a backup archive of `paths` to `output`
+pub fn create_backup(
+    paths: &BackupPaths,
+    output: &Path,
+    include_audit: bool,
+) -> Result<BackupManifest> {
+    // archive path -> source file
+    let mut sources: BTreeMap<String, PathBuf> = BTreeMap::new();
+    collect_tree(&paths.policy_dir, "policies", true, &mut sources)?;
+    collect_tree(&paths.config_dir, "config", false, &mut sources)?;
+    collect_tree(&paths.state_dir, "state", true, &mut sources)?;
+
+    // Snapshot the audit DB so the copy is consistent even while the proxy
+    // keeps writing to it
+    let snapshot = output.with_extension("audit-snapshot.db");
+    if include_audit {
+        if snapshot.exists() {
+            fs::remove_file(&snapshot)?;
+        }
+        let conn = rusqlite::Connection::open(&paths.audit_db).with_context(|| {
+            format!("failed to open audit database {}", paths.audit_db.display())
+        })?;
+        if let Some(key_path) = &paths.vault_key {
+            crate::audit::unlock(&conn, key_path)?;
+        }
+        conn.execute("VACUUM INTO ?1", [snapshot.to_string_lossy()])
+            .context("failed to snapshot audit database")?;
+        sources.insert(AUDIT_ENTRY.to_string(), snapshot.clone());
+    }
+
+    let result = write_archive(&sources, output, include_audit);
+    if include_audit {
+        let _ = fs::remove_file(&snapshot);
+    }
+    result
+}
+
+fn write_archive(
+    sources: &BTreeMap<String, PathBuf>,
+    output: &Path,
+    include_audit: bool,
+) -> Result<BackupManifest> {
+    let mut files = BTreeMap::new();
+    for (name, path) in sources {
+        files.insert(name.clone(), sha256_file(path)?);
+    }
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        yori_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        includes_audit: include_audit,
+        files,
+    };
+
+    let partial = output.with_extension("partial");
+    let file = File::create(&partial)
+        .with_context(|| format!("failed to create {}", partial.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(manifest.created_at.timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+
+    for (name, path) in sources {
+        builder
+            .append_path_with_name(path, name)
+            .with_context(|| format!("failed to add {} to backup", path.display()))?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&partial, output)?;
+
+    tracing::info!(
+        "Backup written to {} ({} files)",
+        output.display(),
+        manifest.files.len()
+    );
+    Ok(manifest)
+}
+
+/// Check an archive's format version and every file hash
+pub fn verify_backup(archive: &Path) -> Result<BackupManifest> {
+    let mut manifest: Option<BackupManifest> = None;
+    let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+
+    let mut tar = open_archive(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry_name(&entry)?;
+        if name == MANIFEST_NAME {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            manifest = Some(serde_json::from_slice(&json).context("invalid backup manifest")?);
+        } else {
+            hashes.insert(name, sha256_reader(&mut entry)?);
+        }
+    }
+
+    let manifest = manifest.context("backup has no manifest")?;
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        bail!(
+            "backup format version {} is newer than supported version {}",
+            manifest.format_version,
+            BACKUP_FORMAT_VERSION
+        );
+    }
+    for (name, expected) in &manifest.files {
+        match hashes.remove(name) {
+            Some(actual) if &actual == expected => {}
+            Some(_) => bail!(
+                "integrity check failed: {} does not match its checksum",
+                name
+            ),
+            None => bail!(
+                "integrity check failed: {} is missing from the archive",
+                name
+            ),
+        }
+    }
+    if let Some(extra) = hashes.keys().next() {
+        bail!(
+            "integrity check failed: {} is not listed in the manifest",
+            extra
+        );
+    }
+    Ok(manifest)
+}
+
+/// Verify an archive, then restore its contents into `paths`
+///
+/// Existing files with the same names are replaced; other files are left
+/// alone. The audit database is only restored when `include_audit` is set
+/// and the archive contains one.
+pub fn restore_backup(
+    archive: &Path,
+    paths: &BackupPaths,
+    include_audit: bool,
+) -> Result<BackupManifest> {
+    let manifest = verify_backup(archive)?;
+
+    let mut tar = open_archive(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry_name(&entry)?;
+        let Some(target) = restore_target(&name, paths, include_audit) else {
+            continue;
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write next to the target and rename so a crash never leaves a
+        // half-written policy or database in place
+        let staging = target.with_extension("restore-tmp");
+        {
+            let mut out = File::create(&staging)
+                .with_context(|| format!("failed to write {}", staging.display()))?;
+            io::copy(&mut entry, &mut out)?;
+            out.sync_all()?;
+        }
+        fs::rename(&staging, &target)?;
+    }
+
+    tracing::info!(
+        "Restored backup from {} (taken {})",
+        archive.display(),
+        manifest.created_at
+    );
+    Ok(manifest)
+}
+
+fn restore_target(name: &str, paths: &BackupPaths, include_audit: bool) -> Option<PathBuf> {
+    if name == AUDIT_ENTRY {
+        return include_audit.then(|| paths.audit_db.clone());
+    }
+    let (section, rest) = name.split_once('/')?;
+    let base = match section {
+        "policies" => &paths.policy_dir,
+        "config" => &paths.config_dir,
+        "state" => &paths.state_dir,
+        _ => return None,
+    };
+    Some(base.join(rest))
+}
+
+fn open_archive(archive: &Path) -> Result<tar::Archive<GzDecoder<File>>> {
+    let file =
+        File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    Ok(tar::Archive::new(GzDecoder::new(file)))
+}
+
+/// Archive entry name, rejecting absolute paths and `..` components
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let path = entry.path()?;
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("backup contains unsafe path {}", path.display());
+    }
+    Ok(path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Add regular files under `dir` to `out` as `<prefix>/<relative path>`
+fn collect_tree(
+    dir: &Path,
+    prefix: &str,
+    recursive: bool,
+    out: &mut BTreeMap<String, PathBuf>,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && recursive {
+                stack.push(path);
+            } else if file_type.is_file() {
+                let relative = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+                out.insert(format!("{}/{}", prefix, relative), path);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    sha256_reader(&mut file)
+}
+
+fn sha256_reader<R: Read>(reader: &mut R) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn manifest_to_py(py: Python, manifest: &BackupManifest) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("format_version", manifest.format_version)?;
+    dict.set_item("yori_version", &manifest.yori_version)?;
+    dict.set_item("created_at", manifest.created_at.to_rfc3339())?;
+    dict.set_item("includes_audit", manifest.includes_audit)?;
+    dict.set_item("files", manifest.files.keys().cloned().collect::<Vec<_>>())?;
+    Ok(dict.into())
+}
+
+fn py_paths(
+    policy_dir: Option<String>,
+    config_dir: Option<String>,
+    state_dir: Option<String>,
+    audit_db: Option<String>,
+    vault_key: Option<String>,
+) -> BackupPaths {
+    let vault_key = vault_key.map(PathBuf::from);
+    let defaults = BackupPaths::default();
+    BackupPaths {
+        policy_dir: policy_dir.map(PathBuf::from).unwrap_or(defaults.policy_dir),
+        config_dir: config_dir.map(PathBuf::from).unwrap_or(defaults.config_dir),
+        state_dir: state_dir.map(PathBuf::from).unwrap_or(defaults.state_dir),
+        audit_db: audit_db.map(PathBuf::from).unwrap_or(defaults.audit_db),
+        vault_key,
+    }
+}
+
+/// Write a full state backup archive
+///
+/// # Arguments
+///
+/// * `output` - Path of the `.tar.gz` archive to write
+/// * `include_audit` - Include a snapshot of the audit database
+/// * `policy_dir`, `config_dir`, `state_dir`, `audit_db` - Override default locations
+/// * `vault_key` - Vault key file, if the audit database is encrypted
+///
+/// # Returns
+///
+/// Manifest dictionary (`format_version`, `created_at`, `files`, ...)
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (output, include_audit=false, policy_dir=None, config_dir=None, state_dir=None, audit_db=None, vault_key=None))]
+pub fn backup(
+    py: Python,
+    output: String,
+    include_audit: bool,
+    policy_dir: Option<String>,
+    config_dir: Option<String>,
+    state_dir: Option<String>,
+    audit_db: Option<String>,
+    vault_key: Option<String>,
+) -> PyResult<PyObject> {
+    let paths = py_paths(policy_dir, config_dir, state_dir, audit_db, vault_key);
+    let manifest = create_backup(&paths, Path::new(&output), include_audit)
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+    manifest_to_py(py, &manifest)
+}
+
+/// Verify and restore a backup archive
+///
+/// # Arguments
+///
+/// * `archive` - Path of the `.tar.gz` archive
+/// * `include_audit` - Also restore the audit database, if present
+/// * `policy_dir`, `config_dir`, `state_dir`, `audit_db` - Override default locations
+///
+/// # Returns
+///
+/// Manifest dictionary of the restored backup
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (archive, include_audit=false, policy_dir=None, config_dir=None, state_dir=None, audit_db=None, vault_key=None))]
+pub fn restore(
+    py: Python,
+    archive: String,
+    include_audit: bool,
+    policy_dir: Option<String>,
+    config_dir: Option<String>,
+    state_dir: Option<String>,
+    audit_db: Option<String>,
+    vault_key: Option<String>,
+) -> PyResult<PyObject> {
+    let paths = py_paths(policy_dir, config_dir, state_dir, audit_db, vault_key);
+    let manifest = restore_backup(Path::new(&archive), &paths, include_audit)
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+    manifest_to_py(py, &manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(root: &Path) -> BackupPaths {
+        BackupPaths {
+            policy_dir: root.join("etc/policies"),
+            config_dir: root.join("etc"),
+            state_dir: root.join("db/state"),
+            audit_db: root.join("db/audit.db"),
+            vault_key: None,
+        }
+    }
+
+    #[test]
+    fn test_backup_restore_roundtrip() {
+        let src = tempfile::tempdir().unwrap();
+        let paths = layout(src.path());
+        fs::create_dir_all(paths.policy_dir.join("tenants/unit-a")).unwrap();
+        fs::create_dir_all(&paths.state_dir).unwrap();
+        fs::write(
+            paths.policy_dir.join("bedtime.rego"),
+            "package yori.bedtime",
+        )
+        .unwrap();
+        fs::write(paths.policy_dir.join("tenants/unit-a/data.json"), "{}").unwrap();
+        fs::write(paths.config_dir.join("yori.conf"), "mode: enforce\n").unwrap();
+        fs::write(paths.state_dir.join("quota.json"), "{\"alice\": 10}").unwrap();
+        rusqlite::Connection::open(&paths.audit_db)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (42);")
+            .unwrap();
+
+        let archive = src.path().join("yori-backup.tar.gz");
+        let manifest = create_backup(&paths, &archive, true).unwrap();
+        assert!(manifest
+            .files
+            .contains_key("policies/tenants/unit-a/data.json"));
+        assert!(manifest.files.contains_key("config/yori.conf"));
+        assert!(!manifest
+            .files
+            .keys()
+            .any(|k| k.starts_with("config/policies")));
+        assert_eq!(verify_backup(&archive).unwrap(), manifest);
+
+        let dst = tempfile::tempdir().unwrap();
+        let restored = layout(dst.path());
+        restore_backup(&archive, &restored, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(restored.policy_dir.join("bedtime.rego")).unwrap(),
+            "package yori.bedtime"
+        );
+        assert_eq!(
+            fs::read_to_string(restored.state_dir.join("quota.json")).unwrap(),
+            "{\"alice\": 10}"
+        );
+        let x: i64 = rusqlite::Connection::open(&restored.audit_db)
+            .unwrap()
+            .query_row("SELECT x FROM t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(x, 42);
+    }
+
+    #[test]
+    fn test_tampered_archive_is_rejected() {
+        let src = tempfile::tempdir().unwrap();
+        let paths = layout(src.path());
+        fs::create_dir_all(&paths.policy_dir).unwrap();
+        fs::write(paths.policy_dir.join("a.rego"), "package a").unwrap();
+
+        let archive = src.path().join("backup.tar.gz");
+        let mut manifest = create_backup(&paths, &archive, false).unwrap();
+
+        // Rewrite the archive with a manifest whose checksum doesn't match
+        manifest
+            .files
+            .insert("policies/a.rego".to_string(), "00".repeat(32));
+        let sources = BTreeMap::from([(
+            "policies/a.rego".to_string(),
+            paths.policy_dir.join("a.rego"),
+        )]);
+        let file = File::create(&archive).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, MANIFEST_NAME, json.as_slice())
+            .unwrap();
+        for (name, path) in &sources {
+            builder.append_path_with_name(path, name).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let err = verify_backup(&archive).unwrap_err().to_string();
+        assert!(err.contains("integrity check failed"));
+        let dst = tempfile::tempdir().unwrap();
+        assert!(restore_backup(&archive, &layout(dst.path()), false).is_err());
+        assert!(!layout(dst.path()).policy_dir.join("a.rego").exists());
+    }
+}
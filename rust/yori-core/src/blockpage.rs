@@ -0,0 +1,288 @@
+//! Responses for requests blocked by policy
+//!
+//! A bare 403 with an empty body leaves apps showing "something went
+//! wrong". Blocked requests are answered in a form the client can show:
+//!
+//! - API clients get the error JSON of the provider they were talking to
+//!   (OpenAI-style `{"error": {...}}` for most, Anthropic's and Gemini's
+//!   own shapes for theirs), so SDKs raise a normal API error carrying the
+//!   reason
+//! - browsers (an `Accept` header preferring `text/html`) get a short HTML
+//!   page
+//!
+//! The text comes from a template filled from the block's audit event:
+//! `{reason}`, `{policy}`, `{device}`, `{endpoint}`, `{request_id}` and
+//! `{time}`. A custom HTML page uses the same placeholders plus
+//! `{title}` and `{message}`; substituted values are HTML-escaped.
+
+use chrono::Local;
+use serde_json::json;
+
+use crate::audit::AuditEvent;
+use crate::honeypot::SyntheticResponse;
+use crate::providers::Provider;
+
+/// Header naming the policy that blocked a request
+pub const BLOCKED_BY_HEADER: &str = "x-yori-blocked-by";
+
+const DEFAULT_HTML: &str = "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 36rem; margin: 4rem auto; padding: 0 1rem; color: #222; }
+h1 { font-size: 1.5rem; }
+.details { color: #666; font-size: 0.875rem; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{message}</p>
+<p class=\"details\">Policy: {policy}<br>Reference: {request_id}<br>Time: {time}</p>
+</body>
+</html>
+";
+
+/// Block response settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockPageConfig {
+    /// Heading of the HTML page
+    pub title: String,
+
+    /// Message template shown to the user
+    pub message: String,
+
+    /// HTML page template (None = the built-in page)
+    pub html_template: Option<String>,
+}
+
+impl Default for BlockPageConfig {
+    fn default() -> Self {
+        BlockPageConfig {
+            title: "Blocked by your home network".to_string(),
+            message: "{reason}".to_string(),
+            html_template: None,
+        }
+    }
+}
+
+/// Body format chosen for a blocked request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// Provider-shaped error JSON
+    Json,
+
+    /// HTML block page
+    Html,
+}
+
+impl BlockFormat {
+    /// Pick a format from the request's `Accept` header
+    ///
+    /// HTML only when `text/html` is explicitly preferred over JSON, so
+    /// clients sending `*/*` (or nothing) still get JSON.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let mut html: f32 = 0.0;
+        let mut json: f32 = 0.0;
+        for range in accept.unwrap_or_default().split(',') {
+            let mut parts = range.split(';');
+            let media = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match media.as_str() {
+                "text/html" | "application/xhtml+xml" => html = html.max(q),
+                "application/json" | "application/*" | "*/*" => json = json.max(q),
+                _ => {}
+            }
+        }
+        if html > json {
+            BlockFormat::Html
+        } else {
+            BlockFormat::Json
+        }
+    }
+}
+
+/// Renders responses for blocked requests
+#[derive(Debug, Clone, Default)]
+pub struct BlockPage {
+    config: BlockPageConfig,
+}
+
+impl BlockPage {
+    /// Create from configuration
+    pub fn new(config: BlockPageConfig) -> Self {
+        BlockPage { config }
+    }
+
+    /// User-facing message for a block event
+    pub fn message(&self, event: &AuditEvent) -> String {
+        fill(&self.config.message, event, |s| s.to_string())
+    }
+
+    /// 403 response for a block event, in the format the client accepts
+    pub fn render(
+        &self,
+        event: &AuditEvent,
+        provider: Option<Provider>,
+        accept: Option<&str>,
+    ) -> SyntheticResponse {
+        let message = self.message(event);
+        let policy = event.policy_name.as_deref().unwrap_or("policy");
+        let mut headers = vec![(BLOCKED_BY_HEADER.to_string(), policy.to_string())];
+
+        let (content_type, body) = match BlockFormat::negotiate(accept) {
+            BlockFormat::Html => {
+                let template = self.config.html_template.as_deref().unwrap_or(DEFAULT_HTML);
+                let page = fill(template, event, html_escape)
+                    .replace("{title}", &html_escape(&self.config.title))
+                    .replace("{message}", &html_escape(&message));
+                ("text/html; charset=utf-8", page)
+            }
+            BlockFormat::Json => {
+                let body = match provider {
+                    Some(Provider::Anthropic) => json!({
+                        "type": "error",
+                        "error": {"type": "permission_error", "message": message},
+                    }),
+                    Some(Provider::Gemini) => json!({
+                        "error": {"code": 403, "message": message, "status": "PERMISSION_DENIED"},
+                    }),
+                    Some(Provider::Bedrock) => {
+                        headers.push((
+                            "x-amzn-errortype".to_string(),
+                            "AccessDeniedException".to_string(),
+                        ));
+                        json!({ "message": message })
+                    }
+                    _ => json!({
+                        "error": {
+                            "message": message,
+                            "type": "permission_error",
+                            "param": null,
+                            "code": "blocked_by_policy",
+                        }
+                    }),
+                };
+                ("application/json", body.to_string())
+            }
+        };
+        headers.insert(0, ("content-type".to_string(), content_type.to_string()));
+
+        SyntheticResponse {
+            status: 403,
+            headers,
+            body,
+        }
+    }
+}
+
+/// Substitute the event placeholders in `template`, passing each value
+/// through `escape`
+fn fill(template: &str, event: &AuditEvent, escape: impl Fn(&str) -> String) -> String {
+    let device = event.client_device.as_deref().unwrap_or(&event.client_ip);
+    template
+        .replace(
+            "{reason}",
+            &escape(
+                event
+                    .policy_reason
+                    .as_deref()
+                    .unwrap_or("Blocked by policy"),
+            ),
+        )
+        .replace(
+            "{policy}",
+            &escape(event.policy_name.as_deref().unwrap_or("policy")),
+        )
+        .replace("{device}", &escape(device))
+        .replace("{endpoint}", &escape(&event.endpoint))
+        .replace("{request_id}", &escape(&event.request_id))
+        .replace("{time}", &Local::now().format("%H:%M").to_string())
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditEventType;
+    use crate::proxy::{test_request, RequestContext};
+
+    fn blocked() -> AuditEvent {
+        let request = RequestContext {
+            client_device: Some("sam-ipad".to_string()),
+            ..test_request()
+        };
+        AuditEvent::from_request(AuditEventType::RequestBlocked, &request).with_policy(
+            "bedtime",
+            "block",
+            "It's past <bedtime> on a school night",
+        )
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(BlockFormat::negotiate(None), BlockFormat::Json);
+        assert_eq!(BlockFormat::negotiate(Some("*/*")), BlockFormat::Json);
+        assert_eq!(
+            BlockFormat::negotiate(Some("application/json, text/html;q=0.5")),
+            BlockFormat::Json
+        );
+        assert_eq!(
+            BlockFormat::negotiate(Some(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            )),
+            BlockFormat::Html
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let page = BlockPage::new(BlockPageConfig {
+            message: "{device}: {reason}".to_string(),
+            ..BlockPageConfig::default()
+        });
+        let event = blocked();
+
+        let response = page.render(&event, Some(Provider::OpenAI), Some("application/json"));
+        assert_eq!(response.status, 403);
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(
+            body["error"]["message"],
+            "sam-ipad: It's past <bedtime> on a school night"
+        );
+        assert_eq!(body["error"]["code"], "blocked_by_policy");
+        assert!(response
+            .headers
+            .contains(&(BLOCKED_BY_HEADER.to_string(), "bedtime".to_string())));
+
+        let response = page.render(&event, Some(Provider::Anthropic), None);
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["error"]["type"], "permission_error");
+
+        let response = page.render(&event, None, Some("text/html"));
+        assert_eq!(response.headers[0].1, "text/html; charset=utf-8");
+        assert!(response
+            .body
+            .contains("sam-ipad: It&#39;s past &lt;bedtime&gt; on a school night"));
+        assert!(response.body.contains(&event.request_id));
+    }
+}
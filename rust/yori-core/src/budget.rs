@@ -0,0 +1,331 @@
+//! Monthly spend caps per user
+//!
+//! The [`BudgetTracker`] adds up the estimated cost of every forwarded
+//! request (see [`crate::cost`]) per subject (the device owner when the
+//! device is known, otherwise the client IP) for the current local month.
+//! Like quota counters, the totals are persisted to a small JSON file so a
+//! reboot doesn't reset them.
+//!
+//! Policies see the running total as `input.budget`:
+//!
+//! ```rego
+//! deny contains "monthly AI budget used up" if {
+//!     input.device.group == "kids"
+//!     input.budget.spent_this_month >= 5
+//! }
+//! ```
+//!
+//! With `hard_cap` set, a subject that has reached its cap is refused
+//! before the policies run, whatever they or the proxy mode say.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::persist::write_atomic;
+
+/// Default location of the persisted monthly totals
+pub const DEFAULT_BUDGET_STATE: &str = "/var/db/yori/state/budget.json";
+
+/// Policy name recorded when a hard cap blocks a request
+pub const BUDGET_POLICY: &str = "budget";
+
+/// Minimum time between writes of the totals file
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Budget configuration
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BudgetConfig {
+    /// File the totals are persisted to (None = in memory only)
+    pub state_path: Option<PathBuf>,
+
+    /// Monthly cap in USD for subjects without their own (None = no cap)
+    pub monthly_cap_usd: Option<f64>,
+
+    /// Monthly caps per subject (device owner or client IP)
+    pub subjects: HashMap<String, f64>,
+
+    /// Refuse requests from subjects over their cap, regardless of policy
+    /// and mode
+    pub hard_cap: bool,
+}
+
+/// Spend of one subject
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Spend {
+    /// Counted month ("2026-03")
+    month: Option<String>,
+    usd: f64,
+}
+
+impl Spend {
+    /// Start over if `month` is a new one
+    fn roll(&mut self, month: &str) {
+        if self.month.as_deref() != Some(month) {
+            self.month = Some(month.to_string());
+            self.usd = 0.0;
+        }
+    }
+}
+
+/// A subject's spend and cap at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    /// Local month the spend is for ("2026-03")
+    pub month: String,
+
+    /// Estimated spend since the start of the month, in USD
+    pub spent_this_month: f64,
+
+    /// Monthly cap in USD (None = no cap)
+    pub cap: Option<f64>,
+}
+
+impl BudgetStatus {
+    /// Cap left this month (None = no cap)
+    pub fn remaining(&self) -> Option<f64> {
+        self.cap.map(|cap| (cap - self.spent_this_month).max(0.0))
+    }
+
+    /// Whether the cap has been reached
+    pub fn exceeded(&self) -> bool {
+        self.cap.is_some_and(|cap| self.spent_this_month >= cap)
+    }
+
+    /// Policy input section
+    pub fn to_json(&self) -> Value {
+        json!({
+            "month": self.month,
+            "spent_this_month": self.spent_this_month,
+            "cap": self.cap,
+            "remaining": self.remaining(),
+            "exceeded": self.exceeded(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    spend: HashMap<String, Spend>,
+    dirty: bool,
+    last_flush: Instant,
+}
+
+/// Monthly spend per subject
+#[derive(Debug)]
+pub struct BudgetTracker {
+    config: BudgetConfig,
+    state: Mutex<State>,
+}
+
+impl Default for BudgetTracker {
+    fn default() -> Self {
+        BudgetTracker::new(BudgetConfig::default())
+    }
+}
+
+impl BudgetTracker {
+    /// Create a tracker, restoring persisted totals if there are any
+    ///
+    /// An unreadable totals file is logged and replaced rather than keeping
+    /// the proxy from starting.
+    pub fn new(config: BudgetConfig) -> Self {
+        let spend = match &config.state_path {
+            Some(path) => load_spend(path).unwrap_or_else(|e| {
+                tracing::warn!("Starting with empty budget totals: {:#}", e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        BudgetTracker {
+            config,
+            state: Mutex::new(State {
+                spend,
+                dirty: false,
+                last_flush: Instant::now(),
+            }),
+        }
+    }
+
+    /// Whether over-cap subjects are refused regardless of policy
+    pub fn hard_cap(&self) -> bool {
+        self.config.hard_cap
+    }
+
+    /// Monthly cap of `subject`
+    pub fn cap(&self, subject: &str) -> Option<f64> {
+        self.config
+            .subjects
+            .get(subject)
+            .copied()
+            .or(self.config.monthly_cap_usd)
+    }
+
+    /// Add the estimated cost of one completed request
+    pub fn record(&self, tenant: &str, subject: &str, cost_usd: f64, now: DateTime<Utc>) {
+        if cost_usd <= 0.0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let spend = state.spend.entry(spend_key(tenant, subject)).or_default();
+        spend.roll(&local_month(now));
+        spend.usd += cost_usd;
+        state.dirty = true;
+
+        if state.last_flush.elapsed() >= FLUSH_INTERVAL {
+            if let Err(e) = self.write(&mut state) {
+                tracing::warn!("Failed to persist budget totals: {:#}", e);
+            }
+        }
+    }
+
+    /// Current spend and cap of `subject`
+    pub fn check(&self, tenant: &str, subject: &str, now: DateTime<Utc>) -> BudgetStatus {
+        let month = local_month(now);
+        let mut spend = self
+            .state
+            .lock()
+            .unwrap()
+            .spend
+            .get(&spend_key(tenant, subject))
+            .cloned()
+            .unwrap_or_default();
+        spend.roll(&month);
+        BudgetStatus {
+            month,
+            spent_this_month: spend.usd,
+            cap: self.cap(subject),
+        }
+    }
+
+    /// Write the totals to the state file if they changed
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.write(&mut state)
+    }
+
+    fn write(&self, state: &mut State) -> Result<()> {
+        let Some(path) = &self.config.state_path else {
+            return Ok(());
+        };
+        if !state.dirty {
+            return Ok(());
+        }
+        write_atomic(path, &serde_json::to_vec(&state.spend)?)?;
+        state.dirty = false;
+        state.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for BudgetTracker {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("Failed to persist budget totals: {:#}", e);
+        }
+    }
+}
+
+/// JSON body of the 403 sent once a hard cap is reached
+pub fn budget_exceeded_body(subject: &str, status: &BudgetStatus) -> Value {
+    json!({
+        "error": {
+            "type": "budget_exceeded",
+            "message": budget_reason(subject, status),
+            "month": status.month,
+            "spent_usd": status.spent_this_month,
+            "cap_usd": status.cap,
+        }
+    })
+}
+
+/// Block reason for a subject over its cap
+pub fn budget_reason(subject: &str, status: &BudgetStatus) -> String {
+    format!(
+        "Monthly AI budget of ${:.2} for {} is used up (${:.2} spent in {})",
+        status.cap.unwrap_or(0.0),
+        subject,
+        status.spent_this_month,
+        status.month
+    )
+}
+
+fn spend_key(tenant: &str, subject: &str) -> String {
+    format!("{}/{}", tenant, subject)
+}
+
+fn local_month(time: DateTime<Utc>) -> String {
+    let local = time.with_timezone(&Local);
+    format!("{:04}-{:02}", local.year(), local.month())
+}
+
+fn load_spend(path: &std::path::Path) -> Result<HashMap<String, Spend>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid budget state in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_spend_capped_and_reset_monthly() {
+        let budgets = BudgetTracker::new(BudgetConfig {
+            monthly_cap_usd: Some(10.0),
+            subjects: HashMap::from([("timmy".to_string(), 2.0)]),
+            hard_cap: true,
+            ..BudgetConfig::default()
+        });
+        let march = Local
+            .with_ymd_and_hms(2026, 3, 30, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        budgets.record("default", "timmy", 1.25, march);
+        budgets.record("default", "timmy", 0.75, march);
+        budgets.record("default", "mum", 3.0, march);
+        let timmy = budgets.check("default", "timmy", march);
+        assert_eq!(
+            (timmy.month.as_str(), timmy.spent_this_month),
+            ("2026-03", 2.0)
+        );
+        assert!(timmy.exceeded());
+        assert_eq!(timmy.remaining(), Some(0.0));
+        let mum = budgets.check("default", "mum", march);
+        assert_eq!((mum.cap, mum.exceeded()), (Some(10.0), false));
+        assert_eq!(budgets.check("other", "timmy", march).spent_this_month, 0.0);
+
+        let april = budgets.check("default", "timmy", march + chrono::Duration::days(3));
+        assert_eq!((april.month.as_str(), april.exceeded()), ("2026-04", false));
+    }
+
+    #[test]
+    fn test_spend_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = BudgetConfig {
+            state_path: Some(dir.path().join("state/budget.json")),
+            ..BudgetConfig::default()
+        };
+        let now = Utc::now();
+
+        let budgets = BudgetTracker::new(config.clone());
+        budgets.record("default", "192.168.1.50", 0.42, now);
+        drop(budgets);
+
+        let restored = BudgetTracker::new(config);
+        let status = restored.check("default", "192.168.1.50", now);
+        assert_eq!((status.spent_this_month, status.cap), (0.42, None));
+        assert!(!status.exceeded());
+    }
+}
@@ -0,0 +1,456 @@
+//! Policy bundles pulled from a central server
+//!
+//! Several YORI boxes (one per floor, or per family member's household)
+//! can share one set of centrally managed policies. The [`BundleDownloader`]
+//! fetches an OPA-style bundle, a `.tar.gz` of `.rego` files and `data.json`
+//! documents, from an HTTPS URL at a fixed interval:
+//!
+//! ```toml
+//! [policy]
+//! bundle_url = "https://policies.example.net/family/bundle.tar.gz"
+//! bundle_interval_secs = 300
+//! trusted_keys = ["3b6a27bc..."]
+//! ```
+//!
+//! The last `ETag` is sent as `If-None-Match`, so an unchanged bundle costs
+//! a single 304. A new bundle is unpacked to a staging directory and, when
+//! trusted keys are configured, must carry a valid `bundle.manifest` and
+//! `bundle.sig` (see [`crate::signing`]; OPA's JWT `.signatures.json` is
+//! not supported). It is only copied into the policy directory once it
+//! verifies and compiles, so a bad download never replaces working
+//! policies. Policy and data files the bundle no longer contains are
+//! removed.
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper::client::conn::http1;
+use hyper::header::{AUTHORIZATION, ETAG, HOST, IF_NONE_MATCH, USER_AGENT};
+use hyper::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsConnector;
+
+use crate::alerts::{load_tls, parse_url};
+use crate::explain::read_files;
+use crate::policy::PolicyEngine;
+use crate::signing::BundleVerifier;
+use crate::watcher::is_policy_file;
+
+/// Default time between bundle checks
+pub const DEFAULT_BUNDLE_INTERVAL_SECS: u64 = 300;
+
+/// Largest bundle download accepted (compressed)
+const MAX_BUNDLE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Largest unpacked bundle accepted
+const MAX_UNPACKED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Directory next to the policy directory a download is unpacked into
+const STAGING_DIR: &str = ".yori-bundle-staging";
+
+/// Bundle download settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleConfig {
+    /// Where the bundle is fetched from (https://, or http:// for testing)
+    pub url: String,
+
+    /// Time between checks
+    pub interval: Duration,
+
+    /// Bearer token sent with the request
+    pub token: Option<String>,
+
+    /// PEM bundle of trusted CAs (None = system bundle)
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Signers the bundle must be signed by (None = unsigned bundles accepted)
+    pub verifier: Option<BundleVerifier>,
+}
+
+/// Result of one bundle check
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleUpdate {
+    /// The server answered 304 for the last ETag
+    NotModified,
+
+    /// A new bundle was installed
+    Updated {
+        /// Files written or replaced
+        files: usize,
+
+        /// Files removed because the bundle no longer has them
+        removed: usize,
+
+        etag: Option<String>,
+    },
+}
+
+/// Fetches bundles into a policy directory
+pub struct BundleDownloader {
+    config: BundleConfig,
+    policy_dir: PathBuf,
+    tls: Option<TlsConnector>,
+
+    /// ETag of the installed bundle
+    etag: Mutex<Option<String>>,
+}
+
+impl BundleDownloader {
+    /// Create a downloader installing into `policy_dir`
+    pub fn new(config: BundleConfig, policy_dir: impl Into<PathBuf>) -> Result<Self> {
+        let url = parse_url(&config.url).context("invalid bundle URL")?;
+        let tls = match url.tls {
+            true => Some(load_tls(config.ca_bundle.as_ref())?),
+            false => None,
+        };
+        Ok(BundleDownloader {
+            config,
+            policy_dir: policy_dir.into(),
+            tls,
+            etag: Mutex::new(None),
+        })
+    }
+
+    /// Check the bundle once, installing it if it changed
+    pub async fn fetch_once(&self) -> Result<BundleUpdate> {
+        let etag = self.etag.lock().unwrap().clone();
+        let Some((body, etag)) = self.download(etag.as_deref()).await? else {
+            return Ok(BundleUpdate::NotModified);
+        };
+
+        let policy_dir = self.policy_dir.clone();
+        let verifier = self.config.verifier.clone();
+        let (files, removed) =
+            tokio::task::spawn_blocking(move || install(&body, &policy_dir, verifier.as_ref()))
+                .await
+                .context("bundle install panicked")??;
+        *self.etag.lock().unwrap() = etag.clone();
+        Ok(BundleUpdate::Updated {
+            files,
+            removed,
+            etag,
+        })
+    }
+
+    /// Check every interval, reloading `engine` after each new bundle
+    ///
+    /// Failures are logged and retried at the next interval; the installed
+    /// policies stay in force meanwhile.
+    pub fn spawn(self: Arc<Self>, engine: Arc<PolicyEngine>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                match self.fetch_once().await {
+                    Ok(BundleUpdate::NotModified) => {}
+                    Ok(BundleUpdate::Updated { files, removed, .. }) => {
+                        tracing::info!(
+                            "Installed policy bundle from {} ({} files, {} removed)",
+                            self.config.url,
+                            files,
+                            removed
+                        );
+                        let engine = Arc::clone(&engine);
+                        match tokio::task::spawn_blocking(move || engine.reload()).await {
+                            Ok(Ok(_)) => {}
+                            Ok(Err(e)) => {
+                                tracing::error!("Policy reload after bundle update failed: {:#}", e)
+                            }
+                            Err(e) => tracing::error!("Policy reload panicked: {}", e),
+                        }
+                    }
+                    Err(e) => tracing::warn!("Policy bundle check failed: {:#}", e),
+                }
+            }
+        })
+    }
+
+    /// GET the bundle, returning None for 304
+    async fn download(&self, etag: Option<&str>) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        let url = parse_url(&self.config.url)?;
+        let mut request = Request::get(url.path.as_str())
+            .header(HOST, url.host.as_str())
+            .header(USER_AGENT, "yori-bundle");
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(token) = &self.config.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request.body(Empty::<Bytes>::new())?;
+
+        let tcp = TcpStream::connect((url.host.as_str(), url.port))
+            .await
+            .with_context(|| format!("failed to connect to {}:{}", url.host, url.port))?;
+        match &self.tls {
+            Some(connector) => {
+                let server_name = ServerName::try_from(url.host.as_str())
+                    .with_context(|| format!("invalid host name {}", url.host))?;
+                let tls = connector.connect(server_name, tcp).await?;
+                get(tls, request).await
+            }
+            None => get(tcp, request).await,
+        }
+    }
+}
+
+/// Send `request` on a fresh HTTP/1.1 connection
+async fn get(
+    io: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    request: Request<Empty<Bytes>>,
+) -> Result<Option<(Vec<u8>, Option<String>)>> {
+    let (mut sender, conn) = http1::handshake(TokioIo::new(io))
+        .await
+        .context("HTTP/1.1 handshake failed")?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            tracing::debug!("Bundle server connection closed: {}", e);
+        }
+    });
+    let response = sender.send_request(request).await?;
+    match response.status() {
+        StatusCode::NOT_MODIFIED => return Ok(None),
+        StatusCode::OK => {}
+        status => bail!("bundle server answered {}", status),
+    }
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = Limited::new(response.into_body(), MAX_BUNDLE_BYTES)
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to read bundle: {}", e))?
+        .to_bytes();
+    Ok(Some((body.to_vec(), etag)))
+}
+
+/// Unpack, verify and test-load a bundle, then copy it into `policy_dir`,
+/// returning the files written and removed
+fn install(
+    archive: &[u8],
+    policy_dir: &Path,
+    verifier: Option<&BundleVerifier>,
+) -> Result<(usize, usize)> {
+    let parent = policy_dir.parent().unwrap_or(Path::new("."));
+    let staging = parent.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .with_context(|| format!("failed to clear {}", staging.display()))?;
+    }
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("failed to create {}", staging.display()))?;
+    let result = install_from(archive, &staging, policy_dir, verifier);
+    if let Err(e) = fs::remove_dir_all(&staging) {
+        tracing::warn!("Failed to remove {}: {}", staging.display(), e);
+    }
+    result
+}
+
+fn install_from(
+    archive: &[u8],
+    staging: &Path,
+    policy_dir: &Path,
+    verifier: Option<&BundleVerifier>,
+) -> Result<(usize, usize)> {
+    unpack(archive, staging)?;
+    if let Some(verifier) = verifier {
+        verifier
+            .verify(staging)
+            .context("bundle signature check failed")?;
+    }
+    PolicyEngine::open(staging).context("bundle policies don't load")?;
+
+    // Copy changed files in, then drop the ones the bundle no longer has
+    fs::create_dir_all(policy_dir)?;
+    let incoming = read_files(staging, "bundle", is_policy_file)?;
+    let mut written = 0;
+    for (file, contents) in &incoming {
+        let target = policy_dir.join(file);
+        if fs::read_to_string(&target).is_ok_and(|current| &current == contents) {
+            continue;
+        }
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = target.with_extension("bundle-tmp");
+        fs::write(&tmp, contents).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &target)
+            .with_context(|| format!("failed to replace {}", target.display()))?;
+        written += 1;
+    }
+    let keep: BTreeSet<&String> = incoming.iter().map(|(file, _)| file).collect();
+    let mut removed = 0;
+    for (file, _) in read_files(policy_dir, "policy", is_policy_file)? {
+        if !keep.contains(&file) {
+            fs::remove_file(policy_dir.join(&file))
+                .with_context(|| format!("failed to remove {}", file))?;
+            removed += 1;
+        }
+    }
+    Ok((written, removed))
+}
+
+/// Extract the regular files of a `.tar.gz` into `dir`
+fn unpack(archive: &[u8], dir: &Path) -> Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(archive));
+    let mut total = 0u64;
+    for entry in tar.entries().context("bundle is not a .tar.gz")? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        // OPA bundles are often packed as "./policy.rego" or "/policy.rego"
+        let relative: PathBuf = path
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir | Component::RootDir))
+            .collect();
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!("bundle contains unsafe path {}", path.display());
+        }
+        total += entry.header().size()?;
+        if total > MAX_UNPACKED_BYTES {
+            bail!("bundle unpacks to more than {} bytes", MAX_UNPACKED_BYTES);
+        }
+        let target = dir.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        fs::write(&target, contents)
+            .with_context(|| format!("failed to write {}", target.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn bundle(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Answer two requests: the bundle with an ETag, then 304 if it is sent back
+    async fn serve(archive: Vec<u8>) -> (String, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/bundle.tar.gz", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                if request.contains("if-none-match: \"v1\"") {
+                    stream
+                        .write_all(b"HTTP/1.1 304 Not Modified\r\n\r\n")
+                        .await
+                        .unwrap();
+                } else {
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\n\r\n",
+                        archive.len()
+                    );
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(&archive).await.unwrap();
+                }
+            }
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_bundle_installed_and_cached_by_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy_dir = dir.path().join("policies");
+        fs::create_dir(&policy_dir).unwrap();
+        fs::write(policy_dir.join("old.rego"), "package yori.old\n").unwrap();
+
+        let archive = bundle(&[
+            (
+                "./bedtime.rego",
+                "package yori.bedtime\n\ndefault allow := true\n",
+            ),
+            ("data.json", "{\"bedtime\": \"21:00\"}"),
+            (".manifest", "{\"revision\": \"1\"}"),
+        ]);
+        let (url, server) = serve(archive).await;
+        let downloader = BundleDownloader::new(
+            BundleConfig {
+                url,
+                interval: Duration::from_secs(DEFAULT_BUNDLE_INTERVAL_SECS),
+                token: None,
+                ca_bundle: None,
+                verifier: None,
+            },
+            &policy_dir,
+        )
+        .unwrap();
+
+        assert_eq!(
+            downloader.fetch_once().await.unwrap(),
+            BundleUpdate::Updated {
+                files: 2,
+                removed: 1,
+                etag: Some("\"v1\"".to_string())
+            }
+        );
+        assert!(policy_dir.join("bedtime.rego").exists());
+        assert!(!policy_dir.join("old.rego").exists());
+        assert_eq!(
+            downloader.fetch_once().await.unwrap(),
+            BundleUpdate::NotModified
+        );
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_bad_bundles_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy_dir = dir.path().join("policies");
+
+        let broken = bundle(&[("bedtime.rego", "package yori.bedtime\n\nallow if {\n")]);
+        assert!(install(&broken, &policy_dir, None).is_err());
+        assert!(!policy_dir.join("bedtime.rego").exists());
+
+        let signer = crate::signing::PolicySigner::open_or_create(&dir.path().join("key")).unwrap();
+        let verifier = BundleVerifier::new(&[signer.public_key()]).unwrap();
+        let unsigned = bundle(&[("bedtime.rego", "package yori.bedtime\n")]);
+        let error = install(&unsigned, &policy_dir, Some(&verifier)).unwrap_err();
+        assert!(format!("{:#}", error).contains("not signed"));
+    }
+}
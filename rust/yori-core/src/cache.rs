@@ -1,26 +1,777 @@
-//! In-memory cache using SARK's lock-free cache implementation
+//! In-memory LRU cache with per-entry TTLs
 //!
-//! This module wraps sark-cache to provide fast, thread-safe caching
-//! without requiring Redis on resource-constrained home routers.
+//! Fast, thread-safe caching without requiring Redis on resource-constrained
+//! home routers. Entries expire after their TTL and the least recently used
+//! entry is evicted when the cache is full.
+//!
+//! Eviction is approximate LRU, as in Redis: a handful of random entries
+//! are sampled and an expired one, or else the least recently used of the
+//! sample, is dropped. That keeps inserts O(1) at capacity instead of
+//! scanning 100k entries for the oldest, and in practice evicts nearly
+//! the same entries as exact LRU.
+//!
+//! Prompt and completion entries vary from a few bytes to hundreds of
+//! kilobytes, so an entry count alone doesn't bound memory. With
+//! [`LruTtlCache::with_max_bytes`] every entry is weighed (key, value and
+//! bookkeeping) and entries are evicted until the total fits.
+//!
+//! A cache can be snapshotted to disk so a router reboot doesn't start
+//! every lookup cold. The snapshot stores each entry's remaining TTL and
+//! the wall-clock time it was written; on restore the time spent powered
+//! off is subtracted, so an entry that would have expired while the
+//! router was down is dropped rather than resurrected.
+//!
+//! Keys are grouped into namespaces by their prefix up to the first `:`
+//! (`policy:alice:openai`, `identity:192.168.1.20`), and entries can carry
+//! tags. [`LruTtlCache::invalidate_namespace`] and
+//! [`LruTtlCache::invalidate_tag`] drop a whole group at once, so a policy
+//! reload flushes decisions without cold-starting device identities.
+//!
+//! Values are any serde type (a policy decision as `serde_json::Value`, a
+//! token count as `u64`), stored as-is rather than re-serialized per lookup.
 
+use anyhow::{bail, Context, Result};
+use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
-use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::persist::write_atomic;
+use crate::policy::{json_to_py, py_to_json};
+
+/// Default time between snapshots while the cache is changing
+pub const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 300;
+
+/// Approximate bookkeeping per entry beyond its key and value (map slot,
+/// key copy in the sampling table, timestamps)
+const ENTRY_OVERHEAD_BYTES: usize = 96;
+
+/// Entries examined per eviction
+const EVICTION_SAMPLES: usize = 5;
+
+/// Snapshot file format version (2: values serialized as-is, not as strings)
+const SNAPSHOT_VERSION: u32 = 2;
+
+/// Where and how often a cache is persisted
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotConfig {
+    /// Snapshot file (written atomically via a `.tmp` sibling)
+    pub path: PathBuf,
+
+    /// Minimum time between snapshots taken on insert
+    pub interval: Duration,
+}
+
+impl SnapshotConfig {
+    /// Snapshot to `path` every [`DEFAULT_SNAPSHOT_INTERVAL_SECS`]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SnapshotConfig {
+            path: path.into(),
+            interval: Duration::from_secs(DEFAULT_SNAPSHOT_INTERVAL_SECS),
+        }
+    }
+}
+
+/// What an [`LruTtlCache`] can store: cloned out on lookup, serialized
+/// into snapshots
+pub trait CacheValue: Clone + Serialize + DeserializeOwned {}
+
+impl<T: Clone + Serialize + DeserializeOwned> CacheValue for T {}
+
+/// Approximate in-memory size of a value: the length of its JSON form
+///
+/// The default weigher of a byte-bounded [`LruTtlCache`]; counted without
+/// allocating the JSON.
+pub fn json_weight<V: Serialize>(value: &V) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    last_access: u64,
+    /// Index of the key in `State::slots`
+    slot: usize,
+    /// Accounted size (0 when the cache has no byte limit)
+    weight: usize,
+    tags: Vec<String>,
+}
+
+#[derive(Debug)]
+struct State<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Every key, densely packed so eviction can sample at random
+    slots: Vec<String>,
+    /// Access counter; higher is more recent
+    clock: u64,
+    /// xorshift state for eviction sampling
+    rng: u64,
+    /// Sum of entry weights
+    bytes: usize,
+    /// Keys carrying each tag
+    tagged: HashMap<String, HashSet<String>>,
+    dirty: bool,
+    last_snapshot: Instant,
+}
+
+impl<V> State<V> {
+    fn new() -> Self {
+        State {
+            entries: HashMap::new(),
+            slots: Vec::new(),
+            clock: 0,
+            rng: RandomState::new().build_hasher().finish() | 1,
+            bytes: 0,
+            tagged: HashMap::new(),
+            dirty: false,
+            last_snapshot: Instant::now(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn random_below(&mut self, n: usize) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % n as u64) as usize
+    }
+
+    /// Insert `key` (not currently held), marking it most recently used
+    fn put(
+        &mut self,
+        key: String,
+        value: V,
+        expires_at: Instant,
+        weight: usize,
+        tags: Vec<String>,
+    ) {
+        let tick = self.tick();
+        self.bytes += weight;
+        for tag in &tags {
+            self.tagged
+                .entry(tag.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+        self.slots.push(key.clone());
+        let slot = self.slots.len() - 1;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                last_access: tick,
+                slot,
+                weight,
+                tags,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.bytes -= entry.weight;
+        for tag in &entry.tags {
+            if let Some(keys) = self.tagged.get_mut(tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.tagged.remove(tag);
+                }
+            }
+        }
+        self.slots.swap_remove(entry.slot);
+        // The last key moved into the freed slot
+        if let Some(moved) = self.slots.get(entry.slot) {
+            if let Some(moved) = self.entries.get_mut(moved) {
+                moved.slot = entry.slot;
+            }
+        }
+        Some(entry)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.slots.clear();
+        self.tagged.clear();
+        self.bytes = 0;
+    }
+
+    /// Drop one entry to make room: the first expired entry in a random
+    /// sample, else the sample's least recently used one
+    ///
+    /// Returns whether the dropped entry had expired (None if empty).
+    fn evict(&mut self, now: Instant) -> Option<bool> {
+        let len = self.slots.len();
+        if len == 0 {
+            return None;
+        }
+        // Small caches are scanned whole, so they evict exactly
+        let samples = EVICTION_SAMPLES.min(len);
+        let mut victim: Option<(usize, u64)> = None;
+        for i in 0..samples {
+            let slot = if len <= EVICTION_SAMPLES {
+                i
+            } else {
+                self.random_below(len)
+            };
+            let entry = &self.entries[&self.slots[slot]];
+            if entry.expires_at <= now {
+                victim = Some((slot, 0));
+                break;
+            }
+            if victim.is_none_or(|(_, access)| entry.last_access < access) {
+                victim = Some((slot, entry.last_access));
+            }
+        }
+        let (slot, _) = victim?;
+        let key = self.slots[slot].clone();
+        self.remove(&key).map(|entry| entry.expires_at <= now)
+    }
+}
+
+/// On-disk form of a cache
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "V: CacheValue")]
+struct Snapshot<V> {
+    version: u32,
+
+    /// Unix time (ms) the snapshot was written
+    saved_at_ms: u64,
+
+    /// Live entries, least recently used first
+    entries: Vec<SnapshotEntry<V>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotEntry<V> {
+    key: String,
+    value: V,
+    /// TTL left when the snapshot was written
+    remaining_ms: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// Counters of an [`LruTtlCache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    /// Entries currently held (expired ones not yet dropped included)
+    pub entries: usize,
+
+    /// Lookups answered from the cache
+    pub hits: u64,
+
+    /// Lookups that found nothing live
+    pub misses: u64,
+
+    /// Live entries dropped to make room
+    pub evictions: u64,
+
+    /// Entries dropped because their TTL ran out
+    pub expirations: u64,
+
+    /// Approximate bytes held (tracked only with a byte limit)
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// Hits as a fraction of all lookups (0.0 before any lookup)
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Thread-safe LRU cache with a TTL per entry
+#[derive(Debug)]
+pub struct LruTtlCache<V: CacheValue = String> {
+    max_entries: usize,
+    max_bytes: Option<usize>,
+    weigher: fn(&V) -> usize,
+    ttl: Duration,
+    snapshot: Option<SnapshotConfig>,
+    state: Mutex<State<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl<V: CacheValue> LruTtlCache<V> {
+    /// Create an empty cache holding up to `max_entries` for `ttl` each
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        LruTtlCache {
+            max_entries,
+            max_bytes: None,
+            weigher: json_weight::<V>,
+            ttl,
+            snapshot: None,
+            state: Mutex::new(State::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a cache persisted to a snapshot file, restoring it if present
+    ///
+    /// An unreadable snapshot is logged and replaced rather than keeping
+    /// the caller from starting.
+    pub fn with_snapshot(max_entries: usize, ttl: Duration, snapshot: SnapshotConfig) -> Self {
+        let mut cache = LruTtlCache::new(max_entries, ttl);
+        match cache.restore(&snapshot.path) {
+            Ok(0) => {}
+            Ok(restored) => tracing::info!(
+                "Restored {} cache entries from {}",
+                restored,
+                snapshot.path.display()
+            ),
+            Err(e) => tracing::warn!("Starting with an empty cache: {:#}", e),
+        }
+        cache.snapshot = Some(snapshot);
+        cache
+    }
+
+    /// Also bound the cache to about `max_bytes`, evicting by weight
+    ///
+    /// Entries already held (e.g. restored from a snapshot) are weighed
+    /// and trimmed to fit.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self.reweigh();
+        self
+    }
+
+    /// Weigh values with `weigher` instead of [`json_weight`]
+    pub fn with_weigher(mut self, weigher: fn(&V) -> usize) -> Self {
+        self.weigher = weigher;
+        self.reweigh();
+        self
+    }
+
+    /// Maximum number of entries
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Byte limit, if any
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// TTL of entries inserted without one
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Live value of `key`, marking it recently used
+    pub fn get(&self, key: &str) -> Option<V> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let tick = state.tick();
+        match state.entries.get_mut(key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_access = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                state.remove(key);
+                state.dirty = true;
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.expirations.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Whether `key` holds a live value (doesn't count as a use)
+    pub fn contains(&self, key: &str) -> bool {
+        let now = Instant::now();
+        self.state
+            .lock()
+            .unwrap()
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.expires_at > now)
+    }
+
+    /// Store a value with the default TTL
+    pub fn insert(&self, key: impl Into<String>, value: V) {
+        self.insert_with_ttl(key, value, self.ttl);
+    }
+
+    /// Store a value with its own TTL, evicting least recently used
+    /// entries if the cache is full
+    ///
+    /// A value heavier than the whole byte limit isn't stored (and any
+    /// previous value of `key` is dropped).
+    pub fn insert_with_ttl(&self, key: impl Into<String>, value: V, ttl: Duration) {
+        self.insert_tagged(key, value, ttl, Vec::new());
+    }
+
+    /// Store a value with its own TTL and tags for
+    /// [`LruTtlCache::invalidate_tag`]
+    pub fn insert_tagged(
+        &self,
+        key: impl Into<String>,
+        value: V,
+        ttl: Duration,
+        tags: Vec<String>,
+    ) {
+        let key = key.into();
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        self.store(&mut state, key, value, now + ttl, now, tags);
+        state.dirty = true;
+        self.maybe_snapshot(&mut state);
+    }
+
+    /// Change the TTL of a live entry, counted from now
+    pub fn set_ttl(&self, key: &str, ttl: Duration) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get_mut(key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.expires_at = now + ttl;
+                state.dirty = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove an entry, returning whether a live one existed
+    pub fn remove(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match state.remove(key) {
+            Some(entry) => {
+                state.dirty = true;
+                entry.expires_at > now
+            }
+            None => false,
+        }
+    }
+
+    /// Remove every entry whose key is in `namespace` (`"policy"` or
+    /// `"policy:"` both match `policy:alice:openai`), returning how many
+    ///
+    /// Scans every key; meant for reloads, not the request path.
+    pub fn invalidate_namespace(&self, namespace: &str) -> usize {
+        let prefix = format!("{}:", namespace.trim_end_matches(':'));
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<String> = state
+            .slots
+            .iter()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect();
+        self.remove_all(&mut state, keys)
+    }
+
+    /// Remove every entry tagged `tag`, returning how many
+    pub fn invalidate_tag(&self, tag: &str) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let keys = state.tagged.remove(tag).unwrap_or_default();
+        self.remove_all(&mut state, keys)
+    }
+
+    /// Remove every entry, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let removed = state.entries.len();
+        state.clear();
+        state.dirty = true;
+        removed
+    }
+
+    /// Number of entries, including expired ones not yet dropped
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Whether the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Current entry count and counters
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            bytes: self.state.lock().unwrap().bytes,
+        }
+    }
+
+    /// Write live entries to `path`, returning how many were written
+    pub fn save(&self, path: &Path) -> Result<usize> {
+        let state = self.state.lock().unwrap();
+        write_snapshot(&state, path)
+    }
+
+    /// Write the configured snapshot if the cache changed since the last one
+    pub fn flush(&self) -> Result<()> {
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().unwrap();
+        if !state.dirty {
+            return Ok(());
+        }
+        write_snapshot(&state, &snapshot.path)?;
+        state.dirty = false;
+        state.last_snapshot = Instant::now();
+        Ok(())
+    }
+
+    /// Load entries from a snapshot written by [`LruTtlCache::save`],
+    /// returning how many were still live
+    ///
+    /// Entries are inserted least recently used first, so the LRU order
+    /// survives the restart. A missing file restores nothing.
+    pub fn restore(&self, path: &Path) -> Result<usize> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let snapshot: Snapshot<V> = serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid cache snapshot {}", path.display()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!(
+                "unsupported cache snapshot version {} in {}",
+                snapshot.version,
+                path.display()
+            );
+        }
+        // A clock behind the snapshot (no RTC, NTP not synced yet) can't
+        // tell how long the router was down; nothing can be trusted
+        let Some(downtime) = unix_millis().checked_sub(snapshot.saved_at_ms) else {
+            bail!(
+                "system clock is behind cache snapshot {}; discarding it",
+                path.display()
+            );
+        };
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let mut restored = 0;
+        for entry in snapshot.entries {
+            let Some(remaining) = entry.remaining_ms.checked_sub(downtime) else {
+                continue;
+            };
+            if remaining == 0 {
+                continue;
+            }
+            let expires_at = now + Duration::from_millis(remaining);
+            if self.store(
+                &mut state,
+                entry.key,
+                entry.value,
+                expires_at,
+                now,
+                entry.tags,
+            ) {
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Accounted size of an entry (0 without a byte limit, so unbounded
+    /// caches never serialize to weigh)
+    fn weigh(&self, key: &str, value: &V) -> usize {
+        match self.max_bytes {
+            Some(_) => 2 * key.len() + (self.weigher)(value) + ENTRY_OVERHEAD_BYTES,
+            None => 0,
+        }
+    }
+
+    /// Make room for and insert an entry, returning whether it was stored
+    fn store(
+        &self,
+        state: &mut State<V>,
+        key: String,
+        value: V,
+        expires_at: Instant,
+        now: Instant,
+        tags: Vec<String>,
+    ) -> bool {
+        let weight = self.weigh(&key, &value);
+        // Replacing: the old value's room is reused
+        state.remove(&key);
+        if self.max_entries == 0 || self.max_bytes.is_some_and(|max| weight > max) {
+            return false;
+        }
+        if state.entries.len() >= self.max_entries {
+            self.evict(state, now);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            while state.bytes + weight > max_bytes && self.evict(state, now) {}
+        }
+        state.put(key, value, expires_at, weight, tags);
+        true
+    }
+
+    fn remove_all(&self, state: &mut State<V>, keys: impl IntoIterator<Item = String>) -> usize {
+        let removed = keys
+            .into_iter()
+            .filter(|key| state.remove(key).is_some())
+            .count();
+        if removed > 0 {
+            state.dirty = true;
+        }
+        removed
+    }
+
+    /// Recompute every weight after the limit or weigher changed, then
+    /// trim to the limit
+    fn reweigh(&mut self) {
+        let now = Instant::now();
+        let mut state = std::mem::replace(self.state.get_mut().unwrap(), State::new());
+        let mut bytes = 0;
+        for (key, entry) in state.entries.iter_mut() {
+            entry.weight = self.weigh(key, &entry.value);
+            bytes += entry.weight;
+        }
+        state.bytes = bytes;
+        if let Some(max_bytes) = self.max_bytes {
+            while state.bytes > max_bytes && self.evict(&mut state, now) {}
+        }
+        *self.state.get_mut().unwrap() = state;
+    }
+
+    /// Make room for one entry, counting what was dropped; false if empty
+    fn evict(&self, state: &mut State<V>, now: Instant) -> bool {
+        let counter = match state.evict(now) {
+            Some(true) => &self.expirations,
+            Some(false) => &self.evictions,
+            None => return false,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    fn maybe_snapshot(&self, state: &mut State<V>) {
+        let Some(snapshot) = &self.snapshot else {
+            return;
+        };
+        if state.last_snapshot.elapsed() < snapshot.interval {
+            return;
+        }
+        match write_snapshot(state, &snapshot.path) {
+            Ok(_) => state.dirty = false,
+            Err(e) => tracing::warn!("Failed to snapshot cache: {:#}", e),
+        }
+        // Retried after the next interval either way, not on every insert
+        state.last_snapshot = Instant::now();
+    }
+}
+
+impl<V: CacheValue> Drop for LruTtlCache<V> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("Failed to snapshot cache: {:#}", e);
+        }
+    }
+}
+
+fn write_snapshot<V: CacheValue>(state: &State<V>, path: &Path) -> Result<usize> {
+    let now = Instant::now();
+    let mut live: Vec<_> = state
+        .entries
+        .iter()
+        .filter(|(_, entry)| entry.expires_at > now)
+        .collect();
+    live.sort_by_key(|(_, entry)| entry.last_access);
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        saved_at_ms: unix_millis(),
+        entries: live
+            .into_iter()
+            .map(|(key, entry)| SnapshotEntry {
+                key: key.clone(),
+                value: entry.value.clone(),
+                remaining_ms: entry.expires_at.duration_since(now).as_millis() as u64,
+                tags: entry.tags.clone(),
+            })
+            .collect(),
+    };
+
+    write_atomic(path, &serde_json::to_vec(&snapshot)?)?;
+    Ok(snapshot.entries.len())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
 
 /// High-performance in-memory cache
 ///
-/// This wraps SARK's lock-free cache implementation, eliminating the need
-/// for external Redis/Valkey instances on home router hardware.
+/// An [`LruTtlCache`] for Python, eliminating the need for external
+/// Redis/Valkey instances on home router hardware. Values are JSON-like
+/// (dict, list, str, int, float, bool, None) and kept as parsed JSON.
 ///
 /// # Example (Python)
 ///
 /// ```python
 /// import yori_core
 ///
-/// cache = yori_core.Cache(max_entries=10000, ttl_seconds=3600)
+/// cache = yori_core.Cache(max_entries=10000, ttl_seconds=3600,
+///                         snapshot_path="/var/db/yori/cache.json")
 ///
 /// # Cache policy evaluation results
 /// cache.set("policy:alice:openai", {"allow": true, "reason": "approved"})
 ///
+/// # Device identity outlives a policy reload
+/// cache.set("identity:192.168.1.20", {"device": "timmy-ipad"}, tags=["dhcp"])
+/// cache.invalidate_namespace("policy")
+///
 /// # Retrieve cached result
 /// result = cache.get("policy:alice:openai")
 /// if result is not None:
@@ -29,9 +780,7 @@ use std::time::Duration;
 /// ```
 #[pyclass]
 pub struct Cache {
-    // TODO: Replace with actual sark-cache instance
-    max_entries: usize,
-    ttl: Duration,
+    store: LruTtlCache<serde_json::Value>,
 }
 
 #[pymethods]
@@ -42,33 +791,69 @@ impl Cache {
     ///
     /// * `max_entries` - Maximum number of entries (default: 10000)
     /// * `ttl_seconds` - Time-to-live for entries in seconds (default: 3600)
+    /// * `snapshot_path` - File the cache is restored from and periodically
+    ///   saved to (default: none, in memory only)
+    /// * `snapshot_interval` - Seconds between snapshots (default: 300)
+    /// * `max_bytes` - Approximate memory limit in bytes, evicting by entry
+    ///   size (default: none, entry count only)
     ///
     /// # Returns
     ///
     /// A new Cache instance
     #[new]
-    #[pyo3(signature = (max_entries=10000, ttl_seconds=3600))]
-    fn new(max_entries: usize, ttl_seconds: u64) -> PyResult<Self> {
-        Ok(Cache {
-            max_entries,
-            ttl: Duration::from_secs(ttl_seconds),
-        })
+    #[pyo3(signature = (max_entries=10000, ttl_seconds=3600, snapshot_path=None, snapshot_interval=DEFAULT_SNAPSHOT_INTERVAL_SECS, max_bytes=None))]
+    fn new(
+        max_entries: usize,
+        ttl_seconds: u64,
+        snapshot_path: Option<PathBuf>,
+        snapshot_interval: u64,
+        max_bytes: Option<usize>,
+    ) -> PyResult<Self> {
+        let ttl = Duration::from_secs(ttl_seconds);
+        let store = match snapshot_path {
+            Some(path) => LruTtlCache::with_snapshot(
+                max_entries,
+                ttl,
+                SnapshotConfig {
+                    path,
+                    interval: Duration::from_secs(snapshot_interval),
+                },
+            ),
+            None => LruTtlCache::new(max_entries, ttl),
+        };
+        let store = match max_bytes {
+            Some(max_bytes) => store.with_max_bytes(max_bytes),
+            None => store,
+        };
+        Ok(Cache { store })
     }
 
     /// Store a value in the cache
     ///
     /// # Arguments
     ///
-    /// * `key` - Cache key (string)
-    /// * `value` - Value to store (any Python object that can be pickled)
+    /// * `key` - Cache key (string); the part before the first ":" is its
+    ///   namespace
+    /// * `value` - Value to store (JSON-like: dict, list, str, number, bool, None)
+    /// * `tags` - Tags for `invalidate_tag` (default: none)
     ///
     /// # Returns
     ///
     /// True if stored successfully
-    fn set(&self, _key: String, _value: PyObject) -> PyResult<bool> {
-        // TODO: Implement actual cache storage with sark-cache
-        // For now, this is a stub that does nothing
-        Ok(true)
+    #[pyo3(signature = (key, value, tags=None))]
+    fn set(
+        &self,
+        key: String,
+        value: &Bound<'_, PyAny>,
+        tags: Option<Vec<String>>,
+    ) -> PyResult<bool> {
+        self.store.insert_tagged(
+            key,
+            py_to_json(value)?,
+            self.store.ttl(),
+            tags.unwrap_or_default(),
+        );
+        Ok(self.store.max_entries() > 0)
     }
 
     /// Retrieve a value from the cache
@@ -80,9 +865,11 @@ impl Cache {
     /// # Returns
     ///
     /// Cached value if found and not expired, None otherwise
-    fn get(&self, _py: Python, _key: String) -> PyResult<Option<PyObject>> {
-        // TODO: Implement actual cache retrieval
-        Ok(None)
+    fn get(&self, py: Python, key: String) -> PyResult<Option<PyObject>> {
+        self.store
+            .get(&key)
+            .map(|value| json_to_py(py, &value))
+            .transpose()
     }
 
     /// Delete a value from the cache
@@ -94,9 +881,34 @@ impl Cache {
     /// # Returns
     ///
     /// True if entry existed and was deleted
-    fn delete(&self, _key: String) -> PyResult<bool> {
-        // TODO: Implement cache deletion
-        Ok(false)
+    fn delete(&self, key: String) -> PyResult<bool> {
+        Ok(self.store.remove(&key))
+    }
+
+    /// Delete every entry in a namespace
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - Key prefix before the first ":" (e.g. "policy")
+    ///
+    /// # Returns
+    ///
+    /// Number of entries removed
+    fn invalidate_namespace(&self, namespace: &str) -> PyResult<usize> {
+        Ok(self.store.invalidate_namespace(namespace))
+    }
+
+    /// Delete every entry carrying a tag
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - Tag given to `set`
+    ///
+    /// # Returns
+    ///
+    /// Number of entries removed
+    fn invalidate_tag(&self, tag: &str) -> PyResult<usize> {
+        Ok(self.store.invalidate_tag(tag))
     }
 
     /// Clear all entries from the cache
@@ -105,8 +917,7 @@ impl Cache {
     ///
     /// Number of entries removed
     fn clear(&self) -> PyResult<usize> {
-        // TODO: Implement cache clearing
-        Ok(0)
+        Ok(self.store.clear())
     }
 
     /// Get cache statistics
@@ -118,14 +929,21 @@ impl Cache {
     /// - `hits` (int): Number of cache hits
     /// - `misses` (int): Number of cache misses
     /// - `hit_rate` (float): Hit rate percentage
+    /// - `evictions` (int): Entries dropped to make room
+    /// - `expirations` (int): Entries dropped when their TTL ran out
+    /// - `bytes` (int): Approximate memory held (0 without `max_bytes`)
     fn stats(&self, py: Python) -> PyResult<PyObject> {
         use pyo3::types::PyDict;
 
+        let counters = self.store.stats();
         let stats = PyDict::new_bound(py);
-        stats.set_item("entries", 0)?;
-        stats.set_item("hits", 0)?;
-        stats.set_item("misses", 0)?;
-        stats.set_item("hit_rate", 0.0)?;
+        stats.set_item("entries", counters.entries)?;
+        stats.set_item("hits", counters.hits)?;
+        stats.set_item("misses", counters.misses)?;
+        stats.set_item("hit_rate", counters.hit_rate() * 100.0)?;
+        stats.set_item("evictions", counters.evictions)?;
+        stats.set_item("expirations", counters.expirations)?;
+        stats.set_item("bytes", counters.bytes)?;
 
         Ok(stats.into())
     }
@@ -139,9 +957,8 @@ impl Cache {
     /// # Returns
     ///
     /// True if key exists and is not expired
-    fn contains(&self, _key: String) -> PyResult<bool> {
-        // TODO: Implement existence check
-        Ok(false)
+    fn contains(&self, key: String) -> PyResult<bool> {
+        Ok(self.store.contains(&key))
     }
 
     /// Set TTL for a specific key
@@ -154,22 +971,189 @@ impl Cache {
     /// # Returns
     ///
     /// True if TTL was updated
-    fn set_ttl(&self, _key: String, _ttl_seconds: u64) -> PyResult<bool> {
-        // TODO: Implement per-key TTL
-        Ok(false)
+    fn set_ttl(&self, key: String, ttl_seconds: u64) -> PyResult<bool> {
+        Ok(self.store.set_ttl(&key, Duration::from_secs(ttl_seconds)))
+    }
+
+    /// Write the cache to a snapshot file now
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Snapshot file (default: the constructor's `snapshot_path`)
+    ///
+    /// # Returns
+    ///
+    /// Number of entries written
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<PathBuf>) -> PyResult<usize> {
+        let path = path
+            .or_else(|| self.store.snapshot.as_ref().map(|s| s.path.clone()))
+            .ok_or_else(|| PyRuntimeError::new_err("no snapshot path configured"))?;
+        self.store
+            .save(&path)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::{json, Value};
 
     #[test]
     fn test_cache_creation() {
-        let cache = Cache::new(1000, 300);
+        let cache = Cache::new(1000, 300, None, DEFAULT_SNAPSHOT_INTERVAL_SECS, None);
         assert!(cache.is_ok());
         let c = cache.unwrap();
-        assert_eq!(c.max_entries, 1000);
-        assert_eq!(c.ttl, Duration::from_secs(300));
+        assert_eq!(c.store.max_entries(), 1000);
+        assert_eq!(c.store.ttl(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_lru_eviction_and_expiry() {
+        let cache = LruTtlCache::new(2, Duration::from_secs(60));
+        cache.insert("a", 1u64);
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c", 3);
+        // "b" was least recently used
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 2);
+
+        cache.insert_with_ttl("d", 4, Duration::ZERO);
+        assert!(!cache.contains("d"));
+        assert_eq!(cache.get("d"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert_eq!((stats.evictions, stats.expirations), (2, 1));
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sampled_eviction_keeps_hot_entries() {
+        let cache = LruTtlCache::new(100, Duration::from_secs(60));
+        cache.insert("hot", 0u64);
+        for i in 0..1000u64 {
+            cache.insert(format!("cold-{}", i), i);
+            assert!(cache.get("hot").is_some(), "hot entry evicted at {}", i);
+        }
+        assert_eq!(cache.len(), 100);
+        assert_eq!(cache.stats().evictions, 901);
+
+        // Slots stay consistent through removals
+        assert!(cache.remove("hot"));
+        assert!(cache.remove("cold-999"));
+        assert_eq!(cache.get("cold-999"), None);
+        cache.insert("new", 1);
+        assert_eq!(cache.get("new"), Some(1));
+        assert_eq!(cache.clear(), 99);
+    }
+
+    #[test]
+    fn test_byte_limit_evicts_by_weight() {
+        let entry = |len: usize| "x".repeat(len);
+        // Each value weighs its length + 2 quotes, plus key and overhead
+        let weight = |len: usize| 2 * 2 + len + 2 + ENTRY_OVERHEAD_BYTES;
+        let cache = LruTtlCache::new(1000, Duration::from_secs(60)).with_max_bytes(3 * weight(100));
+        cache.insert("k1", entry(100));
+        cache.insert("k2", entry(100));
+        cache.insert("k3", entry(100));
+        assert_eq!(cache.stats().bytes, 3 * weight(100));
+
+        // One large value pushes out the two oldest small ones
+        cache.insert("k4", entry(200));
+        assert!(!cache.contains("k1") && !cache.contains("k2"));
+        assert!(cache.contains("k3") && cache.contains("k4"));
+        assert_eq!(cache.stats().bytes, weight(100) + weight(200));
+
+        // Too big to ever fit: not stored, and the old value is gone
+        cache.insert("k3", entry(1000));
+        assert!(!cache.contains("k3"));
+        assert_eq!(cache.stats().bytes, weight(200));
+        assert_eq!(json_weight(&"abc"), 5);
+    }
+
+    #[test]
+    fn test_invalidate_namespace_and_tag() {
+        let cache = LruTtlCache::new(100, Duration::from_secs(60));
+        cache.insert("policy:alice:openai", 1u64);
+        let ttl = cache.ttl();
+        cache.insert("policy:bob:openai", 2);
+        cache.insert("policyx:other", 3);
+        cache.insert_tagged("identity:192.168.1.20", 4, ttl, vec!["dhcp".to_string()]);
+        cache.insert_tagged("identity:192.168.1.21", 5, ttl, vec!["dhcp".to_string()]);
+        cache.insert_tagged("tokens:alice", 6, ttl, vec!["alice".to_string()]);
+
+        assert_eq!(cache.invalidate_namespace("policy:"), 2);
+        assert!(cache.contains("policyx:other"));
+        assert!(cache.contains("identity:192.168.1.20"));
+
+        // Removing one tagged entry keeps the tag index consistent
+        assert!(cache.remove("identity:192.168.1.21"));
+        assert_eq!(cache.invalidate_tag("dhcp"), 1);
+        assert_eq!(cache.invalidate_tag("dhcp"), 0);
+        assert_eq!(cache.len(), 2);
+
+        // Re-inserting without tags drops the old ones
+        cache.insert("tokens:alice", 7);
+        assert_eq!(cache.invalidate_tag("alice"), 0);
+        assert_eq!(cache.get("tokens:alice"), Some(7));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+        {
+            let cache =
+                LruTtlCache::with_snapshot(10, Duration::from_secs(60), SnapshotConfig::new(&path));
+            cache.insert("policy:alice", json!({"allow": true}));
+            cache.insert("policy:bob", json!({"allow": false}));
+            cache.insert_with_ttl("short", json!("x"), Duration::from_millis(1));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let restored: LruTtlCache<Value> = LruTtlCache::new(10, Duration::from_secs(60));
+        assert_eq!(restored.restore(&path).unwrap(), 2);
+        assert_eq!(restored.get("policy:alice"), Some(json!({"allow": true})));
+        assert_eq!(restored.get("short"), None);
+    }
+
+    #[test]
+    fn test_restore_subtracts_downtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            saved_at_ms: unix_millis() - 30_000,
+            entries: vec![
+                SnapshotEntry {
+                    key: "expired".to_string(),
+                    value: "1".to_string(),
+                    remaining_ms: 20_000,
+                    tags: Vec::new(),
+                },
+                SnapshotEntry {
+                    key: "live".to_string(),
+                    value: "2".to_string(),
+                    remaining_ms: 60_000,
+                    tags: Vec::new(),
+                },
+            ],
+        };
+        fs::write(&path, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+
+        let cache: LruTtlCache = LruTtlCache::new(10, Duration::from_secs(60));
+        assert_eq!(cache.restore(&path).unwrap(), 1);
+        assert!(cache.contains("live"));
+        assert!(!cache.contains("expired"));
+
+        let future = Snapshot {
+            saved_at_ms: unix_millis() + 3_600_000,
+            ..snapshot
+        };
+        fs::write(&path, serde_json::to_vec(&future).unwrap()).unwrap();
+        assert!(cache.restore(&path).is_err());
     }
 }
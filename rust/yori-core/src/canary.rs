@@ -0,0 +1,406 @@
+//! Bypass detection with canary requests
+//!
+//! Enforcement only works while LLM traffic actually flows through the
+//! proxy. A changed DNS server, a VPN app on a laptop or drifting firewall
+//! rules can quietly route around it. The canary probe periodically sends
+//! a request for each intercepted endpoint from the LAN side:
+//!
+//! ```text
+//! GET /.yori/canary/<nonce> HTTP/1.1
+//! Host: api.openai.com
+//! ```
+//!
+//! When the request is intercepted, the proxy answers it itself with an
+//! `X-Yori-Canary` header carrying an HMAC of the nonce. The probe trusts
+//! only the YORI CA, so a request that reaches the real provider fails the
+//! TLS handshake, and one that reaches anything else lacks a valid marker.
+//! Either way the endpoint is reported as bypassed and an alert is raised.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpSocket;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::livetail::LiveTail;
+
+/// Path prefix the proxy answers itself
+pub const CANARY_PATH_PREFIX: &str = "/.yori/canary/";
+
+/// Response header carrying the canary marker
+pub const CANARY_HEADER: &str = "x-yori-canary";
+
+/// Largest canary response read by the probe
+const MAX_RESPONSE_BYTES: usize = 16 * 1024;
+
+/// Proxy side: recognizes canary requests and produces their marker
+pub struct CanaryResponder {
+    secret: [u8; 32],
+}
+
+impl CanaryResponder {
+    /// Create a responder with an explicit secret
+    pub fn new(secret: [u8; 32]) -> Self {
+        CanaryResponder { secret }
+    }
+
+    /// Create a responder with a random per-process secret
+    pub fn random() -> Result<Self> {
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).map_err(|e| anyhow!("failed to seed canary: {}", e))?;
+        Ok(CanaryResponder { secret })
+    }
+
+    /// Nonce of a canary request path, if it is one
+    pub fn canary_nonce<'a>(&self, path: &'a str) -> Option<&'a str> {
+        path.strip_prefix(CANARY_PATH_PREFIX)
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_alphanumeric()))
+    }
+
+    /// Marker value for a nonce (hex HMAC-SHA256)
+    pub fn marker(&self, nonce: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(nonce.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Check a marker returned for `nonce`
+    pub fn verify(&self, nonce: &str, marker: &str) -> bool {
+        let Ok(bytes) = hex::decode(marker.trim()) else {
+            return false;
+        };
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(nonce.as_bytes());
+        mac.verify_slice(&bytes).is_ok()
+    }
+}
+
+/// Result of probing one endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanaryOutcome {
+    /// The request went through the proxy
+    Intercepted,
+
+    /// The request did not go through the proxy
+    Bypassed {
+        /// How the bypass showed up
+        reason: String,
+    },
+
+    /// The probe itself failed (DNS, connect, timeout)
+    Error {
+        /// What went wrong
+        reason: String,
+    },
+}
+
+/// Canary probe settings
+#[derive(Debug, Clone)]
+pub struct CanaryConfig {
+    /// Endpoints to probe (normally the proxy's intercept list)
+    pub endpoints: Vec<String>,
+
+    /// YORI CA certificate (PEM) the intercepting proxy's certs chain to
+    pub ca_cert_path: String,
+
+    /// LAN address to send probes from, so they take the same firewall
+    /// path as client traffic
+    pub source_addr: Option<IpAddr>,
+
+    /// Time between probe rounds
+    pub interval: Duration,
+
+    /// Per-probe timeout
+    pub timeout: Duration,
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        CanaryConfig {
+            endpoints: Vec::new(),
+            ca_cert_path: "/usr/local/etc/yori/certs/ca.crt".to_string(),
+            source_addr: None,
+            interval: Duration::from_secs(300),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Periodically verifies that endpoints are intercepted
+pub struct CanaryProbe {
+    config: CanaryConfig,
+    responder: Arc<CanaryResponder>,
+    tls: TlsConnector,
+    last: Mutex<HashMap<String, (DateTime<Utc>, CanaryOutcome)>>,
+    live: Option<LiveTail>,
+}
+
+impl CanaryProbe {
+    /// Create a probe that trusts only the YORI CA
+    pub fn new(config: CanaryConfig, responder: Arc<CanaryResponder>) -> Result<Self> {
+        let pem = std::fs::read(&config.ca_cert_path)
+            .with_context(|| format!("failed to read CA certificate {}", config.ca_cert_path))?;
+        let mut roots = RootCertStore::empty();
+        for der in rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))? {
+            roots.add(&rustls::Certificate(der))?;
+        }
+        if roots.is_empty() {
+            bail!("no certificates in {}", config.ca_cert_path);
+        }
+
+        let tls = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(CanaryProbe {
+            config,
+            responder,
+            tls: TlsConnector::from(Arc::new(tls)),
+            last: Mutex::new(HashMap::new()),
+            live: None,
+        })
+    }
+
+    /// Publish bypass alerts and recoveries to a live-tail channel
+    pub fn with_live_tail(mut self, live: LiveTail) -> Self {
+        self.live = Some(live);
+        self
+    }
+
+    /// Last outcome per endpoint
+    pub fn status(&self) -> HashMap<String, (DateTime<Utc>, CanaryOutcome)> {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// Probe every endpoint once
+    pub async fn run_once(&self) -> Vec<(String, CanaryOutcome)> {
+        let mut results = Vec::new();
+        for endpoint in &self.config.endpoints {
+            let outcome =
+                match tokio::time::timeout(self.config.timeout, self.probe(endpoint)).await {
+                    Ok(outcome) => outcome,
+                    Err(_) => CanaryOutcome::Error {
+                        reason: "timed out".to_string(),
+                    },
+                };
+            self.record(endpoint, &outcome);
+            results.push((endpoint.clone(), outcome));
+        }
+        results
+    }
+
+    /// Probe forever at the configured interval
+    pub fn spawn(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            loop {
+                interval.tick().await;
+                self.run_once().await;
+            }
+        })
+    }
+
+    fn record(&self, endpoint: &str, outcome: &CanaryOutcome) {
+        let previous = self
+            .last
+            .lock()
+            .unwrap()
+            .insert(endpoint.to_string(), (Utc::now(), outcome.clone()))
+            .map(|(_, o)| o);
+
+        let changed = previous.as_ref() != Some(outcome);
+        match outcome {
+            CanaryOutcome::Bypassed { reason } => {
+                tracing::error!("Enforcement bypass detected for {}: {}", endpoint, reason)
+            }
+            CanaryOutcome::Error { reason } if changed => {
+                tracing::warn!("Canary probe for {} failed: {}", endpoint, reason)
+            }
+            CanaryOutcome::Intercepted if changed && previous.is_some() => {
+                tracing::info!("Canary for {} is intercepted again", endpoint)
+            }
+            _ => {}
+        }
+
+        if changed {
+            if let Some(live) = &self.live {
+                let (state, reason) = match outcome {
+                    CanaryOutcome::Intercepted => ("intercepted", None),
+                    CanaryOutcome::Bypassed { reason } => ("bypassed", Some(reason)),
+                    CanaryOutcome::Error { reason } => ("error", Some(reason)),
+                };
+                live.publish_status(
+                    "canary",
+                    serde_json::json!({ "endpoint": endpoint, "state": state, "reason": reason }),
+                );
+            }
+        }
+    }
+
+    async fn probe(&self, endpoint: &str) -> CanaryOutcome {
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let response = match self.fetch(endpoint, &nonce).await {
+            Ok(response) => response,
+            Err(ProbeError::Untrusted(reason)) => return CanaryOutcome::Bypassed { reason },
+            Err(ProbeError::Failed(e)) => {
+                return CanaryOutcome::Error {
+                    reason: format!("{:#}", e),
+                }
+            }
+        };
+        classify_response(&self.responder, &nonce, &response)
+    }
+
+    async fn fetch(&self, endpoint: &str, nonce: &str) -> Result<String, ProbeError> {
+        let (host, port) = match endpoint.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => {
+                (host, port.parse::<u16>().unwrap())
+            }
+            _ => (endpoint, 443),
+        };
+
+        let addr = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| ProbeError::Failed(e.into()))?
+            .find(|a| {
+                self.config
+                    .source_addr
+                    .is_none_or(|src| src.is_ipv4() == a.is_ipv4())
+            })
+            .ok_or_else(|| ProbeError::Failed(anyhow!("{} did not resolve", host)))?;
+
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()
+        } else {
+            TcpSocket::new_v6()
+        }
+        .map_err(|e| ProbeError::Failed(e.into()))?;
+        if let Some(src) = self.config.source_addr {
+            socket
+                .bind(SocketAddr::new(src, 0))
+                .map_err(|e| ProbeError::Failed(e.into()))?;
+        }
+        let tcp = socket
+            .connect(addr)
+            .await
+            .map_err(|e| ProbeError::Failed(e.into()))?;
+
+        let server_name = ServerName::try_from(host)
+            .map_err(|_| ProbeError::Failed(anyhow!("invalid host name {}", host)))?;
+        // A certificate that doesn't chain to the YORI CA means we reached
+        // someone other than the proxy
+        let mut tls = self.tls.connect(server_name, tcp).await.map_err(|e| {
+            match e.get_ref().and_then(|i| i.downcast_ref::<rustls::Error>()) {
+                Some(rustls::Error::InvalidCertificate(_)) => ProbeError::Untrusted(format!(
+                    "TLS certificate for {} is not issued by the YORI CA",
+                    host
+                )),
+                _ => ProbeError::Failed(e.into()),
+            }
+        })?;
+
+        let request = format!(
+            "GET {}{} HTTP/1.1\r\nHost: {}\r\nUser-Agent: yori-canary\r\nConnection: close\r\n\r\n",
+            CANARY_PATH_PREFIX, nonce, host
+        );
+        tls.write_all(request.as_bytes())
+            .await
+            .map_err(|e| ProbeError::Failed(e.into()))?;
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 4096];
+        while response.len() < MAX_RESPONSE_BYTES {
+            match tls.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                // Servers often skip close_notify; what we have is enough
+                Err(_) if !response.is_empty() => break,
+                Err(e) => return Err(ProbeError::Failed(e.into())),
+            }
+            if response.windows(4).any(|w| w == b"\r\n\r\n") {
+                break;
+            }
+        }
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+}
+
+enum ProbeError {
+    Untrusted(String),
+    Failed(anyhow::Error),
+}
+
+/// Decide from a raw HTTP response whether the canary was intercepted
+fn classify_response(responder: &CanaryResponder, nonce: &str, response: &str) -> CanaryOutcome {
+    let head = response.split("\r\n\r\n").next().unwrap_or("");
+    let marker = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case(CANARY_HEADER)
+            .then(|| value.trim())
+    });
+
+    match marker {
+        Some(marker) if responder.verify(nonce, marker) => CanaryOutcome::Intercepted,
+        Some(_) => CanaryOutcome::Bypassed {
+            reason: "canary marker did not verify (another YORI instance or a stale proxy?)"
+                .to_string(),
+        },
+        None => CanaryOutcome::Bypassed {
+            reason: format!(
+                "response has no canary marker ({})",
+                head.lines().next().unwrap_or("empty response")
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_responder_marker_roundtrip() {
+        let responder = CanaryResponder::new([7; 32]);
+        let nonce = responder.canary_nonce("/.yori/canary/3f2a9c").unwrap();
+        assert_eq!(nonce, "3f2a9c");
+        assert!(responder.canary_nonce("/v1/chat/completions").is_none());
+        assert!(responder.canary_nonce("/.yori/canary/../admin").is_none());
+
+        let marker = responder.marker(nonce);
+        assert!(responder.verify(nonce, &marker));
+        assert!(!responder.verify("other", &marker));
+        assert!(!CanaryResponder::new([8; 32]).verify(nonce, &marker));
+    }
+
+    #[test]
+    fn test_classify_response() {
+        let responder = CanaryResponder::new([7; 32]);
+        let ok = format!(
+            "HTTP/1.1 204 No Content\r\nX-Yori-Canary: {}\r\n\r\n",
+            responder.marker("abc")
+        );
+        assert_eq!(
+            classify_response(&responder, "abc", &ok),
+            CanaryOutcome::Intercepted
+        );
+        assert!(matches!(
+            classify_response(&responder, "abc", "HTTP/1.1 404 Not Found\r\nServer: cloudflare\r\n\r\n"),
+            CanaryOutcome::Bypassed { reason } if reason.contains("404")
+        ));
+        assert!(matches!(
+            classify_response(&responder, "abd", &ok),
+            CanaryOutcome::Bypassed { .. }
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+//! TLS certificates for intercepted endpoints
+//!
+//! One listener serves every intercepted provider, so a single static
+//! certificate would need every LLM host name in it and be reissued each
+//! time the endpoint list changes. Instead the [`CertAuthority`] mints a
+//! leaf certificate for the ClientHello's server name on demand, signed by
+//! the local CA from `yori ca generate` that devices already trust. Leaves
+//! are cached per host and reissued when half their lifetime has passed.
+//!
+//! Without a CA, [`static_server_config`] presents the one configured
+//! certificate for every host (the pre-CA setup).
+//!
+//! Either configuration can also ask clients for a certificate, for proxy
+//! authentication (see [`crate::proxyauth`]); clients without one are
+//! still accepted.
+
+use crate::alpn::alpn_protocols;
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, Utc};
+use rcgen::{
+    date_time_ymd, Certificate, CertificateParams, DistinguishedName, DnType, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert, WantsServerCert,
+};
+use rustls::sign::CertifiedKey;
+use rustls::ConfigBuilder;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Lifetime of generated leaf certificates
+const LEAF_VALIDITY_DAYS: i64 = 30;
+
+/// The interception CA, issuing (and caching) a leaf certificate per host
+pub struct CertAuthority {
+    cert: Certificate,
+    key: KeyPair,
+    leaves: Mutex<HashMap<String, (Instant, Arc<CertifiedKey>)>>,
+}
+
+impl CertAuthority {
+    /// Load the CA created by `yori ca generate`
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let cert_pem = std::fs::read_to_string(cert_path)
+            .with_context(|| format!("failed to read CA certificate {}", cert_path.display()))?;
+        let key_pem = std::fs::read_to_string(key_path)
+            .with_context(|| format!("failed to read CA key {}", key_path.display()))?;
+        CertAuthority::from_pem(&cert_pem, &key_pem)
+    }
+
+    /// Build from PEM-encoded CA certificate and key
+    pub fn from_pem(cert_pem: &str, key_pem: &str) -> Result<Self> {
+        let key = KeyPair::from_pem(key_pem).context("invalid CA key")?;
+        let params =
+            CertificateParams::from_ca_cert_pem(cert_pem).context("invalid CA certificate")?;
+        // Re-signing yields an issuer with the same name and key identifier
+        let cert = params.self_signed(&key).context("CA key does not match")?;
+        Ok(CertAuthority {
+            cert,
+            key,
+            leaves: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// DER encoding of the CA certificate
+    pub fn ca_der(&self) -> &[u8] {
+        self.cert.der()
+    }
+
+    /// Number of hosts with a cached leaf certificate
+    pub fn cached_hosts(&self) -> usize {
+        self.leaves.lock().unwrap().len()
+    }
+
+    /// Certificate chain and key presented for `host`
+    pub fn certified_key(&self, host: &str) -> Result<Arc<CertifiedKey>> {
+        let host = host.to_ascii_lowercase();
+        let renew_after = std::time::Duration::from_secs(LEAF_VALIDITY_DAYS as u64 * 86_400 / 2);
+        if let Some((issued, leaf)) = self.leaves.lock().unwrap().get(&host) {
+            if issued.elapsed() < renew_after {
+                return Ok(Arc::clone(leaf));
+            }
+        }
+
+        let leaf = Arc::new(self.issue(&host)?);
+        self.leaves
+            .lock()
+            .unwrap()
+            .insert(host, (Instant::now(), Arc::clone(&leaf)));
+        Ok(leaf)
+    }
+
+    fn issue(&self, host: &str) -> Result<CertifiedKey> {
+        let mut params = CertificateParams::new(vec![host.to_string()])
+            .with_context(|| format!("invalid host name {:?}", host))?;
+        let mut name = DistinguishedName::new();
+        name.push(DnType::CommonName, host);
+        params.distinguished_name = name;
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        // Backdated a day so clients with a slightly slow clock accept it
+        let start = Utc::now() - Duration::days(1);
+        let end = start + Duration::days(LEAF_VALIDITY_DAYS);
+        params.not_before = date_time_ymd(start.year(), start.month() as u8, start.day() as u8);
+        params.not_after = date_time_ymd(end.year(), end.month() as u8, end.day() as u8);
+
+        let key = KeyPair::generate().context("failed to generate leaf key")?;
+        let leaf = params
+            .signed_by(&key, &self.cert, &self.key)
+            .with_context(|| format!("failed to sign certificate for {}", host))?;
+        let signing_key =
+            rustls::sign::any_supported_type(&rustls::PrivateKey(key.serialize_der()))
+                .context("unsupported leaf key type")?;
+        Ok(CertifiedKey::new(
+            vec![
+                rustls::Certificate(leaf.der().to_vec()),
+                rustls::Certificate(self.cert.der().to_vec()),
+            ],
+            signing_key,
+        ))
+    }
+}
+
+impl ResolvesServerCert for CertAuthority {
+    fn resolve(&self, hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let Some(host) = hello.server_name() else {
+            tracing::debug!("ClientHello without SNI; no certificate to present");
+            return None;
+        };
+        self.certified_key(host)
+            .map_err(|e| tracing::warn!("Failed to issue certificate for {}: {:#}", host, e))
+            .ok()
+    }
+}
+
+/// TLS configuration minting certificates from `ca` by server name
+///
+/// `http2` offers `h2` ahead of `http/1.1` over ALPN; `client_roots`
+/// verifies client certificates chaining to them.
+pub fn ca_server_config(
+    ca: Arc<CertAuthority>,
+    http2: bool,
+    client_roots: Option<rustls::RootCertStore>,
+) -> Arc<rustls::ServerConfig> {
+    let mut config = server_builder(client_roots).with_cert_resolver(ca);
+    config.alpn_protocols = alpn_protocols(http2);
+    Arc::new(config)
+}
+
+/// TLS configuration presenting one PEM certificate chain for every host
+pub fn static_server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    http2: bool,
+    client_roots: Option<rustls::RootCertStore>,
+) -> Result<Arc<rustls::ServerConfig>> {
+    let cert_pem = std::fs::read(cert_path)
+        .with_context(|| format!("failed to read certificate {}", cert_path.display()))?;
+    let chain: Vec<_> = rustls_pemfile::certs(&mut BufReader::new(cert_pem.as_slice()))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if chain.is_empty() {
+        bail!("no certificates in {}", cert_path.display());
+    }
+
+    let key_pem = std::fs::read(key_path)
+        .with_context(|| format!("failed to read key {}", key_path.display()))?;
+    let key = rustls_pemfile::read_all(&mut BufReader::new(key_pem.as_slice()))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(rustls::PrivateKey(der)),
+            _ => None,
+        })
+        .with_context(|| format!("no private key in {}", key_path.display()))?;
+
+    let mut config = server_builder(client_roots)
+        .with_single_cert(chain, key)
+        .context("failed to build TLS configuration")?;
+    config.alpn_protocols = alpn_protocols(http2);
+    Ok(Arc::new(config))
+}
+
+fn server_builder(
+    client_roots: Option<rustls::RootCertStore>,
+) -> ConfigBuilder<rustls::ServerConfig, WantsServerCert> {
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    match client_roots {
+        Some(roots) => builder
+            .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()),
+        None => builder.with_no_client_auth(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_authority() -> CertAuthority {
+    use rcgen::{BasicConstraints, IsCa};
+
+    let mut params = CertificateParams::default();
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "YORI Test CA");
+    params.distinguished_name = name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let key = KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+    CertAuthority::from_pem(&cert.pem(), &key.serialize_pem()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaves_are_cached_per_host() {
+        let ca = test_authority();
+        let openai = ca.certified_key("api.openai.com").unwrap();
+        let again = ca.certified_key("API.OpenAI.com").unwrap();
+        let anthropic = ca.certified_key("api.anthropic.com").unwrap();
+
+        assert!(Arc::ptr_eq(&openai, &again));
+        assert!(!Arc::ptr_eq(&openai, &anthropic));
+        assert_eq!(ca.cached_hosts(), 2);
+        // Leaf first, then the CA so clients can build the chain
+        assert_eq!(openai.cert.len(), 2);
+        assert_eq!(openai.cert[1].0, ca.ca_der());
+    }
+
+    #[tokio::test]
+    async fn test_handshakes_for_several_providers_on_one_config() {
+        let ca = Arc::new(test_authority());
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.ca_der().to_vec()))
+            .unwrap();
+        let client = tokio_rustls::TlsConnector::from(Arc::new(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+        let acceptor =
+            tokio_rustls::TlsAcceptor::from(ca_server_config(Arc::clone(&ca), true, None));
+
+        for host in ["api.openai.com", "api.mistral.ai"] {
+            let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+            let name = rustls::ServerName::try_from(host).unwrap();
+            let (server, client) =
+                tokio::join!(acceptor.accept(server_io), client.connect(name, client_io));
+            server.unwrap();
+            client.unwrap();
+        }
+        assert_eq!(ca.cached_hosts(), 2);
+    }
+}
@@ -0,0 +1,398 @@
+//! Prompt content categories
+//!
+//! Parents think about what a prompt is about, not which words it uses:
+//! "nothing adult on the kids' tablets", "homework help only after
+//! school". The classifier tags each request with categories from a small
+//! vocabulary so policies can say exactly that:
+//!
+//! ```rego
+//! deny contains "adult content" if {
+//!     input.device.group == "kids"
+//!     "adult" in input.categories
+//! }
+//! ```
+//!
+//! Built-in rules cover `homework`, `coding`, `adult`, `self_harm` and
+//! `violence`. A rule matches on keywords and phrases (compared on
+//! normalized words, like [`crate::enrich::TagRule`]) or on regular
+//! expressions; configuration can extend a built-in category or add its
+//! own. An optional model ([`CategoryModel`], e.g. a small ONNX text
+//! classifier) adds every category it scores at or above the threshold.
+//!
+//! Categories are part of the policy input (`input.categories`) and are
+//! stored on audit events (`categories`).
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use crate::dedup;
+
+/// Default minimum model score for a category to be tagged
+pub const DEFAULT_MODEL_THRESHOLD: f32 = 0.8;
+
+/// Built-in content categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Homework,
+    Coding,
+    Adult,
+    SelfHarm,
+    Violence,
+}
+
+impl Category {
+    /// Every built-in category
+    pub const ALL: [Category; 5] = [
+        Category::Homework,
+        Category::Coding,
+        Category::Adult,
+        Category::SelfHarm,
+        Category::Violence,
+    ];
+
+    /// Name used in policy input and audit rows
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Homework => "homework",
+            Category::Coding => "coding",
+            Category::Adult => "adult",
+            Category::SelfHarm => "self_harm",
+            Category::Violence => "violence",
+        }
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            Category::Homework => &[
+                "homework",
+                "essay",
+                "assignment",
+                "worksheet",
+                "book report",
+                "thesis statement",
+                "show your work",
+                "solve for x",
+                "my teacher",
+                "for school",
+                "study guide",
+            ],
+            Category::Coding => &[
+                "python",
+                "javascript",
+                "typescript",
+                "rust",
+                "java",
+                "html",
+                "css",
+                "sql",
+                "compiler",
+                "stack trace",
+                "regex",
+                "my code",
+                "this code",
+                "syntax error",
+            ],
+            Category::Adult => &[
+                "porn",
+                "pornography",
+                "nsfw",
+                "nude",
+                "nudes",
+                "naked",
+                "sex",
+                "sexual",
+                "sexy",
+                "erotic",
+                "fetish",
+                "onlyfans",
+                "hookup",
+            ],
+            Category::SelfHarm => &[
+                "suicide",
+                "suicidal",
+                "self harm",
+                "kill myself",
+                "end my life",
+                "want to die",
+                "cut myself",
+                "hurt myself",
+                "overdose",
+            ],
+            Category::Violence => &[
+                "murder",
+                "stab",
+                "stabbing",
+                "shooting",
+                "massacre",
+                "bomb",
+                "explosive",
+                "torture",
+                "assault",
+                "how to kill",
+                "make a weapon",
+            ],
+        }
+    }
+
+    fn patterns(&self) -> &'static [&'static str] {
+        match self {
+            Category::Homework => &[
+                r"\bwrite (a|an|my) (\d+[- ](word|page|paragraph) )?(essay|report|poem|summary)\b",
+                r"\b(solve|simplify|factor)\b.*=",
+            ],
+            Category::Coding => &[
+                "```",
+                r"\b(def|fn|function|class)\s+\w+\s*[(<:{]",
+                // Case-sensitive so "terror" stays out
+                r"\b(?-i:[A-Z]\w*(Error|Exception))\b",
+            ],
+            Category::Adult => &[],
+            Category::SelfHarm => &[r"\b(kill|hurt|cut|starve)(ing)? myself\b"],
+            Category::Violence => &[
+                r"\b(kill|hurt|stab|shoot|beat up) (him|her|them|someone|people|my \w+)\b",
+                r"\bhow (do i|to) (make|build) an? (gun|pipe bomb|weapon)\b",
+            ],
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Category::ALL.iter().find(|c| c.as_str() == s) {
+            Some(category) => Ok(*category),
+            None => bail!(
+                "unknown category {:?} (expected {})",
+                s,
+                Category::ALL.map(|c| c.as_str()).join(", ")
+            ),
+        }
+    }
+}
+
+/// Keywords and patterns tagging prompts with one category
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryRule {
+    /// Built-in or custom category name
+    pub category: String,
+
+    /// Keywords or phrases (matched on normalized words)
+    pub keywords: Vec<String>,
+
+    /// Regular expressions (matched case-insensitively on the raw text)
+    pub patterns: Vec<String>,
+}
+
+impl CategoryRule {
+    /// The built-in rule for a category
+    pub fn builtin(category: Category) -> Self {
+        CategoryRule {
+            category: category.as_str().to_string(),
+            keywords: category.keywords().iter().map(|k| k.to_string()).collect(),
+            patterns: category.patterns().iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+/// Scores prompts for categories (e.g., a small local text classifier)
+pub trait CategoryModel: Send + Sync {
+    /// Score `text` for every category the model knows, from 0.0 to 1.0
+    fn scores(&self, text: &str) -> Result<Vec<(String, f32)>>;
+}
+
+/// Classifier configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifierConfig {
+    /// Rules, checked in order (several may share a category)
+    pub rules: Vec<CategoryRule>,
+
+    /// Minimum [`CategoryModel`] score for a category to be tagged
+    pub model_threshold: f32,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        ClassifierConfig {
+            rules: Category::ALL.map(CategoryRule::builtin).to_vec(),
+            model_threshold: DEFAULT_MODEL_THRESHOLD,
+        }
+    }
+}
+
+struct CompiledRule {
+    category: String,
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+/// Tags prompt text with content categories
+pub struct PromptClassifier {
+    rules: Vec<CompiledRule>,
+    threshold: f32,
+    model: RwLock<Option<Arc<dyn CategoryModel>>>,
+}
+
+impl std::fmt::Debug for PromptClassifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PromptClassifier")
+            .field("categories", &self.categories())
+            .field("model", &self.model.read().unwrap().is_some())
+            .finish()
+    }
+}
+
+impl Default for PromptClassifier {
+    fn default() -> Self {
+        PromptClassifier::new(&ClassifierConfig::default()).expect("built-in category patterns")
+    }
+}
+
+impl PromptClassifier {
+    /// Compile the rules, failing on an invalid pattern
+    pub fn new(config: &ClassifierConfig) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            let patterns = rule
+                .patterns
+                .iter()
+                .map(|p| Regex::new(&format!("(?i){}", p)))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("category {:?}: invalid pattern", rule.category))?;
+            rules.push(CompiledRule {
+                category: rule.category.clone(),
+                keywords: rule
+                    .keywords
+                    .iter()
+                    .map(|k| dedup::normalize(k))
+                    .filter(|k| !k.is_empty())
+                    .collect(),
+                patterns,
+            });
+        }
+        Ok(PromptClassifier {
+            rules,
+            threshold: config.model_threshold,
+            model: RwLock::new(None),
+        })
+    }
+
+    /// Use a model alongside the rules (None = rules only)
+    pub fn set_model(&self, model: Option<Arc<dyn CategoryModel>>) {
+        *self.model.write().unwrap() = model;
+    }
+
+    /// Categories the rules can produce, in order
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories: Vec<&str> = Vec::new();
+        for rule in &self.rules {
+            if !categories.contains(&rule.category.as_str()) {
+                categories.push(&rule.category);
+            }
+        }
+        categories
+    }
+
+    /// Categories of `text`, each once, rule matches first
+    ///
+    /// A failing model is logged and leaves the rule matches.
+    pub fn classify(&self, text: &str) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        if text.trim().is_empty() {
+            return categories;
+        }
+        // Pad so keywords only match on word boundaries
+        let padded = format!(" {} ", dedup::normalize(text));
+        for rule in &self.rules {
+            if categories.contains(&rule.category) {
+                continue;
+            }
+            let matched = rule
+                .keywords
+                .iter()
+                .any(|k| padded.contains(&format!(" {} ", k)))
+                || rule.patterns.iter().any(|p| p.is_match(text));
+            if matched {
+                categories.push(rule.category.clone());
+            }
+        }
+
+        let model = self.model.read().unwrap().clone();
+        if let Some(model) = model {
+            match model.scores(text) {
+                Ok(scores) => {
+                    for (category, score) in scores {
+                        if score >= self.threshold && !categories.contains(&category) {
+                            categories.push(category);
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Category model failed: {:#}", e),
+            }
+        }
+        categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_rules() {
+        let classifier = PromptClassifier::default();
+        assert_eq!(
+            classifier.classify("Can you check my Math homework? Solve 3x + 2 = 11"),
+            ["homework"]
+        );
+        assert_eq!(
+            classifier.classify("Why does my Python code raise a KeyError?"),
+            ["coding"]
+        );
+        assert_eq!(
+            classifier.classify("I want to die, nobody would notice"),
+            ["self_harm"]
+        );
+        assert_eq!(
+            classifier.classify("Write an essay about the Boston Massacre"),
+            ["homework", "violence"]
+        );
+        // Keywords match whole words only
+        assert!(classifier.classify("Tips for Essex sightseeing").is_empty());
+        assert!(classifier.classify("").is_empty());
+    }
+
+    struct Fixed(Vec<(String, f32)>);
+
+    impl CategoryModel for Fixed {
+        fn scores(&self, _text: &str) -> Result<Vec<(String, f32)>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_custom_rules_and_model() {
+        let mut config = ClassifierConfig::default();
+        config.rules.push(CategoryRule {
+            category: "gaming".to_string(),
+            keywords: vec!["minecraft".to_string()],
+            patterns: vec![r"\bv-?bucks\b".to_string()],
+        });
+        let classifier = PromptClassifier::new(&config).unwrap();
+        assert_eq!(classifier.classify("How do I get free VBucks?"), ["gaming"]);
+
+        classifier.set_model(Some(Arc::new(Fixed(vec![
+            ("adult".to_string(), 0.93),
+            ("violence".to_string(), 0.4),
+        ]))));
+        assert_eq!(
+            classifier.classify("Build a Minecraft castle"),
+            ["gaming", "adult"]
+        );
+
+        config.rules[0].patterns.push("(unclosed".to_string());
+        assert!(PromptClassifier::new(&config).is_err());
+    }
+}
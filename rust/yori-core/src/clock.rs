@@ -0,0 +1,257 @@
+//! Local time for policies
+//!
+//! Nearly every home policy is about time ("no LLMs after 21:00 on school
+//! nights"), and Rego's `time.*` built-ins work in nanoseconds and need a
+//! timezone name spelled out. Two things save policy authors that work:
+//!
+//! - the `schedule` section of the policy input carries the router's local
+//!   time, already broken down ([`local_time`])
+//! - policies can call these functions:
+//!
+//! ```rego
+//! # "HH:MM" or an RFC 3339 timestamp, within a window (which may span midnight)
+//! late if yori.time_between(input.schedule.time, "21:00", "06:30")
+//!
+//! # A weekly window: days it starts on (empty = every day), start, end
+//! school_night if yori.in_schedule(
+//!     {"days": ["sun", "mon", "tue", "wed", "thu"], "start": "21:00", "end": "06:30"},
+//!     input.timestamp,
+//! )
+//!
+//! # The same breakdown as input.schedule, for any timestamp
+//! saturday if yori.local_time(input.timestamp).weekday == "saturday"
+//! ```
+//!
+//! Timestamps may also be nanoseconds since the epoch, as returned by
+//! `time.now_ns()`.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Timelike, Utc, Weekday};
+use serde_json::{json, Map, Value};
+
+use crate::enrich::Schedule;
+
+/// Local-time fields of the policy input's `schedule` section
+///
+/// `weekday`, `hour`, `minute`, `time` ("HH:MM"), `date` ("YYYY-MM-DD"),
+/// `timezone` (IANA name, or the offset if the system doesn't say),
+/// `utc_offset` ("+01:00") and `weekend`.
+pub fn local_time(time: DateTime<Utc>) -> Map<String, Value> {
+    let local = time.with_timezone(&Local);
+    let utc_offset = local.format("%:z").to_string();
+    let timezone = iana_time_zone::get_timezone().unwrap_or_else(|_| utc_offset.clone());
+    [
+        ("weekday", json!(weekday_name(local.weekday()))),
+        ("hour", json!(local.hour())),
+        ("minute", json!(local.minute())),
+        ("time", json!(local.format("%H:%M").to_string())),
+        ("date", json!(local.format("%Y-%m-%d").to_string())),
+        ("timezone", json!(timezone)),
+        ("utc_offset", json!(utc_offset)),
+        (
+            "weekend",
+            json!(matches!(local.weekday(), Weekday::Sat | Weekday::Sun)),
+        ),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+/// Lowercase full day name ("monday"), as policies compare against
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+/// Make the `yori.*` time functions available to policies on `engine`
+pub(crate) fn register(engine: &mut regorus::Engine) -> Result<()> {
+    engine.add_extension(
+        "yori.time_between".to_string(),
+        3,
+        Box::new(|args: Vec<regorus::Value>| {
+            let time = clock_time(&args[0])?;
+            let (start, end) = (hh_mm(&args[1])?, hh_mm(&args[2])?);
+            let inside = if start <= end {
+                time >= start && time < end
+            } else {
+                time >= start || time < end
+            };
+            Ok(regorus::Value::from(inside))
+        }),
+    )?;
+    engine.add_extension(
+        "yori.in_schedule".to_string(),
+        2,
+        Box::new(|args: Vec<regorus::Value>| {
+            let schedule = schedule(&serde_json::to_value(&args[0])?)?;
+            Ok(regorus::Value::from(
+                schedule.is_active(timestamp(&args[1])?),
+            ))
+        }),
+    )?;
+    engine.add_extension(
+        "yori.local_time".to_string(),
+        1,
+        Box::new(|args: Vec<regorus::Value>| {
+            let time = timestamp(&args[0])?.with_timezone(&Utc);
+            Ok(regorus::Value::from(Value::Object(local_time(time))))
+        }),
+    )?;
+    Ok(())
+}
+
+/// An RFC 3339 string or nanoseconds since the epoch, in local time
+fn timestamp(value: &regorus::Value) -> Result<DateTime<Local>> {
+    match value {
+        regorus::Value::String(s) => Ok(DateTime::parse_from_rfc3339(s)
+            .with_context(|| format!("{:?} is not an RFC 3339 timestamp", s))?
+            .with_timezone(&Local)),
+        regorus::Value::Number(_) => {
+            let nanos = value.as_i64()?;
+            Ok(DateTime::from_timestamp_nanos(nanos).with_timezone(&Local))
+        }
+        other => bail!("expected a timestamp, got {}", other),
+    }
+}
+
+/// Time of day from "HH:MM[:SS]" or a timestamp
+fn clock_time(value: &regorus::Value) -> Result<NaiveTime> {
+    match value {
+        regorus::Value::String(s) if !s.contains('T') => hh_mm(value),
+        _ => Ok(timestamp(value)?.time()),
+    }
+}
+
+/// "HH:MM" or "HH:MM:SS"
+fn hh_mm(value: &regorus::Value) -> Result<NaiveTime> {
+    let text = value.as_string()?;
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M:%S"))
+        .with_context(|| format!("{:?} is not a time of day (HH:MM)", text.as_ref()))
+}
+
+/// A schedule written in Rego as `{"days": [...], "start": ..., "end": ...}`
+fn schedule(value: &Value) -> Result<Schedule> {
+    let text = |key: &str| {
+        value[key]
+            .as_str()
+            .with_context(|| format!("schedule needs a {:?} time", key))
+    };
+    let time = |key: &str| -> Result<NaiveTime> { hh_mm(&regorus::Value::from(text(key)?)) };
+    let days = match &value["days"] {
+        Value::Null => Vec::new(),
+        Value::Array(days) => days
+            .iter()
+            .map(|day| {
+                day.as_str()
+                    .and_then(|d| d.parse::<Weekday>().ok())
+                    .with_context(|| format!("{} is not a weekday", day))
+            })
+            .collect::<Result<_>>()?,
+        other => bail!("schedule days must be a list, got {}", other),
+    };
+    Ok(Schedule {
+        name: value["name"].as_str().unwrap_or_default().to_string(),
+        days,
+        start: time("start")?,
+        end: time("end")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn policy(rego: &str) -> regorus::Engine {
+        let mut engine = regorus::Engine::new();
+        register(&mut engine).unwrap();
+        engine
+            .add_policy("clock.rego".to_string(), rego.to_string())
+            .unwrap();
+        engine
+    }
+
+    fn eval(engine: &mut regorus::Engine, rule: &str, input: Value) -> Value {
+        engine.set_input(regorus::Value::from(input));
+        serde_json::to_value(
+            engine
+                .eval_rule(format!("data.yori.clock.{}", rule))
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_time_between_handles_midnight() {
+        let mut engine = policy(
+            "package yori.clock\n\nlate := yori.time_between(input.time, \"21:00\", \"06:30\")\n\
+             lunch := yori.time_between(input.time, \"12:00\", \"13:00\")\n",
+        );
+        for (time, late, lunch) in [
+            ("22:15", true, false),
+            ("06:29", true, false),
+            ("06:30", false, false),
+            ("12:00:30", false, true),
+        ] {
+            assert_eq!(
+                eval(&mut engine, "late", json!({"time": time})),
+                late,
+                "{}",
+                time
+            );
+            assert_eq!(
+                eval(&mut engine, "lunch", json!({"time": time})),
+                lunch,
+                "{}",
+                time
+            );
+        }
+        engine.set_input(regorus::Value::from(json!({"time": "9pm"})));
+        assert!(engine
+            .eval_rule("data.yori.clock.late".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn test_in_schedule_and_local_time_take_timestamps() {
+        let mut engine = policy(
+            "package yori.clock\n\n\
+             school_night := yori.in_schedule({\"days\": [\"sun\", \"mon\", \"tue\", \"wed\", \"thu\"], \
+             \"start\": \"21:00\", \"end\": \"06:30\"}, input.timestamp)\n\
+             now := yori.local_time(input.timestamp)\n",
+        );
+        // Built in local time, so the test holds in any timezone
+        let at = |day, hour| {
+            Local
+                .with_ymd_and_hms(2026, 3, day, hour, 0, 0)
+                .unwrap()
+                .to_rfc3339()
+        };
+        // 2026-03-01 is a Sunday
+        for (day, hour, expected) in [(1, 22, true), (2, 5, true), (6, 22, false), (7, 5, false)] {
+            let input = json!({"timestamp": at(day, hour)});
+            assert_eq!(eval(&mut engine, "school_night", input), expected);
+        }
+
+        let nanos = Local
+            .with_ymd_and_hms(2026, 3, 7, 21, 5, 0)
+            .unwrap()
+            .timestamp_nanos_opt();
+        let now = eval(&mut engine, "now", json!({"timestamp": nanos}));
+        assert_eq!(now["weekday"], "saturday");
+        assert_eq!(
+            (&now["time"], &now["weekend"]),
+            (&json!("21:05"), &json!(true))
+        );
+        assert_eq!(now["date"], "2026-03-07");
+    }
+}
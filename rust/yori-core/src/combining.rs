@@ -0,0 +1,227 @@
+//! How the decisions of several policy packages combine into one
+//!
+//! Each package with decision rules votes on a request:
+//!
+//! - **deny** - its `allow` is defined and not true, or its `deny` is
+//!   non-empty
+//! - **allow** - its `allow` is true
+//! - **abstain** - neither (e.g. only a `deny` set that came out empty)
+//!
+//! A [`CombinationStrategy`] turns those votes into the final decision.
+//! Requests nobody votes on (or any request, with no policies loaded) get
+//! the [`DefaultDecision`]: allow unless configured to fail closed.
+
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+/// How package votes combine into a decision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CombinationStrategy {
+    /// Any deny wins, wherever the package sits
+    #[default]
+    DenyOverrides,
+
+    /// Any allow wins; otherwise any deny
+    AllowOverrides,
+
+    /// The first package (in file order) that votes decides
+    FirstMatch,
+
+    /// The first package to vote decides, in order of each package's
+    /// `priority` rule (highest first, 0 if undefined; ties in file order)
+    PriorityOrdered,
+}
+
+impl CombinationStrategy {
+    /// Name used in configuration and the Python API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CombinationStrategy::DenyOverrides => "deny-overrides",
+            CombinationStrategy::AllowOverrides => "allow-overrides",
+            CombinationStrategy::FirstMatch => "first-match",
+            CombinationStrategy::PriorityOrdered => "priority-ordered",
+        }
+    }
+}
+
+impl FromStr for CombinationStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "deny-overrides" => Ok(CombinationStrategy::DenyOverrides),
+            "allow-overrides" => Ok(CombinationStrategy::AllowOverrides),
+            "first-match" => Ok(CombinationStrategy::FirstMatch),
+            "priority-ordered" | "priority" => Ok(CombinationStrategy::PriorityOrdered),
+            other => bail!(
+                "unknown combination strategy {:?} (expected deny-overrides, \
+                 allow-overrides, first-match or priority-ordered)",
+                other
+            ),
+        }
+    }
+}
+
+/// Decision for requests no policy decides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DefaultDecision {
+    /// Let them through (fail open)
+    #[default]
+    Allow,
+
+    /// Block them (fail closed), for enforce installations
+    Deny,
+}
+
+impl DefaultDecision {
+    /// Name used in configuration and the Python API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DefaultDecision::Allow => "allow",
+            DefaultDecision::Deny => "deny",
+        }
+    }
+}
+
+impl FromStr for DefaultDecision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(DefaultDecision::Allow),
+            "deny" => Ok(DefaultDecision::Deny),
+            other => bail!(
+                "unknown default decision {:?} (expected allow or deny)",
+                other
+            ),
+        }
+    }
+}
+
+/// One package's vote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Vote {
+    Allow,
+    Deny,
+    Abstain,
+}
+
+/// Outcome of combining votes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Combined {
+    /// Whether the request is allowed
+    pub allow: bool,
+
+    /// Index of the package the decision is attributed to
+    pub decider: usize,
+
+    /// Indexes of the packages whose votes count toward the decision
+    /// (all of them when nobody voted), decider first
+    pub supporters: Vec<usize>,
+
+    /// Nobody voted, so the default decision applied
+    pub by_default: bool,
+}
+
+/// Combine `(vote, priority)` per package, in file order, falling back to
+/// `default` if nobody voted
+///
+/// `votes` must not be empty.
+pub(crate) fn combine(
+    strategy: CombinationStrategy,
+    default: DefaultDecision,
+    votes: &[(Vote, f64)],
+) -> Combined {
+    let all_of =
+        |vote: Vote| -> Vec<usize> { (0..votes.len()).filter(|&i| votes[i].0 == vote).collect() };
+    let decided_by = |allow: bool, supporters: Vec<usize>| Combined {
+        allow,
+        decider: supporters[0],
+        supporters,
+        by_default: false,
+    };
+
+    let first_voter = |order: Vec<usize>| {
+        order
+            .into_iter()
+            .find(|&i| votes[i].0 != Vote::Abstain)
+            .map(|i| decided_by(votes[i].0 == Vote::Allow, vec![i]))
+    };
+    let decision = match strategy {
+        CombinationStrategy::DenyOverrides => Some(all_of(Vote::Deny))
+            .filter(|denies| !denies.is_empty())
+            .map(|denies| decided_by(false, denies)),
+        CombinationStrategy::AllowOverrides => {
+            let (allows, denies) = (all_of(Vote::Allow), all_of(Vote::Deny));
+            if !allows.is_empty() {
+                Some(decided_by(true, allows))
+            } else if !denies.is_empty() {
+                Some(decided_by(false, denies))
+            } else {
+                None
+            }
+        }
+        CombinationStrategy::FirstMatch => first_voter((0..votes.len()).collect()),
+        CombinationStrategy::PriorityOrdered => {
+            let mut order: Vec<usize> = (0..votes.len()).collect();
+            // Stable, so equal priorities keep file order
+            order.sort_by(|&a, &b| votes[b].1.total_cmp(&votes[a].1));
+            first_voter(order)
+        }
+    };
+    decision.unwrap_or_else(|| Combined {
+        by_default: true,
+        ..decided_by(
+            default == DefaultDecision::Allow,
+            (0..votes.len()).collect(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Vote::*;
+
+    fn outcome(strategy: &str, votes: &[(Vote, f64)]) -> (bool, usize) {
+        let combined = combine(strategy.parse().unwrap(), DefaultDecision::Allow, votes);
+        (combined.allow, combined.decider)
+    }
+
+    #[test]
+    fn test_strategies_resolve_conflicts_differently() {
+        let votes = [(Abstain, 0.0), (Allow, 0.0), (Deny, 5.0), (Deny, 0.0)];
+        assert_eq!(outcome("deny-overrides", &votes), (false, 2));
+        assert_eq!(outcome("allow-overrides", &votes), (true, 1));
+        assert_eq!(outcome("first-match", &votes), (true, 1));
+        assert_eq!(outcome("priority_ordered", &votes), (false, 2));
+
+        let combined = combine(
+            CombinationStrategy::DenyOverrides,
+            DefaultDecision::Deny,
+            &votes,
+        );
+        assert_eq!(combined.supporters, [2, 3]);
+    }
+
+    #[test]
+    fn test_priority_ties_keep_file_order_and_abstain_gets_default() {
+        let votes = [(Deny, 1.0), (Allow, 1.0), (Abstain, 9.0)];
+        assert_eq!(outcome("priority-ordered", &votes), (false, 0));
+
+        let votes = [(Abstain, 0.0), (Abstain, 0.0)];
+        for strategy in [
+            "deny-overrides",
+            "allow-overrides",
+            "first-match",
+            "priority",
+        ] {
+            let combined = combine(strategy.parse().unwrap(), DefaultDecision::Allow, &votes);
+            assert!(combined.allow && combined.by_default);
+            assert_eq!(combined.supporters, [0, 1]);
+            let combined = combine(strategy.parse().unwrap(), DefaultDecision::Deny, &votes);
+            assert!(!combined.allow && combined.by_default);
+        }
+        assert!("most-specific".parse::<CombinationStrategy>().is_err());
+    }
+}
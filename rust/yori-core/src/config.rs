@@ -0,0 +1,2421 @@
+//! Gateway configuration file
+//!
+//! One file, `/usr/local/etc/yori/yori.toml`, configures the proxy, audit
+//! log, caches, quotas and policy engine. Every setting has a default, so
+//! an empty file (or no file) is a working observe-mode gateway:
+//!
+//! ```toml
+//! [proxy]
+//! listen = "[::]:8443"
+//! mode = "enforce"
+//! rate_limit_per_minute = 60
+//!
+//! [providers.hosts]
+//! "api.x.ai" = "openai"
+//!
+//! [audit]
+//! database = "/var/db/yori/audit.db"
+//! retention_days = 90
+//!
+//! [transcripts]
+//! tenants = ["upstairs"]
+//! retention_days = 14
+//!
+//! [cache]
+//! decision_ttl_secs = 5
+//! decision_deny_ttl_secs = 1
+//! prompt_models = ["llama3*"]
+//!
+//! [quota.defaults]
+//! daily_tokens = 50000
+//!
+//! [quota.subjects.timmy]
+//! daily_tokens = 20000
+//!
+//! [models.subjects.timmy]
+//! allow = ["gpt-4o-mini"]
+//! downgrade = { "gpt-4o" = "gpt-4o-mini" }
+//!
+//! [classifier.categories.gaming]
+//! keywords = ["minecraft", "fortnite"]
+//!
+//! [jailbreak]
+//! alert_threshold = 0.6
+//!
+//! [dlp]
+//! actions = { api_key = "block" }
+//!
+//! [block_page]
+//! message = "{reason} Ask a parent if you think this is a mistake."
+//!
+//! [advisory]
+//! notice = true
+//!
+//! [policy]
+//! directory = "/usr/local/etc/yori/policies"
+//! default_decision = "deny"
+//!
+//! [[alerts.targets]]
+//! kind = "ntfy"
+//! url = "https://ntfy.sh/our-family-yori"
+//!
+//! [decision_log]
+//! url = "https://logs.example-msp.net/v1/logs"
+//!
+//! [dns]
+//! addresses = ["192.168.1.1"]
+//!
+//! [[routing.backends]]
+//! name = "ollama"
+//! kind = "ollama"
+//! address = "ollama.lan:11434"
+//! model = "llama3.2"
+//!
+//! [secrets]
+//! strip_client_keys = true
+//!
+//! [cost.prices]
+//! "gpt-4o*" = { input = 2.50, output = 10.00 }
+//!
+//! [budget]
+//! monthly_cap_usd = 20.0
+//! hard_cap = true
+//! subjects = { timmy = 5.0 }
+//! ```
+//!
+//! Files ending in `.yaml`/`.yml` are read as YAML with the same layout.
+//! Any setting can be overridden from the environment as
+//! `YORI_<SECTION>_<SETTING>`, e.g. `YORI_PROXY_MODE=observe` or
+//! `YORI_PROXY_ENDPOINTS=api.openai.com,api.anthropic.com`.
+//!
+//! Unknown settings are rejected rather than ignored, and validation
+//! reports every problem at once, each prefixed with its `section.setting`.
+
+use crate::advisory::AdvisoryConfig;
+use crate::alerts::{parse_url, AlertConfig, AlertKind, AlertTarget};
+use crate::audit::{AuditConfig, AuditEventType};
+use crate::blockpage::BlockPageConfig;
+use crate::budget::{BudgetConfig, DEFAULT_BUDGET_STATE};
+use crate::bundle::{BundleConfig, DEFAULT_BUNDLE_INTERVAL_SECS};
+use crate::canary::CanaryConfig;
+use crate::classify::{
+    Category, CategoryRule, ClassifierConfig, PromptClassifier, DEFAULT_MODEL_THRESHOLD,
+};
+use crate::combining::{CombinationStrategy, DefaultDecision};
+use crate::connect::InterceptionMode;
+use crate::connlimit::ConnectionLimitConfig;
+use crate::cost::{ModelPrice, PricingTable};
+use crate::decisionlog::DecisionLogConfig;
+use crate::decisions::{
+    DecisionCache, DEFAULT_DECISION_CACHE_ENTRIES, DEFAULT_DECISION_DENY_TTL_SECS,
+    DEFAULT_DECISION_TTL_SECS,
+};
+use crate::dlp::{DlpAction, DlpConfig, DlpRule, DlpScanner, SecretKind};
+use crate::dnsoverride::{DnsFormat, DnsOverrideConfig};
+use crate::enrich::EnrichmentConfig;
+use crate::jailbreak::{JailbreakConfig, DEFAULT_JAILBREAK_THRESHOLD};
+use crate::localroute::{BackendKind, LocalBackend, LocalRoute, LocalRoutingConfig};
+use crate::maintenance::MaintenanceConfig;
+use crate::models::{ModelList, ModelPolicyConfig};
+use crate::origdst::DestinationLookup;
+use crate::policy::{json_to_py, PolicyEngine};
+use crate::policymeta::DEFAULT_POLICY_METADATA;
+use crate::promptcache::{
+    PromptCacheConfig, PromptMatch, DEFAULT_PROMPT_CACHE_ENTRIES, DEFAULT_PROMPT_CACHE_TTL_SECS,
+};
+use crate::providers::{validate_pattern, Provider, ProviderRegistry};
+use crate::proxy::{ProxyConfig, ProxyMode};
+use crate::proxyauth::{check_password_hash, ProxyAuthConfig, ProxyUser, DEFAULT_PROXY_REALM};
+use crate::quota::{QuotaConfig, QuotaLimits, DEFAULT_QUOTA_STATE};
+use crate::redact::{RedactionConfig, RedactionRule, Redactor};
+use crate::replay::DEFAULT_WARM_EVENTS;
+use crate::secrets::{ApiKeyStore, DEFAULT_API_KEY_STORE};
+use crate::shortcut::DEFAULT_SHORTCUT_ENTRIES;
+use crate::signing::BundleVerifier;
+use crate::spool::{
+    SpoolConfig, DEFAULT_BUFFER_EVENTS, DEFAULT_SPOOL_MAX_BYTES, DEFAULT_SPOOL_PATH,
+};
+use crate::tenant::is_valid_id;
+use crate::transcript::{
+    TranscriptConfig, DEFAULT_TRANSCRIPT_DB, DEFAULT_TRANSCRIPT_RETENTION_DAYS,
+};
+use crate::upstream::{CircuitBreakerConfig, RetryPolicy};
+use crate::vault::{Vault, DEFAULT_VAULT_PATH};
+use anyhow::{bail, Context, Result};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the gateway looks for its configuration
+pub const DEFAULT_CONFIG_PATH: &str = "/usr/local/etc/yori/yori.toml";
+
+/// Environment variable naming a different configuration file
+pub const CONFIG_PATH_ENV: &str = "YORI_CONFIG";
+
+/// Prefix of per-setting environment overrides
+const ENV_PREFIX: &str = "YORI_";
+
+/// The whole configuration file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct YoriConfig {
+    pub proxy: ProxySettings,
+    pub proxy_auth: ProxyAuthSettings,
+    pub providers: ProviderSettings,
+    pub audit: AuditSettings,
+    pub transcripts: TranscriptSettings,
+    pub cache: CacheSettings,
+    pub quota: QuotaSettings,
+    pub models: ModelSettings,
+    pub classifier: ClassifierSettings,
+    pub jailbreak: JailbreakSettings,
+    pub dlp: DlpSettings,
+    pub block_page: BlockPageSettings,
+    pub advisory: AdvisorySettings,
+    pub policy: PolicySettings,
+    pub alerts: AlertSettings,
+    pub decision_log: DecisionLogSettings,
+    pub dns: DnsSettings,
+    pub routing: RoutingSettings,
+    pub secrets: SecretsSettings,
+    pub cost: CostSettings,
+    pub budget: BudgetSettings,
+}
+
+/// `[proxy]`: listener, interception and traffic limits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxySettings {
+    /// Listen address ("host:port")
+    pub listen: String,
+
+    /// "observe", "advisory" or "enforce"
+    pub mode: String,
+
+    /// Hosts or `*` patterns to intercept
+    pub endpoints: Vec<String>,
+
+    /// Local model servers that stay reachable in local-only mode
+    pub local_endpoints: Vec<String>,
+
+    /// Start with cloud LLM endpoints blocked
+    pub local_only: bool,
+
+    /// "transparent", "explicit" or "both"
+    pub interception: String,
+
+    /// How redirected connections' real destination is found: "sni",
+    /// "nat" (pf rdr-to, iptables REDIRECT) or "tproxy"
+    pub destination_lookup: String,
+
+    pub tls_cert: PathBuf,
+    pub tls_key: PathBuf,
+
+    /// Interception CA (unset = present `tls_cert` for every host)
+    pub ca_cert: Option<PathBuf>,
+    pub ca_key: Option<PathBuf>,
+
+    /// Offer HTTP/2 to clients and upstreams (HTTP/1.1 stays available)
+    pub http2: bool,
+
+    /// Requests per minute per device and endpoint (unset = unlimited)
+    pub rate_limit_per_minute: Option<u32>,
+    pub rate_limit_burst: Option<u32>,
+
+    pub retry_window_secs: u64,
+    pub serve_cached_retries: bool,
+
+    /// Quiet gap after which a device's next request starts a new session
+    pub session_idle_secs: u64,
+
+    pub drain_timeout_secs: u64,
+
+    /// Connections served at once; further ones queue (unset = unlimited)
+    pub max_connections: Option<usize>,
+
+    /// Connections one device may have served or queued (unset =
+    /// unlimited)
+    pub max_connections_per_client: Option<usize>,
+
+    /// How long a queued connection waits for a slot before it's closed
+    pub connection_queue_timeout_ms: u64,
+
+    pub inspect_responses: bool,
+    pub discover_names: bool,
+
+    /// Largest request body in bytes (unset = unlimited)
+    pub max_request_bytes: Option<u64>,
+
+    /// Largest upstream response body in bytes (unset = unlimited)
+    pub max_response_bytes: Option<u64>,
+
+    /// Attempts per idempotent request on upstream errors (1 = no retries)
+    pub upstream_retries: u32,
+
+    /// Consecutive upstream errors that open an endpoint's circuit
+    /// (0 disables the breaker)
+    pub circuit_breaker_threshold: u32,
+
+    /// Seconds an open circuit refuses requests before probing again
+    pub circuit_breaker_cooldown_secs: u64,
+
+    /// Seconds between bypass-detection canary probes (unset = off)
+    pub canary_interval_secs: Option<u64>,
+}
+
+/// `[audit]`: event storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSettings {
+    pub database: PathBuf,
+    pub retention_days: u32,
+    pub prompt_preview_chars: usize,
+    pub dedup_prompts: bool,
+    pub dedup_max_distance: u32,
+
+    /// Vault key file encrypting the database (unset = plaintext)
+    pub encryption_key: Option<PathBuf>,
+
+    /// PII rules run on prompt previews (empty = off)
+    pub redact_pii: Vec<PiiRuleSettings>,
+
+    pub hash_chain: bool,
+
+    /// How often events older than `retention_days` are deleted
+    pub prune_interval_secs: u64,
+
+    /// How often new events are folded into the daily/weekly usage
+    /// rollups (0 = no rollups)
+    pub rollup_interval_secs: u64,
+
+    /// Events held in memory while the database is locked or full
+    pub buffer_events: usize,
+
+    /// File events spill to once the memory buffer is full
+    pub spool_path: PathBuf,
+
+    /// Spool file size cap (0 = memory buffer only)
+    pub spool_max_bytes: u64,
+}
+
+/// `[transcripts]`: full conversations kept for households that opt in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptSettings {
+    /// Tenants whose conversations are recorded (empty = transcripts off)
+    pub tenants: Vec<String>,
+
+    pub database: PathBuf,
+
+    /// Kept apart from `audit.retention_days`
+    pub retention_days: u32,
+
+    pub prune_interval_secs: u64,
+}
+
+/// One `audit.redact_pii` entry: a built-in detector's name, or a table
+/// adjusting one or adding a custom pattern
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PiiRuleSettings {
+    Name(String),
+    Rule(PiiRuleTable),
+}
+
+/// Table form of a redaction rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PiiRuleTable {
+    /// Built-in detector, or the custom rule's name
+    pub name: String,
+
+    /// Regular expression (required for custom rules)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+
+    /// Placeholder (default: the detector's, or `[NAME]`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl PiiRuleSettings {
+    /// Redaction rule for this entry
+    pub fn rule(&self) -> Result<RedactionRule> {
+        let table = match self {
+            PiiRuleSettings::Name(name) => return Ok(RedactionRule::builtin(name.parse()?)),
+            PiiRuleSettings::Rule(table) => table,
+        };
+        let mut rule = match &table.pattern {
+            Some(pattern) => {
+                RedactionRule::custom(&table.name, pattern, table.replacement.as_deref())
+            }
+            None => RedactionRule::builtin(table.name.parse()?),
+        };
+        if let Some(replacement) = &table.replacement {
+            rule.replacement = replacement.clone();
+        }
+        rule.enabled = table.enabled;
+        Ok(rule)
+    }
+}
+
+/// `[cache]`: policy decision and prompt caches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSettings {
+    /// How long identical inputs reuse a decision (0 disables)
+    pub decision_ttl_secs: u64,
+
+    /// How long identical inputs reuse a denial (0 re-evaluates every
+    /// denial); keep it short so loosened policies apply quickly
+    pub decision_deny_ttl_secs: u64,
+
+    /// Maximum number of cached decisions
+    pub decision_max_entries: usize,
+
+    /// Decisions reused across inputs that agree on every field the
+    /// policies read (0 turns decision shortcuts off)
+    pub decision_shortcut_entries: usize,
+
+    /// Recent audit events re-evaluated at startup to fill the decision
+    /// caches (0 = no warming)
+    pub decision_warm_events: usize,
+
+    /// Models whose completions are cached for repeated prompts (empty =
+    /// prompt cache off)
+    pub prompt_models: Vec<String>,
+
+    /// How prompts match: exact, simhash or embedding
+    pub prompt_match: String,
+
+    /// How long a cached completion is reused
+    pub prompt_ttl_secs: u64,
+
+    /// Maximum number of cached completions
+    pub prompt_max_entries: usize,
+}
+
+/// `[quota]`: token and request allowances
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaSettings {
+    /// Counter file (unset = in memory only)
+    pub state_path: Option<PathBuf>,
+
+    /// Limits for subjects without an override
+    pub defaults: QuotaLimits,
+
+    /// Limits per device owner or client IP
+    pub subjects: HashMap<String, QuotaLimits>,
+}
+
+/// `[models]`: which models each device or user may request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelSettings {
+    /// List for subjects without their own (default: every model)
+    pub defaults: ModelList,
+
+    /// Lists per device name, device owner or client IP
+    pub subjects: HashMap<String, ModelList>,
+}
+
+/// `[classifier]`: content categories tagged on prompts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassifierSettings {
+    /// Start from the built-in homework, coding, adult, self_harm and
+    /// violence rules
+    pub builtin: bool,
+
+    /// Rules per category, extending a built-in category of the same name
+    pub categories: BTreeMap<String, CategoryRuleSettings>,
+
+    /// Minimum model score for a category to be tagged
+    pub model_threshold: f32,
+}
+
+/// One `classifier.categories` entry
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CategoryRuleSettings {
+    pub keywords: Vec<String>,
+
+    /// Regular expressions, matched case-insensitively
+    pub patterns: Vec<String>,
+}
+
+/// `[jailbreak]`: jailbreak heuristics scored into the policy input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JailbreakSettings {
+    pub enabled: bool,
+
+    /// Score (0 to 1) at which `input.jailbreak.suspected` is set
+    pub alert_threshold: f32,
+}
+
+/// `[dlp]`: secrets scanned for in outbound request bodies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DlpSettings {
+    pub enabled: bool,
+
+    /// Built-in detector name to "block", "redact", "alert" or "off"
+    pub actions: BTreeMap<String, String>,
+
+    /// Custom patterns, scanned after the built-in detectors
+    pub rules: Vec<DlpRuleSettings>,
+}
+
+/// One `[[dlp.rules]]` entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DlpRuleSettings {
+    pub name: String,
+
+    /// Regular expression
+    pub pattern: String,
+
+    /// "block", "redact" or "alert"
+    pub action: String,
+}
+
+/// `[block_page]`: what clients see when a request is blocked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockPageSettings {
+    /// Heading of the HTML page
+    pub title: String,
+
+    /// Message template ({reason}, {policy}, {device}, {endpoint},
+    /// {request_id}, {time})
+    pub message: String,
+
+    /// HTML page replacing the built-in one (also {title} and {message})
+    pub html_template: Option<PathBuf>,
+}
+
+/// `[advisory]`: warnings on responses advisory mode forwarded despite a
+/// deny
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdvisorySettings {
+    /// Add `X-YORI-Policy`/`X-YORI-Warning` response headers
+    pub headers: bool,
+
+    /// Prepend the warning to non-streaming completions
+    pub notice: bool,
+
+    /// Warning template ({reason}, {policy})
+    pub message: String,
+}
+
+/// `[policy]`: Rego policies and how their decisions combine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicySettings {
+    pub directory: PathBuf,
+
+    /// JSON/YAML data documents outside the policy directory
+    pub data_dir: Option<PathBuf>,
+
+    /// "deny-overrides", "allow-overrides", "first-match" or
+    /// "priority-ordered"
+    pub strategy: String,
+
+    /// "allow" or "deny" for requests no policy decides
+    pub default_decision: String,
+
+    /// Refuse policy bundles not signed by one of `trusted_keys`
+    pub require_signed: bool,
+
+    /// Hex-encoded ed25519 public keys of policy signers
+    pub trusted_keys: Vec<String>,
+
+    /// Where enabled/priority/mode overrides are kept (unset = forgotten
+    /// on restart)
+    pub metadata_path: Option<PathBuf>,
+
+    /// OPA-style bundle (.tar.gz) to keep the policy directory in sync
+    /// with (unset = local policies only)
+    pub bundle_url: Option<String>,
+
+    /// Time between bundle checks
+    pub bundle_interval_secs: u64,
+
+    /// Bearer token for the bundle server
+    pub bundle_token: Option<String>,
+
+    /// PEM bundle of CAs trusted for the bundle server (unset = system
+    /// bundle)
+    pub bundle_ca: Option<PathBuf>,
+}
+
+/// `[alerts]`: notifications for blocked requests and errors
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertSettings {
+    /// Where alerts are posted (empty = alerting off)
+    pub targets: Vec<AlertTargetSettings>,
+
+    /// Audit event types that raise an alert ("block", "error", ...)
+    pub events: Vec<String>,
+
+    pub dedup_window_secs: u64,
+    pub max_per_minute: u32,
+    pub include_prompt: bool,
+
+    /// PEM bundle of trusted CAs (unset = system bundle)
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// One `[[alerts.targets]]` entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertTargetSettings {
+    /// "webhook", "ntfy", "discord" or "slack"
+    pub kind: String,
+    pub url: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// `[dns]`: resolver overrides pointing LLM hosts at the gateway
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsSettings {
+    /// Gateway addresses LLM hosts resolve to (empty = overrides off)
+    pub addresses: Vec<String>,
+
+    /// "unbound" or "dnsmasq"
+    pub format: String,
+
+    /// Override file (unset = the resolver's include directory)
+    pub path: Option<PathBuf>,
+
+    /// Command run after the file changes (unset = the resolver's reload,
+    /// empty = none)
+    pub reload_command: Option<Vec<String>>,
+
+    pub check_interval_secs: u64,
+}
+
+/// `[decision_log]`: shipping policy decisions to a collector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecisionLogSettings {
+    /// OPA-compatible decision log endpoint or HTTPS collector (unset =
+    /// shipping off)
+    pub url: Option<String>,
+
+    /// Bearer token for the collector
+    pub token: Option<String>,
+
+    /// Gzip each batch
+    pub compress: bool,
+
+    pub batch_size: usize,
+    pub flush_interval_secs: u64,
+
+    /// Records kept while the collector is unreachable
+    pub max_buffered: usize,
+
+    pub include_prompt: bool,
+
+    /// PEM bundle of trusted CAs (unset = system bundle)
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// `[routing]`: local model servers requests can be routed to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingSettings {
+    pub backends: Vec<BackendSettings>,
+    pub routes: Vec<RouteSettings>,
+}
+
+/// One `[[routing.backends]]` entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackendSettings {
+    pub name: String,
+
+    /// "ollama" or "llamacpp"
+    pub kind: String,
+
+    /// "host" or "host:port"
+    pub address: String,
+
+    /// Model requested from the backend (unset = the client's model)
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+/// One `[[routing.routes]]` entry: models always answered locally
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSettings {
+    pub models: Vec<String>,
+    pub backend: String,
+}
+
+/// `[proxy_auth]`: credentials for explicit-mode clients
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyAuthSettings {
+    /// Refuse clients that don't authenticate
+    pub required: bool,
+
+    /// PEM CA bundle for client certificates (unset = passwords only)
+    pub client_ca: Option<PathBuf>,
+
+    pub realm: String,
+    pub users: BTreeMap<String, ProxyUserSettings>,
+}
+
+/// One `[proxy_auth.users.<name>]` entry
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyUserSettings {
+    /// From `yori_core.hash_proxy_password()` (unset = certificate only)
+    pub password_hash: Option<String>,
+    pub group: Option<String>,
+}
+
+/// `[providers]`: LLM hosts beyond the built-in catalog
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderSettings {
+    /// Host or wildcard pattern -> API format ("openai", "anthropic",
+    /// ...); these hosts are intercepted as well as `proxy.endpoints`
+    pub hosts: BTreeMap<String, String>,
+}
+
+/// `[secrets]`: provider API keys injected by the gateway
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsSettings {
+    /// Sealed API key file (managed with `yori keys`)
+    pub store: PathBuf,
+
+    /// Vault key file the store is sealed with
+    pub vault_key: PathBuf,
+
+    /// Remove client credentials even for providers without a stored key
+    pub strip_client_keys: bool,
+}
+
+/// `[cost]`: per-model prices for cost estimates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostSettings {
+    /// Start from the built-in list prices
+    pub builtin_prices: bool,
+
+    /// Model pattern -> USD per million input/output tokens, overriding
+    /// the built-in entry for the same pattern
+    pub prices: BTreeMap<String, ModelPrice>,
+}
+
+/// `[budget]`: monthly spend caps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetSettings {
+    /// Spend totals file (unset = in memory only)
+    pub state_path: Option<PathBuf>,
+
+    /// Monthly cap in USD for subjects without their own (unset = no cap)
+    pub monthly_cap_usd: Option<f64>,
+
+    /// Monthly caps in USD per device owner or client IP
+    pub subjects: HashMap<String, f64>,
+
+    /// Block subjects over their cap regardless of policy and mode
+    pub hard_cap: bool,
+}
+
+impl Default for BudgetSettings {
+    fn default() -> Self {
+        BudgetSettings {
+            state_path: Some(PathBuf::from(DEFAULT_BUDGET_STATE)),
+            monthly_cap_usd: None,
+            subjects: HashMap::new(),
+            hard_cap: false,
+        }
+    }
+}
+
+impl Default for CostSettings {
+    fn default() -> Self {
+        CostSettings {
+            builtin_prices: true,
+            prices: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        let proxy = ProxyConfig::default();
+        ProxySettings {
+            listen: proxy.listen_addr.to_string(),
+            mode: proxy.mode.as_str().to_string(),
+            endpoints: proxy.endpoints,
+            local_endpoints: proxy.local_endpoints,
+            local_only: proxy.local_only,
+            interception: proxy.interception.as_str().to_string(),
+            destination_lookup: proxy.destination_lookup.as_str().to_string(),
+            tls_cert: PathBuf::from(proxy.tls_cert_path),
+            tls_key: PathBuf::from(proxy.tls_key_path),
+            ca_cert: proxy.ca_cert_path.map(PathBuf::from),
+            ca_key: proxy.ca_key_path.map(PathBuf::from),
+            http2: proxy.http2,
+            rate_limit_per_minute: proxy.rate_limit_per_minute,
+            rate_limit_burst: proxy.rate_limit_burst,
+            retry_window_secs: proxy.retry_window_secs,
+            serve_cached_retries: proxy.serve_cached_retries,
+            session_idle_secs: proxy.session_idle_secs,
+            drain_timeout_secs: proxy.drain_timeout_secs,
+            max_connections: proxy.connection_limits.max_connections,
+            max_connections_per_client: proxy.connection_limits.max_per_client,
+            connection_queue_timeout_ms: proxy.connection_limits.queue_timeout.as_millis() as u64,
+            inspect_responses: proxy.inspect_responses,
+            discover_names: proxy.discover_names,
+            max_request_bytes: proxy.max_request_bytes,
+            max_response_bytes: proxy.max_response_bytes,
+            upstream_retries: proxy.upstream_retry.max_attempts,
+            circuit_breaker_threshold: proxy.circuit_breaker.failure_threshold,
+            circuit_breaker_cooldown_secs: proxy.circuit_breaker.cooldown.as_secs(),
+            canary_interval_secs: proxy.canary.as_ref().map(|c| c.interval.as_secs()),
+        }
+    }
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        let audit = AuditConfig::default();
+        AuditSettings {
+            database: audit.database,
+            retention_days: audit.retention_days,
+            prompt_preview_chars: audit.prompt_preview_chars,
+            dedup_prompts: audit.dedup_prompts,
+            dedup_max_distance: audit.dedup_max_distance,
+            encryption_key: audit.encryption_key,
+            redact_pii: audit
+                .redaction
+                .rules
+                .iter()
+                .map(|rule| PiiRuleSettings::Name(rule.name.clone()))
+                .collect(),
+            hash_chain: audit.hash_chain,
+            prune_interval_secs: MaintenanceConfig::default().prune_interval.as_secs(),
+            rollup_interval_secs: MaintenanceConfig::default()
+                .rollup_interval
+                .map_or(0, |interval| interval.as_secs()),
+            buffer_events: DEFAULT_BUFFER_EVENTS,
+            spool_path: PathBuf::from(DEFAULT_SPOOL_PATH),
+            spool_max_bytes: DEFAULT_SPOOL_MAX_BYTES,
+        }
+    }
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            decision_ttl_secs: DEFAULT_DECISION_TTL_SECS,
+            decision_deny_ttl_secs: DEFAULT_DECISION_DENY_TTL_SECS,
+            decision_max_entries: DEFAULT_DECISION_CACHE_ENTRIES,
+            decision_shortcut_entries: DEFAULT_SHORTCUT_ENTRIES,
+            decision_warm_events: DEFAULT_WARM_EVENTS,
+            prompt_models: Vec::new(),
+            prompt_match: "exact".to_string(),
+            prompt_ttl_secs: DEFAULT_PROMPT_CACHE_TTL_SECS,
+            prompt_max_entries: DEFAULT_PROMPT_CACHE_ENTRIES,
+        }
+    }
+}
+
+impl Default for QuotaSettings {
+    fn default() -> Self {
+        QuotaSettings {
+            state_path: Some(PathBuf::from(DEFAULT_QUOTA_STATE)),
+            defaults: QuotaLimits::default(),
+            subjects: HashMap::new(),
+        }
+    }
+}
+
+impl Default for PolicySettings {
+    fn default() -> Self {
+        PolicySettings {
+            directory: PathBuf::from("/usr/local/etc/yori/policies"),
+            data_dir: None,
+            strategy: CombinationStrategy::default().as_str().to_string(),
+            default_decision: DefaultDecision::default().as_str().to_string(),
+            require_signed: false,
+            trusted_keys: Vec::new(),
+            metadata_path: Some(PathBuf::from(DEFAULT_POLICY_METADATA)),
+            bundle_url: None,
+            bundle_interval_secs: DEFAULT_BUNDLE_INTERVAL_SECS,
+            bundle_token: None,
+            bundle_ca: None,
+        }
+    }
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        let alerts = AlertConfig::default();
+        AlertSettings {
+            targets: Vec::new(),
+            events: alerts
+                .event_types
+                .iter()
+                .map(|t| t.as_str().to_string())
+                .collect(),
+            dedup_window_secs: alerts.dedup_window.as_secs(),
+            max_per_minute: alerts.max_per_minute,
+            include_prompt: alerts.include_prompt,
+            ca_bundle: alerts.ca_bundle,
+        }
+    }
+}
+
+impl Default for ProxyAuthSettings {
+    fn default() -> Self {
+        ProxyAuthSettings {
+            required: false,
+            client_ca: None,
+            realm: DEFAULT_PROXY_REALM.to_string(),
+            users: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for TranscriptSettings {
+    fn default() -> Self {
+        TranscriptSettings {
+            tenants: Vec::new(),
+            database: PathBuf::from(DEFAULT_TRANSCRIPT_DB),
+            retention_days: DEFAULT_TRANSCRIPT_RETENTION_DAYS,
+            prune_interval_secs: TranscriptConfig::default().prune_interval.as_secs(),
+        }
+    }
+}
+
+impl Default for ClassifierSettings {
+    fn default() -> Self {
+        ClassifierSettings {
+            builtin: true,
+            categories: BTreeMap::new(),
+            model_threshold: DEFAULT_MODEL_THRESHOLD,
+        }
+    }
+}
+
+impl Default for JailbreakSettings {
+    fn default() -> Self {
+        JailbreakSettings {
+            enabled: true,
+            alert_threshold: DEFAULT_JAILBREAK_THRESHOLD,
+        }
+    }
+}
+
+impl Default for DlpSettings {
+    fn default() -> Self {
+        DlpSettings {
+            enabled: true,
+            actions: BTreeMap::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for BlockPageSettings {
+    fn default() -> Self {
+        let page = BlockPageConfig::default();
+        BlockPageSettings {
+            title: page.title,
+            message: page.message,
+            html_template: None,
+        }
+    }
+}
+
+impl Default for AdvisorySettings {
+    fn default() -> Self {
+        let advisory = AdvisoryConfig::default();
+        AdvisorySettings {
+            headers: advisory.headers,
+            notice: advisory.notice,
+            message: advisory.message,
+        }
+    }
+}
+
+impl Default for DnsSettings {
+    fn default() -> Self {
+        DnsSettings {
+            addresses: Vec::new(),
+            format: DnsFormat::Unbound.as_str().to_string(),
+            path: None,
+            reload_command: None,
+            check_interval_secs: 30,
+        }
+    }
+}
+
+impl Default for DecisionLogSettings {
+    fn default() -> Self {
+        let log = DecisionLogConfig::default();
+        DecisionLogSettings {
+            url: None,
+            token: None,
+            compress: log.compress,
+            batch_size: log.batch_size,
+            flush_interval_secs: log.flush_interval.as_secs(),
+            max_buffered: log.max_buffered,
+            include_prompt: log.include_prompt,
+            ca_bundle: log.ca_bundle,
+        }
+    }
+}
+
+impl Default for SecretsSettings {
+    fn default() -> Self {
+        SecretsSettings {
+            store: PathBuf::from(DEFAULT_API_KEY_STORE),
+            vault_key: PathBuf::from(DEFAULT_VAULT_PATH),
+            strip_client_keys: ProxyConfig::default().strip_client_keys,
+        }
+    }
+}
+
+impl YoriConfig {
+    /// Load the file named by `YORI_CONFIG`, else [`DEFAULT_CONFIG_PATH`]
+    ///
+    /// A missing default file means all defaults; environment overrides
+    /// are applied and the result validated either way.
+    pub fn load_default() -> Result<Self> {
+        match std::env::var_os(CONFIG_PATH_ENV) {
+            Some(path) => YoriConfig::load(Path::new(&path)),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                YoriConfig::load(Path::new(DEFAULT_CONFIG_PATH))
+            }
+            None => {
+                let mut config = YoriConfig::default();
+                config.apply_env(std::env::vars())?;
+                config.validate()?;
+                Ok(config)
+            }
+        }
+    }
+
+    /// Load a TOML (or `.yaml`/`.yml`) file, apply environment overrides
+    /// and validate
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config = YoriConfig::parse(&text, is_yaml(path))
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        config.apply_env(std::env::vars())?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Parse configuration text without validating it
+    pub fn parse(text: &str, yaml: bool) -> Result<Self> {
+        // An empty YAML document is null rather than an empty mapping
+        if text.trim().is_empty() {
+            return Ok(YoriConfig::default());
+        }
+        if yaml {
+            Ok(serde_yaml::from_str(text)?)
+        } else {
+            Ok(toml::from_str(text)?)
+        }
+    }
+
+    /// Apply `YORI_<SECTION>_<SETTING>` overrides, returning how many
+    /// were applied
+    ///
+    /// Values are read as JSON where that fits the setting (numbers,
+    /// booleans, lists), otherwise as plain strings; lists also accept
+    /// comma-separated values. An empty value unsets an optional setting.
+    /// Variables for other sections (e.g. `YORI_CONFIG`) are ignored.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<usize> {
+        let mut applied = 0;
+        for (name, raw) in vars {
+            let Some(rest) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let rest = rest.to_ascii_lowercase();
+            let document = serde_json::to_value(&*self)?;
+            // Section names may contain underscores themselves (decision_log),
+            // and the longest match wins (proxy_auth over proxy)
+            let split = document
+                .as_object()
+                .and_then(|sections| {
+                    sections
+                        .keys()
+                        .filter_map(|section| {
+                            let setting = rest.strip_prefix(section.as_str())?.strip_prefix('_')?;
+                            Some((section.clone(), setting.to_string()))
+                        })
+                        .max_by_key(|(section, _)| section.len())
+                })
+                .or_else(|| {
+                    let (section, setting) = rest.split_once('_')?;
+                    Some((section.to_string(), setting.to_string()))
+                });
+            let Some((section, setting)) = split else {
+                continue;
+            };
+            let (section, setting) = (section.as_str(), setting.as_str());
+            let Some(current) = document.get(section).and_then(|s| s.get(setting)) else {
+                if document.get(section).is_some() {
+                    bail!("{}: no setting {}.{}", name, section, setting);
+                }
+                continue;
+            };
+
+            let mut candidates = Vec::new();
+            if raw.trim().is_empty() {
+                candidates.push(Value::Null);
+            } else if let Ok(value) = serde_json::from_str(&raw) {
+                candidates.push(value);
+            }
+            if current.is_array() {
+                candidates.push(Value::Array(
+                    raw.split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(|item| Value::String(item.to_string()))
+                        .collect(),
+                ));
+            }
+            candidates.push(Value::String(raw.clone()));
+
+            let mut last_error = None;
+            for candidate in candidates {
+                let mut document = document.clone();
+                document[section][setting] = candidate;
+                match serde_json::from_value(document) {
+                    Ok(config) => {
+                        *self = config;
+                        last_error = None;
+                        break;
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            if let Some(e) = last_error {
+                bail!("{}: invalid value {:?}: {}", name, raw, e);
+            }
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// Every problem the file format alone can't catch, as
+    /// `section.setting: message`
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let proxy = &self.proxy;
+        if proxy.listen.parse::<SocketAddr>().is_err() {
+            problems.push(format!(
+                "proxy.listen: {:?} is not a host:port address",
+                proxy.listen
+            ));
+        }
+        if let Err(e) = proxy.mode.parse::<ProxyMode>() {
+            problems.push(format!("proxy.mode: {:#}", e));
+        }
+        if let Err(e) = proxy.interception.parse::<InterceptionMode>() {
+            problems.push(format!("proxy.interception: {:#}", e));
+        }
+        if let Err(e) = proxy.destination_lookup.parse::<DestinationLookup>() {
+            problems.push(format!("proxy.destination_lookup: {:#}", e));
+        }
+        for endpoint in &proxy.endpoints {
+            if let Err(e) = validate_pattern(endpoint) {
+                problems.push(format!("proxy.endpoints: {:#}", e));
+            }
+        }
+        for endpoint in &proxy.local_endpoints {
+            if endpoint.trim().is_empty() || endpoint.contains(['/', ' ']) {
+                problems.push(format!(
+                    "proxy.local_endpoints: {:?} must be a host name",
+                    endpoint
+                ));
+            }
+        }
+        let auth = &self.proxy_auth;
+        for (name, user) in &auth.users {
+            if name.is_empty() || name.contains(':') {
+                problems.push(format!("proxy_auth.users: invalid user name {:?}", name));
+            }
+            if let Some(Err(e)) = user.password_hash.as_deref().map(check_password_hash) {
+                problems.push(format!("proxy_auth.users: {}: {:#}", name, e));
+            }
+        }
+        if auth.required && auth.users.is_empty() && auth.client_ca.is_none() {
+            problems.push(
+                "proxy_auth.required: no users or client_ca to authenticate with".to_string(),
+            );
+        }
+
+        for (pattern, provider) in &self.providers.hosts {
+            let checked = validate_pattern(pattern).and_then(|()| provider.parse::<Provider>());
+            if let Err(e) = checked {
+                problems.push(format!("providers.hosts: {:#}", e));
+            }
+        }
+        if proxy.ca_cert.is_some() != proxy.ca_key.is_some() {
+            problems.push("proxy.ca_cert: ca_cert and ca_key must be set together".to_string());
+        }
+        match (proxy.rate_limit_per_minute, proxy.rate_limit_burst) {
+            (Some(0), _) => {
+                problems.push("proxy.rate_limit_per_minute: must be positive".to_string())
+            }
+            (None, Some(_)) => problems.push(
+                "proxy.rate_limit_burst: has no effect without rate_limit_per_minute".to_string(),
+            ),
+            (_, Some(0)) => problems.push("proxy.rate_limit_burst: must be positive".to_string()),
+            _ => {}
+        }
+        for (setting, limit) in [
+            ("max_request_bytes", proxy.max_request_bytes),
+            ("max_response_bytes", proxy.max_response_bytes),
+        ] {
+            if limit == Some(0) {
+                problems.push(format!(
+                    "proxy.{}: must be positive (unset for no limit)",
+                    setting
+                ));
+            }
+        }
+        for (setting, limit) in [
+            ("max_connections", proxy.max_connections),
+            (
+                "max_connections_per_client",
+                proxy.max_connections_per_client,
+            ),
+        ] {
+            if limit == Some(0) {
+                problems.push(format!(
+                    "proxy.{}: must be positive (unset for no limit)",
+                    setting
+                ));
+            }
+        }
+        if proxy.upstream_retries == 0 {
+            problems
+                .push("proxy.upstream_retries: must be at least 1 (the first attempt)".to_string());
+        }
+        if proxy.circuit_breaker_threshold > 0 && proxy.circuit_breaker_cooldown_secs == 0 {
+            problems.push(
+                "proxy.circuit_breaker_cooldown_secs: must be positive while the breaker is on"
+                    .to_string(),
+            );
+        }
+        if proxy.canary_interval_secs == Some(0) {
+            problems.push("proxy.canary_interval_secs: must be positive (unset = off)".to_string());
+        }
+
+        let audit = &self.audit;
+        if audit.retention_days == 0 {
+            problems.push("audit.retention_days: must be positive".to_string());
+        }
+        if audit.prune_interval_secs == 0 {
+            problems.push("audit.prune_interval_secs: must be positive".to_string());
+        }
+        if audit.dedup_max_distance > 3 {
+            problems.push(format!(
+                "audit.dedup_max_distance: {} is above the maximum of 3",
+                audit.dedup_max_distance
+            ));
+        }
+        if let Err(e) = self
+            .redaction_config()
+            .and_then(|config| Redactor::new(&config))
+        {
+            problems.push(format!("audit.redact_pii: {:#}", e));
+        }
+
+        let transcripts = &self.transcripts;
+        for tenant in &transcripts.tenants {
+            if !is_valid_id(tenant) {
+                problems.push(format!(
+                    "transcripts.tenants: {:?} is not a valid tenant id",
+                    tenant
+                ));
+            }
+        }
+        if transcripts.retention_days == 0 {
+            problems.push("transcripts.retention_days: must be positive".to_string());
+        }
+        if transcripts.prune_interval_secs == 0 {
+            problems.push("transcripts.prune_interval_secs: must be positive".to_string());
+        }
+
+        if (self.cache.decision_ttl_secs > 0 || self.cache.decision_deny_ttl_secs > 0)
+            && self.cache.decision_max_entries == 0
+        {
+            problems.push(
+                "cache.decision_max_entries: must be positive while a decision TTL is set"
+                    .to_string(),
+            );
+        }
+
+        if let Err(e) = self.cache.prompt_match.parse::<PromptMatch>() {
+            problems.push(format!("cache.prompt_match: {:#}", e));
+        }
+        if !self.cache.prompt_models.is_empty() && self.cache.prompt_max_entries == 0 {
+            problems.push(
+                "cache.prompt_max_entries: must be positive while prompt_models is set".to_string(),
+            );
+        }
+
+        let lists = std::iter::once(("defaults", &self.models.defaults))
+            .chain(self.models.subjects.iter().map(|(s, l)| (s.as_str(), l)));
+        for (subject, list) in lists {
+            for to in list.downgrade.values() {
+                if to.trim().is_empty() || to.contains('*') {
+                    problems.push(format!(
+                        "models.downgrade: {:?} (for {}) must be a model name, not a pattern",
+                        to, subject
+                    ));
+                }
+            }
+        }
+
+        for category in self.classifier.categories.keys() {
+            if category.trim().is_empty() || category.contains(',') {
+                problems.push(format!(
+                    "classifier.categories: {:?} is not a valid category name",
+                    category
+                ));
+            }
+        }
+        if let Err(e) = PromptClassifier::new(&self.classifier_config()) {
+            problems.push(format!("classifier.categories: {:#}", e));
+        }
+        if !(0.0..=1.0).contains(&self.classifier.model_threshold) {
+            problems.push("classifier.model_threshold: must be between 0 and 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.jailbreak.alert_threshold) {
+            problems.push("jailbreak.alert_threshold: must be between 0 and 1".to_string());
+        }
+
+        for (detector, action) in &self.dlp.actions {
+            if let Err(e) = detector.parse::<SecretKind>() {
+                problems.push(format!("dlp.actions: {:#}", e));
+            }
+            if action != "off" {
+                if let Err(e) = action.parse::<DlpAction>() {
+                    problems.push(format!("dlp.actions: {} (or \"off\")", e));
+                }
+            }
+        }
+        for rule in &self.dlp.rules {
+            if rule.name.trim().is_empty() {
+                problems.push("dlp.rules: every rule needs a name".to_string());
+            }
+            if let Err(e) = rule.action.parse::<DlpAction>() {
+                problems.push(format!("dlp.rules: rule {:?}: {:#}", rule.name, e));
+            }
+        }
+        if let Ok(dlp) = self.dlp_config() {
+            if let Err(e) = DlpScanner::new(&dlp) {
+                problems.push(format!("dlp.rules: {:#}", e));
+            }
+        }
+
+        if self.block_page.message.trim().is_empty() {
+            problems.push("block_page.message: must not be empty".to_string());
+        }
+        if let Err(e) = self.block_page_config() {
+            problems.push(format!("block_page.html_template: {:#}", e));
+        }
+        if self.advisory.notice && self.advisory.message.trim().is_empty() {
+            problems.push("advisory.message: must not be empty".to_string());
+        }
+
+        if let Err(e) = self.policy.strategy.parse::<CombinationStrategy>() {
+            problems.push(format!("policy.strategy: {:#}", e));
+        }
+        if let Err(e) = self.policy.default_decision.parse::<DefaultDecision>() {
+            problems.push(format!("policy.default_decision: {:#}", e));
+        }
+        if self.policy.directory.as_os_str().is_empty() {
+            problems.push("policy.directory: must not be empty".to_string());
+        }
+        if self.policy.require_signed || !self.policy.trusted_keys.is_empty() {
+            if let Err(e) = BundleVerifier::new(&self.policy.trusted_keys) {
+                problems.push(format!("policy.trusted_keys: {:#}", e));
+            }
+        }
+        if let Some(url) = &self.policy.bundle_url {
+            if let Err(e) = parse_url(url) {
+                problems.push(format!("policy.bundle_url: {:#}", e));
+            }
+            if self.policy.bundle_interval_secs == 0 {
+                problems.push("policy.bundle_interval_secs: must be positive".to_string());
+            }
+        }
+
+        let alerts = &self.alerts;
+        for target in &alerts.targets {
+            if let Err(e) = target.kind.parse::<AlertKind>() {
+                problems.push(format!("alerts.targets: {:#}", e));
+            }
+            if !target.url.starts_with("http://") && !target.url.starts_with("https://") {
+                problems.push(format!(
+                    "alerts.targets: {:?} must be an http:// or https:// URL",
+                    target.url
+                ));
+            }
+        }
+        for event in &alerts.events {
+            if let Err(e) = event.parse::<AuditEventType>() {
+                problems.push(format!("alerts.events: {:#}", e));
+            }
+        }
+        if alerts.max_per_minute == 0 {
+            problems.push("alerts.max_per_minute: must be positive".to_string());
+        }
+
+        let log = &self.decision_log;
+        if let Some(url) = &log.url {
+            if let Err(e) = parse_url(url) {
+                problems.push(format!("decision_log.url: {:#}", e));
+            }
+        }
+        if log.batch_size == 0 {
+            problems.push("decision_log.batch_size: must be positive".to_string());
+        }
+        if log.flush_interval_secs == 0 {
+            problems.push("decision_log.flush_interval_secs: must be positive".to_string());
+        }
+        if log.max_buffered < log.batch_size {
+            problems.push("decision_log.max_buffered: must be at least batch_size".to_string());
+        }
+
+        let dns = &self.dns;
+        for address in &dns.addresses {
+            if address.parse::<IpAddr>().is_err() {
+                problems.push(format!("dns.addresses: {:?} is not an IP address", address));
+            }
+        }
+        if let Err(e) = dns.format.parse::<DnsFormat>() {
+            problems.push(format!("dns.format: {:#}", e));
+        }
+        if dns.check_interval_secs == 0 {
+            problems.push("dns.check_interval_secs: must be positive".to_string());
+        }
+
+        let routing = &self.routing;
+        for (i, backend) in routing.backends.iter().enumerate() {
+            if let Err(e) = backend.kind.parse::<BackendKind>() {
+                problems.push(format!("routing.backends: {:#}", e));
+            }
+            if backend.address.trim().is_empty() || backend.address.contains(['/', ' ']) {
+                problems.push(format!(
+                    "routing.backends: {:?} must be a host or host:port",
+                    backend.address
+                ));
+            }
+            if routing.backends[..i].iter().any(|b| b.name == backend.name) {
+                problems.push(format!(
+                    "routing.backends: name {:?} is used twice",
+                    backend.name
+                ));
+            }
+        }
+        for route in &routing.routes {
+            if !routing.backends.iter().any(|b| b.name == route.backend) {
+                problems.push(format!(
+                    "routing.routes: backend {:?} is not configured",
+                    route.backend
+                ));
+            }
+        }
+
+        if let Err(e) = self.pricing() {
+            problems.push(format!("cost.prices: {:#}", e));
+        }
+
+        let valid_cap = |cap: f64| cap.is_finite() && cap >= 0.0;
+        if !self.budget.monthly_cap_usd.is_none_or(valid_cap) {
+            problems.push("budget.monthly_cap_usd: must be a non-negative number".to_string());
+        }
+        let mut bad_caps: Vec<&str> = self
+            .budget
+            .subjects
+            .iter()
+            .filter(|(_, cap)| !valid_cap(**cap))
+            .map(|(subject, _)| subject.as_str())
+            .collect();
+        if !bad_caps.is_empty() {
+            bad_caps.sort_unstable();
+            problems.push(format!(
+                "budget.subjects: caps must be non-negative numbers ({})",
+                bad_caps.join(", ")
+            ));
+        }
+
+        if self.secrets.store.as_os_str().is_empty() {
+            problems.push("secrets.store: must not be empty".to_string());
+        }
+        if self.secrets.vault_key.as_os_str().is_empty() {
+            problems.push("secrets.vault_key: must not be empty".to_string());
+        }
+
+        problems
+    }
+
+    /// Fail with every entry of [`YoriConfig::problems`]
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        bail!(
+            "{} configuration problem(s):\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        )
+    }
+
+    /// Proxy configuration (with the quota, models, classifier, jailbreak,
+    /// dlp, block_page, advisory, routing, secrets, cost and budget
+    /// sections, the policy bundle, the audit buffer and decision warming);
+    /// other proxy settings keep their defaults
+    pub fn proxy_config(&self) -> Result<ProxyConfig> {
+        let proxy = &self.proxy;
+        Ok(ProxyConfig {
+            listen_addr: proxy
+                .listen
+                .parse()
+                .with_context(|| format!("proxy.listen: invalid address {:?}", proxy.listen))?,
+            mode: proxy.mode.parse::<ProxyMode>().context("proxy.mode")?,
+            endpoints: proxy.endpoints.clone(),
+            providers: self.provider_registry().context("providers.hosts")?,
+            auth: self.proxy_auth_config(),
+            local_endpoints: proxy.local_endpoints.clone(),
+            local_only: proxy.local_only,
+            interception: proxy
+                .interception
+                .parse::<InterceptionMode>()
+                .context("proxy.interception")?,
+            destination_lookup: proxy
+                .destination_lookup
+                .parse::<DestinationLookup>()
+                .context("proxy.destination_lookup")?,
+            tls_cert_path: proxy.tls_cert.display().to_string(),
+            tls_key_path: proxy.tls_key.display().to_string(),
+            ca_cert_path: proxy.ca_cert.as_ref().map(|p| p.display().to_string()),
+            ca_key_path: proxy.ca_key.as_ref().map(|p| p.display().to_string()),
+            http2: proxy.http2,
+            rate_limit_per_minute: proxy.rate_limit_per_minute,
+            rate_limit_burst: proxy.rate_limit_burst,
+            retry_window_secs: proxy.retry_window_secs,
+            serve_cached_retries: proxy.serve_cached_retries,
+            session_idle_secs: proxy.session_idle_secs,
+            drain_timeout_secs: proxy.drain_timeout_secs,
+            connection_limits: ConnectionLimitConfig {
+                max_connections: proxy.max_connections,
+                max_per_client: proxy.max_connections_per_client,
+                queue_timeout: Duration::from_millis(proxy.connection_queue_timeout_ms),
+            },
+            inspect_responses: proxy.inspect_responses,
+            discover_names: proxy.discover_names,
+            max_request_bytes: proxy.max_request_bytes,
+            max_response_bytes: proxy.max_response_bytes,
+            upstream_retry: RetryPolicy {
+                max_attempts: proxy.upstream_retries,
+                ..RetryPolicy::default()
+            },
+            circuit_breaker: CircuitBreakerConfig {
+                failure_threshold: proxy.circuit_breaker_threshold,
+                cooldown: Duration::from_secs(proxy.circuit_breaker_cooldown_secs),
+            },
+            quota: self.quota_config(),
+            models: ModelPolicyConfig {
+                defaults: self.models.defaults.clone(),
+                subjects: self.models.subjects.clone(),
+            },
+            classifier: self.classifier_config(),
+            dlp: self.dlp_config()?,
+            block_page: self.block_page_config()?,
+            advisory: AdvisoryConfig {
+                headers: self.advisory.headers,
+                notice: self.advisory.notice,
+                message: self.advisory.message.clone(),
+            },
+            enrichment: EnrichmentConfig {
+                jailbreak: self.jailbreak_config(),
+                ..EnrichmentConfig::default()
+            },
+            local_routing: self.routing_config()?,
+            prompt_cache: self.prompt_cache_config()?,
+            strip_client_keys: self.secrets.strip_client_keys,
+            pricing: self.pricing().context("cost.prices")?,
+            budget: self.budget_config(),
+            warm_decisions: self.cache.decision_warm_events,
+            maintenance: self.maintenance_config(),
+            policy_bundle: self.bundle_config()?,
+            canary: self.canary_config(),
+            audit_spool: SpoolConfig {
+                memory_events: self.audit.buffer_events,
+                path: (self.audit.spool_max_bytes > 0).then(|| self.audit.spool_path.clone()),
+                max_bytes: self.audit.spool_max_bytes,
+            },
+            ..ProxyConfig::default()
+        })
+    }
+
+    /// Content category rules: the built-in ones (unless `builtin` is off)
+    /// followed by the configured ones
+    pub fn classifier_config(&self) -> ClassifierConfig {
+        let classifier = &self.classifier;
+        let mut rules = if classifier.builtin {
+            Category::ALL.map(CategoryRule::builtin).to_vec()
+        } else {
+            Vec::new()
+        };
+        rules.extend(
+            classifier
+                .categories
+                .iter()
+                .map(|(category, rule)| CategoryRule {
+                    category: category.clone(),
+                    keywords: rule.keywords.clone(),
+                    patterns: rule.patterns.clone(),
+                }),
+        );
+        ClassifierConfig {
+            rules,
+            model_threshold: classifier.model_threshold,
+        }
+    }
+
+    /// Secret scanning rules: the built-in detectors with their configured
+    /// actions (those turned "off" left out), then the custom rules; no
+    /// rules at all when `enabled` is off
+    pub fn dlp_config(&self) -> Result<DlpConfig> {
+        let dlp = &self.dlp;
+        if !dlp.enabled {
+            return Ok(DlpConfig { rules: Vec::new() });
+        }
+        let mut rules = Vec::new();
+        for kind in SecretKind::ALL {
+            let mut rule = DlpRule::builtin(kind);
+            match dlp.actions.get(kind.as_str()).map(String::as_str) {
+                Some("off") => continue,
+                Some(action) => rule.action = action.parse().context("dlp.actions")?,
+                None => {}
+            }
+            rules.push(rule);
+        }
+        for rule in &dlp.rules {
+            let action = rule
+                .action
+                .parse()
+                .with_context(|| format!("dlp.rules: rule {:?}", rule.name))?;
+            rules.push(DlpRule::custom(&rule.name, &rule.pattern, action));
+        }
+        Ok(DlpConfig { rules })
+    }
+
+    /// Block response settings, reading the HTML template if one is set
+    pub fn block_page_config(&self) -> Result<BlockPageConfig> {
+        let page = &self.block_page;
+        let html_template = match &page.html_template {
+            Some(path) => Some(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?,
+            ),
+            None => None,
+        };
+        Ok(BlockPageConfig {
+            title: page.title.clone(),
+            message: page.message.clone(),
+            html_template,
+        })
+    }
+
+    /// Jailbreak heuristic settings
+    pub fn jailbreak_config(&self) -> JailbreakConfig {
+        JailbreakConfig {
+            enabled: self.jailbreak.enabled,
+            alert_threshold: self.jailbreak.alert_threshold,
+        }
+    }
+
+    /// Proxy authentication settings
+    pub fn proxy_auth_config(&self) -> ProxyAuthConfig {
+        let auth = &self.proxy_auth;
+        ProxyAuthConfig {
+            users: auth
+                .users
+                .iter()
+                .map(|(name, user)| {
+                    let user = ProxyUser {
+                        password_hash: user.password_hash.clone(),
+                        group: user.group.clone(),
+                    };
+                    (name.clone(), user)
+                })
+                .collect(),
+            client_ca: auth.client_ca.clone(),
+            required: auth.required,
+            realm: auth.realm.clone(),
+        }
+    }
+
+    /// Provider catalog extended with `[providers.hosts]`
+    pub fn provider_registry(&self) -> Result<ProviderRegistry> {
+        let added = self
+            .providers
+            .hosts
+            .iter()
+            .map(|(pattern, provider)| Ok((pattern.clone(), provider.parse()?)))
+            .collect::<Result<_>>()?;
+        ProviderRegistry::new(added)
+    }
+
+    /// Prompt cache configuration (None when no model is listed)
+    pub fn prompt_cache_config(&self) -> Result<Option<PromptCacheConfig>> {
+        let cache = &self.cache;
+        if cache.prompt_models.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(PromptCacheConfig {
+            models: cache.prompt_models.clone(),
+            matching: cache
+                .prompt_match
+                .parse::<PromptMatch>()
+                .context("cache.prompt_match")?,
+            ttl: Duration::from_secs(cache.prompt_ttl_secs),
+            max_entries: cache.prompt_max_entries,
+            ..PromptCacheConfig::default()
+        }))
+    }
+
+    /// Audit log configuration
+    pub fn audit_config(&self) -> Result<AuditConfig> {
+        let audit = &self.audit;
+        Ok(AuditConfig {
+            database: audit.database.clone(),
+            retention_days: audit.retention_days,
+            prompt_preview_chars: audit.prompt_preview_chars,
+            dedup_prompts: audit.dedup_prompts,
+            dedup_max_distance: audit.dedup_max_distance,
+            encryption_key: audit.encryption_key.clone(),
+            redaction: self.redaction_config().context("audit.redact_pii")?,
+            hash_chain: audit.hash_chain,
+        })
+    }
+
+    /// Transcript store settings; PII is redacted with the audit rules and
+    /// turns are sealed with the `secrets.vault_key` vault
+    pub fn transcript_config(&self) -> Result<TranscriptConfig> {
+        let transcripts = &self.transcripts;
+        Ok(TranscriptConfig {
+            database: transcripts.database.clone(),
+            vault_key: self.secrets.vault_key.clone(),
+            tenants: transcripts.tenants.clone(),
+            retention_days: transcripts.retention_days,
+            redaction: self.redaction_config().context("audit.redact_pii")?,
+            prune_interval: Duration::from_secs(transcripts.prune_interval_secs),
+        })
+    }
+
+    /// PII redaction rules, failing on unknown detectors and rules listed
+    /// twice
+    pub fn redaction_config(&self) -> Result<RedactionConfig> {
+        let mut rules: Vec<RedactionRule> = Vec::new();
+        for entry in &self.audit.redact_pii {
+            let rule = entry.rule()?;
+            if rules.iter().any(|r| r.name == rule.name) {
+                bail!("rule {:?} is listed twice", rule.name);
+            }
+            rules.push(rule);
+        }
+        Ok(RedactionConfig { rules })
+    }
+
+    /// Audit maintenance schedule (retention pruning and usage rollup
+    /// intervals)
+    pub fn maintenance_config(&self) -> MaintenanceConfig {
+        MaintenanceConfig {
+            prune_interval: Duration::from_secs(self.audit.prune_interval_secs),
+            rollup_interval: (self.audit.rollup_interval_secs > 0)
+                .then(|| Duration::from_secs(self.audit.rollup_interval_secs)),
+            ..MaintenanceConfig::default()
+        }
+    }
+
+    /// Policy bundle download, if a bundle URL is set
+    ///
+    /// Bundles must be signed whenever trusted keys are configured.
+    pub fn bundle_config(&self) -> Result<Option<BundleConfig>> {
+        let Some(url) = &self.policy.bundle_url else {
+            return Ok(None);
+        };
+        let verifier = match self.policy.trusted_keys.is_empty() {
+            true => None,
+            false => Some(
+                BundleVerifier::new(&self.policy.trusted_keys).context("policy.trusted_keys")?,
+            ),
+        };
+        Ok(Some(BundleConfig {
+            url: url.clone(),
+            interval: Duration::from_secs(self.policy.bundle_interval_secs),
+            token: self.policy.bundle_token.clone(),
+            ca_bundle: self.policy.bundle_ca.clone(),
+            verifier,
+        }))
+    }
+
+    /// Canary probe of the intercepted endpoints, if an interval is set
+    ///
+    /// Wildcard endpoints have no single host to probe and are skipped.
+    pub fn canary_config(&self) -> Option<CanaryConfig> {
+        let proxy = &self.proxy;
+        let interval = proxy.canary_interval_secs?;
+        let ca_cert = proxy.ca_cert.as_ref().unwrap_or(&proxy.tls_cert);
+        Some(CanaryConfig {
+            endpoints: proxy
+                .endpoints
+                .iter()
+                .filter(|e| !e.contains('*'))
+                .cloned()
+                .collect(),
+            ca_cert_path: ca_cert.display().to_string(),
+            interval: Duration::from_secs(interval),
+            ..CanaryConfig::default()
+        })
+    }
+
+    /// Quota configuration
+    pub fn quota_config(&self) -> QuotaConfig {
+        QuotaConfig {
+            state_path: self.quota.state_path.clone(),
+            defaults: self.quota.defaults,
+            subjects: self.quota.subjects.clone(),
+        }
+    }
+
+    /// Budget configuration
+    pub fn budget_config(&self) -> BudgetConfig {
+        BudgetConfig {
+            state_path: self.budget.state_path.clone(),
+            monthly_cap_usd: self.budget.monthly_cap_usd,
+            subjects: self.budget.subjects.clone(),
+            hard_cap: self.budget.hard_cap,
+        }
+    }
+
+    /// Local model backends and routes
+    pub fn routing_config(&self) -> Result<LocalRoutingConfig> {
+        Ok(LocalRoutingConfig {
+            backends: self
+                .routing
+                .backends
+                .iter()
+                .map(|b| {
+                    Ok(LocalBackend {
+                        name: b.name.clone(),
+                        kind: b.kind.parse::<BackendKind>()?,
+                        address: b.address.clone(),
+                        model: b.model.clone(),
+                        api_key: b.api_key.clone(),
+                    })
+                })
+                .collect::<Result<_>>()
+                .context("routing.backends")?,
+            routes: self
+                .routing
+                .routes
+                .iter()
+                .map(|r| LocalRoute {
+                    models: r.models.clone(),
+                    backend: r.backend.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Price table: the built-in prices (unless turned off) plus
+    /// `[cost.prices]`
+    pub fn pricing(&self) -> Result<PricingTable> {
+        let table = if self.cost.builtin_prices {
+            PricingTable::default()
+        } else {
+            PricingTable::empty()
+        };
+        table.with_prices(&self.cost.prices)
+    }
+
+    /// Open the API key store, creating the vault key if it's missing
+    pub fn api_key_store(&self) -> Result<ApiKeyStore> {
+        let vault = Vault::open_or_create(&self.secrets.vault_key).context("secrets.vault_key")?;
+        ApiKeyStore::open(&self.secrets.store, &vault).context("secrets.store")
+    }
+
+    /// Alerting configuration, or None when no targets are configured
+    pub fn alert_config(&self) -> Result<Option<AlertConfig>> {
+        let alerts = &self.alerts;
+        if alerts.targets.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(AlertConfig {
+            targets: alerts
+                .targets
+                .iter()
+                .map(|t| {
+                    Ok(AlertTarget {
+                        kind: t.kind.parse::<AlertKind>()?,
+                        url: t.url.clone(),
+                        token: t.token.clone(),
+                    })
+                })
+                .collect::<Result<_>>()
+                .context("alerts.targets")?,
+            event_types: alerts
+                .events
+                .iter()
+                .map(|e| e.parse::<AuditEventType>())
+                .collect::<Result<_>>()
+                .context("alerts.events")?,
+            dedup_window: Duration::from_secs(alerts.dedup_window_secs),
+            max_per_minute: alerts.max_per_minute,
+            include_prompt: alerts.include_prompt,
+            ca_bundle: alerts.ca_bundle.clone(),
+            ..AlertConfig::default()
+        }))
+    }
+
+    /// Decision log shipping, or None when no collector is configured
+    pub fn decision_log_config(&self) -> Result<Option<DecisionLogConfig>> {
+        let log = &self.decision_log;
+        let Some(url) = &log.url else {
+            return Ok(None);
+        };
+        parse_url(url).context("decision_log.url")?;
+        Ok(Some(DecisionLogConfig {
+            url: url.clone(),
+            token: log.token.clone(),
+            compress: log.compress,
+            batch_size: log.batch_size,
+            flush_interval: Duration::from_secs(log.flush_interval_secs),
+            max_buffered: log.max_buffered,
+            include_prompt: log.include_prompt,
+            ca_bundle: log.ca_bundle.clone(),
+            ..DecisionLogConfig::default()
+        }))
+    }
+
+    /// DNS overrides, or None when no gateway address is configured
+    pub fn dns_override_config(&self) -> Result<Option<DnsOverrideConfig>> {
+        let dns = &self.dns;
+        if dns.addresses.is_empty() {
+            return Ok(None);
+        }
+        let format = dns.format.parse::<DnsFormat>().context("dns.format")?;
+        let addresses = dns
+            .addresses
+            .iter()
+            .map(|a| a.parse::<IpAddr>())
+            .collect::<Result<_, _>>()
+            .context("dns.addresses")?;
+        Ok(Some(DnsOverrideConfig {
+            format,
+            path: dns.path.clone().unwrap_or_else(|| format.default_path()),
+            addresses,
+            reload_command: dns
+                .reload_command
+                .clone()
+                .unwrap_or_else(|| format.default_reload_command()),
+            check_interval: Duration::from_secs(dns.check_interval_secs),
+        }))
+    }
+
+    /// Host patterns the proxy intercepts: `proxy.endpoints` plus
+    /// `[providers.hosts]`
+    pub fn intercepted_hosts(&self) -> Vec<String> {
+        let mut hosts = self.proxy.endpoints.clone();
+        hosts.extend(self.providers.hosts.keys().cloned());
+        hosts
+    }
+
+    /// Open the policy engine with the policy and cache settings
+    pub fn policy_engine(&self) -> Result<PolicyEngine> {
+        let decisions = DecisionCache::new(
+            Duration::from_secs(self.cache.decision_ttl_secs),
+            self.cache.decision_max_entries,
+        )
+        .with_deny_ttl(Duration::from_secs(self.cache.decision_deny_ttl_secs));
+        let engine = if self.policy.require_signed {
+            let verifier =
+                BundleVerifier::new(&self.policy.trusted_keys).context("policy.trusted_keys")?;
+            PolicyEngine::open_verified(&self.policy.directory, decisions, verifier)?
+        } else {
+            PolicyEngine::open_with_cache(&self.policy.directory, decisions)?
+        };
+        engine.set_strategy(
+            self.policy
+                .strategy
+                .parse::<CombinationStrategy>()
+                .context("policy.strategy")?,
+        );
+        engine.set_default_decision(
+            self.policy
+                .default_decision
+                .parse::<DefaultDecision>()
+                .context("policy.default_decision")?,
+        );
+        engine.set_shortcut_entries(self.cache.decision_shortcut_entries);
+        if let Some(data_dir) = &self.policy.data_dir {
+            engine.load_data_dir(data_dir)?;
+        }
+        if let Some(path) = &self.policy.metadata_path {
+            engine
+                .persist_metadata(path)
+                .context("policy.metadata_path")?;
+        }
+        Ok(engine)
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml" | "yml")
+    )
+}
+
+/// Load and validate the gateway configuration
+///
+/// # Arguments
+///
+/// * `path` - Configuration file (default: `$YORI_CONFIG`, else
+///   /usr/local/etc/yori/yori.toml if it exists, else built-in defaults)
+///
+/// # Returns
+///
+/// Dictionary with `proxy`, `proxy_auth`, `providers`, `audit`,
+/// `transcripts`, `cache`, `quota`, `models`, `classifier`, `jailbreak`,
+/// `dlp`, `block_page`, `advisory`, `policy`, `alerts`, `decision_log`,
+/// `dns`, `routing`, `secrets`, `cost` and `budget` sections,
+/// `YORI_*` environment overrides applied
+///
+/// Raises RuntimeError listing every problem if the configuration is
+/// invalid.
+#[pyfunction]
+#[pyo3(signature = (path=None))]
+pub fn load_config(py: Python, path: Option<PathBuf>) -> PyResult<PyObject> {
+    let config = match path {
+        Some(path) => YoriConfig::load(&path),
+        None => YoriConfig::load_default(),
+    }
+    .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+    let document =
+        serde_json::to_value(&config).map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+    json_to_py(py, &document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_toml_and_yaml_sections() {
+        let toml = "\
+[proxy]
+mode = \"enforce\"
+rate_limit_per_minute = 60
+max_connections_per_client = 16
+destination_lookup = \"nat\"
+
+[providers.hosts]
+\"api.x.ai\" = \"openai\"
+
+[audit]
+retention_days = 90
+redact_pii = [\"email\", { name = \"student_id\", pattern = \"S[0-9]{7}\" }]
+rollup_interval_secs = 0
+spool_max_bytes = 0
+
+[transcripts]
+tenants = [\"upstairs\"]
+retention_days = 14
+
+[quota.defaults]
+daily_tokens = 50000
+
+[quota.subjects.timmy]
+daily_tokens = 20000
+
+[models.subjects.timmy]
+allow = [\"gpt-4o-mini\"]
+downgrade = { \"gpt-4o\" = \"gpt-4o-mini\" }
+
+[classifier.categories.gaming]
+keywords = [\"minecraft\"]
+
+[jailbreak]
+alert_threshold = 0.7
+
+[dlp]
+actions = { api_key = \"block\", aws_credentials = \"off\" }
+
+[[dlp.rules]]
+name = \"payroll_id\"
+pattern = \"PAY-[0-9]{6}\"
+action = \"alert\"
+
+[block_page]
+title = \"Not right now\"
+
+[advisory]
+notice = true
+
+[policy]
+default_decision = \"deny\"
+
+[[alerts.targets]]
+kind = \"ntfy\"
+url = \"https://ntfy.sh/yori-test\"
+
+[dns]
+addresses = [\"192.168.1.1\"]
+format = \"dnsmasq\"
+
+[[routing.backends]]
+name = \"ollama\"
+kind = \"ollama\"
+address = \"ollama.lan\"
+
+[[routing.routes]]
+models = [\"llama*\"]
+backend = \"ollama\"
+
+[secrets]
+strip_client_keys = true
+
+[cost.prices]
+\"family-gpt\" = { input = 1.0, output = 2.0 }
+
+[budget]
+hard_cap = true
+subjects = { timmy = 5.0 }
+";
+        let config = YoriConfig::parse(toml, false).unwrap();
+        config.validate().unwrap();
+        let proxy = config.proxy_config().unwrap();
+        assert_eq!(proxy.mode, ProxyMode::Enforce);
+        assert_eq!(proxy.rate_limit_per_minute, Some(60));
+        assert_eq!(proxy.connection_limits.max_per_client, Some(16));
+        assert_eq!(proxy.destination_lookup, DestinationLookup::Nat);
+        assert_eq!(proxy.connection_limits.max_connections, Some(512));
+        assert_eq!(
+            proxy.providers.provider_for_host("api.x.ai"),
+            Some(Provider::OpenAI)
+        );
+        assert_eq!(proxy.quota.defaults.daily_tokens, Some(50000));
+        assert_eq!(proxy.quota.subjects["timmy"].daily_tokens, Some(20000));
+        assert_eq!(
+            proxy.models.subjects["timmy"].downgrade["gpt-4o"],
+            "gpt-4o-mini"
+        );
+        let gaming = proxy.classifier.rules.last().unwrap();
+        assert_eq!(gaming.category, "gaming");
+        assert_eq!(proxy.classifier.rules.len(), Category::ALL.len() + 1);
+        assert_eq!(proxy.enrichment.jailbreak.alert_threshold, 0.7);
+        let dlp: Vec<_> = proxy
+            .dlp
+            .rules
+            .iter()
+            .map(|r| (r.name.as_str(), r.action))
+            .collect();
+        assert_eq!(
+            dlp,
+            [
+                ("private_key", DlpAction::Block),
+                ("api_key", DlpAction::Block),
+                ("payroll_id", DlpAction::Alert)
+            ]
+        );
+        assert_eq!(proxy.block_page.title, "Not right now");
+        assert_eq!(proxy.block_page.message, "{reason}");
+        assert!(proxy.advisory.headers && proxy.advisory.notice);
+        assert_eq!(proxy.local_routing.backends[0].kind, BackendKind::Ollama);
+        assert_eq!(proxy.local_routing.routes[0].backend, "ollama");
+        assert!(proxy.strip_client_keys);
+        assert_eq!(proxy.pricing.price_for("family-gpt").unwrap().output, 2.0);
+        assert!(proxy.pricing.price_for("gpt-4o").is_some());
+        assert!(proxy.budget.hard_cap);
+        assert_eq!(proxy.budget.subjects["timmy"], 5.0);
+        assert_eq!(proxy.audit_spool.path, None);
+        assert_eq!(proxy.warm_decisions, DEFAULT_WARM_EVENTS);
+        assert_eq!(proxy.audit_spool.memory_events, DEFAULT_BUFFER_EVENTS);
+        let transcripts = config.transcript_config().unwrap();
+        assert_eq!(transcripts.tenants, ["upstairs"]);
+        assert_eq!(transcripts.retention_days, 14);
+        assert_eq!(transcripts.vault_key, config.secrets.vault_key);
+        let audit = config.audit_config().unwrap();
+        assert_eq!(audit.retention_days, 90);
+        assert_eq!(
+            config.maintenance_config().prune_interval,
+            Duration::from_secs(3600)
+        );
+        assert_eq!(config.maintenance_config().rollup_interval, None);
+        let rules: Vec<_> = audit
+            .redaction
+            .rules
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        assert_eq!(rules, vec!["email", "student_id"]);
+        assert_eq!(audit.redaction.rules[1].replacement, "[STUDENT_ID]");
+        let alerts = config.alert_config().unwrap().unwrap();
+        assert_eq!(alerts.targets[0].kind, AlertKind::Ntfy);
+        assert_eq!(
+            alerts.event_types,
+            vec![
+                AuditEventType::RequestBlocked,
+                AuditEventType::Error,
+                AuditEventType::SensitiveData
+            ]
+        );
+        assert!(YoriConfig::default().alert_config().unwrap().is_none());
+        let dns = config.dns_override_config().unwrap().unwrap();
+        assert_eq!(dns.format, DnsFormat::Dnsmasq);
+        assert_eq!(dns.path, DnsFormat::Dnsmasq.default_path());
+        assert!(config.intercepted_hosts().contains(&"api.x.ai".to_string()));
+        assert!(YoriConfig::default()
+            .dns_override_config()
+            .unwrap()
+            .is_none());
+
+        let yaml = "proxy:\n  mode: enforce\n  rate_limit_per_minute: 60\n  max_connections_per_client: 16\n  destination_lookup: nat\n";
+        let from_yaml = YoriConfig::parse(yaml, true).unwrap();
+        assert_eq!(from_yaml.proxy, config.proxy);
+        assert_eq!(YoriConfig::parse("", true).unwrap(), YoriConfig::default());
+        YoriConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = YoriConfig::default();
+        let applied = config
+            .apply_env(vars(&[
+                ("YORI_PROXY_MODE", "advisory"),
+                ("YORI_PROXY_RATE_LIMIT_PER_MINUTE", "30"),
+                ("YORI_PROXY_ENDPOINTS", "api.openai.com, api.anthropic.com"),
+                ("YORI_AUDIT_HASH_CHAIN", "true"),
+                ("YORI_QUOTA_STATE_PATH", ""),
+                ("YORI_DECISION_LOG_URL", "https://logs.example.net/v1/logs"),
+                ("YORI_PROXY_AUTH_REQUIRED", "true"),
+                ("YORI_CONFIG", "/tmp/yori.toml"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+        assert_eq!(applied, 7);
+        assert_eq!(config.proxy.mode, "advisory");
+        assert_eq!(config.proxy.rate_limit_per_minute, Some(30));
+        assert_eq!(
+            config.proxy.endpoints,
+            vec!["api.openai.com", "api.anthropic.com"]
+        );
+        assert!(config.audit.hash_chain);
+        assert!(config.proxy_auth.required);
+        assert_eq!(config.quota.state_path, None);
+        assert_eq!(
+            config.decision_log.url.as_deref(),
+            Some("https://logs.example.net/v1/logs")
+        );
+
+        let error = config
+            .apply_env(vars(&[("YORI_PROXY_LISTNE", "0.0.0.0:8443")]))
+            .unwrap_err();
+        assert!(error.to_string().contains("proxy.listne"));
+        let error = config
+            .apply_env(vars(&[("YORI_AUDIT_RETENTION_DAYS", "a year")]))
+            .unwrap_err();
+        assert!(error.to_string().starts_with("YORI_AUDIT_RETENTION_DAYS"));
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let toml = "\
+[proxy]
+listen = \"8443\"
+mode = \"block-everything\"
+rate_limit_burst = 5
+max_connections = 0
+canary_interval_secs = 0
+destination_lookup = \"ipfw\"
+endpoints = [\"api.openai.com\", \"*.com\"]
+
+[proxy_auth]
+required = true
+
+[proxy_auth.users.timmy]
+password_hash = \"hunter2\"
+
+[providers.hosts]
+\"api.x.ai\" = \"grok\"
+
+[audit]
+retention_days = 0
+redact_pii = [\"shoe_size\"]
+
+[transcripts]
+tenants = [\"up stairs\"]
+retention_days = 0
+
+[cache]
+prompt_match = \"fuzzy\"
+
+[classifier]
+model_threshold = 1.5
+
+[classifier.categories.gaming]
+patterns = [\"v(-bucks\"]
+
+[jailbreak]
+alert_threshold = -0.5
+
+[dlp]
+actions = { passwords = \"block\" }
+
+[[dlp.rules]]
+name = \"payroll_id\"
+pattern = \"PAY-(\"
+action = \"quarantine\"
+
+[block_page]
+message = \" \"
+html_template = \"/nonexistent/yori/block.html\"
+
+[advisory]
+notice = true
+message = \"\"
+
+[policy]
+strategy = \"most-specific\"
+require_signed = true
+bundle_url = \"ftp://policies.example.net/bundle.tar.gz\"
+
+[decision_log]
+url = \"logs.example.net\"
+batch_size = 0
+
+[dns]
+addresses = [\"gateway.lan\"]
+format = \"bind\"
+
+[[routing.routes]]
+models = [\"gpt-4o\"]
+backend = \"ollama\"
+
+[cost.prices]
+\"gpt-4o\" = { input = -2.5, output = 10.0 }
+
+[budget]
+monthly_cap_usd = -1.0
+";
+        let error = YoriConfig::parse(toml, false)
+            .unwrap()
+            .validate()
+            .unwrap_err()
+            .to_string();
+        for setting in [
+            "proxy.listen",
+            "proxy.mode",
+            "proxy.rate_limit_burst",
+            "proxy.max_connections",
+            "proxy.canary_interval_secs",
+            "proxy.destination_lookup",
+            "proxy.endpoints",
+            "proxy_auth.users",
+            "providers.hosts",
+            "audit.retention_days",
+            "audit.redact_pii",
+            "transcripts.tenants",
+            "transcripts.retention_days",
+            "cache.prompt_match",
+            "classifier.categories",
+            "classifier.model_threshold",
+            "jailbreak.alert_threshold",
+            "dlp.actions",
+            "dlp.rules",
+            "block_page.message",
+            "block_page.html_template",
+            "advisory.message",
+            "policy.strategy",
+            "policy.trusted_keys",
+            "policy.bundle_url",
+            "decision_log.url",
+            "decision_log.batch_size",
+            "dns.addresses",
+            "dns.format",
+            "routing.routes",
+            "cost.prices",
+            "budget.monthly_cap_usd",
+        ] {
+            assert!(
+                error.contains(setting),
+                "{} missing from {}",
+                setting,
+                error
+            );
+        }
+
+        let error = YoriConfig::parse("[proxy]\nlisten_addr = \"0.0.0.0:1\"\n", false).unwrap_err();
+        assert!(format!("{:#}", error).contains("listen_addr"));
+    }
+
+    #[test]
+    fn test_load_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("yori.toml");
+        std::fs::write(&path, "[cache]\ndecision_ttl_secs = 0\n").unwrap();
+        let config = YoriConfig::load(&path).unwrap();
+        assert_eq!(config.cache.decision_ttl_secs, 0);
+
+        std::fs::write(&path, "[proxy]\nmode = \"loud\"\n").unwrap();
+        let error = YoriConfig::load(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("proxy.mode"));
+    }
+}
@@ -0,0 +1,395 @@
+//! Explicit (CONNECT) forward-proxy support
+//!
+//! Where firewall redirection isn't possible (a laptop on someone else's
+//! Wi-Fi, a network without OPNsense), devices can point their proxy
+//! settings at YORI instead. They then open every HTTPS connection with
+//!
+//! ```text
+//! CONNECT api.openai.com:443 HTTP/1.1
+//! Host: api.openai.com:443
+//! ```
+//!
+//! For configured LLM endpoints the proxy answers `200`, terminates TLS
+//! (with a certificate for that host, see [`crate::certs`]), and hands
+//! the decrypted stream to the normal request pipeline. Every other host
+//! is tunnelled byte for byte without being looked at.
+//!
+//! With proxy authentication configured (see [`crate::proxyauth`]), the
+//! CONNECT request's Basic credentials, or the client certificate of an
+//! intercepted handshake, name the user behind the connection.
+
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::proxyauth::{Credentials, ProxyAuthenticator};
+
+/// Largest CONNECT request head accepted
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+
+/// How the proxy receives traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterceptionMode {
+    /// Connections redirected by the firewall (rdr) straight to the TLS port
+    Transparent,
+
+    /// Devices configured to use YORI as their HTTP proxy (CONNECT)
+    Explicit,
+
+    /// Both of the above on the same listener
+    Both,
+}
+
+impl InterceptionMode {
+    /// Mode name as used in yori.conf
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InterceptionMode::Transparent => "transparent",
+            InterceptionMode::Explicit => "explicit",
+            InterceptionMode::Both => "both",
+        }
+    }
+
+    /// Whether CONNECT requests are accepted
+    pub fn accepts_connect(&self) -> bool {
+        !matches!(self, InterceptionMode::Transparent)
+    }
+}
+
+impl std::str::FromStr for InterceptionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "transparent" => Ok(InterceptionMode::Transparent),
+            "explicit" | "connect" => Ok(InterceptionMode::Explicit),
+            "both" => Ok(InterceptionMode::Both),
+            other => bail!(
+                "unknown interception mode {:?} (expected transparent, explicit or both)",
+                other
+            ),
+        }
+    }
+}
+
+/// Host and port a client asked to CONNECT to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectTarget {
+    /// Host name (or IP literal, without brackets)
+    pub host: String,
+
+    /// Port (443 for HTTPS)
+    pub port: u16,
+}
+
+impl ConnectTarget {
+    /// "host:port" as used to dial upstream
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Parse the head of a CONNECT request
+pub fn parse_connect(head: &[u8]) -> Result<ConnectTarget> {
+    let head = std::str::from_utf8(head).context("request head is not UTF-8")?;
+    let line = head.lines().next().unwrap_or_default();
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(authority), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("malformed request line {:?}", line);
+    };
+    if !method.eq_ignore_ascii_case("CONNECT") {
+        bail!("expected CONNECT, got {}", method);
+    }
+    if !version.starts_with("HTTP/1.") {
+        bail!("unsupported HTTP version {}", version);
+    }
+
+    let (host, port) = authority
+        .rsplit_once(':')
+        .with_context(|| format!("CONNECT target {:?} has no port", authority))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("invalid port in CONNECT target {:?}", authority))?;
+    if host.is_empty() {
+        bail!("CONNECT target {:?} has no host", authority);
+    }
+    Ok(ConnectTarget {
+        host: host.to_ascii_lowercase(),
+        port,
+    })
+}
+
+/// What happened to an accepted CONNECT request
+pub enum ConnectOutcome {
+    /// TLS terminated by YORI; requests on `stream` go through the normal
+    /// pipeline as if they'd arrived transparently for `target`
+    Intercepted {
+        /// Host the client asked for
+        target: ConnectTarget,
+
+        /// Decrypted client connection
+        stream: Box<TlsStream<TcpStream>>,
+
+        /// User the client authenticated as
+        user: Option<String>,
+    },
+
+    /// Not an LLM endpoint: relayed untouched until either side closed
+    Tunneled {
+        /// Host the client asked for
+        target: ConnectTarget,
+
+        /// Bytes sent client → upstream and upstream → client
+        bytes: (u64, u64),
+
+        /// User the client authenticated as
+        user: Option<String>,
+    },
+}
+
+/// Handle a CONNECT request on a freshly accepted client connection
+///
+/// `intercept` decides (by host) which targets get TLS terminated using
+/// `tls`. With `auth`, clients that give wrong credentials (or none, when
+/// required) are answered `407`.
+pub async fn accept_connect(
+    mut client: TcpStream,
+    tls: Arc<rustls::ServerConfig>,
+    intercept: impl Fn(&str) -> bool,
+    auth: Option<&ProxyAuthenticator>,
+) -> Result<ConnectOutcome> {
+    let head = read_head(&mut client).await?;
+    let target = match parse_connect(&head) {
+        Ok(target) => target,
+        Err(e) => {
+            let _ = client
+                .write_all(b"HTTP/1.1 405 Method Not Allowed\r\nAllow: CONNECT\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            return Err(e);
+        }
+    };
+
+    let intercepted = intercept(&target.host);
+    let mut user = None;
+    if let Some(auth) = auth {
+        match auth.basic(&head) {
+            Credentials::User(name) => user = Some(name),
+            Credentials::Rejected => {
+                let _ = client.write_all(&auth.challenge()).await;
+                bail!("invalid proxy credentials for {}", target.host);
+            }
+            // Intercepted hosts can still authenticate with a certificate
+            Credentials::Missing
+                if auth.required() && !(intercepted && auth.accepts_certificates()) =>
+            {
+                let _ = client.write_all(&auth.challenge()).await;
+                bail!("proxy authentication required for {}", target.host);
+            }
+            Credentials::Missing => {}
+        }
+    }
+
+    if intercepted {
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+        let stream = TlsAcceptor::from(tls)
+            .accept(client)
+            .await
+            .with_context(|| format!("TLS handshake for {} failed", target.host))?;
+        if let (None, Some(auth)) = (&user, auth) {
+            user = auth.certificate_user(stream.get_ref().1.peer_certificates());
+            if user.is_none() && auth.required() {
+                bail!(
+                    "no proxy credentials or client certificate for {}",
+                    target.host
+                );
+            }
+        }
+        return Ok(ConnectOutcome::Intercepted {
+            target,
+            stream: Box::new(stream),
+            user,
+        });
+    }
+
+    let mut upstream = match TcpStream::connect(target.authority()).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            let _ = client
+                .write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            return Err(e).with_context(|| format!("failed to connect to {}", target.authority()));
+        }
+    };
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    let bytes = tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(ConnectOutcome::Tunneled {
+        target,
+        bytes,
+        user,
+    })
+}
+
+/// Read up to and including the blank line ending a request head
+///
+/// Reads one byte at a time so nothing after the head (the client's TLS
+/// hello) is consumed.
+async fn read_head<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) -> Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(256);
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_BYTES {
+            bail!("request head larger than {} bytes", MAX_HEAD_BYTES);
+        }
+        let byte = stream
+            .read_u8()
+            .await
+            .context("client closed before CONNECT")?;
+        head.push(byte);
+    }
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certs::{ca_server_config, test_authority};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_connect() {
+        let target =
+            parse_connect(b"CONNECT API.OpenAI.com:443 HTTP/1.1\r\nHost: api.openai.com\r\n\r\n")
+                .unwrap();
+        assert_eq!(target.host, "api.openai.com");
+        assert_eq!(target.port, 443);
+        assert_eq!(
+            parse_connect(b"CONNECT [::1]:8443 HTTP/1.1\r\n\r\n")
+                .unwrap()
+                .authority(),
+            "[::1]:8443"
+        );
+        assert!(parse_connect(b"GET / HTTP/1.1\r\n\r\n").is_err());
+        assert!(parse_connect(b"CONNECT api.openai.com HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_intercepts_llm_hosts_and_tunnels_the_rest() {
+        let ca = Arc::new(test_authority());
+        let tls = ca_server_config(Arc::clone(&ca), false, None);
+
+        // Upstream for the tunnelled case: echoes one message
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = echo.accept().await.unwrap();
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            socket.write_all(&buf).await.unwrap();
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut outcomes = Vec::new();
+            for _ in 0..2 {
+                let (socket, _) = proxy.accept().await.unwrap();
+                let outcome = accept_connect(
+                    socket,
+                    Arc::clone(&tls),
+                    |host| host == "api.openai.com",
+                    None,
+                )
+                .await
+                .unwrap();
+                outcomes.push(outcome);
+            }
+            outcomes
+        });
+
+        // Tunnelled: bytes pass through untouched
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(format!("CONNECT {} HTTP/1.1\r\n\r\n", echo_addr).as_bytes())
+            .await
+            .unwrap();
+        assert!(read_head(&mut client)
+            .await
+            .unwrap()
+            .starts_with(b"HTTP/1.1 200"));
+        client.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+        drop(client);
+
+        // Intercepted: the client completes TLS trusting only the YORI CA
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(b"CONNECT api.openai.com:443 HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(read_head(&mut client)
+            .await
+            .unwrap()
+            .starts_with(b"HTTP/1.1 200"));
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.ca_der().to_vec()))
+            .unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let server_name = rustls::ServerName::try_from("api.openai.com").unwrap();
+        let _tls = connector.connect(server_name, client).await.unwrap();
+
+        let outcomes = server.await.unwrap();
+        assert!(matches!(
+            &outcomes[0],
+            ConnectOutcome::Tunneled { bytes: (5, 5), .. }
+        ));
+        assert!(matches!(
+            &outcomes[1],
+            ConnectOutcome::Intercepted { target, .. } if target.host == "api.openai.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_required_auth_challenges_anonymous_clients() {
+        let tls = ca_server_config(Arc::new(test_authority()), false, None);
+        let auth = crate::proxyauth::ProxyAuthenticator::new(crate::proxyauth::ProxyAuthConfig {
+            users: [("timmy".to_string(), Default::default())].into(),
+            required: true,
+            ..Default::default()
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = proxy.accept().await.unwrap();
+            accept_connect(socket, tls, |_| true, Some(&auth)).await
+        });
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(b"CONNECT api.openai.com:443 HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let response = read_head(&mut client).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 407"));
+        assert!(server.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Connection limits with fair queuing
+//!
+//! A router appliance has a few thousand file descriptors and every proxied
+//! connection needs two. One device stuck in a reconnect loop (or a script
+//! opening a connection per request) could take them all and lock the rest
+//! of the house out. Each accepted connection takes a slot:
+//!
+//! - at most `max_connections` are served at once; further connections wait
+//!   in a queue for up to `queue_timeout` before being closed
+//! - a client may hold at most `max_per_client` slots, queued ones
+//!   included; beyond that its connections are closed right away
+//! - freed slots go to queued clients in turn (round-robin), not in arrival
+//!   order, so a device with fifty connections waiting doesn't push a
+//!   laptop's one connection to the back of the line
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Default cap on connections served at once
+pub const DEFAULT_MAX_CONNECTIONS: usize = 512;
+
+/// Default cap on one client's connections (served or queued)
+pub const DEFAULT_MAX_CONNECTIONS_PER_CLIENT: usize = 64;
+
+/// Default time a connection waits for a slot
+pub const DEFAULT_QUEUE_TIMEOUT_MS: u64 = 5_000;
+
+/// Connection limit settings (None = unlimited)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimitConfig {
+    /// Connections served at once
+    pub max_connections: Option<usize>,
+
+    /// Connections one client may have served or queued
+    pub max_per_client: Option<usize>,
+
+    /// How long a connection waits for a slot before it's closed
+    pub queue_timeout: Duration,
+}
+
+impl Default for ConnectionLimitConfig {
+    fn default() -> Self {
+        ConnectionLimitConfig {
+            max_connections: Some(DEFAULT_MAX_CONNECTIONS),
+            max_per_client: Some(DEFAULT_MAX_CONNECTIONS_PER_CLIENT),
+            queue_timeout: Duration::from_millis(DEFAULT_QUEUE_TIMEOUT_MS),
+        }
+    }
+}
+
+/// Why a connection was refused a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionRefusal {
+    /// The client already has `max_per_client` connections
+    ClientLimit,
+
+    /// No slot freed up within `queue_timeout`
+    QueueTimeout,
+}
+
+impl ConnectionRefusal {
+    /// Reason name for logs and stats
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionRefusal::ClientLimit => "client_limit",
+            ConnectionRefusal::QueueTimeout => "queue_timeout",
+        }
+    }
+}
+
+/// Current use of the limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionLimitStats {
+    /// Connections holding a slot
+    pub active: usize,
+
+    /// Connections waiting for one
+    pub queued: usize,
+
+    /// Connections refused since start
+    pub refused: u64,
+}
+
+struct Waiter {
+    id: u64,
+    grant: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct State {
+    active: usize,
+    refused: u64,
+    next_id: u64,
+    /// Slots held or waited for, per client
+    clients: HashMap<IpAddr, usize>,
+    /// Waiters per client, and the order clients are served in
+    queues: HashMap<IpAddr, VecDeque<Waiter>>,
+    turns: VecDeque<IpAddr>,
+}
+
+impl State {
+    fn queued(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    fn forget(&mut self, client: IpAddr) {
+        if let Some(count) = self.clients.get_mut(&client) {
+            *count -= 1;
+            if *count == 0 {
+                self.clients.remove(&client);
+            }
+        }
+    }
+
+    /// Hand a freed slot to the next queued client in turn
+    fn hand_over(&mut self) {
+        while let Some(client) = self.turns.pop_front() {
+            let Some(queue) = self.queues.get_mut(&client) else {
+                continue;
+            };
+            let waiter = queue.pop_front();
+            if queue.is_empty() {
+                self.queues.remove(&client);
+            } else {
+                self.turns.push_back(client);
+            }
+            match waiter.map(|w| w.grant.send(())) {
+                Some(Ok(())) => {
+                    self.active += 1;
+                    return;
+                }
+                // The waiter gave up; its client count is already released
+                _ => continue,
+            }
+        }
+    }
+}
+
+struct Inner {
+    config: ConnectionLimitConfig,
+    state: Mutex<State>,
+}
+
+/// Hands out connection slots
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for ConnectionLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionLimiter")
+            .field("config", &self.inner.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Default for ConnectionLimiter {
+    fn default() -> Self {
+        ConnectionLimiter::new(ConnectionLimitConfig::default())
+    }
+}
+
+/// A connection slot, released when dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: ConnectionLimiter,
+    client: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.inner.state.lock().unwrap();
+        state.active -= 1;
+        state.forget(self.client);
+        state.hand_over();
+    }
+}
+
+/// A queued connection; leaves the queue when dropped unless it got a slot
+/// (also when the accepting task is cancelled mid-wait)
+struct Waiting<'a> {
+    limiter: &'a ConnectionLimiter,
+    client: IpAddr,
+    id: u64,
+    granted: oneshot::Receiver<()>,
+    claimed: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.claimed {
+            return;
+        }
+        let mut state = self.limiter.inner.state.lock().unwrap();
+        if self.granted.try_recv().is_ok() {
+            // Handed a slot just as the wait ended: pass it on
+            state.active -= 1;
+            state.forget(self.client);
+            state.hand_over();
+            return;
+        }
+        if let Some(queue) = state.queues.get_mut(&self.client) {
+            queue.retain(|waiter| waiter.id != self.id);
+            if queue.is_empty() {
+                state.queues.remove(&self.client);
+                state.turns.retain(|client| *client != self.client);
+            }
+        }
+        state.forget(self.client);
+    }
+}
+
+impl ConnectionLimiter {
+    /// Create limits from configuration
+    pub fn new(config: ConnectionLimitConfig) -> Self {
+        ConnectionLimiter {
+            inner: Arc::new(Inner {
+                config,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Wait for a slot for a connection from `client`
+    pub async fn acquire(&self, client: IpAddr) -> Result<ConnectionPermit, ConnectionRefusal> {
+        let config = &self.inner.config;
+        let (id, granted) = {
+            let mut state = self.inner.state.lock().unwrap();
+            let held = state.clients.get(&client).copied().unwrap_or(0);
+            if config.max_per_client.is_some_and(|max| held >= max) {
+                state.refused += 1;
+                return Err(ConnectionRefusal::ClientLimit);
+            }
+            *state.clients.entry(client).or_default() += 1;
+            if config.max_connections.is_none_or(|max| state.active < max) {
+                state.active += 1;
+                return Ok(self.permit(client));
+            }
+
+            let (grant, granted) = oneshot::channel();
+            state.next_id += 1;
+            let id = state.next_id;
+            let queue = state.queues.entry(client).or_default();
+            queue.push_back(Waiter { id, grant });
+            if queue.len() == 1 {
+                state.turns.push_back(client);
+            }
+            (id, granted)
+        };
+
+        let mut waiting = Waiting {
+            limiter: self,
+            client,
+            id,
+            granted,
+            claimed: false,
+        };
+        if let Ok(Ok(())) = tokio::time::timeout(config.queue_timeout, &mut waiting.granted).await {
+            waiting.claimed = true;
+            return Ok(self.permit(client));
+        }
+        drop(waiting);
+        self.inner.state.lock().unwrap().refused += 1;
+        Err(ConnectionRefusal::QueueTimeout)
+    }
+
+    fn permit(&self, client: IpAddr) -> ConnectionPermit {
+        ConnectionPermit {
+            limiter: self.clone(),
+            client,
+        }
+    }
+
+    /// Slots in use, queued connections and refusals so far
+    pub fn stats(&self) -> ConnectionLimitStats {
+        let state = self.inner.state.lock().unwrap();
+        ConnectionLimitStats {
+            active: state.active,
+            queued: state.queued(),
+            refused: state.refused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[tokio::test]
+    async fn test_client_limit_and_timeout() {
+        let limiter = ConnectionLimiter::new(ConnectionLimitConfig {
+            max_connections: Some(2),
+            max_per_client: Some(1),
+            queue_timeout: Duration::from_millis(20),
+        });
+        let tv = limiter.acquire(ip(20)).await.unwrap();
+        assert_eq!(
+            limiter.acquire(ip(20)).await.unwrap_err(),
+            ConnectionRefusal::ClientLimit
+        );
+        let _laptop = limiter.acquire(ip(30)).await.unwrap();
+        assert_eq!(
+            limiter.acquire(ip(40)).await.unwrap_err(),
+            ConnectionRefusal::QueueTimeout
+        );
+        assert_eq!(
+            limiter.stats(),
+            ConnectionLimitStats {
+                active: 2,
+                queued: 0,
+                refused: 2
+            }
+        );
+
+        drop(tv);
+        assert!(limiter.acquire(ip(20)).await.is_ok());
+        assert_eq!(limiter.stats().active, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fair_queuing() {
+        let limiter = ConnectionLimiter::new(ConnectionLimitConfig {
+            max_connections: Some(1),
+            max_per_client: None,
+            queue_timeout: Duration::from_secs(5),
+        });
+        let first = limiter.acquire(ip(20)).await.unwrap();
+        let (order, mut served) = tokio::sync::mpsc::unbounded_channel();
+        for (queued, client) in [20, 20, 20, 30].into_iter().enumerate() {
+            let waiter = limiter.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                let permit = waiter.acquire(ip(client)).await.unwrap();
+                order.send(client).unwrap();
+                drop(permit);
+            });
+            // Queue them in this order
+            while limiter.stats().queued <= queued {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(first);
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(served.recv().await.unwrap());
+        }
+        // The other device goes second, not behind the whole backlog
+        assert_eq!(seen, [20, 30, 20, 20]);
+        assert_eq!(limiter.stats().active, 0);
+    }
+}
@@ -0,0 +1,303 @@
+//! Per-request cost estimation
+//!
+//! Token counts from the provider's usage report are priced with a
+//! per-model table (USD per million input and output tokens), so the
+//! dashboard can answer "how much did ChatGPT cost us this month" from the
+//! audit log alone. Providers that report the cost themselves (OpenRouter)
+//! are taken at their word.
+//!
+//! The built-in table covers common OpenAI, Anthropic, Google, Mistral and
+//! DeepSeek models at list prices; entries in `[cost.prices]` override or
+//! extend it:
+//!
+//! ```toml
+//! [cost.prices]
+//! "gpt-4o*" = { input = 2.50, output = 10.00 }
+//! "my-azure-deployment" = { input = 0.15, output = 0.60 }
+//! ```
+//!
+//! Patterns are matched like model lists (see [`crate::models`]): exact
+//! names or `*` prefixes, ignoring case, the most specific entry winning.
+//! Estimates are exactly that: cached-input discounts, batch pricing and
+//! free tiers are not modelled.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::promptcache::model_matches;
+use crate::providers::ParsedUsage;
+
+/// List prices in USD per million tokens, as of early 2025
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o*", 2.50, 10.00),
+    ("gpt-4o-mini*", 0.15, 0.60),
+    ("gpt-4.1*", 2.00, 8.00),
+    ("gpt-4.1-mini*", 0.40, 1.60),
+    ("gpt-4.1-nano*", 0.10, 0.40),
+    ("gpt-4-turbo*", 10.00, 30.00),
+    ("gpt-3.5-turbo*", 0.50, 1.50),
+    ("o1*", 15.00, 60.00),
+    ("o1-mini*", 1.10, 4.40),
+    ("o3-mini*", 1.10, 4.40),
+    ("claude-3-opus*", 15.00, 75.00),
+    ("claude-opus-4*", 15.00, 75.00),
+    ("claude-3-5-sonnet*", 3.00, 15.00),
+    ("claude-3-7-sonnet*", 3.00, 15.00),
+    ("claude-sonnet-4*", 3.00, 15.00),
+    ("claude-3-5-haiku*", 0.80, 4.00),
+    ("claude-3-haiku*", 0.25, 1.25),
+    ("gemini-1.5-pro*", 1.25, 5.00),
+    ("gemini-1.5-flash*", 0.075, 0.30),
+    ("gemini-2.0-flash*", 0.10, 0.40),
+    ("mistral-large*", 2.00, 6.00),
+    ("mistral-small*", 0.20, 0.60),
+    ("deepseek-chat*", 0.27, 1.10),
+    ("deepseek-reasoner*", 0.55, 2.19),
+];
+
+/// Price of one model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    /// Input (prompt) tokens
+    pub input: f64,
+
+    /// Output (completion) tokens
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// Cost of one request's usage
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input + completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// Model pattern -> price
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    prices: BTreeMap<String, ModelPrice>,
+}
+
+impl Default for PricingTable {
+    /// The built-in list prices
+    fn default() -> Self {
+        PricingTable {
+            prices: BUILTIN_PRICES
+                .iter()
+                .map(|(pattern, input, output)| {
+                    (
+                        pattern.to_string(),
+                        ModelPrice {
+                            input: *input,
+                            output: *output,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PricingTable {
+    /// Table without any prices
+    pub fn empty() -> Self {
+        PricingTable {
+            prices: BTreeMap::new(),
+        }
+    }
+
+    /// Add or replace prices, failing on negative or non-finite ones
+    pub fn with_prices(mut self, prices: &BTreeMap<String, ModelPrice>) -> Result<Self> {
+        for (pattern, price) in prices {
+            if ![price.input, price.output]
+                .iter()
+                .all(|v| v.is_finite() && *v >= 0.0)
+            {
+                bail!("price for {:?} must be a non-negative number", pattern);
+            }
+            self.prices.insert(pattern.clone(), *price);
+        }
+        Ok(self)
+    }
+
+    /// Price for a model: an exact entry first, else the longest matching
+    /// pattern
+    ///
+    /// Provider prefixes are ignored ("openai/gpt-4o" and Bedrock's
+    /// "anthropic.claude-3-5-haiku-..." price as the bare model).
+    pub fn price_for(&self, model: &str) -> Option<ModelPrice> {
+        let model = bare_model(model);
+        self.prices
+            .iter()
+            .filter(|(pattern, _)| model_matches(pattern, model))
+            .max_by_key(|(pattern, _)| (!pattern.ends_with('*'), pattern.len()))
+            .map(|(_, price)| *price)
+    }
+
+    /// Estimated cost of a response in USD
+    ///
+    /// A cost reported by the provider wins; otherwise the usage is priced
+    /// by model. None when there's no usage or the model has no price.
+    pub fn estimate(&self, model: Option<&str>, usage: &ParsedUsage) -> Option<f64> {
+        if let Some(cost) = usage.cost_usd {
+            return Some(cost);
+        }
+        let price = self.price_for(model?)?;
+        Some(price.cost(usage.prompt_tokens, usage.completion_tokens))
+    }
+}
+
+/// Model name without an aggregator ("vendor/") or Bedrock ("vendor.")
+/// prefix
+fn bare_model(model: &str) -> &str {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let Some(name_start) = model.find('-') else {
+        return model;
+    };
+    match model[..name_start].rfind('.') {
+        Some(dot) => &model[dot + 1..],
+        None => model,
+    }
+}
+
+/// How [`crate::audit::AuditLogger::cost_summary`] groups requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostGrouping {
+    /// Device owner (falling back to the device, then the client IP)
+    User,
+
+    /// Device name (falling back to the client IP)
+    Device,
+
+    /// Model that served the request
+    Model,
+
+    /// UTC day
+    Day,
+}
+
+impl CostGrouping {
+    /// Grouping name as used by the admin API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CostGrouping::User => "user",
+            CostGrouping::Device => "device",
+            CostGrouping::Model => "model",
+            CostGrouping::Day => "day",
+        }
+    }
+
+    /// SQL expression over `audit_events` for the group key
+    pub(crate) fn sql_key(&self) -> &'static str {
+        match self {
+            CostGrouping::User => "COALESCE(client_user, client_device, client_ip)",
+            CostGrouping::Device => "COALESCE(client_device, client_ip)",
+            CostGrouping::Model => {
+                "COALESCE(response_model, rewritten_model, requested_model, 'unknown')"
+            }
+            CostGrouping::Day => "substr(timestamp, 1, 10)",
+        }
+    }
+}
+
+impl std::str::FromStr for CostGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "user" => Ok(CostGrouping::User),
+            "device" => Ok(CostGrouping::Device),
+            "model" => Ok(CostGrouping::Model),
+            "day" => Ok(CostGrouping::Day),
+            _ => bail!(
+                "unknown grouping {:?} (expected user, device, model or day)",
+                s
+            ),
+        }
+    }
+}
+
+/// Cost of one group of requests
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostSummary {
+    /// Group key (user, device, model or day)
+    pub key: String,
+
+    /// UTC day ("2026-01-31") when split by day as well
+    pub day: Option<String>,
+
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub response_tokens: u64,
+
+    /// Estimated cost in USD of the priced requests
+    pub cost_usd: f64,
+
+    /// Requests with tokens but no price (unknown model)
+    pub unpriced: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_specific_price_wins() {
+        let table = PricingTable::default()
+            .with_prices(&BTreeMap::from([(
+                "family-gpt".to_string(),
+                ModelPrice {
+                    input: 1.0,
+                    output: 2.0,
+                },
+            )]))
+            .unwrap();
+        assert_eq!(table.price_for("gpt-4o-2024-08-06").unwrap().input, 2.50);
+        assert_eq!(table.price_for("GPT-4o-mini").unwrap().input, 0.15);
+        assert_eq!(table.price_for("openai/gpt-4o-mini").unwrap().output, 0.60);
+        assert_eq!(
+            table
+                .price_for("anthropic.claude-3-5-haiku-20241022-v1:0")
+                .unwrap()
+                .input,
+            0.80
+        );
+        assert_eq!(table.price_for("family-gpt").unwrap().output, 2.0);
+        assert_eq!(table.price_for("llama3.2"), None);
+
+        let negative = BTreeMap::from([(
+            "x".to_string(),
+            ModelPrice {
+                input: -1.0,
+                output: 0.0,
+            },
+        )]);
+        assert!(PricingTable::empty().with_prices(&negative).is_err());
+    }
+
+    #[test]
+    fn test_estimate_prefers_reported_cost() {
+        let table = PricingTable::default();
+        let usage = ParsedUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+            cost_usd: None,
+        };
+        assert_eq!(table.estimate(Some("gpt-4o"), &usage), Some(7.5));
+        assert_eq!(table.estimate(Some("llama3.2"), &usage), None);
+        assert_eq!(table.estimate(None, &usage), None);
+
+        let reported = ParsedUsage {
+            cost_usd: Some(0.00021),
+            ..usage
+        };
+        assert_eq!(table.estimate(None, &reported), Some(0.00021));
+        assert_eq!(
+            "Device".parse::<CostGrouping>().unwrap(),
+            CostGrouping::Device
+        );
+        assert!("household".parse::<CostGrouping>().is_err());
+    }
+}
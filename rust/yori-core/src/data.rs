@@ -0,0 +1,157 @@
+//! Data documents loaded alongside policies
+//!
+//! Policies often need facts that aren't part of the request: which
+//! devices belong to whom, allowlisted sites, per-child schedules. Keeping
+//! those in JSON or YAML next to the `.rego` files lets them be edited
+//! without touching policy code. Each file is mounted under `data.` by its
+//! path, following the OPA bundle layout:
+//!
+//! ```text
+//! policies/devices.json          → data.devices
+//! policies/family/schedules.yaml → data.family.schedules
+//! policies/family/data.json      → data.family   (merged into)
+//! policies/data.yaml             → data          (merged into)
+//! ```
+//!
+//! ```rego
+//! package yori.devices
+//!
+//! deny contains "unknown device" if {
+//!     not data.devices[input.client_ip]
+//! }
+//! ```
+//!
+//! Data files are loaded (and hot-reloaded) together with the policies, so
+//! a reload never pairs new policies with stale data or the other way round.
+//! Files named `*_test.yaml` hold policy test cases (see
+//! [`crate::policytest`]) and are not mounted.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::policytest::is_test_file;
+
+/// File extensions read as data documents
+pub(crate) const DATA_EXTENSIONS: &[&str] = &["json", "yaml", "yml"];
+
+/// File stem that merges into its directory instead of adding a key
+const MERGE_STEM: &str = "data";
+
+/// Read every data file under `dir` (recursively) as `(relative path,
+/// document mounted at its path)`, sorted by path
+pub(crate) fn read_data_dir(dir: &Path) -> Result<Vec<(String, Value)>> {
+    let mut documents = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        let entries = fs::read_dir(&current)
+            .with_context(|| format!("failed to read data directory {}", current.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+                continue;
+            };
+            if !DATA_EXTENSIONS.contains(&extension) || is_test_file(&path) {
+                continue;
+            }
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read data file {}", path.display()))?;
+            let value: Value = if extension == "json" {
+                serde_json::from_str(&text)
+                    .with_context(|| format!("invalid JSON in {}", path.display()))?
+            } else {
+                serde_yaml::from_str(&text)
+                    .with_context(|| format!("invalid YAML in {}", path.display()))?
+            };
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let document = mount(relative, value)
+                .with_context(|| format!("cannot mount {}", path.display()))?;
+            documents.push((relative.to_string_lossy().replace('\\', "/"), document));
+        }
+    }
+    documents.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(documents)
+}
+
+/// Nest `value` under the keys named by a data file's relative path
+fn mount(relative: &Path, value: Value) -> Result<Value> {
+    let mut keys: Vec<String> = relative
+        .parent()
+        .into_iter()
+        .flat_map(|p| p.components())
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let stem = relative
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if stem != MERGE_STEM {
+        keys.push(stem);
+    }
+    if keys.is_empty() && !value.is_object() {
+        bail!("a top-level {}.* file must hold an object", MERGE_STEM);
+    }
+
+    Ok(keys.into_iter().rev().fold(value, |inner, key| {
+        let mut map = Map::new();
+        map.insert(key, inner);
+        Value::Object(map)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_files_mount_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("family")).unwrap();
+        fs::write(
+            dir.path().join("devices.json"),
+            r#"{"192.168.1.50": "sam-ipad"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("family/schedules.yaml"),
+            "sam:\n  bedtime: \"21:00\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("family/data.yml"), "owner: alex\n").unwrap();
+        fs::write(dir.path().join("bedtime.rego"), "package yori.bedtime").unwrap();
+        fs::write(dir.path().join("bedtime_test.yaml"), "[]\n").unwrap();
+
+        let documents = read_data_dir(dir.path()).unwrap();
+        let paths: Vec<&str> = documents.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["devices.json", "family/data.yml", "family/schedules.yaml"]
+        );
+        assert_eq!(
+            documents[0].1,
+            json!({"devices": {"192.168.1.50": "sam-ipad"}})
+        );
+        assert_eq!(documents[1].1, json!({"family": {"owner": "alex"}}));
+        assert_eq!(
+            documents[2].1,
+            json!({"family": {"schedules": {"sam": {"bedtime": "21:00"}}}})
+        );
+    }
+
+    #[test]
+    fn test_invalid_documents_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("data.json"), "[1, 2]").unwrap();
+        assert!(read_data_dir(dir.path()).is_err());
+
+        fs::write(dir.path().join("data.json"), "{").unwrap();
+        let error = format!("{:#}", read_data_dir(dir.path()).unwrap_err());
+        assert!(error.contains("invalid JSON"), "{}", error);
+    }
+}
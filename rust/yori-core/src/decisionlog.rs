@@ -0,0 +1,445 @@
+//! Decision log shipping
+//!
+//! An MSP or a parent running several boxes may want every policy decision
+//! in one place. The [`DecisionLogShipper`] follows the live-tail channel,
+//! turns each request decision into a record in OPA's decision log format
+//! and POSTs them in batches, gzip-compressed JSON arrays by default, so
+//! an OPA-compatible decision log service (or any HTTPS collector) can
+//! ingest them:
+//!
+//! ```json
+//! {"decision_id": "…", "timestamp": "2026-03-01T21:04:05Z",
+//!  "path": "yori/bedtime", "labels": {"app": "yori", "tenant": "default"},
+//!  "input": {"client_ip": "192.168.1.50", "endpoint": "api.openai.com"},
+//!  "result": {"allow": false, "policy": "bedtime", "reason": "Past bedtime"}}
+//! ```
+//!
+//! Records are buffered in memory while the uplink is down and retried
+//! with exponential backoff; once `max_buffered` records are waiting the
+//! oldest are dropped (and counted). Prompt previews are left out unless
+//! `include_prompt` is set.
+
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsConnector;
+
+use crate::alerts::{exchange, load_tls, parse_url};
+use crate::audit::{AuditEvent, AuditEventType};
+use crate::livetail::{LiveEvent, LiveTail};
+
+/// Default records per POST
+pub const DEFAULT_DECISION_BATCH: usize = 100;
+
+/// Default records kept while the collector is unreachable
+pub const DEFAULT_DECISION_BUFFER: usize = 10_000;
+
+/// Longest wait between retries while the collector is unreachable
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Decision log shipping configuration
+#[derive(Debug, Clone)]
+pub struct DecisionLogConfig {
+    /// Collector URL the batches are POSTed to (e.g. an OPA decision log
+    /// service's `/logs` endpoint)
+    pub url: String,
+
+    /// Sent as `Authorization: Bearer <token>`
+    pub token: Option<String>,
+
+    /// Gzip the batches (`Content-Encoding: gzip`, as OPA sends them)
+    pub compress: bool,
+
+    /// Records per POST at most
+    pub batch_size: usize,
+
+    /// Time between flushes of a partial batch
+    pub flush_interval: Duration,
+
+    /// Records kept while the collector is unreachable; the oldest are
+    /// dropped beyond this
+    pub max_buffered: usize,
+
+    /// Include the prompt preview in each record's input
+    pub include_prompt: bool,
+
+    /// PEM bundle of trusted CAs for https collectors (None = system bundle)
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Connect-and-post timeout per batch
+    pub timeout: Duration,
+}
+
+impl Default for DecisionLogConfig {
+    fn default() -> Self {
+        DecisionLogConfig {
+            url: String::new(),
+            token: None,
+            compress: true,
+            batch_size: DEFAULT_DECISION_BATCH,
+            flush_interval: Duration::from_secs(10),
+            max_buffered: DEFAULT_DECISION_BUFFER,
+            include_prompt: false,
+            ca_bundle: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Decision log record for an audit event, if it records a policy decision
+pub fn decision_record(event: &AuditEvent, include_prompt: bool) -> Option<Value> {
+    if !matches!(
+        event.event_type,
+        AuditEventType::Request | AuditEventType::RequestBlocked | AuditEventType::ResponseBlocked
+    ) {
+        return None;
+    }
+    let policy = event.policy_name.as_deref()?;
+    let mut input = json!({
+        "client_ip": event.client_ip,
+        "client_device": event.client_device,
+        "client_user": event.client_user,
+        "endpoint": event.endpoint,
+        "method": event.http_method,
+        "path": event.http_path,
+        "model": event.requested_model,
+        "user_agent": event.user_agent,
+    });
+    if include_prompt {
+        input["prompt_preview"] = event.prompt_preview.clone().into();
+    }
+    Some(json!({
+        "decision_id": event.request_id,
+        "timestamp": event.timestamp.to_rfc3339(),
+        "path": format!("yori/{}", policy.replace('.', "/")),
+        "labels": {
+            "app": "yori",
+            "version": env!("CARGO_PKG_VERSION"),
+            "tenant": event.tenant,
+        },
+        "input": input,
+        "result": {
+            "allow": !matches!(event.policy_result.as_deref(), Some("block")),
+            "decision": event.policy_result,
+            "policy": policy,
+            "reason": event.policy_reason,
+            "location": event.policy_location,
+            "phase": match event.event_type {
+                AuditEventType::ResponseBlocked => "response",
+                _ => "request",
+            },
+        },
+    }))
+}
+
+/// Batches decision records and ships them to a collector
+pub struct DecisionLogShipper {
+    config: DecisionLogConfig,
+    tls: Option<TlsConnector>,
+    buffer: Mutex<VecDeque<Value>>,
+    shipped: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl DecisionLogShipper {
+    /// Create a shipper, loading the CA bundle for an https collector
+    pub fn new(config: DecisionLogConfig) -> Result<Self> {
+        let url = parse_url(&config.url).context("invalid decision log URL")?;
+        let tls = match url.tls {
+            true => Some(load_tls(config.ca_bundle.as_ref())?),
+            false => None,
+        };
+        Ok(DecisionLogShipper {
+            config,
+            tls,
+            buffer: Mutex::new(VecDeque::new()),
+            shipped: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Queue the event's decision, returning whether it had one
+    pub fn record(&self, event: &AuditEvent) -> bool {
+        let Some(record) = decision_record(event, self.config.include_prompt) else {
+            return false;
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() >= self.config.max_buffered.max(1) {
+            buffer.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        buffer.push_back(record);
+        true
+    }
+
+    /// Records waiting to be shipped
+    pub fn pending(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    /// Records shipped so far
+    pub fn shipped(&self) -> u64 {
+        self.shipped.load(Ordering::Relaxed)
+    }
+
+    /// Records dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Ship everything buffered, one batch at a time, returning how many
+    /// records went out
+    ///
+    /// Stops at the first failed batch, which stays buffered for the next
+    /// attempt.
+    pub async fn flush(&self) -> Result<usize> {
+        let mut sent = 0;
+        loop {
+            let batch: Vec<Value> = {
+                let mut buffer = self.buffer.lock().unwrap();
+                let count = buffer.len().min(self.config.batch_size.max(1));
+                buffer.drain(..count).collect()
+            };
+            if batch.is_empty() {
+                return Ok(sent);
+            }
+            let result = match tokio::time::timeout(self.config.timeout, self.post(&batch)).await {
+                Ok(Ok(status)) if (200..300).contains(&status) => Ok(()),
+                Ok(Ok(status)) => Err(anyhow::anyhow!(
+                    "decision log collector answered HTTP {}",
+                    status
+                )),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(anyhow::anyhow!("decision log upload timed out")),
+            };
+            if let Err(e) = result {
+                self.requeue(batch);
+                return Err(e);
+            }
+            self.shipped
+                .fetch_add(batch.len() as u64, Ordering::Relaxed);
+            sent += batch.len();
+        }
+    }
+
+    /// Put a failed batch back in front of anything queued since, dropping
+    /// the oldest records if that overfills the buffer
+    fn requeue(&self, batch: Vec<Value>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        for record in batch.into_iter().rev() {
+            buffer.push_front(record);
+        }
+        while buffer.len() > self.config.max_buffered.max(1) {
+            buffer.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Queue decisions published to `tail` and ship them until the channel
+    /// closes
+    pub fn watch(self: Arc<Self>, tail: &LiveTail) -> tokio::task::JoinHandle<()> {
+        let mut receiver = tail.subscribe();
+        tokio::spawn(async move {
+            let mut failures = 0u32;
+            let mut next_flush = tokio::time::Instant::now() + self.config.flush_interval;
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Ok(event) => {
+                            if let LiveEvent::Audit(event) = event.as_ref() {
+                                self.record(event);
+                            }
+                            // A full batch goes out right away unless backing off
+                            if failures > 0 || self.pending() < self.config.batch_size {
+                                continue;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            tracing::warn!("Decision log fell behind; {} events not shipped", missed);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            if let Err(e) = self.flush().await {
+                                tracing::warn!("Final decision log upload failed: {:#}", e);
+                            }
+                            return;
+                        }
+                    },
+                    _ = tokio::time::sleep_until(next_flush) => {}
+                }
+
+                match self.flush().await {
+                    Ok(_) => failures = 0,
+                    Err(e) => {
+                        failures += 1;
+                        tracing::warn!(
+                            "Decision log upload to {} failed ({} records buffered): {:#}",
+                            self.config.url,
+                            self.pending(),
+                            e
+                        );
+                    }
+                }
+                let backoff = self
+                    .config
+                    .flush_interval
+                    .saturating_mul(2u32.saturating_pow(failures.min(16)))
+                    .min(MAX_BACKOFF.max(self.config.flush_interval));
+                next_flush = tokio::time::Instant::now() + backoff;
+            }
+        })
+    }
+
+    async fn post(&self, batch: &[Value]) -> Result<u16> {
+        let url = parse_url(&self.config.url)?;
+        let mut body = serde_json::to_vec(batch)?;
+        if self.config.compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&body)?;
+            body = encoder.finish()?;
+        }
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: yori-decision-log\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            url.path,
+            url.host,
+            body.len()
+        );
+        if self.config.compress {
+            head.push_str("Content-Encoding: gzip\r\n");
+        }
+        if let Some(token) = &self.config.token {
+            head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        head.push_str("\r\n");
+        let mut request = head.into_bytes();
+        request.extend_from_slice(&body);
+
+        let tcp = TcpStream::connect((url.host.as_str(), url.port))
+            .await
+            .with_context(|| format!("failed to connect to {}:{}", url.host, url.port))?;
+        let Some(connector) = &self.tls else {
+            return exchange(tcp, &request).await;
+        };
+        let server_name = ServerName::try_from(url.host.as_str())
+            .with_context(|| format!("invalid host name {}", url.host))?;
+        let tls = connector.connect(server_name, tcp).await?;
+        exchange(tls, &request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{test_request, RequestContext};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn decided(event_type: AuditEventType, result: &str) -> AuditEvent {
+        let ctx = RequestContext {
+            client_device: Some("sam-ipad".to_string()),
+            ..test_request()
+        };
+        AuditEvent::from_request(event_type, &ctx)
+            .with_policy("bedtime", result, "past bedtime")
+            .with_prompt("help me with my essay", 200)
+    }
+
+    /// Accept one upload, answer `status` and return the decoded batch
+    async fn accept_batch(listener: &TcpListener, status: &str) -> Vec<Value> {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        let (body_start, length) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the body arrived");
+            received.extend_from_slice(&buf[..n]);
+            if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&received[..end]).to_string();
+                assert!(head.contains("Content-Encoding: gzip"));
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .and_then(|length| length.parse::<usize>().ok())
+                    .unwrap();
+                break (end + 4, length);
+            }
+        };
+        while received.len() < body_start + length {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        stream
+            .write_all(format!("HTTP/1.1 {}\r\n\r\n", status).as_bytes())
+            .await
+            .unwrap();
+        let mut json = String::new();
+        GzDecoder::new(&received[body_start..])
+            .read_to_string(&mut json)
+            .unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_decision_record_format() {
+        let record =
+            decision_record(&decided(AuditEventType::RequestBlocked, "block"), false).unwrap();
+        assert_eq!(record["path"], "yori/bedtime");
+        assert_eq!(record["labels"]["tenant"], "default");
+        assert_eq!(record["input"]["client_device"], "sam-ipad");
+        assert_eq!(record["input"].get("prompt_preview"), None);
+        assert_eq!(record["result"]["allow"], false);
+        assert_eq!(record["result"]["reason"], "past bedtime");
+
+        let record = decision_record(&decided(AuditEventType::Request, "allow"), true).unwrap();
+        assert_eq!(record["result"]["allow"], true);
+        assert_eq!(record["input"]["prompt_preview"], "help me with my essay");
+        assert!(decision_record(&decided(AuditEventType::Response, "allow"), false).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batches_kept_until_accepted() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let shipper = DecisionLogShipper::new(DecisionLogConfig {
+            url: format!("http://{}/logs", listener.local_addr().unwrap()),
+            batch_size: 2,
+            max_buffered: 3,
+            ..DecisionLogConfig::default()
+        })
+        .unwrap();
+        for result in ["allow", "block", "allow", "block"] {
+            assert!(shipper.record(&decided(AuditEventType::Request, result)));
+        }
+        assert_eq!((shipper.pending(), shipper.dropped()), (3, 1));
+
+        // The collector is down: nothing leaves the buffer
+        let (refused, flushed) = tokio::join!(
+            accept_batch(&listener, "503 Service Unavailable"),
+            shipper.flush()
+        );
+        assert!(flushed.is_err());
+        assert_eq!(refused.len(), 2);
+        assert_eq!(shipper.pending(), 3);
+
+        let accept_all = async {
+            let first = accept_batch(&listener, "204 No Content").await;
+            let second = accept_batch(&listener, "204 No Content").await;
+            first.len() + second.len()
+        };
+        let (accepted, flushed) = tokio::join!(accept_all, shipper.flush());
+        assert_eq!((accepted, flushed.unwrap()), (3, 3));
+        assert_eq!((shipper.pending(), shipper.shipped()), (0, 3));
+    }
+}
@@ -0,0 +1,255 @@
+//! Policy decision cache
+//!
+//! A chatty client sends the same request shape (user, endpoint, model)
+//! many times a minute. Decisions are cached by the SHA-256 of the
+//! evaluation input, so an identical input inside the TTL is answered
+//! without running the policies again. Anything that changes the input
+//! (time of day, quota used, device tags) changes the key, and the cache is
+//! emptied whenever the policy set is reloaded.
+//!
+//! Denials get their own, shorter TTL: a blocked app retrying in a loop is
+//! still answered from the cache, while a parent who loosens a policy
+//! doesn't wait long for the old denial to run out.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default time a decision stays cached
+pub const DEFAULT_DECISION_TTL_SECS: u64 = 5;
+
+/// Default time a denial stays cached
+pub const DEFAULT_DECISION_DENY_TTL_SECS: u64 = 1;
+
+/// Default maximum number of cached decisions
+pub const DEFAULT_DECISION_CACHE_ENTRIES: usize = 1024;
+
+/// SHA-256 of a canonical evaluation input
+pub type InputHash = [u8; 32];
+
+/// Hash an evaluation input (object keys are serialized in sorted order)
+pub fn input_hash(input: &serde_json::Value) -> InputHash {
+    Sha256::digest(input.to_string().as_bytes()).into()
+}
+
+/// Hit/miss counters of a decision cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DecisionCacheStats {
+    /// Decisions currently cached
+    pub entries: usize,
+
+    /// Evaluations answered from the cache
+    pub hits: u64,
+
+    /// Evaluations that ran the policies
+    pub misses: u64,
+}
+
+impl DecisionCacheStats {
+    /// Hits as a fraction of all lookups (0.0 before any lookup)
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Whether a decision denies the request (`allow` is false)
+fn is_denial(decision: &serde_json::Value) -> bool {
+    decision.get("allow") == Some(&serde_json::Value::Bool(false))
+}
+
+/// Recent decisions keyed by input hash
+#[derive(Debug)]
+pub struct DecisionCache {
+    ttl: Duration,
+    deny_ttl: Duration,
+    max_entries: usize,
+    /// Decisions with the time they expire
+    entries: Mutex<HashMap<InputHash, (Instant, serde_json::Value)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for DecisionCache {
+    fn default() -> Self {
+        DecisionCache::new(
+            Duration::from_secs(DEFAULT_DECISION_TTL_SECS),
+            DEFAULT_DECISION_CACHE_ENTRIES,
+        )
+    }
+}
+
+impl DecisionCache {
+    /// Create a cache; a zero `ttl` or `max_entries` disables it
+    ///
+    /// Denials are kept for `ttl` or [`DEFAULT_DECISION_DENY_TTL_SECS`],
+    /// whichever is shorter (see [`DecisionCache::with_deny_ttl`]).
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        DecisionCache {
+            ttl,
+            deny_ttl: ttl.min(Duration::from_secs(DEFAULT_DECISION_DENY_TTL_SECS)),
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep denials for `deny_ttl` instead (zero leaves them uncached)
+    pub fn with_deny_ttl(mut self, deny_ttl: Duration) -> Self {
+        self.deny_ttl = deny_ttl;
+        self
+    }
+
+    /// How long an allowing decision stays cached
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// How long a denial stays cached
+    pub fn deny_ttl(&self) -> Duration {
+        self.deny_ttl
+    }
+
+    /// Maximum number of cached decisions
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    fn enabled(&self) -> bool {
+        !(self.ttl.is_zero() && self.deny_ttl.is_zero()) && self.max_entries > 0
+    }
+
+    /// Look up a decision, counting the hit or miss
+    pub fn get(&self, key: &InputHash, now: Instant) -> Option<serde_json::Value> {
+        if !self.enabled() {
+            return None;
+        }
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|(expires_at, _)| now < *expires_at)
+            .map(|(_, decision)| decision.clone());
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    /// Cache a decision for the TTL of its type, evicting expired (or else
+    /// the soonest to expire) entries when full
+    pub fn insert(&self, key: InputHash, decision: serde_json::Value, now: Instant) {
+        let ttl = if is_denial(&decision) {
+            self.deny_ttl
+        } else {
+            self.ttl
+        };
+        if ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, (expires_at, _)| now < *expires_at);
+            if entries.len() >= self.max_entries {
+                if let Some(soonest) = entries
+                    .iter()
+                    .min_by_key(|(_, (expires_at, _))| *expires_at)
+                    .map(|(k, _)| *k)
+                {
+                    entries.remove(&soonest);
+                }
+            }
+        }
+        entries.insert(key, (now + ttl, decision));
+    }
+
+    /// Drop every cached decision (counters are kept)
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Current entry count and hit/miss counters
+    pub fn stats(&self) -> DecisionCacheStats {
+        DecisionCacheStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hits_within_ttl_only() {
+        let cache = DecisionCache::new(Duration::from_secs(5), 16);
+        let key = input_hash(&json!({"user": "alice", "endpoint": "api.openai.com"}));
+        let t0 = Instant::now();
+
+        assert_eq!(cache.get(&key, t0), None);
+        cache.insert(key, json!({"allow": true}), t0);
+        assert_eq!(
+            cache.get(&key, t0 + Duration::from_secs(2)),
+            Some(json!({"allow": true}))
+        );
+        assert_eq!(cache.get(&key, t0 + Duration::from_secs(6)), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_full_cache_evicts_oldest() {
+        let cache = DecisionCache::new(Duration::from_secs(60), 2);
+        let t0 = Instant::now();
+        let keys: Vec<_> = (0..3).map(|i| input_hash(&json!({"n": i}))).collect();
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(*key, json!(i), t0 + Duration::from_secs(i as u64));
+        }
+
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.get(&keys[0], t0), None);
+        assert_eq!(
+            cache.get(&keys[2], t0 + Duration::from_secs(3)),
+            Some(json!(2))
+        );
+    }
+
+    #[test]
+    fn test_denials_expire_sooner() {
+        let cache =
+            DecisionCache::new(Duration::from_secs(30), 16).with_deny_ttl(Duration::from_secs(2));
+        let allowed = input_hash(&json!({"user": "alice"}));
+        let denied = input_hash(&json!({"user": "timmy"}));
+        let t0 = Instant::now();
+        cache.insert(allowed, json!({"allow": true}), t0);
+        cache.insert(denied, json!({"allow": false, "reason": "Bedtime"}), t0);
+
+        let t1 = t0 + Duration::from_secs(1);
+        assert!(cache.get(&denied, t1).is_some());
+        let t3 = t0 + Duration::from_secs(3);
+        assert!(cache.get(&allowed, t3).is_some());
+        assert!(cache.get(&denied, t3).is_none());
+
+        // A zero deny TTL caches allows only
+        let cache = DecisionCache::new(Duration::from_secs(30), 16).with_deny_ttl(Duration::ZERO);
+        cache.insert(denied, json!({"allow": false}), t0);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
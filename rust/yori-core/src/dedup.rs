@@ -0,0 +1,199 @@
+//! Near-duplicate prompt detection for audit ingestion
+//!
+//! Household traffic is extremely repetitive ("what is 7 times 8", asked
+//! twenty times an evening). Rather than storing the same prompt preview
+//! over and over, the audit logger fingerprints each normalized prompt with
+//! a 64-bit SimHash and stores a reference to the first (canonical) copy
+//! when a new prompt is within a small Hamming distance of one already seen.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Default maximum Hamming distance for two prompts to count as duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 3;
+
+/// Default number of canonical prompts kept in the in-memory index
+pub const DEFAULT_INDEX_CAPACITY: usize = 10_000;
+
+/// Number of bands the fingerprint is split into for candidate lookup
+///
+/// With four 16-bit bands, any two fingerprints within distance 3 are
+/// guaranteed to share at least one identical band (pigeonhole).
+const BANDS: usize = 4;
+
+/// Normalize a prompt for fingerprinting
+///
+/// Lowercases, strips punctuation and collapses whitespace so trivial
+/// differences ("What is 7x8?" vs "what is 7x8") don't defeat matching.
+pub fn normalize(prompt: &str) -> String {
+    prompt
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Compute the 64-bit SimHash of a prompt over word shingles
+pub fn simhash(prompt: &str) -> u64 {
+    let normalized = normalize(prompt);
+    let words: Vec<&str> = normalized.split(' ').filter(|w| !w.is_empty()).collect();
+    if words.is_empty() {
+        return 0;
+    }
+
+    let mut weights = [0i32; 64];
+    let mut add = |feature: u64| {
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if feature & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    };
+
+    // Single words plus word bigrams keep short prompts distinguishable
+    for word in &words {
+        add(fnv1a(word.as_bytes()));
+    }
+    for pair in words.windows(2) {
+        add(fnv1a(format!("{} {}", pair[0], pair[1]).as_bytes()));
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0u64, |acc, (bit, _)| acc | (1 << bit))
+}
+
+/// Number of differing bits between two fingerprints
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// FNV-1a hash (stable across runs and Rust versions, unlike `DefaultHasher`)
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn band(fingerprint: u64, index: usize) -> u16 {
+    (fingerprint >> (index * 16)) as u16
+}
+
+/// In-memory index of canonical prompt fingerprints
+///
+/// Maps a fingerprint to the id of the canonical prompt row that owns it.
+/// The index is bounded; once full, the oldest canonical prompts are
+/// forgotten (later repeats of them simply become new canonicals).
+#[derive(Debug)]
+pub struct PromptDeduplicator {
+    max_distance: u32,
+    capacity: usize,
+    bands: Vec<HashMap<u16, Vec<(u64, i64)>>>,
+    order: VecDeque<(u64, i64)>,
+}
+
+impl PromptDeduplicator {
+    /// Create an index with the given match distance and capacity
+    ///
+    /// Distances above 3 are clamped to 3 because the band lookup can only
+    /// guarantee recall up to that distance.
+    pub fn new(max_distance: u32, capacity: usize) -> Self {
+        PromptDeduplicator {
+            max_distance: max_distance.min(BANDS as u32 - 1),
+            capacity: capacity.max(1),
+            bands: vec![HashMap::new(); BANDS],
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Find the canonical prompt id for a fingerprint, if one is close enough
+    pub fn find(&self, fingerprint: u64) -> Option<i64> {
+        (0..BANDS)
+            .filter_map(|i| self.bands[i].get(&band(fingerprint, i)))
+            .flatten()
+            .filter(|(fp, _)| hamming_distance(*fp, fingerprint) <= self.max_distance)
+            .min_by_key(|(fp, _)| hamming_distance(*fp, fingerprint))
+            .map(|(_, id)| *id)
+    }
+
+    /// Register a new canonical prompt
+    pub fn insert(&mut self, fingerprint: u64, id: i64) {
+        if self.order.len() >= self.capacity {
+            if let Some((old_fp, old_id)) = self.order.pop_front() {
+                for i in 0..BANDS {
+                    if let Some(entries) = self.bands[i].get_mut(&band(old_fp, i)) {
+                        entries.retain(|(_, eid)| *eid != old_id);
+                        if entries.is_empty() {
+                            self.bands[i].remove(&band(old_fp, i));
+                        }
+                    }
+                }
+            }
+        }
+
+        for i in 0..BANDS {
+            self.bands[i]
+                .entry(band(fingerprint, i))
+                .or_default()
+                .push((fingerprint, id));
+        }
+        self.order.push_back((fingerprint, id));
+    }
+
+    /// Number of canonical prompts currently indexed
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+impl Default for PromptDeduplicator {
+    fn default() -> Self {
+        PromptDeduplicator::new(DEFAULT_MAX_DISTANCE, DEFAULT_INDEX_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_duplicates_share_fingerprint_neighbourhood() {
+        let a = simhash("Can you help me with my math homework? What is 7 times 8?");
+        let b = simhash("can you help me with my math homework what is 7 times 8");
+        let c = simhash("Write a short story about dragons living on the moon");
+
+        assert_eq!(a, b);
+        assert!(hamming_distance(a, c) > DEFAULT_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn test_index_lookup_and_capacity() {
+        let mut index = PromptDeduplicator::new(3, 2);
+        let fp = simhash("explain photosynthesis for a school project");
+        index.insert(fp, 1);
+
+        assert_eq!(index.find(fp), Some(1));
+        assert_eq!(index.find(fp ^ 0b101), Some(1));
+        assert_eq!(index.find(!fp), None);
+
+        index.insert(simhash("first other prompt"), 2);
+        index.insert(simhash("second other prompt"), 3);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.find(fp), None);
+    }
+}
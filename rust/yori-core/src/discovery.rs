@@ -0,0 +1,571 @@
+//! Device name discovery over mDNS and NetBIOS
+//!
+//! Devices with a static address, or whose DHCP client sends no hostname,
+//! show up in the audit log as a bare IP until someone adds them to the MAC
+//! mapping. Most of them will say who they are when asked: Apple devices,
+//! printers and Linux boxes answer a reverse (PTR) query on the mDNS port,
+//! Windows machines and Samba hosts answer a NetBIOS node status request.
+//!
+//! [`NameDiscovery`] asks the device directly (unicast, so nothing is
+//! flooded onto the LAN), tries mDNS first and NetBIOS second, and caches
+//! the answer, including "no answer", so each device is asked at most once
+//! per TTL. The proxy resolves in the background: the first request from a
+//! new device goes out unnamed, the ones after it carry the name.
+
+use crate::identity::IdentitySource;
+use anyhow::{Context, Result};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// mDNS port; queries from any other port are answered unicast (RFC 6762
+/// "legacy unicast")
+const MDNS_PORT: u16 = 5353;
+
+/// NetBIOS name service port
+const NETBIOS_PORT: u16 = 137;
+
+/// DNS record type for reverse lookups
+const TYPE_PTR: u16 = 12;
+
+/// NetBIOS node status record type
+const TYPE_NBSTAT: u16 = 0x21;
+
+/// Longest name kept from a device's answer
+const MAX_NAME_LEN: usize = 63;
+
+/// How long a device gets to answer each query
+pub const DEFAULT_DISCOVERY_TIMEOUT_MS: u64 = 500;
+
+/// How long a discovered name is trusted before the device is asked again
+pub const DEFAULT_DISCOVERY_TTL_SECS: u64 = 3600;
+
+/// How long a device that answered neither query is left alone
+const NEGATIVE_TTL: Duration = Duration::from_secs(600);
+
+/// A name a device announced for itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredName {
+    /// Name with any `.local` suffix removed (e.g., "Timmys-iPad")
+    pub name: String,
+
+    /// [`IdentitySource::Mdns`] or [`IdentitySource::NetBios`]
+    pub source: IdentitySource,
+}
+
+#[derive(Debug, Clone)]
+struct CachedName {
+    name: Option<DiscoveredName>,
+    expires: Instant,
+}
+
+/// Cached mDNS/NetBIOS name resolver
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// names = yori_core.NameDiscovery(timeout_ms=500)
+/// device = names.resolve("192.168.1.50")
+/// if device is not None:
+///     print(device["name"], device["source"])  # "Timmys-iPad" "mdns"
+/// ```
+#[pyclass]
+#[derive(Debug)]
+pub struct NameDiscovery {
+    timeout: Duration,
+    ttl: Duration,
+    cache: Mutex<HashMap<IpAddr, CachedName>>,
+    pending: Mutex<HashSet<IpAddr>>,
+}
+
+impl Default for NameDiscovery {
+    fn default() -> Self {
+        NameDiscovery::new(
+            Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT_MS),
+            Duration::from_secs(DEFAULT_DISCOVERY_TTL_SECS),
+        )
+    }
+}
+
+impl NameDiscovery {
+    /// Create a resolver waiting `timeout` per query and caching names for
+    /// `ttl`
+    pub fn new(timeout: Duration, ttl: Duration) -> Self {
+        NameDiscovery {
+            timeout,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Cached result for an address: None if it hasn't been asked (or the
+    /// answer expired), `Some(None)` if it didn't answer
+    pub fn cached(&self, ip: IpAddr) -> Option<Option<DiscoveredName>> {
+        self.cache
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.name.clone())
+    }
+
+    /// Ask the device for its name, blocking for up to twice the timeout
+    ///
+    /// A fresh cached answer is returned without asking again.
+    pub fn lookup(&self, ip: IpAddr) -> Option<DiscoveredName> {
+        if let Some(cached) = self.cached(ip) {
+            return cached;
+        }
+        let found = self.query(ip);
+        self.remember(ip, found.clone());
+        found
+    }
+
+    /// Look the address up on the blocking thread pool if it isn't cached
+    /// or already being looked up
+    ///
+    /// Does nothing outside a Tokio runtime. Returns whether a lookup was
+    /// started.
+    pub fn spawn_lookup(self: &Arc<Self>, ip: IpAddr) -> bool {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return false;
+        };
+        if self.cached(ip).is_some() || !self.pending.lock().unwrap().insert(ip) {
+            return false;
+        }
+        let names = Arc::clone(self);
+        runtime.spawn_blocking(move || {
+            names.lookup(ip);
+            names.pending.lock().unwrap().remove(&ip);
+        });
+        true
+    }
+
+    /// Forget every cached name (e.g., after devices were renamed)
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn remember(&self, ip: IpAddr, name: Option<DiscoveredName>) {
+        let ttl = if name.is_some() {
+            self.ttl
+        } else {
+            NEGATIVE_TTL
+        };
+        self.cache.lock().unwrap().insert(
+            ip,
+            CachedName {
+                name,
+                expires: Instant::now() + ttl,
+            },
+        );
+    }
+
+    fn query(&self, ip: IpAddr) -> Option<DiscoveredName> {
+        let id = query_id();
+        let mdns = exchange(
+            SocketAddr::new(ip, MDNS_PORT),
+            &mdns_query(ip, id),
+            self.timeout,
+            |reply| parse_mdns_response(reply, id),
+        );
+        match mdns {
+            Ok(Some(name)) => {
+                return Some(DiscoveredName {
+                    name,
+                    source: IdentitySource::Mdns,
+                })
+            }
+            Ok(None) => {}
+            Err(e) => tracing::debug!("mDNS lookup of {} failed: {:#}", ip, e),
+        }
+
+        // NetBIOS is IPv4 only
+        if !ip.is_ipv4() {
+            return None;
+        }
+        match exchange(
+            SocketAddr::new(ip, NETBIOS_PORT),
+            &netbios_query(id),
+            self.timeout,
+            |reply| parse_netbios_response(reply, id),
+        ) {
+            Ok(name) => name.map(|name| DiscoveredName {
+                name,
+                source: IdentitySource::NetBios,
+            }),
+            Err(e) => {
+                tracing::debug!("NetBIOS lookup of {} failed: {:#}", ip, e);
+                None
+            }
+        }
+    }
+}
+
+/// Random transaction ID so stray replies aren't mistaken for ours
+fn query_id() -> u16 {
+    let mut id = [0u8; 2];
+    // A fixed ID only costs us stray-reply protection
+    let _ = getrandom::getrandom(&mut id);
+    u16::from_be_bytes(id)
+}
+
+/// Send one query and wait for a reply from the target that `parse` accepts
+fn exchange(
+    target: SocketAddr,
+    packet: &[u8],
+    timeout: Duration,
+    parse: impl Fn(&[u8]) -> Option<String>,
+) -> Result<Option<String>> {
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).context("failed to bind query socket")?;
+    socket
+        .send_to(packet, target)
+        .with_context(|| format!("failed to send query to {}", target))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 1500];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) if from.ip() == target.ip() => {
+                if let Some(name) = parse(&buf[..len]) {
+                    return Ok(Some(name));
+                }
+            }
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            // ICMP port unreachable: nothing listening
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// `in-addr.arpa` / `ip6.arpa` name for a reverse lookup
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(v6) => {
+            let mut name = String::new();
+            for byte in v6.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0f, byte >> 4));
+            }
+            name + "ip6.arpa"
+        }
+    }
+}
+
+/// DNS header for a single-question query
+fn query_header(id: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Flags, then one question and no answer/authority/additional records
+    packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    packet
+}
+
+/// Reverse PTR query for `ip`
+fn mdns_query(ip: IpAddr, id: u16) -> Vec<u8> {
+    let mut packet = query_header(id);
+    for label in reverse_name(ip).split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet
+}
+
+/// NetBIOS node status query for the wildcard name `*`
+fn netbios_query(id: u16) -> Vec<u8> {
+    let mut packet = query_header(id);
+    let mut name = [0u8; 16];
+    name[0] = b'*';
+    packet.push(32);
+    for byte in name {
+        packet.push(b'A' + (byte >> 4));
+        packet.push(b'A' + (byte & 0x0f));
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]))
+}
+
+/// Header checks shared by both protocols: our ID, a response, and the
+/// answer count
+fn response_answers(msg: &[u8], id: u16) -> Option<u16> {
+    let is_response = read_u16(msg, 2)? & 0x8000 != 0;
+    if read_u16(msg, 0)? != id || !is_response {
+        return None;
+    }
+    read_u16(msg, 6)
+}
+
+/// Decode a (possibly compressed) DNS name at `pos`
+///
+/// Returns the dotted name and the offset just past it in the record.
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops in malformed packets
+    for _ in 0..128 {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let target = (read_u16(msg, pos)? & 0x3fff) as usize;
+                if end.is_none() {
+                    end = Some(pos + 2);
+                }
+                pos = target;
+            }
+            l => {
+                let label = msg.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
+
+/// Host name from an mDNS reply to [`mdns_query`]
+fn parse_mdns_response(msg: &[u8], id: u16) -> Option<String> {
+    let answers = response_answers(msg, id)?;
+    let mut pos = 12;
+    for _ in 0..read_u16(msg, 4)? {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+    for _ in 0..answers {
+        pos = read_name(msg, pos)?.1;
+        let record_type = read_u16(msg, pos)?;
+        let rdlen = read_u16(msg, pos + 8)? as usize;
+        let rdata = pos + 10;
+        if record_type == TYPE_PTR {
+            if let Some(name) = clean_name(&read_name(msg, rdata)?.0) {
+                return Some(name);
+            }
+        }
+        pos = rdata + rdlen;
+    }
+    None
+}
+
+/// Workstation name from a NetBIOS node status reply
+fn parse_netbios_response(msg: &[u8], id: u16) -> Option<String> {
+    if response_answers(msg, id)? == 0 {
+        return None;
+    }
+    let pos = read_name(msg, 12)?.1;
+    if read_u16(msg, pos)? != TYPE_NBSTAT {
+        return None;
+    }
+    let count = *msg.get(pos + 10)? as usize;
+    msg.get(pos + 11..)?
+        .chunks_exact(18)
+        .take(count)
+        // Unique (not group) name with the workstation suffix
+        .find(|entry| entry[15] == 0x00 && entry[16] & 0x80 == 0)
+        .and_then(|entry| clean_name(&String::from_utf8_lossy(&entry[..15])))
+}
+
+/// Trim a device-supplied name to something safe to show and log
+fn clean_name(raw: &str) -> Option<String> {
+    let name = raw.trim().trim_end_matches('.');
+    let name = match name.len().checked_sub(".local".len()) {
+        Some(cut) if name.is_char_boundary(cut) && name[cut..].eq_ignore_ascii_case(".local") => {
+            &name[..cut]
+        }
+        _ => name,
+    };
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LEN)
+        .collect();
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[pymethods]
+impl NameDiscovery {
+    /// Create a resolver
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - How long each device gets to answer (default: 500)
+    /// * `ttl_secs` - How long discovered names are cached (default: 3600)
+    #[new]
+    #[pyo3(signature = (timeout_ms=DEFAULT_DISCOVERY_TIMEOUT_MS, ttl_secs=DEFAULT_DISCOVERY_TTL_SECS))]
+    fn py_new(timeout_ms: u64, ttl_secs: u64) -> Self {
+        NameDiscovery::new(
+            Duration::from_millis(timeout_ms),
+            Duration::from_secs(ttl_secs),
+        )
+    }
+
+    /// Ask a device for its name over mDNS, then NetBIOS
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `name` and `source` ("mdns" or "netbios"), or None
+    #[pyo3(name = "resolve")]
+    fn py_resolve(&self, py: Python, client_ip: String) -> PyResult<Option<PyObject>> {
+        let Ok(ip) = client_ip.parse() else {
+            return Ok(None);
+        };
+        match py.allow_threads(|| self.lookup(ip)) {
+            Some(found) => {
+                let dict = PyDict::new_bound(py);
+                dict.set_item("name", found.name)?;
+                dict.set_item("source", found.source.as_str())?;
+                Ok(Some(dict.into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Forget every cached name
+    #[pyo3(name = "clear")]
+    fn py_clear(&self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DNS name in wire format (uncompressed)
+    fn wire_name(name: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        for label in name.split('.') {
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        }
+        out.push(0);
+        out
+    }
+
+    #[test]
+    fn test_mdns_reverse_lookup() {
+        let ip: IpAddr = "192.168.1.50".parse().unwrap();
+        let query = mdns_query(ip, 0x1234);
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert_eq!(
+            read_name(&query, 12).unwrap().0,
+            "50.1.168.192.in-addr.arpa"
+        );
+
+        // Question echoed, answer owner compressed to point at it
+        let mut reply = query.clone();
+        reply[2] = 0x84;
+        reply[7] = 1;
+        let target = wire_name("Timmys-iPad.local");
+        reply.extend_from_slice(&[0xc0, 12]);
+        reply.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        reply.extend_from_slice(&[0x80, 0x01, 0, 0, 0x0e, 0x10]);
+        reply.extend_from_slice(&(target.len() as u16).to_be_bytes());
+        reply.extend_from_slice(&target);
+
+        assert_eq!(
+            parse_mdns_response(&reply, 0x1234).as_deref(),
+            Some("Timmys-iPad")
+        );
+        assert_eq!(parse_mdns_response(&reply, 0x4321), None);
+        assert_eq!(parse_mdns_response(&query, 0x1234), None);
+        assert_eq!(parse_mdns_response(&reply[..reply.len() - 4], 0x1234), None);
+
+        let v6: IpAddr = "fe80::1".parse().unwrap();
+        assert!(reverse_name(v6).starts_with("1.0.0.0.0.0.0.0."));
+        assert!(reverse_name(v6).ends_with(".0.8.e.f.ip6.arpa"));
+    }
+
+    #[test]
+    fn test_netbios_node_status() {
+        let query = netbios_query(7);
+        assert_eq!(query.len(), 12 + 34 + 4);
+        assert_eq!(&query[13..15], b"CK");
+
+        let mut reply = query[..12 + 34].to_vec();
+        reply[2] = 0x84;
+        reply[5] = 0;
+        reply[7] = 1;
+        reply.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+        reply.extend_from_slice(&[0, 1, 0, 0, 0, 0]);
+        reply.extend_from_slice(&(1 + 3 * 18u16).to_be_bytes());
+        reply.push(3);
+        for (name, suffix, flags) in [
+            ("WORKGROUP", 0x00, 0x84),
+            ("MUMS-LAPTOP", 0x20, 0x04),
+            ("MUMS-LAPTOP", 0x00, 0x04),
+        ] {
+            reply.extend_from_slice(format!("{:<15}", name).as_bytes());
+            reply.push(suffix);
+            reply.extend_from_slice(&[flags, 0]);
+        }
+
+        assert_eq!(
+            parse_netbios_response(&reply, 7).as_deref(),
+            Some("MUMS-LAPTOP")
+        );
+        assert_eq!(parse_netbios_response(&reply, 8), None);
+    }
+
+    #[test]
+    fn test_names_are_cleaned_and_cached() {
+        assert_eq!(
+            clean_name("Living-Room-TV.LOCAL.").as_deref(),
+            Some("Living-Room-TV")
+        );
+        assert_eq!(clean_name("bad\u{7}name").as_deref(), Some("badname"));
+        assert_eq!(clean_name(" .local ").as_deref(), None);
+        assert_eq!(clean_name(&"x".repeat(100)).unwrap().len(), MAX_NAME_LEN);
+
+        let names = NameDiscovery::default();
+        let ip: IpAddr = "192.168.1.70".parse().unwrap();
+        assert_eq!(names.cached(ip), None);
+        names.remember(ip, None);
+        assert_eq!(names.cached(ip), Some(None));
+        let found = DiscoveredName {
+            name: "printer".to_string(),
+            source: IdentitySource::Mdns,
+        };
+        names.remember(ip, Some(found.clone()));
+        assert_eq!(names.lookup(ip), Some(found));
+        names.clear();
+        assert_eq!(names.cached(ip), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! DNS overrides steering LLM hosts to the gateway
+//!
+//! Transparent interception only sees traffic that is routed through the
+//! router, and explicit proxy mode only sees devices configured for it. A
+//! device that resolves `api.openai.com` through the router's resolver can
+//! instead be handed the gateway's own address, so its connection lands on
+//! the proxy whatever its routing or proxy settings. The override file is
+//! generated from the intercepted hosts (`proxy.endpoints` plus
+//! `[providers.hosts]`) for Unbound or dnsmasq:
+//!
+//! ```text
+//! server:
+//!   local-data: "api.openai.com. A 192.168.1.1"
+//!   local-zone: "openai.azure.com." redirect
+//!   local-data: "openai.azure.com. A 192.168.1.1"
+//! ```
+//!
+//! Exact hosts become single names; `*.example.com` redirects the whole
+//! zone (the apex included, which the proxy relays untouched). Patterns
+//! with a wildcard in the middle (`bedrock-runtime.*.amazonaws.com`) can't
+//! be expressed to either resolver and are listed as skipped. dnsmasq's
+//! `address=` always covers subdomains too.
+//!
+//! The file is rewritten only when its contents change, followed by the
+//! resolver's reload command. [`DnsOverrides::watch`] re-checks the host
+//! list periodically, so edits to the configuration reach the resolver
+//! without a restart. Devices using DNS-over-HTTPS bypass the router's
+//! resolver and are unaffected.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::persist::write_atomic;
+
+/// Where OPNsense's Unbound picks up extra configuration
+pub const DEFAULT_UNBOUND_OVERRIDES: &str = "/usr/local/etc/unbound.opnsense.d/yori.conf";
+
+/// Where dnsmasq picks up extra configuration
+pub const DEFAULT_DNSMASQ_OVERRIDES: &str = "/usr/local/etc/dnsmasq.conf.d/yori.conf";
+
+/// Resolver the override file is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsFormat {
+    Unbound,
+    Dnsmasq,
+}
+
+impl DnsFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsFormat::Unbound => "unbound",
+            DnsFormat::Dnsmasq => "dnsmasq",
+        }
+    }
+
+    /// Default override file location
+    pub fn default_path(&self) -> PathBuf {
+        PathBuf::from(match self {
+            DnsFormat::Unbound => DEFAULT_UNBOUND_OVERRIDES,
+            DnsFormat::Dnsmasq => DEFAULT_DNSMASQ_OVERRIDES,
+        })
+    }
+
+    /// Default command making the resolver re-read its configuration
+    pub fn default_reload_command(&self) -> Vec<String> {
+        let command: &[&str] = match self {
+            DnsFormat::Unbound => &[
+                "unbound-control",
+                "-c",
+                "/var/unbound/unbound.conf",
+                "reload",
+            ],
+            // dnsmasq only re-reads hosts files on SIGHUP
+            DnsFormat::Dnsmasq => &["service", "dnsmasq", "restart"],
+        };
+        command.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+impl FromStr for DnsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "unbound" => Ok(DnsFormat::Unbound),
+            "dnsmasq" => Ok(DnsFormat::Dnsmasq),
+            _ => bail!("unknown DNS format {:?} (expected unbound or dnsmasq)", s),
+        }
+    }
+}
+
+/// DNS override settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsOverrideConfig {
+    pub format: DnsFormat,
+
+    /// Override file to write
+    pub path: PathBuf,
+
+    /// Gateway addresses LLM hosts resolve to
+    pub addresses: Vec<IpAddr>,
+
+    /// Run after the file changes (empty = none)
+    pub reload_command: Vec<String>,
+
+    /// How often [`DnsOverrides::watch`] re-checks the host list
+    pub check_interval: Duration,
+}
+
+/// An override file and the patterns it couldn't express
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedOverrides {
+    pub text: String,
+    pub skipped: Vec<String>,
+}
+
+/// Keeps a resolver override file in step with the intercepted hosts
+#[derive(Debug)]
+pub struct DnsOverrides {
+    config: DnsOverrideConfig,
+
+    /// The file changed but the resolver hasn't reloaded it yet
+    reload_pending: AtomicBool,
+}
+
+impl DnsOverrides {
+    pub fn new(config: DnsOverrideConfig) -> Result<Self> {
+        if config.addresses.is_empty() {
+            bail!("at least one gateway address is required");
+        }
+        Ok(DnsOverrides {
+            config,
+            reload_pending: AtomicBool::new(false),
+        })
+    }
+
+    /// Override file location
+    pub fn path(&self) -> &Path {
+        &self.config.path
+    }
+
+    /// Override file for host patterns
+    pub fn render(&self, patterns: &[String]) -> RenderedOverrides {
+        let mut exact = BTreeSet::new();
+        let mut zones = BTreeSet::new();
+        let mut skipped = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(zone) if !zone.contains('*') => zones.insert(zone.to_string()),
+                None if !pattern.contains('*') => exact.insert(pattern),
+                _ => {
+                    skipped.push(pattern);
+                    continue;
+                }
+            };
+        }
+        // Names inside a redirected zone are already covered
+        exact.retain(|name| !zones.iter().any(|zone| in_zone(name, zone)));
+
+        let mut text =
+            String::from("# Generated by YORI from the intercepted LLM hosts; do not edit\n");
+        for pattern in &skipped {
+            text.push_str(&format!("# not expressible: {}\n", pattern));
+        }
+        match self.config.format {
+            DnsFormat::Unbound => {
+                text.push_str("server:\n");
+                for name in &exact {
+                    self.unbound_data(&mut text, name);
+                }
+                for zone in &zones {
+                    text.push_str(&format!("  local-zone: \"{}.\" redirect\n", zone));
+                    self.unbound_data(&mut text, zone);
+                }
+            }
+            DnsFormat::Dnsmasq => {
+                for name in exact.iter().chain(&zones) {
+                    for address in &self.config.addresses {
+                        text.push_str(&format!("address=/{}/{}\n", name, address));
+                    }
+                }
+            }
+        }
+        RenderedOverrides { text, skipped }
+    }
+
+    fn unbound_data(&self, text: &mut String, name: &str) {
+        for address in &self.config.addresses {
+            let kind = if address.is_ipv4() { "A" } else { "AAAA" };
+            text.push_str(&format!(
+                "  local-data: \"{}. {} {}\"\n",
+                name, kind, address
+            ));
+        }
+    }
+
+    /// Write the override file for `patterns` if it changed, then run the
+    /// reload command; returns whether the file changed
+    ///
+    /// A failed reload is retried on the next sync.
+    pub fn sync(&self, patterns: &[String]) -> Result<bool> {
+        let rendered = self.render(patterns);
+        let path = &self.config.path;
+        match fs::read_to_string(path) {
+            Ok(current) if current == rendered.text => {
+                if self.reload_pending.load(Ordering::Relaxed) {
+                    self.reload()?;
+                }
+                return Ok(false);
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+        // The resolver never reads half a file
+        write_atomic(path, rendered.text.as_bytes())?;
+        for pattern in &rendered.skipped {
+            tracing::warn!(
+                "DNS override for {} can't be expressed; not redirected",
+                pattern
+            );
+        }
+
+        self.reload_pending.store(true, Ordering::Relaxed);
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Run the reload command
+    fn reload(&self) -> Result<()> {
+        if let Some((program, args)) = self.config.reload_command.split_first() {
+            let shown = self.config.reload_command.join(" ");
+            let output = Command::new(program)
+                .args(args)
+                .output()
+                .with_context(|| format!("failed to run `{}`", shown))?;
+            if !output.status.success() {
+                bail!(
+                    "`{}` failed: {}",
+                    shown,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+        self.reload_pending.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sync with the patterns `patterns` returns now and every
+    /// `check_interval` after
+    pub fn watch(
+        self: Arc<Self>,
+        patterns: impl Fn() -> Result<Vec<String>> + Send + Sync + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        let patterns = Arc::new(patterns);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.check_interval);
+            loop {
+                ticker.tick().await;
+                let overrides = self.clone();
+                let patterns = patterns.clone();
+                let synced =
+                    tokio::task::spawn_blocking(move || overrides.sync(&patterns()?)).await;
+                match synced {
+                    Ok(Ok(true)) => {
+                        tracing::info!("Updated DNS overrides in {}", self.config.path.display())
+                    }
+                    Ok(Ok(false)) => {}
+                    Ok(Err(e)) => tracing::warn!("DNS override update failed: {:#}", e),
+                    Err(e) => tracing::warn!("DNS override update failed: {}", e),
+                }
+            }
+        })
+    }
+}
+
+/// Whether `name` is `zone` or below it
+fn in_zone(name: &str, zone: &str) -> bool {
+    name == zone
+        || name
+            .strip_suffix(zone)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(format: DnsFormat, path: PathBuf) -> DnsOverrides {
+        DnsOverrides::new(DnsOverrideConfig {
+            format,
+            path,
+            addresses: vec!["192.168.1.1".parse().unwrap(), "fd00::1".parse().unwrap()],
+            reload_command: Vec::new(),
+            check_interval: Duration::from_secs(30),
+        })
+        .unwrap()
+    }
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_renders_names_zones_and_skips_inner_wildcards() {
+        let hosts = patterns(&[
+            "api.openai.com",
+            "*.openai.azure.com",
+            "eastus.openai.azure.com",
+            "bedrock-runtime.*.amazonaws.com",
+        ]);
+        let unbound = overrides(DnsFormat::Unbound, PathBuf::new()).render(&hosts);
+        assert_eq!(unbound.skipped, vec!["bedrock-runtime.*.amazonaws.com"]);
+        assert!(unbound
+            .text
+            .contains("  local-data: \"api.openai.com. A 192.168.1.1\"\n"));
+        assert!(unbound
+            .text
+            .contains("  local-data: \"api.openai.com. AAAA fd00::1\"\n"));
+        assert!(unbound
+            .text
+            .contains("  local-zone: \"openai.azure.com.\" redirect\n"));
+        assert!(!unbound.text.contains("eastus"));
+
+        let dnsmasq = overrides(DnsFormat::Dnsmasq, PathBuf::new()).render(&hosts);
+        assert!(dnsmasq
+            .text
+            .contains("address=/api.openai.com/192.168.1.1\n"));
+        assert!(dnsmasq.text.contains("address=/openai.azure.com/fd00::1\n"));
+        assert!(!dnsmasq.text.contains("local-"));
+    }
+
+    #[test]
+    fn test_sync_rewrites_only_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("unbound.d").join("yori.conf");
+        let overrides = overrides(DnsFormat::Unbound, path.clone());
+
+        assert!(overrides.sync(&patterns(&["api.openai.com"])).unwrap());
+        assert!(!overrides.sync(&patterns(&["API.openai.com."])).unwrap());
+        assert!(overrides
+            .sync(&patterns(&["api.openai.com", "api.x.ai"]))
+            .unwrap());
+        assert!(fs::read_to_string(&path).unwrap().contains("api.x.ai."));
+        assert!(!path.with_extension("conf.tmp").exists());
+    }
+}
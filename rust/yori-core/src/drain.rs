@@ -0,0 +1,157 @@
+//! Connection tracking for graceful shutdown
+//!
+//! Every accepted connection holds a [`ConnectionGuard`] for as long as it
+//! is being served. On shutdown the proxy stops accepting, then
+//! [`ConnectionTracker::drain`] waits for the guards to be dropped. Whatever
+//! is still open when the drain timeout runs out is told to abort (through
+//! [`ConnectionGuard::aborted`]) and counted as dropped, so a stuck
+//! streaming response can't hold a restart hostage.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// Default time in-flight requests get to finish on shutdown
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Outcome of draining connections at shutdown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DrainReport {
+    /// Connections that finished on their own during the drain
+    pub drained: usize,
+
+    /// Connections still open at the timeout, which were aborted
+    pub dropped: usize,
+
+    /// Time spent waiting
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    active: AtomicUsize,
+    idle: Notify,
+    abort: Mutex<CancellationToken>,
+}
+
+/// Count of in-flight connections
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionTracker {
+    inner: Arc<Inner>,
+}
+
+/// Held by a connection while it's being served
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    inner: Arc<Inner>,
+    abort: CancellationToken,
+}
+
+impl ConnectionGuard {
+    /// Resolves when the drain timeout has passed and the connection must
+    /// be closed
+    pub fn aborted(&self) -> WaitForCancellationFutureOwned {
+        self.abort.clone().cancelled_owned()
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.inner.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+impl ConnectionTracker {
+    /// Start tracking a connection until the returned guard is dropped
+    pub fn track(&self) -> ConnectionGuard {
+        self.inner.active.fetch_add(1, Ordering::AcqRel);
+        ConnectionGuard {
+            inner: Arc::clone(&self.inner),
+            abort: self.inner.abort.lock().unwrap().clone(),
+        }
+    }
+
+    /// Connections currently being served
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::Acquire)
+    }
+
+    /// Wait up to `timeout` for every connection to finish, then abort the
+    /// rest
+    ///
+    /// Connections tracked afterwards get a fresh abort signal, so the
+    /// tracker can be reused when the proxy is started again.
+    pub async fn drain(&self, timeout: Duration) -> DrainReport {
+        let started = Instant::now();
+        let initial = self.active();
+
+        let idle = async {
+            loop {
+                let notified = self.inner.idle.notified();
+                tokio::pin!(notified);
+                // Register before checking, so a guard dropped in between
+                // isn't missed
+                notified.as_mut().enable();
+                if self.active() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        let _ = tokio::time::timeout(timeout, idle).await;
+
+        let dropped = self.active();
+        if dropped > 0 {
+            let abort = std::mem::take(&mut *self.inner.abort.lock().unwrap());
+            abort.cancel();
+        }
+        DrainReport {
+            drained: initial.saturating_sub(dropped),
+            dropped,
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_in_flight_connections() {
+        let tracker = ConnectionTracker::default();
+        let guard = tracker.track();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+
+        let report = tracker.drain(Duration::from_secs(5)).await;
+        assert_eq!((report.drained, report.dropped), (1, 0));
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stragglers_are_aborted_at_timeout() {
+        let tracker = ConnectionTracker::default();
+        let stuck = tracker.track();
+        let aborted = stuck.aborted();
+
+        let report = tracker.drain(Duration::from_millis(20)).await;
+        assert_eq!((report.drained, report.dropped), (0, 1));
+        tokio::time::timeout(Duration::from_secs(1), aborted)
+            .await
+            .expect("straggler was not told to abort");
+
+        // Connections after a restart aren't affected by the old abort
+        let fresh = tracker.track();
+        assert!(!fresh.abort.is_cancelled());
+        drop((stuck, fresh));
+        assert_eq!(tracker.active(), 0);
+    }
+}
@@ -0,0 +1,707 @@
+//! Policy input enrichment
+//!
+//! Policies need more than the raw request: who the device belongs to,
+//! whether a schedule is active, how much quota is left, how busy the
+//! device has been and what the prompt is about. Rather than every
+//! integration assembling its own input, the proxy runs one
+//! [`EnrichmentPipeline`] that builds the base input from the request and
+//! lets each stage add its section:
+//!
+//! ```json
+//! {
+//!   "phase": "request",
+//!   "client_ip": "192.168.1.50", "tenant": "default", "endpoint": "api.openai.com", ...
+//!   "model": "gpt-4o", "messages": [...], "system_prompt": null, "temperature": 0.7,
+//!   "stream": false, "tools": ["web_search"], "categories": ["homework"],
+//!   "device":   {"name": "sam-ipad", "owner": "sam", "group": "kids", "mac": "a4:83:e7:12:34:56"},
+//!   "schedule": {"weekday": "monday", "hour": 21, "minute": 5, "time": "21:05",
+//!                "date": "2026-03-02", "timezone": "Europe/London", "utc_offset": "+00:00",
+//!                "weekend": false, "active": ["bedtime"]},
+//!   "quota":    {"daily_tokens": 20000, "used_tokens": 18250, "remaining_tokens": 1750,
+//!                "tokens_today": 18250, "requests_today": 41, "tokens_this_week": 52000, ...},
+//!   "budget":   {"month": "2026-03", "spent_this_month": 3.42, "cap": 5.0, "remaining": 1.58,
+//!                "exceeded": false},
+//!   "history":  {"requests_last_hour": 14, "requests_today": 63, "blocks_today": 2},
+//!   "tags":     ["homework"],
+//!   "jailbreak": {"score": 0.0, "signals": [], "threshold": 0.5, "suspected": false},
+//!   "enrichment": {"errors": []}
+//! }
+//! ```
+//!
+//! A failing stage never fails the request: its error is recorded under
+//! `enrichment.errors` and the remaining stages still run.
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Utc, Weekday};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+
+use crate::budget::{BudgetStatus, BudgetTracker};
+use crate::clock;
+use crate::dedup;
+use crate::jailbreak::{JailbreakConfig, JailbreakDetector};
+use crate::netaddr::same_client_ip;
+use crate::proxy::RequestContext;
+use crate::quota::{QuotaManager, QuotaUsage};
+use crate::timeseries::UsageSeries;
+
+/// One enrichment stage
+pub trait Enricher: Send + Sync {
+    /// Stage name used in error reports
+    fn name(&self) -> &str;
+
+    /// Add this stage's fields to `input`
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()>;
+}
+
+/// Ordered set of enrichment stages
+#[derive(Default)]
+pub struct EnrichmentPipeline {
+    stages: Vec<Box<dyn Enricher>>,
+}
+
+impl EnrichmentPipeline {
+    /// Create an empty pipeline (base input only)
+    pub fn new() -> Self {
+        EnrichmentPipeline::default()
+    }
+
+    /// Build the standard pipeline from configuration
+    pub fn from_config(
+        config: &EnrichmentConfig,
+        usage: Arc<UsageSeries>,
+        quotas: Arc<QuotaManager>,
+        budgets: Arc<BudgetTracker>,
+    ) -> Self {
+        let mut pipeline = EnrichmentPipeline::new();
+        pipeline.push(DeviceProfileEnricher::new(config.devices.clone()));
+        pipeline.push(ScheduleEnricher::new(config.schedules.clone()));
+        pipeline.push(QuotaEnricher::new(
+            quotas,
+            config.daily_token_quota,
+            config.devices.clone(),
+        ));
+        pipeline.push(BudgetEnricher::new(budgets, config.devices.clone()));
+        pipeline.push(HistoryEnricher::new(usage));
+        pipeline.push(ClassifierEnricher::new(config.tags.clone()));
+        if config.jailbreak.enabled {
+            pipeline.push(JailbreakEnricher::new(&config.jailbreak));
+        }
+        pipeline
+    }
+
+    /// Append a stage
+    pub fn push<E: Enricher + 'static>(&mut self, stage: E) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Names of the configured stages, in order
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    /// Build the policy input document for a request
+    pub fn build_input(&self, request: &RequestContext) -> Value {
+        let mut input = base_input(request);
+        let mut errors = Vec::new();
+
+        for stage in &self.stages {
+            if let Err(e) = stage.enrich(request, &mut input) {
+                tracing::warn!("Enrichment stage '{}' failed: {:#}", stage.name(), e);
+                errors.push(json!({ "stage": stage.name(), "error": format!("{:#}", e) }));
+            }
+        }
+
+        input.insert("enrichment".to_string(), json!({ "errors": errors }));
+        Value::Object(input)
+    }
+}
+
+fn base_input(request: &RequestContext) -> Map<String, Value> {
+    let value = json!({
+        "client_ip": request.client_ip,
+        "tenant": request.tenant,
+        "scope": request.scope,
+        "client_device": request.client_device,
+        "endpoint": request.endpoint,
+        "method": request.method,
+        "path": request.path,
+        "user_agent": request.user_agent,
+        "prompt_preview": request.prompt_preview,
+        "categories": request.categories,
+        "timestamp": request.timestamp.to_rfc3339(),
+        // Response-phase evaluations (crate::inspect) set "response"
+        "phase": "request",
+    });
+    let Value::Object(mut map) = value else {
+        unreachable!("json! object literal");
+    };
+    // Body fields sit at the top level: `input.model == "gpt-4o"`
+    let parsed = request.parsed.clone().unwrap_or_default();
+    if let Value::Object(fields) = parsed.policy_fields() {
+        map.extend(fields);
+    }
+    map
+}
+
+/// Enrichment configuration
+#[derive(Debug, Clone, Default)]
+pub struct EnrichmentConfig {
+    /// Known devices and who they belong to
+    pub devices: Vec<DeviceProfile>,
+
+    /// Named recurring schedules (bedtime, homework hours, ...)
+    pub schedules: Vec<Schedule>,
+
+    /// Default daily token allowance per device (None = unlimited)
+    pub daily_token_quota: Option<u64>,
+
+    /// Keyword classifier rules producing prompt tags
+    pub tags: Vec<TagRule>,
+
+    /// Jailbreak heuristics scoring the prompt
+    pub jailbreak: JailbreakConfig,
+}
+
+/// A known device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceProfile {
+    /// Device IP address
+    pub ip: String,
+
+    /// Device name (e.g., "sam-ipad")
+    pub name: String,
+
+    /// Person the device belongs to
+    pub owner: Option<String>,
+
+    /// Group (e.g., "kids", "adults")
+    pub group: Option<String>,
+
+    /// Daily token allowance overriding the default
+    pub daily_token_quota: Option<u64>,
+}
+
+fn find_device<'a>(
+    devices: &'a [DeviceProfile],
+    request: &RequestContext,
+) -> Option<&'a DeviceProfile> {
+    devices
+        .iter()
+        .find(|d| same_client_ip(&d.ip, &request.client_ip))
+}
+
+/// Who a request counts against: the device owner if the device is known
+/// (by profile or identity), otherwise the client IP
+fn subject_for(devices: &[DeviceProfile], request: &RequestContext) -> String {
+    find_device(devices, request)
+        .and_then(|d| d.owner.clone())
+        .or_else(|| request.identity.as_ref().and_then(|i| i.owner.clone()))
+        .unwrap_or_else(|| request.client_ip.clone())
+}
+
+/// Adds `device` (profile of the requesting device, else its identity
+/// from DHCP/ARP and the MAC mapping)
+pub struct DeviceProfileEnricher {
+    devices: Vec<DeviceProfile>,
+}
+
+impl DeviceProfileEnricher {
+    /// Create from known devices
+    pub fn new(devices: Vec<DeviceProfile>) -> Self {
+        DeviceProfileEnricher { devices }
+    }
+}
+
+impl Enricher for DeviceProfileEnricher {
+    fn name(&self) -> &str {
+        "device"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        let identity = request.identity.as_ref();
+        let mac = identity.and_then(|i| i.mac.as_deref());
+        let device = match (find_device(&self.devices, request), identity) {
+            (Some(d), _) => json!({
+                "known": true,
+                "name": d.name,
+                "owner": d.owner,
+                "group": d.group,
+                "mac": mac,
+            }),
+            (None, Some(i)) => json!({
+                "known": i.known,
+                "name": i.name.as_ref().or(request.client_device.as_ref()),
+                "owner": i.owner,
+                "group": i.group,
+                "mac": mac,
+            }),
+            (None, None) => json!({
+                "known": false,
+                "name": request.client_device,
+                "owner": null,
+                "group": null,
+                "mac": null,
+            }),
+        };
+        input.insert("device".to_string(), device);
+        Ok(())
+    }
+}
+
+/// A recurring weekly time window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    /// Schedule name (e.g., "bedtime")
+    pub name: String,
+
+    /// Days the window starts on (empty = every day)
+    pub days: Vec<Weekday>,
+
+    /// Window start (local time)
+    pub start: NaiveTime,
+
+    /// Window end (local time); before `start` for windows past midnight
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    /// Whether the schedule is active at `time`
+    pub fn is_active(&self, time: DateTime<Local>) -> bool {
+        let now = time.time();
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            on(time.weekday()) && now >= self.start && now < self.end
+        } else {
+            // Past midnight the window belongs to the previous day
+            (on(time.weekday()) && now >= self.start)
+                || (on(time.weekday().pred()) && now < self.end)
+        }
+    }
+}
+
+/// Adds `schedule` (local time and active schedules)
+pub struct ScheduleEnricher {
+    schedules: Vec<Schedule>,
+}
+
+impl ScheduleEnricher {
+    /// Create from configured schedules
+    pub fn new(schedules: Vec<Schedule>) -> Self {
+        ScheduleEnricher { schedules }
+    }
+}
+
+impl Enricher for ScheduleEnricher {
+    fn name(&self) -> &str {
+        "schedule"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        let local = request.timestamp.with_timezone(&Local);
+        let active: Vec<&str> = self
+            .schedules
+            .iter()
+            .filter(|s| s.is_active(local))
+            .map(|s| s.name.as_str())
+            .collect();
+        let mut schedule = clock::local_time(request.timestamp);
+        schedule.insert("active".to_string(), json!(active));
+        input.insert("schedule".to_string(), Value::Object(schedule));
+        Ok(())
+    }
+}
+
+/// Start of the local day containing `time`, in UTC
+fn local_midnight(time: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let local = time.with_timezone(&Local);
+    Local
+        .from_local_datetime(&local.date_naive().and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| anyhow::anyhow!("local midnight does not exist"))
+}
+
+/// Adds `quota` (daily/weekly allowances and usage against them)
+pub struct QuotaEnricher {
+    quotas: Arc<QuotaManager>,
+    default_quota: Option<u64>,
+    devices: Vec<DeviceProfile>,
+}
+
+impl QuotaEnricher {
+    /// Create from the quota counters and configured allowances
+    pub fn new(
+        quotas: Arc<QuotaManager>,
+        default_quota: Option<u64>,
+        devices: Vec<DeviceProfile>,
+    ) -> Self {
+        QuotaEnricher {
+            quotas,
+            default_quota,
+            devices,
+        }
+    }
+}
+
+impl QuotaEnricher {
+    /// Who the request counts against: the device owner if the device is
+    /// known (by profile or identity), otherwise the client IP
+    pub fn subject(&self, request: &RequestContext) -> String {
+        subject_for(&self.devices, request)
+    }
+
+    /// Counters and limits for the requesting subject
+    ///
+    /// A device profile's daily allowance overrides the quota manager's,
+    /// which overrides the enrichment default.
+    pub fn usage(&self, request: &RequestContext) -> QuotaUsage {
+        let mut usage =
+            self.quotas
+                .check(&request.tenant, &self.subject(request), request.timestamp);
+        usage.limits.daily_tokens = find_device(&self.devices, request)
+            .and_then(|d| d.daily_token_quota)
+            .or(usage.limits.daily_tokens)
+            .or(self.default_quota);
+        usage
+    }
+
+    /// Today's allowance and usage for the requesting device
+    pub fn status(&self, request: &RequestContext) -> Result<QuotaStatus> {
+        let midnight = local_midnight(request.timestamp)?;
+        let usage = self.usage(request);
+        let reset_secs = (midnight + Duration::days(1) - request.timestamp)
+            .num_seconds()
+            .max(0) as u64;
+
+        Ok(QuotaStatus {
+            daily_tokens: usage.limits.daily_tokens,
+            used_tokens: usage.tokens_today,
+            reset_secs,
+        })
+    }
+}
+
+/// A device's daily token allowance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaStatus {
+    /// Daily allowance (None = unlimited)
+    pub daily_tokens: Option<u64>,
+
+    /// Tokens used since local midnight
+    pub used_tokens: u64,
+
+    /// Seconds until the allowance resets
+    pub reset_secs: u64,
+}
+
+impl QuotaStatus {
+    /// Tokens left today (None = unlimited)
+    pub fn remaining_tokens(&self) -> Option<u64> {
+        self.daily_tokens
+            .map(|q| q.saturating_sub(self.used_tokens))
+    }
+}
+
+impl Enricher for QuotaEnricher {
+    fn name(&self) -> &str {
+        "quota"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        let status = self.status(request)?;
+        let usage = self.usage(request);
+        input.insert(
+            "quota".to_string(),
+            json!({
+                "daily_tokens": status.daily_tokens,
+                "used_tokens": status.used_tokens,
+                "remaining_tokens": status.remaining_tokens(),
+                "tokens_today": usage.tokens_today,
+                "requests_today": usage.requests_today,
+                "tokens_this_week": usage.tokens_this_week,
+                "requests_this_week": usage.requests_this_week,
+                "limits": usage.limits,
+                "exceeded": usage.exceeded(),
+            }),
+        );
+        Ok(())
+    }
+}
+
+/// Adds `budget` (the subject's estimated spend this month against its cap)
+pub struct BudgetEnricher {
+    budgets: Arc<BudgetTracker>,
+    devices: Vec<DeviceProfile>,
+}
+
+impl BudgetEnricher {
+    /// Create from the spend totals and known devices
+    pub fn new(budgets: Arc<BudgetTracker>, devices: Vec<DeviceProfile>) -> Self {
+        BudgetEnricher { budgets, devices }
+    }
+
+    /// Spend and cap of the request's subject (see [`QuotaEnricher::subject`])
+    pub fn status(&self, request: &RequestContext) -> BudgetStatus {
+        self.budgets.check(
+            &request.tenant,
+            &subject_for(&self.devices, request),
+            request.timestamp,
+        )
+    }
+}
+
+impl Enricher for BudgetEnricher {
+    fn name(&self) -> &str {
+        "budget"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        input.insert("budget".to_string(), self.status(request).to_json());
+        Ok(())
+    }
+}
+
+/// Adds `history` (recent request and block counters for the device)
+pub struct HistoryEnricher {
+    usage: Arc<UsageSeries>,
+}
+
+impl HistoryEnricher {
+    /// Create from the usage series
+    pub fn new(usage: Arc<UsageSeries>) -> Self {
+        HistoryEnricher { usage }
+    }
+}
+
+impl Enricher for HistoryEnricher {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        let totals = |since| {
+            self.usage
+                .device_totals_since(since, &request.tenant, &request.client_ip)
+        };
+        let hour = totals(request.timestamp - Duration::hours(1));
+        let today = totals(local_midnight(request.timestamp)?);
+
+        input.insert(
+            "history".to_string(),
+            json!({
+                "requests_last_hour": hour.requests,
+                "tokens_last_hour": hour.tokens,
+                "requests_today": today.requests,
+                "blocks_today": today.blocks,
+            }),
+        );
+        Ok(())
+    }
+}
+
+/// Tag applied to prompts containing any of its keywords
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagRule {
+    /// Tag name (e.g., "homework")
+    pub tag: String,
+
+    /// Keywords or phrases (matched on normalized words)
+    pub keywords: Vec<String>,
+}
+
+/// Adds `tags` (classifier tags for the prompt)
+pub struct ClassifierEnricher {
+    rules: Vec<TagRule>,
+}
+
+impl ClassifierEnricher {
+    /// Create from keyword rules
+    pub fn new(rules: Vec<TagRule>) -> Self {
+        ClassifierEnricher { rules }
+    }
+}
+
+impl Enricher for ClassifierEnricher {
+    fn name(&self) -> &str {
+        "classifier"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        let text = request
+            .prompt_preview
+            .as_deref()
+            .map(dedup::normalize)
+            .unwrap_or_default();
+        // Pad so keywords only match on word boundaries
+        let padded = format!(" {} ", text);
+
+        let tags: Vec<&str> = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.keywords.iter().any(|k| {
+                    let k = dedup::normalize(k);
+                    !k.is_empty() && padded.contains(&format!(" {} ", k))
+                })
+            })
+            .map(|rule| rule.tag.as_str())
+            .collect();
+        input.insert("tags".to_string(), json!(tags));
+        Ok(())
+    }
+}
+
+/// Adds `jailbreak` (heuristic score of the prompt, see
+/// [`crate::jailbreak`])
+pub struct JailbreakEnricher {
+    detector: JailbreakDetector,
+}
+
+impl JailbreakEnricher {
+    /// Create with the configured threshold
+    pub fn new(config: &JailbreakConfig) -> Self {
+        JailbreakEnricher {
+            detector: JailbreakDetector::new(config),
+        }
+    }
+}
+
+impl Enricher for JailbreakEnricher {
+    fn name(&self) -> &str {
+        "jailbreak"
+    }
+
+    fn enrich(&self, request: &RequestContext, input: &mut Map<String, Value>) -> Result<()> {
+        // Floods and encoded payloads rarely fit in the preview
+        let text = match request.parsed.as_ref() {
+            Some(parsed) if !parsed.prompt.is_empty() => parsed.prompt.as_str(),
+            _ => request.prompt_preview.as_deref().unwrap_or_default(),
+        };
+        let report = self.detector.detect(text);
+        input.insert("jailbreak".to_string(), serde_json::to_value(report)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::test_request;
+
+    fn request(prompt: &str) -> RequestContext {
+        RequestContext {
+            prompt_preview: Some(prompt.to_string()),
+            ..test_request()
+        }
+    }
+
+    struct Failing;
+
+    impl Enricher for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn enrich(&self, _: &RequestContext, _: &mut Map<String, Value>) -> Result<()> {
+            anyhow::bail!("lookup timed out")
+        }
+    }
+
+    #[test]
+    fn test_pipeline_builds_enriched_input() {
+        let usage = Arc::new(UsageSeries::default());
+        usage.record_usage("default", "192.168.1.50", 1_500, false, 0.0);
+        usage.record_usage("default", "192.168.1.50", 500, true, 0.0);
+        let quotas = Arc::new(QuotaManager::default());
+        quotas.record("default", "sam", 1_500, Utc::now());
+        quotas.record("default", "sam", 500, Utc::now());
+        let budgets = Arc::new(BudgetTracker::new(crate::budget::BudgetConfig {
+            monthly_cap_usd: Some(5.0),
+            ..Default::default()
+        }));
+        budgets.record("default", "sam", 1.5, Utc::now());
+
+        let config = EnrichmentConfig {
+            devices: vec![DeviceProfile {
+                ip: "192.168.1.50".to_string(),
+                name: "sam-ipad".to_string(),
+                owner: Some("sam".to_string()),
+                group: Some("kids".to_string()),
+                daily_token_quota: Some(5_000),
+            }],
+            schedules: vec![Schedule {
+                name: "always".to_string(),
+                days: Vec::new(),
+                start: NaiveTime::MIN,
+                end: NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+            }],
+            daily_token_quota: Some(20_000),
+            tags: vec![TagRule {
+                tag: "homework".to_string(),
+                keywords: vec!["math homework".to_string(), "essay".to_string()],
+            }],
+            jailbreak: JailbreakConfig::default(),
+        };
+        let mut pipeline = EnrichmentPipeline::from_config(&config, usage, quotas, budgets);
+        pipeline.push(Failing);
+
+        let input = pipeline.build_input(&request("Help with my Math homework, please"));
+        assert_eq!(input["device"]["group"], "kids");
+        assert_eq!(input["quota"]["used_tokens"], 2_000);
+        assert_eq!(input["quota"]["remaining_tokens"], 3_000);
+        assert_eq!(input["quota"]["requests_today"], 2);
+        assert_eq!(input["quota"]["exceeded"], Value::Null);
+        assert_eq!(input["budget"]["spent_this_month"], 1.5);
+        assert_eq!(input["budget"]["remaining"], 3.5);
+        assert_eq!(input["history"]["requests_last_hour"], 2);
+        assert_eq!(input["history"]["blocks_today"], 1);
+        assert_eq!(input["tags"], json!(["homework"]));
+        assert_eq!(input["jailbreak"]["suspected"], false);
+        assert_eq!(input["enrichment"]["errors"][0]["stage"], "failing");
+    }
+
+    #[test]
+    fn test_parsed_body_fields_are_top_level() {
+        let mut req = request("Solve 2x=4");
+        assert_eq!(
+            EnrichmentPipeline::new().build_input(&req)["model"],
+            Value::Null
+        );
+
+        req.parsed = Some(crate::providers::parse_request(
+            crate::providers::Provider::OpenAI,
+            "api.openai.com",
+            "/v1/chat/completions",
+            br#"{"model":"gpt-4o","messages":[{"role":"user","content":"Solve 2x=4"}]}"#,
+        ));
+        let input = EnrichmentPipeline::new().build_input(&req);
+        assert_eq!(input["model"], "gpt-4o");
+        assert_eq!(input["messages"][0]["content"], "Solve 2x=4");
+        assert_eq!(input["stream"], false);
+    }
+
+    #[test]
+    fn test_schedule_window_past_midnight() {
+        let bedtime = Schedule {
+            name: "bedtime".to_string(),
+            days: vec![
+                Weekday::Sun,
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+            ],
+            start: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+        };
+        // 2026-03-05 is a Thursday
+        let at = |d: u32, h: u32| Local.with_ymd_and_hms(2026, 3, d, h, 0, 0).unwrap();
+
+        assert!(bedtime.is_active(at(5, 22)));
+        assert!(bedtime.is_active(at(6, 6)));
+        assert!(!bedtime.is_active(at(6, 22)));
+        assert!(!bedtime.is_active(at(7, 6)));
+        assert!(!bedtime.is_active(at(5, 12)));
+    }
+}
@@ -0,0 +1,355 @@
+//! Decision explanations: where a rule lives and what input it looked at
+//!
+//! When a request is blocked, the dashboard shows which Rego rule was
+//! responsible and links straight to it. The rule index is built from the
+//! policy sources when policies are loaded: every rule head is recorded
+//! with its file, line and the `input.*` paths referenced in its body.
+//! Explaining a decision then just means looking up the rules that fired
+//! and picking the referenced input fields out of the evaluated input.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::policytest::is_test_file;
+use crate::tenant::{is_valid_id, tenant_policy_dir, TENANT_POLICY_DIR};
+
+/// Where a rule is defined
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleLocation {
+    /// Rego package (e.g., "yori.bedtime")
+    pub package: String,
+
+    /// Rule name (e.g., "deny")
+    pub rule: String,
+
+    /// Policy file, relative to the policy directory
+    pub file: String,
+
+    /// 1-based line of the rule head
+    pub line: usize,
+}
+
+/// An input field referenced by a rule, with the value it had
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchedInput {
+    /// Dotted input path (e.g., "input.user.age")
+    pub path: String,
+
+    /// Value of the field in the evaluated input
+    pub value: serde_json::Value,
+}
+
+/// Why a decision came out the way it did
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Explanation {
+    /// Rules that produced the decision
+    pub rules: Vec<RuleLocation>,
+
+    /// Input fields those rules referenced
+    pub matched_inputs: Vec<MatchedInput>,
+}
+
+#[derive(Debug, Clone)]
+struct RuleDef {
+    location: RuleLocation,
+    input_refs: BTreeSet<String>,
+}
+
+/// Rule definitions of all loaded policy files
+#[derive(Debug, Clone, Default)]
+pub struct RuleIndex {
+    rules: Vec<RuleDef>,
+    files: Vec<String>,
+}
+
+impl RuleIndex {
+    /// Index every `.rego` file under `policy_dir` (recursively)
+    pub fn build(policy_dir: &Path) -> Result<Self> {
+        let mut index = RuleIndex::default();
+        for (file, source) in read_sources(policy_dir)? {
+            index.add_source(&file, &source);
+        }
+        Ok(index)
+    }
+
+    /// Index one policy source
+    pub fn add_source(&mut self, file: &str, source: &str) {
+        let mut package = String::new();
+        let mut current: Option<RuleDef> = None;
+
+        for (number, line) in source.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            let trimmed = code.trim();
+
+            if let Some(name) = trimmed.strip_prefix("package ") {
+                package = name.trim().to_string();
+                continue;
+            }
+            if trimmed.starts_with("import ") {
+                continue;
+            }
+
+            // Rule heads start in column 0; bodies are indented or braced
+            if !code.starts_with(char::is_whitespace) && !trimmed.is_empty() && trimmed != "}" {
+                if let Some(rule) = rule_name(trimmed) {
+                    self.rules.extend(current.take());
+                    current = Some(RuleDef {
+                        location: RuleLocation {
+                            package: package.clone(),
+                            rule,
+                            file: file.to_string(),
+                            line: number + 1,
+                        },
+                        input_refs: BTreeSet::new(),
+                    });
+                }
+            }
+            if let Some(def) = current.as_mut() {
+                def.input_refs.extend(input_refs(code));
+            }
+        }
+        self.rules.extend(current);
+        self.files.push(file.to_string());
+    }
+
+    /// Indexed policy files
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Every rule definition, in file order
+    pub fn rules(&self) -> impl Iterator<Item = &RuleLocation> {
+        self.rules.iter().map(|r| &r.location)
+    }
+
+    /// All definitions of a rule (a rule may be defined incrementally)
+    pub fn locate(&self, package: &str, rule: &str) -> Vec<&RuleLocation> {
+        self.rules
+            .iter()
+            .filter(|r| r.location.package == package && r.location.rule == rule)
+            .map(|r| &r.location)
+            .collect()
+    }
+
+    /// Explain a decision made by `rules` (each "package.rule") on `input`
+    ///
+    /// Only input fields that are present in `input` are reported.
+    pub fn explain(&self, rules: &[&str], input: &serde_json::Value) -> Explanation {
+        let mut explanation = Explanation::default();
+        let mut seen = BTreeSet::new();
+
+        for reference in rules {
+            let Some((package, rule)) = reference.rsplit_once('.') else {
+                continue;
+            };
+            let package = package.strip_prefix("data.").unwrap_or(package);
+            for def in self
+                .rules
+                .iter()
+                .filter(|r| r.location.package == package && r.location.rule == rule)
+            {
+                explanation.rules.push(def.location.clone());
+                for path in &def.input_refs {
+                    if let Some(value) = lookup(input, path) {
+                        if seen.insert(path.clone()) {
+                            explanation.matched_inputs.push(MatchedInput {
+                                path: path.clone(),
+                                value: value.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        explanation
+    }
+}
+
+/// Read every shared `.rego` file under `policy_dir` (recursively) as
+/// `(relative path, source)`, sorted by path
+///
+/// `*_test.rego` files are left out; see [`crate::policytest`]. So are the
+/// tenants' own policies, see [`read_tenant_sources`].
+pub(crate) fn read_sources(policy_dir: &Path) -> Result<Vec<(String, String)>> {
+    let tenants = format!("{}/", TENANT_POLICY_DIR);
+    let mut files = read_files(policy_dir, "policy", is_rego_source)?;
+    files.retain(|(file, _)| !file.starts_with(&tenants));
+    Ok(files)
+}
+
+/// Read each tenant's `.rego` files (see [`tenant_policy_dir`]), keyed by
+/// tenant id, with paths relative to `policy_dir`
+pub(crate) fn read_tenant_sources(
+    policy_dir: &Path,
+) -> Result<BTreeMap<String, Vec<(String, String)>>> {
+    let mut tenants = BTreeMap::new();
+    let root = policy_dir.join(TENANT_POLICY_DIR);
+    if !root.is_dir() {
+        return Ok(tenants);
+    }
+    let entries = fs::read_dir(&root)
+        .with_context(|| format!("failed to read policy directory {}", root.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(id) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !path.is_dir() || !is_valid_id(id) {
+            continue;
+        }
+        let files = read_files(&tenant_policy_dir(policy_dir, id), "policy", is_rego_source)?
+            .into_iter()
+            .map(|(file, source)| (format!("{}/{}/{}", TENANT_POLICY_DIR, id, file), source))
+            .collect();
+        tenants.insert(id.to_string(), files);
+    }
+    Ok(tenants)
+}
+
+fn is_rego_source(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "rego") && !is_test_file(path)
+}
+
+/// Read every file under `dir` (recursively) that `keep` accepts as
+/// `(relative path, contents)`, sorted by path
+pub(crate) fn read_files(
+    dir: &Path,
+    kind: &str,
+    keep: impl Fn(&Path) -> bool,
+) -> Result<Vec<(String, String)>> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        let entries = fs::read_dir(&current)
+            .with_context(|| format!("failed to read {} directory {}", kind, current.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if keep(&path) {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {} {}", kind, path.display()))?;
+                let relative = path
+                    .strip_prefix(dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                files.push((relative, contents));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Name of the rule defined by a head line, if it is one
+fn rule_name(head: &str) -> Option<String> {
+    let head = head.strip_prefix("default ").unwrap_or(head);
+    let name: String = head
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let rest = head[name.len()..].trim_start();
+    let is_head = rest.is_empty()
+        || ["{", ":=", "=", "[", "(", "if ", "if{", "contains "]
+            .iter()
+            .any(|p| rest.starts_with(p));
+    is_head.then_some(name)
+}
+
+/// `input.a.b` paths referenced on a line (bracket indexes are cut off)
+fn input_refs(code: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    let bytes = code.as_bytes();
+    let mut start = 0;
+    while let Some(offset) = code[start..].find("input.") {
+        let begin = start + offset;
+        let boundary = begin == 0 || {
+            let prev = bytes[begin - 1];
+            !(prev.is_ascii_alphanumeric() || prev == b'_' || prev == b'.')
+        };
+        let end = code[begin..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .map_or(code.len(), |e| begin + e);
+        let path = code[begin..end].trim_end_matches('.');
+        if boundary && path.len() > "input.".len() {
+            refs.push(path.to_string());
+        }
+        start = end.max(begin + 1);
+    }
+    refs
+}
+
+fn lookup<'a>(input: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.strip_prefix("input.")?
+        .split('.')
+        .try_fold(input, |value, key| value.get(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BEDTIME: &str = r#"package yori.bedtime
+
+import rego.v1
+
+default allow := true
+
+# No LLMs for kids after 21:00
+deny contains msg if {
+    input.user.group == "kids"
+    input.time.hour >= 21
+    msg := "bedtime"
+}
+
+allow := false if {
+    count(deny) > 0
+}
+"#;
+
+    #[test]
+    fn test_index_records_rule_lines() {
+        let mut index = RuleIndex::default();
+        index.add_source("bedtime.rego", BEDTIME);
+
+        let allow: Vec<usize> = index
+            .locate("yori.bedtime", "allow")
+            .iter()
+            .map(|l| l.line)
+            .collect();
+        assert_eq!(allow, vec![5, 14]);
+        assert_eq!(index.locate("yori.bedtime", "deny")[0].line, 8);
+        assert!(index.locate("yori.bedtime", "msg").is_empty());
+    }
+
+    #[test]
+    fn test_explain_picks_referenced_inputs() {
+        let mut index = RuleIndex::default();
+        index.add_source("bedtime.rego", BEDTIME);
+
+        let input = json!({
+            "user": {"group": "kids", "name": "sam"},
+            "time": {"hour": 22},
+            "endpoint": "api.openai.com"
+        });
+        let explanation = index.explain(&["data.yori.bedtime.deny"], &input);
+
+        assert_eq!(explanation.rules.len(), 1);
+        assert_eq!(explanation.rules[0].file, "bedtime.rego");
+        let paths: Vec<&str> = explanation
+            .matched_inputs
+            .iter()
+            .map(|m| m.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["input.time.hour", "input.user.group"]);
+        assert_eq!(explanation.matched_inputs[1].value, json!("kids"));
+    }
+}
@@ -0,0 +1,392 @@
+//! Audit event export for offline analysis
+//!
+//! A year of audit logs is far more than a router has memory for, so
+//! exports never load the result set: [`AuditLogger::export`] reads matching
+//! events in batches (holding the database lock only per batch) and writes
+//! each batch out before reading the next.
+//!
+//! - **JSONL**: one JSON object per event, as returned by
+//!   [`AuditLogger::query`]
+//! - **CSV**: header row of column names, RFC 4180 quoting, empty for NULL
+//! - **Parquet**: typed columns (INTEGER → Int64, REAL → Float64, otherwise
+//!   UTF-8), Snappy-compressed, for pandas/DuckDB/Spark. Needs the
+//!   `parquet` feature.
+//!
+//! Columns follow the `audit_events` table, so columns added by later
+//! migrations are exported without changes here.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::audit::{sql_to_json, AuditLogger, AuditQuery};
+
+/// Events read from the database per batch
+const EXPORT_BATCH_ROWS: usize = 1000;
+
+/// File format of an audit export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Newline-delimited JSON objects
+    Jsonl,
+
+    /// Comma-separated values with a header row
+    Csv,
+
+    /// Apache Parquet (requires the `parquet` feature)
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Name used in configuration and the CLI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Format implied by a file extension (".jsonl", ".csv", ".parquet")
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => bail!(
+                "unknown export format {:?} (expected jsonl, csv or parquet)",
+                other
+            ),
+        }
+    }
+}
+
+/// Outcome of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExportReport {
+    /// Events written
+    pub rows: u64,
+
+    /// Wall-clock time of the export
+    pub duration_ms: u64,
+}
+
+impl AuditLogger {
+    /// Write events matching `query` to `out`, oldest first
+    ///
+    /// The query's `limit` caps the number of events; its `offset` is
+    /// ignored.
+    pub fn export<W: Write + Send>(
+        &self,
+        query: &AuditQuery,
+        format: ExportFormat,
+        out: W,
+    ) -> Result<ExportReport> {
+        let started = Instant::now();
+        let rows = match format {
+            ExportFormat::Jsonl => self.export_jsonl(query, out)?,
+            ExportFormat::Csv => self.export_csv(query, out)?,
+            ExportFormat::Parquet => self.export_parquet(query, out)?,
+        };
+        Ok(ExportReport {
+            rows,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Export to a new file, replacing any existing one
+    pub fn export_file(
+        &self,
+        query: &AuditQuery,
+        format: ExportFormat,
+        path: &Path,
+    ) -> Result<ExportReport> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        self.export(query, format, file)
+            .with_context(|| format!("failed to export audit events to {}", path.display()))
+    }
+
+    fn export_jsonl<W: Write>(&self, query: &AuditQuery, out: W) -> Result<u64> {
+        let columns = self.columns()?;
+        let mut out = BufWriter::new(out);
+        let rows = self.scan(query, EXPORT_BATCH_ROWS, |rows| {
+            for row in rows {
+                let record: serde_json::Map<_, _> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| (column.name.clone(), sql_to_json(value.into())))
+                    .collect();
+                serde_json::to_writer(&mut out, &record)?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(rows)
+    }
+
+    fn export_csv<W: Write>(&self, query: &AuditQuery, out: W) -> Result<u64> {
+        let columns = self.columns()?;
+        let mut out = BufWriter::new(out);
+        let header: Vec<String> = columns.iter().map(|c| csv_field(&c.name)).collect();
+        writeln!(out, "{}", header.join(","))?;
+        let rows = self.scan(query, EXPORT_BATCH_ROWS, |rows| {
+            for row in rows {
+                let fields: Vec<String> = row
+                    .iter()
+                    .map(|value| match sql_to_json(value.into()) {
+                        serde_json::Value::Null => String::new(),
+                        serde_json::Value::String(s) => csv_field(&s),
+                        other => csv_field(&other.to_string()),
+                    })
+                    .collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(rows)
+    }
+
+    #[cfg(feature = "parquet")]
+    fn export_parquet<W: Write + Send>(&self, query: &AuditQuery, out: W) -> Result<u64> {
+        let columns = self.columns()?;
+        let schema = parquet_export::schema(&columns);
+        let mut writer = parquet_export::writer(out, schema.clone())?;
+        let rows = self.scan(query, EXPORT_BATCH_ROWS, |rows| {
+            let batch = parquet_export::record_batch(&columns, &schema, rows)?;
+            writer.write(&batch)?;
+            Ok(())
+        })?;
+        writer.close()?;
+        Ok(rows)
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn export_parquet<W: Write + Send>(&self, _query: &AuditQuery, _out: W) -> Result<u64> {
+        bail!("yori-core was built without the parquet feature")
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_export {
+    use anyhow::Result;
+    use arrow_array::builder::{Float64Builder, Int64Builder, StringBuilder};
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use rusqlite::types::Value;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use crate::audit::{sql_to_json, AuditColumn};
+
+    /// Rows per row group; bounds what the writer buffers before flushing
+    const ROW_GROUP_ROWS: usize = 10_000;
+
+    fn data_type(column: &AuditColumn) -> DataType {
+        // SQLite type affinity: "INT" anywhere means integer
+        if column.decl_type.contains("INT") || column.decl_type == "BOOLEAN" {
+            DataType::Int64
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|t| column.decl_type.contains(t))
+        {
+            DataType::Float64
+        } else {
+            DataType::Utf8
+        }
+    }
+
+    pub(super) fn schema(columns: &[AuditColumn]) -> SchemaRef {
+        Arc::new(Schema::new(
+            columns
+                .iter()
+                .map(|c| Field::new(&c.name, data_type(c), true))
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    pub(super) fn writer<W: Write + Send>(out: W, schema: SchemaRef) -> Result<ArrowWriter<W>> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .build();
+        Ok(ArrowWriter::try_new(out, schema, Some(properties))?)
+    }
+
+    /// Convert a batch of rows, coercing values SQLite stored with another
+    /// type than the column's declaration (or NULL where that's impossible)
+    pub(super) fn record_batch(
+        columns: &[AuditColumn],
+        schema: &SchemaRef,
+        rows: &[Vec<Value>],
+    ) -> Result<RecordBatch> {
+        let arrays = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| -> ArrayRef {
+                let values = rows.iter().map(|row| &row[i]);
+                match data_type(column) {
+                    DataType::Int64 => {
+                        let mut builder = Int64Builder::with_capacity(rows.len());
+                        for value in values {
+                            builder.append_option(match value {
+                                Value::Integer(n) => Some(*n),
+                                Value::Real(f) => Some(*f as i64),
+                                Value::Text(t) => t.parse().ok(),
+                                _ => None,
+                            });
+                        }
+                        Arc::new(builder.finish())
+                    }
+                    DataType::Float64 => {
+                        let mut builder = Float64Builder::with_capacity(rows.len());
+                        for value in values {
+                            builder.append_option(match value {
+                                Value::Integer(n) => Some(*n as f64),
+                                Value::Real(f) => Some(*f),
+                                Value::Text(t) => t.parse().ok(),
+                                _ => None,
+                            });
+                        }
+                        Arc::new(builder.finish())
+                    }
+                    _ => {
+                        let mut builder = StringBuilder::new();
+                        for value in values {
+                            match sql_to_json(value.into()) {
+                                serde_json::Value::Null => builder.append_null(),
+                                serde_json::Value::String(s) => builder.append_value(s),
+                                other => builder.append_value(other.to_string()),
+                            }
+                        }
+                        Arc::new(builder.finish())
+                    }
+                }
+            })
+            .collect();
+        Ok(RecordBatch::try_new(Arc::clone(schema), arrays)?)
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::tests::{memory_logger, request};
+
+    #[test]
+    fn test_scan_walks_batches_oldest_first() {
+        let logger = memory_logger();
+        for i in 0..5 {
+            logger.log(&request(&format!("prompt {i}"))).unwrap();
+        }
+        let mut batches = Vec::new();
+        let rows = logger
+            .scan(&AuditQuery::default(), 2, |rows| {
+                batches.push(rows.len());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(rows, 5);
+        assert_eq!(batches, vec![2, 2, 1]);
+
+        let capped = AuditQuery {
+            limit: Some(3),
+            ..AuditQuery::default()
+        };
+        assert_eq!(logger.scan(&capped, 2, |_| Ok(())).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_jsonl_and_csv_exports() {
+        let logger = memory_logger();
+        logger.log(&request("first")).unwrap();
+        logger
+            .log(&request("say \"hi\", please").with_policy("bedtime", "block", "after 9pm"))
+            .unwrap();
+
+        let mut jsonl = Vec::new();
+        let report = logger
+            .export(&AuditQuery::default(), ExportFormat::Jsonl, &mut jsonl)
+            .unwrap();
+        assert_eq!(report.rows, 2);
+        let lines: Vec<serde_json::Value> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["policy_name"], "bedtime");
+
+        let blocked = AuditQuery {
+            policy_result: Some("block".to_string()),
+            ..AuditQuery::default()
+        };
+        let mut csv = Vec::new();
+        logger
+            .export(&blocked, ExportFormat::Csv, &mut csv)
+            .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("id,timestamp,event_type,"));
+        assert!(lines.next().unwrap().contains(",bedtime,block,"));
+        assert_eq!(lines.next(), None);
+        assert_eq!(
+            csv_field("say \"hi\", please"),
+            "\"say \"\"hi\"\", please\""
+        );
+
+        assert_eq!(
+            ExportFormat::from_path(Path::new("audit-2026.PARQUET")),
+            Some(ExportFormat::Parquet)
+        );
+        assert!("xlsx".parse::<ExportFormat>().is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_export_round_trips_row_count() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let logger = memory_logger();
+        for i in 0..3 {
+            logger.log(&request(&format!("prompt {i}"))).unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.parquet");
+        let report = logger
+            .export_file(&AuditQuery::default(), ExportFormat::Parquet, &path)
+            .unwrap();
+        assert_eq!(report.rows, 3);
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+    }
+}
@@ -0,0 +1,290 @@
+//! Synthetic "honeypot" responses for blocked categories
+//!
+//! A bare 403 invites a frustrated kid to go hunting for a workaround. For
+//! categories where the policy says so, YORI can instead answer the
+//! request locally with a gentle, clearly labeled refusal shaped like a
+//! normal completion from the provider the client was talking to, so the
+//! app shows a message instead of an error.
+//!
+//! Policies opt in per decision with an obligation:
+//!
+//! ```rego
+//! obligations := {"honeypot": "homework_cheating"}
+//! ```
+//!
+//! Responses are always labeled: the model is reported as `yori-local`,
+//! the text starts with [`HoneypotConfig::label`] and the HTTP response
+//! carries an `X-Yori-Synthetic: 1` header.
+
+use chrono::Local;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::proxy::RequestContext;
+
+/// Model name reported in synthetic responses
+pub const SYNTHETIC_MODEL: &str = "yori-local";
+
+/// Header marking a synthetic response
+pub const SYNTHETIC_HEADER: &str = "x-yori-synthetic";
+
+/// Honeypot settings
+#[derive(Debug, Clone)]
+pub struct HoneypotConfig {
+    /// Serve synthetic responses at all
+    pub enabled: bool,
+
+    /// Prefix that labels every synthetic message
+    pub label: String,
+
+    /// Category -> message template; `{category}`, `{device}`, `{reason}`
+    /// and `{time}` are substituted
+    pub templates: HashMap<String, String>,
+
+    /// Template for categories without their own
+    pub default_template: String,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        HoneypotConfig {
+            enabled: false,
+            label: "[Message from your home network]".to_string(),
+            templates: HashMap::new(),
+            default_template: "I can't help with that one right now. {reason} \
+                 If you think this is a mistake, ask a parent to take a look."
+                .to_string(),
+        }
+    }
+}
+
+/// A locally generated response
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticResponse {
+    /// HTTP status
+    pub status: u16,
+
+    /// Response headers
+    pub headers: Vec<(String, String)>,
+
+    /// Response body
+    pub body: String,
+}
+
+/// Builds provider-shaped synthetic responses
+#[derive(Debug, Clone, Default)]
+pub struct HoneypotResponder {
+    config: HoneypotConfig,
+}
+
+impl HoneypotResponder {
+    /// Create from configuration
+    pub fn new(config: HoneypotConfig) -> Self {
+        HoneypotResponder { config }
+    }
+
+    /// Honeypot category requested by a decision's obligations, if any
+    ///
+    /// Returns None when the mode is disabled.
+    pub fn category<'a>(&self, obligations: &'a Value) -> Option<&'a str> {
+        if !self.config.enabled {
+            return None;
+        }
+        obligations
+            .get("honeypot")?
+            .as_str()
+            .filter(|c| !c.is_empty())
+    }
+
+    /// Message text for a category
+    pub fn message(&self, category: &str, request: &RequestContext, reason: &str) -> String {
+        let template = self
+            .config
+            .templates
+            .get(category)
+            .unwrap_or(&self.config.default_template);
+        let text = template
+            .replace("{category}", category)
+            .replace(
+                "{device}",
+                request.client_device.as_deref().unwrap_or("this device"),
+            )
+            .replace("{reason}", reason)
+            .replace("{time}", &Local::now().format("%H:%M").to_string());
+        format!("{} {}", self.config.label, text.trim())
+    }
+
+    /// Render a response in the shape the client's provider uses
+    ///
+    /// `stream` selects the provider's streaming (SSE) format.
+    pub fn render(
+        &self,
+        category: &str,
+        request: &RequestContext,
+        reason: &str,
+        stream: bool,
+    ) -> SyntheticResponse {
+        let text = self.message(category, request, reason);
+        let id = format!("yori-{}", uuid::Uuid::new_v4().simple());
+        let anthropic = request.endpoint.contains("anthropic.com");
+
+        let (content_type, body) = match (anthropic, stream) {
+            (false, false) => ("application/json", openai_body(&id, &text).to_string()),
+            (false, true) => ("text/event-stream", openai_stream(&id, &text)),
+            (true, false) => ("application/json", anthropic_body(&id, &text).to_string()),
+            (true, true) => ("text/event-stream", anthropic_stream(&id, &text)),
+        };
+
+        SyntheticResponse {
+            status: 200,
+            headers: vec![
+                ("content-type".to_string(), content_type.to_string()),
+                (SYNTHETIC_HEADER.to_string(), "1".to_string()),
+                ("x-yori-category".to_string(), category.to_string()),
+            ],
+            body,
+        }
+    }
+}
+
+fn openai_body(id: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": SYNTHETIC_MODEL,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": text},
+            "finish_reason": "stop",
+        }],
+        "usage": {"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0},
+    })
+}
+
+fn openai_stream(id: &str, text: &str) -> String {
+    let chunk = |delta: Value, finish: Value| {
+        json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": chrono::Utc::now().timestamp(),
+            "model": SYNTHETIC_MODEL,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish}],
+        })
+    };
+    format!(
+        "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+        chunk(json!({"role": "assistant", "content": text}), Value::Null),
+        chunk(json!({}), json!("stop")),
+    )
+}
+
+fn anthropic_body(id: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "message",
+        "role": "assistant",
+        "model": SYNTHETIC_MODEL,
+        "content": [{"type": "text", "text": text}],
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 0, "output_tokens": 0},
+    })
+}
+
+fn anthropic_stream(id: &str, text: &str) -> String {
+    let events = [
+        (
+            "message_start",
+            json!({"type": "message_start", "message": {
+                "id": id, "type": "message", "role": "assistant", "model": SYNTHETIC_MODEL,
+                "content": [], "usage": {"input_tokens": 0, "output_tokens": 0},
+            }}),
+        ),
+        (
+            "content_block_start",
+            json!({"type": "content_block_start", "index": 0,
+                   "content_block": {"type": "text", "text": ""}}),
+        ),
+        (
+            "content_block_delta",
+            json!({"type": "content_block_delta", "index": 0,
+                   "delta": {"type": "text_delta", "text": text}}),
+        ),
+        (
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": 0}),
+        ),
+        (
+            "message_delta",
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"},
+                   "usage": {"output_tokens": 0}}),
+        ),
+        ("message_stop", json!({"type": "message_stop"})),
+    ];
+    events
+        .iter()
+        .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::test_request;
+
+    fn request(endpoint: &str) -> RequestContext {
+        RequestContext {
+            client_device: Some("sam-ipad".to_string()),
+            endpoint: endpoint.to_string(),
+            ..test_request()
+        }
+    }
+
+    fn responder() -> HoneypotResponder {
+        HoneypotResponder::new(HoneypotConfig {
+            enabled: true,
+            templates: HashMap::from([(
+                "homework_cheating".to_string(),
+                "Let's work through it together instead of copying an answer on {device}."
+                    .to_string(),
+            )]),
+            ..HoneypotConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_category_from_obligations() {
+        let obligations = json!({"honeypot": "homework_cheating"});
+        assert_eq!(
+            responder().category(&obligations),
+            Some("homework_cheating")
+        );
+        assert_eq!(responder().category(&json!({})), None);
+        assert_eq!(HoneypotResponder::default().category(&obligations), None);
+    }
+
+    #[test]
+    fn test_render_provider_shapes() {
+        let openai = responder().render("homework_cheating", &request("api.openai.com"), "", false);
+        let body: Value = serde_json::from_str(&openai.body).unwrap();
+        let text = body["choices"][0]["message"]["content"].as_str().unwrap();
+        assert!(text.starts_with("[Message from your home network]"));
+        assert!(text.contains("sam-ipad"));
+        assert_eq!(body["model"], SYNTHETIC_MODEL);
+        assert!(openai
+            .headers
+            .contains(&(SYNTHETIC_HEADER.to_string(), "1".to_string())));
+
+        let claude = responder().render(
+            "violence",
+            &request("api.anthropic.com"),
+            "It's past bedtime.",
+            true,
+        );
+        assert!(claude.body.starts_with("event: message_start"));
+        assert!(claude.body.contains("It's past bedtime."));
+        assert!(claude
+            .body
+            .ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+    }
+}
@@ -0,0 +1,735 @@
+//! Device identity from DHCP leases, the ARP table and a MAC mapping
+//!
+//! Client IPs change with every DHCP renewal, so policies written against
+//! them break. The router already knows which hardware holds each address:
+//! this module reads its DHCP leases (ISC dhcpd, dnsmasq or Kea CSV, v4 and
+//! v6), ARP table and IPv6 neighbor table to find the MAC address behind a
+//! client IP, then looks the MAC up in a user-maintained mapping:
+//!
+//! ```yaml
+//! devices:
+//!   - mac: "a4:83:e7:12:34:56"
+//!     name: "Timmy's iPad"
+//!     owner: timmy
+//!     group: kids
+//!     prefixes: ["2001:db8:0:20::/64"]
+//! ```
+//!
+//! so policies can target `input.device.owner == "timmy"` instead of an
+//! address. Devices missing from the mapping are still named after their
+//! DHCP hostname, or whatever they announce over mDNS/NetBIOS
+//! ([`crate::discovery`]).
+//!
+//! SLAAC privacy addresses rotate daily and only reach the neighbor table
+//! once the device has talked from them. A device with a prefix of its own
+//! (its own VLAN, a delegated prefix, a static suffix) can list it under
+//! `prefixes`; any address inside is that device, the most specific prefix
+//! winning.
+
+use anyhow::{bail, Context, Result};
+use ipnet::IpNet;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::netaddr::parse_client_ip;
+
+/// Where OPNsense/pfSense keep DHCP leases (ISC dhcpd, dnsmasq, Kea)
+pub const DEFAULT_LEASE_FILES: &[&str] = &[
+    "/var/dhcpd/var/db/dhcpd.leases",
+    "/var/db/dnsmasq.leases",
+    "/var/db/kea/kea-leases4.csv",
+    "/var/db/kea/kea-leases6.csv",
+];
+
+/// How a client address was tied to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentitySource {
+    /// Active DHCP lease
+    Dhcp,
+
+    /// ARP table entry (static addresses, leases from another server)
+    Arp,
+
+    /// IPv6 neighbor table entry (SLAAC addresses)
+    Ndp,
+
+    /// Address inside a prefix the mapping gives the device
+    Prefix,
+
+    /// WireGuard peer owning the tunnel address
+    WireGuard,
+
+    /// Name the device announced over mDNS
+    Mdns,
+
+    /// Name the device announced over NetBIOS
+    NetBios,
+
+    /// Credentials the client gave the proxy (see [`crate::proxyauth`])
+    #[serde(rename = "proxy_auth")]
+    ProxyAuth,
+}
+
+impl IdentitySource {
+    /// Name used in policy input and the Python API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdentitySource::Dhcp => "dhcp",
+            IdentitySource::Arp => "arp",
+            IdentitySource::Ndp => "ndp",
+            IdentitySource::Prefix => "prefix",
+            IdentitySource::WireGuard => "wireguard",
+            IdentitySource::Mdns => "mdns",
+            IdentitySource::NetBios => "netbios",
+            IdentitySource::ProxyAuth => "proxy_auth",
+        }
+    }
+}
+
+/// A device as far as the router can tell
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceIdentity {
+    /// Hardware address, lowercase and colon-separated
+    pub mac: Option<String>,
+
+    /// Name from the mapping, else the DHCP hostname, else the name the
+    /// device announced over mDNS/NetBIOS
+    pub name: Option<String>,
+
+    /// Hostname the device announced over DHCP
+    pub hostname: Option<String>,
+
+    /// Person the device belongs to
+    pub owner: Option<String>,
+
+    /// Group (e.g., "kids", "adults")
+    pub group: Option<String>,
+
+    /// Whether the MAC is in the user's mapping
+    pub known: bool,
+
+    /// How the address was tied to the device
+    pub source: IdentitySource,
+}
+
+/// A user-maintained MAC mapping entry
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KnownDevice {
+    /// Hardware address (any common notation)
+    pub mac: String,
+
+    /// Device name (e.g., "Timmy's iPad")
+    pub name: String,
+
+    /// Person the device belongs to
+    #[serde(default)]
+    pub owner: Option<String>,
+
+    /// Group (e.g., "kids", "adults")
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// Networks whose every address is this device (e.g., its IPv6 /64)
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+/// The mapping file: a bare list or a `devices:` key
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MappingFile {
+    Wrapped { devices: Vec<KnownDevice> },
+    List(Vec<KnownDevice>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Lease {
+    mac: String,
+    hostname: Option<String>,
+}
+
+/// Client address to device lookup table
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// devices = yori_core.DeviceRegistry()
+/// devices.load_mapping(open("/usr/local/etc/yori/devices.yaml").read())
+/// devices.refresh()  # DHCP lease files and `arp -an`
+///
+/// device = devices.identify("192.168.1.50")
+/// if device is not None:
+///     print(device["name"], device["owner"])  # "Timmy's iPad" "timmy"
+/// ```
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRegistry {
+    leases: HashMap<IpAddr, Lease>,
+    /// ARP and IPv6 neighbor entries
+    arp: HashMap<IpAddr, String>,
+    known: HashMap<String, KnownDevice>,
+    /// Mapping prefixes and the MAC they belong to
+    prefixes: Vec<(IpNet, String)>,
+}
+
+impl DeviceRegistry {
+    /// Parse a MAC mapping (YAML) and merge its devices
+    pub fn merge_mapping(&mut self, yaml: &str) -> Result<usize> {
+        let devices = match serde_yaml::from_str(yaml).context("invalid device mapping")? {
+            MappingFile::Wrapped { devices } | MappingFile::List(devices) => devices,
+        };
+        let count = devices.len();
+        for device in devices {
+            self.set_known_device(device)?;
+        }
+        Ok(count)
+    }
+
+    /// Add or replace one mapping entry
+    pub fn set_known_device(&mut self, mut device: KnownDevice) -> Result<()> {
+        device.mac = normalize_mac(&device.mac)
+            .with_context(|| format!("device {:?} has an invalid MAC address", device.name))?;
+        let prefixes = device
+            .prefixes
+            .iter()
+            .map(|prefix| {
+                prefix
+                    .parse::<IpNet>()
+                    .map(|net| net.trunc())
+                    .with_context(|| {
+                        format!(
+                            "device {:?} has an invalid prefix {:?}",
+                            device.name, prefix
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.prefixes.retain(|(_, mac)| *mac != device.mac);
+        self.prefixes
+            .extend(prefixes.into_iter().map(|net| (net, device.mac.clone())));
+        self.known.insert(device.mac.clone(), device);
+        Ok(())
+    }
+
+    /// Parse a DHCP lease file and merge its active leases
+    ///
+    /// ISC dhcpd (`lease ... { }` blocks), dnsmasq (one lease per line) and
+    /// Kea memfile CSV are recognized by their content.
+    pub fn merge_leases(&mut self, text: &str) -> Result<usize> {
+        let leases = if text.trim_start().starts_with("address,") {
+            parse_kea_csv(text)?
+        } else if text.contains("lease ") && text.contains('{') {
+            parse_isc_leases(text)
+        } else {
+            parse_dnsmasq_leases(text)
+        };
+        let count = leases.len();
+        for (ip, lease) in leases {
+            match lease {
+                Some(lease) => self.leases.insert(ip, lease),
+                // Released or expired later in the file
+                None => self.leases.remove(&ip),
+            };
+        }
+        Ok(count)
+    }
+
+    /// Parse ARP table output and merge its complete entries
+    ///
+    /// Accepts BSD `arp -an` output and Linux `/proc/net/arp`.
+    pub fn merge_arp(&mut self, text: &str) -> usize {
+        let mut count = 0;
+        for line in text.lines() {
+            let entry = if line.trim_start().starts_with('?') || line.contains(") at ") {
+                // ? (192.168.1.50) at a4:83:e7:12:34:56 on igb1 expires in 1185 seconds [ethernet]
+                let ip = line.split(['(', ')']).nth(1);
+                let mac = line
+                    .split(" at ")
+                    .nth(1)
+                    .and_then(|s| s.split_whitespace().next());
+                ip.zip(mac)
+            } else {
+                // 192.168.1.50  0x1  0x2  a4:83:e7:12:34:56  *  eth0
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                (fields.len() >= 4 && fields[2] != "0x0").then(|| (fields[0], fields[3]))
+            };
+            let Some((ip, mac)) = entry else {
+                continue;
+            };
+            let (Ok(ip), Some(mac)) = (ip.parse::<IpAddr>(), normalize_mac(mac)) else {
+                continue;
+            };
+            if mac != "00:00:00:00:00:00" {
+                self.arp.insert(ip, mac);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Parse IPv6 neighbor table output and merge its resolved entries
+    ///
+    /// Accepts FreeBSD `ndp -an` and Linux `ip -6 neigh` output.
+    pub fn merge_ndp(&mut self, text: &str) -> usize {
+        let mut count = 0;
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let mac = match fields.iter().position(|f| *f == "lladdr") {
+                // 2001:db8::5 dev eth0 lladdr a4:83:e7:12:34:56 REACHABLE
+                Some(i) => fields.get(i + 1),
+                // 2001:db8::5  a4:83:e7:12:34:56  igb1 23h59m58s S R
+                None => fields.get(1),
+            };
+            let ip = fields.first().and_then(|ip| parse_client_ip(ip));
+            let (Some(ip), Some(mac)) = (ip, mac.and_then(|mac| normalize_mac(mac))) else {
+                continue;
+            };
+            self.arp.insert(ip, mac);
+            count += 1;
+        }
+        count
+    }
+
+    /// Re-read the lease files that exist and the system ARP table,
+    /// replacing what was loaded before (the mapping is kept)
+    pub fn refresh_from_system(&mut self, lease_files: &[PathBuf]) -> Result<usize> {
+        let mut leases = DeviceRegistry::default();
+        for path in lease_files.iter().filter(|p| p.exists()) {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            leases
+                .merge_leases(&text)
+                .with_context(|| format!("invalid lease file {}", path.display()))?;
+        }
+        self.leases = leases.leases;
+
+        let arp = command_output("arp", &["-an"])?;
+        self.arp.clear();
+        self.merge_arp(&arp);
+        // `ndp` on FreeBSD, iproute2 on Linux; an IPv4-only host may have
+        // neither
+        match command_output("ndp", &["-an"])
+            .or_else(|_| command_output("ip", &["-6", "neigh", "show"]))
+        {
+            Ok(neighbors) => {
+                self.merge_ndp(&neighbors);
+            }
+            Err(e) => tracing::debug!("No IPv6 neighbor table: {:#}", e),
+        }
+        Ok(self.leases.len() + self.arp.len())
+    }
+
+    /// Identify the device holding a client address
+    ///
+    /// A DHCP lease wins over the ARP/neighbor tables, which win over
+    /// mapping prefixes; None if none of them knows the address.
+    pub fn identify(&self, ip: IpAddr) -> Option<DeviceIdentity> {
+        let ip = ip.to_canonical();
+        let (mac, hostname, source) = match (self.leases.get(&ip), self.arp.get(&ip)) {
+            (Some(lease), _) => (&lease.mac, lease.hostname.clone(), IdentitySource::Dhcp),
+            (None, Some(mac)) if ip.is_ipv6() => (mac, None, IdentitySource::Ndp),
+            (None, Some(mac)) => (mac, None, IdentitySource::Arp),
+            (None, None) => {
+                let (_, mac) = self
+                    .prefixes
+                    .iter()
+                    .filter(|(net, _)| net.contains(&ip))
+                    .max_by_key(|(net, _)| net.prefix_len())?;
+                (mac, None, IdentitySource::Prefix)
+            }
+        };
+        // The lease for this MAC may name the device even when the address
+        // came from ARP (e.g., a static IP on a DHCP-known device)
+        let hostname = hostname.or_else(|| {
+            self.leases
+                .values()
+                .find(|l| &l.mac == mac)
+                .and_then(|l| l.hostname.clone())
+        });
+        let known = self.known.get(mac);
+        Some(DeviceIdentity {
+            mac: Some(mac.clone()),
+            name: known.map(|d| d.name.clone()).or_else(|| hostname.clone()),
+            hostname,
+            owner: known.and_then(|d| d.owner.clone()),
+            group: known.and_then(|d| d.group.clone()),
+            known: known.is_some(),
+            source,
+        })
+    }
+
+    /// Mapping entries
+    pub fn known_devices(&self) -> impl Iterator<Item = &KnownDevice> {
+        self.known.values()
+    }
+}
+
+/// Standard output of a system command
+fn command_output(program: &str, args: &[&str]) -> Result<String> {
+    let command = format!("`{} {}`", program, args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run {}", command))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lowercase colon-separated MAC from `AA-BB-...`, `aa:b:cc:...` etc.
+fn normalize_mac(mac: &str) -> Option<String> {
+    let parts: Vec<&str> = mac.trim().split([':', '-']).collect();
+    if parts.len() != 6
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || p.len() > 2 || !p.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+    Some(
+        parts
+            .iter()
+            .map(|p| format!("{:0>2}", p.to_ascii_lowercase()))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+/// ISC dhcpd leases; later blocks for an address supersede earlier ones
+fn parse_isc_leases(text: &str) -> Vec<(IpAddr, Option<Lease>)> {
+    let mut leases = Vec::new();
+    let mut current: Option<(IpAddr, Option<String>, Option<String>, bool)> = None;
+    for line in text.lines() {
+        let line = line.trim().trim_end_matches(';');
+        if let Some(rest) = line.strip_prefix("lease ") {
+            current = rest
+                .trim_end_matches('{')
+                .trim()
+                .parse()
+                .ok()
+                .map(|ip| (ip, None, None, true));
+        } else if line == "}" {
+            if let Some((ip, mac, hostname, active)) = current.take() {
+                let lease = mac.filter(|_| active).map(|mac| Lease { mac, hostname });
+                leases.push((ip, lease));
+            }
+        } else if let Some((_, mac, hostname, active)) = current.as_mut() {
+            if let Some(hw) = line.strip_prefix("hardware ethernet ") {
+                *mac = normalize_mac(hw);
+            } else if let Some(name) = line.strip_prefix("client-hostname ") {
+                *hostname = Some(name.trim_matches('"').to_string());
+            } else if let Some(state) = line.strip_prefix("binding state ") {
+                *active = state == "active";
+            }
+        }
+    }
+    leases
+}
+
+/// dnsmasq: `<expiry> <mac> <ip> <hostname or *> <client id>`
+fn parse_dnsmasq_leases(text: &str) -> Vec<(IpAddr, Option<Lease>)> {
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (mac, ip) = (normalize_mac(fields.get(1)?)?, fields.get(2)?.parse().ok()?);
+            let hostname = fields.get(3).filter(|h| **h != "*").map(|h| h.to_string());
+            Some((ip, Some(Lease { mac, hostname })))
+        })
+        .collect()
+}
+
+/// Kea memfile CSV; state 0 is an active lease
+fn parse_kea_csv(text: &str) -> Result<Vec<(IpAddr, Option<Lease>)>> {
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| *h == name)
+            .with_context(|| format!("Kea lease file has no {} column", name))
+    };
+    let (address, hwaddr, hostname, state) = (
+        column("address")?,
+        column("hwaddr")?,
+        column("hostname")?,
+        column("state")?,
+    );
+
+    Ok(lines
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let ip = fields.get(address)?.parse().ok()?;
+            let lease = normalize_mac(fields.get(hwaddr)?)
+                .filter(|_| fields.get(state).is_some_and(|s| *s == "0"))
+                .map(|mac| Lease {
+                    mac,
+                    hostname: fields
+                        .get(hostname)
+                        .filter(|h| !h.is_empty())
+                        .map(|h| h.trim_end_matches('.').to_string()),
+                });
+            Some((ip, lease))
+        })
+        .collect())
+}
+
+/// Identity as a Python dict
+pub(crate) fn identity_to_py(py: Python, device: &DeviceIdentity) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("mac", &device.mac)?;
+    dict.set_item("name", &device.name)?;
+    dict.set_item("hostname", &device.hostname)?;
+    dict.set_item("owner", &device.owner)?;
+    dict.set_item("group", &device.group)?;
+    dict.set_item("known", device.known)?;
+    dict.set_item("source", device.source.as_str())?;
+    Ok(dict.into())
+}
+
+#[pymethods]
+impl DeviceRegistry {
+    /// Create an empty registry
+    #[new]
+    fn new() -> Self {
+        DeviceRegistry::default()
+    }
+
+    /// Load the MAC → name/owner/group mapping (YAML)
+    ///
+    /// # Returns
+    ///
+    /// Number of devices in the mapping
+    fn load_mapping(&mut self, yaml: String) -> PyResult<usize> {
+        self.merge_mapping(&yaml)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Map one MAC address (and optionally IPv6 prefixes) to a device
+    #[pyo3(signature = (mac, name, owner=None, group=None, prefixes=None))]
+    fn set_device(
+        &mut self,
+        mac: String,
+        name: String,
+        owner: Option<String>,
+        group: Option<String>,
+        prefixes: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        self.set_known_device(KnownDevice {
+            mac,
+            name,
+            owner,
+            group,
+            prefixes: prefixes.unwrap_or_default(),
+        })
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Merge DHCP leases (ISC dhcpd, dnsmasq or Kea CSV file contents)
+    ///
+    /// # Returns
+    ///
+    /// Number of lease entries parsed
+    fn load_leases(&mut self, text: String) -> PyResult<usize> {
+        self.merge_leases(&text)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Merge ARP table output (`arp -an` or `/proc/net/arp`)
+    ///
+    /// # Returns
+    ///
+    /// Number of entries parsed
+    fn load_arp(&mut self, text: String) -> usize {
+        self.merge_arp(&text)
+    }
+
+    /// Merge IPv6 neighbor table output (`ndp -an` or `ip -6 neigh`)
+    ///
+    /// # Returns
+    ///
+    /// Number of entries parsed
+    fn load_ndp(&mut self, text: String) -> usize {
+        self.merge_ndp(&text)
+    }
+
+    /// Re-read DHCP leases and run `arp -an` (and `ndp -an`)
+    ///
+    /// # Arguments
+    ///
+    /// * `lease_files` - Lease files to read (default: the OPNsense
+    ///   locations for ISC dhcpd, dnsmasq and Kea; missing files are skipped)
+    #[pyo3(signature = (lease_files=None))]
+    fn refresh(&mut self, py: Python, lease_files: Option<Vec<String>>) -> PyResult<usize> {
+        let lease_files: Vec<PathBuf> = match lease_files {
+            Some(files) => files.into_iter().map(PathBuf::from).collect(),
+            None => DEFAULT_LEASE_FILES.iter().map(PathBuf::from).collect(),
+        };
+        // Runs `arp -an`; other Python threads keep going meanwhile
+        py.allow_threads(|| self.refresh_from_system(&lease_files))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Identify the device holding a client address
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `mac`, `name`, `hostname`, `owner`, `group`, `known`
+    /// and `source` ("dhcp", "arp", "ndp" or "prefix"), or None
+    #[pyo3(name = "identify")]
+    fn py_identify(&self, py: Python, client_ip: String) -> PyResult<Option<PyObject>> {
+        match parse_client_ip(&client_ip).and_then(|ip| self.identify(ip)) {
+            Some(device) => identity_to_py(py, &device).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPING: &str = "\
+devices:
+  - mac: A4-83-E7-12-34-56
+    name: Timmy's iPad
+    owner: timmy
+    group: kids
+";
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_lease_formats_and_mapping() {
+        let mut devices = DeviceRegistry::default();
+        assert_eq!(devices.merge_mapping(MAPPING).unwrap(), 1);
+
+        let isc = "\
+lease 192.168.1.50 {
+  binding state active;
+  hardware ethernet a4:83:e7:12:34:56;
+  client-hostname \"timmys-ipad\";
+}
+lease 192.168.1.60 {
+  binding state active;
+  hardware ethernet 3c:22:fb:00:00:01;
+}
+lease 192.168.1.60 {
+  binding state free;
+  hardware ethernet 3c:22:fb:00:00:01;
+}
+";
+        devices.merge_leases(isc).unwrap();
+        let timmy = devices.identify(ip("192.168.1.50")).unwrap();
+        assert_eq!(timmy.name.as_deref(), Some("Timmy's iPad"));
+        assert_eq!(timmy.hostname.as_deref(), Some("timmys-ipad"));
+        assert_eq!((timmy.owner.as_deref(), timmy.known), (Some("timmy"), true));
+        assert_eq!(timmy.source, IdentitySource::Dhcp);
+        assert!(devices.identify(ip("192.168.1.60")).is_none());
+
+        devices
+            .merge_leases("1741190400 3c:22:fb:00:00:02 192.168.1.61 living-room-tv *\n")
+            .unwrap();
+        devices
+            .merge_leases(
+                "address,hwaddr,client_id,valid_lifetime,expire,subnet_id,fqdn_fwd,fqdn_rev,hostname,state\n\
+                 192.168.1.62,3c:22:fb:00:00:03,,3600,1741190400,1,0,0,mums-laptop.,0\n",
+            )
+            .unwrap();
+        let tv = devices.identify(ip("192.168.1.61")).unwrap();
+        assert_eq!(
+            (tv.name.as_deref(), tv.known),
+            (Some("living-room-tv"), false)
+        );
+        let laptop = devices.identify(ip("192.168.1.62")).unwrap();
+        assert_eq!(laptop.name.as_deref(), Some("mums-laptop"));
+    }
+
+    #[test]
+    fn test_arp_fills_in_static_addresses() {
+        let mut devices = DeviceRegistry::default();
+        devices.merge_mapping(MAPPING).unwrap();
+        let bsd = "\
+? (192.168.1.5) at a4:83:e7:12:34:56 on igb1 expires in 1185 seconds [ethernet]
+? (192.168.1.6) at (incomplete) on igb1 expired [ethernet]
+";
+        let linux = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.7      0x1         0x2         3c:22:fb:0:0:4        *        eth0
+192.168.1.8      0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+        assert_eq!(devices.merge_arp(bsd), 1);
+        assert_eq!(devices.merge_arp(linux), 1);
+
+        let timmy = devices.identify(ip("192.168.1.5")).unwrap();
+        assert_eq!(timmy.source, IdentitySource::Arp);
+        assert_eq!(timmy.group.as_deref(), Some("kids"));
+        let unknown = devices.identify(ip("192.168.1.7")).unwrap();
+        assert_eq!(unknown.mac.as_deref(), Some("3c:22:fb:00:00:04"));
+        assert!(devices.identify(ip("192.168.1.6")).is_none());
+        assert!(devices.identify(ip("192.168.1.8")).is_none());
+    }
+
+    #[test]
+    fn test_ipv6_neighbors_and_prefixes() {
+        let mut devices = DeviceRegistry::default();
+        devices
+            .merge_mapping(&format!(
+                "{}    prefixes: [\"2001:db8:0:20::/64\"]\n",
+                MAPPING
+            ))
+            .unwrap();
+        let bsd = "\
+Neighbor                             Linklayer Address  Netif Expire    S Flags
+2001:db8::1c2:3ff:fe04:506           3c:22:fb:00:00:05    igb1 23h59m58s S R
+fe80::1%igb1                         (incomplete)         igb1 expired   N
+";
+        let linux = "\
+2001:db8::6 dev eth0 lladdr 3c:22:fb:00:00:06 REACHABLE
+2001:db8::7 dev eth0  FAILED
+";
+        assert_eq!(devices.merge_ndp(bsd), 1);
+        assert_eq!(devices.merge_ndp(linux), 1);
+
+        let phone = devices.identify(ip("2001:db8::1c2:3ff:fe04:506")).unwrap();
+        assert_eq!(phone.source, IdentitySource::Ndp);
+        assert_eq!(phone.mac.as_deref(), Some("3c:22:fb:00:00:05"));
+        assert!(devices.identify(ip("2001:db8::7")).is_none());
+
+        // A privacy address the neighbor table hasn't seen yet
+        let timmy = devices
+            .identify(ip("2001:db8:0:20:8d3e:11ff:a2c4:9b01"))
+            .unwrap();
+        assert_eq!(timmy.source, IdentitySource::Prefix);
+        assert_eq!(timmy.owner.as_deref(), Some("timmy"));
+
+        // IPv4 clients on a dual-stack listener
+        devices.merge_arp("? (192.168.1.5) at a4:83:e7:12:34:56 on igb1 [ethernet]\n");
+        let mapped = devices.identify(ip("::ffff:192.168.1.5")).unwrap();
+        assert_eq!(mapped.source, IdentitySource::Arp);
+    }
+
+    #[test]
+    fn test_invalid_mapping_mac_is_rejected() {
+        let mut devices = DeviceRegistry::default();
+        let error = devices
+            .merge_mapping("- mac: not-a-mac\n  name: Toaster\n")
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("Toaster"));
+    }
+}
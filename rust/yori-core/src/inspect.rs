@@ -0,0 +1,238 @@
+//! Response inspection: a second policy evaluation after the upstream answers
+//!
+//! Request policies can't see what the model will say. Once a
+//! (non-streaming) response has arrived, the proxy evaluates the policies a
+//! second time with the request input plus the completion:
+//!
+//! ```json
+//! {
+//!   "phase": "response",
+//!   "model": "gpt-4o", "client_device": "sam-ipad", ...,
+//!   "response": {"status": 200, "model": "gpt-4o-2024-08-06",
+//!                "finish_reason": "stop", "text": "..."}
+//! }
+//! ```
+//!
+//! Request-time evaluations carry `"phase": "request"`, so policies choose
+//! which point they apply to:
+//!
+//! ```rego
+//! deny contains "response mentions a blocked topic" if {
+//!     input.phase == "response"
+//!     contains(lower(input.response.text), "casino")
+//! }
+//!
+//! obligations := {"redact_response": ["(?i)\\bpassword:\\s*\\S+"]} if {
+//!     input.phase == "response"
+//! }
+//! ```
+//!
+//! In enforce mode a denied response is replaced by a 403 error body, and
+//! `redact_response` patterns are masked in the completion text while the
+//! rest of the provider's JSON is left intact. Streamed responses reach the
+//! client as they arrive and are not inspected.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::providers::{completion_pointers, Provider};
+use crate::proxy::{ProxyMode, ResponseContext};
+
+/// Obligation listing regular expressions to mask in the completion
+pub const REDACT_RESPONSE_OBLIGATION: &str = "redact_response";
+
+/// Text masked completion fragments are replaced with
+pub const RESPONSE_REDACTION: &str = "[REDACTED]";
+
+/// What to do with an upstream response after its policy decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseVerdict {
+    /// Forward the response unchanged
+    Pass,
+
+    /// Forward this rewritten body instead
+    Redacted {
+        /// Response body with matches masked
+        body: Vec<u8>,
+
+        /// Number of fragments masked
+        replacements: usize,
+    },
+
+    /// Don't forward; answer with [`response_blocked_body`] (403)
+    Blocked {
+        /// Reason from the policy decision
+        reason: String,
+    },
+}
+
+/// Policy input for the response phase of a request
+///
+/// `request_input` is the input the request was evaluated with.
+pub fn response_input(request_input: &Value, response: &ResponseContext, text: &str) -> Value {
+    let mut input = request_input.clone();
+    if let Value::Object(map) = &mut input {
+        map.insert("phase".to_string(), json!("response"));
+        map.insert(
+            "response".to_string(),
+            json!({
+                "status": response.status,
+                "model": response.model,
+                "finish_reason": response.finish_reason,
+                "safety_flags": response.safety_flags,
+                "text": text,
+            }),
+        );
+    }
+    input
+}
+
+/// Apply a response-phase decision to a response body
+///
+/// Only enforce mode changes anything; in observe and advisory modes the
+/// decision is just audited.
+pub fn apply_response_decision(
+    provider: Provider,
+    body: &[u8],
+    decision: &Value,
+    mode: ProxyMode,
+) -> Result<ResponseVerdict> {
+    if mode != ProxyMode::Enforce {
+        return Ok(ResponseVerdict::Pass);
+    }
+    if decision["allow"] == json!(false) {
+        return Ok(ResponseVerdict::Blocked {
+            reason: decision["reason"]
+                .as_str()
+                .unwrap_or("Response blocked by policy")
+                .to_string(),
+        });
+    }
+
+    let patterns = decision["obligations"][REDACT_RESPONSE_OBLIGATION]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|p| {
+            Regex::new(p)
+                .with_context(|| format!("invalid {} pattern {:?}", REDACT_RESPONSE_OBLIGATION, p))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if patterns.is_empty() {
+        return Ok(ResponseVerdict::Pass);
+    }
+
+    let Ok(mut json) = serde_json::from_slice::<Value>(body) else {
+        return Ok(ResponseVerdict::Pass);
+    };
+    let mut replacements = 0;
+    for pointer in completion_pointers(provider, &json) {
+        let Some(Value::String(text)) = json.pointer_mut(&pointer) else {
+            continue;
+        };
+        for pattern in &patterns {
+            let found = pattern.find_iter(text).count();
+            if found > 0 {
+                replacements += found;
+                *text = pattern.replace_all(text, RESPONSE_REDACTION).into_owned();
+            }
+        }
+    }
+
+    if replacements == 0 {
+        return Ok(ResponseVerdict::Pass);
+    }
+    Ok(ResponseVerdict::Redacted {
+        body: serde_json::to_vec(&json)?,
+        replacements,
+    })
+}
+
+/// JSON body of a 403 answer replacing a blocked response
+pub fn response_blocked_body(endpoint: &str, reason: &str) -> Value {
+    json!({
+        "error": {
+            "type": "content_policy_violation",
+            "code": "yori_response_blocked",
+            "message": format!("YORI blocked the response from {}: {}", endpoint, reason),
+            "endpoint": endpoint,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> ResponseContext {
+        ResponseContext {
+            status: 200,
+            duration_ms: 850,
+            tokens: Some(42),
+            model: Some("gpt-4o-2024-08-06".to_string()),
+            finish_reason: Some("stop".to_string()),
+            safety_flags: Vec::new(),
+            cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn test_response_input_extends_request_input() {
+        let request_input = json!({"phase": "request", "model": "gpt-4o"});
+        let input = response_input(&request_input, &response(), "The answer is 4");
+        assert_eq!(input["phase"], "response");
+        assert_eq!(input["model"], "gpt-4o");
+        assert_eq!(input["response"]["text"], "The answer is 4");
+        assert_eq!(input["response"]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn test_redacts_completion_text_only_in_enforce_mode() {
+        let body = br#"{"model":"gpt-4o","choices":[{"message":{"role":"assistant","content":"Use password: hunter2 to log in"}}]}"#;
+        let decision = json!({
+            "allow": true,
+            "obligations": {"redact_response": ["password:\\s*\\S+"]}
+        });
+
+        assert_eq!(
+            apply_response_decision(Provider::OpenAI, body, &decision, ProxyMode::Advisory)
+                .unwrap(),
+            ResponseVerdict::Pass
+        );
+        let ResponseVerdict::Redacted { body, replacements } =
+            apply_response_decision(Provider::OpenAI, body, &decision, ProxyMode::Enforce).unwrap()
+        else {
+            panic!("expected a redacted body");
+        };
+        assert_eq!(replacements, 1);
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["choices"][0]["message"]["content"],
+            "Use [REDACTED] to log in"
+        );
+        assert_eq!(json["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_denied_response_is_blocked() {
+        let decision = json!({"allow": false, "reason": "Gambling content"});
+        let verdict =
+            apply_response_decision(Provider::Anthropic, b"{}", &decision, ProxyMode::Enforce)
+                .unwrap();
+        assert_eq!(
+            verdict,
+            ResponseVerdict::Blocked {
+                reason: "Gambling content".to_string()
+            }
+        );
+        let body = response_blocked_body("api.anthropic.com", "Gambling content");
+        assert_eq!(body["error"]["type"], "content_policy_violation");
+
+        let bad = json!({"allow": true, "obligations": {"redact_response": ["("]}});
+        assert!(
+            apply_response_decision(Provider::OpenAI, b"{}", &bad, ProxyMode::Enforce).is_err()
+        );
+    }
+}
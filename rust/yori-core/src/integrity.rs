@@ -0,0 +1,335 @@
+//! Tamper-evident hash chain over the audit log
+//!
+//! With [`AuditConfig::hash_chain`](crate::AuditConfig::hash_chain) on, every
+//! stored event gets two extra columns:
+//!
+//! - `prev_hash`: the `event_hash` of the chained event before it
+//!   ([`GENESIS_HASH`] for the first one)
+//! - `event_hash`: SHA-256 over `prev_hash` and the event's other non-NULL
+//!   columns (id included), hex-encoded
+//!
+//! Editing an event changes its hash; deleting or inserting one breaks the
+//! next event's `prev_hash`. [`AuditLogger::verify_integrity`] walks the
+//! chain and reports the first record that doesn't check out.
+//!
+//! Limits worth knowing: events logged before the chain was turned on are
+//! counted as unchained and skipped; the oldest chained event's `prev_hash`
+//! is taken on trust (so pruning old history doesn't fail verification);
+//! and removing the newest events can't be seen from the chain alone, so
+//! keep a copy of the reported `head` hash somewhere else if that matters.
+//! Canonical prompt text stored for near-duplicate prompts is referenced by
+//! `prompt_ref`, not hashed.
+
+use anyhow::{Context, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::audit::{sql_to_json, AuditColumn, AuditLogger, AuditQuery};
+
+/// `prev_hash` of the first event in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Events read per batch while verifying
+const VERIFY_BATCH_ROWS: usize = 1000;
+
+/// Columns holding the chain itself, left out of the hashed content
+const CHAIN_COLUMNS: [&str; 2] = ["prev_hash", "event_hash"];
+
+/// A record that failed verification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TamperedRecord {
+    /// Row id of the record
+    pub id: i64,
+
+    /// What didn't match
+    pub reason: String,
+}
+
+/// Outcome of [`AuditLogger::verify_integrity`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    /// Chained events whose hashes matched
+    pub verified: u64,
+
+    /// Events from before the chain was enabled
+    pub unchained: u64,
+
+    /// `event_hash` of the newest verified event
+    pub head: Option<String>,
+
+    /// First record that failed verification (None = chain intact)
+    pub first_tampered: Option<TamperedRecord>,
+}
+
+impl IntegrityReport {
+    /// Whether every chained event verified
+    pub fn is_intact(&self) -> bool {
+        self.first_tampered.is_none()
+    }
+}
+
+/// Hash of one event given the previous event's hash
+///
+/// Columns are hashed as `name=json` lines sorted by name, with NULLs left
+/// out, so columns added by later migrations don't change existing hashes.
+fn event_hash(prev_hash: &str, record: &[(&str, &Value)]) -> String {
+    let mut fields: Vec<_> = record
+        .iter()
+        .filter(|(name, value)| !CHAIN_COLUMNS.contains(name) && **value != Value::Null)
+        .collect();
+    fields.sort_by_key(|(name, _)| *name);
+
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(b"\n");
+    for (name, value) in fields {
+        hasher.update(name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(sql_to_json((*value).into()).to_string().as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Link a just-inserted event into the chain
+///
+/// Runs inside the insert's transaction, so the previous hash can't change
+/// underneath it.
+pub(crate) fn chain_event(conn: &Connection, columns: &[AuditColumn], id: i64) -> Result<()> {
+    let prev_hash: String = conn
+        .query_row(
+            "SELECT event_hash FROM audit_events
+             WHERE id < ?1 AND event_hash IS NOT NULL
+             ORDER BY id DESC LIMIT 1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+    let names = columns
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let values = conn
+        .query_row(
+            &format!("SELECT {names} FROM audit_events WHERE id = ?1"),
+            [id],
+            |row| {
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            },
+        )
+        .context("chained event disappeared")?;
+    let record: Vec<_> = columns
+        .iter()
+        .map(|c| c.name.as_str())
+        .zip(values.iter())
+        .collect();
+
+    conn.execute(
+        "UPDATE audit_events SET prev_hash = ?1, event_hash = ?2 WHERE id = ?3",
+        params![prev_hash, event_hash(&prev_hash, &record), id],
+    )?;
+    Ok(())
+}
+
+impl AuditLogger {
+    /// Walk the hash chain from the oldest event, stopping at the first
+    /// record that was altered, removed from or inserted into the chain
+    pub fn verify_integrity(&self) -> Result<IntegrityReport> {
+        let columns = self.columns()?;
+        let position = |name: &str| {
+            columns
+                .iter()
+                .position(|c| c.name == name)
+                .with_context(|| format!("audit_events has no {} column", name))
+        };
+        let (id_at, prev_at, hash_at) = (
+            position("id")?,
+            position("prev_hash")?,
+            position("event_hash")?,
+        );
+
+        let mut report = IntegrityReport::default();
+        self.scan(&AuditQuery::default(), VERIFY_BATCH_ROWS, |rows| {
+            for row in rows {
+                if report.first_tampered.is_some() {
+                    break;
+                }
+                let id = match row[id_at] {
+                    Value::Integer(id) => id,
+                    _ => 0,
+                };
+                let text = |value: &Value| match value {
+                    Value::Text(s) => Some(s.clone()),
+                    _ => None,
+                };
+                let tampered = |reason: &str| {
+                    Some(TamperedRecord {
+                        id,
+                        reason: reason.to_string(),
+                    })
+                };
+
+                let Some(stored) = text(&row[hash_at]) else {
+                    if report.head.is_some() {
+                        report.first_tampered =
+                            tampered("no event_hash: inserted outside the chain or hash removed");
+                    } else {
+                        report.unchained += 1;
+                    }
+                    continue;
+                };
+                let prev = text(&row[prev_at]).unwrap_or_default();
+                if let Some(head) = &report.head {
+                    if &prev != head {
+                        report.first_tampered = tampered(
+                            "prev_hash does not match the event before it: \
+                             an event was deleted or inserted",
+                        );
+                        continue;
+                    }
+                }
+                let record: Vec<_> = columns
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .zip(row.iter())
+                    .collect();
+                if event_hash(&prev, &record) != stored {
+                    report.first_tampered = tampered("contents do not match event_hash");
+                    continue;
+                }
+                report.verified += 1;
+                report.head = Some(stored);
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::tests::request;
+    use crate::audit::AuditConfig;
+    use std::path::{Path, PathBuf};
+
+    fn chained_logger(database: PathBuf) -> AuditLogger {
+        AuditLogger::open(AuditConfig {
+            database,
+            hash_chain: true,
+            ..AuditConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Edit the database behind the logger's back
+    fn tamper(database: &Path, sql: &str, id: i64) {
+        Connection::open(database)
+            .unwrap()
+            .execute(sql, [id])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_event_hash_matches_python_logger() {
+        // Same record and digest as tests/unit/test_audit_enforcement.py
+        let values = [
+            Value::Integer(7),
+            Value::Text("2026-03-01T12:00:00+00:00".to_string()),
+            Value::Text("say \"hi\" – naïve\n".to_string()),
+            Value::Integer(300),
+            Value::Null,
+            Value::Text("x".to_string()),
+            Value::Real(0.25),
+        ];
+        let names = [
+            "id",
+            "timestamp",
+            "prompt_preview",
+            "response_tokens",
+            "policy_name",
+            "event_hash",
+            "cost",
+        ];
+        let record: Vec<_> = names.into_iter().zip(values.iter()).collect();
+        assert_eq!(
+            event_hash(GENESIS_HASH, &record),
+            "64f85dda841e6570655b85b9378b98f974865f11b6adf166df38865e4f596c5a"
+        );
+    }
+
+    #[test]
+    fn test_intact_chain_verifies() {
+        let logger = chained_logger(PathBuf::from(":memory:"));
+        let ids: Vec<i64> = (0..3)
+            .map(|i| logger.log(&request(&format!("prompt {i}"))).unwrap())
+            .collect();
+
+        let report = logger.verify_integrity().unwrap();
+        assert!(report.is_intact());
+        assert_eq!((report.verified, report.unchained), (3, 0));
+
+        let first = logger.query(&AuditQuery::default()).unwrap();
+        let oldest = first.iter().find(|e| e["id"] == ids[0]).unwrap();
+        assert_eq!(oldest["prev_hash"], GENESIS_HASH);
+        assert_eq!(report.head.as_deref(), first[0]["event_hash"].as_str());
+    }
+
+    #[test]
+    fn test_edits_and_deletions_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("audit.db");
+        let logger = chained_logger(database.clone());
+        let ids: Vec<i64> = (0..4)
+            .map(|i| logger.log(&request(&format!("prompt {i}"))).unwrap())
+            .collect();
+
+        tamper(
+            &database,
+            "UPDATE audit_events SET policy_result = 'allow' WHERE id = ?1",
+            ids[1],
+        );
+        let report = logger.verify_integrity().unwrap();
+        assert_eq!(report.verified, 1);
+        let tampered = report.first_tampered.unwrap();
+        assert_eq!(tampered.id, ids[1]);
+        assert!(tampered.reason.contains("contents"));
+
+        tamper(
+            &database,
+            "UPDATE audit_events SET policy_result = NULL WHERE id = ?1",
+            ids[1],
+        );
+        assert!(logger.verify_integrity().unwrap().is_intact());
+        tamper(&database, "DELETE FROM audit_events WHERE id = ?1", ids[2]);
+        let tampered = logger.verify_integrity().unwrap().first_tampered.unwrap();
+        assert_eq!(tampered.id, ids[3]);
+        assert!(tampered.reason.contains("deleted"));
+    }
+
+    #[test]
+    fn test_events_before_enabling_are_unchained() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("audit.db");
+        let plain = AuditLogger::open(AuditConfig {
+            database: database.clone(),
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        plain.log(&request("before")).unwrap();
+        drop(plain);
+
+        let chained = chained_logger(database);
+        chained.log(&request("after")).unwrap();
+        let report = chained.verify_integrity().unwrap();
+        assert!(report.is_intact());
+        assert_eq!((report.verified, report.unchained), (1, 1));
+    }
+}
@@ -0,0 +1,336 @@
+//! Upstream latency tracking for SLO-style provider reports
+//!
+//! Every proxied request records how long the upstream LLM API took to
+//! answer. This module keeps a bounded window of those samples per provider
+//! and turns them into percentile reports ("Anthropic p95 this week: 2.3s")
+//! so homelab users can decide which providers to prefer or route around.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Default retention window for latency samples (one week)
+const DEFAULT_RETENTION_SECS: u64 = 7 * 24 * 3600;
+
+/// Default cap on samples kept per provider
+const DEFAULT_MAX_SAMPLES: usize = 50_000;
+
+/// A single upstream latency observation
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    timestamp: DateTime<Utc>,
+    duration_ms: u64,
+}
+
+/// Percentile summary of upstream latency for one provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    /// Provider / endpoint host (e.g., "api.anthropic.com")
+    pub provider: String,
+
+    /// Length of the reporting window in seconds
+    pub window_seconds: u64,
+
+    /// Number of samples in the window
+    pub count: usize,
+
+    /// Median latency
+    pub p50_ms: u64,
+
+    /// 95th percentile latency
+    pub p95_ms: u64,
+
+    /// 99th percentile latency
+    pub p99_ms: u64,
+
+    /// Slowest observed request
+    pub max_ms: u64,
+
+    /// Arithmetic mean latency
+    pub mean_ms: f64,
+}
+
+impl LatencyReport {
+    /// Whether the given percentile stayed at or under `threshold_ms`
+    ///
+    /// Only p50, p95 and p99 are tracked; any other percentile falls back to
+    /// the next tracked one above it.
+    pub fn meets(&self, percentile: f64, threshold_ms: u64) -> bool {
+        let observed = if percentile <= 50.0 {
+            self.p50_ms
+        } else if percentile <= 95.0 {
+            self.p95_ms
+        } else {
+            self.p99_ms
+        };
+        observed <= threshold_ms
+    }
+
+    fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("provider", &self.provider)?;
+        dict.set_item("window_seconds", self.window_seconds)?;
+        dict.set_item("count", self.count)?;
+        dict.set_item("p50_ms", self.p50_ms)?;
+        dict.set_item("p95_ms", self.p95_ms)?;
+        dict.set_item("p99_ms", self.p99_ms)?;
+        dict.set_item("max_ms", self.max_ms)?;
+        dict.set_item("mean_ms", self.mean_ms)?;
+        Ok(dict)
+    }
+}
+
+/// Per-provider upstream latency tracker
+///
+/// Samples older than the retention window are discarded as new ones
+/// arrive, and each provider keeps at most `max_samples` observations so
+/// memory stays bounded on router hardware.
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// tracker = yori_core.LatencyTracker(retention_seconds=7 * 24 * 3600)
+/// tracker.record("api.anthropic.com", 2300)
+///
+/// report = tracker.report("api.anthropic.com", window_seconds=7 * 24 * 3600)
+/// print(f"Anthropic p95 this week: {report['p95_ms'] / 1000:.1f}s")
+/// ```
+#[pyclass]
+pub struct LatencyTracker {
+    samples: Mutex<HashMap<String, VecDeque<Sample>>>,
+    retention: ChronoDuration,
+    max_samples: usize,
+}
+
+impl LatencyTracker {
+    /// Create a tracker with the given retention window and per-provider cap
+    pub fn with_limits(retention_seconds: u64, max_samples: usize) -> Self {
+        LatencyTracker {
+            samples: Mutex::new(HashMap::new()),
+            retention: ChronoDuration::seconds(retention_seconds as i64),
+            max_samples: max_samples.max(1),
+        }
+    }
+
+    /// Record an upstream latency observation taken now
+    pub fn record_latency(&self, provider: &str, duration_ms: u64) {
+        self.record_at(provider, Utc::now(), duration_ms);
+    }
+
+    /// Record an upstream latency observation with an explicit timestamp
+    pub fn record_at(&self, provider: &str, timestamp: DateTime<Utc>, duration_ms: u64) {
+        let cutoff = Utc::now() - self.retention;
+        let mut samples = self.samples.lock().unwrap();
+        let queue = samples.entry(provider.to_string()).or_default();
+
+        queue.push_back(Sample {
+            timestamp,
+            duration_ms,
+        });
+
+        while queue.len() > self.max_samples {
+            queue.pop_front();
+        }
+        while queue.front().is_some_and(|s| s.timestamp < cutoff) {
+            queue.pop_front();
+        }
+    }
+
+    /// Build a percentile report for one provider over the trailing window
+    ///
+    /// Returns `None` if no samples fall inside the window.
+    pub fn provider_report(&self, provider: &str, window_seconds: u64) -> Option<LatencyReport> {
+        let since = Utc::now() - ChronoDuration::seconds(window_seconds as i64);
+        let samples = self.samples.lock().unwrap();
+        let mut durations: Vec<u64> = samples
+            .get(provider)?
+            .iter()
+            .filter(|s| s.timestamp >= since)
+            .map(|s| s.duration_ms)
+            .collect();
+
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_unstable();
+
+        let total: u64 = durations.iter().sum();
+        Some(LatencyReport {
+            provider: provider.to_string(),
+            window_seconds,
+            count: durations.len(),
+            p50_ms: percentile(&durations, 50.0),
+            p95_ms: percentile(&durations, 95.0),
+            p99_ms: percentile(&durations, 99.0),
+            max_ms: *durations.last().unwrap(),
+            mean_ms: total as f64 / durations.len() as f64,
+        })
+    }
+
+    /// Build reports for every provider with samples in the window,
+    /// sorted fastest p95 first
+    pub fn all_reports(&self, window_seconds: u64) -> Vec<LatencyReport> {
+        let providers: Vec<String> = self.samples.lock().unwrap().keys().cloned().collect();
+        let mut reports: Vec<LatencyReport> = providers
+            .iter()
+            .filter_map(|p| self.provider_report(p, window_seconds))
+            .collect();
+        reports.sort_by(|a, b| a.p95_ms.cmp(&b.p95_ms).then(a.provider.cmp(&b.provider)));
+        reports
+    }
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        LatencyTracker::with_limits(DEFAULT_RETENTION_SECS, DEFAULT_MAX_SAMPLES)
+    }
+}
+
+#[pymethods]
+impl LatencyTracker {
+    /// Create a new latency tracker
+    ///
+    /// # Arguments
+    ///
+    /// * `retention_seconds` - How long samples are kept (default: 7 days)
+    /// * `max_samples` - Maximum samples kept per provider (default: 50000)
+    #[new]
+    #[pyo3(signature = (retention_seconds=DEFAULT_RETENTION_SECS, max_samples=DEFAULT_MAX_SAMPLES))]
+    fn new(retention_seconds: u64, max_samples: usize) -> Self {
+        LatencyTracker::with_limits(retention_seconds, max_samples)
+    }
+
+    /// Record an upstream latency observation
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Endpoint host (e.g., "api.openai.com")
+    /// * `duration_ms` - Upstream response time in milliseconds
+    fn record(&self, provider: String, duration_ms: u64) {
+        self.record_latency(&provider, duration_ms);
+    }
+
+    /// Get a percentile report for one provider
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Endpoint host
+    /// * `window_seconds` - Trailing window to report on (default: 7 days)
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `provider`, `window_seconds`, `count`, `p50_ms`,
+    /// `p95_ms`, `p99_ms`, `max_ms` and `mean_ms`, or None if no samples
+    #[pyo3(signature = (provider, window_seconds=DEFAULT_RETENTION_SECS))]
+    fn report(
+        &self,
+        py: Python,
+        provider: String,
+        window_seconds: u64,
+    ) -> PyResult<Option<PyObject>> {
+        match self.provider_report(&provider, window_seconds) {
+            Some(report) => Ok(Some(report.to_py_dict(py)?.into())),
+            None => Ok(None),
+        }
+    }
+
+    /// Get percentile reports for all providers, fastest p95 first
+    ///
+    /// # Arguments
+    ///
+    /// * `window_seconds` - Trailing window to report on (default: 7 days)
+    #[pyo3(signature = (window_seconds=DEFAULT_RETENTION_SECS))]
+    fn report_all(&self, py: Python, window_seconds: u64) -> PyResult<PyObject> {
+        let list = PyList::empty_bound(py);
+        for report in self.all_reports(window_seconds) {
+            list.append(report.to_py_dict(py)?)?;
+        }
+        Ok(list.into())
+    }
+
+    /// Check a provider against an SLO target
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Endpoint host
+    /// * `percentile` - Percentile to check (50, 95 or 99)
+    /// * `threshold_ms` - Maximum acceptable latency at that percentile
+    /// * `window_seconds` - Trailing window to report on (default: 7 days)
+    ///
+    /// # Returns
+    ///
+    /// True if the target was met, False if missed, None if no samples
+    #[pyo3(signature = (provider, percentile, threshold_ms, window_seconds=DEFAULT_RETENTION_SECS))]
+    fn meets_slo(
+        &self,
+        provider: String,
+        percentile: f64,
+        threshold_ms: u64,
+        window_seconds: u64,
+    ) -> Option<bool> {
+        self.provider_report(&provider, window_seconds)
+            .map(|r| r.meets(percentile, threshold_ms))
+    }
+
+    /// List providers with recorded samples
+    fn providers(&self) -> Vec<String> {
+        let mut providers: Vec<String> = self.samples.lock().unwrap().keys().cloned().collect();
+        providers.sort();
+        providers
+    }
+}
+
+/// Nearest-rank percentile over an already sorted slice
+fn percentile(sorted: &[u64], pct: f64) -> u64 {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_report_percentiles() {
+        let tracker = LatencyTracker::default();
+        for ms in 1..=100 {
+            tracker.record_latency("api.anthropic.com", ms * 10);
+        }
+
+        let report = tracker.provider_report("api.anthropic.com", 3600).unwrap();
+        assert_eq!(report.count, 100);
+        assert_eq!(report.p50_ms, 500);
+        assert_eq!(report.p95_ms, 950);
+        assert_eq!(report.p99_ms, 990);
+        assert_eq!(report.max_ms, 1000);
+        assert!(report.meets(95.0, 1000));
+        assert!(!report.meets(99.0, 900));
+        assert!(tracker.provider_report("api.openai.com", 3600).is_none());
+    }
+
+    #[test]
+    fn test_window_and_sample_cap() {
+        let tracker = LatencyTracker::with_limits(3600, 3);
+        tracker.record_at(
+            "api.openai.com",
+            Utc::now() - ChronoDuration::minutes(30),
+            5000,
+        );
+        for ms in [100, 200, 300] {
+            tracker.record_latency("api.openai.com", ms);
+        }
+
+        // Oldest sample was pushed out by the cap
+        let report = tracker.provider_report("api.openai.com", 3600).unwrap();
+        assert_eq!(report.count, 3);
+        assert_eq!(report.max_ms, 300);
+
+        tracker.record_latency("api.mistral.ai", 50);
+        let reports = tracker.all_reports(3600);
+        assert_eq!(reports[0].provider, "api.mistral.ai");
+    }
+}
@@ -8,16 +8,91 @@
 //! ```text
 //! Python (FastAPI) ─── PyO3 bindings ───► yori-core (Rust)
 //!                                             │
-//!                                             ├─► sark-opa (policy engine)
+//!                                             ├─► regorus (Rego policy engine)
 //!                                             ├─► sark-cache (in-memory cache)
 //!                                             └─► HTTP proxy logic
 //! ```
 //!
 //! # Features
 //!
-//! - **Policy Evaluation**: Embedded OPA engine (4-10x faster than HTTP)
-//! - **Caching**: Lock-free in-memory cache (no Redis needed)
+//! - **Policy Evaluation**: Raw `.rego` files evaluated in-process (4-10x faster than HTTP)
+//! - **Policy Data**: JSON/YAML documents in the policy directory mounted as `data.*`
+//! - **Combining Strategies**: Deny-overrides, allow-overrides, first-match or priority-ordered
+//! - **Default Decision**: Fail open or closed when no policy decides a request
+//! - **Policy Metadata**: Policies disabled, re-ranked or given a mode without editing them
+//! - **Decision Cache**: Identical inputs reuse a recent decision for a short TTL
+//! - **Decision Shortcuts**: Inputs agreeing on every field the policies read share one decision
+//! - **Hot Reload**: Policy directory watched and reloaded atomically on change
+//! - **Quotas**: Daily/weekly token and request counters per user or device, kept across restarts
+//! - **Input Enrichment**: Device, schedule, quota, history and tags in one policy input
+//! - **Content Categories**: Prompts tagged homework/coding/adult/self-harm/violence for policy and audit
+//! - **Jailbreak Heuristics**: Instruction overrides, encoded payloads and token floods scored in policy input
+//! - **Time Helpers**: Local time in policy input plus `yori.time_between`/`yori.in_schedule`
+//! - **Response Inspection**: Completions evaluated by policy before they reach the client
+//! - **Policy Bundles**: OPA-style bundles pulled from a central HTTPS server, ETag-cached and verified
+//! - **Signed Policies**: Optional ed25519-signed policy bundles; unsigned or altered policies refused
+//! - **Policy Validation**: Edited policies compiled without loading, errors reported by line and column
+//! - **Policy Tests**: `*_test.rego` rules and YAML input/expectation cases run in-process, like `opa test`
+//! - **Decision Explanations**: Rule file/line and matched input fields per decision
+//! - **Caching**: In-memory LRU/TTL cache (no Redis needed), snapshotted across reboots
+//! - **Prompt Cache**: Repeated or near-duplicate prompts to allowed models answered from cache
 //! - **Proxy**: Transparent HTTP/HTTPS proxy for LLM traffic
+//! - **Certificate Minting**: Per-host leaf certificates from the local CA, cached and renewed
+//! - **HTTP/2**: `h2` negotiated over ALPN with clients and upstreams, HTTP/1.1 fallback
+//! - **SNI Passthrough**: Non-LLM HTTPS relayed untouched when all 443 traffic is redirected
+//! - **Original Destination**: Real host/port of redirected connections from pf `rdr-to` state or TPROXY
+//! - **DNS Overrides**: Unbound/dnsmasq overrides resolving intercepted hosts to the gateway, kept in sync
+//! - **Explicit Proxy Mode**: CONNECT for devices configured to use YORI, TLS intercepted only for LLM hosts
+//! - **Proxy Authentication**: Basic or client-certificate logins name the user instead of the client IP
+//! - **Connection Limits**: Global and per-device connection caps, queued round-robin so one device can't exhaust descriptors
+//! - **Graceful Shutdown**: In-flight requests drained up to a timeout, then reported as dropped
+//! - **Streaming**: SSE responses relayed as they arrive, with usage still accounted
+//! - **Provider Catalog**: OpenAI, Azure OpenAI, Anthropic, Gemini, Mistral, Bedrock,
+//!   OpenRouter, Groq, DeepSeek and Together, plus hosts added in config; wildcard host matching
+//! - **Model Governance**: Per-user/device model allow/deny lists, blocking or downgrading
+//! - **Local Model Routing**: Requests sent to Ollama/llama.cpp by policy or model, API shape translated
+//! - **Honeypot Responses**: Labeled local refusals instead of errors for chosen categories
+//! - **Block Pages**: Blocked requests answered with the provider's error JSON or an HTML page, reason templated
+//! - **Advisory Warnings**: Would-be blocks flagged in response headers and optionally in the completion
+//! - **Bypass Detection**: Canary requests verify traffic still traverses the proxy
+//! - **Size Limits**: Oversized request/response bodies refused while streaming (413)
+//! - **Rate Limiting**: Token bucket per device and endpoint; 429 with a JSON body when exceeded
+//! - **Allowance Headers**: Rate-limit and budget remaining on every proxied response
+//! - **Retry Detection**: Client retry storms flagged in audit and not double-counted
+//! - **Sessions**: Requests of one chat grouped into a session, with per-session token/cost totals
+//! - **Upstream Resilience**: Idempotent retries with backoff, per-endpoint circuit breaker
+//! - **Latency SLOs**: Per-provider upstream latency percentiles
+//! - **Audit Logging**: SQLite audit trail with near-duplicate prompt storage
+//! - **Audit Buffering**: Events held in memory, then spilled to disk, while the database is locked or full, and replayed on recovery
+//! - **Transcripts**: Opt-in full conversations per session, redacted, sealed and kept on their own retention
+//! - **Audit Queries**: Events filtered by time, client, endpoint, type and decision, paged
+//! - **Audit Stats**: Totals, blocks, errors and time span, also per endpoint/client/day
+//! - **Usage Rollups**: Daily/weekly requests, blocks, tokens and cost per user/device/provider, kept past retention
+//! - **Cost Estimation**: Per-request cost from a per-model price table, summed per user/device/day
+//! - **Budgets**: Monthly spend per user in policy input, with an optional hard cap
+//! - **Policy Replay**: Candidate policies re-run against recorded traffic, reporting changed decisions
+//! - **Cache Warming**: Decision caches filled at startup from the most recent audited requests
+//! - **Audit Export**: Streaming JSONL, CSV and (optional) Parquet dumps for offline analysis
+//! - **Tamper Evidence**: Optional SHA-256 hash chain over audit events, with verification
+//! - **Secret Scanning**: API keys, private keys and AWS credentials in request bodies blocked, redacted or alerted
+//! - **PII Redaction**: Emails, phones, cards, SSNs and addresses masked in prompt previews
+//! - **Live Tail**: SSE stream of audit events and status changes for the dashboard, also on the admin API and in Python
+//! - **Alerts**: Webhook, ntfy, Discord and Slack notifications on blocks, de-duplicated
+//! - **Decision Logs**: Policy decisions batched to an OPA-compatible collector, buffered while offline
+//! - **DB Maintenance**: Retention pruning, plus vacuum/ANALYZE/WAL checkpoints during quiet hours
+//! - **Encryption at Rest**: Optional SQLCipher audit DB keyed from the local vault
+//! - **API Key Vault**: Provider keys sealed on the router and injected when forwarding
+//! - **Network Scopes**: Per-subnet/VLAN modes and profiles, resolved at accept
+//! - **Multi-tenant**: Several households isolated on one shared router
+//! - **Device Identity**: Client IPs mapped to named devices via DHCP leases, ARP and a MAC list
+//! - **IPv6**: Dual-stack listener, canonical client addresses, NDP neighbors and prefix-matched devices
+//! - **Name Discovery**: Unlisted devices named from their mDNS/NetBIOS answers
+//! - **WireGuard Identity**: Names off-LAN clients by their tunnel peer
+//! - **Backup/Restore**: Single verified archive of policies, config and state
+//! - **Time Series**: Grafana-ready usage export (Influx line protocol, JSON)
+//! - **Admin API**: REST endpoints for policies, mode, audit events and cache stats, plus health/readiness probes
+//! - **Config File**: One validated yori.toml with `YORI_*` environment overrides
+//! - **Config Reload**: SIGHUP or the admin API re-reads yori.toml, applying endpoints, mode, rate limits and audit settings live
 //!
 //! # Usage from Python
 //!
@@ -41,15 +116,245 @@
 //!     # Block with reason
 //!     print(f"Blocked: {result['reason']}")
 //! ```
+//!
+//! # Command line
+//!
+//! The `yori` binary (`rust/yori-cli`) links this crate directly for
+//! headless administration over SSH: policy tests, audit queries, quota
+//! and cache state, config validation and CA generation.
 
 use pyo3::prelude::*;
 
+mod admin;
+mod advisory;
+mod alerts;
+mod alpn;
+mod audit;
+mod backup;
+mod blockpage;
+mod budget;
+mod bundle;
 mod cache;
+mod canary;
+mod certs;
+mod classify;
+mod clock;
+mod combining;
+mod config;
+mod connect;
+mod connlimit;
+mod cost;
+mod data;
+mod decisionlog;
+mod decisions;
+mod dedup;
+mod discovery;
+mod dlp;
+mod dnsoverride;
+mod drain;
+mod enrich;
+mod explain;
+mod export;
+mod honeypot;
+mod identity;
+mod inspect;
+mod integrity;
+mod jailbreak;
+mod latency;
+mod limits;
+mod livetail;
+mod localroute;
+mod maintenance;
+mod models;
+mod netaddr;
+mod origdst;
+mod persist;
 mod policy;
+mod policymeta;
+mod policytest;
+mod promptcache;
+mod providers;
 mod proxy;
+mod proxyauth;
+mod quota;
+mod ratelimit;
+mod redact;
+mod reload;
+mod replay;
+mod retry;
+mod rollup;
+mod scope;
+mod secrets;
+mod session;
+mod shortcut;
+mod signing;
+mod sni;
+mod spool;
+mod stream;
+mod tenant;
+mod timeseries;
+mod transcript;
+mod upstream;
+mod validate;
+mod vault;
+mod watcher;
+mod wireguard;
 
-pub use cache::Cache;
-pub use policy::PolicyEngine;
+pub use admin::{AdminState, DEFAULT_ADMIN_ADDR};
+pub use advisory::{AdvisoryConfig, AdvisoryNotice, HEADER_POLICY, HEADER_WARNING};
+pub use alerts::{
+    Alert, AlertConfig, AlertKind, AlertTarget, Alerter, DEFAULT_CA_BUNDLES,
+    DEFAULT_DEDUP_WINDOW_SECS, DEFAULT_MAX_ALERTS_PER_MINUTE,
+};
+pub use alpn::{
+    connect_upstream, prepare_upstream_request, serve_connection, upstream_tls_config, HttpVersion,
+    UpstreamSender,
+};
+pub use audit::{
+    encrypt_database, AuditConfig, AuditEvent, AuditEventType, AuditLogger, AuditQuery, AuditStats,
+    DeviceUsage, GroupStats, MaintenanceReport, PromptCacheStats, PruneReport, SessionTotals,
+    StatsCounts, StatsGrouping,
+};
+pub use backup::{BackupManifest, BackupPaths};
+pub use blockpage::{BlockFormat, BlockPage, BlockPageConfig, BLOCKED_BY_HEADER};
+pub use budget::{
+    budget_exceeded_body, BudgetConfig, BudgetStatus, BudgetTracker, BUDGET_POLICY,
+    DEFAULT_BUDGET_STATE,
+};
+pub use bundle::{BundleConfig, BundleDownloader, BundleUpdate, DEFAULT_BUNDLE_INTERVAL_SECS};
+pub use cache::{
+    json_weight, Cache, CacheStats, CacheValue, LruTtlCache, SnapshotConfig,
+    DEFAULT_SNAPSHOT_INTERVAL_SECS,
+};
+pub use canary::{CanaryConfig, CanaryOutcome, CanaryProbe, CanaryResponder};
+pub use certs::{ca_server_config, static_server_config, CertAuthority};
+pub use classify::{
+    Category, CategoryModel, CategoryRule, ClassifierConfig, PromptClassifier,
+    DEFAULT_MODEL_THRESHOLD,
+};
+pub use clock::local_time;
+pub use combining::{CombinationStrategy, DefaultDecision};
+pub use config::{
+    AdvisorySettings, AlertSettings, AlertTargetSettings, AuditSettings, BlockPageSettings,
+    CacheSettings, CategoryRuleSettings, ClassifierSettings, DecisionLogSettings, DlpRuleSettings,
+    DlpSettings, DnsSettings, JailbreakSettings, PiiRuleSettings, PiiRuleTable, PolicySettings,
+    ProviderSettings, ProxyAuthSettings, ProxySettings, ProxyUserSettings, QuotaSettings,
+    TranscriptSettings, YoriConfig, CONFIG_PATH_ENV, DEFAULT_CONFIG_PATH,
+};
+pub use connect::{accept_connect, parse_connect, ConnectOutcome, ConnectTarget, InterceptionMode};
+pub use connlimit::{
+    ConnectionLimitConfig, ConnectionLimitStats, ConnectionLimiter, ConnectionPermit,
+    ConnectionRefusal, DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_CONNECTIONS_PER_CLIENT,
+    DEFAULT_QUEUE_TIMEOUT_MS,
+};
+pub use cost::{CostGrouping, CostSummary, ModelPrice, PricingTable};
+pub use decisionlog::{
+    decision_record, DecisionLogConfig, DecisionLogShipper, DEFAULT_DECISION_BATCH,
+    DEFAULT_DECISION_BUFFER,
+};
+pub use decisions::{DecisionCache, DecisionCacheStats};
+pub use discovery::{
+    DiscoveredName, NameDiscovery, DEFAULT_DISCOVERY_TIMEOUT_MS, DEFAULT_DISCOVERY_TTL_SECS,
+};
+pub use dlp::{
+    dlp_blocked_body, DlpAction, DlpConfig, DlpFinding, DlpPattern, DlpRule, DlpScan, DlpScanner,
+    SecretKind, DLP_POLICY,
+};
+pub use dnsoverride::{
+    DnsFormat, DnsOverrideConfig, DnsOverrides, RenderedOverrides, DEFAULT_DNSMASQ_OVERRIDES,
+    DEFAULT_UNBOUND_OVERRIDES,
+};
+pub use drain::{ConnectionGuard, ConnectionTracker, DrainReport};
+pub use enrich::{
+    BudgetEnricher, DeviceProfile, Enricher, EnrichmentConfig, EnrichmentPipeline,
+    JailbreakEnricher, QuotaStatus, Schedule, TagRule,
+};
+pub use explain::{Explanation, MatchedInput, RuleIndex, RuleLocation};
+pub use export::{ExportFormat, ExportReport};
+pub use honeypot::{HoneypotConfig, HoneypotResponder, SyntheticResponse};
+pub use identity::{
+    DeviceIdentity, DeviceRegistry, IdentitySource, KnownDevice, DEFAULT_LEASE_FILES,
+};
+pub use inspect::{
+    apply_response_decision, response_blocked_body, response_input, ResponseVerdict,
+    REDACT_RESPONSE_OBLIGATION,
+};
+pub use integrity::{IntegrityReport, TamperedRecord, GENESIS_HASH};
+pub use jailbreak::{
+    JailbreakConfig, JailbreakDetector, JailbreakReport, JailbreakSignal,
+    DEFAULT_JAILBREAK_THRESHOLD,
+};
+pub use latency::{LatencyReport, LatencyTracker};
+pub use limits::{
+    check_content_length, collect_limited, payload_too_large_body, BodyKind, BodyTooLarge,
+    LimitError, LimitedBody, DEFAULT_MAX_REQUEST_BYTES, DEFAULT_MAX_RESPONSE_BYTES,
+};
+pub use livetail::{LiveEvent, LiveFilter, LiveTail};
+pub use localroute::{
+    BackendKind, LocalBackend, LocalRoute, LocalRouter, LocalRoutingConfig, RoutedRequest,
+    ROUTED_HEADER, ROUTE_OBLIGATION,
+};
+pub use maintenance::{MaintenanceConfig, MaintenanceScheduler};
+pub use models::{
+    model_blocked_body, rewrite_model, ModelDecision, ModelGovernor, ModelList, ModelPolicyConfig,
+};
+pub use netaddr::{bind_listener, normalize_client_ip, parse_client_ip, same_client_ip};
+pub use origdst::{original_destination, DestinationLookup};
+pub use policy::{PolicyEngine, ReloadOutcome};
+pub use policymeta::{PolicyMetadata, DEFAULT_POLICY_METADATA};
+pub use policytest::{PolicyTestReport, PolicyTestResult, TestOutcome};
+pub use promptcache::{
+    PromptCache, PromptCacheConfig, PromptEmbedder, PromptMatch, DEFAULT_MIN_SIMILARITY,
+    DEFAULT_PROMPT_CACHE_BYTES, DEFAULT_PROMPT_CACHE_ENTRIES, DEFAULT_PROMPT_CACHE_TTL_SECS,
+};
+pub use providers::{
+    normalize_host, parse_completion, parse_request, parse_response_metadata, parse_usage,
+    provider_for_host, sigv4_credential, validate_pattern, ParsedMessage, ParsedRequest,
+    ParsedUsage, Provider, ProviderRegistry, ResponseMetadata, SigV4Credential,
+};
+pub use proxy::{ProxyConfig, ProxyMode};
+pub use proxyauth::{
+    check_password_hash, hash_password, Credentials, ProxyAuthConfig, ProxyAuthenticator,
+    ProxyUser, DEFAULT_PROXY_REALM,
+};
+pub use quota::{QuotaConfig, QuotaLimits, QuotaManager, QuotaUsage, DEFAULT_QUOTA_STATE};
+pub use ratelimit::{allowance_headers, rate_limited_body, RateDecision, RateLimiter};
+pub use redact::{PiiKind, Redaction, RedactionConfig, RedactionRule, Redactor, RulePattern};
+pub use reload::{diff_config, ConfigReloader, ReloadReport, SettingChange};
+pub use replay::{
+    PolicyReplay, ReplayChange, ReplayReport, WarmReport, DEFAULT_WARM_EVENTS, MAX_REPLAY_CHANGES,
+};
+pub use retry::{CachedResponse, RetryCheck, RetryDetector};
+pub use rollup::{RollupGrouping, RollupPeriod, RollupQuery, RollupReport, UsageRollup};
+pub use scope::{NetworkScope, ScopeDecision, ScopeMatcher};
+pub use secrets::{
+    credential_header, provider_by_id, ApiKeyStore, CLIENT_CREDENTIAL_HEADERS,
+    DEFAULT_API_KEY_STORE,
+};
+pub use session::{SessionTracker, DEFAULT_SESSION_IDLE_SECS};
+pub use shortcut::{ShortcutStats, DEFAULT_SHORTCUT_ENTRIES};
+pub use signing::{
+    is_bundle_signature, BundleVerifier, PolicySigner, MANIFEST_FILE, SIGNATURE_FILE,
+};
+pub use sni::{parse_sni, peek_sni, TlsRoute};
+pub use spool::{
+    AuditSpool, SpoolConfig, SpoolStats, DEFAULT_BUFFER_EVENTS, DEFAULT_SPOOL_MAX_BYTES,
+    DEFAULT_SPOOL_PATH,
+};
+pub use stream::{is_event_stream, SseAccumulator, StreamingBody};
+pub use tenant::{Tenant, TenantRegistry, DEFAULT_TENANT};
+pub use timeseries::{UsagePoint, UsageRow, UsageSeries};
+pub use transcript::{
+    TranscriptConfig, TranscriptStore, TranscriptTurn, DEFAULT_TRANSCRIPT_DB,
+    DEFAULT_TRANSCRIPT_RETENTION_DAYS,
+};
+pub use upstream::{
+    is_idempotent, upstream_error_body, CircuitBreaker, CircuitBreakerConfig, CircuitState,
+    RetryPolicy, DEFAULT_COOLDOWN_SECS, DEFAULT_FAILURE_THRESHOLD, DEFAULT_RETRY_ATTEMPTS,
+};
+pub use validate::{Diagnostic, PolicyValidation, Severity};
+pub use vault::{Vault, DEFAULT_VAULT_PATH};
+pub use wireguard::{WireGuardPeer, WireGuardPeers};
 
 /// Initialize the YORI core module for Python.
 ///
@@ -58,10 +363,48 @@ pub use policy::PolicyEngine;
 fn yori_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
     // Register PolicyEngine class
     m.add_class::<PolicyEngine>()?;
+    m.add_class::<policy::PyPolicyResult>()?;
+
+    // Register ProxyServer class
+    m.add_class::<proxy::PyProxyServer>()?;
 
     // Register Cache class
     m.add_class::<Cache>()?;
 
+    // Register LatencyTracker class
+    m.add_class::<LatencyTracker>()?;
+
+    // Register UsageSeries class
+    m.add_class::<UsageSeries>()?;
+
+    // Register TenantRegistry class
+    m.add_class::<TenantRegistry>()?;
+
+    // Register AuditLogger class
+    m.add_class::<audit::PyAuditLogger>()?;
+    m.add_class::<audit::PyAuditSubscription>()?;
+
+    // Register TranscriptStore class
+    m.add_class::<transcript::PyTranscriptStore>()?;
+
+    // Register ApiKeyStore class
+    m.add_class::<secrets::PyApiKeyStore>()?;
+
+    // Register WireGuardPeers class
+    m.add_class::<WireGuardPeers>()?;
+    m.add_class::<DeviceRegistry>()?;
+    m.add_class::<NameDiscovery>()?;
+
+    // Register backup/restore functions
+    m.add_function(wrap_pyfunction!(backup::backup, m)?)?;
+    m.add_function(wrap_pyfunction!(backup::restore, m)?)?;
+
+    // Register config loading
+    m.add_function(wrap_pyfunction!(config::load_config, m)?)?;
+
+    // Register proxy password hashing
+    m.add_function(wrap_pyfunction!(proxyauth::py_hash_password, m)?)?;
+
     // Add version info
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
     m.add("__author__", "James Henry <jamesrahenry@henrynet.ca>")?;
@@ -71,8 +414,6 @@ fn yori_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_module_initialization() {
         // Basic smoke test
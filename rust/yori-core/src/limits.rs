@@ -0,0 +1,259 @@
+//! Request and response body size limits
+//!
+//! A router has little memory to spare, and a buggy client (or a hostile
+//! one) shouldn't be able to make it buffer a 2 GB upload. Bodies are
+//! checked twice:
+//!
+//! - Up front, from `Content-Length` ([`check_content_length`]), so an
+//!   oversized request is refused before a byte of it is read
+//! - While reading, by counting frames ([`collect_limited`] for bodies the
+//!   proxy buffers, [`LimitedBody`] for bodies streamed through), since
+//!   chunked bodies carry no length and a length can lie
+//!
+//! Oversized requests are answered with 413 and a JSON body
+//! ([`payload_too_large_body`]). An oversized upstream response that is
+//! still being buffered becomes a 502; one already streaming to the client
+//! is cut off.
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use serde_json::json;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// Default largest request body (prompts with a few images fit easily)
+pub const DEFAULT_MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Default largest upstream response body
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Which body went over its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    Request,
+    Response,
+}
+
+impl BodyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyKind::Request => "request",
+            BodyKind::Response => "response",
+        }
+    }
+}
+
+/// A body exceeded its size limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyTooLarge {
+    pub kind: BodyKind,
+
+    /// Configured limit in bytes
+    pub limit: u64,
+
+    /// Bytes declared or read when the limit was hit
+    pub size: u64,
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} body of {}+ bytes exceeds the {} byte limit",
+            self.kind.as_str(),
+            self.size,
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+/// Refuse a body whose declared `Content-Length` is over `limit`
+pub fn check_content_length(
+    kind: BodyKind,
+    content_length: Option<u64>,
+    limit: Option<u64>,
+) -> Result<(), BodyTooLarge> {
+    match (content_length, limit) {
+        (Some(size), Some(limit)) if size > limit => Err(BodyTooLarge { kind, limit, size }),
+        _ => Ok(()),
+    }
+}
+
+/// Error from reading a size-limited body
+#[derive(Debug)]
+pub enum LimitError<E> {
+    /// The body went over its limit
+    TooLarge(BodyTooLarge),
+
+    /// The underlying body failed
+    Body(E),
+}
+
+impl<E: fmt::Display> fmt::Display for LimitError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::TooLarge(e) => e.fmt(f),
+            LimitError::Body(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for LimitError<E> {}
+
+/// Read a whole body into memory, stopping as soon as it passes `limit`
+pub async fn collect_limited<B>(
+    mut body: B,
+    kind: BodyKind,
+    limit: Option<u64>,
+) -> Result<Bytes, LimitError<B::Error>>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    use http_body_util::BodyExt;
+
+    let mut collected = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(LimitError::Body)?;
+        if let Some(data) = frame.data_ref() {
+            let size = (collected.len() + data.len()) as u64;
+            if let Some(limit) = limit.filter(|limit| size > *limit) {
+                return Err(LimitError::TooLarge(BodyTooLarge { kind, limit, size }));
+            }
+            collected.extend_from_slice(data);
+        }
+    }
+    Ok(Bytes::from(collected))
+}
+
+/// Body passed through frame by frame that fails once it passes its limit
+pub struct LimitedBody<B> {
+    inner: B,
+    kind: BodyKind,
+    limit: Option<u64>,
+    seen: u64,
+}
+
+impl<B> LimitedBody<B> {
+    /// Wrap a body; `None` passes it through unlimited
+    pub fn new(inner: B, kind: BodyKind, limit: Option<u64>) -> Self {
+        LimitedBody {
+            inner,
+            kind,
+            limit,
+            seen: 0,
+        }
+    }
+
+    /// Bytes passed through so far
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = LimitError<B::Error>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => return Poll::Ready(Some(Err(LimitError::Body(e)))),
+            None => return Poll::Ready(None),
+        };
+        if let Some(data) = frame.data_ref() {
+            this.seen += data.len() as u64;
+            if let Some(limit) = this.limit.filter(|limit| this.seen > *limit) {
+                return Poll::Ready(Some(Err(LimitError::TooLarge(BodyTooLarge {
+                    kind: this.kind,
+                    limit,
+                    size: this.seen,
+                }))));
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// JSON body of the 413 sent for an oversized request
+pub fn payload_too_large_body(error: &BodyTooLarge) -> serde_json::Value {
+    json!({
+        "error": {
+            "type": "payload_too_large",
+            "message": format!(
+                "The {} body is larger than this network allows ({} bytes)",
+                error.kind.as_str(),
+                error.limit
+            ),
+            "limit_bytes": error.limit,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::convert::Infallible;
+
+    fn chunks(
+        sizes: &[usize],
+    ) -> StreamBody<impl futures::Stream<Item = Result<Frame<Bytes>, Infallible>>> {
+        let frames: Vec<_> = sizes
+            .iter()
+            .map(|n| Ok(Frame::data(Bytes::from(vec![b'x'; *n]))))
+            .collect();
+        StreamBody::new(futures::stream::iter(frames))
+    }
+
+    #[test]
+    fn test_content_length_checked_up_front() {
+        assert!(check_content_length(BodyKind::Request, Some(100), Some(100)).is_ok());
+        assert!(check_content_length(BodyKind::Request, None, Some(100)).is_ok());
+        let error = check_content_length(BodyKind::Request, Some(101), Some(100)).unwrap_err();
+        assert_eq!(error.size, 101);
+        assert_eq!(payload_too_large_body(&error)["error"]["limit_bytes"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_collect_stops_at_limit() {
+        let body = collect_limited(chunks(&[40, 40]), BodyKind::Request, Some(100))
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 80);
+
+        match collect_limited(chunks(&[40, 40, 40]), BodyKind::Request, Some(100)).await {
+            Err(LimitError::TooLarge(e)) => assert_eq!((e.limit, e.size), (100, 120)),
+            other => panic!("expected TooLarge, got {:?}", other.map(|b| b.len())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_body_fails_past_limit() {
+        let mut body = LimitedBody::new(chunks(&[60, 60]), BodyKind::Response, Some(100));
+        assert!(body.frame().await.unwrap().is_ok());
+        assert!(matches!(
+            body.frame().await.unwrap(),
+            Err(LimitError::TooLarge(_))
+        ));
+
+        let body = LimitedBody::new(chunks(&[60, 60]), BodyKind::Response, None);
+        assert_eq!(body.collect().await.unwrap().to_bytes().len(), 120);
+    }
+}
@@ -0,0 +1,305 @@
+//! Live-tail streaming of audit events and proxy status
+//!
+//! The dashboard's live view used to poll the audit table every few
+//! seconds. Instead, the audit logger and proxy publish to a [`LiveTail`]
+//! broadcast channel and the admin API streams it to browsers as
+//! Server-Sent Events:
+//!
+//! ```text
+//! GET /api/audit/stream?tenant=upstairs&type=block,request&endpoint=openai
+//!
+//! event: audit
+//! data: {"event_type":"block","client_ip":"192.168.1.50",...}
+//!
+//! event: status
+//! data: {"kind":"local_only","enabled":true,...}
+//! ```
+//!
+//! Slow clients never hold up the proxy: if a subscriber falls behind the
+//! channel capacity it receives a `lagged` event with the number of events
+//! it missed and carries on from the newest one.
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use hyper::body::Bytes;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::audit::AuditEvent;
+use crate::netaddr::same_client_ip;
+
+/// Events buffered per subscriber before it counts as lagging
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Interval between SSE keep-alive comments
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Something the live view should show
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    /// An audit event was written
+    Audit(Box<AuditEvent>),
+
+    /// Proxy status changed (mode, local-only, ...)
+    Status {
+        /// What changed (e.g., "local_only")
+        kind: String,
+
+        /// Details of the change
+        detail: serde_json::Value,
+
+        /// When it changed
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl LiveEvent {
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Audit(_) => "audit",
+            LiveEvent::Status { .. } => "status",
+        }
+    }
+
+    /// JSON payload sent as the SSE `data` field
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            LiveEvent::Audit(event) => event.to_json(),
+            LiveEvent::Status {
+                kind,
+                detail,
+                timestamp,
+            } => json!({
+                "kind": kind,
+                "detail": detail,
+                "timestamp": timestamp.to_rfc3339(),
+            }),
+        }
+    }
+
+    /// Render as one Server-Sent Events message
+    pub fn to_sse(&self) -> String {
+        format!("event: {}\ndata: {}\n\n", self.name(), self.to_json())
+    }
+}
+
+/// Subscriber-side filter, parsed from the endpoint's query string
+///
+/// Every set field must match. Status events only honour `status`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveFilter {
+    /// Only events for this tenant (`tenant=`)
+    pub tenant: Option<String>,
+
+    /// Only events from this client IP (`client_ip=`)
+    pub client_ip: Option<String>,
+
+    /// Only endpoints containing this text (`endpoint=`)
+    pub endpoint: Option<String>,
+
+    /// Only these audit event types (`type=request,block`)
+    pub event_types: Vec<String>,
+
+    /// Include proxy status events (`status=false` to drop them)
+    pub status: bool,
+}
+
+impl Default for LiveFilter {
+    fn default() -> Self {
+        LiveFilter {
+            tenant: None,
+            client_ip: None,
+            endpoint: None,
+            event_types: Vec::new(),
+            status: true,
+        }
+    }
+}
+
+impl LiveFilter {
+    /// Parse `key=value&...` (unknown keys are ignored)
+    pub fn from_query(query: &str) -> Self {
+        let mut filter = LiveFilter::default();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match key {
+                "tenant" => filter.tenant = Some(value),
+                "client_ip" => filter.client_ip = Some(value),
+                "endpoint" => filter.endpoint = Some(value),
+                "type" => {
+                    filter.event_types = value
+                        .split(',')
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "status" => filter.status = value != "false" && value != "0",
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    /// Whether an event passes the filter
+    pub fn matches(&self, event: &LiveEvent) -> bool {
+        let event = match event {
+            LiveEvent::Status { .. } => return self.status,
+            LiveEvent::Audit(event) => event,
+        };
+        self.tenant.as_ref().is_none_or(|t| *t == event.tenant)
+            && self
+                .client_ip
+                .as_ref()
+                .is_none_or(|ip| same_client_ip(ip, &event.client_ip))
+            && self
+                .endpoint
+                .as_ref()
+                .is_none_or(|e| event.endpoint.contains(e.as_str()))
+            && (self.event_types.is_empty()
+                || self
+                    .event_types
+                    .iter()
+                    .any(|t| t == event.event_type.as_str()))
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (_, Some(b)) => {
+                out.push(b);
+                i += 2;
+            }
+            (b'+', None) => out.push(b' '),
+            (b, None) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Broadcast channel the audit logger and proxy publish to
+#[derive(Debug, Clone)]
+pub struct LiveTail {
+    sender: broadcast::Sender<Arc<LiveEvent>>,
+}
+
+impl Default for LiveTail {
+    fn default() -> Self {
+        LiveTail::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl LiveTail {
+    /// Create a channel buffering `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        LiveTail { sender }
+    }
+
+    /// Publish an event (dropped silently when nobody is watching)
+    pub fn publish(&self, event: LiveEvent) {
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    /// Publish a proxy status change
+    pub fn publish_status(&self, kind: &str, detail: serde_json::Value) {
+        self.publish(LiveEvent::Status {
+            kind: kind.to_string(),
+            detail,
+            timestamp: Utc::now(),
+        });
+    }
+
+    /// Subscribe to future events
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// Number of connected subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// SSE byte stream of events passing `filter`
+    pub fn sse_stream(&self, filter: LiveFilter) -> impl Stream<Item = Bytes> + Send + 'static {
+        let receiver = self.subscribe();
+        // Comment line so clients (and intermediaries) see the stream open
+        let hello = stream::once(async { Bytes::from_static(b": connected\n\n") });
+        let events = stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+            loop {
+                let next = tokio::time::timeout(KEEPALIVE, receiver.recv()).await;
+                let chunk = match next {
+                    Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+                    Ok(Ok(event)) if filter.matches(&event) => Bytes::from(event.to_sse()),
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(broadcast::error::RecvError::Lagged(missed))) => Bytes::from(format!(
+                        "event: lagged\ndata: {}\n\n",
+                        json!({ "missed": missed })
+                    )),
+                    Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+                };
+                return Some((chunk, (receiver, filter)));
+            }
+        });
+        futures::StreamExt::chain(hello, events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditEventType;
+    use crate::proxy::{test_request, RequestContext};
+    use futures::StreamExt;
+
+    fn event(tenant: &str, event_type: AuditEventType) -> LiveEvent {
+        let ctx = RequestContext {
+            tenant: tenant.to_string(),
+            ..test_request()
+        };
+        LiveEvent::Audit(Box::new(AuditEvent::from_request(event_type, &ctx)))
+    }
+
+    #[test]
+    fn test_filter_from_query() {
+        let filter = LiveFilter::from_query("tenant=unit%2Da&type=block,request&status=false");
+        assert_eq!(filter.tenant.as_deref(), Some("unit-a"));
+        assert!(!filter.status);
+
+        assert!(filter.matches(&event("unit-a", AuditEventType::RequestBlocked)));
+        assert!(!filter.matches(&event("unit-b", AuditEventType::RequestBlocked)));
+        assert!(!filter.matches(&event("unit-a", AuditEventType::Response)));
+        assert!(!filter.matches(&LiveEvent::Status {
+            kind: "local_only".to_string(),
+            detail: json!({ "enabled": true }),
+            timestamp: Utc::now(),
+        }));
+        assert!(LiveFilter::from_query("").matches(&event("x", AuditEventType::Error)));
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_yields_matching_events() {
+        let tail = LiveTail::default();
+        let mut stream = Box::pin(tail.sse_stream(LiveFilter::from_query("type=block")));
+        assert_eq!(stream.next().await.unwrap(), ": connected\n\n");
+
+        tail.publish(event("default", AuditEventType::Request));
+        tail.publish(event("default", AuditEventType::RequestBlocked));
+        let chunk = stream.next().await.unwrap();
+        let text = String::from_utf8_lossy(&chunk);
+        assert!(text.starts_with("event: audit"));
+        assert!(text.contains("\"event_type\":\"block\""));
+    }
+}
@@ -0,0 +1,525 @@
+//! Routing requests to a local model server (Ollama, llama.cpp)
+//!
+//! Some requests should never leave the house: a kid's tablet asking for
+//! `gpt-4o` can be answered by `llama3.2` on the home server instead. A
+//! request is routed locally when a policy decision asks for it with an
+//! obligation, or when its model matches a configured route:
+//!
+//! ```rego
+//! obligations := {"route_local": "ollama"} if input.device.profile == "kids"
+//! ```
+//!
+//! `true` instead of a backend name picks the first configured backend.
+//!
+//! Both Ollama and the llama.cpp server speak the OpenAI API under `/v1`,
+//! so OpenAI-compatible requests are passed through with the model
+//! swapped. Anthropic Messages requests are translated to Chat Completions
+//! and the answer translated back (as SSE when the client streamed). Gemini
+//! and Bedrock requests can't be routed. Client credentials are never
+//! forwarded to the local server, and responses carry an
+//! `X-Yori-Routed: <backend>` header.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::promptcache::model_matches;
+use crate::providers::Provider;
+
+/// Obligation naming the backend a decision routes to
+pub const ROUTE_OBLIGATION: &str = "route_local";
+
+/// Header naming the backend that answered a routed request
+pub const ROUTED_HEADER: &str = "x-yori-routed";
+
+/// Local model server software
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Ollama,
+    LlamaCpp,
+}
+
+impl BackendKind {
+    /// Kind name as used in yori.toml
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "ollama",
+            BackendKind::LlamaCpp => "llamacpp",
+        }
+    }
+
+    /// Port the server listens on out of the box
+    pub fn default_port(&self) -> u16 {
+        match self {
+            BackendKind::Ollama => 11434,
+            BackendKind::LlamaCpp => 8080,
+        }
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ollama" => Ok(BackendKind::Ollama),
+            "llamacpp" | "llama.cpp" | "llama-cpp" => Ok(BackendKind::LlamaCpp),
+            _ => bail!("unknown backend kind {:?} (expected ollama or llamacpp)", s),
+        }
+    }
+}
+
+/// A local model server
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalBackend {
+    /// Name used by routes and obligations
+    pub name: String,
+
+    pub kind: BackendKind,
+
+    /// "host" or "host:port" (port defaults per kind)
+    pub address: String,
+
+    /// Model to request (None = keep the client's model name; llama.cpp
+    /// serves whatever model it was started with either way)
+    pub model: Option<String>,
+
+    /// Bearer token for servers started with an API key
+    pub api_key: Option<String>,
+}
+
+impl LocalBackend {
+    /// `host:port` to connect to
+    pub fn authority(&self) -> String {
+        if self
+            .address
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+        {
+            self.address.clone()
+        } else {
+            format!("{}:{}", self.address, self.kind.default_port())
+        }
+    }
+}
+
+/// Models always sent to a backend, whatever the policy decides
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalRoute {
+    /// Model names, exact or a prefix ending in `*`
+    pub models: Vec<String>,
+
+    /// Backend name
+    pub backend: String,
+}
+
+/// Local routing settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalRoutingConfig {
+    pub backends: Vec<LocalBackend>,
+    pub routes: Vec<LocalRoute>,
+}
+
+/// API shape the client expects its answer in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiShape {
+    OpenAI,
+    Anthropic,
+}
+
+/// A request rewritten for a local backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedRequest {
+    /// Backend name
+    pub backend: String,
+
+    /// `host:port` of the backend (plain HTTP)
+    pub authority: String,
+
+    /// Path on the backend, e.g. "/v1/chat/completions"
+    pub path: String,
+
+    /// Complete header set to send (client headers are not forwarded)
+    pub headers: Vec<(String, String)>,
+
+    /// Rewritten JSON body
+    pub body: Vec<u8>,
+
+    /// Shape the answer has to be translated back to
+    pub client_shape: ApiShape,
+
+    /// Whether the client asked for a streamed answer
+    pub client_stream: bool,
+}
+
+impl RoutedRequest {
+    /// Translate the backend's answer for the client
+    ///
+    /// OpenAI-shaped answers (streamed or not) pass through untouched.
+    /// Returns the content type and body.
+    pub fn translate_response(&self, body: &[u8]) -> Result<(&'static str, Vec<u8>)> {
+        match self.client_shape {
+            ApiShape::OpenAI if self.client_stream => Ok(("text/event-stream", body.to_vec())),
+            ApiShape::OpenAI => Ok(("application/json", body.to_vec())),
+            ApiShape::Anthropic => {
+                let completion: Value = serde_json::from_slice(body)
+                    .context("local backend answered with invalid JSON")?;
+                let message = anthropic_message(&completion);
+                if self.client_stream {
+                    Ok(("text/event-stream", anthropic_events(&message).into_bytes()))
+                } else {
+                    Ok(("application/json", message.to_string().into_bytes()))
+                }
+            }
+        }
+    }
+}
+
+/// Picks a backend for each request and rewrites it
+#[derive(Debug, Clone, Default)]
+pub struct LocalRouter {
+    config: LocalRoutingConfig,
+}
+
+impl LocalRouter {
+    /// Create from configuration
+    ///
+    /// Routes naming an unknown backend are logged and never match.
+    pub fn new(config: LocalRoutingConfig) -> Self {
+        for route in &config.routes {
+            if !config.backends.iter().any(|b| b.name == route.backend) {
+                tracing::warn!(
+                    "Route for {:?} names unknown backend {:?}",
+                    route.models,
+                    route.backend
+                );
+            }
+        }
+        LocalRouter { config }
+    }
+
+    /// Backend for a request, from the decision's obligations first, then
+    /// the configured model routes
+    ///
+    /// Fails when an obligation names a backend that isn't configured, so
+    /// the request can be blocked instead of reaching the cloud.
+    pub fn backend_for(
+        &self,
+        model: Option<&str>,
+        obligations: &Value,
+    ) -> Result<Option<&LocalBackend>> {
+        let by_name = |name: &str| self.config.backends.iter().find(|b| b.name == name);
+        match obligations.get(ROUTE_OBLIGATION) {
+            Some(Value::String(name)) => {
+                return by_name(name)
+                    .map(Some)
+                    .with_context(|| format!("policy routed to unknown backend {:?}", name));
+            }
+            Some(Value::Bool(true)) => {
+                return self
+                    .config
+                    .backends
+                    .first()
+                    .map(Some)
+                    .context("policy routed to a local model, but none is configured");
+            }
+            _ => {}
+        }
+        let Some(model) = model else {
+            return Ok(None);
+        };
+        Ok(self
+            .config
+            .routes
+            .iter()
+            .find(|route| {
+                route
+                    .models
+                    .iter()
+                    .any(|pattern| model_matches(pattern, model))
+            })
+            .and_then(|route| by_name(&route.backend)))
+    }
+
+    /// Rewrite a provider request for `backend`
+    ///
+    /// Fails for APIs that can't be translated (Gemini, Bedrock, and
+    /// OpenAI endpoints the local servers don't offer).
+    pub fn rewrite(
+        &self,
+        backend: &LocalBackend,
+        provider: Provider,
+        path: &str,
+        body: &[u8],
+    ) -> Result<RoutedRequest> {
+        let mut json: Value = serde_json::from_slice(body).context("request body is not JSON")?;
+        let client_stream = json["stream"].as_bool().unwrap_or(false);
+        let path = path.split('?').next().unwrap_or(path);
+
+        let (local_path, client_shape) = match provider {
+            Provider::OpenAI
+            | Provider::AzureOpenAI
+            | Provider::Mistral
+            | Provider::OpenRouter
+            | Provider::Groq
+            | Provider::DeepSeek
+            | Provider::Together => {
+                let local_path = ["chat/completions", "completions", "embeddings"]
+                    .into_iter()
+                    .find(|suffix| path.ends_with(&format!("/{}", suffix)))
+                    .map(|suffix| format!("/v1/{}", suffix))
+                    .with_context(|| format!("{} has no local equivalent", path))?;
+                (local_path, ApiShape::OpenAI)
+            }
+            Provider::Anthropic if path.ends_with("/messages") => {
+                json = chat_request(&json);
+                ("/v1/chat/completions".to_string(), ApiShape::Anthropic)
+            }
+            _ => bail!(
+                "{} requests to {} can't be routed to a local model",
+                provider.as_str(),
+                path
+            ),
+        };
+
+        if let Some(model) = &backend.model {
+            json["model"] = json!(model);
+        }
+
+        let mut headers = vec![("content-type".to_string(), "application/json".to_string())];
+        if let Some(key) = &backend.api_key {
+            headers.push(("authorization".to_string(), format!("Bearer {}", key)));
+        }
+        Ok(RoutedRequest {
+            backend: backend.name.clone(),
+            authority: backend.authority(),
+            path: local_path,
+            headers,
+            body: serde_json::to_vec(&json)?,
+            client_shape,
+            client_stream,
+        })
+    }
+}
+
+/// Anthropic Messages request as a (non-streaming) Chat Completions request
+fn chat_request(request: &Value) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = text_content(&request["system"]) {
+        messages.push(json!({"role": "system", "content": system}));
+    }
+    for message in request["messages"].as_array().into_iter().flatten() {
+        messages.push(json!({
+            "role": message["role"].as_str().unwrap_or("user"),
+            "content": text_content(&message["content"]).unwrap_or_default(),
+        }));
+    }
+    let mut chat = json!({
+        "model": request["model"],
+        "messages": messages,
+        "stream": false,
+    });
+    for (from, to) in [
+        ("max_tokens", "max_tokens"),
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("stop_sequences", "stop"),
+    ] {
+        if !request[from].is_null() {
+            chat[to] = request[from].clone();
+        }
+    }
+    chat
+}
+
+/// Text of a string or a list of `{"type": "text"}` blocks
+fn text_content(content: &Value) -> Option<String> {
+    match content {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(blocks) => Some(
+            blocks
+                .iter()
+                .filter_map(|block| block["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        _ => None,
+    }
+}
+
+/// Chat Completions answer as an Anthropic message
+fn anthropic_message(completion: &Value) -> Value {
+    let choice = &completion["choices"][0];
+    let stop_reason = match choice["finish_reason"].as_str() {
+        Some("length") => "max_tokens",
+        Some("tool_calls") => "tool_use",
+        _ => "end_turn",
+    };
+    json!({
+        "id": format!("msg_{}", completion["id"].as_str().unwrap_or("local")),
+        "type": "message",
+        "role": "assistant",
+        "model": completion["model"],
+        "content": [{"type": "text", "text": choice["message"]["content"].as_str().unwrap_or("")}],
+        "stop_reason": stop_reason,
+        "usage": {
+            "input_tokens": completion["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            "output_tokens": completion["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        },
+    })
+}
+
+/// An Anthropic message as the SSE events of a streamed answer
+fn anthropic_events(message: &Value) -> String {
+    let text = &message["content"][0]["text"];
+    let mut start = message.clone();
+    start["content"] = json!([]);
+    let events = [
+        (
+            "message_start",
+            json!({"type": "message_start", "message": start}),
+        ),
+        (
+            "content_block_start",
+            json!({"type": "content_block_start", "index": 0,
+                   "content_block": {"type": "text", "text": ""}}),
+        ),
+        (
+            "content_block_delta",
+            json!({"type": "content_block_delta", "index": 0,
+                   "delta": {"type": "text_delta", "text": text}}),
+        ),
+        (
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": 0}),
+        ),
+        (
+            "message_delta",
+            json!({"type": "message_delta",
+                   "delta": {"stop_reason": message["stop_reason"]},
+                   "usage": {"output_tokens": message["usage"]["output_tokens"]}}),
+        ),
+        ("message_stop", json!({"type": "message_stop"})),
+    ];
+    events
+        .iter()
+        .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> LocalRouter {
+        LocalRouter::new(LocalRoutingConfig {
+            backends: vec![LocalBackend {
+                name: "ollama".to_string(),
+                kind: BackendKind::Ollama,
+                address: "ollama.lan".to_string(),
+                model: Some("llama3.2".to_string()),
+                api_key: None,
+            }],
+            routes: vec![LocalRoute {
+                models: vec!["llama*".to_string()],
+                backend: "ollama".to_string(),
+            }],
+        })
+    }
+
+    #[test]
+    fn test_backend_from_obligation_or_route() {
+        let router = router();
+        let routed = |model, obligations: Value| {
+            router
+                .backend_for(model, &obligations)
+                .map(|b| b.map(|b| b.name.clone()))
+        };
+        let ollama = Some("ollama".to_string());
+        assert_eq!(
+            routed(Some("gpt-4o"), json!({"route_local": "ollama"})).unwrap(),
+            ollama
+        );
+        assert_eq!(
+            routed(Some("gpt-4o"), json!({"route_local": true})).unwrap(),
+            ollama
+        );
+        assert_eq!(routed(Some("Llama3-70b"), json!({})).unwrap(), ollama);
+        assert_eq!(routed(Some("gpt-4o"), json!({})).unwrap(), None);
+        // A policy asking for a backend that isn't there must not fall
+        // through to the cloud
+        assert!(routed(Some("gpt-4o"), json!({"route_local": "nope"})).is_err());
+        assert_eq!(router.config.backends[0].authority(), "ollama.lan:11434");
+    }
+
+    #[test]
+    fn test_openai_requests_pass_through_with_model_swapped() {
+        let router = router();
+        let backend = &router.config.backends[0];
+        let body = br#"{"model": "gpt-4o", "stream": true, "messages": [{"role": "user", "content": "hi"}]}"#;
+        let routed = router
+            .rewrite(
+                backend,
+                Provider::AzureOpenAI,
+                "/openai/deployments/gpt4/chat/completions?api-version=1",
+                body,
+            )
+            .unwrap();
+        assert_eq!(routed.path, "/v1/chat/completions");
+        assert_eq!(routed.client_shape, ApiShape::OpenAI);
+        assert!(routed.client_stream);
+        let sent: Value = serde_json::from_slice(&routed.body).unwrap();
+        assert_eq!(sent["model"], "llama3.2");
+        assert_eq!(sent["messages"][0]["content"], "hi");
+        // Cloud credentials stay behind
+        assert!(routed
+            .headers
+            .iter()
+            .all(|(name, _)| name != "authorization"));
+
+        assert!(router
+            .rewrite(
+                backend,
+                Provider::Gemini,
+                "/v1beta/models/gemini-pro:generateContent",
+                b"{}"
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_anthropic_translated_both_ways() {
+        let router = router();
+        let backend = &router.config.backends[0];
+        let body = br#"{"model": "claude-sonnet-4", "max_tokens": 100, "stream": true,
+            "system": "Be brief", "messages": [{"role": "user", "content": [{"type": "text", "text": "hi"}]}]}"#;
+        let routed = router
+            .rewrite(backend, Provider::Anthropic, "/v1/messages", body)
+            .unwrap();
+        let sent: Value = serde_json::from_slice(&routed.body).unwrap();
+        assert_eq!(
+            sent["messages"][0],
+            json!({"role": "system", "content": "Be brief"})
+        );
+        assert_eq!(
+            sent["messages"][1],
+            json!({"role": "user", "content": "hi"})
+        );
+        assert_eq!(
+            (sent["max_tokens"].clone(), sent["stream"].clone()),
+            (json!(100), json!(false))
+        );
+
+        let answer = json!({
+            "id": "chatcmpl-1", "model": "llama3.2",
+            "choices": [{"message": {"role": "assistant", "content": "Hello!"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 2},
+        });
+        let (content_type, events) = routed
+            .translate_response(answer.to_string().as_bytes())
+            .unwrap();
+        let events = String::from_utf8(events).unwrap();
+        assert_eq!(content_type, "text/event-stream");
+        assert!(events.contains(r#""text":"Hello!""#));
+        assert!(events.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+    }
+}
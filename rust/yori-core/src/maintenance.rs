@@ -0,0 +1,331 @@
+//! Scheduled audit database maintenance
+//!
+//! On a router the audit database lives on slow flash and grows for a year
+//! before retention trims it. Left alone, deleted rows leave free pages
+//! behind, query plans go stale and the WAL file balloons. The scheduler
+//! runs [`AuditLogger::run_maintenance`] once per quiet window (e.g. 03:00
+//! to 05:00 local time) so the work never competes with daytime traffic.
+//!
+//! Retention is enforced separately and around the clock: every
+//! `prune_interval` the scheduler deletes events older than
+//! `audit.retention_days` ([`AuditLogger::prune_old_logs`]), leaving the
+//! freed pages to the next quiet-hours pass. Every `rollup_interval` it
+//! first folds new events into the daily and weekly usage rollups
+//! ([`AuditLogger::refresh_rollups`]), so they are counted before pruning
+//! removes them.
+
+use chrono::{DateTime, Local, NaiveTime, Timelike};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audit::{AuditLogger, MaintenanceReport, PruneReport};
+use crate::rollup::RollupReport;
+
+/// When and how much maintenance to run
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    /// Start of the quiet window (local time)
+    pub quiet_start: NaiveTime,
+
+    /// End of the quiet window (local time); may be before `quiet_start`
+    /// for windows spanning midnight
+    pub quiet_end: NaiveTime,
+
+    /// Minimum time between two maintenance passes
+    pub min_interval: Duration,
+
+    /// Free pages reclaimed per pass
+    pub vacuum_pages: u32,
+
+    /// How often the scheduler wakes up to check the window
+    pub poll_interval: Duration,
+
+    /// Minimum time between two retention pruning passes
+    pub prune_interval: Duration,
+
+    /// Minimum time between two usage rollup refreshes (None = no rollups)
+    pub rollup_interval: Option<Duration>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            quiet_start: NaiveTime::from_hms_opt(3, 0, 0).unwrap(),
+            quiet_end: NaiveTime::from_hms_opt(5, 0, 0).unwrap(),
+            min_interval: Duration::from_secs(20 * 3600),
+            vacuum_pages: 2_000,
+            poll_interval: Duration::from_secs(300),
+            prune_interval: Duration::from_secs(3600),
+            rollup_interval: Some(Duration::from_secs(900)),
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    /// Whether `time` falls inside the quiet window
+    pub fn is_quiet(&self, time: NaiveTime) -> bool {
+        let time = time.with_nanosecond(0).unwrap_or(time);
+        if self.quiet_start <= self.quiet_end {
+            time >= self.quiet_start && time < self.quiet_end
+        } else {
+            time >= self.quiet_start || time < self.quiet_end
+        }
+    }
+}
+
+/// Runs audit maintenance during quiet hours and prunes expired events
+pub struct MaintenanceScheduler {
+    config: MaintenanceConfig,
+    last_run: Mutex<Option<DateTime<Local>>>,
+    last_prune: Mutex<Option<DateTime<Local>>>,
+    last_rollup: Mutex<Option<DateTime<Local>>>,
+    events_pruned: AtomicU64,
+}
+
+impl MaintenanceScheduler {
+    /// Create a scheduler that hasn't run yet
+    pub fn new(config: MaintenanceConfig) -> Self {
+        MaintenanceScheduler {
+            config,
+            last_run: Mutex::new(None),
+            last_prune: Mutex::new(None),
+            last_rollup: Mutex::new(None),
+            events_pruned: AtomicU64::new(0),
+        }
+    }
+
+    /// Events deleted by retention pruning since the scheduler started
+    pub fn events_pruned(&self) -> u64 {
+        self.events_pruned.load(Ordering::Relaxed)
+    }
+
+    /// Prune expired events if `prune_interval` has passed since the last
+    /// pass at `now`
+    pub fn prune_tick(&self, logger: &AuditLogger, now: DateTime<Local>) -> Option<PruneReport> {
+        {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            if let Some(last) = *last_prune {
+                if (now - last).to_std().unwrap_or_default() < self.config.prune_interval {
+                    return None;
+                }
+            }
+            *last_prune = Some(now);
+        }
+
+        match logger.prune_old_logs() {
+            Ok(report) => {
+                self.events_pruned
+                    .fetch_add(report.events_deleted, Ordering::Relaxed);
+                if report.events_deleted > 0 || report.prompts_deleted > 0 {
+                    tracing::info!(
+                        "Audit retention: {} events and {} prompts deleted in {}ms",
+                        report.events_deleted,
+                        report.prompts_deleted,
+                        report.duration_ms
+                    );
+                }
+                Some(report)
+            }
+            Err(e) => {
+                tracing::warn!("Audit retention pruning failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Refresh the usage rollups if `rollup_interval` has passed since the
+    /// last refresh at `now`
+    pub fn rollup_tick(&self, logger: &AuditLogger, now: DateTime<Local>) -> Option<RollupReport> {
+        let interval = self.config.rollup_interval?;
+        {
+            let mut last_rollup = self.last_rollup.lock().unwrap();
+            if let Some(last) = *last_rollup {
+                if (now - last).to_std().unwrap_or_default() < interval {
+                    return None;
+                }
+            }
+            *last_rollup = Some(now);
+        }
+
+        match logger.refresh_rollups(now.with_timezone(&chrono::Utc)) {
+            Ok(report) => {
+                tracing::debug!(
+                    "Usage rollups: {} rows written in {}ms",
+                    report.rows_written,
+                    report.duration_ms
+                );
+                Some(report)
+            }
+            Err(e) => {
+                tracing::warn!("Usage rollup refresh failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Whether a pass should run at `now`
+    pub fn is_due(&self, now: DateTime<Local>) -> bool {
+        if !self.config.is_quiet(now.time()) {
+            return false;
+        }
+        match *self.last_run.lock().unwrap() {
+            Some(last) => (now - last).to_std().unwrap_or_default() >= self.config.min_interval,
+            None => true,
+        }
+    }
+
+    /// Run a pass if one is due at `now`
+    pub fn tick(&self, logger: &AuditLogger, now: DateTime<Local>) -> Option<MaintenanceReport> {
+        if !self.is_due(now) {
+            return None;
+        }
+
+        // A failed pass is retried at the next poll, not a day later
+        match logger.run_maintenance(self.config.vacuum_pages) {
+            Ok(report) => {
+                *self.last_run.lock().unwrap() = Some(now);
+                tracing::info!(
+                    "Audit maintenance: {} pages freed, {} WAL frames checkpointed in {}ms",
+                    report.pages_freed,
+                    report.frames_checkpointed,
+                    report.duration_ms
+                );
+                Some(report)
+            }
+            Err(e) => {
+                tracing::warn!("Audit maintenance failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Spawn the background loop on the current Tokio runtime
+    ///
+    /// `logger` is asked for the audit logger on every poll, so a logger
+    /// attached or replaced later (e.g. by a config reload) is picked up.
+    pub fn spawn<F>(self: Arc<Self>, logger: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn() -> Option<Arc<AuditLogger>> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.poll_interval);
+            loop {
+                interval.tick().await;
+                let Some(logger) = logger() else {
+                    continue;
+                };
+                let scheduler = Arc::clone(&self);
+                // SQLite work is blocking; keep it off the proxy's reactor
+                let _ = tokio::task::spawn_blocking(move || {
+                    let now = Local::now();
+                    scheduler.rollup_tick(&logger, now);
+                    scheduler.prune_tick(&logger, now);
+                    scheduler.tick(&logger, now);
+                })
+                .await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditConfig;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_quiet_window_spanning_midnight() {
+        let config = MaintenanceConfig {
+            quiet_start: at(23, 0),
+            quiet_end: at(4, 0),
+            ..MaintenanceConfig::default()
+        };
+        assert!(config.is_quiet(at(23, 30)));
+        assert!(config.is_quiet(at(2, 0)));
+        assert!(!config.is_quiet(at(4, 0)));
+        assert!(!config.is_quiet(at(12, 0)));
+        assert!(MaintenanceConfig::default().is_quiet(at(3, 15)));
+    }
+
+    #[test]
+    fn test_runs_once_per_window() {
+        let logger = AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        let scheduler = MaintenanceScheduler::new(MaintenanceConfig::default());
+        let day = |d: u32, h: u32| Local.with_ymd_and_hms(2026, 3, d, h, 0, 0).unwrap();
+
+        assert!(scheduler.tick(&logger, day(1, 14)).is_none());
+        assert!(scheduler.tick(&logger, day(1, 3)).is_some());
+        assert!(scheduler.tick(&logger, day(1, 4)).is_none());
+        assert!(scheduler.tick(&logger, day(2, 3)).is_some());
+    }
+
+    #[test]
+    fn test_prunes_once_per_interval() {
+        let logger = AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            retention_days: 30,
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        let mut old = crate::audit::tests::request("hello");
+        old.timestamp = chrono::Utc::now() - chrono::Duration::days(60);
+        logger.log(&old).unwrap();
+        let scheduler = MaintenanceScheduler::new(MaintenanceConfig::default());
+        let now = Local::now();
+
+        assert_eq!(
+            scheduler.prune_tick(&logger, now).unwrap().events_deleted,
+            1
+        );
+        assert!(scheduler
+            .prune_tick(&logger, now + chrono::Duration::minutes(30))
+            .is_none());
+        assert!(scheduler
+            .prune_tick(&logger, now + chrono::Duration::minutes(61))
+            .is_some());
+        assert_eq!(scheduler.events_pruned(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_loop_picks_up_a_later_logger() {
+        let logger = AuditLogger::open(AuditConfig {
+            database: PathBuf::from(":memory:"),
+            retention_days: 30,
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        let mut old = crate::audit::tests::request("hello");
+        old.timestamp = chrono::Utc::now() - chrono::Duration::days(60);
+        logger.log(&old).unwrap();
+        let scheduler = Arc::new(MaintenanceScheduler::new(MaintenanceConfig {
+            poll_interval: Duration::from_millis(10),
+            ..MaintenanceConfig::default()
+        }));
+        let attached: Arc<Mutex<Option<Arc<AuditLogger>>>> = Arc::default();
+        let current = Arc::clone(&attached);
+        let task = Arc::clone(&scheduler).spawn(move || current.lock().unwrap().clone());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(scheduler.events_pruned(), 0);
+        *attached.lock().unwrap() = Some(Arc::new(logger));
+        for _ in 0..100 {
+            if scheduler.events_pruned() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        task.abort();
+        assert_eq!(scheduler.events_pruned(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+//! Model governance: per-user and per-device model allow/deny lists
+//!
+//! Families rarely want "no AI", they want "not the expensive one" or "not
+//! the one without a safety layer". Each subject (a device name, a device
+//! owner or a client IP) gets a [`ModelList`]; subjects without their own
+//! use `defaults`. A model outside the list is either blocked or, when the
+//! list names a downgrade for it, transparently rewritten in the request
+//! before it's forwarded:
+//!
+//! ```toml
+//! [models.subjects.timmy]
+//! allow = ["gpt-4o-mini", "claude-haiku*"]
+//! downgrade = { "gpt-4o*" = "gpt-4o-mini" }
+//! ```
+//!
+//! Patterns are exact model names or prefixes ending in `*`, ignoring
+//! case. Rewrites are recorded on the audit event (`requested_model` and
+//! `rewritten_model`). Bedrock requests are signed and can't be rewritten,
+//! so a downgrade there blocks instead.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::promptcache::model_matches;
+use crate::providers::Provider;
+
+/// Policy name recorded on model governance audit events
+pub const MODEL_POLICY: &str = "model_governance";
+
+/// Models one subject may use
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelList {
+    /// Models allowed (empty = every model not denied)
+    pub allow: Vec<String>,
+
+    /// Models refused even if `allow` matches
+    pub deny: Vec<String>,
+
+    /// Refused model pattern -> model to use instead
+    pub downgrade: BTreeMap<String, String>,
+}
+
+impl ModelList {
+    /// Whether `model` may be used as requested
+    pub fn allows(&self, model: &str) -> bool {
+        let listed = |patterns: &[String]| patterns.iter().any(|p| model_matches(p, model));
+        (self.allow.is_empty() || listed(&self.allow)) && !listed(&self.deny)
+    }
+
+    /// Replacement for a refused model: an exact entry first, else the
+    /// longest matching pattern
+    fn downgrade_for(&self, model: &str) -> Option<&str> {
+        self.downgrade
+            .iter()
+            .filter(|(pattern, _)| model_matches(pattern, model))
+            .max_by_key(|(pattern, _)| (!pattern.ends_with('*'), pattern.len()))
+            .map(|(_, to)| to.as_str())
+    }
+}
+
+/// Model lists for every subject
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelPolicyConfig {
+    /// List for subjects without their own
+    pub defaults: ModelList,
+
+    /// Lists by device name, device owner or client IP
+    pub subjects: HashMap<String, ModelList>,
+}
+
+/// Outcome of checking a request's model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelDecision {
+    /// Forward as is
+    Allow,
+
+    /// Forward with the model replaced
+    Rewrite { from: String, to: String },
+
+    /// Refuse the request
+    Block { model: String, reason: String },
+}
+
+/// Checks requested models against the configured lists
+#[derive(Debug, Clone, Default)]
+pub struct ModelGovernor {
+    config: ModelPolicyConfig,
+}
+
+impl ModelGovernor {
+    /// Create from configuration
+    pub fn new(config: ModelPolicyConfig) -> Self {
+        ModelGovernor { config }
+    }
+
+    /// List for the first of `subjects` that has one, else the defaults
+    pub fn list_for(&self, subjects: &[&str]) -> &ModelList {
+        subjects
+            .iter()
+            .find_map(|s| self.config.subjects.get(*s))
+            .unwrap_or(&self.config.defaults)
+    }
+
+    /// Decide what to do with a request for `model`
+    ///
+    /// `subjects` are tried in order (e.g. device name, then owner). A
+    /// downgrade is only used when its target is itself allowed.
+    pub fn check(&self, subjects: &[&str], model: &str) -> ModelDecision {
+        let list = self.list_for(subjects);
+        if list.allows(model) {
+            return ModelDecision::Allow;
+        }
+        match list.downgrade_for(model) {
+            Some(to) if list.allows(to) => ModelDecision::Rewrite {
+                from: model.to_string(),
+                to: to.to_string(),
+            },
+            _ => ModelDecision::Block {
+                model: model.to_string(),
+                reason: format!(
+                    "Model {} is not allowed for {}",
+                    model,
+                    subjects.first().unwrap_or(&"this device")
+                ),
+            },
+        }
+    }
+}
+
+/// Rewrite the model of a provider request, returning the new path and body
+///
+/// The model is in the JSON body for OpenAI-compatible and Anthropic
+/// APIs, in the path for Azure deployments and Gemini.
+pub fn rewrite_model(
+    provider: Provider,
+    path: &str,
+    body: &[u8],
+    to: &str,
+) -> Result<(String, Vec<u8>)> {
+    match provider {
+        Provider::AzureOpenAI => Ok((
+            replace_path_segment(path, "deployments", to)?,
+            body.to_vec(),
+        )),
+        Provider::Gemini => {
+            let (prefix, rest) = path
+                .split_once("/models/")
+                .context("Gemini path without a model")?;
+            let method = rest.find([':', '/', '?']).map_or("", |i| &rest[i..]);
+            Ok((format!("{}/models/{}{}", prefix, to, method), body.to_vec()))
+        }
+        Provider::Bedrock => bail!("Bedrock requests are signed and can't be rewritten"),
+        _ => {
+            let mut json: Value =
+                serde_json::from_slice(body).context("request body is not JSON")?;
+            let Some(object) = json.as_object_mut() else {
+                bail!("request body is not a JSON object");
+            };
+            object.insert("model".to_string(), json!(to));
+            Ok((path.to_string(), serde_json::to_vec(&json)?))
+        }
+    }
+}
+
+/// Replace the path segment following `after`
+fn replace_path_segment(path: &str, after: &str, value: &str) -> Result<String> {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut segments: Vec<&str> = path.split('/').collect();
+    let index = segments
+        .iter()
+        .position(|s| *s == after)
+        .filter(|i| i + 1 < segments.len())
+        .with_context(|| format!("no {} segment in {}", after, path))?;
+    segments[index + 1] = value;
+    let path = segments.join("/");
+    Ok(match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    })
+}
+
+/// JSON body of the 403 sent for a refused model
+pub fn model_blocked_body(model: &str, reason: &str) -> Value {
+    json!({
+        "error": {
+            "type": "model_not_allowed",
+            "message": reason,
+            "model": model,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn governor() -> ModelGovernor {
+        let mut subjects = HashMap::new();
+        subjects.insert(
+            "timmy".to_string(),
+            ModelList {
+                allow: vec!["gpt-4o-mini".to_string(), "claude-haiku*".to_string()],
+                downgrade: BTreeMap::from([
+                    ("gpt-4*".to_string(), "gpt-4o-nano".to_string()),
+                    ("gpt-4o".to_string(), "gpt-4o-mini".to_string()),
+                ]),
+                ..ModelList::default()
+            },
+        );
+        ModelGovernor::new(ModelPolicyConfig {
+            defaults: ModelList {
+                deny: vec!["o1*".to_string()],
+                ..ModelList::default()
+            },
+            subjects,
+        })
+    }
+
+    #[test]
+    fn test_lists_allow_rewrite_or_block() {
+        let governor = governor();
+        assert_eq!(
+            governor.check(&["kitchen-ipad"], "gpt-4o"),
+            ModelDecision::Allow
+        );
+        assert!(matches!(
+            governor.check(&["kitchen-ipad"], "o1-preview"),
+            ModelDecision::Block { .. }
+        ));
+
+        let timmy = ["timmys-laptop", "timmy"];
+        assert_eq!(
+            governor.check(&timmy, "Claude-Haiku-3.5"),
+            ModelDecision::Allow
+        );
+        // The exact entry wins over the pattern
+        assert_eq!(
+            governor.check(&timmy, "gpt-4o"),
+            ModelDecision::Rewrite {
+                from: "gpt-4o".to_string(),
+                to: "gpt-4o-mini".to_string()
+            }
+        );
+        // A downgrade to a model that isn't allowed either blocks
+        assert!(matches!(
+            governor.check(&timmy, "gpt-4-turbo"),
+            ModelDecision::Block { .. }
+        ));
+        assert!(matches!(
+            governor.check(&timmy, "claude-opus-4"),
+            ModelDecision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_body_or_path() {
+        let body = br#"{"model": "gpt-4o", "messages": []}"#;
+        let (path, rewritten) = rewrite_model(
+            Provider::OpenAI,
+            "/v1/chat/completions",
+            body,
+            "gpt-4o-mini",
+        )
+        .unwrap();
+        assert_eq!(path, "/v1/chat/completions");
+        let rewritten: Value = serde_json::from_slice(&rewritten).unwrap();
+        assert_eq!(rewritten["model"], "gpt-4o-mini");
+
+        let (path, _) = rewrite_model(
+            Provider::AzureOpenAI,
+            "/openai/deployments/gpt4o/chat/completions?api-version=2024-06-01",
+            body,
+            "gpt4o-mini",
+        )
+        .unwrap();
+        assert_eq!(
+            path,
+            "/openai/deployments/gpt4o-mini/chat/completions?api-version=2024-06-01"
+        );
+
+        let (path, _) = rewrite_model(
+            Provider::Gemini,
+            "/v1beta/models/gemini-1.5-pro:streamGenerateContent?alt=sse",
+            b"{}",
+            "gemini-1.5-flash",
+        )
+        .unwrap();
+        assert_eq!(
+            path,
+            "/v1beta/models/gemini-1.5-flash:streamGenerateContent?alt=sse"
+        );
+
+        assert!(rewrite_model(Provider::Bedrock, "/model/x/invoke", b"{}", "y").is_err());
+    }
+}
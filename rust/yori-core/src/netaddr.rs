@@ -0,0 +1,186 @@
+//! Client addresses on a dual-stack network
+//!
+//! The same client can show up as `192.168.1.50`, `::ffff:192.168.1.50`
+//! (an IPv4 connection on a dual-stack listener), or with its IPv6 address
+//! written in upper case, with leading zeros, in brackets or with a zone
+//! (`fe80::1%igb1`). Addresses are kept as [`IpAddr`] where possible and
+//! written in one canonical form everywhere else (audit records, rate-limit
+//! keys, device profiles), so a policy or query written against one spelling
+//! matches every other:
+//!
+//! - IPv4-mapped IPv6 addresses become plain IPv4
+//! - IPv6 is lower case and compressed (`2001:db8::1`)
+//! - brackets, zones and ports are dropped
+//!
+//! The proxy listens on `[::]` by default, taking IPv4 connections on the
+//! same socket ([`bind_listener`]).
+
+use anyhow::{Context, Result};
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpListener, TcpSocket};
+
+/// Pending connections queued by the kernel on the proxy listener
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Parse a client address in any common spelling
+///
+/// Accepts bare addresses, `[v6]`, `v6%zone` and `addr:port`/`[v6]:port`;
+/// None if it's none of those.
+pub fn parse_client_ip(text: &str) -> Option<IpAddr> {
+    let text = text.trim();
+    let ip = match text.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => match text.parse::<SocketAddr>() {
+            Ok(addr) => addr.ip(),
+            Err(_) => {
+                let bare = text.trim_start_matches('[');
+                let bare = bare.split(['%', ']']).next().unwrap_or_default();
+                bare.parse().ok()?
+            }
+        },
+    };
+    Some(ip.to_canonical())
+}
+
+/// Canonical text form of a client address; text that isn't an address
+/// (e.g., a Unix socket peer) is returned unchanged
+pub fn normalize_client_ip(text: &str) -> String {
+    match parse_client_ip(text) {
+        Some(ip) => ip.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Whether two spellings name the same client address
+pub fn same_client_ip(a: &str, b: &str) -> bool {
+    match (parse_client_ip(a), parse_client_ip(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Bind the proxy's listening socket
+///
+/// An unspecified IPv6 address (`[::]`) accepts IPv4 connections too,
+/// whatever the system default (FreeBSD's `net.inet6.ip6.v6only` is on);
+/// if the host has IPv6 disabled it falls back to `0.0.0.0` on the same
+/// port. `transparent` binds for [`crate::origdst::DestinationLookup::Tproxy`].
+pub fn bind_listener(addr: SocketAddr, transparent: bool) -> Result<TcpListener> {
+    match bind(addr, transparent) {
+        Err(e) if is_unspecified_v6(addr) && ipv6_unavailable(&e) => {
+            let fallback = SocketAddr::from(([0, 0, 0, 0], addr.port()));
+            tracing::warn!("IPv6 unavailable, listening on {} only", fallback);
+            bind(fallback, transparent)
+        }
+        result => result,
+    }
+}
+
+fn bind(addr: SocketAddr, transparent: bool) -> Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    if is_unspecified_v6(addr) {
+        set_dual_stack(&socket).context("failed to accept IPv4 on the IPv6 listener")?;
+    }
+    if transparent {
+        crate::origdst::set_transparent(&socket, addr.is_ipv4())
+            .context("failed to make the listener transparent (needs root)")?;
+    }
+    socket
+        .bind(addr)
+        .with_context(|| format!("failed to bind {}", addr))?;
+    Ok(socket.listen(LISTEN_BACKLOG)?)
+}
+
+fn is_unspecified_v6(addr: SocketAddr) -> bool {
+    matches!(addr, SocketAddr::V6(v6) if v6.ip().is_unspecified())
+}
+
+/// Socket creation or bind failed because the host has no IPv6
+fn ipv6_unavailable(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::AddrNotAvailable | std::io::ErrorKind::Unsupported
+            ) || e.raw_os_error() == Some(libc::EAFNOSUPPORT)
+        })
+}
+
+#[cfg(unix)]
+fn set_dual_stack(socket: &TcpSocket) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    set_socket_option(socket.as_raw_fd(), libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, 0)
+}
+
+#[cfg(not(unix))]
+fn set_dual_stack(_: &TcpSocket) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Set an integer socket option
+#[cfg(unix)]
+pub(crate) fn set_socket_option(
+    fd: std::os::fd::RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> std::io::Result<()> {
+    // SAFETY: the option value is a c_int that outlives the call
+    let rc = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_client_ip() {
+        for (spelling, canonical) in [
+            ("192.168.1.50", "192.168.1.50"),
+            ("::ffff:192.168.1.50", "192.168.1.50"),
+            ("192.168.1.50:51234", "192.168.1.50"),
+            ("2001:DB8:0:0::0001", "2001:db8::1"),
+            ("[2001:db8::1]:443", "2001:db8::1"),
+            ("fe80::1%igb1", "fe80::1"),
+            ("[fe80::1%igb1]", "fe80::1"),
+            ("unknown", "unknown"),
+        ] {
+            assert_eq!(normalize_client_ip(spelling), canonical, "{}", spelling);
+        }
+        assert!(same_client_ip("::FFFF:10.0.0.5", "10.0.0.5"));
+        assert!(!same_client_ip("2001:db8::1", "2001:db8::2"));
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_listener() {
+        // Falls back to 0.0.0.0 on a test host without IPv6
+        let listener = bind_listener("[::]:0".parse().unwrap(), false).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let _client = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(
+            parse_client_ip(&peer.to_string()),
+            Some("127.0.0.1".parse().unwrap())
+        );
+    }
+}
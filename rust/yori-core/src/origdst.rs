@@ -0,0 +1,339 @@
+//! Original destination of redirected connections
+//!
+//! A transparently redirected connection arrives at YORI's listener, so its
+//! local address is the proxy's rather than the server the client dialed.
+//! The ClientHello names the host but not the port, is missing for clients
+//! connecting to a bare IP, and is whatever the client chose to send. The
+//! firewall knows the real destination:
+//!
+//! - `nat`: pf `rdr-to` (OPNsense, pfSense) or iptables `REDIRECT`/`DNAT`
+//!   rewrote the destination. It is looked up in pf's state table through
+//!   `/dev/pf` (`DIOCNATLOOK`) on FreeBSD, or with `SO_ORIGINAL_DST` on
+//!   Linux
+//! - `tproxy`: Linux `TPROXY`, or pf `divert-to`/ipfw `fwd` on FreeBSD,
+//!   deliver the connection unmodified, so its local address is the
+//!   destination. The listener must accept foreign addresses
+//!   ([`crate::netaddr::bind_listener`] with `transparent`)
+//! - `sni` (default): no lookup; the server name and port 443 are used
+//!
+//! Both lookups need root (or `CAP_NET_ADMIN`). Connections made to the
+//! listener directly (explicit proxy clients, health checks) have no
+//! original destination.
+
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// How the original destination of a redirected connection is found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DestinationLookup {
+    /// Not looked up; the ClientHello's server name is the destination
+    #[default]
+    Sni,
+
+    /// Asked of the firewall's NAT state (pf `rdr-to`, iptables `REDIRECT`)
+    Nat,
+
+    /// The connection's own local address (TPROXY, pf `divert-to`)
+    Tproxy,
+}
+
+impl DestinationLookup {
+    /// Lookup name as used in yori.toml
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DestinationLookup::Sni => "sni",
+            DestinationLookup::Nat => "nat",
+            DestinationLookup::Tproxy => "tproxy",
+        }
+    }
+}
+
+impl std::str::FromStr for DestinationLookup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sni" => Ok(DestinationLookup::Sni),
+            "nat" | "rdr" => Ok(DestinationLookup::Nat),
+            "tproxy" | "divert" => Ok(DestinationLookup::Tproxy),
+            other => bail!(
+                "unknown destination lookup {:?} (expected sni, nat or tproxy)",
+                other
+            ),
+        }
+    }
+}
+
+/// Where an accepted connection was originally headed
+///
+/// None with [`DestinationLookup::Sni`], and for connections addressed to
+/// `listen` itself rather than redirected to it.
+pub fn original_destination(
+    stream: &TcpStream,
+    lookup: DestinationLookup,
+    listen: SocketAddr,
+) -> Result<Option<SocketAddr>> {
+    let local = canonical(stream.local_addr()?);
+    let destination = match lookup {
+        DestinationLookup::Sni => return Ok(None),
+        DestinationLookup::Tproxy => local,
+        DestinationLookup::Nat => {
+            let client = canonical(stream.peer_addr()?);
+            match sys::nat_lookup(stream, client, local) {
+                Ok(destination) => canonical(destination),
+                // No NAT state: the client connected to us directly
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e).context("NAT lookup of the original destination failed"),
+            }
+        }
+    };
+    let direct = destination.port() == listen.port()
+        && (listen.ip().is_unspecified() || destination.ip() == canonical(listen).ip());
+    Ok((!direct).then_some(destination))
+}
+
+/// Let a not yet bound socket accept connections for any destination
+/// address (`IP_TRANSPARENT` on Linux, `IP_BINDANY` on FreeBSD), as
+/// [`DestinationLookup::Tproxy`] needs
+pub(crate) fn set_transparent(socket: &TcpSocket, ipv4: bool) -> io::Result<()> {
+    sys::set_transparent(socket, ipv4)
+}
+
+/// IPv4-mapped IPv6 addresses (dual-stack sockets) as plain IPv4
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::os::fd::AsRawFd;
+    use tokio::net::{TcpSocket, TcpStream};
+
+    /// `IP6T_SO_ORIGINAL_DST` from linux/netfilter_ipv6/ip6_tables.h
+    const IP6T_SO_ORIGINAL_DST: libc::c_int = 80;
+
+    pub fn nat_lookup(
+        stream: &TcpStream,
+        _client: SocketAddr,
+        local: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        let (level, name) = match local {
+            SocketAddr::V4(_) => (libc::SOL_IP, libc::SO_ORIGINAL_DST),
+            SocketAddr::V6(_) => (libc::SOL_IPV6, IP6T_SO_ORIGINAL_DST),
+        };
+        // SAFETY: an all-zero sockaddr_storage is valid, and `len` tells
+        // the kernel how much of it may be written
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                level,
+                name,
+                &mut storage as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // SAFETY: the family says the kernel wrote a sockaddr_in
+                let addr = unsafe { &*(&storage as *const _ as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                Ok(SocketAddr::from((ip, u16::from_be(addr.sin_port))))
+            }
+            libc::AF_INET6 => {
+                // SAFETY: the family says the kernel wrote a sockaddr_in6
+                let addr = unsafe { &*(&storage as *const _ as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+                Ok(SocketAddr::from((ip, u16::from_be(addr.sin6_port))))
+            }
+            family => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected address family {}", family),
+            )),
+        }
+    }
+
+    pub fn set_transparent(socket: &TcpSocket, ipv4: bool) -> io::Result<()> {
+        let (level, name) = if ipv4 {
+            (libc::SOL_IP, libc::IP_TRANSPARENT)
+        } else {
+            (libc::SOL_IPV6, libc::IPV6_TRANSPARENT)
+        };
+        crate::netaddr::set_socket_option(socket.as_raw_fd(), level, name, 1)
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::os::fd::AsRawFd;
+    use tokio::net::{TcpSocket, TcpStream};
+
+    /// pf's control device
+    const PF_DEVICE: &str = "/dev/pf";
+
+    /// `PF_OUT` from net/pfvar.h: the state was created as the packet left
+    /// toward the proxy
+    const PF_OUT: u8 = 2;
+
+    /// `IPV6_BINDANY` from netinet6/in6.h
+    const IPV6_BINDANY: libc::c_int = 64;
+
+    /// `struct pf_addr`: an IPv4 or IPv6 address in network order
+    #[repr(C, align(4))]
+    #[derive(Clone, Copy)]
+    struct PfAddr([u8; 16]);
+
+    /// `struct pfioc_natlook`
+    #[repr(C)]
+    struct PfiocNatlook {
+        saddr: PfAddr,
+        daddr: PfAddr,
+        rsaddr: PfAddr,
+        rdaddr: PfAddr,
+        sport: u16,
+        dport: u16,
+        rsport: u16,
+        rdport: u16,
+        af: libc::sa_family_t,
+        proto: u8,
+        direction: u8,
+    }
+
+    /// `_IOWR('D', 23, struct pfioc_natlook)`
+    const DIOCNATLOOK: libc::c_ulong = 0xc000_0000
+        | ((mem::size_of::<PfiocNatlook>() as libc::c_ulong & 0x1fff) << 16)
+        | ((b'D' as libc::c_ulong) << 8)
+        | 23;
+
+    fn pf_addr(ip: IpAddr) -> PfAddr {
+        let mut addr = [0u8; 16];
+        match ip {
+            IpAddr::V4(ip) => addr[..4].copy_from_slice(&ip.octets()),
+            IpAddr::V6(ip) => addr.copy_from_slice(&ip.octets()),
+        }
+        PfAddr(addr)
+    }
+
+    pub fn nat_lookup(
+        _stream: &TcpStream,
+        client: SocketAddr,
+        local: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        let pf = File::open(PF_DEVICE)?;
+        let ipv4 = client.is_ipv4();
+        let mut lookup = PfiocNatlook {
+            saddr: pf_addr(client.ip()),
+            daddr: pf_addr(local.ip()),
+            rsaddr: PfAddr([0; 16]),
+            rdaddr: PfAddr([0; 16]),
+            sport: client.port().to_be(),
+            dport: local.port().to_be(),
+            rsport: 0,
+            rdport: 0,
+            af: if ipv4 { libc::AF_INET } else { libc::AF_INET6 } as libc::sa_family_t,
+            proto: libc::IPPROTO_TCP as u8,
+            direction: PF_OUT,
+        };
+        // SAFETY: DIOCNATLOOK reads and writes exactly one pfioc_natlook
+        let rc = unsafe { libc::ioctl(pf.as_raw_fd(), DIOCNATLOOK, &mut lookup) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let PfAddr(addr) = lookup.rdaddr;
+        let ip = if ipv4 {
+            IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+        } else {
+            IpAddr::V6(Ipv6Addr::from(addr))
+        };
+        Ok(SocketAddr::new(ip, u16::from_be(lookup.rdport)))
+    }
+
+    pub fn set_transparent(socket: &TcpSocket, ipv4: bool) -> io::Result<()> {
+        let (level, name) = if ipv4 {
+            (libc::IPPROTO_IP, libc::IP_BINDANY)
+        } else {
+            (libc::IPPROTO_IPV6, IPV6_BINDANY)
+        };
+        crate::netaddr::set_socket_option(socket.as_raw_fd(), level, name, 1)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+mod sys {
+    use std::io;
+    use std::net::SocketAddr;
+    use tokio::net::{TcpSocket, TcpStream};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "original-destination lookup needs FreeBSD pf or Linux netfilter",
+        )
+    }
+
+    pub fn nat_lookup(_: &TcpStream, _: SocketAddr, _: SocketAddr) -> io::Result<SocketAddr> {
+        Err(unsupported())
+    }
+
+    pub fn set_transparent(_: &TcpSocket, _: bool) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lookup() {
+        for lookup in [
+            DestinationLookup::Sni,
+            DestinationLookup::Nat,
+            DestinationLookup::Tproxy,
+        ] {
+            assert_eq!(
+                lookup.as_str().parse::<DestinationLookup>().unwrap(),
+                lookup
+            );
+        }
+        assert_eq!(
+            "RDR".parse::<DestinationLookup>().unwrap(),
+            DestinationLookup::Nat
+        );
+        assert!("ipfw".parse::<DestinationLookup>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_direct_connection_has_no_destination() {
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(listen).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        for lookup in [DestinationLookup::Sni, DestinationLookup::Tproxy] {
+            assert_eq!(original_destination(&stream, lookup, listen).unwrap(), None);
+        }
+        // Redirected to a listener on another port: the local address is
+        // where the client was headed
+        let elsewhere = SocketAddr::from(([0, 0, 0, 0], listen.port().wrapping_add(1)));
+        assert_eq!(
+            original_destination(&stream, DestinationLookup::Tproxy, elsewhere).unwrap(),
+            Some(listen)
+        );
+    }
+}
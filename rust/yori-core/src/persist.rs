@@ -0,0 +1,42 @@
+//! Crash-safe writes of state files
+//!
+//! Quota counters, budget totals, cache snapshots, policy metadata and DNS
+//! override files are all rewritten in place on a router that can lose
+//! power at any moment. They go through [`write_atomic`] so readers only
+//! ever see the old contents or the new ones.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Replace `path` with `bytes`, creating its directory if needed
+///
+/// Written to a temporary file next to `path` and renamed over it, so a
+/// power cut never leaves a torn file.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let tmp = match path.extension() {
+        Some(extension) => path.with_extension(format!("{}.tmp", extension.to_string_lossy())),
+        None => path.with_extension("tmp"),
+    };
+    fs::write(&tmp, bytes).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replaces_file_and_leaves_no_temporary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("quota.json");
+
+        write_atomic(&path, b"{\"a\":1}").unwrap();
+        write_atomic(&path, b"{\"a\":2}").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"{\"a\":2}");
+        assert!(!dir.path().join("state").join("quota.json.tmp").exists());
+    }
+}
@@ -1,16 +1,59 @@
-//! Policy evaluation engine using SARK's embedded OPA
+//! Policy evaluation engine using an embedded Rego interpreter
 //!
-//! This module wraps sark-opa to provide policy evaluation for LLM requests.
-//! It's 4-10x faster than HTTP-based OPA calls.
+//! Raw `.rego` files are loaded straight into Regorus (the same interpreter
+//! sark-opa uses for Rego sources), so there is no `opa build` / WASM compile
+//! step. It's 4-10x faster than HTTP-based OPA calls.
 
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
-use std::path::PathBuf;
+use pyo3::types::{
+    PyBool, PyDate, PyDict, PyFloat, PyFrozenSet, PyList, PyLong, PyMapping, PySet, PyString,
+    PyTime, PyTuple,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::audit::{AuditConfig, AuditEvent, AuditLogger};
+use crate::clock;
+use crate::combining::{combine, CombinationStrategy, DefaultDecision, Vote};
+use crate::data::read_data_dir;
+use crate::decisions::{
+    input_hash, DecisionCache, DecisionCacheStats, DEFAULT_DECISION_CACHE_ENTRIES,
+    DEFAULT_DECISION_DENY_TTL_SECS, DEFAULT_DECISION_TTL_SECS,
+};
+use crate::explain::{read_sources, read_tenant_sources, Explanation, RuleIndex};
+use crate::policymeta::{load_metadata, save_metadata, PolicyMetadata};
+use crate::policytest::{self, PolicyTestReport};
+use crate::proxy::ProxyMode;
+use crate::shortcut::{DecisionTable, ShortcutPlan, ShortcutStats, DEFAULT_SHORTCUT_ENTRIES};
+use crate::signing::BundleVerifier;
+use crate::validate::{self, PolicyValidation, DECISION_RULES};
+use crate::watcher::PolicyWatcher;
 
 /// Policy evaluation engine for LLM governance
 ///
-/// This wraps SARK's embedded OPA engine for high-performance policy evaluation
-/// on resource-constrained home router hardware.
+/// Evaluates raw Rego policies in-process for high-performance policy
+/// evaluation on resource-constrained home router hardware.
+///
+/// Each package may define any of these rules; all are optional:
+///
+/// - `allow` - the request is denied unless this is true
+/// - `deny` - a set of violation messages (or a bool); non-empty denies
+/// - `reason` - human-readable explanation for the decision
+/// - `mode` - observe, advisory or enforce
+/// - `obligations` - extra data passed through to the caller
+/// - `priority` - orders packages under the priority-ordered strategy
+///
+/// Policies can also be disabled, re-ranked or given a different mode
+/// without editing them (see [`crate::policymeta`]).
+///
+/// By default any package's deny wins (see [`CombinationStrategy`] for the
+/// alternatives), and requests no package decides are allowed (see
+/// [`DefaultDecision`] to fail closed instead).
 ///
 /// # Example (Python)
 ///
@@ -24,17 +67,38 @@ use std::path::PathBuf;
 ///     "time": "20:00"
 /// })
 ///
-/// if result["allow"]:
+/// if result.allow:
 ///     # Forward request
 ///     pass
 /// else:
 ///     # Block or alert
-///     print(f"Policy violation: {result['reason']}")
+///     print(f"Policy violation: {result.reason}")
 /// ```
 #[pyclass]
 pub struct PolicyEngine {
-    // TODO: Replace with actual sark-opa engine once integrated
     policy_dir: PathBuf,
+
+    /// Loaded policies (shared with the watcher)
+    policies: Arc<RwLock<PolicySet>>,
+
+    /// Recent decisions by input hash, emptied on every reload
+    decisions: Arc<DecisionCache>,
+
+    /// Data added through `add_data`/`load_data_dir`, reloaded with the
+    /// policies
+    data: Arc<Mutex<DataSources>>,
+
+    /// Hot-reload watcher, while watching
+    watcher: Mutex<Option<PolicyWatcher>>,
+
+    /// Trusted signers, when only signed bundles may load
+    verifier: Arc<Mutex<Option<BundleVerifier>>>,
+
+    /// File policy metadata is saved to, once persisted
+    metadata_path: Mutex<Option<PathBuf>>,
+
+    /// Size of the shortcut decision table (0 disables shortcuts)
+    shortcut_entries: AtomicUsize,
 }
 
 #[pymethods]
@@ -44,14 +108,58 @@ impl PolicyEngine {
     /// # Arguments
     ///
     /// * `policy_dir` - Path to directory containing .rego policy files
+    /// * `cache_ttl_seconds` - How long identical inputs reuse a decision (default: 5, 0 disables)
+    /// * `cache_max_entries` - Maximum number of cached decisions (default: 1024)
+    /// * `strategy` - How decisions of several packages combine:
+    ///   "deny-overrides" (default), "allow-overrides", "first-match" or
+    ///   "priority-ordered"
+    /// * `default_decision` - "allow" (default) or "deny" for requests no
+    ///   policy decides; use "deny" to fail closed in enforce mode
+    /// * `cache_deny_ttl_seconds` - How long identical inputs reuse a denial
+    ///   (default: 1, 0 re-evaluates every denial)
     ///
     /// # Returns
     ///
     /// A new PolicyEngine instance
     #[new]
-    fn new(policy_dir: String) -> PyResult<Self> {
+    #[pyo3(signature = (
+        policy_dir,
+        cache_ttl_seconds=DEFAULT_DECISION_TTL_SECS,
+        cache_max_entries=DEFAULT_DECISION_CACHE_ENTRIES,
+        strategy="deny-overrides",
+        default_decision="allow",
+        cache_deny_ttl_seconds=DEFAULT_DECISION_DENY_TTL_SECS
+    ))]
+    fn new(
+        policy_dir: String,
+        cache_ttl_seconds: u64,
+        cache_max_entries: usize,
+        strategy: &str,
+        default_decision: &str,
+        cache_deny_ttl_seconds: u64,
+    ) -> PyResult<Self> {
+        let strategy = strategy
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        let default_decision = default_decision
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
         Ok(PolicyEngine {
             policy_dir: PathBuf::from(policy_dir),
+            policies: Arc::new(RwLock::new(PolicySet {
+                strategy,
+                default_decision,
+                ..PolicySet::default()
+            })),
+            decisions: Arc::new(
+                DecisionCache::new(Duration::from_secs(cache_ttl_seconds), cache_max_entries)
+                    .with_deny_ttl(Duration::from_secs(cache_deny_ttl_seconds)),
+            ),
+            data: Arc::new(Mutex::new(DataSources::default())),
+            watcher: Mutex::new(None),
+            verifier: Arc::new(Mutex::new(None)),
+            metadata_path: Mutex::new(None),
+            shortcut_entries: AtomicUsize::new(DEFAULT_SHORTCUT_ENTRIES),
         })
     }
 
@@ -60,36 +168,231 @@ impl PolicyEngine {
     /// # Arguments
     ///
     /// * `input_data` - Dictionary containing request context (user, endpoint, time, etc.)
+    /// * `explain` - Also return a `trace` of how the decision was reached
+    ///   (bypasses the decision cache; meant for debugging policies)
     ///
     /// # Returns
     ///
-    /// Dictionary with evaluation result:
+    /// `PolicyResult` with typed `allow`, `policy`, `reason`, `mode` and
+    /// `eval_duration_us` attributes; every field below is also readable as
+    /// `result["field"]` and returned by `to_dict()`:
     /// - `allow` (bool): Whether request is allowed
     /// - `policy` (str): Name of policy that made decision
     /// - `reason` (str): Human-readable explanation
     /// - `mode` (str): Policy mode (observe, advisory, enforce)
-    fn evaluate(&self, py: Python, _input_data: Bound<'_, PyDict>) -> PyResult<PyObject> {
-        // TODO: Implement actual OPA evaluation with sark-opa
-        // For now, return a stub that allows all requests (observe mode)
-
-        let result = PyDict::new_bound(py);
-        result.set_item("allow", true)?;
-        result.set_item("policy", "stub_default")?;
-        result.set_item("reason", "Stub policy engine - all requests allowed")?;
-        result.set_item("mode", "observe")?;
-
-        Ok(result.into())
+    /// - `rules` (list): File/line of the rules that produced the decision
+    /// - `matched_inputs` (list): Input fields those rules referenced
+    /// - `violations` (list): Messages from `deny` rules
+    /// - `obligations` (dict): Merged `obligations` of the evaluated policies
+    /// - `trace` (dict, with `explain=True`): `evaluated` (every decision rule
+    ///   with its value), `consulted_inputs` (input fields those rules read)
+    ///   `prints` (output of `print()` calls in the policies) and the
+    ///   `strategy` that combined them
+    #[pyo3(signature = (input_data, explain=false))]
+    fn evaluate(
+        &self,
+        py: Python,
+        input_data: Bound<'_, PyDict>,
+        explain: bool,
+    ) -> PyResult<PyPolicyResult> {
+        let input = py_to_json(input_data.as_any())?;
+        let started = Instant::now();
+        // Without the GIL, so Python worker threads evaluate in parallel
+        let result = py
+            .allow_threads(|| match explain {
+                true => self.explain_json(&input),
+                false => self.evaluate_json(&input),
+            })
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        Ok(PyPolicyResult::new(result, started.elapsed()))
     }
 
     /// Load or reload policy files from disk
     ///
+    /// Not needed while `watch()` is active.
+    ///
     /// # Returns
     ///
     /// Number of policies loaded
-    fn load_policies(&self) -> PyResult<usize> {
-        // TODO: Implement policy loading from .rego files
-        // This should scan policy_dir and load all .rego files into OPA
-        Ok(0)
+    fn load_policies(&self, py: Python) -> PyResult<usize> {
+        py.allow_threads(|| self.reload())
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Merge a data document into `data`, then reload
+    ///
+    /// The document is kept and merged again on every reload. Keys that
+    /// conflict with existing data raise an error and nothing changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Dictionary merged at the root of `data`
+    fn add_data(&self, py: Python, data: Bound<'_, PyDict>) -> PyResult<()> {
+        let document = py_to_json(data.as_any())?;
+        py.allow_threads(|| self.add_data_document(document))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Load JSON/YAML data files from a directory outside the policy
+    /// directory, then reload
+    ///
+    /// Files mount by path as in the policy directory (`devices.json` →
+    /// `data.devices`) and are re-read on every reload.
+    ///
+    /// # Returns
+    ///
+    /// Number of data files found
+    #[pyo3(name = "load_data_dir")]
+    fn py_load_data_dir(&self, py: Python, path: String) -> PyResult<usize> {
+        py.allow_threads(|| self.load_data_dir(path))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Reload automatically whenever a policy or data file changes
+    ///
+    /// If a reload fails the previous policies stay in force. Each reload
+    /// (or failure) is written to the audit log when `audit_db` is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_db` - Audit database to record reloads in (default: none)
+    /// * `encryption_key` - Vault key file if the audit database is encrypted
+    /// * `debounce_ms` - Quiet period before a burst of changes reloads (default: 250)
+    /// * `hash_chain` - Hash-chain the reload events (match `audit.hash_chain`)
+    #[pyo3(name = "watch", signature = (audit_db=None, encryption_key=None, debounce_ms=250, hash_chain=false))]
+    fn py_watch(
+        &self,
+        audit_db: Option<String>,
+        encryption_key: Option<String>,
+        debounce_ms: u64,
+        hash_chain: bool,
+    ) -> PyResult<()> {
+        let logger = audit_db
+            .map(|database| {
+                AuditLogger::open(AuditConfig {
+                    database: PathBuf::from(database),
+                    encryption_key: encryption_key.map(PathBuf::from),
+                    hash_chain,
+                    ..AuditConfig::default()
+                })
+            })
+            .transpose()
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+
+        let policy_dir = self.policy_dir.clone();
+        self.watch(Duration::from_millis(debounce_ms), move |outcome| {
+            if let Some(logger) = &logger {
+                let event = AuditEvent::policy_reload(
+                    &policy_dir,
+                    outcome.policies,
+                    outcome.error.as_deref(),
+                );
+                if let Err(e) = logger.log(&event) {
+                    tracing::warn!("Failed to audit policy reload: {:#}", e);
+                }
+            }
+        })
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// How decisions of several packages combine ("deny-overrides",
+    /// "allow-overrides", "first-match" or "priority-ordered")
+    ///
+    /// Setting it drops cached decisions.
+    #[getter(strategy)]
+    fn py_get_strategy(&self) -> &'static str {
+        self.strategy().as_str()
+    }
+
+    #[setter(strategy)]
+    fn py_set_strategy(&self, strategy: &str) -> PyResult<()> {
+        let strategy = strategy
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        self.set_strategy(strategy);
+        Ok(())
+    }
+
+    /// Decision for requests no policy decides: "allow" or "deny"
+    ///
+    /// Setting it drops cached decisions.
+    #[getter(default_decision)]
+    fn py_get_default_decision(&self) -> &'static str {
+        self.default_decision().as_str()
+    }
+
+    #[setter(default_decision)]
+    fn py_set_default_decision(&self, default_decision: &str) -> PyResult<()> {
+        let default_decision = default_decision
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        self.set_default_decision(default_decision);
+        Ok(())
+    }
+
+    /// Decision cache statistics
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with cache stats:
+    /// - `entries` (int): Decisions currently cached
+    /// - `hits` (int): Evaluations answered from the cache
+    /// - `misses` (int): Evaluations that ran the policies
+    /// - `hit_rate` (float): Hits as a fraction of all lookups
+    /// - `ttl_seconds` (float): How long a decision stays cached
+    /// - `deny_ttl_seconds` (float): How long a denial stays cached
+    /// - `max_entries` (int): Maximum number of cached decisions
+    /// - `shortcut` (dict): Decisions reused across inputs that agree on
+    ///   the fields the policies read: `paths` (or `unavailable`, why
+    ///   not), `entries`, `hits` and `misses` (see [`crate::shortcut`])
+    #[pyo3(name = "stats")]
+    fn py_stats(&self, py: Python) -> PyResult<PyObject> {
+        let stats = self.cache_stats();
+        let dict = PyDict::new_bound(py);
+        dict.set_item("entries", stats.entries)?;
+        dict.set_item("hits", stats.hits)?;
+        dict.set_item("misses", stats.misses)?;
+        dict.set_item("hit_rate", stats.hit_rate())?;
+        dict.set_item("ttl_seconds", self.decisions.ttl().as_secs_f64())?;
+        dict.set_item("deny_ttl_seconds", self.decisions.deny_ttl().as_secs_f64())?;
+        dict.set_item("max_entries", self.decisions.max_entries())?;
+        let shortcut = serde_json::to_value(self.shortcut_stats())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        dict.set_item("shortcut", json_to_py(py, &shortcut)?)?;
+        Ok(dict.into())
+    }
+
+    /// Stop watching the policy directory
+    ///
+    /// # Returns
+    ///
+    /// True if a watcher was running
+    fn unwatch(&self) -> bool {
+        self.watcher.lock().unwrap().take().is_some()
+    }
+
+    /// Explain a decision: where its rules are defined and what input they used
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - Rules that produced the decision ("yori.bedtime.deny")
+    /// * `input_data` - The input the decision was made on
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `rules` (list of `{package, rule, file, line}`) and
+    /// `matched_inputs` (list of `{path, value}`)
+    fn explain(
+        &self,
+        py: Python,
+        rules: Vec<String>,
+        input_data: Bound<'_, PyDict>,
+    ) -> PyResult<PyObject> {
+        let input = py_to_json(input_data.as_any())?;
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        let explanation =
+            py.allow_threads(|| self.policies.read().unwrap().index.explain(&rules, &input));
+        explanation_to_py(py, &explanation)
     }
 
     /// Get list of loaded policy names
@@ -98,8 +401,10 @@ impl PolicyEngine {
     ///
     /// List of policy names (without .rego extension)
     fn list_policies(&self, py: Python) -> PyResult<PyObject> {
-        // TODO: Return actual loaded policies
         let policies = PyList::empty_bound(py);
+        for name in self.policy_names() {
+            policies.append(name)?;
+        }
         Ok(policies.into())
     }
 
@@ -112,15 +417,1251 @@ impl PolicyEngine {
     ///
     /// # Returns
     ///
-    /// Evaluation result without side effects
-    fn test_policy(&self, py: Python, policy_name: String, _input_data: Bound<'_, PyDict>) -> PyResult<PyObject> {
-        // TODO: Implement policy testing
-        let result = PyDict::new_bound(py);
-        result.set_item("allow", true)?;
-        result.set_item("policy", policy_name)?;
-        result.set_item("reason", "Test mode")?;
+    /// `PolicyResult` of that policy alone (evaluated even while disabled),
+    /// without side effects (the decision cache is neither read nor
+    /// filled), with the evaluation `trace` in its metadata
+    fn test_policy(
+        &self,
+        py: Python,
+        policy_name: String,
+        input_data: Bound<'_, PyDict>,
+    ) -> PyResult<PyPolicyResult> {
+        if !self.policy_names().contains(&policy_name) {
+            return Err(PyValueError::new_err(format!(
+                "no policy named {:?}",
+                policy_name
+            )));
+        }
+        let input = py_to_json(input_data.as_any())?;
+        let started = Instant::now();
+        let mut result = py
+            .allow_threads(|| self.explain_policy_json(&policy_name, &input))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        result["tested_policy"] = policy_name.into();
+        Ok(PyPolicyResult::new(result, started.elapsed()))
+    }
+
+    /// Run the `*_test.rego` and `*_test.yaml` files in the policy directory
+    /// against the loaded policies, like `opa test`
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `passed`, `failed`, `errors`, `ok` and `results`
+    /// (list of `{name, file, line, outcome, message, duration_us}`, where
+    /// `outcome` is "pass", "fail" or "error")
+    #[pyo3(name = "run_tests")]
+    fn py_run_tests(&self, py: Python) -> PyResult<PyObject> {
+        let report = py
+            .allow_threads(|| self.run_tests())
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let mut value =
+            serde_json::to_value(&report).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        value["ok"] = report.ok().into();
+        json_to_py(py, &value)
+    }
+
+    /// Check a policy source before saving it, without loading it
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Policy name ("bedtime" or "family/bedtime.rego"); an
+    ///   existing policy of that name is replaced for the check
+    /// * `source` - Rego source to validate
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `valid` (bool), `package` and `diagnostics` (list of
+    /// `{severity, line, column, message}`, severity "error" or "warning")
+    #[pyo3(name = "validate_policy")]
+    fn py_validate_policy(&self, py: Python, name: String, source: String) -> PyResult<PyObject> {
+        let validation = py
+            .allow_threads(|| self.validate_policy(&name, &source))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let value = serde_json::to_value(&validation)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    /// Refuse policy bundles not signed by one of `trusted_keys`
+    ///
+    /// # Arguments
+    ///
+    /// * `trusted_keys` - Hex-encoded ed25519 public keys
+    ///
+    /// # Returns
+    ///
+    /// Number of policies loaded. Raises RuntimeError if the current
+    /// policy directory isn't signed; later reloads keep refusing it.
+    #[pyo3(name = "require_signatures")]
+    fn py_require_signatures(&self, py: Python, trusted_keys: Vec<String>) -> PyResult<usize> {
+        let verifier = BundleVerifier::new(&trusted_keys)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        py.allow_threads(|| self.require_signatures(verifier))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Metadata of every loaded policy
+    ///
+    /// # Returns
+    ///
+    /// Dictionary of policy name to `{enabled, priority, mode}`; `priority`
+    /// and `mode` are None where the policy's own rules decide
+    #[pyo3(name = "policy_metadata")]
+    fn py_policy_metadata(&self, py: Python) -> PyResult<PyObject> {
+        let value = serde_json::to_value(self.policy_metadata())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    /// Let a disabled policy take part in decisions again
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Policy name (as in `list_policies()`)
+    #[pyo3(name = "enable_policy")]
+    fn py_enable_policy(&self, name: &str) -> PyResult<()> {
+        self.enable_policy(name)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Keep a policy out of decisions without deleting it
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Policy name (as in `list_policies()`)
+    #[pyo3(name = "disable_policy")]
+    fn py_disable_policy(&self, name: &str) -> PyResult<()> {
+        self.disable_policy(name)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Rank a policy under the priority-ordered strategy
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Policy name (as in `list_policies()`)
+    /// * `priority` - Higher decides first; None goes back to the policy's
+    ///   own `priority` rule
+    #[pyo3(name = "set_priority", signature = (name, priority=None))]
+    fn py_set_priority(&self, name: &str, priority: Option<f64>) -> PyResult<()> {
+        self.set_priority(name, priority)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Override the mode a policy's decisions carry
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Policy name (as in `list_policies()`)
+    /// * `mode` - "observe", "advisory" or "enforce"; None goes back to the
+    ///   policy's own `mode` rule
+    #[pyo3(name = "set_policy_mode", signature = (name, mode=None))]
+    fn py_set_policy_mode(&self, name: &str, mode: Option<&str>) -> PyResult<()> {
+        let mode = mode
+            .map(str::parse::<ProxyMode>)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        self.set_policy_mode(name, mode)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+}
+
+impl PolicyEngine {
+    /// Create an engine for a policy directory and load it
+    pub fn open(policy_dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        PolicyEngine::open_with_cache(policy_dir, DecisionCache::default())
+    }
+
+    /// Like [`PolicyEngine::open`], reusing decisions through `decisions`
+    /// (see [`DecisionCache::new`] and [`DecisionCache::with_deny_ttl`])
+    pub fn open_with_cache(
+        policy_dir: impl Into<PathBuf>,
+        decisions: DecisionCache,
+    ) -> anyhow::Result<Self> {
+        let engine = PolicyEngine {
+            policy_dir: policy_dir.into(),
+            policies: Arc::new(RwLock::new(PolicySet::default())),
+            decisions: Arc::new(decisions),
+            data: Arc::new(Mutex::new(DataSources::default())),
+            watcher: Mutex::new(None),
+            verifier: Arc::new(Mutex::new(None)),
+            metadata_path: Mutex::new(None),
+            shortcut_entries: AtomicUsize::new(DEFAULT_SHORTCUT_ENTRIES),
+        };
+        engine.reload()?;
+        Ok(engine)
+    }
+
+    /// Like [`PolicyEngine::open_with_cache`], loading the policy directory
+    /// only if it is signed by one of `verifier`'s keys (see
+    /// [`crate::signing`])
+    pub fn open_verified(
+        policy_dir: impl Into<PathBuf>,
+        decisions: DecisionCache,
+        verifier: BundleVerifier,
+    ) -> anyhow::Result<Self> {
+        let engine = PolicyEngine {
+            policy_dir: policy_dir.into(),
+            policies: Arc::new(RwLock::new(PolicySet::default())),
+            decisions: Arc::new(decisions),
+            data: Arc::new(Mutex::new(DataSources::default())),
+            watcher: Mutex::new(None),
+            verifier: Arc::new(Mutex::new(Some(verifier))),
+            metadata_path: Mutex::new(None),
+            shortcut_entries: AtomicUsize::new(DEFAULT_SHORTCUT_ENTRIES),
+        };
+        engine.reload()?;
+        Ok(engine)
+    }
+
+    /// Directory the policies are loaded from
+    pub fn policy_dir(&self) -> &Path {
+        &self.policy_dir
+    }
+
+    /// Only load signed policy bundles from now on, reloading to check the
+    /// current one
+    ///
+    /// If the current policies aren't signed the error is returned and
+    /// they stay loaded; later reloads refuse them.
+    pub fn require_signatures(&self, verifier: BundleVerifier) -> anyhow::Result<usize> {
+        *self.verifier.lock().unwrap() = Some(verifier);
+        self.reload()
+    }
+
+    /// Load or reload policy files from disk, returning how many were loaded
+    pub fn reload(&self) -> anyhow::Result<usize> {
+        load_into(
+            &self.policy_dir,
+            &self.data,
+            &self.verifier,
+            &self.policies,
+            &self.decisions,
+        )
+    }
+
+    /// Merge a data document (a JSON object) into `data` and reload
+    ///
+    /// If the reload fails the document is dropped again and the previous
+    /// policies and data stay in force.
+    pub fn add_data_document(&self, document: serde_json::Value) -> anyhow::Result<()> {
+        if !document.is_object() {
+            anyhow::bail!("data documents must be objects");
+        }
+        self.data.lock().unwrap().documents.push(document);
+        self.reload().map(|_| ()).inspect_err(|_| {
+            self.data.lock().unwrap().documents.pop();
+        })
+    }
+
+    /// Load data files from `dir` on this and every later reload, returning
+    /// how many were found
+    pub fn load_data_dir(&self, dir: impl Into<PathBuf>) -> anyhow::Result<usize> {
+        let dir = dir.into();
+        let files = read_data_dir(&dir)?.len();
+        self.data.lock().unwrap().dirs.push(dir);
+        self.reload().inspect_err(|_| {
+            self.data.lock().unwrap().dirs.pop();
+        })?;
+        Ok(files)
+    }
+
+    /// Reload automatically when policy files change (replaces any
+    /// previous watcher)
+    ///
+    /// `on_reload` is called after every attempt; on failure the previous
+    /// policy set keeps serving.
+    pub fn watch(
+        &self,
+        debounce: Duration,
+        on_reload: impl Fn(&ReloadOutcome) + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let policy_dir = self.policy_dir.clone();
+        let data = Arc::clone(&self.data);
+        let verifier = Arc::clone(&self.verifier);
+        let policies = Arc::clone(&self.policies);
+        let decisions = Arc::clone(&self.decisions);
+        let watcher = PolicyWatcher::new(&self.policy_dir.clone(), debounce, move |changed| {
+            let outcome = match load_into(&policy_dir, &data, &verifier, &policies, &decisions) {
+                Ok(policies) => {
+                    tracing::info!(
+                        "Reloaded {} policies after changes to {:?}",
+                        policies,
+                        changed
+                    );
+                    ReloadOutcome {
+                        changed,
+                        policies,
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Policy reload failed, keeping previous policies: {:#}", e);
+                    ReloadOutcome {
+                        changed,
+                        policies: policies.read().unwrap().index.files().len(),
+                        error: Some(format!("{:#}", e)),
+                    }
+                }
+            };
+            on_reload(&outcome);
+        })?;
+        *self.watcher.lock().unwrap() = Some(watcher);
+        Ok(())
+    }
+
+    /// Names of the loaded policies (without .rego extension)
+    pub fn policy_names(&self) -> Vec<String> {
+        self.policies
+            .read()
+            .unwrap()
+            .index
+            .files()
+            .iter()
+            .map(|file| file.trim_end_matches(".rego").to_string())
+            .collect()
+    }
+
+    /// Evaluate a JSON input document, returning the same fields as `evaluate`
+    ///
+    /// An input identical to one evaluated within the cache TTL reuses
+    /// that decision, as does one that agrees on every input field the
+    /// policies read (see [`crate::shortcut`]).
+    pub fn evaluate_json(&self, input: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let key = input_hash(input);
+        // Held until the decision is cached, so a concurrent reload can't
+        // leave a decision from the old policies behind
+        let guard = self.policies.read().unwrap();
+        if let Some(decision) = self.decisions.get(&key, Instant::now()) {
+            return Ok(decision);
+        }
+        let policies = guard.for_input(input);
+        let shortcut_entries = self.shortcut_entries.load(Ordering::Relaxed);
+        let shortcut = match &policies.shortcut {
+            Ok(plan) if shortcut_entries > 0 => Some(plan.key(input)),
+            _ => None,
+        };
+        if let Some(decision) = shortcut
+            .as_ref()
+            .and_then(|shortcut| policies.table.get(shortcut))
+        {
+            self.decisions.insert(key, decision.clone(), Instant::now());
+            return Ok(decision);
+        }
+        let decision = policies.evaluate(input)?;
+        if let Some(shortcut) = shortcut {
+            policies
+                .table
+                .insert(shortcut, decision.clone(), shortcut_entries);
+        }
+        self.decisions.insert(key, decision.clone(), Instant::now());
+        Ok(decision)
+    }
+
+    /// Evaluate like `evaluate_json`, adding a `trace` of every decision
+    /// rule's value, the input fields they read and the policies' `print()`
+    /// output
+    ///
+    /// Always evaluates afresh: the decision cache is neither read nor
+    /// filled.
+    pub fn explain_json(&self, input: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        self.policies
+            .read()
+            .unwrap()
+            .for_input(input)
+            .explain(input, None)
+    }
+
+    /// Explain like `explain_json` with only the policy `name` taking part
+    /// (evaluated even while disabled)
+    pub fn explain_policy_json(
+        &self,
+        name: &str,
+        input: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.policies
+            .read()
+            .unwrap()
+            .for_input(input)
+            .explain(input, Some(name))
+    }
+
+    /// Run the policy directory's test files against the loaded policies
+    /// (see [`crate::policytest`])
+    ///
+    /// YAML cases bypass the decision cache.
+    pub fn run_tests(&self) -> anyhow::Result<PolicyTestReport> {
+        let policies = self.policies.read().unwrap();
+        policytest::run(&self.policy_dir, &policies.engine, |input| {
+            policies.evaluate(input)
+        })
+    }
+
+    /// Compile `source` as the policy `name` together with the other
+    /// policies on disk, without loading it (see [`crate::validate`])
+    pub fn validate_policy(&self, name: &str, source: &str) -> anyhow::Result<PolicyValidation> {
+        let file = match name.ends_with(".rego") {
+            true => name.to_string(),
+            false => format!("{}.rego", name),
+        };
+        let others = read_sources(&self.policy_dir)?;
+        Ok(validate::validate(&file, source, &others))
+    }
+
+    /// How decisions of several packages combine
+    pub fn strategy(&self) -> CombinationStrategy {
+        self.policies.read().unwrap().strategy
+    }
+
+    /// Change how decisions combine, dropping decisions made the old way
+    pub fn set_strategy(&self, strategy: CombinationStrategy) {
+        let mut policies = self.policies.write().unwrap();
+        policies.strategy = strategy;
+        policies.settings_changed();
+        self.decisions.clear();
+    }
+
+    /// Decision for requests no policy decides
+    pub fn default_decision(&self) -> DefaultDecision {
+        self.policies.read().unwrap().default_decision
+    }
+
+    /// Change the decision for requests no policy decides, dropping cached
+    /// decisions
+    pub fn set_default_decision(&self, default_decision: DefaultDecision) {
+        let mut policies = self.policies.write().unwrap();
+        policies.default_decision = default_decision;
+        policies.settings_changed();
+        self.decisions.clear();
+    }
+
+    /// Decision cache entry count and hit/miss counters
+    pub fn cache_stats(&self) -> DecisionCacheStats {
+        self.decisions.stats()
+    }
+
+    /// Size the shortcut decision table (0 turns shortcuts off), dropping
+    /// the decisions in it
+    pub fn set_shortcut_entries(&self, max_entries: usize) {
+        let policies = self.policies.read().unwrap();
+        self.shortcut_entries.store(max_entries, Ordering::Relaxed);
+        policies.clear_tables();
+    }
+
+    /// Input fields decisions are keyed by, and shortcut table counters
+    pub fn shortcut_stats(&self) -> ShortcutStats {
+        let policies = self.policies.read().unwrap();
+        let (entries, hits, misses) = policies.table.stats();
+        let (paths, unavailable) = match (
+            &policies.shortcut,
+            self.shortcut_entries.load(Ordering::Relaxed),
+        ) {
+            (_, 0) => (None, Some("shortcuts are turned off".to_string())),
+            (Ok(plan), _) => (Some(plan.paths().to_vec()), None),
+            (Err(reason), _) => (None, Some(reason.clone())),
+        };
+        ShortcutStats {
+            paths,
+            unavailable,
+            entries,
+            hits,
+            misses,
+        }
+    }
+
+    /// Metadata of every loaded policy, defaults where none is set
+    pub fn policy_metadata(&self) -> BTreeMap<String, PolicyMetadata> {
+        let policies = self.policies.read().unwrap();
+        policies
+            .index
+            .files()
+            .iter()
+            .map(|file| {
+                let name = file.trim_end_matches(".rego");
+                (
+                    name.to_string(),
+                    policies.metadata.get(name).cloned().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// Let the policy `name` take part in decisions again
+    pub fn enable_policy(&self, name: &str) -> anyhow::Result<()> {
+        self.update_metadata(name, |metadata| metadata.enabled = true)
+    }
+
+    /// Keep the policy `name` out of decisions until it is enabled again
+    pub fn disable_policy(&self, name: &str) -> anyhow::Result<()> {
+        self.update_metadata(name, |metadata| metadata.enabled = false)
+    }
+
+    /// Override (or with None, stop overriding) the policy's `priority`
+    pub fn set_priority(&self, name: &str, priority: Option<f64>) -> anyhow::Result<()> {
+        if priority.is_some_and(|p| !p.is_finite()) {
+            anyhow::bail!("priority must be a finite number");
+        }
+        self.update_metadata(name, |metadata| metadata.priority = priority)
+    }
+
+    /// Override (or with None, stop overriding) the policy's `mode`
+    pub fn set_policy_mode(&self, name: &str, mode: Option<ProxyMode>) -> anyhow::Result<()> {
+        self.update_metadata(name, |metadata| {
+            metadata.mode = mode.map(|m| m.as_str().to_string())
+        })
+    }
+
+    /// Restore policy metadata from `path` and save every later change
+    /// there, returning how many policies have metadata set
+    pub fn persist_metadata(&self, path: impl Into<PathBuf>) -> anyhow::Result<usize> {
+        let path = path.into();
+        let metadata = load_metadata(&path)?;
+        for (name, entry) in &metadata {
+            if let Some(mode) = &entry.mode {
+                mode.parse::<ProxyMode>()
+                    .map_err(|e| e.context(format!("policy {:?} in {}", name, path.display())))?;
+            }
+        }
+        let count = metadata.len();
+        let mut policies = self.policies.write().unwrap();
+        policies.metadata = metadata;
+        policies.settings_changed();
+        *self.metadata_path.lock().unwrap() = Some(path);
+        self.decisions.clear();
+        Ok(count)
+    }
+
+    /// Change one loaded policy's metadata, saving it if persisted and
+    /// dropping decisions made with the old settings
+    fn update_metadata(
+        &self,
+        name: &str,
+        update: impl FnOnce(&mut PolicyMetadata),
+    ) -> anyhow::Result<()> {
+        let name = name.trim_end_matches(".rego");
+        if !self.policy_names().iter().any(|policy| policy == name) {
+            anyhow::bail!("no policy named {:?}", name);
+        }
+        let mut policies = self.policies.write().unwrap();
+        let mut metadata = policies.metadata.clone();
+        update(metadata.entry(name.to_string()).or_default());
+        if let Some(path) = &*self.metadata_path.lock().unwrap() {
+            save_metadata(path, &metadata)?;
+        }
+        policies.metadata = metadata;
+        policies.settings_changed();
+        self.decisions.clear();
+        Ok(())
+    }
+}
+
+/// Result of one automatic reload attempt
+#[derive(Debug, Clone)]
+pub struct ReloadOutcome {
+    /// Policy files whose changes triggered the reload
+    pub changed: Vec<PathBuf>,
+
+    /// Policies now in force (the previous count if the reload failed)
+    pub policies: usize,
+
+    /// Why the reload failed, if it did
+    pub error: Option<String>,
+}
+
+/// Data supplied through the API rather than the policy directory
+#[derive(Debug, Clone, Default)]
+struct DataSources {
+    /// Documents from `add_data`, merged in order
+    documents: Vec<serde_json::Value>,
+
+    /// Extra directories from `load_data_dir`
+    dirs: Vec<PathBuf>,
+}
+
+/// Source of [`PolicySet::generation`]; 0 is left for the empty default set
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// This thread's working copy of the last policy set it evaluated
+    ///
+    /// Evaluation only needs read access to the shared set, so proxy
+    /// workers run in parallel; each keeps its own clone of the prepared
+    /// engine instead of cloning it per request, and re-clones only after a
+    /// reload (when the generation changes).
+    static WORKER_ENGINE: RefCell<Option<(u64, regorus::Engine)>> = const { RefCell::new(None) };
+}
+
+/// What one package's decision rules said about a request
+struct PackageOutcome {
+    vote: Vote,
+
+    /// The package's `priority`, read only for priority-ordered combining
+    priority: f64,
+
+    /// Rules that made the package deny
+    fired: Vec<String>,
+    violations: Vec<String>,
+    reason: Option<String>,
+    mode: Option<String>,
+    obligations: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Compiled policies plus the rule index used to explain their decisions
+struct PolicySet {
+    engine: regorus::Engine,
+    index: RuleIndex,
+
+    /// Packages with at least one decision rule, in file order
+    packages: Vec<String>,
+
+    /// Policy (file name without `.rego`) that declared each package
+    package_policies: Vec<String>,
+
+    /// Operator metadata by policy name (kept across reloads)
+    metadata: BTreeMap<String, PolicyMetadata>,
+
+    /// Input fields the decisions depend on, or why that isn't known
+    shortcut: Result<ShortcutPlan, String>,
+
+    /// Decisions by shortcut key
+    table: DecisionTable,
+
+    /// Unique per loaded set, so worker engines notice reloads
+    generation: u64,
+
+    /// How package decisions combine (kept across reloads)
+    strategy: CombinationStrategy,
+
+    /// Decision when no package decides (kept across reloads)
+    default_decision: DefaultDecision,
+
+    /// Shared plus tenant policies, by tenant id (see [`crate::tenant`])
+    tenants: BTreeMap<String, PolicySet>,
+}
+
+impl Default for PolicySet {
+    fn default() -> Self {
+        PolicySet {
+            engine: regorus::Engine::default(),
+            index: RuleIndex::default(),
+            packages: Vec::new(),
+            package_policies: Vec::new(),
+            metadata: BTreeMap::new(),
+            shortcut: Err("no policies loaded".to_string()),
+            table: DecisionTable::default(),
+            generation: 0,
+            strategy: CombinationStrategy::default(),
+            default_decision: DefaultDecision::default(),
+            tenants: BTreeMap::new(),
+        }
+    }
+}
+
+impl PolicySet {
+    /// Parse and prepare every `.rego` file under `policy_dir`, with the
+    /// data files next to them and the extra `sources`
+    ///
+    /// Each tenant's policies are compiled with the shared ones into a set
+    /// of their own, so they never decide another tenant's requests.
+    fn load(policy_dir: &Path, sources: &DataSources) -> anyhow::Result<Self> {
+        let files = read_sources(policy_dir)?;
+        let mut set = PolicySet::compile(policy_dir, &files, sources)?;
+        for (tenant, own) in read_tenant_sources(policy_dir)? {
+            let files = [files.as_slice(), own.as_slice()].concat();
+            let compiled = PolicySet::compile(policy_dir, &files, sources)
+                .map_err(|e| e.context(format!("policies of tenant {:?}", tenant)))?;
+            set.tenants.insert(tenant, compiled);
+        }
+        Ok(set)
+    }
+
+    /// Compile `files` with the data files under `policy_dir` and the extra
+    /// `sources`
+    ///
+    /// Files are read as Rego v1 first and fall back to the older v0
+    /// syntax, so policies written for either OPA generation load as-is.
+    fn compile(
+        policy_dir: &Path,
+        files: &[(String, String)],
+        sources: &DataSources,
+    ) -> anyhow::Result<Self> {
+        let mut engine = regorus::Engine::new();
+        clock::register(&mut engine)?;
+        let mut index = RuleIndex::default();
+        let mut packages = Vec::new();
+        let mut package_policies = Vec::new();
+
+        for (file, source) in files {
+            engine.set_rego_v0(false);
+            let package = match engine.add_policy(file.clone(), source.clone()) {
+                Ok(package) => package,
+                Err(v1_error) => {
+                    engine.set_rego_v0(true);
+                    engine
+                        .add_policy(file.clone(), source.clone())
+                        .map_err(|_| anyhow::anyhow!("failed to compile {}: {}", file, v1_error))?
+                }
+            };
+            index.add_source(file, source);
+
+            let package = package.trim_start_matches("data.").to_string();
+            if !packages.contains(&package)
+                && DECISION_RULES
+                    .iter()
+                    .any(|rule| !index.locate(&package, rule).is_empty())
+            {
+                packages.push(package);
+                package_policies.push(file.trim_end_matches(".rego").to_string());
+            }
+        }
+
+        let mut documents = read_data_dir(policy_dir)?;
+        for dir in &sources.dirs {
+            documents.extend(
+                read_data_dir(dir)?
+                    .into_iter()
+                    .map(|(file, document)| (dir.join(file).display().to_string(), document)),
+            );
+        }
+        documents.extend(
+            sources
+                .documents
+                .iter()
+                .map(|document| ("add_data()".to_string(), document.clone())),
+        );
+        for (file, document) in documents {
+            engine
+                .add_data(regorus::Value::from(document))
+                .map_err(|e| anyhow::anyhow!("failed to load data from {}: {}", file, e))?;
+        }
+
+        // Prepare now so semantic errors fail the load, not the first request
+        engine
+            .eval_query("true".to_string(), false)
+            .map_err(|e| anyhow::anyhow!("failed to prepare policies: {}", e))?;
+
+        Ok(PolicySet {
+            engine,
+            index,
+            packages,
+            package_policies,
+            metadata: BTreeMap::new(),
+            shortcut: ShortcutPlan::analyse(files),
+            table: DecisionTable::default(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            strategy: CombinationStrategy::default(),
+            default_decision: DefaultDecision::default(),
+            tenants: BTreeMap::new(),
+        })
+    }
+
+    /// The set deciding `input`: its tenant's, or the shared one
+    fn for_input(&self, input: &serde_json::Value) -> &PolicySet {
+        input["tenant"]
+            .as_str()
+            .and_then(|tenant| self.tenants.get(tenant))
+            .unwrap_or(self)
+    }
+
+    /// Hand strategy, default decision and metadata on to the tenant sets
+    /// and drop every shortcut decision made with the old ones
+    fn settings_changed(&mut self) {
+        for tenant in self.tenants.values_mut() {
+            tenant.strategy = self.strategy;
+            tenant.default_decision = self.default_decision;
+            tenant.metadata = self.metadata.clone();
+        }
+        self.clear_tables();
+    }
+
+    /// Drop the shortcut decisions of this and every tenant set
+    fn clear_tables(&self) {
+        self.table.clear();
+        for tenant in self.tenants.values() {
+            tenant.table.clear();
+        }
+    }
+
+    /// Operator metadata of the policy that declared package `i`
+    fn package_metadata(&self, i: usize) -> Option<&PolicyMetadata> {
+        self.metadata.get(&self.package_policies[i])
+    }
+
+    /// Evaluate every package's decision rules against `input`, combining
+    /// their votes by the set's strategy
+    fn evaluate(&self, input: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        WORKER_ENGINE.with(|worker| {
+            let mut worker = worker.borrow_mut();
+            if !matches!(&*worker, Some((generation, _)) if *generation == self.generation) {
+                // Clones share the prepared policies; only the input differs
+                *worker = Some((self.generation, self.engine.clone()));
+            }
+            let (_, engine) = worker.as_mut().expect("worker engine was just set");
+            self.decide(engine, input, None, None)
+        })
+    }
+
+    /// Evaluate on a private engine, recording a trace of the evaluation;
+    /// with `only`, that policy's packages alone take part
+    fn explain(
+        &self,
+        input: &serde_json::Value,
+        only: Option<&str>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let mut engine = self.engine.clone();
+        engine.set_gather_prints(true);
+        let mut evaluated = Vec::new();
+        let mut decision = self.decide(&mut engine, input, only, Some(&mut evaluated))?;
+
+        let consulted: Vec<String> = evaluated
+            .iter()
+            .map(|rule| {
+                let name = |key: &str| rule[key].as_str().unwrap_or_default().to_string();
+                format!("{}.{}", name("package"), name("rule"))
+            })
+            .collect();
+        let consulted: Vec<&str> = consulted.iter().map(String::as_str).collect();
+        let prints = engine
+            .take_prints()
+            .map_err(|e| anyhow::anyhow!("failed to collect policy output: {}", e))?;
+        decision["trace"] = serde_json::json!({
+            "evaluated": evaluated,
+            "consulted_inputs": self.index.explain(&consulted, input).matched_inputs,
+            "prints": prints,
+            "strategy": self.strategy.as_str(),
+            "disabled": self
+                .package_policies
+                .iter()
+                .filter(|policy| self.metadata.get(*policy).is_some_and(|m| !m.enabled))
+                .collect::<std::collections::BTreeSet<_>>(),
+        });
+        Ok(decision)
+    }
+
+    /// Run the decision rules on `engine`, appending each rule evaluated (and
+    /// its value) to `trace` if given
+    ///
+    /// With `only`, the packages of that policy alone take part, whether
+    /// or not it is enabled.
+    fn decide(
+        &self,
+        engine: &mut regorus::Engine,
+        input: &serde_json::Value,
+        only: Option<&str>,
+        mut trace: Option<&mut Vec<serde_json::Value>>,
+    ) -> anyhow::Result<serde_json::Value> {
+        // Packages of disabled policies take no part
+        let active: Vec<usize> = (0..self.packages.len())
+            .filter(|&i| match only {
+                Some(policy) => self.package_policies[i] == policy,
+                None => self.package_metadata(i).is_none_or(|m| m.enabled),
+            })
+            .collect();
+        if active.is_empty() {
+            let allow = self.default_decision == DefaultDecision::Allow;
+            let policies = match self.packages.is_empty() {
+                true => "No policies loaded",
+                false => "No policies enabled",
+            };
+            return Ok(serde_json::json!({
+                "allow": allow,
+                "policy": "default",
+                "reason": match allow {
+                    true => format!("{} - all requests allowed", policies),
+                    false => format!("{} - all requests denied", policies),
+                },
+                "mode": "observe",
+                "rules": [],
+                "matched_inputs": [],
+                "violations": [],
+                "obligations": {},
+            }));
+        }
+
+        engine.set_input(regorus::Value::from(input.clone()));
+
+        let mut packages = Vec::with_capacity(active.len());
+        for &i in &active {
+            let package = &self.packages[i];
+            let metadata = self.package_metadata(i);
+            let mut eval = |rule: &str| -> anyhow::Result<Option<serde_json::Value>> {
+                if self.index.locate(package, rule).is_empty() {
+                    return Ok(None);
+                }
+                let value = engine
+                    .eval_rule(format!("data.{}.{}", package, rule))
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to evaluate {}.{}: {}", package, rule, e)
+                    })?;
+                let value = match value {
+                    regorus::Value::Undefined => serde_json::Value::Null,
+                    value => serde_json::to_value(&value)?,
+                };
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.push(
+                        serde_json::json!({"package": package, "rule": rule, "value": value}),
+                    );
+                }
+                Ok(Some(value))
+            };
+
+            let mut outcome = PackageOutcome {
+                vote: Vote::Abstain,
+                priority: 0.0,
+                fired: Vec::new(),
+                violations: Vec::new(),
+                reason: None,
+                mode: None,
+                obligations: serde_json::Map::new(),
+            };
+            match eval("allow")? {
+                Some(serde_json::Value::Bool(true)) => outcome.vote = Vote::Allow,
+                Some(_) => {
+                    outcome.vote = Vote::Deny;
+                    outcome.fired.push(format!("{}.allow", package));
+                }
+                None => {}
+            }
+            match eval("deny")? {
+                Some(serde_json::Value::Array(messages)) if !messages.is_empty() => {
+                    outcome.vote = Vote::Deny;
+                    outcome.fired.push(format!("{}.deny", package));
+                    outcome
+                        .violations
+                        .extend(messages.into_iter().map(|m| match m {
+                            serde_json::Value::String(s) => s,
+                            other => other.to_string(),
+                        }));
+                }
+                Some(serde_json::Value::Bool(true)) => {
+                    outcome.vote = Vote::Deny;
+                    outcome.fired.push(format!("{}.deny", package));
+                }
+                _ => {}
+            }
+            outcome.reason = eval("reason")?.and_then(|r| r.as_str().map(str::to_string));
+            outcome.mode = eval("mode")?.and_then(|m| m.as_str().map(str::to_string));
+            if let Some(mode) = metadata.and_then(|m| m.mode.clone()) {
+                outcome.mode = Some(mode);
+            }
+            if let Some(serde_json::Value::Object(map)) = eval("obligations")? {
+                outcome.obligations = map;
+            }
+            if self.strategy == CombinationStrategy::PriorityOrdered {
+                outcome.priority = match metadata.and_then(|m| m.priority) {
+                    Some(priority) => priority,
+                    None => eval("priority")?.and_then(|p| p.as_f64()).unwrap_or(0.0),
+                };
+            }
+            packages.push(outcome);
+        }
+
+        let votes: Vec<(Vote, f64)> = packages.iter().map(|p| (p.vote, p.priority)).collect();
+        let combined = combine(self.strategy, self.default_decision, &votes);
+        let allow = combined.allow;
+        // A default deny isn't any package's doing
+        let policy = match combined.by_default && !allow {
+            true => "default",
+            false => &self.packages[active[combined.decider]],
+        };
+
+        // Only packages whose votes counted shape the result, the decider's
+        // reason first; mode falls back to any package that set one
+        let mut fired = Vec::new();
+        let mut violations = Vec::new();
+        let mut obligations = serde_json::Map::new();
+        let mut reason = None;
+        let mut mode = None;
+        for &i in &combined.supporters {
+            let outcome = &packages[i];
+            if allow {
+                fired.push(format!("{}.allow", self.packages[active[i]]));
+            } else {
+                fired.extend(outcome.fired.iter().cloned());
+                violations.extend(outcome.violations.iter().cloned());
+            }
+            obligations.extend(outcome.obligations.clone());
+            reason = reason.or_else(|| outcome.reason.clone());
+            mode = mode.or_else(|| outcome.mode.clone());
+        }
+        let mode = mode.or_else(|| packages.iter().find_map(|p| p.mode.clone()));
+
+        let unanimous = combined.supporters.len() == active.len();
+        let reason = match reason {
+            Some(reason) => reason,
+            None if !violations.is_empty() => violations.join("; "),
+            None if allow && unanimous => "Allowed by all policies".to_string(),
+            None if allow => format!("Allowed by {}", policy),
+            None if combined.by_default => "No policy decided - denied by default".to_string(),
+            None => format!("Denied by {}", policy),
+        };
+        let fired: Vec<&str> = fired.iter().map(String::as_str).collect();
+        let explanation = self.index.explain(&fired, input);
+
+        Ok(serde_json::json!({
+            "allow": allow,
+            "policy": policy,
+            "reason": reason,
+            "mode": mode.unwrap_or_else(|| "observe".to_string()),
+            "rules": explanation.rules,
+            "matched_inputs": explanation.matched_inputs,
+            "violations": violations,
+            "obligations": obligations,
+        }))
+    }
+}
+
+/// Outcome of `PolicyEngine.evaluate`
+///
+/// The common fields are typed attributes; the rest of the decision (rules,
+/// matched inputs, violations, obligations, trace) is in `metadata`. Item
+/// access (`result["allow"]`) keeps dict-style callers working.
+#[pyclass(name = "PolicyResult", frozen)]
+pub struct PyPolicyResult {
+    /// Whether the request is allowed
+    #[pyo3(get)]
+    allow: bool,
+
+    /// Policy (package) that made the decision
+    #[pyo3(get)]
+    policy: String,
+
+    /// Human-readable explanation
+    #[pyo3(get)]
+    reason: String,
+
+    /// Policy mode (observe, advisory, enforce)
+    #[pyo3(get)]
+    mode: String,
+
+    /// Time spent evaluating, including a decision cache lookup
+    #[pyo3(get)]
+    eval_duration_us: u64,
+
+    /// The full decision document
+    decision: serde_json::Value,
+}
+
+impl PyPolicyResult {
+    fn new(decision: serde_json::Value, elapsed: Duration) -> Self {
+        let text = |field: &str| decision[field].as_str().unwrap_or_default().to_string();
+        PyPolicyResult {
+            allow: decision["allow"].as_bool().unwrap_or(false),
+            policy: text("policy"),
+            reason: text("reason"),
+            mode: text("mode"),
+            eval_duration_us: elapsed.as_micros() as u64,
+            decision,
+        }
+    }
+}
+
+#[pymethods]
+impl PyPolicyResult {
+    /// Decision fields beyond the typed attributes (rules, matched_inputs,
+    /// violations, obligations and, when explained, trace)
+    #[getter]
+    fn metadata(&self, py: Python) -> PyResult<PyObject> {
+        let mut metadata = self.decision.clone();
+        if let Some(map) = metadata.as_object_mut() {
+            for field in ["allow", "policy", "reason", "mode"] {
+                map.remove(field);
+            }
+        }
+        json_to_py(py, &metadata)
+    }
+
+    /// The whole result as a dictionary
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with every decision field plus `eval_duration_us`
+    fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+        let mut decision = self.decision.clone();
+        decision["eval_duration_us"] = self.eval_duration_us.into();
+        json_to_py(py, &decision)
+    }
 
-        Ok(result.into())
+    /// A decision field, as from the dictionary `evaluate` used to return
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Field name (e.g. "allow", "violations")
+    /// * `default` - Returned when the field is missing (default: None)
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, py: Python, key: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        match self.decision.get(key) {
+            Some(value) => json_to_py(py, value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    fn __getitem__(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        match self.decision.get(key) {
+            Some(value) => json_to_py(py, value),
+            None => Err(pyo3::exceptions::PyKeyError::new_err(key.to_string())),
+        }
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        self.decision.get(key).is_some()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PolicyResult(allow={}, policy={:?}, reason={:?}, mode={:?}, eval_duration_us={})",
+            if self.allow { "True" } else { "False" },
+            self.policy,
+            self.reason,
+            self.mode,
+            self.eval_duration_us
+        )
+    }
+}
+
+/// Build a complete new policy set, then swap it in
+///
+/// The swap only happens once everything compiled, so a broken file never
+/// leaves the engine half-updated.
+fn load_into(
+    policy_dir: &Path,
+    data: &Mutex<DataSources>,
+    verifier: &Mutex<Option<BundleVerifier>>,
+    policies: &RwLock<PolicySet>,
+    decisions: &DecisionCache,
+) -> anyhow::Result<usize> {
+    if let Some(verifier) = &*verifier.lock().unwrap() {
+        verifier
+            .verify(policy_dir)
+            .map_err(|e| e.context("policy signature check failed"))?;
+    }
+    let sources = data.lock().unwrap().clone();
+    let mut set = PolicySet::load(policy_dir, &sources)?;
+    let count = set.index.files().len();
+    let mut current = policies.write().unwrap();
+    set.strategy = current.strategy;
+    set.default_decision = current.default_decision;
+    set.metadata = current.metadata.clone();
+    set.settings_changed();
+    *current = set;
+    decisions.clear();
+    Ok(count)
+}
+
+fn explanation_to_py(py: Python, explanation: &Explanation) -> PyResult<PyObject> {
+    let rules = PyList::empty_bound(py);
+    for location in &explanation.rules {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("package", &location.package)?;
+        dict.set_item("rule", &location.rule)?;
+        dict.set_item("file", &location.file)?;
+        dict.set_item("line", location.line)?;
+        rules.append(dict)?;
+    }
+
+    let matched = PyList::empty_bound(py);
+    for input in &explanation.matched_inputs {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("path", &input.path)?;
+        dict.set_item("value", json_to_py(py, &input.value)?)?;
+        matched.append(dict)?;
+    }
+
+    let result = PyDict::new_bound(py);
+    result.set_item("rules", rules)?;
+    result.set_item("matched_inputs", matched)?;
+    Ok(result.into())
+}
+
+/// Convert a JSON value to the equivalent Python object
+pub(crate) fn json_to_py(py: Python, value: &serde_json::Value) -> PyResult<PyObject> {
+    Ok(match value {
+        serde_json::Value::Null => py.None(),
+        serde_json::Value::Bool(b) => b.into_py(py),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_py(py),
+            None => match n.as_u64() {
+                Some(u) => u.into_py(py),
+                None => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+            },
+        },
+        serde_json::Value::String(s) => s.into_py(py),
+        serde_json::Value::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into()
+        }
+        serde_json::Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into()
+        }
+    })
+}
+
+/// Convert a Python value to JSON directly, without a `json.dumps` round trip
+///
+/// Handles dict/list/tuple/str/int/float/bool/None as `json.dumps` does,
+/// plus other mappings, sets (as lists) and dates/times (as ISO 8601
+/// strings). Integers beyond 64 bits and other types are rejected rather
+/// than silently rounded or stringified.
+pub(crate) fn py_to_json(obj: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    if obj.is_none() {
+        Ok(serde_json::Value::Null)
+    } else if obj.is_instance_of::<PyBool>() {
+        Ok(serde_json::Value::Bool(obj.extract()?))
+    } else if obj.is_instance_of::<PyLong>() {
+        if let Ok(i) = obj.extract::<i64>() {
+            Ok(serde_json::Value::from(i))
+        } else if let Ok(u) = obj.extract::<u64>() {
+            Ok(serde_json::Value::from(u))
+        } else {
+            Err(PyValueError::new_err(format!(
+                "integer {} does not fit in 64 bits",
+                obj
+            )))
+        }
+    } else if obj.is_instance_of::<PyFloat>() {
+        Ok(serde_json::Number::from_f64(obj.extract()?)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number))
+    } else if obj.is_instance_of::<PyString>() {
+        Ok(serde_json::Value::String(obj.extract()?))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = serde_json::Map::new();
+        for (key, value) in dict.iter() {
+            map.insert(key.str()?.to_string(), py_to_json(&value)?);
+        }
+        Ok(serde_json::Value::Object(map))
+    } else if obj.is_instance_of::<PyList>()
+        || obj.is_instance_of::<PyTuple>()
+        || obj.is_instance_of::<PySet>()
+        || obj.is_instance_of::<PyFrozenSet>()
+    {
+        obj.iter()?
+            .map(|item| py_to_json(&item?))
+            .collect::<PyResult<Vec<_>>>()
+            .map(serde_json::Value::Array)
+    } else if obj.is_instance_of::<PyDate>() || obj.is_instance_of::<PyTime>() {
+        // datetime is a date subclass; all three have isoformat()
+        Ok(serde_json::Value::String(
+            obj.call_method0("isoformat")?.extract()?,
+        ))
+    } else if let Ok(mapping) = obj.downcast::<PyMapping>() {
+        let mut map = serde_json::Map::new();
+        for item in mapping.items()?.iter()? {
+            let (key, value): (Bound<'_, PyAny>, Bound<'_, PyAny>) = item?.extract()?;
+            map.insert(key.str()?.to_string(), py_to_json(&value)?);
+        }
+        Ok(serde_json::Value::Object(map))
+    } else {
+        Err(PyValueError::new_err(format!(
+            "unsupported input value of type {}",
+            obj.get_type().name()?
+        )))
     }
 }
 
@@ -130,7 +1671,513 @@ mod tests {
 
     #[test]
     fn test_policy_engine_creation() {
-        let engine = PolicyEngine::new("/tmp/policies".to_string());
+        let engine = PolicyEngine::new(
+            "/tmp/policies".to_string(),
+            5,
+            1024,
+            "deny-overrides",
+            "allow",
+            1,
+        );
         assert!(engine.is_ok());
     }
+
+    #[test]
+    fn test_policy_result_fields() {
+        let decision = serde_json::json!({
+            "allow": false,
+            "policy": "yori.bedtime",
+            "reason": "It's past bedtime",
+            "mode": "enforce",
+            "violations": ["It's past bedtime"],
+        });
+        let result = PyPolicyResult::new(decision, Duration::from_micros(42));
+        assert!(!result.allow);
+        assert_eq!(result.policy, "yori.bedtime");
+        assert_eq!(result.mode, "enforce");
+        assert_eq!(result.eval_duration_us, 42);
+        assert!(result.__contains__("violations"));
+        assert_eq!(
+            result.__repr__(),
+            "PolicyResult(allow=False, policy=\"yori.bedtime\", reason=\"It's past bedtime\", mode=\"enforce\", eval_duration_us=42)"
+        );
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_previous_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("home_default.rego"), "package yori.home\n").unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        engine
+            .watch(Duration::from_millis(50), move |outcome| {
+                tx.send(outcome.clone()).unwrap();
+            })
+            .unwrap();
+
+        std::fs::write(dir.path().join("bedtime.rego"), "package yori.bedtime\n").unwrap();
+        let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(outcome.error.is_none());
+        assert_eq!(engine.policy_names(), vec!["bedtime", "home_default"]);
+
+        // An unreadable policy file fails the reload as a whole
+        std::fs::write(dir.path().join("broken.rego"), [0xff, 0xfe, 0x00]).unwrap();
+        let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(outcome.error.is_some());
+        assert_eq!(outcome.policies, 2);
+        assert_eq!(engine.policy_names(), vec!["bedtime", "home_default"]);
+    }
+    #[test]
+    fn test_evaluates_raw_rego_in_either_syntax() {
+        let dir = tempfile::tempdir().unwrap();
+        // Rego v1 syntax
+        std::fs::write(
+            dir.path().join("bedtime.rego"),
+            "package yori.bedtime\n\ndeny contains msg if {\n    input.hour >= 21\n    msg := \"past bedtime\"\n}\n",
+        )
+        .unwrap();
+        // Rego v0 syntax
+        std::fs::write(
+            dir.path().join("home_default.rego"),
+            "package yori.home\n\ndefault allow = false\n\nallow {\n    input.user != \"guest\"\n}\n\nmode = \"enforce\"\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        let result = engine
+            .evaluate_json(&serde_json::json!({"user": "alice", "hour": 20}))
+            .unwrap();
+        assert_eq!(result["allow"], true);
+        assert_eq!(result["mode"], "enforce");
+
+        let result = engine
+            .evaluate_json(&serde_json::json!({"user": "alice", "hour": 22}))
+            .unwrap();
+        assert_eq!(result["allow"], false);
+        assert_eq!(result["policy"], "yori.bedtime");
+        assert_eq!(result["reason"], "past bedtime");
+        assert_eq!(result["rules"][0]["file"], "bedtime.rego");
+        assert_eq!(result["matched_inputs"][0]["path"], "input.hour");
+
+        let result = engine
+            .evaluate_json(&serde_json::json!({"user": "guest", "hour": 20}))
+            .unwrap();
+        assert_eq!(result["allow"], false);
+        assert_eq!(result["policy"], "yori.home");
+    }
+
+    #[test]
+    fn test_data_documents_are_available_to_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("devices.rego"),
+            "package yori.devices\n\ndeny contains \"unknown device\" if {\n    not data.devices[input.client_ip]\n}\n\ndeny contains \"blocked site\" if {\n    input.endpoint in data.blocked\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("devices.yaml"),
+            "\"192.168.1.50\": sam-ipad\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        let known = serde_json::json!({"client_ip": "192.168.1.50", "endpoint": "api.openai.com"});
+        let unknown =
+            serde_json::json!({"client_ip": "192.168.1.99", "endpoint": "api.openai.com"});
+        assert_eq!(engine.evaluate_json(&known).unwrap()["allow"], true);
+        assert_eq!(
+            engine.evaluate_json(&unknown).unwrap()["reason"],
+            "unknown device"
+        );
+
+        // Added data survives reloads; conflicting data is rejected as a whole
+        engine
+            .add_data_document(serde_json::json!({"blocked": ["api.openai.com"]}))
+            .unwrap();
+        engine.reload().unwrap();
+        assert_eq!(
+            engine.evaluate_json(&known).unwrap()["reason"],
+            "blocked site"
+        );
+        assert!(engine
+            .add_data_document(serde_json::json!({"devices": {"192.168.1.50": "other"}}))
+            .is_err());
+        assert_eq!(engine.data.lock().unwrap().documents.len(), 1);
+        assert_eq!(
+            engine.evaluate_json(&known).unwrap()["reason"],
+            "blocked site"
+        );
+    }
+
+    #[test]
+    fn test_identical_inputs_reuse_decision_until_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("home_default.rego"),
+            "package yori.home\n\ndefault allow := true\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let input =
+            serde_json::json!({"user": "alice", "endpoint": "api.openai.com", "model": "gpt-4o"});
+
+        assert_eq!(engine.evaluate_json(&input).unwrap()["allow"], true);
+        assert_eq!(engine.evaluate_json(&input).unwrap()["allow"], true);
+        let stats = engine.cache_stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+
+        // A reload must not serve decisions made by the old policies
+        std::fs::write(
+            dir.path().join("home_default.rego"),
+            "package yori.home\n\ndefault allow := false\n",
+        )
+        .unwrap();
+        engine.reload().unwrap();
+        assert_eq!(engine.evaluate_json(&input).unwrap()["allow"], false);
+        assert_eq!(engine.cache_stats().misses, 2);
+    }
+
+    #[test]
+    fn test_inputs_agreeing_on_read_fields_share_a_decision() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bedtime.rego"), "package yori.bedtime\n\ndeny contains \"past bedtime\" if {\n    input.user == \"sam\"\n    input.schedule.hour >= 21\n}\n").unwrap();
+        let engine =
+            PolicyEngine::open_with_cache(dir.path(), DecisionCache::new(Duration::ZERO, 0))
+                .unwrap();
+        let request = |prompt: &str, hour: u32| serde_json::json!({"user": "sam", "prompt": prompt, "schedule": {"hour": hour, "minute": 7}});
+
+        assert_eq!(
+            engine.evaluate_json(&request("essay", 22)).unwrap()["allow"],
+            false
+        );
+        assert_eq!(
+            engine.evaluate_json(&request("poem", 22)).unwrap()["allow"],
+            false
+        );
+        assert_eq!(
+            engine.evaluate_json(&request("essay", 20)).unwrap()["allow"],
+            true
+        );
+        let stats = engine.shortcut_stats();
+        assert_eq!(stats.paths.unwrap(), ["input.schedule.hour", "input.user"]);
+        assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+
+        // Reading the whole input rules shortcuts out
+        std::fs::write(
+            dir.path().join("audit.rego"),
+            "package yori.audit\n\ndeny contains \"big\" if count(input) > 10\n",
+        )
+        .unwrap();
+        engine.reload().unwrap();
+        let stats = engine.shortcut_stats();
+        assert!(stats.unavailable.unwrap().starts_with("audit.rego:3"));
+        engine.evaluate_json(&request("essay", 22)).unwrap();
+        assert_eq!(engine.shortcut_stats().entries, 0);
+    }
+
+    #[test]
+    fn test_rego_syntax_error_keeps_previous_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("home_default.rego"),
+            "package yori.home\n\ndefault allow := true\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        std::fs::write(
+            dir.path().join("broken.rego"),
+            "package yori.broken\n\nallow if {\n",
+        )
+        .unwrap();
+        let error = engine.reload().unwrap_err();
+        assert!(format!("{:#}", error).contains("broken.rego"));
+        assert_eq!(engine.policy_names(), vec!["home_default"]);
+        assert_eq!(
+            engine.evaluate_json(&serde_json::json!({})).unwrap()["allow"],
+            true
+        );
+    }
+    #[test]
+    fn test_explain_traces_rules_inputs_and_prints() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("bedtime.rego"),
+            "package yori.bedtime\n\ndeny contains \"past bedtime\" if {\n    print(\"hour is\", input.hour)\n    input.hour >= 21\n}\n\nmode := \"enforce\" if input.user == \"sam\"\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let input = serde_json::json!({"user": "sam", "hour": 22, "model": "gpt-4o"});
+
+        let plain = engine.evaluate_json(&input).unwrap();
+        assert!(plain.get("trace").is_none());
+        let explained = engine.explain_json(&input).unwrap();
+        assert_eq!(explained["allow"], false);
+        assert_eq!(explained["reason"], plain["reason"]);
+
+        let trace = &explained["trace"];
+        assert_eq!(
+            trace["evaluated"],
+            serde_json::json!([
+                {"package": "yori.bedtime", "rule": "deny", "value": ["past bedtime"]},
+                {"package": "yori.bedtime", "rule": "mode", "value": "enforce"},
+            ])
+        );
+        let consulted: Vec<&str> = trace["consulted_inputs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(consulted, ["input.hour", "input.user"]);
+        assert_eq!(
+            trace["prints"],
+            serde_json::json!(["bedtime.rego:4: hour is 22"])
+        );
+
+        // Explaining neither reads nor fills the decision cache
+        assert_eq!(engine.cache_stats().entries, 1);
+        assert_eq!(
+            engine.explain_json(&serde_json::json!({})).unwrap()["trace"]["prints"][0],
+            "bedtime.rego:4: hour is <undefined>"
+        );
+        assert_eq!(engine.cache_stats().entries, 1);
+    }
+
+    #[test]
+    fn test_default_deny_fails_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let input = serde_json::json!({"hour": 10});
+        assert_eq!(engine.evaluate_json(&input).unwrap()["allow"], true);
+
+        engine.set_default_decision(DefaultDecision::Deny);
+        let result = engine.evaluate_json(&input).unwrap();
+        assert_eq!(result["allow"], false);
+        assert_eq!(result["reason"], "No policies loaded - all requests denied");
+
+        // A package that doesn't vote leaves the default in charge, across reloads
+        std::fs::write(
+            dir.path().join("bedtime.rego"),
+            "package yori.bedtime\n\ndeny contains \"past bedtime\" if input.hour >= 21\n",
+        )
+        .unwrap();
+        engine.reload().unwrap();
+        let result = engine.evaluate_json(&input).unwrap();
+        assert_eq!(
+            (&result["allow"], &result["policy"]),
+            (&serde_json::json!(false), &serde_json::json!("default"))
+        );
+        assert_eq!(result["reason"], "No policy decided - denied by default");
+        assert_eq!(
+            engine
+                .evaluate_json(&serde_json::json!({"hour": 22}))
+                .unwrap()["reason"],
+            "past bedtime"
+        );
+    }
+
+    #[test]
+    fn test_strategy_decides_conflicting_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a_homework.rego"), "package yori.homework\n\nallow if input.site == \"khanacademy.org\"\n\nreason := \"homework help\" if allow\n").unwrap();
+        std::fs::write(dir.path().join("b_bedtime.rego"), "package yori.bedtime\n\npriority := 10\n\ndeny contains \"past bedtime\" if input.hour >= 21\n").unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let late_homework = serde_json::json!({"site": "khanacademy.org", "hour": 22});
+
+        let decide = |strategy: &str| {
+            engine.set_strategy(strategy.parse().unwrap());
+            let result = engine.evaluate_json(&late_homework).unwrap();
+            (
+                result["allow"].as_bool().unwrap(),
+                result["policy"].as_str().unwrap().to_string(),
+            )
+        };
+        assert_eq!(engine.strategy(), CombinationStrategy::DenyOverrides);
+        assert_eq!(
+            decide("deny-overrides"),
+            (false, "yori.bedtime".to_string())
+        );
+        assert_eq!(
+            decide("allow-overrides"),
+            (true, "yori.homework".to_string())
+        );
+        assert_eq!(decide("first-match"), (true, "yori.homework".to_string()));
+        assert_eq!(
+            decide("priority-ordered"),
+            (false, "yori.bedtime".to_string())
+        );
+
+        // The strategy survives reloads, and overridden packages don't leak
+        // their violations into the result
+        engine.set_strategy(CombinationStrategy::AllowOverrides);
+        engine.reload().unwrap();
+        let result = engine.evaluate_json(&late_homework).unwrap();
+        assert_eq!(result["reason"], "homework help");
+        assert_eq!(result["violations"], serde_json::json!([]));
+        assert_eq!(result["rules"][0]["file"], "a_homework.rego");
+
+        // Testing one policy leaves the other out
+        let bedtime = engine
+            .explain_policy_json("b_bedtime", &late_homework)
+            .unwrap();
+        assert_eq!(bedtime["allow"], false);
+        let homework = engine
+            .explain_policy_json("a_homework", &late_homework)
+            .unwrap();
+        assert_eq!(homework["allow"], true);
+    }
+
+    #[test]
+    fn test_py_to_json_reads_non_dict_mappings() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mapping = py
+                .eval_bound(
+                    "__import__('types').MappingProxyType({'user': 'alice', 'hour': 21})",
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(
+                py_to_json(&mapping).unwrap(),
+                serde_json::json!({"user": "alice", "hour": 21})
+            );
+        });
+    }
+
+    #[test]
+    fn test_policy_metadata_disables_and_reranks_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("homework_hours.rego"),
+            "package yori.homework\n\nallow if input.site == \"khanacademy.org\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("bedtime.rego"), "package yori.bedtime\n\npriority := 10\n\nmode := \"enforce\"\n\ndeny contains \"past bedtime\" if input.hour >= 21\n").unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let metadata_path = dir.path().join("state/policy-metadata.json");
+        assert_eq!(engine.persist_metadata(&metadata_path).unwrap(), 0);
+        let late_homework = serde_json::json!({"site": "khanacademy.org", "hour": 22});
+
+        engine.set_strategy(CombinationStrategy::PriorityOrdered);
+        assert_eq!(
+            engine.evaluate_json(&late_homework).unwrap()["policy"],
+            "yori.bedtime"
+        );
+        engine.set_priority("homework_hours", Some(20.0)).unwrap();
+        let result = engine.evaluate_json(&late_homework).unwrap();
+        assert_eq!(
+            (result["allow"].as_bool(), result["mode"].as_str()),
+            (Some(true), Some("enforce"))
+        );
+
+        // A school holiday: bedtime off, homework left ranked first
+        engine.disable_policy("bedtime").unwrap();
+        engine
+            .set_policy_mode("homework_hours", Some(ProxyMode::Observe))
+            .unwrap();
+        engine.reload().unwrap();
+        let result = engine.explain_json(&late_homework).unwrap();
+        assert_eq!(
+            (result["allow"].as_bool(), result["mode"].as_str()),
+            (Some(true), Some("observe"))
+        );
+        assert_eq!(result["trace"]["disabled"], serde_json::json!(["bedtime"]));
+        engine.disable_policy("homework_hours").unwrap();
+        let result = engine.evaluate_json(&late_homework).unwrap();
+        assert_eq!(
+            result["reason"],
+            "No policies enabled - all requests allowed"
+        );
+        engine.enable_policy("homework_hours").unwrap();
+        assert!(engine.disable_policy("weekends").is_err());
+
+        // Restored on the next start
+        let restarted = PolicyEngine::open(dir.path()).unwrap();
+        assert_eq!(restarted.persist_metadata(&metadata_path).unwrap(), 2);
+        let metadata = restarted.policy_metadata();
+        assert!(!metadata["bedtime"].enabled);
+        assert_eq!(metadata["homework_hours"].priority, Some(20.0));
+        restarted.enable_policy("bedtime").unwrap();
+        assert!(restarted.policy_metadata()["bedtime"].enabled);
+    }
+
+    #[test]
+    fn test_parallel_workers_pick_up_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = |limit: u32| {
+            format!(
+                "package yori.quota
+
+deny contains \"over quota\" if {{\n    input.tokens > {}\n}}\n",
+                limit
+            )
+        };
+        std::fs::write(dir.path().join("quota.rego"), policy(100)).unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        // Distinct inputs, so every evaluation runs the policies
+        let decide = |worker: u32, request: u32| {
+            let input = serde_json::json!({"tokens": 50, "worker": worker, "request": request});
+            engine.evaluate_json(&input).unwrap()["allow"].clone()
+        };
+        std::thread::scope(|scope| {
+            for worker in 0..4 {
+                scope.spawn(move || {
+                    for request in 0..25 {
+                        assert_eq!(decide(worker, request), true);
+                    }
+                });
+            }
+        });
+        assert_eq!(decide(99, 0), true);
+
+        // This thread's worker engine is from the old policies
+        std::fs::write(dir.path().join("quota.rego"), policy(10)).unwrap();
+        engine.reload().unwrap();
+        assert_eq!(decide(99, 1), false);
+    }
+    #[test]
+    fn test_tenant_policies_decide_only_their_tenant() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("home_default.rego"),
+            "package yori.home\n\ndefault allow := true\n",
+        )
+        .unwrap();
+        let tenant = crate::tenant::tenant_policy_dir(dir.path(), "smiths");
+        std::fs::create_dir_all(&tenant).unwrap();
+        std::fs::write(
+            tenant.join("strict.rego"),
+            "package yori.strict\n\ndeny contains \"smiths only\" if true\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let request = |tenant: &str| serde_json::json!({"tenant": tenant, "user": "alice"});
+
+        assert_eq!(engine.policy_names(), vec!["home_default"]);
+        assert_eq!(
+            engine.evaluate_json(&request("smiths")).unwrap()["allow"],
+            false
+        );
+        assert_eq!(
+            engine
+                .evaluate_json(&request(crate::tenant::DEFAULT_TENANT))
+                .unwrap()["allow"],
+            true
+        );
+        assert_eq!(
+            engine.evaluate_json(&request("joneses")).unwrap()["allow"],
+            true
+        );
+
+        // Shared settings reach the tenant's set too
+        engine.disable_policy("home_default").unwrap();
+        assert_eq!(
+            engine.explain_json(&request("smiths")).unwrap()["trace"]["disabled"],
+            serde_json::json!(["home_default"])
+        );
+    }
 }
@@ -0,0 +1,86 @@
+//! Per-policy metadata kept outside the policy files
+//!
+//! A parent can switch a policy off for a school holiday, or re-rank it,
+//! without editing or deleting the `.rego` file:
+//!
+//! ```python
+//! engine.disable_policy("homework_hours")
+//! engine.set_priority("bedtime", 10)
+//! engine.set_policy_mode("gaming", "observe")
+//! ```
+//!
+//! Metadata is keyed by policy name (the file name without `.rego`) and
+//! applies to the packages that file declares:
+//!
+//! - `enabled` - disabled policies take no part in decisions
+//! - `priority` - replaces the package's `priority` rule under the
+//!   priority-ordered strategy
+//! - `mode` - replaces the package's `mode` rule
+//!
+//! It survives reloads, and restarts too when a metadata file is set
+//! (`policy.metadata_path`). Policies without an entry use the defaults.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::persist::write_atomic;
+
+/// Where the gateway keeps policy metadata
+pub const DEFAULT_POLICY_METADATA: &str = "/var/db/yori/state/policy-metadata.json";
+
+/// Operator settings for one policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyMetadata {
+    pub enabled: bool,
+
+    /// Priority under the priority-ordered strategy (None = the package's
+    /// own `priority` rule)
+    pub priority: Option<f64>,
+
+    /// "observe", "advisory" or "enforce" (None = the package's own `mode`
+    /// rule)
+    pub mode: Option<String>,
+}
+
+impl Default for PolicyMetadata {
+    fn default() -> Self {
+        PolicyMetadata {
+            enabled: true,
+            priority: None,
+            mode: None,
+        }
+    }
+}
+
+impl PolicyMetadata {
+    /// Whether every setting is at its default (no need to store it)
+    pub fn is_default(&self) -> bool {
+        *self == PolicyMetadata::default()
+    }
+}
+
+/// Read a metadata file; a missing file is empty
+pub(crate) fn load_metadata(path: &Path) -> Result<BTreeMap<String, PolicyMetadata>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid policy metadata in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Write a metadata file, leaving out policies at their defaults
+pub(crate) fn save_metadata(
+    path: &Path,
+    metadata: &BTreeMap<String, PolicyMetadata>,
+) -> Result<()> {
+    let stored: BTreeMap<&String, &PolicyMetadata> = metadata
+        .iter()
+        .filter(|(_, metadata)| !metadata.is_default())
+        .collect();
+    write_atomic(path, &serde_json::to_vec_pretty(&stored)?)
+}
@@ -0,0 +1,343 @@
+//! Policy unit tests, run in-process like `opa test`
+//!
+//! Test files live in the policy directory next to the policies they cover
+//! and are recognised by a `_test` suffix. They are never loaded as
+//! policies or data, so shipping them to the router changes no decision.
+//!
+//! Rego tests follow the OPA convention: every rule named `test_*` in a
+//! `*_test.rego` file passes when it evaluates to `true`.
+//!
+//! ```rego
+//! package yori.bedtime_test
+//!
+//! import data.yori.bedtime
+//!
+//! test_blocks_after_bedtime if {
+//!     not bedtime.allow with input as {"schedule": {"hour": 22}}
+//! }
+//! ```
+//!
+//! `*_test.yaml` files list whole-request cases instead. Each field under
+//! `expect` must equal the same field of the combined decision:
+//!
+//! ```yaml
+//! - name: homework help is fine at 8pm
+//!   input: {client_ip: 192.168.1.50, schedule: {hour: 20}}
+//!   expect: {allow: true}
+//! - name: no chatbots after bedtime
+//!   input: {client_ip: 192.168.1.50, schedule: {hour: 22}}
+//!   expect: {allow: false, policy: yori.bedtime}
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::explain::{read_files, RuleIndex};
+
+/// File stem suffix that marks a test file
+pub(crate) const TEST_SUFFIX: &str = "_test";
+
+/// Prefix of the rules run as Rego tests
+const TEST_RULE_PREFIX: &str = "test_";
+
+/// Whether `path` is a test file (its stem ends in `_test`)
+pub(crate) fn is_test_file(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .is_some_and(|stem| stem.ends_with(TEST_SUFFIX))
+}
+
+/// How a single test ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOutcome {
+    Pass,
+
+    /// The test ran and its expectation didn't hold
+    Fail,
+
+    /// The test could not be run (compile or evaluation error)
+    Error,
+}
+
+impl TestOutcome {
+    /// Outcome name as reported to callers
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestOutcome::Pass => "pass",
+            TestOutcome::Fail => "fail",
+            TestOutcome::Error => "error",
+        }
+    }
+}
+
+/// Result of one Rego test rule or YAML case
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyTestResult {
+    /// "package.test_rule" for Rego tests, the case name for YAML cases
+    pub name: String,
+
+    /// Test file, relative to the policy directory
+    pub file: String,
+
+    /// Line of the test rule or the position of the case (1-based)
+    pub line: usize,
+
+    pub outcome: TestOutcome,
+
+    /// Why the test failed or could not run
+    pub message: Option<String>,
+
+    pub duration_us: u64,
+}
+
+/// Results of a [`crate::PolicyEngine::run_tests`] run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PolicyTestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub errors: usize,
+
+    /// Every test in file order
+    pub results: Vec<PolicyTestResult>,
+}
+
+impl PolicyTestReport {
+    /// Whether every test passed
+    pub fn ok(&self) -> bool {
+        self.failed == 0 && self.errors == 0
+    }
+
+    fn push(&mut self, result: PolicyTestResult) {
+        match result.outcome {
+            TestOutcome::Pass => self.passed += 1,
+            TestOutcome::Fail => self.failed += 1,
+            TestOutcome::Error => self.errors += 1,
+        }
+        self.results.push(result);
+    }
+}
+
+/// One case of a `*_test.yaml` file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestCase {
+    name: String,
+    #[serde(default)]
+    input: Value,
+    expect: Map<String, Value>,
+}
+
+/// Run the test files under `policy_dir`
+///
+/// Rego tests are compiled on a copy of `engine` (the loaded policies and
+/// data); YAML cases are decided by `decide`.
+pub(crate) fn run(
+    policy_dir: &Path,
+    engine: &regorus::Engine,
+    decide: impl Fn(&Value) -> Result<Value>,
+) -> Result<PolicyTestReport> {
+    let files = read_files(policy_dir, "policy", |path| {
+        is_test_file(path)
+            && path
+                .extension()
+                .is_some_and(|e| e == "rego" || e == "yaml" || e == "yml")
+    })?;
+    let mut report = PolicyTestReport::default();
+    for (file, source) in files {
+        if file.ends_with(".rego") {
+            run_rego(engine, &file, &source, &mut report);
+        } else {
+            run_cases(&file, &source, &decide, &mut report);
+        }
+    }
+    Ok(report)
+}
+
+/// Evaluate every `test_*` rule of one Rego test file
+fn run_rego(engine: &regorus::Engine, file: &str, source: &str, report: &mut PolicyTestReport) {
+    let started = Instant::now();
+    let mut engine = engine.clone();
+    engine.set_rego_v0(false);
+    if let Err(v1_error) = engine.add_policy(file.to_string(), source.to_string()) {
+        engine.set_rego_v0(true);
+        if engine
+            .add_policy(file.to_string(), source.to_string())
+            .is_err()
+        {
+            report.push(PolicyTestResult {
+                name: file.to_string(),
+                file: file.to_string(),
+                line: 1,
+                outcome: TestOutcome::Error,
+                message: Some(format!("failed to compile: {}", v1_error)),
+                duration_us: started.elapsed().as_micros() as u64,
+            });
+            return;
+        }
+    }
+
+    let mut index = RuleIndex::default();
+    index.add_source(file, source);
+    // Incremental definitions of a test share one result
+    let mut seen = BTreeSet::new();
+    for location in index.rules() {
+        if !location.rule.starts_with(TEST_RULE_PREFIX)
+            || !seen.insert((location.package.clone(), location.rule.clone()))
+        {
+            continue;
+        }
+        let name = format!("{}.{}", location.package, location.rule);
+        let started = Instant::now();
+        let (outcome, message) = match engine.eval_rule(format!("data.{}", name)) {
+            Ok(regorus::Value::Bool(true)) => (TestOutcome::Pass, None),
+            Ok(regorus::Value::Undefined) => (
+                TestOutcome::Fail,
+                Some("test rule is undefined".to_string()),
+            ),
+            Ok(value) => (
+                TestOutcome::Fail,
+                Some(format!(
+                    "test rule evaluated to {}",
+                    serde_json::to_string(&value).unwrap_or_default()
+                )),
+            ),
+            Err(e) => (TestOutcome::Error, Some(format!("{:#}", e))),
+        };
+        report.push(PolicyTestResult {
+            name,
+            file: file.to_string(),
+            line: location.line,
+            outcome,
+            message,
+            duration_us: started.elapsed().as_micros() as u64,
+        });
+    }
+}
+
+/// Decide every case of one YAML test file and compare the expected fields
+fn run_cases(
+    file: &str,
+    source: &str,
+    decide: &impl Fn(&Value) -> Result<Value>,
+    report: &mut PolicyTestReport,
+) {
+    let cases: Vec<TestCase> = match serde_yaml::from_str(source)
+        .with_context(|| format!("invalid test cases in {}", file))
+    {
+        Ok(cases) => cases,
+        Err(e) => {
+            report.push(PolicyTestResult {
+                name: file.to_string(),
+                file: file.to_string(),
+                line: 1,
+                outcome: TestOutcome::Error,
+                message: Some(format!("{:#}", e)),
+                duration_us: 0,
+            });
+            return;
+        }
+    };
+    for (position, case) in cases.into_iter().enumerate() {
+        let started = Instant::now();
+        let (outcome, message) = match decide(&case.input) {
+            Ok(decision) => {
+                let mismatches: Vec<String> = case
+                    .expect
+                    .iter()
+                    .filter(|(field, expected)| decision.get(field.as_str()) != Some(*expected))
+                    .map(|(field, expected)| {
+                        format!(
+                            "expected {} = {}, got {}",
+                            field,
+                            expected,
+                            decision.get(field.as_str()).unwrap_or(&Value::Null)
+                        )
+                    })
+                    .collect();
+                if mismatches.is_empty() {
+                    (TestOutcome::Pass, None)
+                } else {
+                    (TestOutcome::Fail, Some(mismatches.join("; ")))
+                }
+            }
+            Err(e) => (TestOutcome::Error, Some(format!("{:#}", e))),
+        };
+        report.push(PolicyTestResult {
+            name: case.name,
+            file: file.to_string(),
+            line: position + 1,
+            outcome,
+            message,
+            duration_us: started.elapsed().as_micros() as u64,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PolicyEngine;
+    use std::fs;
+
+    #[test]
+    fn test_runs_rego_and_yaml_tests() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("bedtime.rego"),
+            "package yori.bedtime\n\n\
+             default allow := true\n\n\
+             allow := false if input.hour >= 21\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("bedtime_test.rego"),
+            "package yori.bedtime_test\n\n\
+             import data.yori.bedtime as bedtime\n\n\
+             test_allowed_in_the_afternoon if {\n    bedtime.allow with input as {\"hour\": 15}\n}\n\n\
+             test_wrong_expectation if {\n    bedtime.allow with input as {\"hour\": 22}\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("bedtime_test.yaml"),
+            "- name: blocked late\n  input: {hour: 22}\n  expect: {allow: false}\n\
+             - name: wrong policy\n  input: {hour: 22}\n  expect: {policy: yori.other}\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        assert_eq!(engine.policy_names(), ["bedtime"]);
+
+        let report = engine.run_tests().unwrap();
+        let outcomes: Vec<(&str, TestOutcome)> = report
+            .results
+            .iter()
+            .map(|r| (r.name.as_str(), r.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                (
+                    "yori.bedtime_test.test_allowed_in_the_afternoon",
+                    TestOutcome::Pass
+                ),
+                (
+                    "yori.bedtime_test.test_wrong_expectation",
+                    TestOutcome::Fail
+                ),
+                ("blocked late", TestOutcome::Pass),
+                ("wrong policy", TestOutcome::Fail),
+            ]
+        );
+        assert_eq!((report.passed, report.failed, report.errors), (2, 2, 0));
+        assert!(!report.ok());
+        assert!(report.results[3]
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("expected policy = \"yori.other\""));
+    }
+}
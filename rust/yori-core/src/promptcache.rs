@@ -0,0 +1,450 @@
+//! Semantic prompt cache
+//!
+//! Households ask the same thing again and again: the homework question
+//! five times in an evening, "what's the capital of Australia" from two
+//! kids. For models the parent has allowed, a completion can be served
+//! from cache instead of paying for it again.
+//!
+//! Prompts are keyed by model, system prompt and normalized text
+//! ([`crate::dedup::normalize`]), so case, punctuation and spacing don't
+//! defeat the cache. Two optional modes also catch near-duplicates:
+//!
+//! - `simhash`: prompts within a small SimHash distance of a cached one
+//!   ("what is 7 times 8" / "what is 7 times 8 please")
+//! - `embedding`: prompts whose embedding, from a small local model behind
+//!   [`PromptEmbedder`], is close enough by cosine similarity
+//!
+//! Streaming requests, requests offering tools and anything but a 200
+//! response are never cached.
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cache::{CacheStats, LruTtlCache};
+use crate::dedup::{normalize, simhash, PromptDeduplicator, DEFAULT_MAX_DISTANCE};
+use crate::providers::ParsedRequest;
+use crate::retry::CachedResponse;
+
+/// Default time a completion stays cached
+pub const DEFAULT_PROMPT_CACHE_TTL_SECS: u64 = 24 * 3600;
+
+/// Default maximum number of cached completions
+pub const DEFAULT_PROMPT_CACHE_ENTRIES: usize = 2_000;
+
+/// Default memory limit for cached completions
+pub const DEFAULT_PROMPT_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+/// Default cosine similarity for an embedding match
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.95;
+
+/// How a prompt is matched against cached ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptMatch {
+    /// Same normalized text only
+    Exact,
+
+    /// Normalized text or a SimHash within `max_distance` bits (at most 3)
+    SimHash { max_distance: u32 },
+
+    /// Normalized text or an embedding with at least `min_similarity`
+    /// (falls back to exact matching without an embedder)
+    Embedding { min_similarity: f32 },
+}
+
+impl PromptMatch {
+    /// Mode name used in configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptMatch::Exact => "exact",
+            PromptMatch::SimHash { .. } => "simhash",
+            PromptMatch::Embedding { .. } => "embedding",
+        }
+    }
+}
+
+impl std::str::FromStr for PromptMatch {
+    type Err = anyhow::Error;
+
+    /// Parse a mode name with its default threshold
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "exact" => Ok(PromptMatch::Exact),
+            "simhash" => Ok(PromptMatch::SimHash {
+                max_distance: DEFAULT_MAX_DISTANCE,
+            }),
+            "embedding" => Ok(PromptMatch::Embedding {
+                min_similarity: DEFAULT_MIN_SIMILARITY,
+            }),
+            other => bail!(
+                "unknown prompt match {:?} (expected exact, simhash or embedding)",
+                other
+            ),
+        }
+    }
+}
+
+/// Turns prompt text into an embedding vector (e.g., a small local model)
+pub trait PromptEmbedder: Send + Sync {
+    /// Embed `text`; vectors from one embedder must share a dimension
+    fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+/// Prompt cache configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptCacheConfig {
+    /// Models whose completions may be cached ("llama3*" matches any
+    /// model name starting with "llama3"); empty = cache off
+    pub models: Vec<String>,
+
+    /// How prompts are matched
+    pub matching: PromptMatch,
+
+    /// How long a completion stays cached
+    pub ttl: Duration,
+
+    /// Maximum number of cached completions
+    pub max_entries: usize,
+
+    /// Approximate memory limit for cached completions
+    pub max_bytes: usize,
+}
+
+impl Default for PromptCacheConfig {
+    fn default() -> Self {
+        PromptCacheConfig {
+            models: Vec::new(),
+            matching: PromptMatch::Exact,
+            ttl: Duration::from_secs(DEFAULT_PROMPT_CACHE_TTL_SECS),
+            max_entries: DEFAULT_PROMPT_CACHE_ENTRIES,
+            max_bytes: DEFAULT_PROMPT_CACHE_BYTES,
+        }
+    }
+}
+
+/// Near-duplicate lookup structures, per model and system prompt
+#[derive(Default)]
+struct SimilarityIndex {
+    /// SimHash fingerprints → id
+    fingerprints: HashMap<String, PromptDeduplicator>,
+
+    /// Embeddings with the key they were cached under, oldest first
+    embeddings: HashMap<String, VecDeque<(Vec<f32>, String)>>,
+
+    /// Cache key of each fingerprint id
+    keys: HashMap<i64, String>,
+
+    /// Fingerprint ids, oldest first (bounds `keys`)
+    order: VecDeque<i64>,
+}
+
+/// Completions of allowed models, keyed by (near-)identical prompt
+pub struct PromptCache {
+    config: PromptCacheConfig,
+    store: LruTtlCache<CachedResponse>,
+    index: Mutex<SimilarityIndex>,
+    embedder: Option<Arc<dyn PromptEmbedder>>,
+    next_id: AtomicI64,
+}
+
+impl PromptCache {
+    /// Create an empty cache
+    pub fn new(config: PromptCacheConfig) -> Self {
+        PromptCache {
+            store: LruTtlCache::new(config.max_entries, config.ttl)
+                .with_max_bytes(config.max_bytes),
+            config,
+            index: Mutex::new(SimilarityIndex::default()),
+            embedder: None,
+            next_id: AtomicI64::new(0),
+        }
+    }
+
+    /// Match near-duplicates by embedding in [`PromptMatch::Embedding`] mode
+    pub fn with_embedder(mut self, embedder: Arc<dyn PromptEmbedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Whether a request's completion may be cached (and served from cache)
+    pub fn cacheable(&self, request: &ParsedRequest) -> bool {
+        let Some(model) = &request.model else {
+            return false;
+        };
+        !request.stream
+            && request.tools.is_empty()
+            && !normalize(&request.prompt).is_empty()
+            && self
+                .config
+                .models
+                .iter()
+                .any(|pattern| model_matches(pattern, model))
+    }
+
+    /// Cached completion for a request, if one matches
+    pub fn lookup(&self, request: &ParsedRequest) -> Option<CachedResponse> {
+        if !self.cacheable(request) {
+            return None;
+        }
+        let key = cache_key(request);
+        if let Some(response) = self.store.get(&key) {
+            return Some(response);
+        }
+        let scope = index_scope(request);
+        let similar = match self.config.matching {
+            PromptMatch::Exact => None,
+            PromptMatch::SimHash { .. } => {
+                let index = self.index.lock().unwrap();
+                index
+                    .fingerprints
+                    .get(&scope)
+                    .and_then(|dedup| dedup.find(simhash(&request.prompt)))
+                    .and_then(|id| index.keys.get(&id).cloned())
+            }
+            PromptMatch::Embedding { min_similarity } => {
+                let embedding = self.embed(&request.prompt)?;
+                let index = self.index.lock().unwrap();
+                index
+                    .embeddings
+                    .get(&scope)?
+                    .iter()
+                    .map(|(cached, key)| (cosine_similarity(cached, &embedding), key))
+                    .filter(|(similarity, _)| *similarity >= min_similarity)
+                    .max_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(_, key)| key.clone())
+            }
+        };
+        similar.and_then(|key| self.store.get(&key))
+    }
+
+    /// Cache a successful completion of a cacheable request
+    pub fn store(&self, request: &ParsedRequest, response: &CachedResponse) {
+        if response.status != 200 || !self.cacheable(request) {
+            return;
+        }
+        let scope = index_scope(request);
+        let key = cache_key(request);
+        self.store.insert(key.clone(), response.clone());
+
+        match self.config.matching {
+            PromptMatch::Exact => {}
+            PromptMatch::SimHash { max_distance } => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let mut index = self.index.lock().unwrap();
+                index
+                    .fingerprints
+                    .entry(scope)
+                    .or_insert_with(|| {
+                        PromptDeduplicator::new(max_distance, self.config.max_entries)
+                    })
+                    .insert(simhash(&request.prompt), id);
+                index.keys.insert(id, key);
+                index.order.push_back(id);
+                while index.order.len() > self.config.max_entries {
+                    if let Some(old) = index.order.pop_front() {
+                        index.keys.remove(&old);
+                    }
+                }
+            }
+            PromptMatch::Embedding { .. } => {
+                let Some(embedding) = self.embed(&request.prompt) else {
+                    return;
+                };
+                let mut index = self.index.lock().unwrap();
+                let embeddings = index.embeddings.entry(scope).or_default();
+                embeddings.push_back((embedding, key));
+                if embeddings.len() > self.config.max_entries {
+                    embeddings.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Hit/miss counters of the underlying store
+    pub fn stats(&self) -> CacheStats {
+        self.store.stats()
+    }
+
+    /// Drop every cached completion
+    pub fn clear(&self) {
+        self.store.clear();
+        *self.index.lock().unwrap() = SimilarityIndex::default();
+    }
+
+    fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        match self.embedder.as_ref()?.embed(text) {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                tracing::warn!("Prompt embedding failed: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Whether `model` matches a pattern (exact, or a prefix ending in `*`),
+/// ignoring case
+pub(crate) fn model_matches(pattern: &str, model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => model == pattern,
+    }
+}
+
+/// Model and system prompt; near-duplicates are only looked for within one
+fn index_scope(request: &ParsedRequest) -> String {
+    format!(
+        "{}\0{}",
+        request.model.as_deref().unwrap_or(""),
+        request.system_prompt.as_deref().unwrap_or("")
+    )
+}
+
+/// SHA-256 of model, system prompt and normalized prompt text
+fn cache_key(request: &ParsedRequest) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        request.model.as_deref().unwrap_or(""),
+        request.system_prompt.as_deref().unwrap_or(""),
+        &normalize(&request.prompt),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &str, prompt: &str) -> ParsedRequest {
+        ParsedRequest {
+            model: Some(model.to_string()),
+            prompt: prompt.to_string(),
+            ..ParsedRequest::default()
+        }
+    }
+
+    fn completion(text: &str) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: text.as_bytes().to_vec(),
+        }
+    }
+
+    fn cache(matching: PromptMatch) -> PromptCache {
+        PromptCache::new(PromptCacheConfig {
+            models: vec!["llama3*".to_string()],
+            matching,
+            ..PromptCacheConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_exact_match_ignores_case_and_punctuation() {
+        let cache = cache(PromptMatch::Exact);
+        cache.store(
+            &request("llama3.2:3b", "What is the capital of Australia?"),
+            &completion("Canberra"),
+        );
+
+        let hit = cache.lookup(&request("llama3.2:3b", "what is the capital of australia"));
+        assert_eq!(hit.unwrap().body, b"Canberra");
+        // Other models and other prompts miss
+        assert!(cache
+            .lookup(&request("gpt-4o", "What is the capital of Australia?"))
+            .is_none());
+        assert!(cache
+            .lookup(&request("llama3.2:3b", "What is the capital of Austria?"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_uncacheable_requests() {
+        let cache = cache(PromptMatch::Exact);
+        let mut streaming = request("llama3", "Tell me a joke");
+        streaming.stream = true;
+        cache.store(&streaming, &completion("..."));
+        assert!(cache.lookup(&streaming).is_none());
+
+        let failed = CachedResponse {
+            status: 500,
+            ..completion("error")
+        };
+        cache.store(&request("llama3", "Tell me a joke"), &failed);
+        assert!(cache.lookup(&request("llama3", "Tell me a joke")).is_none());
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_simhash_matches_near_duplicates() {
+        let cache = cache(PromptMatch::SimHash { max_distance: 3 });
+        cache.store(
+            &request(
+                "llama3",
+                "Explain photosynthesis in simple terms for a ten year old student",
+            ),
+            &completion("Plants make food from light"),
+        );
+        let hit = cache.lookup(&request(
+            "llama3",
+            "Please explain photosynthesis in simple terms for a ten year old student",
+        ));
+        assert_eq!(hit.unwrap().body, b"Plants make food from light");
+    }
+
+    struct LetterCounts;
+
+    impl PromptEmbedder for LetterCounts {
+        fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            let mut counts = vec![0.0; 26];
+            for c in normalize(text).chars().filter(char::is_ascii_lowercase) {
+                counts[(c as u8 - b'a') as usize] += 1.0;
+            }
+            Ok(counts)
+        }
+    }
+
+    #[test]
+    fn test_embedding_match() {
+        let cache = cache(PromptMatch::Embedding {
+            min_similarity: 0.99,
+        })
+        .with_embedder(Arc::new(LetterCounts));
+        cache.store(
+            &request("llama3", "listen to the silent night"),
+            &completion("anagram"),
+        );
+        assert!(cache
+            .lookup(&request("llama3", "silent to the listen night"))
+            .is_some());
+        assert!(cache
+            .lookup(&request("llama3", "a different question"))
+            .is_none());
+        assert_eq!(
+            "simhash".parse::<PromptMatch>().unwrap().as_str(),
+            "simhash"
+        );
+    }
+}
@@ -7,6 +7,7 @@
 //!
 //! ```text
 //! Device → OPNsense Firewall (rdr) → YORI Proxy (:8443)
+//!   (or Device → CONNECT → YORI Proxy, see [`crate::connect`])
 //!     ↓
 //!   TLS Termination (CA cert)
 //!     ↓
@@ -19,13 +20,109 @@
 //!   Return Response
 //! ```
 
-use anyhow::Result;
-use std::net::SocketAddr;
+use anyhow::{anyhow, bail, Context, Result};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT,
+};
+use hyper::http::request::Parts;
+use hyper::{Request, Response, StatusCode, Uri};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_util::sync::CancellationToken;
+
+use crate::admin::{AdminState, DEFAULT_ADMIN_ADDR};
+use crate::advisory::{AdvisoryConfig, AdvisoryNotice};
+use crate::alerts::{load_roots, Alerter};
+use crate::alpn::{
+    connect_upstream, negotiated_version, prepare_upstream_request, serve_connection,
+    strip_hop_headers, upstream_tls_config, HttpVersion, UpstreamSender,
+};
+use crate::audit::{AuditConfig, AuditEvent, AuditEventType, AuditLogger, PyAuditSubscription};
+use crate::blockpage::{BlockPage, BlockPageConfig};
+use crate::budget::{
+    budget_exceeded_body, BudgetConfig, BudgetStatus, BudgetTracker, DEFAULT_BUDGET_STATE,
+};
+use crate::bundle::{BundleConfig, BundleDownloader};
+use crate::canary::{CanaryConfig, CanaryProbe, CanaryResponder, CANARY_HEADER};
+use crate::certs::{ca_server_config, static_server_config, CertAuthority};
+use crate::classify::{CategoryModel, ClassifierConfig, PromptClassifier};
+use crate::combining::DefaultDecision;
+use crate::config::YoriConfig;
+use crate::connect::{accept_connect, ConnectOutcome, InterceptionMode};
+use crate::connlimit::{
+    ConnectionLimitConfig, ConnectionLimitStats, ConnectionLimiter, ConnectionPermit,
+    ConnectionRefusal,
+};
+use crate::cost::PricingTable;
+use crate::decisionlog::DecisionLogShipper;
+use crate::discovery::NameDiscovery;
+use crate::dlp::{dlp_blocked_body, DlpAction, DlpConfig, DlpScan, DlpScanner};
+use crate::dnsoverride::DnsOverrides;
+use crate::drain::{ConnectionGuard, ConnectionTracker, DrainReport, DEFAULT_DRAIN_TIMEOUT_SECS};
+use crate::enrich::{EnrichmentConfig, EnrichmentPipeline, QuotaEnricher};
+use crate::honeypot::{HoneypotConfig, HoneypotResponder, SyntheticResponse};
+use crate::identity::{DeviceIdentity, DeviceRegistry, IdentitySource};
+use crate::inspect::{
+    apply_response_decision, response_blocked_body, response_input, ResponseVerdict,
+};
+use crate::latency::LatencyTracker;
+use crate::limits::{
+    check_content_length, collect_limited, payload_too_large_body, BodyKind, BodyTooLarge,
+    LimitError, LimitedBody, DEFAULT_MAX_REQUEST_BYTES, DEFAULT_MAX_RESPONSE_BYTES,
+};
+use crate::livetail::{LiveFilter, LiveTail};
+use crate::localroute::{LocalRouter, LocalRoutingConfig, RoutedRequest, ROUTED_HEADER};
+use crate::maintenance::{MaintenanceConfig, MaintenanceScheduler};
+use crate::models::{
+    model_blocked_body, rewrite_model, ModelDecision, ModelGovernor, ModelPolicyConfig,
+};
+use crate::netaddr::{bind_listener, normalize_client_ip};
+use crate::origdst::{original_destination, DestinationLookup};
+use crate::policy::{json_to_py, PolicyEngine};
+use crate::promptcache::{PromptCache, PromptCacheConfig};
+use crate::providers::{
+    host_matches, parse_request, parse_response_metadata, ParsedRequest, Provider,
+    ProviderRegistry, ResponseMetadata,
+};
+use crate::proxyauth::{ProxyAuthConfig, ProxyAuthenticator};
+use crate::quota::{QuotaConfig, QuotaManager, QuotaUsage};
+use crate::ratelimit::{allowance_headers, rate_limited_body, RateDecision, RateLimiter};
+use crate::reload::ConfigReloader;
+use crate::replay::{PolicyReplay, WarmReport, DEFAULT_WARM_EVENTS};
+use crate::retry::{
+    request_hash, CachedResponse, RetryCheck, RetryDetector, DEFAULT_RETRY_WINDOW_SECS,
+};
+use crate::scope::{NetworkScope, ScopeDecision, ScopeMatcher};
+use crate::secrets::{ApiKeyStore, DEFAULT_API_KEY_STORE};
+use crate::session::{SessionTracker, DEFAULT_SESSION_IDLE_SECS};
+use crate::sni::{passthrough, peek_sni, TlsRoute, PASSTHROUGH_PORT};
+use crate::spool::{AuditSpool, SpoolConfig};
+use crate::stream::{is_event_stream, StreamingBody};
+use crate::tenant::TenantRegistry;
+use crate::timeseries::UsageSeries;
+use crate::transcript::{TranscriptStore, TranscriptTurn};
+use crate::upstream::{
+    is_upstream_failure, upstream_error_body, CircuitBreaker, CircuitBreakerConfig, CircuitState,
+    RetryPolicy,
+};
+use crate::wireguard::WireGuardPeers;
 
 /// Configuration for the YORI proxy server
 #[derive(Debug, Clone)]
 pub struct ProxyConfig {
-    /// Listen address (e.g., "0.0.0.0:8443")
+    /// Listen address; `[::]` takes IPv4 and IPv6 clients on one socket
+    /// (see [`crate::netaddr::bind_listener`])
     pub listen_addr: SocketAddr,
 
     /// Path to TLS certificate
@@ -34,13 +131,176 @@ pub struct ProxyConfig {
     /// Path to TLS private key
     pub tls_key_path: String,
 
-    /// List of LLM endpoints to intercept
+    /// LLM endpoints to intercept (host names or `*` patterns, see
+    /// [`crate::providers::host_matches`])
     pub endpoints: Vec<String>,
 
+    /// Provider catalog plus hosts added in configuration; added hosts are
+    /// intercepted too
+    pub providers: ProviderRegistry,
+
     /// Policy evaluation mode (observe, advisory, enforce)
     pub mode: ProxyMode,
+
+    /// Households sharing this router, matched by client network
+    pub tenants: TenantRegistry,
+
+    /// Subnet/VLAN scopes with their own mode, profile or outright block
+    pub scopes: Vec<NetworkScope>,
+
+    /// Local model servers (e.g., "ollama.lan:11434") that stay reachable
+    /// in local-only mode
+    pub local_endpoints: Vec<String>,
+
+    /// Start with every cloud LLM endpoint blocked
+    pub local_only: bool,
+
+    /// Local model servers that routed requests are answered by (see
+    /// [`crate::localroute`])
+    pub local_routing: LocalRoutingConfig,
+
+    /// Models each device or user may request, with downgrades (see
+    /// [`crate::models`])
+    pub models: ModelPolicyConfig,
+
+    /// Device profiles, schedules, quotas and tags added to policy input
+    pub enrichment: EnrichmentConfig,
+
+    /// Window in which an identical request from the same device is a retry
+    pub retry_window_secs: u64,
+
+    /// Answer retries with the original request's response
+    pub serve_cached_retries: bool,
+
+    /// Gap after which a device's next request to a provider starts a new
+    /// conversation session (see [`crate::session`])
+    pub session_idle_secs: u64,
+
+    /// Content category rules (see [`crate::classify`])
+    pub classifier: ClassifierConfig,
+
+    /// Secret scanning rules for outbound bodies (see [`crate::dlp`])
+    pub dlp: DlpConfig,
+
+    /// Synthetic local responses for blocked categories
+    pub honeypot: HoneypotConfig,
+
+    /// Error JSON and HTML page for blocked requests (see
+    /// [`crate::blockpage`])
+    pub block_page: BlockPageConfig,
+
+    /// Warnings on responses advisory mode forwarded despite a deny (see
+    /// [`crate::advisory`])
+    pub advisory: AdvisoryConfig,
+
+    /// Requests per minute allowed per device and endpoint (None = unlimited)
+    pub rate_limit_per_minute: Option<u32>,
+
+    /// Requests a device may send back to back before the per-minute rate
+    /// applies (None = same as `rate_limit_per_minute`)
+    pub rate_limit_burst: Option<u32>,
+
+    /// Daily/weekly token and request quotas per user or device (counters
+    /// are persisted to `quota.state_path`, e.g.
+    /// [`crate::quota::DEFAULT_QUOTA_STATE`])
+    pub quota: QuotaConfig,
+
+    /// Whether traffic arrives via firewall redirection, explicit CONNECT
+    /// requests from devices configured to use the proxy, or both
+    pub interception: InterceptionMode,
+
+    /// How transparently redirected connections' original destination is
+    /// found (see [`crate::origdst`])
+    pub destination_lookup: DestinationLookup,
+
+    /// Proxy credentials naming the user behind explicit-mode connections
+    /// (see [`crate::proxyauth`])
+    pub auth: ProxyAuthConfig,
+
+    /// CA certificate used to mint a certificate per intercepted host
+    /// (created by `yori ca generate`); None = present `tls_cert_path` for
+    /// every host
+    pub ca_cert_path: Option<String>,
+
+    /// Private key of the interception CA
+    pub ca_key_path: Option<String>,
+
+    /// Offer HTTP/2 over ALPN to clients and upstreams (HTTP/1.1 is always
+    /// offered as the fallback)
+    pub http2: bool,
+
+    /// Seconds in-flight requests get to finish on shutdown before their
+    /// connections are dropped
+    pub drain_timeout_secs: u64,
+
+    /// Caps on connections served at once, overall and per client (see
+    /// [`crate::connlimit`])
+    pub connection_limits: ConnectionLimitConfig,
+
+    /// Evaluate policies a second time on non-streaming responses, with the
+    /// completion text in the input (see [`crate::inspect`])
+    pub inspect_responses: bool,
+
+    /// Ask devices nothing else names for their mDNS/NetBIOS name (see
+    /// [`crate::discovery`])
+    pub discover_names: bool,
+
+    /// Largest request body accepted (None = unlimited); larger requests
+    /// get a 413
+    pub max_request_bytes: Option<u64>,
+
+    /// Largest upstream response body passed on (None = unlimited)
+    pub max_response_bytes: Option<u64>,
+
+    /// Retries of idempotent requests that hit an upstream error
+    pub upstream_retry: RetryPolicy,
+
+    /// Per-endpoint circuit breaker for failing upstreams
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    /// Answer repeated prompts to allowed models from cache (see
+    /// [`crate::promptcache`]); None = off
+    pub prompt_cache: Option<PromptCacheConfig>,
+
+    /// Remove credentials sent by clients even for providers without a
+    /// stored API key (see [`crate::secrets`])
+    pub strip_client_keys: bool,
+
+    /// Per-model prices used to estimate request cost (see [`crate::cost`])
+    pub pricing: PricingTable,
+
+    /// Monthly spend caps per user (see [`crate::budget`]); with
+    /// `budget.hard_cap`, subjects over their cap are refused in every mode
+    pub budget: BudgetConfig,
+
+    /// Buffering of audit events while the database is unavailable (see
+    /// [`crate::spool`])
+    pub audit_spool: SpoolConfig,
+
+    /// Recent audit events re-evaluated at startup to fill the policy
+    /// decision caches (see [`ProxyServer::warm_from_audit`]; 0 = off)
+    pub warm_decisions: usize,
+
+    /// Audit retention, rollups and quiet-hours vacuuming while the proxy
+    /// runs (see [`crate::maintenance`])
+    pub maintenance: MaintenanceConfig,
+
+    /// Central policy bundle kept in sync with the policy directory while
+    /// the proxy runs (see [`crate::bundle`]; None = off)
+    pub policy_bundle: Option<BundleConfig>,
+
+    /// Bypass-detection probe of the intercepted endpoints while the proxy
+    /// runs (see [`crate::canary`]; None = off)
+    pub canary: Option<CanaryConfig>,
 }
 
+/// Policy name recorded when local-only mode blocks a request
+pub const LOCAL_ONLY_POLICY: &str = "local_only";
+
+/// Block reason recorded when local-only mode blocks a request
+pub const LOCAL_ONLY_REASON: &str =
+    "Local-only mode is on: cloud LLM services are switched off, local models are still available";
+
 /// Proxy operation mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProxyMode {
@@ -54,19 +314,98 @@ pub enum ProxyMode {
     Enforce,
 }
 
+impl ProxyMode {
+    /// Mode name as used in yori.conf
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProxyMode::Observe => "observe",
+            ProxyMode::Advisory => "advisory",
+            ProxyMode::Enforce => "enforce",
+        }
+    }
+}
+
+impl std::str::FromStr for ProxyMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "observe" => Ok(ProxyMode::Observe),
+            "advisory" => Ok(ProxyMode::Advisory),
+            "enforce" => Ok(ProxyMode::Enforce),
+            _ => bail!(
+                "unknown mode {:?} (expected observe, advisory or enforce)",
+                s
+            ),
+        }
+    }
+}
+
 impl Default for ProxyConfig {
     fn default() -> Self {
         ProxyConfig {
-            listen_addr: "0.0.0.0:8443".parse().unwrap(),
+            listen_addr: "[::]:8443".parse().unwrap(),
             tls_cert_path: "/usr/local/etc/yori/certs/yori.crt".to_string(),
             tls_key_path: "/usr/local/etc/yori/certs/yori.key".to_string(),
             endpoints: vec![
                 "api.openai.com".to_string(),
                 "api.anthropic.com".to_string(),
                 "gemini.google.com".to_string(),
+                "generativelanguage.googleapis.com".to_string(),
                 "api.mistral.ai".to_string(),
+                "*.openai.azure.com".to_string(),
+                "bedrock-runtime.*.amazonaws.com".to_string(),
+                "openrouter.ai".to_string(),
+                "api.groq.com".to_string(),
+                "api.deepseek.com".to_string(),
+                "api.together.xyz".to_string(),
             ],
+            providers: ProviderRegistry::default(),
             mode: ProxyMode::Observe,
+            tenants: TenantRegistry::default(),
+            scopes: Vec::new(),
+            local_endpoints: Vec::new(),
+            local_only: false,
+            local_routing: LocalRoutingConfig::default(),
+            models: ModelPolicyConfig::default(),
+            enrichment: EnrichmentConfig::default(),
+            retry_window_secs: DEFAULT_RETRY_WINDOW_SECS,
+            session_idle_secs: DEFAULT_SESSION_IDLE_SECS,
+            serve_cached_retries: false,
+            classifier: ClassifierConfig::default(),
+            dlp: DlpConfig::default(),
+            honeypot: HoneypotConfig::default(),
+            block_page: BlockPageConfig::default(),
+            advisory: AdvisoryConfig::default(),
+            rate_limit_per_minute: None,
+            rate_limit_burst: None,
+            quota: QuotaConfig::default(),
+            interception: InterceptionMode::Transparent,
+            destination_lookup: DestinationLookup::Sni,
+            auth: ProxyAuthConfig::default(),
+            ca_cert_path: Some("/usr/local/etc/yori/certs/ca.crt".to_string()),
+            ca_key_path: Some("/usr/local/etc/yori/certs/ca.key".to_string()),
+            http2: true,
+            drain_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+            connection_limits: ConnectionLimitConfig::default(),
+            inspect_responses: true,
+            discover_names: true,
+            max_request_bytes: Some(DEFAULT_MAX_REQUEST_BYTES),
+            max_response_bytes: Some(DEFAULT_MAX_RESPONSE_BYTES),
+            upstream_retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            prompt_cache: None,
+            strip_client_keys: false,
+            pricing: PricingTable::default(),
+            budget: BudgetConfig {
+                state_path: Some(DEFAULT_BUDGET_STATE.into()),
+                ..BudgetConfig::default()
+            },
+            audit_spool: SpoolConfig::default(),
+            warm_decisions: DEFAULT_WARM_EVENTS,
+            maintenance: MaintenanceConfig::default(),
+            policy_bundle: None,
+            canary: None,
         }
     }
 }
@@ -74,118 +413,3150 @@ impl Default for ProxyConfig {
 /// YORI transparent proxy server
 pub struct ProxyServer {
     config: ProxyConfig,
+    endpoints: RwLock<Vec<String>>,
+    scopes: ScopeMatcher,
+    latency: Arc<LatencyTracker>,
+    usage: Arc<UsageSeries>,
+    wireguard: RwLock<WireGuardPeers>,
+    devices: RwLock<DeviceRegistry>,
+    names: Option<Arc<NameDiscovery>>,
+    local_only: AtomicBool,
+    mode: RwLock<ProxyMode>,
+    shutdown: Mutex<CancellationToken>,
+    connections: ConnectionTracker,
+    connection_limits: ConnectionLimiter,
+    audit: Arc<RwLock<Option<Arc<AuditLogger>>>>,
+    audit_spool: Arc<AuditSpool>,
+    transcripts: RwLock<Option<Arc<TranscriptStore>>>,
+    policies: RwLock<Option<Arc<PolicyEngine>>>,
+    api_keys: RwLock<Option<Arc<ApiKeyStore>>>,
+    live: LiveTail,
+    enrichment: EnrichmentPipeline,
+    retries: RetryDetector,
+    sessions: SessionTracker,
+    classifier: PromptClassifier,
+    dlp: DlpScanner,
+    canary: Arc<CanaryResponder>,
+    honeypot: HoneypotResponder,
+    block_page: BlockPage,
+    advisory: AdvisoryNotice,
+    router: LocalRouter,
+    models: ModelGovernor,
+    prompt_cache: Option<PromptCache>,
+    breaker: CircuitBreaker,
+    rate_limiter: RwLock<Option<RateLimiter>>,
+    quotas: Arc<QuotaManager>,
+    quota: QuotaEnricher,
+    pricing: Arc<PricingTable>,
+    budgets: Arc<BudgetTracker>,
+    auth: Option<ProxyAuthenticator>,
+    tls: Mutex<Option<Arc<rustls::ServerConfig>>>,
+    upstream_tls: Mutex<Option<TlsConnector>>,
 }
 
 impl ProxyServer {
     /// Create a new proxy server with the given configuration
     pub fn new(config: ProxyConfig) -> Self {
-        ProxyServer { config }
+        let usage = Arc::new(UsageSeries::default());
+        let quotas = Arc::new(QuotaManager::new(config.quota.clone()));
+        let budgets = Arc::new(BudgetTracker::new(config.budget.clone()));
+        let names = config
+            .discover_names
+            .then(|| Arc::new(NameDiscovery::default()));
+        ProxyServer {
+            endpoints: RwLock::new(config.endpoints.clone()),
+            scopes: ScopeMatcher::new(config.scopes.clone()),
+            local_only: AtomicBool::new(config.local_only),
+            mode: RwLock::new(config.mode),
+            shutdown: Mutex::new(CancellationToken::new()),
+            connections: ConnectionTracker::default(),
+            connection_limits: ConnectionLimiter::new(config.connection_limits),
+            audit: Arc::new(RwLock::new(None)),
+            audit_spool: Arc::new(AuditSpool::new(config.audit_spool.clone())),
+            transcripts: RwLock::new(None),
+            policies: RwLock::new(None),
+            api_keys: RwLock::new(None),
+            enrichment: EnrichmentPipeline::from_config(
+                &config.enrichment,
+                Arc::clone(&usage),
+                Arc::clone(&quotas),
+                Arc::clone(&budgets),
+            ),
+            retries: RetryDetector::new(config.retry_window_secs, config.serve_cached_retries),
+            sessions: SessionTracker::new(config.session_idle_secs),
+            classifier: PromptClassifier::new(&config.classifier).unwrap_or_else(|e| {
+                tracing::warn!("Invalid category rules, using the built-in ones: {:#}", e);
+                PromptClassifier::default()
+            }),
+            dlp: DlpScanner::new(&config.dlp).unwrap_or_else(|e| {
+                tracing::warn!("Invalid DLP rules, using the built-in ones: {:#}", e);
+                DlpScanner::default()
+            }),
+            honeypot: HoneypotResponder::new(config.honeypot.clone()),
+            block_page: BlockPage::new(config.block_page.clone()),
+            advisory: AdvisoryNotice::new(config.advisory.clone()),
+            router: LocalRouter::new(config.local_routing.clone()),
+            models: ModelGovernor::new(config.models.clone()),
+            prompt_cache: config.prompt_cache.clone().map(PromptCache::new),
+            breaker: CircuitBreaker::new(config.circuit_breaker),
+            rate_limiter: RwLock::new(
+                config
+                    .rate_limit_per_minute
+                    .map(|limit| RateLimiter::new(limit, config.rate_limit_burst.unwrap_or(limit))),
+            ),
+            quota: QuotaEnricher::new(
+                Arc::clone(&quotas),
+                config.enrichment.daily_token_quota,
+                config.enrichment.devices.clone(),
+            ),
+            quotas,
+            pricing: Arc::new(config.pricing.clone()),
+            budgets,
+            canary: Arc::new(CanaryResponder::random().expect("system RNG unavailable")),
+            auth: config
+                .auth
+                .enabled()
+                .then(|| ProxyAuthenticator::new(config.auth.clone())),
+            config,
+            latency: Arc::new(LatencyTracker::default()),
+            usage,
+            wireguard: RwLock::new(WireGuardPeers::default()),
+            devices: RwLock::new(DeviceRegistry::default()),
+            names,
+            live: LiveTail::default(),
+            tls: Mutex::new(None),
+            upstream_tls: Mutex::new(None),
+        }
+    }
+
+    /// Upstream latency tracker shared with reporting APIs
+    pub fn latency(&self) -> Arc<LatencyTracker> {
+        Arc::clone(&self.latency)
+    }
+
+    /// Usage time series shared with export APIs
+    pub fn usage(&self) -> Arc<UsageSeries> {
+        Arc::clone(&self.usage)
+    }
+
+    /// Quota counters shared with reporting APIs
+    pub fn quotas(&self) -> Arc<QuotaManager> {
+        Arc::clone(&self.quotas)
+    }
+
+    /// Monthly spend totals shared with reporting APIs
+    pub fn budgets(&self) -> Arc<BudgetTracker> {
+        Arc::clone(&self.budgets)
+    }
+
+    /// Live-tail channel for audit events and status changes
+    pub fn live_tail(&self) -> LiveTail {
+        self.live.clone()
+    }
+
+    /// Switch local-only mode on or off at runtime
+    ///
+    /// While on, every cloud LLM endpoint is blocked regardless of policy
+    /// and mode, and only `local_endpoints` are reachable.
+    pub fn set_local_only(&self, enabled: bool) {
+        let previous = self.local_only.swap(enabled, Ordering::SeqCst);
+        if previous != enabled {
+            tracing::info!(
+                "Local-only mode {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+            self.live
+                .publish_status("local_only", serde_json::json!({ "enabled": enabled }));
+        }
+    }
+
+    /// Whether local-only mode is currently on
+    pub fn is_local_only(&self) -> bool {
+        self.local_only.load(Ordering::SeqCst)
+    }
+
+    /// Current default mode (scopes may override it per connection)
+    pub fn mode(&self) -> ProxyMode {
+        *self.mode.read().unwrap()
+    }
+
+    /// Change the default mode at runtime
+    ///
+    /// Applies to connections classified after the change.
+    pub fn set_mode(&self, mode: ProxyMode) {
+        let previous = std::mem::replace(&mut *self.mode.write().unwrap(), mode);
+        if previous != mode {
+            tracing::info!("Mode changed from {:?} to {:?}", previous, mode);
+            self.live
+                .publish_status("mode", serde_json::json!({ "mode": mode.as_str() }));
+        }
+    }
+
+    /// LLM endpoints currently intercepted
+    pub fn endpoints(&self) -> Vec<String> {
+        self.endpoints.read().unwrap().clone()
+    }
+
+    /// Replace the intercepted endpoints at runtime
+    ///
+    /// Applies to connections accepted after the change.
+    pub fn set_endpoints(&self, endpoints: Vec<String>) {
+        let mut current = self.endpoints.write().unwrap();
+        if *current != endpoints {
+            tracing::info!("Intercepting {} endpoint patterns", endpoints.len());
+            *current = endpoints;
+        }
+    }
+
+    /// Replace the per-device rate limit at runtime (None = unlimited)
+    ///
+    /// Buckets start full again under the new limit.
+    pub fn set_rate_limit(&self, per_minute: Option<u32>, burst: Option<u32>) {
+        *self.rate_limiter.write().unwrap() =
+            per_minute.map(|limit| RateLimiter::new(limit, burst.unwrap_or(limit)));
+    }
+
+    /// Listen address from the configuration
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.config.listen_addr
+    }
+
+    /// Whether a request to `host` must be blocked by local-only mode
+    ///
+    /// Blocked requests are audited with [`LOCAL_ONLY_POLICY`] and
+    /// [`LOCAL_ONLY_REASON`] so they're distinguishable from policy blocks.
+    pub fn blocked_by_local_only(&self, host: &str) -> bool {
+        self.is_local_only() && !self.is_local_endpoint(host)
+    }
+
+    /// 403 answer for a request local-only mode blocks, and the audit
+    /// event to log
+    pub fn local_only_response(
+        &self,
+        request: &RequestContext,
+        accept: Option<&str>,
+    ) -> (SyntheticResponse, AuditEvent) {
+        let mut event = AuditEvent::from_request(AuditEventType::RequestBlocked, request)
+            .with_policy(LOCAL_ONLY_POLICY, "block", LOCAL_ONLY_REASON)
+            .with_requested_model(requested_model(request));
+        event.response_status = Some(403);
+        (self.block_response(&event, accept), event)
+    }
+
+    /// Check if a host (optionally with port) is a configured local model server
+    fn is_local_endpoint(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let bare = host.rsplit_once(':').map_or(host.as_str(), |(h, _)| h);
+        self.config.local_endpoints.iter().any(|e| {
+            let e = e.to_ascii_lowercase();
+            e == host || e == bare
+        })
     }
 
     /// Start the proxy server (blocking)
     ///
-    /// This starts the HTTP/HTTPS server and begins intercepting traffic.
-    /// This method blocks until the server is stopped.
-    pub async fn start(&self) -> Result<()> {
-        // TODO: Implement actual proxy server using hyper + rustls
-        //
-        // High-level flow:
-        // 1. Set up TLS listener with rustls
-        // 2. Accept connections
-        // 3. For each request:
-        //    a. Parse request details (endpoint, method, path)
-        //    b. Extract prompt data (if applicable)
-        //    c. Call PolicyEngine.evaluate()
-        //    d. Log to audit database
-        //    e. Based on mode and policy result:
-        //       - Observe: Always forward
-        //       - Advisory: Forward but log alerts
-        //       - Enforce: Block if policy denies
-        //    f. Forward to real LLM endpoint (if allowed)
-        //    g. Log response details
-        //    h. Return response to client
+    /// Binds `listen_addr` (transparently when `destination_lookup` is
+    /// tproxy) and serves it until [`Self::shutdown`] is called.
+    pub async fn start(self: Arc<Self>) -> Result<()> {
+        let transparent = self.config.destination_lookup == DestinationLookup::Tproxy;
+        let listener = bind_listener(self.config.listen_addr, transparent)?;
+        self.serve_listener(listener).await
+    }
 
+    /// Serve an already bound listener until [`Self::shutdown`] is called
+    pub async fn serve_listener(self: Arc<Self>, listener: TcpListener) -> Result<()> {
         tracing::info!(
             "YORI proxy server starting on {} (mode: {:?})",
-            self.config.listen_addr,
-            self.config.mode
+            listener.local_addr()?,
+            self.mode()
         );
 
-        // Stub implementation
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        // Before the morning rush hits cold caches
+        if let Err(e) = self.warm_from_audit() {
+            tracing::warn!("Failed to warm policy decisions: {:#}", e);
+        }
+
+        // Refreshes the usage rollups every rollup_interval, prunes events
+        // past audit.retention_days every prune_interval and vacuums during
+        // quiet hours, on whichever audit logger is attached at the time
+        let audit = Arc::clone(&self.audit);
+        let maintenance = Arc::new(MaintenanceScheduler::new(self.config.maintenance.clone()))
+            .spawn(move || audit.read().unwrap().clone());
+        let bundle = self.spawn_bundle_sync();
+        let canary = self.spawn_canary_probe();
+        // Writes events buffered while the database was locked or full
+        let audit = Arc::clone(&self.audit);
+        let spool = Arc::clone(&self.audit_spool).spawn(move || audit.read().unwrap().clone());
+
+        let shutdown = CancellationToken::new();
+        *self.shutdown.lock().unwrap() = shutdown.clone();
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept a connection: {}", e);
+                        continue;
+                    }
+                },
+            };
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = server.serve_client(stream, peer).await {
+                    tracing::debug!("Connection from {} failed: {:#}", peer, e);
+                }
+            });
+        }
+        maintenance.abort();
+        spool.abort();
+        for task in [bundle, canary].into_iter().flatten() {
+            task.abort();
+        }
 
         Ok(())
     }
 
-    /// Gracefully shutdown the proxy server
-    pub async fn shutdown(&self) -> Result<()> {
-        // TODO: Implement graceful shutdown
-        tracing::info!("YORI proxy server shutting down");
+    /// Keep the attached policy engine's directory in sync with
+    /// `policy_bundle`, if one is configured
+    fn spawn_bundle_sync(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let config = self.config.policy_bundle.clone()?;
+        let Some(engine) = self.policy_engine() else {
+            tracing::warn!("Policy bundle {} not synced: no policy engine", config.url);
+            return None;
+        };
+        match BundleDownloader::new(config, engine.policy_dir()) {
+            Ok(downloader) => Some(Arc::new(downloader).spawn(engine)),
+            Err(e) => {
+                tracing::error!("Policy bundle sync not started: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Probe the intercepted endpoints for bypasses, if `canary` is
+    /// configured
+    fn spawn_canary_probe(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let config = self.config.canary.clone()?;
+        match CanaryProbe::new(config, self.canary_responder()) {
+            Ok(probe) => Some(Arc::new(probe.with_live_tail(self.live_tail())).spawn()),
+            Err(e) => {
+                tracing::error!("Canary probe not started: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Serve one accepted connection: admit and classify it, then terminate
+    /// TLS for intercepted hosts and answer its requests
+    async fn serve_client(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        // Refused connections are closed by dropping them
+        let Ok(_permit) = self.admit_connection(peer.ip()).await else {
+            return Ok(());
+        };
+        let Some(guard) = self.accept_connection() else {
+            return Ok(());
+        };
+        // Accepted sockets don't tell their ingress interface
+        let (scope, mode) = match self.classify_connection(peer, None) {
+            ScopeDecision::Reject { scope } => {
+                tracing::debug!(
+                    "Closing connection from {}: scope {} is blocked",
+                    peer,
+                    scope
+                );
+                return Ok(());
+            }
+            ScopeDecision::Accept { scope, mode, .. } => (scope, mode),
+        };
+
+        let ip = peer.ip().to_canonical();
+        let connect = match self.config.interception {
+            InterceptionMode::Transparent => false,
+            InterceptionMode::Explicit => true,
+            // A TLS ClientHello starts with a handshake record, CONNECT with "C"
+            InterceptionMode::Both => {
+                let mut first = [0u8; 1];
+                stream.peek(&mut first).await?;
+                first[0] != TLS_HANDSHAKE_RECORD
+            }
+        };
+        let (host, destination, identity, tls) = if connect {
+            match self.handle_connect(stream).await? {
+                ConnectOutcome::Intercepted {
+                    target,
+                    stream,
+                    user,
+                } => {
+                    let identity = match user {
+                        Some(user) => Some(self.authenticated_identity(ip, &user)),
+                        None => self.client_identity(ip),
+                    };
+                    (target.host, None, identity, *stream)
+                }
+                ConnectOutcome::Tunneled { .. } => return Ok(()),
+            }
+        } else {
+            match self.route_tls(stream).await? {
+                TlsRoute::Intercept {
+                    host,
+                    destination,
+                    stream,
+                } => {
+                    let tls = TlsAcceptor::from(self.tls_config()?)
+                        .accept(stream)
+                        .await
+                        .with_context(|| format!("TLS handshake for {} failed", host))?;
+                    (host, destination, self.client_identity(ip), tls)
+                }
+                TlsRoute::PassedThrough { .. } => return Ok(()),
+            }
+        };
+
+        let version = negotiated_version(tls.get_ref().1);
+        let client = Arc::new(InterceptedClient {
+            ip,
+            scope,
+            mode,
+            identity,
+            host,
+            destination,
+        });
+        let server = Arc::clone(&self);
+        let service = hyper::service::service_fn(move |request| {
+            let server = Arc::clone(&server);
+            let client = Arc::clone(&client);
+            async move { Ok::<_, Infallible>(server.handle_request(&client, request).await) }
+        });
+        tokio::select! {
+            served = serve_connection(tls, version, service) => served?,
+            _ = guard.aborted() => tracing::debug!("Closing connection from {}: drain timed out", peer),
+        }
         Ok(())
     }
 
-    /// Check if an endpoint should be intercepted
-    fn should_intercept(&self, host: &str) -> bool {
-        self.config.endpoints.iter().any(|e| host.contains(e))
+    /// Answer one request on an intercepted connection
+    async fn handle_request(
+        self: &Arc<Self>,
+        client: &InterceptedClient,
+        request: Request<Incoming>,
+    ) -> Response<ProxyBody> {
+        let (mut parts, body) = request.into_parts();
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+        };
+        // The probe only checks the request got here: not forwarded or audited
+        if let Some((name, marker)) = self.canary_response(parts.uri.path()) {
+            return text_response(204, &[(name.to_string(), marker)], String::new());
+        }
+        let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+        let mut request = RequestContext {
+            client_ip: normalize_client_ip(&client.ip.to_string()),
+            tenant: self.config.tenants.resolve_ip(client.ip).to_string(),
+            scope: client.scope.clone(),
+            client_device: client.identity.as_ref().and_then(|i| i.name.clone()),
+            identity: client.identity.clone(),
+            endpoint: client.host.clone(),
+            destination: client.destination,
+            method: parts.method.to_string(),
+            path: path.to_string(),
+            user_agent: header(USER_AGENT).map(str::to_string),
+            prompt_preview: None,
+            timestamp: chrono::Utc::now(),
+            retry_of: None,
+            session_id: None,
+            categories: Vec::new(),
+            parsed: None,
+        };
+        let accept = header(ACCEPT);
+
+        let limit = self.body_limit(BodyKind::Request);
+        let content_length = header(CONTENT_LENGTH).and_then(|v| v.parse().ok());
+        let body = match check_content_length(BodyKind::Request, content_length, limit) {
+            Ok(()) => collect_limited(body, BodyKind::Request, limit).await,
+            Err(error) => Err(LimitError::TooLarge(error)),
+        };
+        let body = match body {
+            Ok(body) => body,
+            Err(LimitError::TooLarge(error)) => {
+                let (status, headers, body, event) = self.body_too_large_response(&request, &error);
+                self.record_audit(&event);
+                return json_response(status, &headers, &body);
+            }
+            Err(LimitError::Body(e)) => {
+                tracing::debug!("Failed to read request from {}: {}", request.client_ip, e);
+                return json_response(
+                    400,
+                    &[],
+                    &serde_json::json!({ "error": "unreadable request body" }),
+                );
+            }
+        };
+        let provider = self.config.providers.provider_for_host(&request.endpoint);
+        if let Some(provider) = provider {
+            let parsed = parse_request(provider, &request.endpoint, &request.path, &body);
+            if !parsed.prompt.is_empty() {
+                request.prompt_preview =
+                    Some(parsed.prompt.chars().take(PROMPT_PREVIEW_CHARS).collect());
+            }
+            request.parsed = Some(parsed);
+        }
+        self.assign_session(&mut request);
+        self.classify_request(&mut request);
+        let request_id = uuid::Uuid::new_v4().to_string();
+        // With serve_cached_retries, a retry gets its original's answer
+        let retried = match self.detect_retry(&mut request, &request_id, &body) {
+            RetryCheck::Retry { cached, .. } => cached,
+            RetryCheck::Original => None,
+        };
+
+        if self.blocked_by_local_only(&request.endpoint) {
+            let (response, event) = self.local_only_response(&request, accept);
+            self.record_audit(&event);
+            return synthetic_response(response);
+        }
+
+        let rate = self.check_rate_limit(&request);
+        if let Some(rate) = rate.as_ref().filter(|r| !r.allowed) {
+            let (headers, body, event) = self.rate_limited_response(&request, rate);
+            self.record_audit(&event);
+            return json_response(429, &headers, &body);
+        }
+        if let Some(status) = self.check_budget(&request) {
+            let (headers, body, event) = self.budget_exceeded_response(&request, &status);
+            self.record_audit(&event);
+            return json_response(403, &headers, &body);
+        }
+        // Secrets are refused or redacted whatever the mode
+        let mut forward_body = body.clone();
+        if let Some(scan) = self.scan_request_body(&request, &body) {
+            if scan.action() == Some(DlpAction::Block) {
+                let (headers, body, event) = self.dlp_blocked_response(&request, &scan);
+                self.record_audit(&event);
+                return json_response(403, &headers, &body);
+            }
+            self.record_audit(&AuditEvent::sensitive_data(&request, &scan));
+            if let Some(redacted) = scan.redacted {
+                forward_body = Bytes::from(redacted);
+            }
+        }
+        let mut model_rewrite = None;
+        match self.check_model(&request) {
+            ModelDecision::Allow => {}
+            ModelDecision::Block { model, reason } => {
+                if client.mode == ProxyMode::Enforce {
+                    let (headers, body, event) =
+                        self.model_blocked_response(&request, &model, &reason);
+                    self.record_audit(&event);
+                    return json_response(403, &headers, &body);
+                }
+            }
+            ModelDecision::Rewrite { from, to } => {
+                let rewritten = provider
+                    .context("unknown provider")
+                    .and_then(|p| rewrite_model(p, &request.path, &forward_body, &to))
+                    .and_then(|(path, body)| Ok((path.parse::<Uri>()?, body)));
+                match rewritten {
+                    Ok((uri, body)) => {
+                        parts.uri = uri;
+                        forward_body = Bytes::from(body);
+                        model_rewrite = Some((from, to));
+                    }
+                    // A model that can't be downgraded counts as refused
+                    Err(e) if client.mode == ProxyMode::Enforce => {
+                        let reason =
+                            format!("Model {} can't be replaced with {}: {:#}", from, to, e);
+                        let (headers, body, event) =
+                            self.model_blocked_response(&request, &from, &reason);
+                        self.record_audit(&event);
+                        return json_response(403, &headers, &body);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to replace model {} with {}: {:#}", from, to, e);
+                    }
+                }
+            }
+        }
+
+        let engine = self.policy_engine();
+        let input = self.policy_input(&request);
+        let decision = match &engine {
+            Some(engine) => match engine.evaluate_json(&input) {
+                Ok(decision) => Some(decision),
+                Err(e) => {
+                    tracing::warn!("Policy evaluation failed for {}: {:#}", request.endpoint, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let text = |field: &str| {
+            decision
+                .as_ref()
+                .and_then(|d| d[field].as_str())
+                .map(str::to_string)
+        };
+        let mut allowed = match (&decision, &engine) {
+            (Some(decision), _) => decision["allow"].as_bool().unwrap_or(false),
+            // Evaluation failed: fail closed while enforcing
+            (None, Some(engine)) => {
+                client.mode != ProxyMode::Enforce
+                    && engine.default_decision() == DefaultDecision::Allow
+            }
+            // No policies loaded
+            (None, None) => true,
+        };
+        let mut policy = text("policy").unwrap_or_else(|| "default".to_string());
+        let mut reason = match text("reason") {
+            Some(reason) => reason,
+            None if !allowed => "No policy decision could be made".to_string(),
+            None => String::new(),
+        };
+        // The policies see quota counters in every mode; enforce mode also
+        // blocks once one runs out
+        if allowed && client.mode == ProxyMode::Enforce {
+            if let Some(limit) = self.check_quota(&request).exceeded() {
+                allowed = false;
+                policy = "quota".to_string();
+                reason = format!("The {} has been reached", limit);
+            }
+        }
+        let requested = requested_model(&request).map(str::to_string);
+        let event = |event_type| {
+            let mut event = AuditEvent::from_request(event_type, &request)
+                .with_policy(&policy, if allowed { "allow" } else { "block" }, &reason)
+                .with_requested_model(requested.as_deref());
+            // Later retries point back at this id
+            event.request_id = request_id.clone();
+            match &model_rewrite {
+                Some((from, to)) => event.with_model_rewrite(from, to),
+                None => event,
+            }
+        };
+
+        let obligations = decision
+            .as_ref()
+            .map_or(&serde_json::Value::Null, |d| &d["obligations"]);
+
+        if !allowed && client.mode == ProxyMode::Enforce {
+            let mut event = event(AuditEventType::RequestBlocked);
+            let stream = request.parsed.as_ref().is_some_and(|p| p.stream);
+            let response = self
+                .honeypot_response(&request, obligations, &reason, stream)
+                .unwrap_or_else(|| self.block_response(&event, accept));
+            event.response_status = Some(response.status);
+            self.record_audit(&event);
+            return synthetic_response(response);
+        }
+
+        // Sent to a local model server instead, whatever the mode; never to
+        // the cloud when it should have stayed local
+        let routed = match self.local_route(&request, obligations, &forward_body) {
+            Some(Ok(routed)) => Some(routed),
+            Some(Err(e)) => {
+                let reason = format!("Request can't be sent to a local model: {:#}", e);
+                let mut event =
+                    event(AuditEventType::RequestBlocked).with_policy(&policy, "block", &reason);
+                event.response_status = Some(403);
+                self.record_audit(&event);
+                return synthetic_response(self.block_response(&event, accept));
+            }
+            None => None,
+        };
+
+        let advisory = !allowed && client.mode == ProxyMode::Advisory;
+        let answer_headers = |status: StatusCode| {
+            let mut headers = self.allowance_headers(&request, rate.as_ref(), status.as_u16());
+            if advisory {
+                headers.extend(self.advisory_headers(&policy, &reason));
+            }
+            headers
+        };
+        let started = Instant::now();
+        let cached = match retried {
+            Some(cached) => Some(cached),
+            // Prompts already answered are served from the prompt cache
+            // once the policies allow them
+            None if allowed && routed.is_none() => self.cached_completion(&request),
+            None => None,
+        };
+        let from_cache = cached.is_some();
+        let upstream = match (cached, &routed) {
+            (Some(cached), _) => Ok(cached_parts(cached)),
+            (None, Some(routed)) => self.forward_local(&request, routed).await,
+            (None, None) => {
+                if let Err(retry_after) = self.check_upstream(&request) {
+                    let reason = "endpoint is failing, requests are paused";
+                    let (headers, body, event) =
+                        self.upstream_error_response(&request, reason, Some(retry_after));
+                    self.record_audit(&event);
+                    return json_response(502, &headers, &body);
+                }
+                match self.forward(&request, &parts, &forward_body).await {
+                    Ok(response) => {
+                        let content_type = response
+                            .headers()
+                            .get(CONTENT_TYPE)
+                            .and_then(|v| v.to_str().ok());
+                        match provider {
+                            Some(provider) if is_event_stream(content_type) => {
+                                let event = event(AuditEventType::Request);
+                                let mut answer =
+                                    self.relay_stream(&request, provider, response, started, event);
+                                let headers = answer_headers(answer.status());
+                                append_headers(&mut answer, &headers);
+                                return answer;
+                            }
+                            _ => self.read_upstream(response).await,
+                        }
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        let (status, headers, mut response_body) = match upstream {
+            Ok(response) => response,
+            Err(e) => {
+                let reason = format!("{:#}", e);
+                let (headers, body, event) = self.upstream_error_response(&request, &reason, None);
+                self.record_audit(&event);
+                return json_response(502, &headers, &body);
+            }
+        };
+        let response =
+            self.buffered_response(&request, provider, status.as_u16(), started, &response_body);
+
+        // Response-phase policies see the completion text
+        if let (Some(provider), Some(engine)) = (provider, &engine) {
+            let decision = self
+                .response_policy_input(&input, &response, provider, &response_body)
+                .and_then(|input| match engine.evaluate_json(&input) {
+                    Ok(decision) => Some(decision),
+                    Err(e) => {
+                        tracing::warn!("Response policy evaluation failed: {:#}", e);
+                        None
+                    }
+                });
+            if let Some(decision) = decision {
+                match self.inspect_response(provider, &response_body, &decision, client.mode) {
+                    ResponseVerdict::Pass => {}
+                    ResponseVerdict::Redacted { body, .. } => response_body = Bytes::from(body),
+                    ResponseVerdict::Blocked { reason } => {
+                        let policy = decision["policy"].as_str().unwrap_or("default");
+                        let event =
+                            AuditEvent::response_blocked(&request, &response, policy, &reason);
+                        self.record_audit(&event);
+                        let headers =
+                            [("Content-Type".to_string(), "application/json".to_string())];
+                        let body = response_blocked_body(&request.endpoint, &reason);
+                        return json_response(403, &headers, &body);
+                    }
+                }
+            }
+        }
+        let audited = event(AuditEventType::Request).with_response(&response);
+        self.record_audit(&audited);
+        let completion =
+            provider.and_then(|p| crate::providers::parse_completion(p, &response_body));
+        self.record_transcript(&request, &audited, completion.as_deref());
+        if !from_cache && (self.retries.serves_cached() || self.prompt_cache.is_some()) {
+            let cached = cached_response(status, &headers, &response_body);
+            if routed.is_none() {
+                self.cache_completion(&request, &cached);
+            }
+            if request.retry_of.is_none() {
+                self.remember_response(&request, &body, cached);
+            }
+        }
+
+        let response_body = match advisory {
+            true => self
+                .annotate_advisory(&request, &response_body, &policy, &reason)
+                .map_or(response_body, Bytes::from),
+            false => response_body,
+        };
+        let mut answer = Response::new(full_body(response_body));
+        *answer.status_mut() = status;
+        *answer.headers_mut() = headers;
+        append_headers(&mut answer, &answer_headers(status));
+        answer
     }
-}
 
-/// Request context for policy evaluation and auditing
-#[derive(Debug, Clone)]
-pub struct RequestContext {
-    /// Client IP address
-    pub client_ip: String,
+    /// Relay a streamed (SSE) upstream response chunk by chunk, auditing
+    /// `event` with the response details once the stream ends
+    fn relay_stream(
+        self: &Arc<Self>,
+        request: &RequestContext,
+        provider: Provider,
+        response: Response<Incoming>,
+        started: Instant,
+        event: AuditEvent,
+    ) -> Response<ProxyBody> {
+        let (head, body) = response.into_parts();
+        let server = Arc::clone(self);
+        let body = self.stream_response(
+            request.clone(),
+            provider,
+            head.status.as_u16(),
+            started,
+            body,
+            move |request, response| {
+                let event = event.with_response(response);
+                server.record_audit(&event);
+                server.record_transcript(request, &event, None);
+            },
+        );
+        let mut answer = Response::new(body.map_err(Into::into).boxed_unsync());
+        *answer.status_mut() = head.status;
+        *answer.headers_mut() = head.headers;
+        answer
+    }
 
-    /// Target endpoint (e.g., "api.openai.com")
-    pub endpoint: String,
+    /// Send a request to its upstream, retrying idempotent requests per
+    /// `upstream_retry`
+    ///
+    /// Every attempt's outcome is reported to the endpoint's circuit
+    /// breaker. The response body is left for the caller to read or stream.
+    async fn forward(
+        &self,
+        request: &RequestContext,
+        parts: &Parts,
+        body: &Bytes,
+    ) -> Result<Response<Incoming>> {
+        self.upstream_retry()
+            .run(
+                &request.method,
+                move |_| async move {
+                    let forwarded = Request::from_parts(parts.clone(), Full::new(body.clone()));
+                    let outcome = self.send_upstream(request, forwarded).await;
+                    let status = outcome.as_ref().ok().map(|r| r.status().as_u16());
+                    self.record_upstream(request, status);
+                    outcome
+                },
+                |outcome| match outcome {
+                    Ok(response) => is_upstream_failure(response.status().as_u16()),
+                    Err(_) => true,
+                },
+            )
+            .await
+    }
 
-    /// HTTP method
-    pub method: String,
+    /// Send a routed request to its local model server and translate the
+    /// answer for the client
+    async fn forward_local(
+        &self,
+        request: &RequestContext,
+        routed: &RoutedRequest,
+    ) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let tcp = TcpStream::connect(&routed.authority)
+            .await
+            .with_context(|| format!("failed to connect to {}", routed.authority))?;
+        let mut sender = UpstreamSender::handshake(tcp, HttpVersion::Http1).await?;
+        let mut builder = Request::builder()
+            .method(request.method.as_str())
+            .uri(&routed.path)
+            .header(hyper::header::HOST, &routed.authority);
+        for (name, value) in &routed.headers {
+            builder = builder.header(name, value);
+        }
+        let forwarded = builder.body(Full::new(Bytes::from(routed.body.clone())))?;
+        let (parts, body) = sender.send_request(forwarded).await?.into_parts();
+        let body = collect_limited(
+            body,
+            BodyKind::Response,
+            self.body_limit(BodyKind::Response),
+        )
+        .await
+        .map_err(|e| anyhow!("failed to read the local answer: {}", e))?;
 
-    /// Request path
-    pub path: String,
+        let mut headers = HeaderMap::new();
+        let body = if parts.status.is_success() {
+            let (content_type, body) = routed.translate_response(&body)?;
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            Bytes::from(body)
+        } else {
+            // Errors go back as the backend sent them
+            if let Some(content_type) = parts.headers.get(CONTENT_TYPE) {
+                headers.insert(CONTENT_TYPE, content_type.clone());
+            }
+            body
+        };
+        headers.insert(ROUTED_HEADER, HeaderValue::from_str(&routed.backend)?);
+        Ok((parts.status, headers, body))
+    }
 
-    /// User agent
-    pub user_agent: Option<String>,
+    /// Send one attempt of a request to its upstream
+    ///
+    /// The upstream is the intercepted host, on the port the client dialed
+    /// (443 when that isn't known).
+    async fn send_upstream(
+        &self,
+        request: &RequestContext,
+        mut forwarded: Request<Full<Bytes>>,
+    ) -> Result<Response<Incoming>> {
+        let port = request.destination.map_or(PASSTHROUGH_PORT, |d| d.port());
+        let mut upstream =
+            connect_upstream(&self.upstream_connector()?, &request.endpoint, port).await?;
+        prepare_upstream_request(&mut forwarded, upstream.version(), &request.endpoint)?;
+        self.inject_credentials(request, forwarded.headers_mut())
+            .context("failed to apply the stored API key")?;
+        // Framed from the body, which may have been rewritten
+        forwarded.headers_mut().remove(CONTENT_LENGTH);
+        let mut response = upstream.send_request(forwarded).await?;
+        strip_hop_headers(response.headers_mut());
+        // The body is re-framed for the client connection
+        response.headers_mut().remove(CONTENT_LENGTH);
+        Ok(response)
+    }
 
-    /// Prompt preview (first 200 chars, if applicable)
-    pub prompt_preview: Option<String>,
+    /// Read a whole upstream response, up to `max_response_bytes`
+    async fn read_upstream(
+        &self,
+        response: Response<Incoming>,
+    ) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let (parts, body) = response.into_parts();
+        let body = collect_limited(
+            body,
+            BodyKind::Response,
+            self.body_limit(BodyKind::Response),
+        )
+        .await
+        .map_err(|e| anyhow!("failed to read the response: {}", e))?;
+        Ok((parts.status, parts.headers, body))
+    }
 
-    /// Request timestamp
-    pub timestamp: chrono::DateTime<chrono::Utc>,
-}
+    /// TLS connector for upstream providers, trusting the system CA bundle
+    /// (loaded on first use)
+    fn upstream_connector(&self) -> Result<TlsConnector> {
+        let mut upstream = self.upstream_tls.lock().unwrap();
+        if let Some(connector) = upstream.as_ref() {
+            return Ok(connector.clone());
+        }
+        let roots = load_roots(None)?;
+        let connector = TlsConnector::from(upstream_tls_config(roots, self.config.http2));
+        *upstream = Some(connector.clone());
+        Ok(connector)
+    }
 
-/// Response context for auditing
-#[derive(Debug, Clone)]
-pub struct ResponseContext {
-    /// HTTP status code
-    pub status: u16,
+    /// Gracefully shutdown the proxy server
+    ///
+    /// Stops accepting connections (a running [`Self::start`] returns), gives
+    /// in-flight requests up to `drain_timeout_secs` to finish, aborts the
+    /// rest, then flushes quota counters, buffered audit events (to the
+    /// spool file if the database still refuses them) and the audit
+    /// database.
+    pub async fn shutdown(&self) -> Result<DrainReport> {
+        tracing::info!(
+            "YORI proxy server shutting down ({} connections in flight)",
+            self.connections.active()
+        );
+        self.shutdown.lock().unwrap().cancel();
 
-    /// Response duration in milliseconds
-    pub duration_ms: u64,
+        let report = self
+            .connections
+            .drain(std::time::Duration::from_secs(
+                self.config.drain_timeout_secs,
+            ))
+            .await;
+        if report.dropped > 0 {
+            tracing::warn!(
+                "Dropped {} connections still open after {}s",
+                report.dropped,
+                self.config.drain_timeout_secs
+            );
+        }
 
-    /// Estimated token count (if applicable)
-    pub tokens: Option<usize>,
-}
+        if let Err(e) = self.quotas.flush() {
+            tracing::warn!("Failed to persist quota counters: {:#}", e);
+        }
+        if let Err(e) = self.budgets.flush() {
+            tracing::warn!("Failed to persist budget totals: {:#}", e);
+        }
+        let audit = self.audit_logger();
+        if let Err(e) = self.audit_spool.flush(audit.as_deref()) {
+            tracing::warn!("Failed to write buffered audit events: {:#}", e);
+        }
+        if let Some(audit) = audit {
+            if let Err(e) = audit.flush() {
+                tracing::warn!("Failed to flush audit database: {:#}", e);
+            }
+        }
+        Ok(report)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Register a newly accepted connection
+    ///
+    /// Returns None once shutdown has begun, in which case the connection
+    /// should be closed straight away.
+    pub fn accept_connection(&self) -> Option<ConnectionGuard> {
+        if self.shutdown.lock().unwrap().is_cancelled() {
+            return None;
+        }
+        Some(self.connections.track())
+    }
 
-    #[test]
-    fn test_proxy_config_default() {
-        let config = ProxyConfig::default();
-        assert_eq!(config.mode, ProxyMode::Observe);
-        assert!(config.endpoints.contains(&"api.openai.com".to_string()));
+    /// Connections currently being served
+    pub fn active_connections(&self) -> usize {
+        self.connections.active()
     }
 
-    #[test]
-    fn test_should_intercept() {
-        let config = ProxyConfig::default();
-        let server = ProxyServer::new(config);
+    /// Wait for a connection slot for `client`, queuing behind other
+    /// clients' connections when the proxy is at `max_connections`
+    ///
+    /// Refused connections should be closed straight away; the slot is
+    /// released when the permit is dropped.
+    pub async fn admit_connection(
+        &self,
+        client: IpAddr,
+    ) -> Result<ConnectionPermit, ConnectionRefusal> {
+        let admitted = self.connection_limits.acquire(client.to_canonical()).await;
+        if let Err(refusal) = &admitted {
+            tracing::debug!("Connection from {} refused: {}", client, refusal.as_str());
+        }
+        admitted
+    }
 
-        assert!(server.should_intercept("api.openai.com"));
-        assert!(server.should_intercept("api.anthropic.com"));
-        assert!(!server.should_intercept("example.com"));
+    /// Connection slots in use, queued connections and refusals so far
+    pub fn connection_limit_stats(&self) -> ConnectionLimitStats {
+        self.connection_limits.stats()
+    }
+
+    /// Audit logger flushed on shutdown
+    pub fn set_audit_logger(&self, audit: Arc<AuditLogger>) {
+        *self.audit.write().unwrap() = Some(audit);
+    }
+
+    /// Audit logger, if one is attached
+    pub fn audit_logger(&self) -> Option<Arc<AuditLogger>> {
+        self.audit.read().unwrap().clone()
+    }
+
+    /// Log an audit event, buffering it while the database is locked or
+    /// full; returns the row id once written
+    pub fn record_audit(&self, event: &AuditEvent) -> Option<i64> {
+        let audit = self.audit_logger()?;
+        match self.audit_spool.record(&audit, event) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Failed to log audit event: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Buffer holding audit events while the database is unavailable
+    pub fn audit_spool(&self) -> Arc<AuditSpool> {
+        Arc::clone(&self.audit_spool)
+    }
+
+    /// Transcript store full conversations are recorded to, for the
+    /// tenants that opted in
+    pub fn set_transcript_store(&self, transcripts: Option<Arc<TranscriptStore>>) {
+        *self.transcripts.write().unwrap() = transcripts;
+    }
+
+    /// Transcript store, if one is attached
+    pub fn transcript_store(&self) -> Option<Arc<TranscriptStore>> {
+        self.transcripts.read().unwrap().clone()
+    }
+
+    /// Record the full prompt and completion of an audited request, if its
+    /// tenant opted in to transcripts
+    ///
+    /// Requests without a session or a parsed body are skipped; failures
+    /// are logged rather than failing the request.
+    pub fn record_transcript(
+        &self,
+        request: &RequestContext,
+        event: &AuditEvent,
+        completion: Option<&str>,
+    ) {
+        let Some(store) = self.transcript_store() else {
+            return;
+        };
+        let (Some(session_id), Some(parsed)) = (&request.session_id, &request.parsed) else {
+            return;
+        };
+        if !store.records(&request.tenant) {
+            return;
+        }
+        let turn = TranscriptTurn {
+            session_id: session_id.clone(),
+            request_id: event.request_id.clone(),
+            timestamp: request.timestamp,
+            tenant: request.tenant.clone(),
+            client_ip: request.client_ip.clone(),
+            endpoint: request.endpoint.clone(),
+            model: event
+                .response_model
+                .clone()
+                .or_else(|| parsed.model.clone()),
+            prompt: parsed.prompt.clone(),
+            completion: completion.map(str::to_string),
+        };
+        if let Err(e) = store.record(&turn) {
+            tracing::warn!("Failed to record transcript turn: {:#}", e);
+        }
+    }
+
+    /// Policy engine requests are evaluated against
+    pub fn set_policy_engine(&self, engine: Arc<PolicyEngine>) {
+        *self.policies.write().unwrap() = Some(engine);
+    }
+
+    /// Policy engine, if one is attached
+    pub fn policy_engine(&self) -> Option<Arc<PolicyEngine>> {
+        self.policies.read().unwrap().clone()
+    }
+
+    /// Fill the policy engine's decision caches from the last
+    /// `warm_decisions` audited requests, so the first requests after a
+    /// restart don't all run the policies in full
+    ///
+    /// Returns None without a policy engine or audit logger, or with
+    /// warming turned off.
+    pub fn warm_from_audit(&self) -> Result<Option<WarmReport>> {
+        let (Some(engine), Some(audit)) = (self.policy_engine(), self.audit_logger()) else {
+            return Ok(None);
+        };
+        if self.config.warm_decisions == 0 {
+            return Ok(None);
+        }
+        let report = PolicyReplay::with_enrichment(&engine, &self.config.enrichment).warm(
+            &audit,
+            self.config.warm_decisions,
+            chrono::Utc::now(),
+        )?;
+        tracing::info!(
+            "Warmed policy decisions from {} audited requests in {}ms ({} shortcuts)",
+            report.evaluated,
+            report.duration_ms,
+            report.shortcuts
+        );
+        Ok(Some(report))
+    }
+
+    /// Provider API keys injected into forwarded requests
+    pub fn set_api_key_store(&self, store: Arc<ApiKeyStore>) {
+        *self.api_keys.write().unwrap() = Some(store);
+    }
+
+    /// API key store, if one is attached
+    pub fn api_key_store(&self) -> Option<Arc<ApiKeyStore>> {
+        self.api_keys.read().unwrap().clone()
+    }
+
+    /// Put the stored API key for the request's provider on its upstream
+    /// headers, replacing the client's (see [`crate::secrets`])
+    ///
+    /// Returns whether a stored key was injected. Without a store, only
+    /// `strip_client_keys` applies.
+    pub fn inject_credentials(
+        &self,
+        request: &RequestContext,
+        headers: &mut hyper::HeaderMap,
+    ) -> Result<bool> {
+        let Some(provider) = self.config.providers.provider_for_host(&request.endpoint) else {
+            return Ok(false);
+        };
+        match self.api_key_store() {
+            Some(store) => store.apply(provider, headers, self.config.strip_client_keys),
+            None => {
+                if self.config.strip_client_keys && !provider.signed_requests() {
+                    for name in crate::secrets::CLIENT_CREDENTIAL_HEADERS {
+                        headers.remove(name);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Address a transparently redirected connection was headed for,
+    /// looked up as `destination_lookup` says
+    ///
+    /// None when lookups are off, for connections made to the listener
+    /// directly, and when the lookup fails (logged; SNI is used instead).
+    pub fn original_destination(&self, stream: &tokio::net::TcpStream) -> Option<SocketAddr> {
+        original_destination(
+            stream,
+            self.config.destination_lookup,
+            self.config.listen_addr,
+        )
+        .unwrap_or_else(|e| {
+            tracing::warn!("Original destination unknown, using SNI: {:#}", e);
+            None
+        })
+    }
+
+    /// Decide from the ClientHello's SNI whether to intercept a transparently
+    /// redirected connection
+    ///
+    /// Only configured LLM endpoints are TLS-terminated; every other host is
+    /// relayed byte for byte to the address the client dialed (see
+    /// [`Self::original_destination`]), or port 443 of the named server when
+    /// that isn't known. Connections with neither SNI nor a known
+    /// destination are refused.
+    pub async fn route_tls(&self, stream: tokio::net::TcpStream) -> Result<TlsRoute> {
+        let destination = self.original_destination(&stream);
+        let host = match (peek_sni(&stream).await?, destination) {
+            (Some(host), _) => host,
+            (None, Some(destination)) => destination.ip().to_string(),
+            (None, None) => bail!("ClientHello without SNI; destination unknown"),
+        };
+        if self.should_intercept(&host) {
+            return Ok(TlsRoute::Intercept {
+                host,
+                destination,
+                stream,
+            });
+        }
+        let upstream = match destination {
+            Some(destination) => destination.to_string(),
+            None => format!("{}:{}", host, PASSTHROUGH_PORT),
+        };
+        tracing::debug!("Passing through TLS for {} to {}", host, upstream);
+        let bytes = passthrough(stream, &upstream).await?;
+        Ok(TlsRoute::PassedThrough {
+            host,
+            destination,
+            bytes,
+        })
+    }
+
+    /// Answer a CONNECT request on a newly accepted connection
+    ///
+    /// Configured LLM endpoints are TLS-terminated (see [`Self::tls_config`]);
+    /// any other host is tunnelled untouched. With proxy authentication
+    /// configured, the outcome names the user the client authenticated as
+    /// (see [`Self::authenticated_identity`]). Fails if the proxy isn't in
+    /// explicit or both mode.
+    pub async fn handle_connect(&self, stream: tokio::net::TcpStream) -> Result<ConnectOutcome> {
+        if !self.config.interception.accepts_connect() {
+            bail!(
+                "CONNECT requests are not accepted in {} interception mode",
+                self.config.interception.as_str()
+            );
+        }
+        let tls = self.tls_config()?;
+        accept_connect(
+            stream,
+            tls,
+            |host| self.should_intercept(host),
+            self.auth.as_ref(),
+        )
+        .await
+    }
+
+    /// TLS configuration for intercepted connections, loaded on first use
+    ///
+    /// With a CA configured, a certificate is minted (and cached) for each
+    /// host from the ClientHello's SNI, so one listener serves every
+    /// provider. Otherwise the static `tls_cert_path` certificate is used.
+    /// ALPN offers `h2` first unless `http2` is off, and clients are asked
+    /// for a certificate when `auth.client_ca` is set.
+    pub fn tls_config(&self) -> Result<Arc<rustls::ServerConfig>> {
+        let mut tls = self.tls.lock().unwrap();
+        if let Some(tls) = tls.as_ref() {
+            return Ok(Arc::clone(tls));
+        }
+        let client_roots = match &self.auth {
+            Some(auth) => auth.client_roots()?,
+            None => None,
+        };
+        let loaded = match (&self.config.ca_cert_path, &self.config.ca_key_path) {
+            (Some(cert), Some(key)) => ca_server_config(
+                Arc::new(CertAuthority::load(
+                    std::path::Path::new(cert),
+                    std::path::Path::new(key),
+                )?),
+                self.config.http2,
+                client_roots,
+            ),
+            (None, None) => static_server_config(
+                std::path::Path::new(&self.config.tls_cert_path),
+                std::path::Path::new(&self.config.tls_key_path),
+                self.config.http2,
+                client_roots,
+            )?,
+            _ => bail!("ca_cert_path and ca_key_path must be set together"),
+        };
+        *tls = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+
+    /// Classify a newly accepted connection by its source network
+    ///
+    /// Runs before TLS termination so blocked segments (e.g., an IoT VLAN)
+    /// cost nothing beyond the accept.
+    pub fn classify_connection(&self, peer: SocketAddr, interface: Option<&str>) -> ScopeDecision {
+        self.scopes.classify(peer.ip(), interface, self.mode())
+    }
+
+    /// Replace the WireGuard peer table used to name tunnel clients
+    pub fn set_wireguard_peers(&self, peers: WireGuardPeers) {
+        *self.wireguard.write().unwrap() = peers;
+    }
+
+    /// Replace the DHCP/ARP/MAC mapping used to identify LAN clients
+    pub fn set_device_registry(&self, devices: DeviceRegistry) {
+        *self.devices.write().unwrap() = devices;
+    }
+
+    /// Identify the device behind a client address
+    ///
+    /// Clients arriving over the family VPN are identified by their
+    /// WireGuard peer; LAN clients by DHCP lease or ARP entry and the MAC
+    /// mapping. Devices none of those name are asked over mDNS/NetBIOS in
+    /// the background, so their name appears from the next request on.
+    pub fn client_identity(&self, ip: IpAddr) -> Option<DeviceIdentity> {
+        let ip = ip.to_canonical();
+        if let Some(name) = self.wireguard.read().unwrap().name_for_ip(ip) {
+            return Some(DeviceIdentity {
+                mac: None,
+                name: Some(name.to_string()),
+                hostname: None,
+                owner: None,
+                group: None,
+                known: true,
+                source: IdentitySource::WireGuard,
+            });
+        }
+        let identity = self.devices.read().unwrap().identify(ip);
+        if identity
+            .as_ref()
+            .is_some_and(|device| device.name.is_some())
+        {
+            return identity;
+        }
+        let Some(names) = &self.names else {
+            return identity;
+        };
+        match names.cached(ip) {
+            Some(Some(found)) => Some(match identity {
+                Some(device) => DeviceIdentity {
+                    name: Some(found.name),
+                    ..device
+                },
+                None => DeviceIdentity {
+                    mac: None,
+                    name: Some(found.name),
+                    hostname: None,
+                    owner: None,
+                    group: None,
+                    known: false,
+                    source: found.source,
+                },
+            }),
+            Some(None) => identity,
+            None => {
+                names.spawn_lookup(ip);
+                identity
+            }
+        }
+    }
+
+    /// mDNS/NetBIOS name cache (None if `discover_names` is off)
+    pub fn name_discovery(&self) -> Option<Arc<NameDiscovery>> {
+        self.names.clone()
+    }
+
+    /// Identity of a client that authenticated to the proxy as `user`
+    ///
+    /// The user (and their configured group) replace whatever owner and
+    /// group the device has, since the credentials say who is asking; the
+    /// device's name and MAC are kept.
+    pub fn authenticated_identity(&self, ip: IpAddr, user: &str) -> DeviceIdentity {
+        let device = self.client_identity(ip);
+        DeviceIdentity {
+            mac: device.as_ref().and_then(|d| d.mac.clone()),
+            name: device.as_ref().and_then(|d| d.name.clone()),
+            hostname: device.and_then(|d| d.hostname),
+            owner: Some(user.to_string()),
+            group: self
+                .auth
+                .as_ref()
+                .and_then(|auth| auth.group(user))
+                .map(str::to_string),
+            known: true,
+            source: IdentitySource::ProxyAuth,
+        }
+    }
+
+    /// Resolve a human-readable device name for a client address
+    pub fn client_device(&self, ip: IpAddr) -> Option<String> {
+        self.client_identity(ip).and_then(|device| device.name)
+    }
+
+    /// Build the enriched policy input document for a request
+    pub fn policy_input(&self, request: &RequestContext) -> serde_json::Value {
+        self.enrichment.build_input(request)
+    }
+
+    /// Canary responder shared with the bypass-detection probe
+    pub fn canary_responder(&self) -> Arc<CanaryResponder> {
+        Arc::clone(&self.canary)
+    }
+
+    /// Header to answer a canary request with, if `path` is one
+    ///
+    /// Canary requests are answered by the proxy itself (204 plus this
+    /// header) and never forwarded upstream or audited.
+    pub fn canary_response(&self, path: &str) -> Option<(&'static str, String)> {
+        self.canary
+            .canary_nonce(path)
+            .map(|nonce| (CANARY_HEADER, self.canary.marker(nonce)))
+    }
+
+    /// Synthetic response for a blocked request, if its policy obligations
+    /// ask for one and honeypot mode is enabled
+    pub fn honeypot_response(
+        &self,
+        request: &RequestContext,
+        obligations: &serde_json::Value,
+        reason: &str,
+        stream: bool,
+    ) -> Option<SyntheticResponse> {
+        let category = self.honeypot.category(obligations)?;
+        Some(self.honeypot.render(category, request, reason, stream))
+    }
+
+    /// 403 answer for a request blocked by policy, shaped for the client
+    /// (see [`crate::blockpage`])
+    ///
+    /// `accept` is the request's `Accept` header; `event` is the block's
+    /// audit event, whose policy name and reason fill the message.
+    pub fn block_response(&self, event: &AuditEvent, accept: Option<&str>) -> SyntheticResponse {
+        let provider = self.config.providers.provider_for_host(&event.endpoint);
+        self.block_page.render(event, provider, accept)
+    }
+
+    /// Warning headers for a response advisory mode forwarded although
+    /// `policy` would have denied it (see [`crate::advisory`])
+    pub fn advisory_headers(&self, policy: &str, reason: &str) -> Vec<(String, String)> {
+        self.advisory.headers(policy, reason)
+    }
+
+    /// Non-streaming response body with the advisory warning prepended to
+    /// its completion text, if notices are on and the body has any
+    pub fn annotate_advisory(
+        &self,
+        request: &RequestContext,
+        body: &[u8],
+        policy: &str,
+        reason: &str,
+    ) -> Option<Vec<u8>> {
+        let provider = self.config.providers.provider_for_host(&request.endpoint)?;
+        self.advisory.annotate(provider, body, policy, reason)
+    }
+
+    /// Local model request for a request that a decision's obligations
+    /// or a model route send to a local server
+    ///
+    /// None when the request stays with its provider. An error means it
+    /// should be answered locally but its API can't be translated; such
+    /// requests are blocked rather than forwarded to the cloud.
+    pub fn local_route(
+        &self,
+        request: &RequestContext,
+        obligations: &serde_json::Value,
+        body: &[u8],
+    ) -> Option<Result<RoutedRequest>> {
+        let model = request.parsed.as_ref().and_then(|p| p.model.as_deref());
+        let backend = match self.router.backend_for(model, obligations) {
+            Ok(backend) => backend?,
+            Err(e) => return Some(Err(e)),
+        };
+        tracing::debug!(
+            "Routing {} {} to local backend {}",
+            request.client_ip,
+            request.endpoint,
+            backend.name
+        );
+        let Some(provider) = self.config.providers.provider_for_host(&request.endpoint) else {
+            return Some(Err(anyhow!("unknown provider {}", request.endpoint)));
+        };
+        Some(self.router.rewrite(backend, provider, &request.path, body))
+    }
+
+    /// Policy input for the response phase of a request
+    ///
+    /// None when response inspection is off or the body carries no
+    /// completion text (errors, tool-only answers, non-JSON bodies).
+    pub fn response_policy_input(
+        &self,
+        request_input: &serde_json::Value,
+        response: &ResponseContext,
+        provider: Provider,
+        body: &[u8],
+    ) -> Option<serde_json::Value> {
+        if !self.config.inspect_responses {
+            return None;
+        }
+        let text = crate::providers::parse_completion(provider, body)?;
+        Some(response_input(request_input, response, &text))
+    }
+
+    /// Apply a response-phase decision under the connection's `mode`
+    ///
+    /// An invalid redaction pattern in the decision is logged and the
+    /// response forwarded unchanged.
+    pub fn inspect_response(
+        &self,
+        provider: Provider,
+        body: &[u8],
+        decision: &serde_json::Value,
+        mode: ProxyMode,
+    ) -> ResponseVerdict {
+        apply_response_decision(provider, body, decision, mode).unwrap_or_else(|e| {
+            tracing::warn!("Response policy could not be applied: {:#}", e);
+            ResponseVerdict::Pass
+        })
+    }
+
+    /// Count a request against its device's rate limit for the endpoint
+    ///
+    /// Returns None when no rate limit is configured. Requests that are not
+    /// allowed should be answered with [`Self::rate_limited_response`].
+    pub fn check_rate_limit(&self, request: &RequestContext) -> Option<RateDecision> {
+        self.rate_limiter
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|limiter| limiter.check(&request.client_ip, &request.endpoint, request.timestamp))
+    }
+
+    /// 429 answer for a rate-limited request: headers (including
+    /// `Retry-After`), JSON body and the audit event to log
+    pub fn rate_limited_response(
+        &self,
+        request: &RequestContext,
+        decision: &RateDecision,
+    ) -> (Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        let mut headers = self.allowance_headers(request, Some(decision), 429);
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        (
+            headers,
+            rate_limited_body(&request.endpoint, decision),
+            AuditEvent::rate_limited(request, decision),
+        )
+    }
+
+    /// Check the requested model against the model list of the device
+    /// (by name) or its owner (see [`crate::models`])
+    pub fn check_model(&self, request: &RequestContext) -> ModelDecision {
+        let Some(model) = request.parsed.as_ref().and_then(|p| p.model.as_deref()) else {
+            return ModelDecision::Allow;
+        };
+        let owner = self.quota.subject(request);
+        let mut subjects = Vec::with_capacity(2);
+        if let Some(device) = request.client_device.as_deref() {
+            subjects.push(device);
+        }
+        subjects.push(owner.as_str());
+        self.models.check(&subjects, model)
+    }
+
+    /// Answer for a request refused for its model: headers, JSON body and
+    /// the audit event to log (the client gets a 403)
+    pub fn model_blocked_response(
+        &self,
+        request: &RequestContext,
+        model: &str,
+        reason: &str,
+    ) -> (Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        (
+            vec![("Content-Type".to_string(), "application/json".to_string())],
+            model_blocked_body(model, reason),
+            AuditEvent::model_blocked(request, model, reason),
+        )
+    }
+
+    /// Scan an outbound request body for secrets (see [`crate::dlp`])
+    ///
+    /// Returns None when no rule matched. Bedrock requests are signed, so
+    /// a redact rule matching one blocks it instead.
+    pub fn scan_request_body(&self, request: &RequestContext, body: &[u8]) -> Option<DlpScan> {
+        let rewritable = self
+            .config
+            .providers
+            .provider_for_host(&request.endpoint)
+            .is_none_or(|p| !p.signed_requests());
+        self.dlp.scan(body, rewritable)
+    }
+
+    /// Answer for a request blocked by a DLP rule: headers, JSON body and
+    /// the audit event to log (the client gets a 403)
+    pub fn dlp_blocked_response(
+        &self,
+        request: &RequestContext,
+        scan: &DlpScan,
+    ) -> (Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        (
+            vec![("Content-Type".to_string(), "application/json".to_string())],
+            dlp_blocked_body(scan),
+            AuditEvent::dlp_blocked(request, scan),
+        )
+    }
+
+    /// Size limit for a request or response body (see [`crate::limits`])
+    pub fn body_limit(&self, kind: BodyKind) -> Option<u64> {
+        match kind {
+            BodyKind::Request => self.config.max_request_bytes,
+            BodyKind::Response => self.config.max_response_bytes,
+        }
+    }
+
+    /// Answer for an oversized body: status, headers, JSON body and the
+    /// audit event to log (413 for requests, 502 for upstream responses)
+    pub fn body_too_large_response(
+        &self,
+        request: &RequestContext,
+        error: &BodyTooLarge,
+    ) -> (u16, Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        let event = AuditEvent::body_too_large(request, error);
+        let body = match error.kind {
+            BodyKind::Request => payload_too_large_body(error),
+            BodyKind::Response => {
+                upstream_error_body(&request.endpoint, "response too large", None)
+            }
+        };
+        (
+            event.response_status.unwrap_or(413),
+            vec![("Content-Type".to_string(), "application/json".to_string())],
+            body,
+            event,
+        )
+    }
+
+    /// Check the endpoint's circuit breaker before forwarding
+    ///
+    /// Returns the seconds until the endpoint is tried again when its
+    /// circuit is open; answer with [`Self::upstream_error_response`].
+    pub fn check_upstream(&self, request: &RequestContext) -> Result<(), u64> {
+        self.breaker.check(&request.endpoint, Instant::now())
+    }
+
+    /// Report an upstream outcome (a status, or None when the connection
+    /// failed) to the endpoint's circuit breaker
+    pub fn record_upstream(&self, request: &RequestContext, status: Option<u16>) {
+        match status {
+            Some(status) if !is_upstream_failure(status) => {
+                self.breaker.record_success(&request.endpoint)
+            }
+            _ => {
+                if self
+                    .breaker
+                    .record_failure(&request.endpoint, Instant::now())
+                {
+                    tracing::warn!(
+                        "Circuit opened for {} after repeated upstream errors",
+                        request.endpoint
+                    );
+                }
+            }
+        }
+    }
+
+    /// 502 answer for a failed upstream or an open circuit: headers, JSON
+    /// body and the audit event to log
+    pub fn upstream_error_response(
+        &self,
+        request: &RequestContext,
+        reason: &str,
+        retry_after_secs: Option<u64>,
+    ) -> (Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        if let Some(secs) = retry_after_secs {
+            headers.push(("Retry-After".to_string(), secs.to_string()));
+        }
+        (
+            headers,
+            upstream_error_body(&request.endpoint, reason, retry_after_secs),
+            AuditEvent::upstream_error(request, reason),
+        )
+    }
+
+    /// Intercepted hosts that name one server (no `*` patterns), for
+    /// reachability checks
+    pub fn upstream_hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = self
+            .endpoints
+            .read()
+            .unwrap()
+            .iter()
+            .chain(
+                self.config
+                    .providers
+                    .added()
+                    .iter()
+                    .map(|(pattern, _)| pattern),
+            )
+            .filter(|host| !host.contains('*'))
+            .cloned()
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Endpoints whose circuit is open or probing
+    pub fn tripped_circuits(&self) -> Vec<(String, CircuitState)> {
+        self.breaker.tripped()
+    }
+
+    /// Retry schedule for idempotent upstream requests
+    pub fn upstream_retry(&self) -> &RetryPolicy {
+        &self.config.upstream_retry
+    }
+
+    /// Daily/weekly usage and limits of the request's user or device
+    ///
+    /// [`QuotaUsage::exceeded`] names the limit that has been reached, if any.
+    pub fn check_quota(&self, request: &RequestContext) -> QuotaUsage {
+        self.quota.usage(request)
+    }
+
+    /// Spend of the request's user or device when the hard cap refuses it
+    ///
+    /// None when `budget.hard_cap` is off or the subject is under its cap;
+    /// the policies still see the spend as `input.budget` either way.
+    pub fn check_budget(&self, request: &RequestContext) -> Option<BudgetStatus> {
+        if !self.budgets.hard_cap() {
+            return None;
+        }
+        let subject = self.quota.subject(request);
+        let status = self
+            .budgets
+            .check(&request.tenant, &subject, request.timestamp);
+        status.exceeded().then_some(status)
+    }
+
+    /// Answer for a request refused by the hard budget cap: headers, JSON
+    /// body and the audit event to log (the client gets a 403)
+    pub fn budget_exceeded_response(
+        &self,
+        request: &RequestContext,
+        status: &BudgetStatus,
+    ) -> (Vec<(String, String)>, serde_json::Value, AuditEvent) {
+        let subject = self.quota.subject(request);
+        (
+            vec![("Content-Type".to_string(), "application/json".to_string())],
+            budget_exceeded_body(&subject, status),
+            AuditEvent::budget_exceeded(request, &subject, status),
+        )
+    }
+
+    /// Headers advertising the device's remaining allowance
+    ///
+    /// Added to every proxied response; `Retry-After` is included on 429s.
+    pub fn allowance_headers(
+        &self,
+        request: &RequestContext,
+        rate: Option<&RateDecision>,
+        status: u16,
+    ) -> Vec<(String, String)> {
+        let quota = self.quota.status(request).ok();
+        allowance_headers(
+            rate,
+            quota.and_then(|q| q.remaining_tokens()),
+            quota.map(|q| q.reset_secs),
+            status,
+        )
+    }
+
+    /// Check whether a request repeats one the same device sent moments ago
+    ///
+    /// On a retry, `request.retry_of` is set to the original request id so
+    /// the audit event is marked and usage isn't counted twice.
+    pub fn detect_retry(
+        &self,
+        request: &mut RequestContext,
+        request_id: &str,
+        body: &[u8],
+    ) -> RetryCheck {
+        let hash = request_hash(&request.method, &request.endpoint, &request.path, body);
+        let check = self
+            .retries
+            .check(&request.client_ip, hash, request_id, request.timestamp);
+        if let RetryCheck::Retry {
+            original_request_id,
+            attempt,
+            ..
+        } = &check
+        {
+            tracing::debug!(
+                "Request from {} is retry #{} of {}",
+                request.client_ip,
+                attempt,
+                original_request_id
+            );
+            request.retry_of = Some(original_request_id.clone());
+        }
+        check
+    }
+
+    /// Keep the response to an original request for answering its retries
+    /// (see [`Self::detect_retry`])
+    pub fn remember_response(
+        &self,
+        request: &RequestContext,
+        body: &[u8],
+        response: CachedResponse,
+    ) {
+        let hash = request_hash(&request.method, &request.endpoint, &request.path, body);
+        self.retries
+            .store_response(&request.client_ip, hash, response);
+    }
+
+    /// Place a request in its conversation session
+    ///
+    /// Requests are grouped per user (or device) and provider, and by the
+    /// body's conversation id when it has one; `request.session_id` is set
+    /// so every audit event of the request carries it.
+    pub fn assign_session(&self, request: &mut RequestContext) {
+        let device = request
+            .identity
+            .as_ref()
+            .and_then(|i| i.owner.clone())
+            .unwrap_or_else(|| request.client_ip.clone());
+        let provider = self
+            .config
+            .providers
+            .provider_for_host(&request.endpoint)
+            .map_or(request.endpoint.as_str(), |p| p.as_str());
+        let conversation = request
+            .parsed
+            .as_ref()
+            .and_then(|p| p.conversation_id.as_deref());
+        request.session_id =
+            Some(
+                self.sessions
+                    .assign(&device, provider, conversation, request.timestamp),
+            );
+    }
+
+    /// Tag a request with the content categories of its prompt
+    ///
+    /// Classifies the full parsed prompt when there is one, else the
+    /// preview; `request.categories` feeds `input.categories` and the
+    /// audit event.
+    pub fn classify_request(&self, request: &mut RequestContext) {
+        let text = match request.parsed.as_ref() {
+            Some(parsed) if !parsed.prompt.is_empty() => parsed.prompt.as_str(),
+            _ => request.prompt_preview.as_deref().unwrap_or_default(),
+        };
+        request.categories = self.classifier.classify(text);
+    }
+
+    /// Score prompts with a model alongside the category rules
+    /// (None = rules only)
+    pub fn set_category_model(&self, model: Option<Arc<dyn CategoryModel>>) {
+        self.classifier.set_model(model);
+    }
+
+    /// Completion cached for a repeated (or near-duplicate) prompt
+    pub fn cached_completion(&self, request: &RequestContext) -> Option<CachedResponse> {
+        let response = self
+            .prompt_cache
+            .as_ref()?
+            .lookup(request.parsed.as_ref()?)?;
+        tracing::debug!(
+            "Answering {} {} from the prompt cache",
+            request.client_ip,
+            request.endpoint
+        );
+        Some(response)
+    }
+
+    /// Keep an upstream completion for later identical prompts
+    pub fn cache_completion(&self, request: &RequestContext, response: &CachedResponse) {
+        if let (Some(cache), Some(parsed)) = (&self.prompt_cache, &request.parsed) {
+            cache.store(parsed, response);
+        }
+    }
+
+    /// Estimated cost of a buffered response in USD, priced by the served
+    /// model (else the requested one)
+    pub fn estimate_cost(
+        &self,
+        request: &RequestContext,
+        provider: Provider,
+        served_model: Option<&str>,
+        body: &[u8],
+    ) -> Option<f64> {
+        let usage = crate::providers::parse_usage(provider, body)?;
+        self.pricing
+            .estimate(served_model.or(requested_model(request)), &usage)
+    }
+
+    /// Response context for a buffered upstream response, recorded in the
+    /// latency, usage, quota and budget counters
+    ///
+    /// Tokens, cost and metadata are only parsed from known providers.
+    pub fn buffered_response(
+        &self,
+        request: &RequestContext,
+        provider: Option<Provider>,
+        status: u16,
+        started: Instant,
+        body: &[u8],
+    ) -> ResponseContext {
+        let mut response = ResponseContext {
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            tokens: None,
+            model: None,
+            finish_reason: None,
+            safety_flags: Vec::new(),
+            cost_usd: None,
+        };
+        if let Some(provider) = provider {
+            let usage = crate::providers::parse_usage(provider, body);
+            response = response.with_metadata(parse_response_metadata(provider, body));
+            response.tokens = usage.as_ref().map(|u| u.total_tokens() as usize);
+            response.cost_usd =
+                self.estimate_cost(request, provider, response.model.as_deref(), body);
+        }
+        self.record_response(request, &response);
+        response
+    }
+
+    /// Record the outcome of a forwarded request
+    fn record_response(&self, request: &RequestContext, response: &ResponseContext) {
+        let subject = self.quota.subject(request);
+        record_outcome(
+            &self.latency,
+            &self.usage,
+            &self.quotas,
+            &self.budgets,
+            &subject,
+            request,
+            response,
+        );
+    }
+
+    /// Forward a streamed (SSE) upstream response to the client as it arrives
+    ///
+    /// Chunks are passed through unmodified. When the stream ends (or the
+    /// client disconnects) its latency and token usage are recorded like a
+    /// buffered response, and the completed `ResponseContext` is handed to
+    /// `on_complete` for auditing.
+    ///
+    /// A stream running past `max_response_bytes` fails with
+    /// [`crate::limits::LimitError::TooLarge`], cutting the client off.
+    pub fn stream_response<B>(
+        &self,
+        request: RequestContext,
+        provider: Provider,
+        status: u16,
+        started: Instant,
+        body: B,
+        on_complete: impl FnOnce(&RequestContext, &ResponseContext) + Send + 'static,
+    ) -> StreamingBody<LimitedBody<B>> {
+        let latency = Arc::clone(&self.latency);
+        let usage = Arc::clone(&self.usage);
+        let quotas = Arc::clone(&self.quotas);
+        let pricing = Arc::clone(&self.pricing);
+        let budgets = Arc::clone(&self.budgets);
+        let subject = self.quota.subject(&request);
+        let body = LimitedBody::new(body, BodyKind::Response, self.config.max_response_bytes);
+        StreamingBody::new(body, provider, move |stream| {
+            let model = stream.metadata().model.as_deref();
+            let cost_usd = stream
+                .usage()
+                .and_then(|usage| pricing.estimate(model.or(requested_model(&request)), &usage));
+            let response = ResponseContext {
+                status,
+                duration_ms: started.elapsed().as_millis() as u64,
+                tokens: Some(stream.tokens()),
+                model: None,
+                finish_reason: None,
+                safety_flags: Vec::new(),
+                cost_usd,
+            }
+            .with_metadata(stream.metadata().clone());
+            record_outcome(
+                &latency, &usage, &quotas, &budgets, &subject, &request, &response,
+            );
+            on_complete(&request, &response);
+        })
+    }
+
+    /// Check if an endpoint should be intercepted
+    fn should_intercept(&self, host: &str) -> bool {
+        self.endpoints
+            .read()
+            .unwrap()
+            .iter()
+            .chain(
+                self.config
+                    .providers
+                    .added()
+                    .iter()
+                    .map(|(pattern, _)| pattern),
+            )
+            .any(|pattern| host_matches(pattern, host))
+    }
+}
+
+/// Record upstream latency and (for non-retries) token usage, quota and
+/// spend
+fn record_outcome(
+    latency: &LatencyTracker,
+    usage: &UsageSeries,
+    quotas: &QuotaManager,
+    budgets: &BudgetTracker,
+    subject: &str,
+    request: &RequestContext,
+    response: &ResponseContext,
+) {
+    latency.record_latency(&request.endpoint, response.duration_ms);
+    // Retries already counted with their original request
+    if request.retry_of.is_some() {
+        return;
+    }
+    usage.record_usage(
+        &request.tenant,
+        &request.client_ip,
+        response.tokens.unwrap_or(0) as u64,
+        false,
+        response.cost_usd.unwrap_or(0.0),
+    );
+    quotas.record(
+        &request.tenant,
+        subject,
+        response.tokens.unwrap_or(0) as u64,
+        request.timestamp,
+    );
+    budgets.record(
+        &request.tenant,
+        subject,
+        response.cost_usd.unwrap_or(0.0),
+        request.timestamp,
+    );
+}
+
+/// Characters of the prompt kept in [`RequestContext::prompt_preview`]
+const PROMPT_PREVIEW_CHARS: usize = 200;
+
+/// First byte of a TLS handshake record (a ClientHello)
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/// What the accept loop learned about an intercepted connection, shared by
+/// its requests
+struct InterceptedClient {
+    ip: IpAddr,
+    scope: Option<String>,
+    mode: ProxyMode,
+    identity: Option<DeviceIdentity>,
+    host: String,
+    destination: Option<SocketAddr>,
+}
+
+/// Response with a JSON body
+fn json_response(
+    status: u16,
+    headers: &[(String, String)],
+    body: &serde_json::Value,
+) -> Response<ProxyBody> {
+    text_response(status, headers, body.to_string())
+}
+
+/// Status, headers and body of a cached response
+fn cached_parts(cached: CachedResponse) -> (StatusCode, HeaderMap, Bytes) {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &cached.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    let status = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    (status, headers, Bytes::from(cached.body))
+}
+
+/// Upstream response in the form the retry detector and prompt cache keep
+fn cached_response(status: StatusCode, headers: &HeaderMap, body: &Bytes) -> CachedResponse {
+    CachedResponse {
+        status: status.as_u16(),
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: body.to_vec(),
+    }
+}
+
+/// Body of the proxy's answers: buffered, or streamed through from the
+/// upstream
+type ProxyBody = UnsyncBoxBody<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+
+fn full_body(body: Bytes) -> ProxyBody {
+    Full::new(body)
+        .map_err(|never| match never {})
+        .boxed_unsync()
+}
+
+/// Response for a block page or other synthetic answer
+fn synthetic_response(response: SyntheticResponse) -> Response<ProxyBody> {
+    text_response(response.status, &response.headers, response.body)
+}
+
+fn text_response(status: u16, headers: &[(String, String)], body: String) -> Response<ProxyBody> {
+    let mut response = Response::new(full_body(Bytes::from(body)));
+    *response.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+    append_headers(&mut response, headers);
+    response
+}
+
+/// Add headers, skipping any that aren't valid HTTP
+fn append_headers<B>(response: &mut Response<B>, headers: &[(String, String)]) {
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().append(name, value);
+        }
+    }
+}
+
+/// Model the client asked for, as parsed from the request body
+fn requested_model(request: &RequestContext) -> Option<&str> {
+    request.parsed.as_ref().and_then(|p| p.model.as_deref())
+}
+
+/// Request context for policy evaluation and auditing
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Client IP address, in canonical form
+    /// ([`crate::netaddr::normalize_client_ip`]: IPv4-mapped addresses as
+    /// IPv4, IPv6 compressed)
+    pub client_ip: String,
+
+    /// Tenant (household) the client belongs to
+    pub tenant: String,
+
+    /// Network scope (subnet/VLAN) the client connected from
+    pub scope: Option<String>,
+
+    /// Device name (from DHCP or WireGuard peer), if known
+    pub client_device: Option<String>,
+
+    /// MAC, owner and group of the device, if the router could identify it
+    /// ([`ProxyServer::client_identity`])
+    pub identity: Option<DeviceIdentity>,
+
+    /// Target endpoint (e.g., "api.openai.com")
+    pub endpoint: String,
+
+    /// Address the client dialed, when the firewall reports it (see
+    /// [`crate::origdst`])
+    pub destination: Option<SocketAddr>,
+
+    /// HTTP method
+    pub method: String,
+
+    /// Request path
+    pub path: String,
+
+    /// User agent
+    pub user_agent: Option<String>,
+
+    /// Prompt preview (first 200 chars, if applicable)
+    pub prompt_preview: Option<String>,
+
+    /// Request timestamp
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+
+    /// Request id of the original request, if this one is a client retry
+    pub retry_of: Option<String>,
+
+    /// Conversation session the request belongs to (assign_session)
+    pub session_id: Option<String>,
+
+    /// Content categories of the prompt (classify_request)
+    pub categories: Vec<String>,
+
+    /// Model, messages, tools etc. parsed from the body
+    /// (providers::parse_request); None when the body wasn't parsed
+    pub parsed: Option<ParsedRequest>,
+}
+
+/// Request from a default-tenant client to OpenAI chat completions, for tests
+#[cfg(test)]
+pub(crate) fn test_request() -> RequestContext {
+    RequestContext {
+        client_ip: "192.168.1.50".to_string(),
+        tenant: "default".to_string(),
+        scope: None,
+        client_device: None,
+        identity: None,
+        endpoint: "api.openai.com".to_string(),
+        destination: None,
+        method: "POST".to_string(),
+        path: "/v1/chat/completions".to_string(),
+        user_agent: None,
+        prompt_preview: None,
+        timestamp: chrono::Utc::now(),
+        retry_of: None,
+        session_id: None,
+        categories: Vec::new(),
+        parsed: None,
+    }
+}
+
+/// Response context for auditing
+#[derive(Debug, Clone)]
+pub struct ResponseContext {
+    /// HTTP status code
+    pub status: u16,
+
+    /// Response duration in milliseconds
+    pub duration_ms: u64,
+
+    /// Estimated token count (if applicable)
+    pub tokens: Option<usize>,
+
+    /// Model that served the response (may differ from the one requested)
+    pub model: Option<String>,
+
+    /// Why generation stopped ("stop", "length", "content_filter", ...)
+    pub finish_reason: Option<String>,
+
+    /// Provider safety annotations
+    pub safety_flags: Vec<String>,
+
+    /// Estimated cost in USD (see [`crate::cost`])
+    pub cost_usd: Option<f64>,
+}
+
+impl ResponseContext {
+    /// Attach metadata parsed from the response body
+    pub fn with_metadata(mut self, metadata: ResponseMetadata) -> Self {
+        self.model = metadata.model;
+        self.finish_reason = metadata.finish_reason;
+        self.safety_flags = metadata.safety_flags;
+        self
+    }
+}
+
+/// Python handle that runs a [`ProxyServer`] on its own Tokio runtime
+///
+/// Lets the FastAPI layer start and stop the proxy in-process, switch its
+/// mode at runtime and report listener status.
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// proxy = yori_core.ProxyServer(listen="[::]:8443", mode="observe")
+/// proxy.start()
+/// proxy.mode = "enforce"
+/// print(proxy.status())   # {"running": True, "mode": "enforce", ...}
+/// proxy.stop()
+/// ```
+#[pyclass(name = "ProxyServer")]
+pub struct PyProxyServer {
+    server: Arc<ProxyServer>,
+    runtime: tokio::runtime::Runtime,
+    task: Mutex<Option<tokio::task::JoinHandle<Result<()>>>>,
+    admin: Mutex<Option<tokio::task::JoinHandle<Result<()>>>>,
+    alerts: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    decision_log: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    dns_overrides: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    transcripts: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    reloader: Mutex<Option<Arc<ConfigReloader>>>,
+    config_watch: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    started_at: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl PyProxyServer {
+    /// Whether the server task is still running, collecting its error if it
+    /// has exited
+    fn is_running(&self) -> bool {
+        let mut task = self.task.lock().unwrap();
+        match task.as_ref() {
+            Some(handle) if handle.is_finished() => {
+                let result = self.runtime.block_on(task.take().unwrap());
+                if let Some(error) = match result {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!("{:#}", e)),
+                    Err(e) => Some(e.to_string()),
+                } {
+                    *self.last_error.lock().unwrap() = Some(error);
+                }
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+#[pymethods]
+impl PyProxyServer {
+    /// Create a proxy server (not started)
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - Listen address (default: "[::]:8443", IPv4 and IPv6)
+    /// * `mode` - "observe", "advisory" or "enforce" (default: "observe")
+    /// * `endpoints` - Hosts or `*` patterns to intercept (default: built-in list)
+    /// * `tls_cert` / `tls_key` - Certificate and key paths (default: /usr/local/etc/yori/certs)
+    /// * `local_only` - Start with cloud LLM endpoints blocked (default: False)
+    /// * `rate_limit_per_minute` - Per-device, per-endpoint request limit (default: unlimited)
+    /// * `rate_limit_burst` - Requests allowed back to back (default: the per-minute limit)
+    /// * `interception` - "transparent", "explicit" (CONNECT) or "both" (default: "transparent")
+    #[new]
+    #[pyo3(signature = (
+        listen="[::]:8443",
+        mode="observe",
+        endpoints=None,
+        tls_cert=None,
+        tls_key=None,
+        local_only=false,
+        rate_limit_per_minute=None,
+        rate_limit_burst=None,
+        interception="transparent",
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        listen: &str,
+        mode: &str,
+        endpoints: Option<Vec<String>>,
+        tls_cert: Option<String>,
+        tls_key: Option<String>,
+        local_only: bool,
+        rate_limit_per_minute: Option<u32>,
+        rate_limit_burst: Option<u32>,
+        interception: &str,
+    ) -> PyResult<Self> {
+        let defaults = ProxyConfig::default();
+        let config = ProxyConfig {
+            listen_addr: listen.parse().map_err(|_| {
+                PyValueError::new_err(format!("invalid listen address {:?}", listen))
+            })?,
+            mode: mode
+                .parse()
+                .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?,
+            endpoints: endpoints.unwrap_or(defaults.endpoints.clone()),
+            tls_cert_path: tls_cert.unwrap_or(defaults.tls_cert_path.clone()),
+            tls_key_path: tls_key.unwrap_or(defaults.tls_key_path.clone()),
+            local_only,
+            rate_limit_per_minute,
+            rate_limit_burst,
+            interception: interception
+                .parse()
+                .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?,
+            ..defaults
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            // Router hardware: a couple of workers is plenty
+            .worker_threads(2)
+            .thread_name("yori-proxy")
+            .enable_all()
+            .build()
+            .map_err(|e| PyRuntimeError::new_err(format!("failed to start runtime: {}", e)))?;
+        Ok(PyProxyServer {
+            server: Arc::new(ProxyServer::new(config)),
+            runtime,
+            task: Mutex::new(None),
+            admin: Mutex::new(None),
+            alerts: Mutex::new(None),
+            decision_log: Mutex::new(None),
+            dns_overrides: Mutex::new(None),
+            transcripts: Mutex::new(None),
+            reloader: Mutex::new(None),
+            config_watch: Mutex::new(None),
+            started_at: Mutex::new(None),
+            last_error: Mutex::new(None),
+        })
+    }
+
+    /// Start serving in the background
+    ///
+    /// Raises RuntimeError if the proxy is already running.
+    fn start(&self) -> PyResult<()> {
+        if self.is_running() {
+            return Err(PyRuntimeError::new_err("proxy is already running"));
+        }
+        let server = Arc::clone(&self.server);
+        let handle = self.runtime.spawn(async move { server.start().await });
+        *self.task.lock().unwrap() = Some(handle);
+        *self.started_at.lock().unwrap() = Some(chrono::Utc::now());
+        *self.last_error.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Stop the proxy and wait for it to exit
+    ///
+    /// # Returns
+    ///
+    /// True if the proxy was running
+    fn stop(&self, py: Python<'_>) -> PyResult<bool> {
+        let Some(handle) = self.task.lock().unwrap().take() else {
+            return Ok(false);
+        };
+        let server = Arc::clone(&self.server);
+        let result = py.allow_threads(|| {
+            self.runtime.block_on(async {
+                let report = server.shutdown().await?;
+                tracing::info!(
+                    "Proxy stopped: {} connections drained, {} dropped",
+                    report.drained,
+                    report.dropped
+                );
+                handle.await?
+            })
+        });
+        *self.started_at.lock().unwrap() = None;
+        result
+            .map(|()| true)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Serve the REST admin API in the background
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - Listen address (default: "127.0.0.1:8444")
+    /// * `token` - Bearer token required on every route but `/api/health`
+    /// * `policy_dir` - Policy directory backing the policy routes
+    /// * `audit_db` - Audit database backing the audit and cache routes
+    ///
+    /// Raises RuntimeError if the admin API is already running.
+    #[pyo3(signature = (listen=DEFAULT_ADMIN_ADDR, token=None, policy_dir=None, audit_db=None))]
+    fn start_admin(
+        &self,
+        listen: &str,
+        token: Option<String>,
+        policy_dir: Option<String>,
+        audit_db: Option<String>,
+    ) -> PyResult<()> {
+        let addr: SocketAddr = listen
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid listen address {:?}", listen)))?;
+        let mut admin = self.admin.lock().unwrap();
+        if admin.as_ref().is_some_and(|handle| !handle.is_finished()) {
+            return Err(PyRuntimeError::new_err("admin API is already running"));
+        }
+        if let Some(dir) = policy_dir {
+            let engine =
+                PolicyEngine::open(dir).map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+            self.server.set_policy_engine(Arc::new(engine));
+        }
+        if let Some(database) = audit_db {
+            let audit = AuditLogger::open(AuditConfig {
+                database: database.into(),
+                ..AuditConfig::default()
+            })
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?
+            .with_live_tail(self.server.live_tail());
+            self.server.set_audit_logger(Arc::new(audit));
+        }
+        let mut state = AdminState::new(Arc::clone(&self.server));
+        if let Some(token) = token {
+            state = state.with_token(token);
+        }
+        if let Some(reloader) = self.reloader.lock().unwrap().clone() {
+            state = state.with_reloader(reloader);
+        }
+        *admin = Some(self.runtime.spawn(crate::admin::serve(addr, state)));
+        Ok(())
+    }
+
+    /// Inject stored provider API keys into forwarded requests
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - API key store (default: /var/db/yori/api-keys.json)
+    /// * `vault_key` - Vault key file the store is sealed with (default:
+    ///   /var/db/yori/vault.key)
+    ///
+    /// # Returns
+    ///
+    /// Providers with a stored key
+    #[pyo3(signature = (path=None, vault_key=None))]
+    fn load_api_keys(
+        &self,
+        path: Option<String>,
+        vault_key: Option<String>,
+    ) -> PyResult<Vec<String>> {
+        let path = path.unwrap_or_else(|| DEFAULT_API_KEY_STORE.to_string());
+        let vault_key = vault_key.unwrap_or_else(|| crate::vault::DEFAULT_VAULT_PATH.to_string());
+        let store = crate::vault::Vault::open_or_create(std::path::Path::new(&vault_key))
+            .and_then(|vault| ApiKeyStore::open(std::path::Path::new(&path), &vault))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let providers = store.providers();
+        self.server.set_api_key_store(Arc::new(store));
+        Ok(providers)
+    }
+
+    /// Stop the REST admin API
+    ///
+    /// # Returns
+    ///
+    /// True if it was running
+    fn stop_admin(&self) -> bool {
+        match self.admin.lock().unwrap().take() {
+            Some(handle) => {
+                let running = !handle.is_finished();
+                handle.abort();
+                running
+            }
+            None => false,
+        }
+    }
+
+    /// Send webhook/ntfy/Discord/Slack alerts for blocked requests and
+    /// errors published to the live tail
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file with an `[alerts]` section
+    ///   (default: the gateway's yori.toml)
+    ///
+    /// # Returns
+    ///
+    /// Number of alert targets
+    ///
+    /// Raises RuntimeError if no targets are configured.
+    #[pyo3(signature = (config=None))]
+    fn start_alerts(&self, config: Option<String>) -> PyResult<usize> {
+        let config = match config {
+            Some(path) => YoriConfig::load(std::path::Path::new(&path)),
+            None => YoriConfig::load_default(),
+        }
+        .and_then(|c| c.alert_config())
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?
+        .ok_or_else(|| PyRuntimeError::new_err("no alert targets configured"))?;
+        let targets = config.targets.len();
+        let alerter =
+            Alerter::new(config).map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+
+        let mut alerts = self.alerts.lock().unwrap();
+        if let Some(previous) = alerts.take() {
+            previous.abort();
+        }
+        let _guard = self.runtime.enter();
+        *alerts = Some(Arc::new(alerter).watch(&self.server.live_tail()));
+        Ok(targets)
+    }
+
+    /// Stop sending alerts
+    ///
+    /// # Returns
+    ///
+    /// True if alerting was running
+    fn stop_alerts(&self) -> bool {
+        match self.alerts.lock().unwrap().take() {
+            Some(handle) => {
+                let running = !handle.is_finished();
+                handle.abort();
+                running
+            }
+            None => false,
+        }
+    }
+
+    /// Ship policy decisions published to the live tail to a decision log
+    /// collector
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file with a `[decision_log]` section
+    ///   (default: the gateway's yori.toml)
+    ///
+    /// # Returns
+    ///
+    /// The collector URL
+    ///
+    /// Raises RuntimeError if no collector is configured.
+    #[pyo3(signature = (config=None))]
+    fn start_decision_log(&self, config: Option<String>) -> PyResult<String> {
+        let config = match config {
+            Some(path) => YoriConfig::load(std::path::Path::new(&path)),
+            None => YoriConfig::load_default(),
+        }
+        .and_then(|c| c.decision_log_config())
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?
+        .ok_or_else(|| PyRuntimeError::new_err("no decision log URL configured"))?;
+        let url = config.url.clone();
+        let shipper = DecisionLogShipper::new(config)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+
+        let mut decision_log = self.decision_log.lock().unwrap();
+        if let Some(previous) = decision_log.take() {
+            previous.abort();
+        }
+        let _guard = self.runtime.enter();
+        *decision_log = Some(Arc::new(shipper).watch(&self.server.live_tail()));
+        Ok(url)
+    }
+
+    /// Stop shipping decisions; records not yet shipped are discarded
+    ///
+    /// # Returns
+    ///
+    /// True if shipping was running
+    fn stop_decision_log(&self) -> bool {
+        match self.decision_log.lock().unwrap().take() {
+            Some(handle) => {
+                let running = !handle.is_finished();
+                handle.abort();
+                running
+            }
+            None => false,
+        }
+    }
+
+    /// Keep the resolver's override file pointing the intercepted hosts at
+    /// the gateway, following changes to the configuration file
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file with a `[dns]` section (default: the
+    ///   gateway's yori.toml)
+    ///
+    /// # Returns
+    ///
+    /// Path of the override file
+    ///
+    /// Raises RuntimeError if no gateway address is configured.
+    #[pyo3(signature = (config=None))]
+    fn start_dns_overrides(&self, config: Option<String>) -> PyResult<String> {
+        let load = move || match &config {
+            Some(path) => YoriConfig::load(std::path::Path::new(path)),
+            None => YoriConfig::load_default(),
+        };
+        let settings = load()
+            .and_then(|c| c.dns_override_config())
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?
+            .ok_or_else(|| PyRuntimeError::new_err("no DNS override addresses configured"))?;
+        let overrides =
+            DnsOverrides::new(settings).map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let path = overrides.path().display().to_string();
+
+        let mut dns_overrides = self.dns_overrides.lock().unwrap();
+        if let Some(previous) = dns_overrides.take() {
+            previous.abort();
+        }
+        let _guard = self.runtime.enter();
+        *dns_overrides = Some(Arc::new(overrides).watch(move || Ok(load()?.intercepted_hosts())));
+        Ok(path)
+    }
+
+    /// Stop updating DNS overrides; the override file is left in place
+    ///
+    /// # Returns
+    ///
+    /// True if updates were running
+    fn stop_dns_overrides(&self) -> bool {
+        match self.dns_overrides.lock().unwrap().take() {
+            Some(handle) => {
+                let running = !handle.is_finished();
+                handle.abort();
+                running
+            }
+            None => false,
+        }
+    }
+
+    /// Record full conversations for the tenants listed in
+    /// `transcripts.tenants`, pruning them past their retention
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file with a `[transcripts]` section
+    ///   (default: the gateway's yori.toml)
+    ///
+    /// # Returns
+    ///
+    /// Tenants whose conversations are recorded
+    ///
+    /// Raises RuntimeError if no tenant opted in.
+    #[pyo3(signature = (config=None))]
+    fn start_transcripts(&self, config: Option<String>) -> PyResult<Vec<String>> {
+        let config = match config {
+            Some(path) => YoriConfig::load(std::path::Path::new(&path)),
+            None => YoriConfig::load_default(),
+        }
+        .and_then(|c| c.transcript_config())
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        if config.tenants.is_empty() {
+            return Err(PyRuntimeError::new_err(
+                "no tenants opted in to transcripts",
+            ));
+        }
+        let tenants = config.tenants.clone();
+        let store = Arc::new(
+            TranscriptStore::open(config)
+                .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?,
+        );
+
+        let mut transcripts = self.transcripts.lock().unwrap();
+        if let Some(previous) = transcripts.take() {
+            previous.abort();
+        }
+        self.server.set_transcript_store(Some(Arc::clone(&store)));
+        let _guard = self.runtime.enter();
+        *transcripts = Some(store.spawn_pruning());
+        Ok(tenants)
+    }
+
+    /// Stop recording transcripts; stored turns are kept
+    ///
+    /// # Returns
+    ///
+    /// True if transcripts were being recorded
+    fn stop_transcripts(&self) -> bool {
+        self.server.set_transcript_store(None);
+        match self.transcripts.lock().unwrap().take() {
+            Some(handle) => {
+                let running = !handle.is_finished();
+                handle.abort();
+                running
+            }
+            None => false,
+        }
+    }
+
+    /// Reload the configuration file on SIGHUP (and through the admin
+    /// API's `/api/config/reload` once `start_admin` is called)
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file the proxy was started from
+    ///   (default: the gateway's yori.toml)
+    ///
+    /// Raises RuntimeError if the file can't be read or is invalid.
+    #[pyo3(signature = (config=None))]
+    fn watch_config(&self, config: Option<String>) -> PyResult<()> {
+        let reloader = Arc::new(
+            ConfigReloader::load(config.map(std::path::PathBuf::from))
+                .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?,
+        );
+        let mut watch = self.config_watch.lock().unwrap();
+        if let Some(previous) = watch.take() {
+            previous.abort();
+        }
+        let _guard = self.runtime.enter();
+        *watch = Some(
+            Arc::clone(&reloader)
+                .watch(Arc::clone(&self.server))
+                .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?,
+        );
+        *self.reloader.lock().unwrap() = Some(reloader);
+        Ok(())
+    }
+
+    /// Re-read the configuration file and apply what changed
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `changes` (setting, old and new value), `applied`
+    /// and `restart_required`
+    ///
+    /// Raises RuntimeError if `watch_config` wasn't called or the file is
+    /// invalid (nothing is changed then).
+    fn reload_config(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let reloader = self
+            .reloader
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| PyRuntimeError::new_err("call watch_config first"))?;
+        let server = Arc::clone(&self.server);
+        let report = py
+            .allow_threads(move || reloader.reload(&server))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let report =
+            serde_json::to_value(report).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        json_to_py(py, &report)
+    }
+
+    /// Default mode: "observe", "advisory" or "enforce"
+    #[getter]
+    fn get_mode(&self) -> &'static str {
+        self.server.mode().as_str()
+    }
+
+    #[setter]
+    fn set_mode(&self, mode: &str) -> PyResult<()> {
+        let mode = mode
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+        self.server.set_mode(mode);
+        Ok(())
+    }
+
+    /// Switch local-only mode on or off
+    fn set_local_only(&self, enabled: bool) {
+        self.server.set_local_only(enabled);
+    }
+
+    /// Follow audit events as they are logged (see `AuditSubscription`)
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`, `client_ip` - Exact matches
+    /// * `endpoint` - Endpoints containing this text
+    /// * `event_type` - Comma-separated event types (e.g., "block,alert")
+    /// * `status` - Also pass mode and local-only changes (default: False)
+    ///
+    /// Only events logged after the call are seen.
+    #[pyo3(signature = (tenant=None, client_ip=None, endpoint=None, event_type=None, status=false))]
+    fn subscribe(
+        &self,
+        tenant: Option<String>,
+        client_ip: Option<String>,
+        endpoint: Option<String>,
+        event_type: Option<&str>,
+        status: bool,
+    ) -> PyResult<PyAuditSubscription> {
+        let filter = LiveFilter {
+            tenant,
+            client_ip,
+            endpoint,
+            event_types: event_type
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+            status,
+        };
+        PyAuditSubscription::new(&self.server.live_tail(), filter)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Fill the policy decision caches from recent audited requests
+    ///
+    /// Meant for right after `start_admin` attached the policy engine and
+    /// audit log; `start` already warms whatever is attached by then.
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `evaluated`, `errors`, `shortcuts` and `duration_ms`,
+    /// or None without a policy engine or audit log
+    fn warm_from_audit(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let server = Arc::clone(&self.server);
+        let report = py
+            .allow_threads(move || server.warm_from_audit())
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let Some(report) = report else {
+            return Ok(None);
+        };
+        let report =
+            serde_json::to_value(report).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        json_to_py(py, &report).map(Some)
+    }
+
+    /// Listener status
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `running`, `listen`, `mode`, `local_only`,
+    /// `started_at` (ISO 8601 or None), `uptime_seconds` and `last_error`
+    fn status(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let running = self.is_running();
+        let started_at = if running {
+            *self.started_at.lock().unwrap()
+        } else {
+            None
+        };
+        let status = PyDict::new_bound(py);
+        status.set_item("running", running)?;
+        status.set_item("listen", self.server.listen_addr().to_string())?;
+        status.set_item("mode", self.server.mode().as_str())?;
+        status.set_item("local_only", self.server.is_local_only())?;
+        status.set_item("started_at", started_at.map(|t| t.to_rfc3339()))?;
+        status.set_item(
+            "uptime_seconds",
+            started_at.map(|t| (chrono::Utc::now() - t).num_seconds().max(0)),
+        )?;
+        status.set_item("last_error", self.last_error.lock().unwrap().clone())?;
+        let circuits = PyDict::new_bound(py);
+        for (endpoint, state) in self.server.tripped_circuits() {
+            circuits.set_item(endpoint, state.as_str())?;
+        }
+        status.set_item("open_circuits", circuits)?;
+        Ok(status.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_proxy_config_default() {
+        let config = ProxyConfig::default();
+        assert_eq!(config.mode, ProxyMode::Observe);
+        assert!(config.endpoints.contains(&"api.openai.com".to_string()));
+    }
+
+    #[test]
+    fn test_should_intercept() {
+        let config = ProxyConfig::default();
+        let server = ProxyServer::new(config);
+
+        assert!(server.should_intercept("api.openai.com"));
+        assert!(server.should_intercept("api.anthropic.com"));
+        assert!(server.should_intercept("contoso.openai.azure.com"));
+        assert!(server.should_intercept("bedrock-runtime.us-east-1.amazonaws.com"));
+        assert!(!server.should_intercept("example.com"));
+        assert!(!server.should_intercept("evil-api.openai.com.attacker.net"));
+        assert!(!server.should_intercept("api.openai.com.attacker.net:443"));
+        assert!(server.should_intercept("API.OpenAI.com.:443"));
+        assert!(!server.should_intercept("api.x.ai"));
+
+        let config = ProxyConfig {
+            providers: ProviderRegistry::new(vec![("api.x.ai".to_string(), Provider::OpenAI)])
+                .unwrap(),
+            ..ProxyConfig::default()
+        };
+        assert!(ProxyServer::new(config).should_intercept("api.x.ai"));
+    }
+
+    #[test]
+    fn test_authenticated_identity_overrides_device_owner() {
+        let config = ProxyConfig {
+            auth: ProxyAuthConfig {
+                users: [(
+                    "timmy".to_string(),
+                    crate::proxyauth::ProxyUser {
+                        password_hash: None,
+                        group: Some("kids".to_string()),
+                    },
+                )]
+                .into(),
+                ..ProxyAuthConfig::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let server = ProxyServer::new(config);
+        let identity = server.authenticated_identity("192.168.1.50".parse().unwrap(), "timmy");
+        assert_eq!(identity.owner.as_deref(), Some("timmy"));
+        assert_eq!(identity.group.as_deref(), Some("kids"));
+        assert_eq!(identity.source, IdentitySource::ProxyAuth);
+
+        let identity = server.authenticated_identity("192.168.1.50".parse().unwrap(), "guest");
+        assert_eq!(identity.group, None);
+    }
+
+    #[test]
+    fn test_local_only_toggle() {
+        let config = ProxyConfig {
+            local_endpoints: vec!["ollama.lan:11434".to_string(), "llama.lan".to_string()],
+            ..ProxyConfig::default()
+        };
+        let server = ProxyServer::new(config);
+
+        assert!(!server.blocked_by_local_only("api.openai.com"));
+
+        server.set_local_only(true);
+        assert!(server.blocked_by_local_only("api.openai.com"));
+        assert!(!server.blocked_by_local_only("ollama.lan:11434"));
+        assert!(!server.blocked_by_local_only("LLAMA.lan:8080"));
+        assert!(server.blocked_by_local_only("ollama.lan:9999"));
+
+        let request = test_request();
+        let (response, event) = server.local_only_response(&request, None);
+        assert_eq!(response.status, 403);
+        assert_eq!(event.event_type, AuditEventType::RequestBlocked);
+        assert_eq!(event.policy_name.as_deref(), Some(LOCAL_ONLY_POLICY));
+        assert_eq!(event.policy_reason.as_deref(), Some(LOCAL_ONLY_REASON));
+
+        server.set_local_only(false);
+        assert!(!server.blocked_by_local_only("api.anthropic.com"));
+    }
+
+    #[test]
+    fn test_buffered_response_feeds_latency_and_usage() {
+        let server = ProxyServer::new(ProxyConfig::default());
+        let request = test_request();
+        let body = br#"{"model":"gpt-4o","choices":[{"finish_reason":"stop"}],
+            "usage":{"prompt_tokens":12,"completion_tokens":30}}"#;
+
+        let response =
+            server.buffered_response(&request, Some(Provider::OpenAI), 200, Instant::now(), body);
+        assert_eq!(response.tokens, Some(42));
+        assert_eq!(response.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+
+        let report = server
+            .latency()
+            .provider_report("api.openai.com", 60)
+            .unwrap();
+        assert_eq!(report.count, 1);
+        let rows = server
+            .usage()
+            .rows_since(request.timestamp - chrono::Duration::hours(1));
+        assert_eq!(rows.iter().map(|r| r.point.tokens).sum::<u64>(), 42);
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        let server = ProxyServer::new(ProxyConfig {
+            rate_limit_per_minute: Some(30),
+            rate_limit_burst: Some(1),
+            ..ProxyConfig::default()
+        });
+        let request = test_request();
+
+        assert!(server.check_rate_limit(&request).unwrap().allowed);
+        let decision = server.check_rate_limit(&request).unwrap();
+        assert!(!decision.allowed);
+
+        let (headers, body, event) = server.rate_limited_response(&request, &decision);
+        assert!(headers.contains(&("Retry-After".to_string(), "2".to_string())));
+        assert_eq!(body["error"]["type"], "rate_limit_exceeded");
+        assert_eq!(event.event_type, crate::audit::AuditEventType::RateLimited);
+        assert_eq!(event.response_status, Some(429));
+    }
+
+    #[test]
+    fn test_retry_answered_from_remembered_response() {
+        let server = ProxyServer::new(ProxyConfig {
+            serve_cached_retries: true,
+            ..ProxyConfig::default()
+        });
+        let body = br#"{"model":"gpt-4o","messages":[]}"#;
+        let mut original = test_request();
+        assert_eq!(
+            server.detect_retry(&mut original, "req-1", body),
+            RetryCheck::Original
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        let response = cached_response(StatusCode::OK, &headers, &Bytes::from_static(b"{}"));
+        server.remember_response(&original, body, response);
+
+        let mut retry = test_request();
+        let RetryCheck::Retry {
+            cached: Some(cached),
+            ..
+        } = server.detect_retry(&mut retry, "req-2", body)
+        else {
+            panic!("retry not answered from the original response");
+        };
+        assert_eq!(retry.retry_of.as_deref(), Some("req-1"));
+        let (status, headers, body) = cached_parts(cached);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(&body[..], b"{}");
+    }
+
+    #[test]
+    fn test_mode_change_and_shutdown() {
+        let server = Arc::new(ProxyServer::new(ProxyConfig::default()));
+        assert_eq!("Enforce".parse::<ProxyMode>().unwrap(), ProxyMode::Enforce);
+        assert!("block".parse::<ProxyMode>().is_err());
+
+        let mut status = server.live_tail().subscribe();
+        server.set_mode(ProxyMode::Enforce);
+        assert_eq!(server.mode(), ProxyMode::Enforce);
+        assert!(status.try_recv().is_ok());
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let task = runtime.spawn(Arc::clone(&server).serve_listener(listener));
+        runtime.block_on(async {
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+            assert!(!task.is_finished());
+            let in_flight = server.accept_connection().unwrap();
+            assert_eq!(server.active_connections(), 1);
+            tokio::spawn(async move {
+                tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                drop(in_flight);
+            });
+
+            let report = server.shutdown().await.unwrap();
+            assert_eq!((report.drained, report.dropped), (1, 0));
+            assert!(server.accept_connection().is_none());
+            task.await.unwrap().unwrap();
+        });
+    }
+
+    #[tokio::test]
+    async fn test_blocked_scope_connection_is_closed() {
+        let config = ProxyConfig {
+            scopes: vec![NetworkScope {
+                name: "iot".to_string(),
+                networks: vec!["127.0.0.0/8".parse().unwrap()],
+                interface: None,
+                block_all: true,
+                mode: None,
+                profile: None,
+            }],
+            ..ProxyConfig::default()
+        };
+        let server = Arc::new(ProxyServer::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let task = tokio::spawn(Arc::clone(&server).serve_listener(listener));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 1];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+
+        server.shutdown().await.unwrap();
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forward_local_translates_the_answer() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let authority = backend.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !received.ends_with(b"}") {
+                let n = stream.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            tokio::io::AsyncWriteExt::write_all(&mut stream, response.as_bytes())
+                .await
+                .unwrap();
+        });
+
+        let server = ProxyServer::new(ProxyConfig::default());
+        let routed = RoutedRequest {
+            backend: "ollama".to_string(),
+            authority,
+            path: "/v1/chat/completions".to_string(),
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: br#"{"model":"llama3","messages":[]}"#.to_vec(),
+            client_shape: crate::localroute::ApiShape::Anthropic,
+            client_stream: false,
+        };
+        let (status, headers, body) = server
+            .forward_local(&test_request(), &routed)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[ROUTED_HEADER], "ollama");
+        let message: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(message["content"][0]["text"], "Hi");
     }
 }
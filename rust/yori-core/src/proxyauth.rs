@@ -0,0 +1,402 @@
+//! Proxy authentication for explicit mode
+//!
+//! Behind CGNAT, on a shared laptop or over a VPN, the client address says
+//! little about who is asking. Devices using YORI as their proxy can log
+//! in instead, and the user they authenticate as becomes the request's
+//! owner in policy input and audit events, whichever device they're on:
+//!
+//! ```toml
+//! [proxy_auth]
+//! required = true
+//! client_ca = "/usr/local/etc/yori/clients-ca.pem"
+//!
+//! [proxy_auth.users.timmy]
+//! password_hash = "pbkdf2-sha256$100000$..."   # yori_core.hash_proxy_password()
+//! group = "kids"
+//! ```
+//!
+//! Two kinds of credentials are accepted:
+//!
+//! - `Proxy-Authorization: Basic` on the CONNECT request, checked against
+//!   the users' PBKDF2 password hashes
+//! - a client certificate issued by `client_ca`, presented in the TLS
+//!   handshake of an intercepted connection; its common name is the user
+//!
+//! With `required`, a CONNECT without valid Basic credentials is answered
+//! `407` - unless its host is intercepted and a client CA is configured,
+//! in which case the handshake must carry a certificate instead. Wrong
+//! credentials are always refused.
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Realm named in `Proxy-Authenticate` challenges
+pub const DEFAULT_PROXY_REALM: &str = "YORI";
+
+/// PBKDF2 rounds for newly hashed passwords
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Prefix of the password hash format
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+
+/// Accepted `Proxy-Authorization` values remembered, so each connection
+/// doesn't pay for PBKDF2 again
+const VERIFIED_ENTRIES: usize = 256;
+
+/// A proxy user
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyUser {
+    /// From [`hash_password`] (None = certificate login only)
+    pub password_hash: Option<String>,
+
+    /// Group in policy input (e.g., "kids")
+    pub group: Option<String>,
+}
+
+/// Proxy authentication settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyAuthConfig {
+    pub users: BTreeMap<String, ProxyUser>,
+
+    /// PEM CA bundle client certificates must chain to
+    pub client_ca: Option<PathBuf>,
+
+    /// Refuse clients that don't authenticate
+    pub required: bool,
+
+    pub realm: String,
+}
+
+impl Default for ProxyAuthConfig {
+    fn default() -> Self {
+        ProxyAuthConfig {
+            users: BTreeMap::new(),
+            client_ca: None,
+            required: false,
+            realm: DEFAULT_PROXY_REALM.to_string(),
+        }
+    }
+}
+
+impl ProxyAuthConfig {
+    /// Whether any way of authenticating is configured
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty() || self.client_ca.is_some()
+    }
+}
+
+/// Basic credentials on a CONNECT request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Valid credentials for this user
+    User(String),
+
+    /// No `Proxy-Authorization` header (or no password users)
+    Missing,
+
+    /// Malformed, unknown user or wrong password
+    Rejected,
+}
+
+/// Checks proxy credentials
+#[derive(Debug)]
+pub struct ProxyAuthenticator {
+    config: ProxyAuthConfig,
+
+    /// SHA-256 of accepted header values -> user
+    verified: Mutex<HashMap<[u8; 32], String>>,
+}
+
+impl ProxyAuthenticator {
+    pub fn new(config: ProxyAuthConfig) -> Self {
+        ProxyAuthenticator {
+            config,
+            verified: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether unauthenticated clients are refused
+    pub fn required(&self) -> bool {
+        self.config.required
+    }
+
+    /// Whether client certificates are accepted
+    pub fn accepts_certificates(&self) -> bool {
+        self.config.client_ca.is_some()
+    }
+
+    /// Group configured for a user
+    pub fn group(&self, user: &str) -> Option<&str> {
+        self.config.users.get(user)?.group.as_deref()
+    }
+
+    /// Check the `Proxy-Authorization` header of a CONNECT request head
+    pub fn basic(&self, head: &[u8]) -> Credentials {
+        if self.config.users.is_empty() {
+            return Credentials::Missing;
+        }
+        let Some(value) = header(head, "proxy-authorization") else {
+            return Credentials::Missing;
+        };
+        let key: [u8; 32] = Sha256::digest(value.as_bytes()).into();
+        if let Some(user) = self.verified.lock().unwrap().get(&key) {
+            return Credentials::User(user.clone());
+        }
+
+        let Some((user, password)) = decode_basic(value) else {
+            return Credentials::Rejected;
+        };
+        let valid = self
+            .config
+            .users
+            .get(&user)
+            .and_then(|u| u.password_hash.as_deref())
+            .is_some_and(|hash| verify_password(hash, &password));
+        if !valid {
+            return Credentials::Rejected;
+        }
+        let mut verified = self.verified.lock().unwrap();
+        if verified.len() >= VERIFIED_ENTRIES {
+            verified.clear();
+        }
+        verified.insert(key, user.clone());
+        Credentials::User(user)
+    }
+
+    /// User named by a verified client certificate chain (its common name)
+    pub fn certificate_user(&self, chain: Option<&[rustls::Certificate]>) -> Option<String> {
+        if !self.accepts_certificates() {
+            return None;
+        }
+        let leaf = chain?.first()?;
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&leaf.0);
+        let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(std::str::from_utf8(line).ok()?);
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+        let params = rcgen::CertificateParams::from_ca_cert_pem(&pem).ok()?;
+        let name = match params.distinguished_name.get(&rcgen::DnType::CommonName)? {
+            rcgen::DnValue::Utf8String(name) => name.clone(),
+            rcgen::DnValue::PrintableString(name) => name.as_str().to_string(),
+            _ => return None,
+        };
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// Trust roots for client certificates, if accepted
+    pub(crate) fn client_roots(&self) -> Result<Option<rustls::RootCertStore>> {
+        let Some(path) = &self.config.client_ca else {
+            return Ok(None);
+        };
+        let pem = std::fs::read(path)
+            .with_context(|| format!("failed to read client CA {}", path.display()))?;
+        let mut roots = rustls::RootCertStore::empty();
+        for der in rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))? {
+            roots
+                .add(&rustls::Certificate(der))
+                .with_context(|| format!("invalid certificate in {}", path.display()))?;
+        }
+        if roots.is_empty() {
+            bail!("no certificates in {}", path.display());
+        }
+        Ok(Some(roots))
+    }
+
+    /// `407` response asking for Basic credentials
+    pub fn challenge(&self) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 407 Proxy Authentication Required\r\n\
+             Proxy-Authenticate: Basic realm=\"{}\"\r\n\
+             Content-Length: 0\r\n\r\n",
+            self.config.realm.replace('"', "")
+        )
+        .into_bytes()
+    }
+}
+
+/// Hash a password for [`ProxyUser::password_hash`]
+pub fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt)
+        .map_err(|e| anyhow::anyhow!("failed to generate salt: {}", e))?;
+    let hash = pbkdf2(password.as_bytes(), &salt, PBKDF2_ITERATIONS);
+    Ok(format!(
+        "{}${}${}${}",
+        HASH_SCHEME,
+        PBKDF2_ITERATIONS,
+        hex::encode(salt),
+        hex::encode(hash)
+    ))
+}
+
+/// Check a password hash's format
+pub fn check_password_hash(hash: &str) -> Result<()> {
+    match parse_hash(hash) {
+        Some(_) => Ok(()),
+        None => bail!("not a {} password hash", HASH_SCHEME),
+    }
+}
+
+fn parse_hash(hash: &str) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+    let mut parts = hash.split('$');
+    if parts.next()? != HASH_SCHEME {
+        return None;
+    }
+    let iterations = parts.next()?.parse().ok().filter(|i| *i > 0)?;
+    let salt = hex::decode(parts.next()?).ok()?;
+    let expected = hex::decode(parts.next()?).ok().filter(|h| h.len() == 32)?;
+    parts
+        .next()
+        .is_none()
+        .then_some((iterations, salt, expected))
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    let Some((iterations, salt, expected)) = parse_hash(hash) else {
+        return false;
+    };
+    let actual = pbkdf2(password.as_bytes(), &salt, iterations);
+    // Constant time, so timing doesn't reveal how much matched
+    actual
+        .iter()
+        .zip(&expected)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// PBKDF2-HMAC-SHA256 with one output block
+fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let prf = Hmac::<Sha256>::new_from_slice(password).expect("HMAC accepts any key length");
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block: [u8; 32] = mac.finalize().into_bytes().into();
+    let mut output = block;
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&block);
+        block = mac.finalize().into_bytes().into();
+        for (out, b) in output.iter_mut().zip(&block) {
+            *out ^= b;
+        }
+    }
+    output
+}
+
+/// Value of a header in a request head
+fn header<'a>(head: &'a [u8], name: &str) -> Option<&'a str> {
+    std::str::from_utf8(head)
+        .ok()?
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// User and password from a `Basic` credentials value
+fn decode_basic(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let (user, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Hash a password for `[proxy_auth.users.<name>] password_hash`
+///
+/// # Arguments
+///
+/// * `password` - The user's proxy password
+///
+/// # Returns
+///
+/// PBKDF2-SHA256 hash with a random salt
+#[pyfunction]
+#[pyo3(name = "hash_proxy_password")]
+pub fn py_hash_password(password: &str) -> PyResult<String> {
+    hash_password(password).map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(authorization: Option<&str>) -> Vec<u8> {
+        let mut head =
+            "CONNECT api.openai.com:443 HTTP/1.1\r\nHost: api.openai.com:443\r\n".to_string();
+        if let Some(value) = authorization {
+            head.push_str(&format!("Proxy-Authorization: {}\r\n", value));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+
+    fn basic(user: &str, password: &str) -> String {
+        let encoded =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        format!("Basic {}", encoded)
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        // RFC 7914 test vector
+        assert_eq!(
+            hex::encode(pbkdf2(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        check_password_hash(&hash_password("correct horse").unwrap()).unwrap();
+        assert!(check_password_hash("correct horse").is_err());
+        // Few rounds, to keep the test quick
+        let hash = format!(
+            "{}$1000${}${}",
+            HASH_SCHEME,
+            hex::encode(b"salt"),
+            hex::encode(pbkdf2(b"correct horse", b"salt", 1000))
+        );
+
+        let auth = ProxyAuthenticator::new(ProxyAuthConfig {
+            users: BTreeMap::from([(
+                "timmy".to_string(),
+                ProxyUser {
+                    password_hash: Some(hash),
+                    group: Some("kids".to_string()),
+                },
+            )]),
+            required: true,
+            ..ProxyAuthConfig::default()
+        });
+        let valid = connect(Some(&basic("timmy", "correct horse")));
+        assert_eq!(auth.basic(&valid), Credentials::User("timmy".to_string()));
+        // Remembered the second time
+        assert_eq!(auth.basic(&valid), Credentials::User("timmy".to_string()));
+        assert_eq!(auth.group("timmy"), Some("kids"));
+
+        for wrong in [
+            basic("timmy", "battery staple"),
+            basic("tammy", "correct horse"),
+            "Basic !!!".to_string(),
+            "Bearer abc".to_string(),
+        ] {
+            assert_eq!(auth.basic(&connect(Some(&wrong))), Credentials::Rejected);
+        }
+        assert_eq!(auth.basic(&connect(None)), Credentials::Missing);
+        assert!(String::from_utf8(auth.challenge())
+            .unwrap()
+            .contains("Proxy-Authenticate: Basic realm=\"YORI\""));
+    }
+}
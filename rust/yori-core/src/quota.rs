@@ -0,0 +1,328 @@
+//! Per-user and per-device quota tracking
+//!
+//! The [`QuotaManager`] counts tokens and requests per subject (the device
+//! owner when the device is known, otherwise the client IP) for the current
+//! local day and ISO week. Counters survive restarts: they're written to a
+//! small JSON file in the state directory (at most once a minute, and on
+//! shutdown) so a reboot of the router doesn't hand out a fresh allowance.
+//!
+//! The proxy consults [`QuotaManager::check`] before forwarding, and the
+//! quota enrichment stage exposes the same counters to policies:
+//!
+//! ```rego
+//! deny contains "daily token allowance used up" if {
+//!     input.device.group == "kids"
+//!     input.quota.tokens_today >= 50000
+//! }
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::persist::write_atomic;
+
+/// Default location of the persisted counters
+pub const DEFAULT_QUOTA_STATE: &str = "/var/db/yori/state/quota.json";
+
+/// Minimum time between writes of the counter file
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Allowances for one subject (None = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    /// Tokens per local day
+    pub daily_tokens: Option<u64>,
+
+    /// Tokens per ISO week
+    pub weekly_tokens: Option<u64>,
+
+    /// Requests per local day
+    pub daily_requests: Option<u64>,
+
+    /// Requests per ISO week
+    pub weekly_requests: Option<u64>,
+}
+
+/// Quota configuration
+#[derive(Debug, Clone, Default)]
+pub struct QuotaConfig {
+    /// File the counters are persisted to (None = in memory only)
+    pub state_path: Option<PathBuf>,
+
+    /// Limits for subjects without an override
+    pub defaults: QuotaLimits,
+
+    /// Limits per subject (device owner or client IP)
+    pub subjects: HashMap<String, QuotaLimits>,
+}
+
+/// Counters of one subject
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Counters {
+    day: Option<NaiveDate>,
+    tokens_today: u64,
+    requests_today: u64,
+
+    /// Monday of the counted week
+    week: Option<NaiveDate>,
+    tokens_this_week: u64,
+    requests_this_week: u64,
+}
+
+impl Counters {
+    /// Reset whichever windows `today` has moved past
+    fn roll(&mut self, today: NaiveDate) {
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.tokens_today = 0;
+            self.requests_today = 0;
+        }
+        let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+        if self.week != Some(monday) {
+            self.week = Some(monday);
+            self.tokens_this_week = 0;
+            self.requests_this_week = 0;
+        }
+    }
+}
+
+/// A subject's usage and limits at one point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QuotaUsage {
+    /// Tokens used since local midnight
+    pub tokens_today: u64,
+
+    /// Requests since local midnight
+    pub requests_today: u64,
+
+    /// Tokens used since Monday
+    pub tokens_this_week: u64,
+
+    /// Requests since Monday
+    pub requests_this_week: u64,
+
+    /// Limits that apply to the subject
+    pub limits: QuotaLimits,
+}
+
+impl QuotaUsage {
+    /// The first limit that has been reached, if any
+    pub fn exceeded(&self) -> Option<&'static str> {
+        let over = |used: u64, limit: Option<u64>| limit.is_some_and(|l| used >= l);
+        if over(self.tokens_today, self.limits.daily_tokens) {
+            Some("daily token quota")
+        } else if over(self.requests_today, self.limits.daily_requests) {
+            Some("daily request quota")
+        } else if over(self.tokens_this_week, self.limits.weekly_tokens) {
+            Some("weekly token quota")
+        } else if over(self.requests_this_week, self.limits.weekly_requests) {
+            Some("weekly request quota")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    counters: HashMap<String, Counters>,
+    dirty: bool,
+    last_flush: Instant,
+}
+
+/// Daily/weekly token and request counters per subject
+#[derive(Debug)]
+pub struct QuotaManager {
+    config: QuotaConfig,
+    state: Mutex<State>,
+}
+
+impl Default for QuotaManager {
+    fn default() -> Self {
+        QuotaManager::new(QuotaConfig::default())
+    }
+}
+
+impl QuotaManager {
+    /// Create a manager, restoring persisted counters if there are any
+    ///
+    /// An unreadable counter file is logged and replaced rather than
+    /// keeping the proxy from starting.
+    pub fn new(config: QuotaConfig) -> Self {
+        let counters = match &config.state_path {
+            Some(path) => load_counters(path).unwrap_or_else(|e| {
+                tracing::warn!("Starting with empty quota counters: {:#}", e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        QuotaManager {
+            config,
+            state: Mutex::new(State {
+                counters,
+                dirty: false,
+                last_flush: Instant::now(),
+            }),
+        }
+    }
+
+    /// Limits that apply to `subject`
+    pub fn limits(&self, subject: &str) -> QuotaLimits {
+        self.config
+            .subjects
+            .get(subject)
+            .copied()
+            .unwrap_or(self.config.defaults)
+    }
+
+    /// Count one completed request and its tokens
+    pub fn record(&self, tenant: &str, subject: &str, tokens: u64, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        let counters = state
+            .counters
+            .entry(counter_key(tenant, subject))
+            .or_default();
+        counters.roll(local_date(now));
+        counters.tokens_today += tokens;
+        counters.requests_today += 1;
+        counters.tokens_this_week += tokens;
+        counters.requests_this_week += 1;
+        state.dirty = true;
+
+        if state.last_flush.elapsed() >= FLUSH_INTERVAL {
+            if let Err(e) = self.write(&mut state) {
+                tracing::warn!("Failed to persist quota counters: {:#}", e);
+            }
+        }
+    }
+
+    /// Current usage and limits of `subject`
+    ///
+    /// The proxy blocks the request when [`QuotaUsage::exceeded`] names a
+    /// limit (in enforce mode).
+    pub fn check(&self, tenant: &str, subject: &str, now: DateTime<Utc>) -> QuotaUsage {
+        let mut counters = self
+            .state
+            .lock()
+            .unwrap()
+            .counters
+            .get(&counter_key(tenant, subject))
+            .cloned()
+            .unwrap_or_default();
+        counters.roll(local_date(now));
+        QuotaUsage {
+            tokens_today: counters.tokens_today,
+            requests_today: counters.requests_today,
+            tokens_this_week: counters.tokens_this_week,
+            requests_this_week: counters.requests_this_week,
+            limits: self.limits(subject),
+        }
+    }
+
+    /// Write the counters to the state file if they changed
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.write(&mut state)
+    }
+
+    fn write(&self, state: &mut State) -> Result<()> {
+        let Some(path) = &self.config.state_path else {
+            return Ok(());
+        };
+        if !state.dirty {
+            return Ok(());
+        }
+        write_atomic(path, &serde_json::to_vec(&state.counters)?)?;
+        state.dirty = false;
+        state.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for QuotaManager {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("Failed to persist quota counters: {:#}", e);
+        }
+    }
+}
+
+fn counter_key(tenant: &str, subject: &str) -> String {
+    format!("{}/{}", tenant, subject)
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&Local).date_naive()
+}
+
+fn load_counters(path: &std::path::Path) -> Result<HashMap<String, Counters>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("invalid quota state in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_counters_roll_over_by_day_and_week() {
+        let quotas = QuotaManager::new(QuotaConfig {
+            defaults: QuotaLimits {
+                daily_tokens: Some(1_000),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        });
+        // 2026-03-04 is a Wednesday
+        let wednesday = Local
+            .with_ymd_and_hms(2026, 3, 4, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        quotas.record("default", "sam", 600, wednesday);
+        quotas.record("default", "sam", 400, wednesday);
+        let usage = quotas.check("default", "sam", wednesday);
+        assert_eq!((usage.tokens_today, usage.requests_today), (1_000, 2));
+        assert_eq!(usage.exceeded(), Some("daily token quota"));
+        assert_eq!(quotas.check("other", "sam", wednesday).tokens_today, 0);
+
+        let thursday = quotas.check("default", "sam", wednesday + Duration::days(1));
+        assert_eq!(
+            (thursday.tokens_today, thursday.tokens_this_week),
+            (0, 1_000)
+        );
+        assert_eq!(thursday.exceeded(), None);
+
+        let next_week = quotas.check("default", "sam", wednesday + Duration::days(7));
+        assert_eq!(next_week.tokens_this_week, 0);
+    }
+
+    #[test]
+    fn test_counters_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = QuotaConfig {
+            state_path: Some(dir.path().join("state/quota.json")),
+            ..QuotaConfig::default()
+        };
+        let now = Utc::now();
+
+        let quotas = QuotaManager::new(config.clone());
+        quotas.record("default", "192.168.1.50", 1_234, now);
+        drop(quotas);
+
+        let restored = QuotaManager::new(config);
+        let usage = restored.check("default", "192.168.1.50", now);
+        assert_eq!((usage.tokens_today, usage.requests_this_week), (1_234, 1));
+    }
+}
@@ -0,0 +1,260 @@
+//! Per-device, per-endpoint request rate limiting and allowance headers
+//!
+//! Each (client IP, endpoint) pair gets a token bucket: it holds up to
+//! `burst` requests and refills at `requests_per_minute`, so a short burst
+//! of requests goes through while a runaway script settles to the
+//! configured rate. Over-limit requests get a 429 with a JSON body
+//! ([`rate_limited_body`]) instead of being forwarded.
+//!
+//! Savvy users and scripts shouldn't discover their limits by hitting a
+//! sudden block. Every proxied response carries the device's remaining
+//! allowance:
+//!
+//! ```text
+//! X-YORI-RateLimit-Limit: 30
+//! X-YORI-RateLimit-Remaining: 12
+//! X-YORI-RateLimit-Reset: 2
+//! X-YORI-Budget-Remaining: 1750
+//! Retry-After: 2             (on 429 responses only)
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Remaining requests in the current window
+pub const HEADER_RATELIMIT_REMAINING: &str = "X-YORI-RateLimit-Remaining";
+
+/// Requests allowed per window
+pub const HEADER_RATELIMIT_LIMIT: &str = "X-YORI-RateLimit-Limit";
+
+/// Seconds until a request slot frees up
+pub const HEADER_RATELIMIT_RESET: &str = "X-YORI-RateLimit-Reset";
+
+/// Tokens left in today's budget
+pub const HEADER_BUDGET_REMAINING: &str = "X-YORI-Budget-Remaining";
+
+/// Outcome of a rate-limit check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateDecision {
+    /// Whether the request may proceed
+    pub allowed: bool,
+
+    /// Requests allowed per minute
+    pub limit: u32,
+
+    /// Requests that may be sent back to back
+    pub burst: u32,
+
+    /// Requests that could be sent right now after this one
+    pub remaining: u32,
+
+    /// Seconds until another request slot frees up (0 when the bucket is full)
+    pub reset_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+}
+
+/// Token-bucket request limiter keyed by client and endpoint
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: u32,
+    burst: u32,
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Allow `per_minute` requests per minute per client and endpoint,
+    /// with bursts of up to `burst` requests
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        RateLimiter {
+            limit: per_minute.max(1),
+            burst: burst.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Tokens regained per second
+    fn rate(&self) -> f64 {
+        self.limit as f64 / 60.0
+    }
+
+    /// Count a request from `client` to `endpoint` at `now` if a token is left
+    pub fn check(&self, client: &str, endpoint: &str, now: DateTime<Utc>) -> RateDecision {
+        let capacity = self.burst as f64;
+        let rate = self.rate();
+        let elapsed =
+            |bucket: &Bucket| (now - bucket.updated).num_milliseconds().max(0) as f64 / 1000.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        // Drop buckets that have refilled completely so the map doesn't
+        // grow forever; they'd start out full again anyway
+        buckets.retain(|_, bucket| bucket.tokens + elapsed(bucket) * rate < capacity);
+
+        let bucket = buckets
+            .entry((client.to_string(), endpoint.to_string()))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+        bucket.tokens = (bucket.tokens + elapsed(bucket) * rate).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let reset_secs = if bucket.tokens >= capacity {
+            0
+        } else {
+            // Time until the next whole token
+            let missing = bucket.tokens.floor() + 1.0 - bucket.tokens;
+            (missing / rate).ceil() as u64
+        };
+
+        RateDecision {
+            allowed,
+            limit: self.limit,
+            burst: self.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs,
+        }
+    }
+}
+
+/// JSON body of a 429 answer to a rate-limited request
+///
+/// Shaped like the providers' own error bodies so SDKs surface the message.
+pub fn rate_limited_body(endpoint: &str, decision: &RateDecision) -> serde_json::Value {
+    json!({
+        "error": {
+            "type": "rate_limit_exceeded",
+            "code": "yori_rate_limited",
+            "message": format!(
+                "YORI rate limit reached for {}: {} requests per minute (bursts of {}). Retry in {}s.",
+                endpoint,
+                decision.limit,
+                decision.burst,
+                decision.reset_secs.max(1)
+            ),
+            "endpoint": endpoint,
+            "limit_per_minute": decision.limit,
+            "burst": decision.burst,
+            "retry_after_secs": decision.reset_secs.max(1),
+        }
+    })
+}
+
+/// Allowance headers for a proxied (or blocked) response
+///
+/// `Retry-After` is only added to 429 responses, using the rate-limit reset
+/// time, or the seconds until the budget resets when the budget is spent.
+pub fn allowance_headers(
+    rate: Option<&RateDecision>,
+    budget_remaining: Option<u64>,
+    budget_reset_secs: Option<u64>,
+    status: u16,
+) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(rate) = rate {
+        headers.push((HEADER_RATELIMIT_LIMIT.to_string(), rate.limit.to_string()));
+        headers.push((
+            HEADER_RATELIMIT_REMAINING.to_string(),
+            rate.remaining.to_string(),
+        ));
+        headers.push((
+            HEADER_RATELIMIT_RESET.to_string(),
+            rate.reset_secs.to_string(),
+        ));
+    }
+    if let Some(budget) = budget_remaining {
+        headers.push((HEADER_BUDGET_REMAINING.to_string(), budget.to_string()));
+    }
+
+    if status == 429 {
+        let retry_after = match (rate, budget_remaining) {
+            (Some(rate), _) if !rate.allowed => Some(rate.reset_secs.max(1)),
+            (_, Some(0)) => budget_reset_secs,
+            _ => None,
+        };
+        if let Some(secs) = retry_after {
+            headers.push(("Retry-After".to_string(), secs.to_string()));
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_token_bucket_allows_burst_then_rate() {
+        let limiter = RateLimiter::new(30, 3);
+        let t0 = Utc::now();
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.check("192.168.1.50", "api.openai.com", t0);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let blocked = limiter.check("192.168.1.50", "api.openai.com", t0);
+        assert!(!blocked.allowed);
+        // 30/min refills one request every 2 seconds
+        assert_eq!(blocked.reset_secs, 2);
+
+        // Other endpoints and devices have their own buckets
+        assert!(
+            limiter
+                .check("192.168.1.50", "api.anthropic.com", t0)
+                .allowed
+        );
+        assert!(limiter.check("192.168.1.51", "api.openai.com", t0).allowed);
+
+        let t1 = t0 + Duration::seconds(2);
+        assert!(limiter.check("192.168.1.50", "api.openai.com", t1).allowed);
+        assert!(!limiter.check("192.168.1.50", "api.openai.com", t1).allowed);
+    }
+
+    #[test]
+    fn test_rate_limited_body() {
+        let decision = RateDecision {
+            allowed: false,
+            limit: 30,
+            burst: 5,
+            remaining: 0,
+            reset_secs: 2,
+        };
+        let body = rate_limited_body("api.openai.com", &decision);
+        assert_eq!(body["error"]["type"], "rate_limit_exceeded");
+        assert_eq!(body["error"]["retry_after_secs"], 2);
+        assert_eq!(body["error"]["burst"], 5);
+    }
+
+    #[test]
+    fn test_headers_and_retry_after() {
+        let blocked = RateDecision {
+            allowed: false,
+            limit: 30,
+            burst: 30,
+            remaining: 0,
+            reset_secs: 41,
+        };
+        let headers = allowance_headers(Some(&blocked), Some(1_750), Some(3_600), 429);
+        assert!(headers.contains(&("X-YORI-RateLimit-Remaining".to_string(), "0".to_string())));
+        assert!(headers.contains(&("X-YORI-Budget-Remaining".to_string(), "1750".to_string())));
+        assert!(headers.contains(&("Retry-After".to_string(), "41".to_string())));
+
+        let spent = allowance_headers(None, Some(0), Some(3_600), 429);
+        assert!(spent.contains(&("Retry-After".to_string(), "3600".to_string())));
+
+        let ok = allowance_headers(None, Some(0), Some(3_600), 200);
+        assert!(!ok.iter().any(|(name, _)| name == "Retry-After"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Configuration reload without a restart
+//!
+//! `kill -HUP` (or `POST /api/config/reload`) re-reads yori.toml and
+//! applies what a running gateway can change in place, leaving in-flight
+//! connections untouched:
+//!
+//! - `proxy.endpoints`, `proxy.mode`, `proxy.local_only`: for connections
+//!   accepted afterwards
+//! - `proxy.rate_limit_per_minute`, `proxy.rate_limit_burst`: buckets
+//!   start full under the new limit
+//! - `audit.*` (except `prune_interval_secs`, `rollup_interval_secs` and
+//!   the `buffer_events`/`spool_*` outage buffer): the audit database is reopened with the new settings and swapped in;
+//!   requests already logging finish on the old handle
+//!
+//! Every reload is reported as a diff of the settings that changed, split
+//! into applied and restart-required:
+//!
+//! ```json
+//! {
+//!   "changes": [{"setting": "proxy.mode", "old": "advisory", "new": "enforce"}],
+//!   "applied": ["proxy.mode"],
+//!   "restart_required": []
+//! }
+//! ```
+//!
+//! An invalid file changes nothing. Only settings that changed in the file
+//! are applied, so a mode switched through the admin API stays until the
+//! file's own mode is edited.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::audit::AuditLogger;
+use crate::config::YoriConfig;
+use crate::proxy::ProxyServer;
+
+/// Settings applied to a running proxy
+const LIVE_SETTINGS: &[&str] = &[
+    "proxy.endpoints",
+    "proxy.mode",
+    "proxy.local_only",
+    "proxy.rate_limit_per_minute",
+    "proxy.rate_limit_burst",
+];
+
+/// Audit settings that only the maintenance scheduler reads
+const SCHEDULER_SETTINGS: &[&str] = &["audit.prune_interval_secs", "audit.rollup_interval_secs"];
+
+/// Audit buffer settings, read once when the proxy is created
+const SPOOL_SETTINGS: &[&str] = &[
+    "audit.buffer_events",
+    "audit.spool_path",
+    "audit.spool_max_bytes",
+];
+
+/// One setting whose value differs between two configurations
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettingChange {
+    /// `section.setting`
+    pub setting: String,
+
+    /// Previous value (null if unset)
+    pub old: Value,
+
+    /// New value (null if unset)
+    pub new: Value,
+}
+
+/// Outcome of one reload
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReloadReport {
+    /// Every setting that changed
+    pub changes: Vec<SettingChange>,
+
+    /// Changed settings now in effect
+    pub applied: Vec<String>,
+
+    /// Changed settings that take effect on the next restart
+    pub restart_required: Vec<String>,
+}
+
+/// Settings that differ between `old` and `new`, by `section.setting`
+pub fn diff_config(old: &YoriConfig, new: &YoriConfig) -> Result<Vec<SettingChange>> {
+    let old = settings(old)?;
+    let new = settings(new)?;
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    Ok(names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| SettingChange {
+            setting: name.clone(),
+            old: old.get(name).cloned().unwrap_or(Value::Null),
+            new: new.get(name).cloned().unwrap_or(Value::Null),
+        })
+        .collect())
+}
+
+/// Flatten a configuration into `section.setting` values
+fn settings(config: &YoriConfig) -> Result<BTreeMap<String, Value>> {
+    let Value::Object(sections) = serde_json::to_value(config)? else {
+        return Ok(BTreeMap::new());
+    };
+    let mut flat = BTreeMap::new();
+    for (section, value) in sections {
+        match value {
+            Value::Object(settings) => {
+                for (setting, value) in settings {
+                    flat.insert(format!("{}.{}", section, setting), value);
+                }
+            }
+            value => {
+                flat.insert(section, value);
+            }
+        }
+    }
+    Ok(flat)
+}
+
+fn is_live(setting: &str) -> bool {
+    LIVE_SETTINGS.contains(&setting)
+        || (setting.starts_with("audit.")
+            && !SCHEDULER_SETTINGS.contains(&setting)
+            && !SPOOL_SETTINGS.contains(&setting))
+}
+
+/// Re-reads the configuration file and applies it to a running proxy
+#[derive(Debug)]
+pub struct ConfigReloader {
+    path: Option<PathBuf>,
+    current: Mutex<YoriConfig>,
+}
+
+impl ConfigReloader {
+    /// Reload from `path` (None = [`YoriConfig::load_default`]), starting
+    /// from the configuration the proxy is running with
+    pub fn new(path: Option<PathBuf>, current: YoriConfig) -> Self {
+        ConfigReloader {
+            path,
+            current: Mutex::new(current),
+        }
+    }
+
+    /// Read the configuration the proxy starts with
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let current = read(path.as_ref())?;
+        Ok(ConfigReloader::new(path, current))
+    }
+
+    /// Configuration last loaded
+    pub fn current(&self) -> YoriConfig {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Re-read the file and apply what changed to `proxy`
+    pub fn reload(&self, proxy: &ProxyServer) -> Result<ReloadReport> {
+        let new = read(self.path.as_ref())?;
+        self.apply(proxy, new)
+    }
+
+    /// Apply `new` to `proxy`, reporting what changed
+    ///
+    /// Nothing is changed if any part of `new` fails to apply.
+    pub fn apply(&self, proxy: &ProxyServer, new: YoriConfig) -> Result<ReloadReport> {
+        new.validate()?;
+        let mut current = self.current.lock().unwrap();
+        let changes = diff_config(&current, &new)?;
+        let changed = |setting: &str| changes.iter().any(|c| c.setting == setting);
+        let proxy_config = new.proxy_config()?;
+
+        // Fallible work first so a failure leaves the proxy as it was
+        let audit = match proxy.audit_logger() {
+            Some(_) if changes.iter().any(|c| c.setting.starts_with("audit.")) => {
+                let logger = AuditLogger::open(new.audit_config()?)
+                    .context("failed to reopen the audit database")?
+                    .with_live_tail(proxy.live_tail());
+                Some(Arc::new(logger))
+            }
+            _ => None,
+        };
+
+        if changed("proxy.endpoints") {
+            proxy.set_endpoints(proxy_config.endpoints.clone());
+        }
+        if changed("proxy.mode") {
+            proxy.set_mode(proxy_config.mode);
+        }
+        if changed("proxy.local_only") {
+            proxy.set_local_only(proxy_config.local_only);
+        }
+        if changed("proxy.rate_limit_per_minute") || changed("proxy.rate_limit_burst") {
+            proxy.set_rate_limit(
+                proxy_config.rate_limit_per_minute,
+                proxy_config.rate_limit_burst,
+            );
+        }
+        if let Some(audit) = audit {
+            proxy.set_audit_logger(audit);
+        }
+
+        let (applied, restart_required): (Vec<String>, Vec<String>) = changes
+            .iter()
+            .map(|c| c.setting.clone())
+            .partition(|setting| is_live(setting));
+        if changes.is_empty() {
+            tracing::info!("Configuration reloaded: no changes");
+        } else {
+            tracing::info!(
+                "Configuration reloaded: applied [{}], restart required for [{}]",
+                applied.join(", "),
+                restart_required.join(", ")
+            );
+        }
+        *current = new;
+        Ok(ReloadReport {
+            changes,
+            applied,
+            restart_required,
+        })
+    }
+
+    /// Reload on every SIGHUP until the task is dropped
+    #[cfg(unix)]
+    pub fn watch(self: Arc<Self>, proxy: Arc<ProxyServer>) -> Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                let reloader = Arc::clone(&self);
+                let proxy = Arc::clone(&proxy);
+                // Reopening the audit database touches the disk
+                let result = tokio::task::spawn_blocking(move || reloader.reload(&proxy)).await;
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => tracing::warn!("Configuration reload failed: {:#}", e),
+                    Err(e) => tracing::warn!("Configuration reload failed: {}", e),
+                }
+            }
+        }))
+    }
+}
+
+fn read(path: Option<&PathBuf>) -> Result<YoriConfig> {
+    match path {
+        Some(path) => YoriConfig::load(path),
+        None => YoriConfig::load_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditConfig;
+    use crate::proxy::{test_request, ProxyMode};
+
+    #[test]
+    fn test_diff_config() {
+        let old = YoriConfig::default();
+        let mut new = old.clone();
+        new.proxy.mode = "enforce".to_string();
+        new.audit.retention_days = 30;
+        let changes = diff_config(&old, &new).unwrap();
+        let settings: Vec<_> = changes.iter().map(|c| c.setting.as_str()).collect();
+        assert_eq!(settings, ["audit.retention_days", "proxy.mode"]);
+        assert_eq!(changes[1].old, "observe");
+        assert_eq!(changes[1].new, "enforce");
+        assert!(diff_config(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = YoriConfig::default();
+        config.audit.database = dir.path().join("audit.db");
+        let proxy = ProxyServer::new(config.proxy_config().unwrap());
+        let audit = AuditLogger::open(AuditConfig {
+            database: config.audit.database.clone(),
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        proxy.set_audit_logger(Arc::new(audit));
+        let reloader = ConfigReloader::new(None, config.clone());
+
+        let mut new = config.clone();
+        new.proxy.mode = "advisory".to_string();
+        new.proxy.endpoints = vec!["api.openai.com".to_string()];
+        new.proxy.rate_limit_per_minute = Some(1);
+        new.proxy.listen = "0.0.0.0:9443".to_string();
+        new.audit.retention_days = 7;
+        let report = reloader.apply(&proxy, new).unwrap();
+        assert_eq!(
+            report.applied,
+            [
+                "audit.retention_days",
+                "proxy.endpoints",
+                "proxy.mode",
+                "proxy.rate_limit_per_minute"
+            ]
+        );
+        assert_eq!(report.restart_required, ["proxy.listen"]);
+        assert_eq!(proxy.mode(), ProxyMode::Advisory);
+        assert_eq!(proxy.endpoints(), ["api.openai.com"]);
+        assert_eq!(proxy.audit_logger().unwrap().config().retention_days, 7);
+
+        let request = test_request();
+        assert!(proxy.check_rate_limit(&request).unwrap().allowed);
+        assert!(!proxy.check_rate_limit(&request).unwrap().allowed);
+
+        // An invalid configuration changes nothing
+        let mut bad = reloader.current();
+        bad.proxy.mode = "loud".to_string();
+        assert!(reloader.apply(&proxy, bad).is_err());
+        assert_eq!(proxy.mode(), ProxyMode::Advisory);
+        assert!(reloader
+            .apply(&proxy, reloader.current())
+            .unwrap()
+            .changes
+            .is_empty());
+    }
+}
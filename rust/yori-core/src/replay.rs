@@ -0,0 +1,423 @@
+//! Policy replay against recorded traffic
+//!
+//! Before switching a new policy set to enforce mode it helps to know what
+//! it would have done. [`PolicyReplay`] rebuilds the policy input of every
+//! recorded request in the audit log, evaluates it with a candidate
+//! [`PolicyEngine`] and reports where the candidate disagrees with what was
+//! recorded:
+//!
+//! ```python
+//! candidate = yori_core.PolicyEngine("/tmp/bedtime-v2")
+//! audit = yori_core.AuditLogger()
+//! report = audit.replay(candidate, since="2026-03-01T00:00:00Z")
+//! print(report["newly_blocked_count"], "requests would now be blocked")
+//! ```
+//!
+//! The input is rebuilt from what the log keeps: client, endpoint, model,
+//! timestamp (so `input.schedule` is the local time the request was made)
+//! and the prompt, taken from the canonical copy when it was de-duplicated.
+//! Stored previews are truncated and PII-redacted, and quota, budget and
+//! history counters are not reconstructed, so policies depending on those
+//! may decide differently than they would have live.
+//!
+//! Requests the proxy refused on its own (local-only mode, model lists,
+//! budget caps) are skipped: no policy set would change their outcome.
+//!
+//! # Warming
+//!
+//! [`PolicyReplay::warm`] runs the live engine over the most recent recorded
+//! requests at startup, rebuilt as if made now, so the morning rush after a
+//! restart finds the decision shortcut table (see [`crate::shortcut`])
+//! already filled instead of evaluating every request in full. Exact-input
+//! decisions are cached too, but only last the decision TTL.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::time::Instant;
+
+use crate::audit::{sql_to_json, AuditLogger, AuditQuery};
+use crate::budget::BUDGET_POLICY;
+use crate::dlp::DLP_POLICY;
+use crate::enrich::{
+    ClassifierEnricher, DeviceProfileEnricher, EnrichmentConfig, EnrichmentPipeline,
+    JailbreakEnricher, ScheduleEnricher,
+};
+use crate::models::MODEL_POLICY;
+use crate::policy::PolicyEngine;
+use crate::providers::{ParsedMessage, ParsedRequest};
+use crate::proxy::{RequestContext, LOCAL_ONLY_POLICY};
+
+/// Events read from the audit log per batch
+const REPLAY_BATCH_ROWS: usize = 500;
+
+/// Changed decisions listed per direction; the counts include the rest
+pub const MAX_REPLAY_CHANGES: usize = 500;
+
+/// Default number of recent audit events evaluated by [`PolicyReplay::warm`]
+pub const DEFAULT_WARM_EVENTS: usize = 1000;
+
+/// Policies recorded for refusals that don't come from the policy set
+const BUILTIN_POLICIES: [&str; 4] = [LOCAL_ONLY_POLICY, MODEL_POLICY, BUDGET_POLICY, DLP_POLICY];
+
+/// A recorded request the candidate policies decide differently
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayChange {
+    pub request_id: String,
+    pub timestamp: String,
+    pub client_ip: String,
+    pub client_device: Option<String>,
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub prompt_preview: Option<String>,
+
+    /// Recorded policy and result ("allow", "alert", "block")
+    pub recorded_policy: Option<String>,
+    pub recorded_result: Option<String>,
+
+    /// Policy and reason of the candidate's decision
+    pub policy: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Outcome of a [`PolicyReplay::replay`] run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReplayReport {
+    /// Requests evaluated with the candidate policies
+    pub replayed: u64,
+
+    /// Requests refused by the proxy itself, left out
+    pub skipped: u64,
+
+    /// Requests decided the same way as recorded
+    pub unchanged: u64,
+
+    /// Recorded as allowed, blocked by the candidate
+    pub newly_blocked_count: u64,
+
+    /// Recorded as blocked, allowed by the candidate
+    pub newly_allowed_count: u64,
+
+    /// The first [`MAX_REPLAY_CHANGES`] newly blocked requests
+    pub newly_blocked: Vec<ReplayChange>,
+
+    /// The first [`MAX_REPLAY_CHANGES`] newly allowed requests
+    pub newly_allowed: Vec<ReplayChange>,
+
+    /// Requests the candidate failed to evaluate, with the first error
+    pub errors: u64,
+    pub first_error: Option<String>,
+}
+
+/// Outcome of a [`PolicyReplay::warm`] run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WarmReport {
+    /// Recorded requests evaluated
+    pub evaluated: u64,
+
+    /// Requests that failed to evaluate
+    pub errors: u64,
+
+    /// Decision shortcuts held afterwards
+    pub shortcuts: usize,
+
+    pub duration_ms: u64,
+}
+
+/// Re-evaluates recorded requests with a candidate policy set
+pub struct PolicyReplay<'a> {
+    engine: &'a PolicyEngine,
+    pipeline: EnrichmentPipeline,
+}
+
+impl<'a> PolicyReplay<'a> {
+    /// Replay with the base input, `schedule` and `jailbreak` only
+    pub fn new(engine: &'a PolicyEngine) -> Self {
+        PolicyReplay::with_enrichment(engine, &EnrichmentConfig::default())
+    }
+
+    /// Replay adding the configured devices, schedules, tags and jailbreak
+    /// threshold to the input
+    pub fn with_enrichment(engine: &'a PolicyEngine, config: &EnrichmentConfig) -> Self {
+        let mut pipeline = EnrichmentPipeline::new();
+        pipeline.push(DeviceProfileEnricher::new(config.devices.clone()));
+        pipeline.push(ScheduleEnricher::new(config.schedules.clone()));
+        pipeline.push(ClassifierEnricher::new(config.tags.clone()));
+        if config.jailbreak.enabled {
+            pipeline.push(JailbreakEnricher::new(&config.jailbreak));
+        }
+        PolicyReplay { engine, pipeline }
+    }
+
+    /// Replay the recorded requests matching `query`, oldest first
+    ///
+    /// The query's event type and policy result filters are ignored; its
+    /// limit caps the events read.
+    pub fn replay(&self, audit: &AuditLogger, query: &AuditQuery) -> Result<ReplayReport> {
+        let query = AuditQuery {
+            event_type: None,
+            policy_result: None,
+            ..query.clone()
+        };
+        let columns = audit.columns()?;
+        let mut report = ReplayReport::default();
+        audit.scan(&query, REPLAY_BATCH_ROWS, |rows| {
+            for row in rows {
+                let record: Map<String, Value> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| (column.name.clone(), sql_to_json(value.into())))
+                    .collect();
+                self.replay_event(audit, &record, &mut report)?;
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Fill the engine's decision caches by evaluating the last `limit`
+    /// recorded requests, oldest first, as if they were made at `now`
+    pub fn warm(
+        &self,
+        audit: &AuditLogger,
+        limit: usize,
+        now: DateTime<Utc>,
+    ) -> Result<WarmReport> {
+        let started = Instant::now();
+        let records = audit.query(&AuditQuery {
+            limit: Some(limit),
+            ..AuditQuery::default()
+        })?;
+        let mut report = WarmReport::default();
+        for record in records.iter().rev() {
+            let Some(mut request) = self.decided_request(audit, record)? else {
+                continue;
+            };
+            // Schedules and hour-of-day fields should match the coming requests
+            request.timestamp = now;
+            match self.engine.evaluate_json(&self.input(&request, record)) {
+                Ok(_) => report.evaluated += 1,
+                Err(_) => report.errors += 1,
+            }
+        }
+        report.shortcuts = self.engine.shortcut_stats().entries;
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// The request a record describes, None for events the policy set
+    /// didn't decide
+    fn decided_request(
+        &self,
+        audit: &AuditLogger,
+        record: &Map<String, Value>,
+    ) -> Result<Option<RequestContext>> {
+        let text = |key: &str| record.get(key).and_then(Value::as_str);
+        if !matches!(text("event_type"), Some("request" | "block")) {
+            return Ok(None);
+        }
+        if text("policy_name").is_some_and(|p| BUILTIN_POLICIES.contains(&p)) {
+            return Ok(None);
+        }
+        let prompt = match record.get("prompt_ref").and_then(Value::as_i64) {
+            Some(prompt_ref) => audit
+                .canonical_prompt(prompt_ref)?
+                .or_else(|| text("prompt_preview").map(str::to_string)),
+            None => text("prompt_preview").map(str::to_string),
+        };
+        recorded_request(record, prompt).map(Some)
+    }
+
+    /// Policy input for a recorded request
+    fn input(&self, request: &RequestContext, record: &Map<String, Value>) -> Value {
+        let mut input = self.pipeline.build_input(request);
+        // Owners resolved from DHCP/ARP identity were recorded with the event
+        if let (Some(owner), Some(device)) = (
+            record.get("client_user").and_then(Value::as_str),
+            input.get_mut("device"),
+        ) {
+            if device["owner"].is_null() {
+                device["owner"] = owner.into();
+            }
+        }
+        input
+    }
+
+    fn replay_event(
+        &self,
+        audit: &AuditLogger,
+        record: &Map<String, Value>,
+        report: &mut ReplayReport,
+    ) -> Result<()> {
+        let text = |key: &str| record.get(key).and_then(Value::as_str).map(str::to_string);
+        if !matches!(text("event_type").as_deref(), Some("request" | "block")) {
+            return Ok(());
+        }
+        let Some(request) = self.decided_request(audit, record)? else {
+            report.skipped += 1;
+            return Ok(());
+        };
+        let recorded_policy = text("policy_name");
+        let recorded_result = text("policy_result");
+        let input = self.input(&request, record);
+
+        report.replayed += 1;
+        let decision = match self.engine.evaluate_json(&input) {
+            Ok(decision) => decision,
+            Err(e) => {
+                report.errors += 1;
+                report.first_error.get_or_insert_with(|| format!("{:#}", e));
+                return Ok(());
+            }
+        };
+        let was_blocked = recorded_result.as_deref() == Some("block");
+        let now_blocked = !decision["allow"].as_bool().unwrap_or(false);
+        if was_blocked == now_blocked {
+            report.unchanged += 1;
+            return Ok(());
+        }
+
+        let change = ReplayChange {
+            request_id: text("request_id").unwrap_or_default(),
+            timestamp: text("timestamp").unwrap_or_default(),
+            client_ip: request.client_ip,
+            client_device: request.client_device,
+            endpoint: request.endpoint,
+            model: request.parsed.and_then(|p| p.model),
+            prompt_preview: text("prompt_preview"),
+            recorded_policy,
+            recorded_result,
+            policy: decision["policy"].as_str().map(str::to_string),
+            reason: decision["reason"].as_str().map(str::to_string),
+        };
+        let (count, changes) = if now_blocked {
+            (&mut report.newly_blocked_count, &mut report.newly_blocked)
+        } else {
+            (&mut report.newly_allowed_count, &mut report.newly_allowed)
+        };
+        *count += 1;
+        if changes.len() < MAX_REPLAY_CHANGES {
+            changes.push(change);
+        }
+        Ok(())
+    }
+}
+
+/// Request context as far as an audit record describes it
+fn recorded_request(record: &Map<String, Value>, prompt: Option<String>) -> Result<RequestContext> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(str::to_string);
+    let timestamp = text("timestamp").unwrap_or_default();
+    let timestamp = DateTime::parse_from_rfc3339(&timestamp)
+        .map_err(|e| anyhow::anyhow!("invalid timestamp {:?}: {}", timestamp, e))?
+        .with_timezone(&Utc);
+    let parsed = ParsedRequest {
+        model: text("requested_model"),
+        prompt: prompt.clone().unwrap_or_default(),
+        messages: prompt
+            .iter()
+            .map(|content| ParsedMessage {
+                role: "user".to_string(),
+                content: content.clone(),
+            })
+            .collect(),
+        ..ParsedRequest::default()
+    };
+    Ok(RequestContext {
+        client_ip: text("client_ip").unwrap_or_default(),
+        tenant: text("tenant").unwrap_or_else(|| "default".to_string()),
+        scope: None,
+        client_device: text("client_device"),
+        identity: None,
+        endpoint: text("endpoint").unwrap_or_default(),
+        destination: text("destination").and_then(|d| d.parse().ok()),
+        method: text("http_method").unwrap_or_default(),
+        path: text("http_path").unwrap_or_default(),
+        user_agent: text("user_agent"),
+        prompt_preview: prompt,
+        timestamp,
+        retry_of: text("retry_of"),
+        session_id: text("session_id"),
+        categories: text("categories")
+            .map(|c| c.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+        parsed: Some(parsed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::tests::{memory_logger, request};
+    use crate::audit::AuditEventType;
+    use std::fs;
+
+    #[test]
+    fn test_reports_changed_decisions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("homework.rego"),
+            "package yori.homework\n\n\
+             default allow := true\n\n\
+             allow := false if contains(input.prompt_preview, \"essay\")\n\n\
+             reason := \"No essay writing\" if not allow\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+
+        let audit = memory_logger();
+        audit
+            .log(&request("write my essay about volcanoes"))
+            .unwrap();
+        audit.log(&request("what rhymes with orange")).unwrap();
+        let mut blocked = request("tell me a joke").with_policy("old", "block", "no jokes");
+        blocked.event_type = AuditEventType::RequestBlocked;
+        audit.log(&blocked).unwrap();
+        let mut local_only = request("hello").with_policy(LOCAL_ONLY_POLICY, "block", "");
+        local_only.event_type = AuditEventType::RequestBlocked;
+        audit.log(&local_only).unwrap();
+
+        let report = PolicyReplay::new(&engine)
+            .replay(&audit, &AuditQuery::default())
+            .unwrap();
+        assert_eq!(
+            (report.replayed, report.skipped, report.unchanged),
+            (3, 1, 1)
+        );
+        assert_eq!(report.newly_blocked_count, 1);
+        assert_eq!(
+            report.newly_blocked[0].prompt_preview.as_deref(),
+            Some("write my essay about volcanoes")
+        );
+        assert_eq!(report.newly_allowed_count, 1);
+        assert_eq!(
+            report.newly_allowed[0].recorded_policy.as_deref(),
+            Some("old")
+        );
+        assert_eq!(report.errors, 0);
+    }
+
+    #[test]
+    fn test_warm_fills_shortcut_table() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("endpoints.rego"),
+            "package yori.endpoints\n\n\
+             default allow := true\n\n\
+             allow := false if input.endpoint == \"api.anthropic.com\"\n",
+        )
+        .unwrap();
+        let engine = PolicyEngine::open(dir.path()).unwrap();
+        let audit = memory_logger();
+        for prompt in ["first", "second", "third"] {
+            audit.log(&request(prompt)).unwrap();
+        }
+
+        let report = PolicyReplay::new(&engine)
+            .warm(&audit, DEFAULT_WARM_EVENTS, Utc::now())
+            .unwrap();
+        assert_eq!((report.evaluated, report.errors), (3, 0));
+        assert_eq!(report.shortcuts, 1);
+        assert_eq!(engine.shortcut_stats().hits, 2);
+    }
+}
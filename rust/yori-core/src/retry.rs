@@ -0,0 +1,248 @@
+//! Duplicate and retry request detection
+//!
+//! On flaky Wi-Fi, LLM clients retry aggressively: the same chat request
+//! can arrive three or four times within seconds. Counting each copy
+//! inflates usage statistics and burns through budgets. The detector keys
+//! requests by (device, SHA-256 of method + endpoint + path + body) and
+//! flags any repeat inside a short window as a retry of the first copy.
+//!
+//! Optionally, the first copy's successful response is kept so retries can
+//! be answered locally without another upstream call.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Default window in which an identical request counts as a retry
+pub const DEFAULT_RETRY_WINDOW_SECS: u64 = 10;
+
+/// Largest response body kept for answering retries
+pub const MAX_CACHED_BODY_BYTES: usize = 256 * 1024;
+
+/// Maximum number of requests tracked at once
+const MAX_TRACKED: usize = 4096;
+
+/// Fingerprint of a request
+pub type RequestHash = [u8; 32];
+
+/// Hash the parts of a request that make two requests "the same"
+pub fn request_hash(method: &str, endpoint: &str, path: &str, body: &[u8]) -> RequestHash {
+    let mut hasher = Sha256::new();
+    for part in [method.as_bytes(), endpoint.as_bytes(), path.as_bytes()] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+/// Upstream response kept for answering retries (and by the prompt cache)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// HTTP status
+    pub status: u16,
+
+    /// Response headers
+    pub headers: Vec<(String, String)>,
+
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+/// Result of checking a request against recent ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetryCheck {
+    /// First time this request was seen in the window
+    Original,
+
+    /// Repeat of an earlier request
+    Retry {
+        /// Request id of the first copy
+        original_request_id: String,
+
+        /// Attempt number (2 for the first retry)
+        attempt: u32,
+
+        /// The first copy's response, if caching is enabled and it succeeded
+        cached: Option<CachedResponse>,
+    },
+}
+
+#[derive(Debug)]
+struct Tracked {
+    first_seen: DateTime<Utc>,
+    request_id: String,
+    attempts: u32,
+    response: Option<CachedResponse>,
+}
+
+/// Tracks recent requests per device
+#[derive(Debug)]
+pub struct RetryDetector {
+    window: Duration,
+    serve_cached: bool,
+    recent: Mutex<HashMap<(String, RequestHash), Tracked>>,
+}
+
+impl Default for RetryDetector {
+    fn default() -> Self {
+        RetryDetector::new(DEFAULT_RETRY_WINDOW_SECS, false)
+    }
+}
+
+impl RetryDetector {
+    /// Create a detector with the given window; `serve_cached` keeps
+    /// successful responses for answering retries
+    pub fn new(window_secs: u64, serve_cached: bool) -> Self {
+        RetryDetector {
+            window: Duration::seconds(window_secs as i64),
+            serve_cached,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether retries may be answered from the cached response
+    pub fn serves_cached(&self) -> bool {
+        self.serve_cached
+    }
+
+    /// Check a request, registering it as the original if it's new
+    pub fn check(
+        &self,
+        device: &str,
+        hash: RequestHash,
+        request_id: &str,
+        now: DateTime<Utc>,
+    ) -> RetryCheck {
+        let mut recent = self.recent.lock().unwrap();
+        let key = (device.to_string(), hash);
+
+        if let Some(tracked) = recent.get_mut(&key) {
+            if now - tracked.first_seen <= self.window {
+                tracked.attempts += 1;
+                return RetryCheck::Retry {
+                    original_request_id: tracked.request_id.clone(),
+                    attempt: tracked.attempts,
+                    cached: tracked.response.clone(),
+                };
+            }
+        }
+
+        if recent.len() >= MAX_TRACKED {
+            let cutoff = now - self.window;
+            recent.retain(|_, t| t.first_seen >= cutoff);
+            if recent.len() >= MAX_TRACKED {
+                // Still full of fresh entries: drop the oldest
+                if let Some(oldest) = recent
+                    .iter()
+                    .min_by_key(|(_, t)| t.first_seen)
+                    .map(|(k, _)| k.clone())
+                {
+                    recent.remove(&oldest);
+                }
+            }
+        }
+
+        recent.insert(
+            key,
+            Tracked {
+                first_seen: now,
+                request_id: request_id.to_string(),
+                attempts: 1,
+                response: None,
+            },
+        );
+        RetryCheck::Original
+    }
+
+    /// Keep the original request's response for answering its retries
+    ///
+    /// Ignored unless caching is enabled, the response succeeded (2xx) and
+    /// the body is small enough.
+    pub fn store_response(&self, device: &str, hash: RequestHash, response: CachedResponse) {
+        if !self.serve_cached
+            || !(200..300).contains(&response.status)
+            || response.body.len() > MAX_CACHED_BODY_BYTES
+        {
+            return;
+        }
+        if let Some(tracked) = self
+            .recent
+            .lock()
+            .unwrap()
+            .get_mut(&(device.to_string(), hash))
+        {
+            tracked.response = Some(response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_within_window() {
+        let detector = RetryDetector::new(10, false);
+        let body = br#"{"model":"gpt-4o","messages":[{"role":"user","content":"hi"}]}"#;
+        let hash = request_hash("POST", "api.openai.com", "/v1/chat/completions", body);
+        let t0 = Utc::now();
+
+        assert_eq!(
+            detector.check("ipad", hash, "req-1", t0),
+            RetryCheck::Original
+        );
+        assert_eq!(
+            detector.check("ipad", hash, "req-2", t0 + Duration::seconds(3)),
+            RetryCheck::Retry {
+                original_request_id: "req-1".to_string(),
+                attempt: 2,
+                cached: None,
+            }
+        );
+        // Another device sending the same body is not a retry
+        assert_eq!(
+            detector.check("laptop", hash, "req-3", t0),
+            RetryCheck::Original
+        );
+        // Outside the window it's a fresh request again
+        assert_eq!(
+            detector.check("ipad", hash, "req-4", t0 + Duration::seconds(30)),
+            RetryCheck::Original
+        );
+    }
+
+    #[test]
+    fn test_cached_response_served_to_retries() {
+        let detector = RetryDetector::new(10, true);
+        let hash = request_hash("POST", "api.openai.com", "/v1/chat/completions", b"{}");
+        let t0 = Utc::now();
+        let ok = CachedResponse {
+            status: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: b"{\"id\":\"chatcmpl-1\"}".to_vec(),
+        };
+
+        detector.check("ipad", hash, "req-1", t0);
+        detector.store_response(
+            "ipad",
+            hash,
+            CachedResponse {
+                status: 502,
+                ..ok.clone()
+            },
+        );
+        assert!(matches!(
+            detector.check("ipad", hash, "req-2", t0),
+            RetryCheck::Retry { cached: None, .. }
+        ));
+
+        detector.store_response("ipad", hash, ok.clone());
+        assert!(matches!(
+            detector.check("ipad", hash, "req-3", t0),
+            RetryCheck::Retry { cached: Some(ref r), attempt: 3, .. } if *r == ok
+        ));
+    }
+}
@@ -0,0 +1,464 @@
+//! Daily and weekly usage rollups
+//!
+//! A year of household traffic is millions of audit events; charting it
+//! from `audit_events` means scanning all of them on every dashboard load,
+//! and events past `audit.retention_days` are gone anyway. The maintenance
+//! scheduler folds events into two small tables in the audit database
+//! instead, one row per bucket, tenant, user, device and provider:
+//!
+//! - `usage_rollups`: requests, blocks, prompt/response tokens and
+//!   estimated cost per UTC day (`period = 'day'`) and per ISO week
+//!   starting Monday (`period = 'week'`)
+//! - `usage_rollup_state`: per period, the open bucket (today, this week)
+//!   that the next refresh rebuilds from
+//!
+//! Closed buckets are never rebuilt, so they outlive the raw events.
+//! Requests count `request`, `block` and `rate_limited` events; tokens and
+//! cost come from responses, as in [`crate::audit::AuditLogger::cost_summary`].
+//! Retries are left out of both.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::providers::provider_for_host;
+
+/// Rollup tables, created alongside the audit schema
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS usage_rollups (
+    period TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    tenant TEXT NOT NULL,
+    user TEXT NOT NULL,
+    device TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    blocks INTEGER NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    response_tokens INTEGER NOT NULL,
+    cost_usd REAL NOT NULL,
+    PRIMARY KEY (period, bucket, tenant, user, device, provider)
+);
+
+CREATE TABLE IF NOT EXISTS usage_rollup_state (
+    period TEXT PRIMARY KEY,
+    open_bucket TEXT NOT NULL,
+    refreshed_at TEXT NOT NULL
+);
+";
+
+/// Width of a rollup bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupPeriod {
+    /// UTC day
+    Day,
+
+    /// ISO week, starting Monday
+    Week,
+}
+
+impl RollupPeriod {
+    /// Every period, in refresh order
+    pub const ALL: [RollupPeriod; 2] = [RollupPeriod::Day, RollupPeriod::Week];
+
+    /// Value stored in the `period` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RollupPeriod::Day => "day",
+            RollupPeriod::Week => "week",
+        }
+    }
+
+    /// SQL expression over `audit_events` for the bucket start date
+    fn sql_bucket(&self) -> &'static str {
+        match self {
+            RollupPeriod::Day => "substr(timestamp, 1, 10)",
+            RollupPeriod::Week => {
+                "date(substr(timestamp, 1, 10),
+                      '-' || ((CAST(strftime('%w', substr(timestamp, 1, 10)) AS INTEGER) + 6) % 7)
+                          || ' days')"
+            }
+        }
+    }
+
+    /// Start date of the bucket holding `time`
+    pub fn bucket_start(&self, time: DateTime<Utc>) -> NaiveDate {
+        let day = time.date_naive();
+        match self {
+            RollupPeriod::Day => day,
+            RollupPeriod::Week => {
+                day - ChronoDuration::days(day.weekday().num_days_from_monday() as i64)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for RollupPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "day" | "daily" => Ok(RollupPeriod::Day),
+            "week" | "weekly" => Ok(RollupPeriod::Week),
+            _ => bail!("unknown rollup period {:?} (expected day or week)", s),
+        }
+    }
+}
+
+/// Dimension rollups are broken down by when read back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupGrouping {
+    /// Device owner (falling back to the device, then the client IP)
+    User,
+
+    /// Device name (falling back to the client IP)
+    Device,
+
+    /// Provider serving the endpoint ("openai", "anthropic", ...)
+    Provider,
+}
+
+impl RollupGrouping {
+    /// Grouping name as used by the Python API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RollupGrouping::User => "user",
+            RollupGrouping::Device => "device",
+            RollupGrouping::Provider => "provider",
+        }
+    }
+}
+
+impl std::str::FromStr for RollupGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "user" => Ok(RollupGrouping::User),
+            "device" => Ok(RollupGrouping::Device),
+            "provider" => Ok(RollupGrouping::Provider),
+            _ => bail!(
+                "unknown grouping {:?} (expected user, device or provider)",
+                s
+            ),
+        }
+    }
+}
+
+/// Which rollups to read back
+#[derive(Debug, Clone)]
+pub struct RollupQuery {
+    pub period: RollupPeriod,
+
+    /// Buckets starting on or after this date
+    pub since: Option<NaiveDate>,
+
+    /// Buckets starting before this date
+    pub until: Option<NaiveDate>,
+
+    pub tenant: Option<String>,
+
+    /// Break each bucket down by user, device or provider (None = one
+    /// total per bucket and tenant)
+    pub group_by: Option<RollupGrouping>,
+}
+
+impl Default for RollupQuery {
+    fn default() -> Self {
+        RollupQuery {
+            period: RollupPeriod::Day,
+            since: None,
+            until: None,
+            tenant: None,
+            group_by: None,
+        }
+    }
+}
+
+/// Usage within one bucket
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageRollup {
+    /// Bucket start date ("2026-01-26")
+    pub bucket: String,
+
+    pub tenant: String,
+
+    /// User, device or provider when grouped
+    pub key: Option<String>,
+
+    pub requests: u64,
+    pub blocks: u64,
+    pub prompt_tokens: u64,
+    pub response_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Outcome of one refresh
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RollupReport {
+    /// Rollup rows written (open and newly closed buckets)
+    pub rows_written: u64,
+
+    /// Wall-clock time of the refresh
+    pub duration_ms: u64,
+}
+
+/// Create the rollup tables if missing
+pub(crate) fn ensure_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    Ok(())
+}
+
+/// Row key before aggregation by provider
+type RollupKey = (String, String, String, String, String);
+
+/// Rebuild every bucket from each period's open bucket through `now`
+pub(crate) fn refresh(conn: &Connection, now: DateTime<Utc>) -> Result<RollupReport> {
+    let started = Instant::now();
+    let tx = conn.unchecked_transaction()?;
+    let mut rows_written = 0;
+    for period in RollupPeriod::ALL {
+        let bucket = period.sql_bucket();
+        let open: Option<String> = tx
+            .query_row(
+                "SELECT open_bucket FROM usage_rollup_state WHERE period = ?1",
+                [period.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        // First refresh: start from the oldest event still kept
+        let from = match open {
+            Some(open) => Some(open),
+            None => tx.query_row(
+                &format!("SELECT MIN({bucket}) FROM audit_events"),
+                [],
+                |row| row.get(0),
+            )?,
+        };
+
+        if let Some(from) = &from {
+            tx.execute(
+                "DELETE FROM usage_rollups WHERE period = ?1 AND bucket >= ?2",
+                params![period.as_str(), from],
+            )?;
+            let mut totals: BTreeMap<RollupKey, UsageRollup> = BTreeMap::new();
+            let mut stmt = tx.prepare(&format!(
+                "SELECT {bucket}, tenant,
+                        COALESCE(client_user, client_device, client_ip),
+                        COALESCE(client_device, client_ip),
+                        endpoint,
+                        SUM(CASE WHEN event_type IN ('request', 'block', 'rate_limited')
+                                  AND retry_of IS NULL THEN 1 ELSE 0 END),
+                        SUM(CASE WHEN event_type IN ('block', 'response_block')
+                                 THEN 1 ELSE 0 END),
+                        COALESCE(SUM(CASE WHEN {RESPONSE} THEN prompt_tokens END), 0),
+                        COALESCE(SUM(CASE WHEN {RESPONSE} THEN response_tokens END), 0),
+                        COALESCE(SUM(CASE WHEN {RESPONSE} THEN estimated_cost END), 0.0)
+                 FROM audit_events
+                 WHERE timestamp >= ?1
+                 GROUP BY 1, 2, 3, 4, 5"
+            ))?;
+            let mut rows = stmt.query([from])?;
+            while let Some(row) = rows.next()? {
+                let endpoint: String = row.get(4)?;
+                let provider = provider_for_host(&endpoint)
+                    .map_or_else(|| endpoint.clone(), |p| p.as_str().to_string());
+                let key = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, provider);
+                let total = totals.entry(key).or_default();
+                total.requests += row.get::<_, i64>(5)? as u64;
+                total.blocks += row.get::<_, i64>(6)? as u64;
+                total.prompt_tokens += row.get::<_, i64>(7)? as u64;
+                total.response_tokens += row.get::<_, i64>(8)? as u64;
+                total.cost_usd += row.get::<_, f64>(9)?;
+            }
+            drop(rows);
+            drop(stmt);
+
+            let mut insert = tx.prepare(
+                "INSERT INTO usage_rollups (period, bucket, tenant, user, device, provider,
+                     requests, blocks, prompt_tokens, response_tokens, cost_usd)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for ((bucket, tenant, user, device, provider), total) in &totals {
+                insert.execute(params![
+                    period.as_str(),
+                    bucket,
+                    tenant,
+                    user,
+                    device,
+                    provider,
+                    total.requests as i64,
+                    total.blocks as i64,
+                    total.prompt_tokens as i64,
+                    total.response_tokens as i64,
+                    total.cost_usd,
+                ])?;
+            }
+            rows_written += totals.len() as u64;
+        }
+
+        tx.execute(
+            "INSERT INTO usage_rollup_state (period, open_bucket, refreshed_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(period) DO UPDATE SET
+                 open_bucket = excluded.open_bucket,
+                 refreshed_at = excluded.refreshed_at",
+            params![
+                period.as_str(),
+                period.bucket_start(now).to_string(),
+                now.to_rfc3339()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(RollupReport {
+        rows_written,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Response rows carrying tokens or a cost, retries left out
+const RESPONSE: &str =
+    "retry_of IS NULL AND (estimated_cost IS NOT NULL OR response_tokens IS NOT NULL)";
+
+/// Rollups matching `query`, oldest bucket first
+pub(crate) fn query(conn: &Connection, query: &RollupQuery) -> Result<Vec<UsageRollup>> {
+    let mut clause = String::from("period = ?1");
+    let mut args: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(query.period.as_str())];
+    let mut filter = |condition: &str, value: Box<dyn rusqlite::ToSql>| {
+        args.push(value);
+        clause.push_str(&format!(" AND {condition} ?{}", args.len()));
+    };
+    if let Some(since) = query.since {
+        filter("bucket >=", Box::new(since.to_string()));
+    }
+    if let Some(until) = query.until {
+        filter("bucket <", Box::new(until.to_string()));
+    }
+    if let Some(tenant) = &query.tenant {
+        filter("tenant =", Box::new(tenant.clone()));
+    }
+    // Grouping names double as column names
+    let key = query.group_by.map_or("NULL", |g| g.as_str());
+    let sql = format!(
+        "SELECT bucket, tenant, {key},
+                SUM(requests), SUM(blocks), SUM(prompt_tokens), SUM(response_tokens),
+                SUM(cost_usd)
+         FROM usage_rollups
+         WHERE {clause}
+         GROUP BY 1, 2, 3
+         ORDER BY 1, 2, 3"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+        Ok(UsageRollup {
+            bucket: row.get(0)?,
+            tenant: row.get(1)?,
+            key: row.get(2)?,
+            requests: row.get::<_, i64>(3)? as u64,
+            blocks: row.get::<_, i64>(4)? as u64,
+            prompt_tokens: row.get::<_, i64>(5)? as u64,
+            response_tokens: row.get::<_, i64>(6)? as u64,
+            cost_usd: row.get(7)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::tests::{memory_logger, request};
+    use crate::audit::{AuditEventType, AuditLogger};
+    use chrono::TimeZone;
+
+    fn log_at(logger: &AuditLogger, time: DateTime<Utc>, device: &str, blocked: bool) {
+        let mut event = request("hello");
+        event.timestamp = time;
+        event.client_device = Some(device.to_string());
+        if blocked {
+            event.event_type = AuditEventType::RequestBlocked;
+        }
+        logger.log(&event).unwrap();
+        if !blocked {
+            let mut response = request("hello");
+            response.timestamp = time;
+            response.client_device = Some(device.to_string());
+            response.event_type = AuditEventType::Response;
+            response.prompt_tokens = Some(10);
+            response.response_tokens = Some(90);
+            response.estimated_cost = Some(0.01);
+            logger.log(&response).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_week_starts_monday() {
+        // 2026-03-05 is a Thursday
+        let thursday = Utc.with_ymd_and_hms(2026, 3, 5, 12, 0, 0).unwrap();
+        assert_eq!(
+            RollupPeriod::Week.bucket_start(thursday).to_string(),
+            "2026-03-02"
+        );
+        assert_eq!(
+            RollupPeriod::Day.bucket_start(thursday).to_string(),
+            "2026-03-05"
+        );
+        assert_eq!(
+            "weekly".parse::<RollupPeriod>().unwrap(),
+            RollupPeriod::Week
+        );
+        assert!("monthly".parse::<RollupPeriod>().is_err());
+    }
+
+    #[test]
+    fn test_refresh_and_query() {
+        let logger = memory_logger();
+        let day = |d: u32, h: u32| Utc.with_ymd_and_hms(2026, 3, d, h, 0, 0).unwrap();
+        log_at(&logger, day(2, 9), "kids-ipad", false);
+        log_at(&logger, day(2, 21), "kids-ipad", true);
+        log_at(&logger, day(5, 9), "laptop", false);
+
+        logger.refresh_rollups(day(5, 10)).unwrap();
+        let daily = logger
+            .usage_rollups(&RollupQuery {
+                group_by: Some(RollupGrouping::Device),
+                ..RollupQuery::default()
+            })
+            .unwrap();
+        let buckets: Vec<_> = daily
+            .iter()
+            .map(|r| (r.bucket.as_str(), r.key.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            buckets,
+            [("2026-03-02", "kids-ipad"), ("2026-03-05", "laptop")]
+        );
+        assert_eq!((daily[0].requests, daily[0].blocks), (2, 1));
+        assert_eq!(daily[0].response_tokens, 90);
+
+        let weekly = logger
+            .usage_rollups(&RollupQuery {
+                period: RollupPeriod::Week,
+                group_by: Some(RollupGrouping::Provider),
+                ..RollupQuery::default()
+            })
+            .unwrap();
+        assert_eq!(weekly.len(), 1);
+        assert_eq!(weekly[0].key.as_deref(), Some("openai"));
+        assert_eq!(weekly[0].requests, 3);
+        assert!((weekly[0].cost_usd - 0.02).abs() < 1e-9);
+
+        // Closed buckets are left alone; the open one picks up new events
+        log_at(&logger, day(2, 22), "kids-ipad", false);
+        log_at(&logger, day(5, 11), "laptop", false);
+        logger.refresh_rollups(day(5, 12)).unwrap();
+        let daily = logger.usage_rollups(&RollupQuery::default()).unwrap();
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].requests, 2);
+        assert_eq!(daily[1].requests, 2);
+    }
+}
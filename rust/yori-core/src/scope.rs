@@ -0,0 +1,246 @@
+//! Subnet and VLAN based policy scoping
+//!
+//! Home networks are usually already segmented: an IoT VLAN, a kids VLAN,
+//! an adults VLAN. Scopes let YORI treat each segment differently (block
+//! the IoT VLAN outright, restrict the kids VLAN, observe-only for adults)
+//! and are resolved from the peer address when a connection is accepted,
+//! before any TLS or body parsing happens.
+
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::proxy::ProxyMode;
+
+/// A named network segment with its own treatment
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkScope {
+    /// Scope name (e.g., "iot", "kids", "adults")
+    pub name: String,
+
+    /// Source networks belonging to the scope
+    pub networks: Vec<IpNet>,
+
+    /// Ingress interface (e.g., "vlan0.30"); when set, the interface must
+    /// match as well as the network
+    pub interface: Option<String>,
+
+    /// Reject every LLM connection from this scope
+    pub block_all: bool,
+
+    /// Mode override for this scope (e.g., observe-only for adults)
+    pub mode: Option<ProxyMode>,
+
+    /// Policy/quota profile name exposed to policies as `input.scope.profile`
+    pub profile: Option<String>,
+}
+
+/// Outcome of classifying a new connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeDecision {
+    /// Close the connection immediately
+    Reject {
+        /// Scope that blocks the client
+        scope: String,
+    },
+
+    /// Continue processing with the given effective mode
+    Accept {
+        /// Matching scope, if any
+        scope: Option<String>,
+
+        /// Effective proxy mode for this connection
+        mode: ProxyMode,
+
+        /// Policy/quota profile for this connection
+        profile: Option<String>,
+    },
+}
+
+/// Prefix table for fast scope lookup at accept time
+///
+/// Networks are bucketed by prefix length, so a lookup costs one hash probe
+/// per distinct prefix length configured (typically two or three) rather
+/// than a scan over every network.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeMatcher {
+    scopes: Vec<NetworkScope>,
+    // prefix length (descending) -> masked network -> scope indices
+    v4: Vec<(u8, HashMap<IpNet, Vec<usize>>)>,
+    v6: Vec<(u8, HashMap<IpNet, Vec<usize>>)>,
+}
+
+impl ScopeMatcher {
+    /// Build a matcher from configured scopes
+    ///
+    /// Earlier scopes win when two scopes share the same network.
+    pub fn new(scopes: Vec<NetworkScope>) -> Self {
+        let mut v4: HashMap<u8, HashMap<IpNet, Vec<usize>>> = HashMap::new();
+        let mut v6: HashMap<u8, HashMap<IpNet, Vec<usize>>> = HashMap::new();
+
+        for (idx, scope) in scopes.iter().enumerate() {
+            for net in &scope.networks {
+                let table = match net {
+                    IpNet::V4(_) => &mut v4,
+                    IpNet::V6(_) => &mut v6,
+                };
+                table
+                    .entry(net.prefix_len())
+                    .or_default()
+                    .entry(net.trunc())
+                    .or_default()
+                    .push(idx);
+            }
+        }
+
+        let sorted = |table: HashMap<u8, HashMap<IpNet, Vec<usize>>>| {
+            let mut levels: Vec<_> = table.into_iter().collect();
+            levels.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+            levels
+        };
+
+        ScopeMatcher {
+            scopes,
+            v4: sorted(v4),
+            v6: sorted(v6),
+        }
+    }
+
+    /// Configured scopes
+    pub fn scopes(&self) -> &[NetworkScope] {
+        &self.scopes
+    }
+
+    /// Find the most specific scope for a peer address and ingress interface
+    pub fn lookup(&self, ip: IpAddr, interface: Option<&str>) -> Option<&NetworkScope> {
+        let ip = ip.to_canonical();
+        let levels = match ip {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        };
+
+        for (prefix_len, table) in levels {
+            let Ok(net) = IpNet::new(ip, *prefix_len) else {
+                continue;
+            };
+            if let Some(indices) = table.get(&net.trunc()) {
+                let found = indices
+                    .iter()
+                    .map(|i| &self.scopes[*i])
+                    .find(|s| s.interface.is_none() || s.interface.as_deref() == interface);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+        None
+    }
+
+    /// Classify a new connection, applying scope overrides to the global mode
+    pub fn classify(
+        &self,
+        ip: IpAddr,
+        interface: Option<&str>,
+        default_mode: ProxyMode,
+    ) -> ScopeDecision {
+        match self.lookup(ip, interface) {
+            Some(scope) if scope.block_all => ScopeDecision::Reject {
+                scope: scope.name.clone(),
+            },
+            Some(scope) => ScopeDecision::Accept {
+                scope: Some(scope.name.clone()),
+                mode: scope.mode.unwrap_or(default_mode),
+                profile: scope.profile.clone(),
+            },
+            None => ScopeDecision::Accept {
+                scope: None,
+                mode: default_mode,
+                profile: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(name: &str, nets: &[&str]) -> NetworkScope {
+        NetworkScope {
+            name: name.to_string(),
+            networks: nets.iter().map(|n| n.parse().unwrap()).collect(),
+            interface: None,
+            block_all: false,
+            mode: None,
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn test_classify_vlan_scopes() {
+        let matcher = ScopeMatcher::new(vec![
+            NetworkScope {
+                block_all: true,
+                ..scope("iot", &["192.168.30.0/24"])
+            },
+            NetworkScope {
+                profile: Some("kids".to_string()),
+                mode: Some(ProxyMode::Enforce),
+                ..scope("kids", &["192.168.20.0/24"])
+            },
+            NetworkScope {
+                mode: Some(ProxyMode::Observe),
+                ..scope("lan", &["192.168.0.0/16"])
+            },
+        ]);
+
+        assert_eq!(
+            matcher.classify("192.168.30.9".parse().unwrap(), None, ProxyMode::Advisory),
+            ScopeDecision::Reject {
+                scope: "iot".to_string()
+            }
+        );
+        assert_eq!(
+            matcher.classify("192.168.20.5".parse().unwrap(), None, ProxyMode::Advisory),
+            ScopeDecision::Accept {
+                scope: Some("kids".to_string()),
+                mode: ProxyMode::Enforce,
+                profile: Some("kids".to_string()),
+            }
+        );
+        assert_eq!(
+            matcher.classify("192.168.1.5".parse().unwrap(), None, ProxyMode::Advisory),
+            ScopeDecision::Accept {
+                scope: Some("lan".to_string()),
+                mode: ProxyMode::Observe,
+                profile: None,
+            }
+        );
+        assert_eq!(
+            matcher.classify("10.0.0.1".parse().unwrap(), None, ProxyMode::Advisory),
+            ScopeDecision::Accept {
+                scope: None,
+                mode: ProxyMode::Advisory,
+                profile: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_interface_constraint() {
+        let matcher = ScopeMatcher::new(vec![NetworkScope {
+            interface: Some("vlan0.20".to_string()),
+            ..scope("kids-wifi", &["0.0.0.0/0"])
+        }]);
+
+        let ip = "192.168.1.10".parse().unwrap();
+        assert_eq!(
+            matcher
+                .lookup(ip, Some("vlan0.20"))
+                .map(|s| s.name.as_str()),
+            Some("kids-wifi")
+        );
+        assert!(matcher.lookup(ip, Some("igb0")).is_none());
+        assert!(matcher.lookup(ip, None).is_none());
+    }
+}
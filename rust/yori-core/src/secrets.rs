@@ -0,0 +1,388 @@
+//! Provider API keys held by the gateway
+//!
+//! With a key stored for a provider, family members point their apps at
+//! YORI with any placeholder key and the proxy puts the real one on the
+//! request as it forwards it. Nobody but the router ever holds the key, so
+//! a lost tablet or a curious teenager can't walk off with it, and rotating
+//! it is one command on the router.
+//!
+//! Keys are kept in a JSON file (`/var/db/yori/api-keys.json` by default),
+//! each sealed with ChaCha20-Poly1305 under a key derived from the
+//! [`crate::vault`] master key; the provider name is bound as associated
+//! data, so a sealed key can't be moved to another provider's entry.
+//!
+//! When forwarding, a stored key replaces whatever credential the client
+//! sent. With `strip_client_keys`, client credentials are removed even for
+//! providers without a stored key. Bedrock requests are SigV4-signed by
+//! the client and are never touched.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use zeroize::Zeroizing;
+
+use crate::providers::{Provider, CATALOG};
+use crate::vault::{create_private, Vault, API_KEYS_PURPOSE, DEFAULT_VAULT_PATH};
+
+/// Default location of the sealed API key file
+pub const DEFAULT_API_KEY_STORE: &str = "/var/db/yori/api-keys.json";
+
+/// Headers clients put provider credentials in
+pub const CLIENT_CREDENTIAL_HEADERS: [&str; 4] =
+    ["authorization", "x-api-key", "api-key", "x-goog-api-key"];
+
+const STORE_VERSION: u32 = 1;
+const NONCE_LEN: usize = 12;
+
+/// Provider for a catalog identifier ("openai", "anthropic", ...)
+pub fn provider_by_id(id: &str) -> Option<Provider> {
+    CATALOG
+        .iter()
+        .map(|(_, provider)| *provider)
+        .find(|provider| provider.as_str() == id)
+}
+
+/// Header (and value) carrying `api_key` for a provider
+///
+/// None for Bedrock, whose requests are signed rather than keyed.
+pub fn credential_header(provider: Provider, api_key: &str) -> Option<(&'static str, String)> {
+    match provider {
+        Provider::Anthropic => Some(("x-api-key", api_key.to_string())),
+        Provider::AzureOpenAI => Some(("api-key", api_key.to_string())),
+        Provider::Gemini => Some(("x-goog-api-key", api_key.to_string())),
+        Provider::Bedrock => None,
+        _ => Some(("authorization", format!("Bearer {}", api_key))),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedKey {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    keys: BTreeMap<String, SealedKey>,
+}
+
+/// Sealed provider API keys
+pub struct ApiKeyStore {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    keys: RwLock<BTreeMap<String, SealedKey>>,
+}
+
+impl std::fmt::Debug for ApiKeyStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeyStore")
+            .field("path", &self.path)
+            .field("providers", &self.providers())
+            .finish()
+    }
+}
+
+impl ApiKeyStore {
+    /// Open the store at `path` (a missing file is an empty store)
+    pub fn open(path: &Path, vault: &Vault) -> Result<Self> {
+        let keys = match fs::read_to_string(path) {
+            Ok(text) => {
+                let file: StoreFile = serde_json::from_str(&text)
+                    .with_context(|| format!("API key store {} is corrupt", path.display()))?;
+                if file.version != STORE_VERSION {
+                    bail!(
+                        "API key store {} has unsupported version {}",
+                        path.display(),
+                        file.version
+                    );
+                }
+                file.keys
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read API key store {}", path.display()))
+            }
+        };
+        let key = vault.derive_key(API_KEYS_PURPOSE);
+        Ok(ApiKeyStore {
+            path: path.to_path_buf(),
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key.as_ref())),
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Providers with a stored key
+    pub fn providers(&self) -> Vec<String> {
+        self.keys.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Store (or replace) the key for a provider and save the file
+    pub fn set(&self, provider: &str, api_key: &str) -> Result<()> {
+        if provider_by_id(provider).is_none() {
+            bail!("unknown provider {:?}", provider);
+        }
+        let api_key = api_key.trim();
+        if api_key.is_empty() {
+            bail!("API key for {} is empty", provider);
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| anyhow!("failed to generate nonce: {}", e))?;
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: api_key.as_bytes(),
+                    aad: provider.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("failed to seal API key for {}", provider))?;
+
+        let mut keys = self.keys.write().unwrap();
+        keys.insert(
+            provider.to_string(),
+            SealedKey {
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+            },
+        );
+        self.save(&keys)
+    }
+
+    /// Forget the key for a provider, returning whether there was one
+    pub fn remove(&self, provider: &str) -> Result<bool> {
+        let mut keys = self.keys.write().unwrap();
+        if keys.remove(provider).is_none() {
+            return Ok(false);
+        }
+        self.save(&keys)?;
+        Ok(true)
+    }
+
+    /// Unseal the key for a provider
+    ///
+    /// Fails if the entry was tampered with or the vault key changed.
+    pub fn get(&self, provider: &str) -> Result<Option<Zeroizing<String>>> {
+        let keys = self.keys.read().unwrap();
+        let Some(sealed) = keys.get(provider) else {
+            return Ok(None);
+        };
+        let nonce = hex::decode(&sealed.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            bail!("API key entry for {} is corrupt", provider);
+        }
+        let plaintext = Zeroizing::new(
+            self.cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &hex::decode(&sealed.ciphertext)?,
+                        aad: provider.as_bytes(),
+                    },
+                )
+                .map_err(|_| {
+                    anyhow!(
+                        "API key for {} can't be unsealed (wrong vault key or tampered entry)",
+                        provider
+                    )
+                })?,
+        );
+        let api_key = std::str::from_utf8(&plaintext)
+            .with_context(|| format!("API key for {} is not UTF-8", provider))?;
+        Ok(Some(Zeroizing::new(api_key.to_string())))
+    }
+
+    /// Replace the client's credentials with the stored key for `provider`
+    ///
+    /// With `strip_client_keys`, client credentials are removed even when
+    /// no key is stored. Returns whether a stored key was injected.
+    pub fn apply(
+        &self,
+        provider: Provider,
+        headers: &mut HeaderMap,
+        strip_client_keys: bool,
+    ) -> Result<bool> {
+        if provider.signed_requests() {
+            return Ok(false);
+        }
+        let api_key = self.get(provider.as_str())?;
+        if api_key.is_some() || strip_client_keys {
+            for name in CLIENT_CREDENTIAL_HEADERS {
+                headers.remove(name);
+            }
+        }
+        let Some((name, value)) = api_key.and_then(|key| credential_header(provider, &key)) else {
+            return Ok(false);
+        };
+        let mut value = HeaderValue::from_str(&value)
+            .with_context(|| format!("API key for {} is not a valid header", provider.as_str()))?;
+        value.set_sensitive(true);
+        headers.insert(HeaderName::from_static(name), value);
+        Ok(true)
+    }
+
+    /// Write the store atomically (temporary file, then rename), mode 0600
+    fn save(&self, keys: &BTreeMap<String, SealedKey>) -> Result<()> {
+        let file = StoreFile {
+            version: STORE_VERSION,
+            keys: keys.clone(),
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let _ = fs::remove_file(&tmp);
+        let mut out =
+            create_private(&tmp).with_context(|| format!("failed to create {}", tmp.display()))?;
+        out.write_all(serde_json::to_string_pretty(&file)?.as_bytes())?;
+        out.sync_all()?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Python handle for managing stored API keys
+///
+/// Keys can be set and removed but never read back from Python.
+///
+/// ```python
+/// keys = yori_core.ApiKeyStore()
+/// keys.set("openai", "sk-...")
+/// keys.providers()  # ["openai"]
+/// ```
+#[pyclass(name = "ApiKeyStore")]
+pub struct PyApiKeyStore {
+    store: ApiKeyStore,
+}
+
+#[pymethods]
+impl PyApiKeyStore {
+    /// Open the API key store
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Store file (default: /var/db/yori/api-keys.json)
+    /// * `vault_key` - Vault key file, created if missing (default:
+    ///   /var/db/yori/vault.key)
+    #[new]
+    #[pyo3(signature = (path=None, vault_key=None))]
+    fn new(path: Option<PathBuf>, vault_key: Option<PathBuf>) -> PyResult<Self> {
+        let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_API_KEY_STORE));
+        let vault_key = vault_key.unwrap_or_else(|| PathBuf::from(DEFAULT_VAULT_PATH));
+        let store = Vault::open_or_create(&vault_key)
+            .and_then(|vault| ApiKeyStore::open(&path, &vault))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        Ok(PyApiKeyStore { store })
+    }
+
+    /// Store (or replace) a provider's API key
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider id ("openai", "anthropic", "gemini", ...)
+    /// * `api_key` - The key
+    fn set(&self, provider: &str, api_key: &str) -> PyResult<()> {
+        self.store
+            .set(provider, api_key)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Remove a provider's API key
+    ///
+    /// # Returns
+    ///
+    /// Whether a key was stored
+    fn remove(&self, provider: &str) -> PyResult<bool> {
+        self.store
+            .remove(provider)
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+
+    /// Providers with a stored key
+    fn providers(&self) -> Vec<String> {
+        self.store.providers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path) -> ApiKeyStore {
+        let vault = Vault::open_or_create(&dir.join("vault.key")).unwrap();
+        ApiKeyStore::open(&dir.join("api-keys.json"), &vault).unwrap()
+    }
+
+    #[test]
+    fn test_keys_sealed_at_rest_and_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let keys = store(dir.path());
+        keys.set("openai", "sk-live-secret").unwrap();
+        keys.set("anthropic", "sk-ant-secret").unwrap();
+        assert!(keys.set("openia", "sk-typo").is_err());
+
+        let on_disk = fs::read_to_string(dir.path().join("api-keys.json")).unwrap();
+        assert!(!on_disk.contains("sk-live-secret"));
+
+        let reopened = store(dir.path());
+        assert_eq!(reopened.providers(), vec!["anthropic", "openai"]);
+        assert_eq!(
+            reopened.get("openai").unwrap().unwrap().as_str(),
+            "sk-live-secret"
+        );
+        assert!(reopened.remove("openai").unwrap());
+        assert!(store(dir.path()).get("openai").unwrap().is_none());
+
+        // An entry copied under another provider's name doesn't unseal
+        let mut file: StoreFile =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("api-keys.json")).unwrap())
+                .unwrap();
+        let sealed = file.keys["anthropic"].clone();
+        file.keys.insert("openai".to_string(), sealed);
+        fs::write(
+            dir.path().join("api-keys.json"),
+            serde_json::to_string(&file).unwrap(),
+        )
+        .unwrap();
+        assert!(store(dir.path()).get("openai").is_err());
+    }
+
+    #[test]
+    fn test_client_credentials_replaced_or_stripped() {
+        let dir = tempfile::tempdir().unwrap();
+        let keys = store(dir.path());
+        keys.set("anthropic", "sk-ant-secret").unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer placeholder"),
+        );
+        headers.insert("x-api-key", HeaderValue::from_static("placeholder"));
+        assert!(keys
+            .apply(Provider::Anthropic, &mut headers, false)
+            .unwrap());
+        assert_eq!(headers["x-api-key"], "sk-ant-secret");
+        assert!(!headers.contains_key("authorization"));
+
+        // No stored key: the client's own key is kept unless stripping
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer sk-own"));
+        assert!(!keys.apply(Provider::OpenAI, &mut headers, false).unwrap());
+        assert!(headers.contains_key("authorization"));
+        assert!(!keys.apply(Provider::OpenAI, &mut headers, true).unwrap());
+        assert!(headers.is_empty());
+    }
+}
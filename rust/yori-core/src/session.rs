@@ -0,0 +1,160 @@
+//! Conversation sessions across requests
+//!
+//! A chat with an assistant is many requests: each turn resends the
+//! conversation so far. To show (and cost) a chat as one thing, requests
+//! from the same device to the same provider join one session for as long
+//! as they keep coming within an idle window. A request that names its
+//! conversation ([`crate::ParsedRequest::conversation_id`]) joins that
+//! conversation's session instead, so two chats open side by side stay
+//! apart.
+//!
+//! The session id is stored on every audit event of the request
+//! (`session_id`); [`crate::AuditLogger::session_totals`] sums the
+//! requests, tokens and cost of each session.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Default gap after which the next request starts a new session
+pub const DEFAULT_SESSION_IDLE_SECS: u64 = 30 * 60;
+
+/// Maximum number of sessions tracked at once
+const MAX_TRACKED: usize = 4096;
+
+/// (device, provider, conversation id)
+type SessionKey = (String, String, Option<String>);
+
+#[derive(Debug)]
+struct Session {
+    id: String,
+    last_seen: DateTime<Utc>,
+}
+
+/// Assigns requests to sessions
+#[derive(Debug)]
+pub struct SessionTracker {
+    idle: Duration,
+    sessions: Mutex<HashMap<SessionKey, Session>>,
+}
+
+impl Default for SessionTracker {
+    fn default() -> Self {
+        SessionTracker::new(DEFAULT_SESSION_IDLE_SECS)
+    }
+}
+
+impl SessionTracker {
+    /// Create a tracker ending sessions after `idle_secs` without requests
+    pub fn new(idle_secs: u64) -> Self {
+        SessionTracker {
+            idle: Duration::seconds(idle_secs as i64),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Session id for a request, starting a new session if the last one
+    /// has gone idle
+    pub fn assign(
+        &self,
+        device: &str,
+        provider: &str,
+        conversation: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> String {
+        let mut sessions = self.sessions.lock().unwrap();
+        let key = (
+            device.to_string(),
+            provider.to_string(),
+            conversation.map(str::to_string),
+        );
+
+        if let Some(session) = sessions.get_mut(&key) {
+            if now - session.last_seen <= self.idle {
+                session.last_seen = session.last_seen.max(now);
+                return session.id.clone();
+            }
+        }
+
+        if sessions.len() >= MAX_TRACKED && !sessions.contains_key(&key) {
+            let cutoff = now - self.idle;
+            sessions.retain(|_, s| s.last_seen >= cutoff);
+            if sessions.len() >= MAX_TRACKED {
+                // Still full of live sessions: drop the quietest
+                if let Some(oldest) = sessions
+                    .iter()
+                    .min_by_key(|(_, s)| s.last_seen)
+                    .map(|(k, _)| k.clone())
+                {
+                    sessions.remove(&oldest);
+                }
+            }
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        sessions.insert(
+            key,
+            Session {
+                id: id.clone(),
+                last_seen: now,
+            },
+        );
+        id
+    }
+
+    /// Number of sessions tracked (live or not yet evicted)
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Whether no session is tracked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_grouped_until_idle() {
+        let tracker = SessionTracker::new(600);
+        let t0 = Utc::now();
+
+        let first = tracker.assign("ipad", "openai", None, t0);
+        let second = tracker.assign("ipad", "openai", None, t0 + Duration::minutes(8));
+        // The window runs from the latest request, not the first
+        let third = tracker.assign("ipad", "openai", None, t0 + Duration::minutes(16));
+        assert_eq!(first, second);
+        assert_eq!(second, third);
+
+        // Other providers and devices get sessions of their own
+        assert_ne!(tracker.assign("ipad", "anthropic", None, t0), first);
+        assert_ne!(tracker.assign("laptop", "openai", None, t0), first);
+
+        // After the idle gap a new session starts
+        let later = tracker.assign("ipad", "openai", None, t0 + Duration::minutes(40));
+        assert_ne!(later, first);
+    }
+
+    #[test]
+    fn test_conversation_ids_keep_chats_apart() {
+        let tracker = SessionTracker::default();
+        let t0 = Utc::now();
+
+        let essay = tracker.assign("laptop", "openai", Some("conv_essay"), t0);
+        let maths = tracker.assign("laptop", "openai", Some("conv_maths"), t0);
+        assert_ne!(essay, maths);
+        assert_eq!(
+            tracker.assign(
+                "laptop",
+                "openai",
+                Some("conv_essay"),
+                t0 + Duration::minutes(1)
+            ),
+            essay
+        );
+        assert_eq!(tracker.len(), 2);
+    }
+}
@@ -0,0 +1,269 @@
+//! Decision shortcuts for policies that read few input fields
+//!
+//! Most home policies look at a handful of fields: who is asking, which
+//! endpoint, the hour. The decision cache only helps for byte-identical
+//! inputs, but two requests from the same child to the same endpoint in the
+//! same hour differ in prompt, request id and timestamp. When the policies
+//! are loaded they are scanned for every `input.*` path they reference; if
+//! that's all they read, a decision depends on nothing but the values at
+//! those paths, and is reused for any input that agrees on them:
+//!
+//! ```text
+//! input.user.group, input.endpoint, input.schedule.hour
+//!   -> {"allow": false, "policy": "yori.bedtime", ...}
+//! ```
+//!
+//! Shortcuts are only taken when the analysis is sure. Policies that use
+//! `input` as a whole (`object.get(input, ...)`, `input[key]`, passing it to
+//! a function) or call a built-in whose result changes between calls
+//! (`time.now_ns`, `rand.*`, `http.send`, ...) are always evaluated in full.
+//! The table is emptied on every reload and whenever the strategy, default
+//! decision or policy metadata change, like the decision cache.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::decisions::{input_hash, InputHash};
+
+/// Default maximum number of shortcut decisions
+pub const DEFAULT_SHORTCUT_ENTRIES: usize = 4096;
+
+/// Built-ins whose result can change between two calls with equal input
+const VOLATILE_BUILTINS: &[&str] = &[
+    "time.now_ns",
+    "rand.",
+    "uuid.",
+    "http.send",
+    "net.lookup_ip_addr",
+    "opa.runtime",
+    "crypto.x509.parse_and_verify_certificates",
+];
+
+/// The input fields a policy set's decisions depend on
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ShortcutPlan {
+    /// Dotted `input.*` paths, none a prefix of another
+    paths: Vec<String>,
+}
+
+impl ShortcutPlan {
+    /// Scan policy `(file, source)` pairs, or say why their decisions can't
+    /// be keyed by a few input fields
+    pub(crate) fn analyse(sources: &[(String, String)]) -> Result<ShortcutPlan, String> {
+        let mut paths = BTreeSet::new();
+        for (file, source) in sources {
+            for (number, line) in source.lines().enumerate() {
+                let code = strip_comment(line);
+                if let Some(builtin) = VOLATILE_BUILTINS.iter().find(|b| calls(code, b)) {
+                    return Err(format!(
+                        "{}:{} calls {}",
+                        file,
+                        number + 1,
+                        builtin.trim_end_matches('.')
+                    ));
+                }
+                for reference in input_references(code) {
+                    match reference {
+                        Some(path) => paths.insert(path),
+                        None => {
+                            return Err(format!(
+                                "{}:{} uses the whole input document",
+                                file,
+                                number + 1
+                            ))
+                        }
+                    };
+                }
+            }
+        }
+        // A path covers everything below it
+        let mut kept: Vec<String> = Vec::new();
+        for path in paths {
+            if !kept
+                .iter()
+                .any(|p| path.starts_with(p.as_str()) && path[p.len()..].starts_with('.'))
+            {
+                kept.push(path);
+            }
+        }
+        Ok(ShortcutPlan { paths: kept })
+    }
+
+    /// Input paths decisions depend on
+    pub(crate) fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Key for `input`: the hash of its values at the plan's paths
+    pub(crate) fn key(&self, input: &Value) -> InputHash {
+        let projection: Map<String, Value> = self
+            .paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), lookup(input, path)?.clone())))
+            .collect();
+        input_hash(&Value::Object(projection))
+    }
+}
+
+/// Shortcut table statistics of a [`crate::PolicyEngine`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ShortcutStats {
+    /// Input paths decisions are keyed by, when shortcuts are in use
+    pub paths: Option<Vec<String>>,
+
+    /// Why shortcuts aren't in use, if they aren't
+    pub unavailable: Option<String>,
+
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Decisions by shortcut key, for one loaded policy set
+#[derive(Debug, Default)]
+pub(crate) struct DecisionTable {
+    entries: Mutex<HashMap<InputHash, Value>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DecisionTable {
+    /// Look up a decision, counting the hit or miss
+    pub(crate) fn get(&self, key: &InputHash) -> Option<Value> {
+        let decision = self.entries.lock().unwrap().get(key).cloned();
+        let counter = match decision.is_some() {
+            true => &self.hits,
+            false => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        decision
+    }
+
+    /// Remember a decision, starting over once `max_entries` are stored
+    pub(crate) fn insert(&self, key: InputHash, decision: Value, max_entries: usize) {
+        if max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= max_entries && !entries.contains_key(&key) {
+            // The common cases come straight back
+            entries.clear();
+        }
+        entries.insert(key, decision);
+    }
+
+    /// Drop every decision (counters are kept)
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Entry count, hits and misses
+    pub(crate) fn stats(&self) -> (usize, u64, u64) {
+        (
+            self.entries.lock().unwrap().len(),
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// A line without its `#` comment (left alone after a quote, where `#`
+/// may be part of a string)
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(hash) if !line[..hash].contains(['"', '`']) => &line[..hash],
+        _ => line,
+    }
+}
+
+/// Whether `code` mentions the built-in (or built-in family) `name`
+fn calls(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(begin, _)| {
+        !code[..begin]
+            .chars()
+            .next_back()
+            .is_some_and(|prev| prev.is_ascii_alphanumeric() || prev == '_' || prev == '.')
+    })
+}
+
+/// Every `input` reference on a line: the dotted path for `input.a.b`
+/// (cut at a bracket), None for any other use of `input`
+fn input_references(code: &str) -> Vec<Option<String>> {
+    let mut references = Vec::new();
+    let bytes = code.as_bytes();
+    let mut start = 0;
+    while let Some(offset) = code[start..].find("input") {
+        let begin = start + offset;
+        let end = begin + "input".len();
+        start = end;
+        let word_start = begin == 0 || {
+            let prev = bytes[begin - 1];
+            !(prev.is_ascii_alphanumeric() || prev == b'_' || prev == b'.')
+        };
+        let word_end = bytes
+            .get(end)
+            .is_none_or(|next| !(next.is_ascii_alphanumeric() || *next == b'_'));
+        if !word_start || !word_end {
+            continue;
+        }
+        let path_end = code[end..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .map_or(code.len(), |e| end + e);
+        let path = code[begin..path_end].trim_end_matches('.');
+        references.push((path.len() > "input".len()).then(|| path.to_string()));
+        start = path_end.max(end);
+    }
+    references
+}
+
+fn lookup<'a>(input: &'a Value, path: &str) -> Option<&'a Value> {
+    path.strip_prefix("input.")?
+        .split('.')
+        .try_fold(input, |value, key| value.get(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sources(rego: &str) -> Vec<(String, String)> {
+        vec![("bedtime.rego".to_string(), rego.to_string())]
+    }
+
+    #[test]
+    fn test_plan_keys_by_referenced_fields_only() {
+        let plan = ShortcutPlan::analyse(&sources(
+            "package yori.bedtime\n\n\
+             # Kids' devices are off after 21:00 (see input docs)\n\
+             deny contains \"past bedtime\" if {\n\
+             \x20   input.user.group == \"kids\"\n\
+             \x20   input.schedule.hour >= 21\n\
+             \x20   input.user.group.name != \"brand.new\"\n\
+             }\n",
+        ))
+        .unwrap();
+        assert_eq!(plan.paths(), ["input.schedule.hour", "input.user.group"]);
+
+        let first = json!({"user": {"group": "kids"}, "schedule": {"hour": 22, "minute": 5}, "prompt": "a"});
+        let second = json!({"user": {"group": "kids"}, "schedule": {"hour": 22, "minute": 40}, "prompt": "b"});
+        let later = json!({"user": {"group": "kids"}, "schedule": {"hour": 23}, "prompt": "a"});
+        assert_eq!(plan.key(&first), plan.key(&second));
+        assert_ne!(plan.key(&first), plan.key(&later));
+
+        for unsure in [
+            "allow if object.get(input, \"user\", {}) == {}",
+            "allow if input[\"user\"]",
+            "allow if time.now_ns() > 0",
+        ] {
+            assert!(
+                ShortcutPlan::analyse(&sources(unsure)).is_err(),
+                "{}",
+                unsure
+            );
+        }
+    }
+}
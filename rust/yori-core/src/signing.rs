@@ -0,0 +1,275 @@
+//! Signed policy bundles
+//!
+//! Anyone who can write to the policy directory (a compromised dashboard, a
+//! shared folder) could otherwise swap in a permissive policy. With
+//! verification on, the engine only loads a policy directory that carries
+//! an ed25519 signature by a trusted key:
+//!
+//! ```text
+//! policies/bundle.manifest   "<sha256>  <path>" per policy and data file
+//! policies/bundle.sig        hex signature of bundle.manifest
+//! ```
+//!
+//! The manifest uses `sha256sum` format and lists every `.rego` and data
+//! file the engine would load (test files excepted). A file that is
+//! missing, unlisted or changed fails the load just like a bad signature,
+//! so a policy can't be dropped or added behind the signer's back either.
+//! `yori policy sign` writes both files with a key kept on the signing
+//! machine.
+//!
+//! Only the policy directory is covered: data added with `load_data_dir`
+//! or `add_data` is trusted as it is.
+
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::data::DATA_EXTENSIONS;
+use crate::explain::read_files;
+use crate::policytest::is_test_file;
+use crate::vault::create_private;
+
+/// Manifest of file hashes, relative to the policy directory
+pub const MANIFEST_FILE: &str = "bundle.manifest";
+
+/// Signature of the manifest, relative to the policy directory
+pub const SIGNATURE_FILE: &str = "bundle.sig";
+
+/// Whether `path` is a bundle manifest or signature
+pub fn is_bundle_signature(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == MANIFEST_FILE || name == SIGNATURE_FILE)
+}
+
+/// Checks policy directories against trusted public keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleVerifier {
+    keys: Vec<VerifyingKey>,
+}
+
+impl BundleVerifier {
+    /// Trust the given hex-encoded ed25519 public keys
+    pub fn new(public_keys: &[String]) -> Result<Self> {
+        if public_keys.is_empty() {
+            bail!("at least one trusted key is required");
+        }
+        let keys = public_keys
+            .iter()
+            .map(|key| {
+                let mut bytes = [0u8; 32];
+                hex::decode_to_slice(key.trim(), &mut bytes)
+                    .with_context(|| format!("invalid public key {:?}", key))?;
+                VerifyingKey::from_bytes(&bytes)
+                    .with_context(|| format!("invalid public key {:?}", key))
+            })
+            .collect::<Result<_>>()?;
+        Ok(BundleVerifier { keys })
+    }
+
+    /// Verify the signature and every file of `policy_dir`, returning how
+    /// many files the manifest covers
+    pub fn verify(&self, policy_dir: &Path) -> Result<usize> {
+        let manifest_path = policy_dir.join(MANIFEST_FILE);
+        let manifest = match fs::read(&manifest_path) {
+            Ok(manifest) => manifest,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                bail!("policy bundle is not signed (no {})", MANIFEST_FILE)
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read {}", manifest_path.display()))
+            }
+        };
+        let signature_path = policy_dir.join(SIGNATURE_FILE);
+        let signature = match fs::read_to_string(&signature_path) {
+            Ok(signature) => signature,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                bail!("policy bundle is not signed (no {})", SIGNATURE_FILE)
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read {}", signature_path.display()))
+            }
+        };
+        let mut bytes = [0u8; 64];
+        hex::decode_to_slice(signature.trim(), &mut bytes)
+            .with_context(|| format!("{} is not a hex-encoded signature", SIGNATURE_FILE))?;
+        let signature = Signature::from_bytes(&bytes);
+        if !self
+            .keys
+            .iter()
+            .any(|key| key.verify_strict(&manifest, &signature).is_ok())
+        {
+            bail!("{} is not signed by a trusted key", MANIFEST_FILE);
+        }
+
+        let mut listed = parse_manifest(&manifest)?;
+        let count = listed.len();
+        for (file, contents) in read_bundle_files(policy_dir)? {
+            match listed.remove(&file) {
+                Some(hash) if hash == sha256_hex(contents.as_bytes()) => {}
+                Some(_) => bail!("{} was changed after the bundle was signed", file),
+                None => bail!("{} is not listed in {}", file, MANIFEST_FILE),
+            }
+        }
+        if let Some(file) = listed.keys().next() {
+            bail!("{} is listed in {} but missing", file, MANIFEST_FILE);
+        }
+        Ok(count)
+    }
+}
+
+/// Signs policy directories with a locally kept key
+pub struct PolicySigner {
+    path: PathBuf,
+    key: SigningKey,
+}
+
+impl std::fmt::Debug for PolicySigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print key material
+        f.debug_struct("PolicySigner")
+            .field("path", &self.path)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl PolicySigner {
+    /// Load the signing key from `path` (hex-encoded seed), generating a
+    /// new one if absent
+    pub fn open_or_create(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            let mut seed = Zeroizing::new([0u8; 32]);
+            getrandom::getrandom(seed.as_mut())
+                .map_err(|e| anyhow::anyhow!("failed to generate signing key: {}", e))?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let encoded = Zeroizing::new(hex::encode(seed.as_ref()));
+            let mut file = create_private(path)
+                .with_context(|| format!("failed to create signing key {}", path.display()))?;
+            file.write_all(encoded.as_bytes())?;
+            file.sync_all()?;
+            tracing::info!("Generated new policy signing key at {}", path.display());
+        }
+
+        let encoded = Zeroizing::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("failed to read signing key {}", path.display()))?,
+        );
+        let mut seed = Zeroizing::new([0u8; 32]);
+        hex::decode_to_slice(encoded.trim(), seed.as_mut())
+            .with_context(|| format!("signing key {} is corrupt", path.display()))?;
+        Ok(PolicySigner {
+            path: path.to_path_buf(),
+            key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    /// Hex-encoded public key, for `policy.trusted_keys`
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Write the manifest and signature for `policy_dir`, returning how
+    /// many files were signed
+    pub fn sign(&self, policy_dir: &Path) -> Result<usize> {
+        let files = read_bundle_files(policy_dir)?;
+        let manifest: String = files
+            .iter()
+            .map(|(file, contents)| format!("{}  {}\n", sha256_hex(contents.as_bytes()), file))
+            .collect();
+        let signature = self.key.sign(manifest.as_bytes());
+
+        let manifest_path = policy_dir.join(MANIFEST_FILE);
+        fs::write(&manifest_path, &manifest)
+            .with_context(|| format!("failed to write {}", manifest_path.display()))?;
+        let signature_path = policy_dir.join(SIGNATURE_FILE);
+        fs::write(&signature_path, hex::encode(signature.to_bytes()) + "\n")
+            .with_context(|| format!("failed to write {}", signature_path.display()))?;
+        Ok(files.len())
+    }
+}
+
+/// Policy and data files the engine loads from `policy_dir`
+fn read_bundle_files(policy_dir: &Path) -> Result<Vec<(String, String)>> {
+    read_files(policy_dir, "policy", |path| {
+        !is_test_file(path)
+            && path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e == "rego" || DATA_EXTENSIONS.contains(&e))
+    })
+}
+
+/// Path -> hex SHA-256 from `sha256sum` output
+fn parse_manifest(manifest: &[u8]) -> Result<BTreeMap<String, String>> {
+    let text = std::str::from_utf8(manifest).context("bundle manifest is not UTF-8")?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (hash, file) = line
+                .split_once(' ')
+                .with_context(|| format!("invalid manifest line {:?}", line))?;
+            // sha256sum marks binary mode with '*' in place of the second space
+            let file = file.trim_start_matches([' ', '*']).trim_start_matches("./");
+            Ok((file.to_string(), hash.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_bundle_verifies_until_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let policies = dir.path().join("policies");
+        fs::create_dir(&policies).unwrap();
+        fs::write(policies.join("bedtime.rego"), "package yori.bedtime\n").unwrap();
+        fs::write(policies.join("devices.json"), "{}").unwrap();
+        fs::write(
+            policies.join("bedtime_test.rego"),
+            "package yori.bedtime_test\n",
+        )
+        .unwrap();
+
+        let signer = PolicySigner::open_or_create(&dir.path().join("signing.key")).unwrap();
+        let verifier = BundleVerifier::new(&[signer.public_key()]).unwrap();
+        assert!(verifier.verify(&policies).is_err());
+
+        assert_eq!(signer.sign(&policies).unwrap(), 2);
+        assert_eq!(verifier.verify(&policies).unwrap(), 2);
+        // Test files aren't covered
+        fs::write(policies.join("bedtime_test.rego"), "package yori.x_test\n").unwrap();
+        assert!(verifier.verify(&policies).is_ok());
+
+        let other = PolicySigner::open_or_create(&dir.path().join("other.key")).unwrap();
+        let untrusted = BundleVerifier::new(&[other.public_key()]).unwrap();
+        assert!(untrusted.verify(&policies).is_err());
+
+        fs::write(
+            policies.join("bedtime.rego"),
+            "package yori.bedtime\n# allow all\n",
+        )
+        .unwrap();
+        let error = verifier.verify(&policies).unwrap_err().to_string();
+        assert!(error.contains("bedtime.rego was changed"), "{}", error);
+
+        signer.sign(&policies).unwrap();
+        fs::remove_file(policies.join("devices.json")).unwrap();
+        let error = verifier.verify(&policies).unwrap_err().to_string();
+        assert!(error.contains("devices.json is listed"), "{}", error);
+    }
+}
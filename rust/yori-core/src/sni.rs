@@ -0,0 +1,273 @@
+//! SNI sniffing and passthrough for non-LLM TLS traffic
+//!
+//! When the firewall redirects all outbound 443 traffic to YORI, most of
+//! it is ordinary HTTPS (banking, video calls, OS updates) that must not
+//! be touched. Before terminating TLS the proxy peeks at the ClientHello
+//! without consuming it, reads the server name, and either hands the
+//! connection on for interception (configured LLM endpoints) or connects
+//! to the real host and relays the bytes unmodified, ClientHello included.
+//! The client's TLS session is then with the real server, so certificate
+//! pinning and client certificates keep working.
+
+use anyhow::{bail, Context, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+/// Port passthrough connections are made to upstream
+pub const PASSTHROUGH_PORT: u16 = 443;
+
+/// Longest a client may take to send its ClientHello
+const PEEK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest TLS record (and so ClientHello) inspected
+const MAX_RECORD_BYTES: usize = 16 * 1024 + 5;
+
+/// Result of parsing a possibly partial ClientHello
+enum Parsed {
+    /// More bytes are needed
+    Incomplete,
+
+    /// Complete; the server name if the client sent one
+    Done(Option<String>),
+}
+
+/// Read the server name from a TLS ClientHello
+///
+/// Returns `Ok(None)` for a well-formed hello without SNI (clients
+/// connecting to a bare IP address) and an error for anything that isn't
+/// a complete ClientHello.
+pub fn parse_sni(buf: &[u8]) -> Result<Option<String>> {
+    match parse(buf)? {
+        Parsed::Done(name) => Ok(name),
+        Parsed::Incomplete => bail!("truncated TLS ClientHello"),
+    }
+}
+
+fn parse(buf: &[u8]) -> Result<Parsed> {
+    if buf.len() < 5 {
+        return Ok(Parsed::Incomplete);
+    }
+    if buf[0] != 0x16 {
+        bail!("not a TLS handshake (first byte {:#04x})", buf[0]);
+    }
+    let record_len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    if buf.len() < 5 + record_len {
+        return Ok(Parsed::Incomplete);
+    }
+    let mut r = Reader(&buf[5..5 + record_len]);
+
+    if r.u8()? != 0x01 {
+        bail!("first handshake message is not a ClientHello");
+    }
+    let hello_len = r.u24()?;
+    let mut hello = Reader(
+        r.take(hello_len)
+            .context("ClientHello spans several records")?,
+    );
+
+    hello.take(2 + 32)?; // client_version, random
+    let session_id = hello.u8()? as usize;
+    hello.take(session_id)?;
+    let cipher_suites = hello.u16()? as usize;
+    hello.take(cipher_suites)?;
+    let compression = hello.u8()? as usize;
+    hello.take(compression)?;
+    if hello.0.is_empty() {
+        return Ok(Parsed::Done(None));
+    }
+
+    let extensions_len = hello.u16()? as usize;
+    let mut extensions = Reader(hello.take(extensions_len)?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let len = extensions.u16()? as usize;
+        let mut data = Reader(extensions.take(len)?);
+        if kind != 0x0000 {
+            continue;
+        }
+        // server_name: list of (type, name); type 0 is host_name
+        let list_len = data.u16()? as usize;
+        let mut list = Reader(data.take(list_len)?);
+        while !list.0.is_empty() {
+            let name_type = list.u8()?;
+            let name_len = list.u16()? as usize;
+            let name = list.take(name_len)?;
+            if name_type == 0 {
+                let name = std::str::from_utf8(name).context("server name is not UTF-8")?;
+                return Ok(Parsed::Done(Some(name.to_ascii_lowercase())));
+            }
+        }
+    }
+    Ok(Parsed::Done(None))
+}
+
+/// Big-endian cursor over handshake bytes
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            bail!("malformed ClientHello");
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Result<usize> {
+        let b = self.take(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+}
+
+/// Read the server name of a newly accepted connection without consuming
+/// any bytes, so the stream can still be TLS-terminated or relayed as is
+pub async fn peek_sni(stream: &TcpStream) -> Result<Option<String>> {
+    let peek = async {
+        let mut buf = vec![0u8; MAX_RECORD_BYTES];
+        let mut seen = 0;
+        loop {
+            let n = stream.peek(&mut buf).await?;
+            if n == 0 {
+                bail!("client closed before sending a ClientHello");
+            }
+            if let Parsed::Done(name) = parse(&buf[..n])? {
+                return Ok(name);
+            }
+            if n == buf.len() {
+                bail!("ClientHello larger than {} bytes", MAX_RECORD_BYTES);
+            }
+            if n == seen {
+                // peek returns what's buffered; wait for the rest to arrive
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            seen = n;
+        }
+    };
+    tokio::time::timeout(PEEK_TIMEOUT, peek)
+        .await
+        .context("timed out waiting for a ClientHello")?
+}
+
+/// Where a transparently redirected TLS connection goes
+#[derive(Debug)]
+pub enum TlsRoute {
+    /// A configured LLM endpoint: terminate TLS on `stream` (nothing has
+    /// been read from it yet)
+    Intercept {
+        /// Server name from the ClientHello
+        host: String,
+
+        /// Address the client dialed, when the firewall tells (see
+        /// [`crate::origdst`])
+        destination: Option<SocketAddr>,
+
+        /// The untouched client connection
+        stream: TcpStream,
+    },
+
+    /// Any other host: relayed to the real server until either side closed
+    PassedThrough {
+        /// Server name from the ClientHello (the destination address when
+        /// the client sent none)
+        host: String,
+
+        /// Address the client dialed, when the firewall tells
+        destination: Option<SocketAddr>,
+
+        /// Bytes sent client → upstream and upstream → client
+        bytes: (u64, u64),
+    },
+}
+
+/// Relay a client connection to `upstream` unmodified until either side
+/// closes, returning the bytes sent in each direction
+pub async fn passthrough(mut client: TcpStream, upstream: &str) -> Result<(u64, u64)> {
+    let mut server = TcpStream::connect(upstream)
+        .await
+        .with_context(|| format!("failed to connect to {}", upstream))?;
+    let bytes = tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+    let _ = server.shutdown().await;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// A real ClientHello as rustls would send it
+    fn client_hello(host: &str) -> Vec<u8> {
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let name = rustls::ServerName::try_from(host).unwrap();
+        let mut conn = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut hello = Vec::new();
+        conn.write_tls(&mut hello).unwrap();
+        hello
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello("Www.Example.com");
+        assert_eq!(
+            parse_sni(&hello).unwrap(),
+            Some("www.example.com".to_string())
+        );
+        assert!(parse_sni(&hello[..hello.len() - 1]).is_err());
+        assert!(parse_sni(b"GET / HTTP/1.1\r\n\r\n").is_err());
+        // IP literals are never sent as SNI
+        assert_eq!(parse_sni(&client_hello("192.0.2.1")).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_relays_hello_unmodified() {
+        let hello = client_hello("bank.example");
+        let expected = hello.clone();
+
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap().to_string();
+        let received = tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let mut buf = Vec::new();
+            socket.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let relay = tokio::spawn(async move {
+            let (socket, _) = proxy.accept().await.unwrap();
+            let host = peek_sni(&socket).await.unwrap();
+            let bytes = passthrough(socket, &upstream_addr).await.unwrap();
+            (host, bytes)
+        });
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        // Split the hello across two writes to exercise partial peeks
+        client.write_all(&hello[..10]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        client.write_all(&hello[10..]).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let (host, (sent, _)) = relay.await.unwrap();
+        assert_eq!(host.as_deref(), Some("bank.example"));
+        assert_eq!(sent, expected.len() as u64);
+        assert_eq!(received.await.unwrap(), expected);
+    }
+}
@@ -0,0 +1,498 @@
+//! Write-ahead buffering of audit events
+//!
+//! The audit database can be briefly unavailable: a long dashboard export
+//! holding a lock, maintenance checkpointing the WAL, a full disk, or the
+//! USB stick it lives on reseating. Events written then used to be lost.
+//! [`AuditSpool`] sits in front of the [`AuditLogger`]; while the database
+//! refuses writes, events queue up instead:
+//!
+//! 1. in memory, up to `memory_events`
+//! 2. then appended as JSON lines to the spool file, up to `max_bytes`
+//! 3. beyond that they are dropped (memory only: the oldest) and counted
+//!
+//! Once the database accepts writes again the queue is replayed in order,
+//! memory first, then the file. Events still queued at shutdown are moved
+//! to the file, and a spool file left by an earlier run is replayed after
+//! the next start. Events are stored as [`AuditEvent::to_json`] and keep
+//! their request ids, so replaying one twice is harmless.
+
+use anyhow::{Context, Result};
+use rusqlite::ErrorCode;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audit::{AuditEvent, AuditLogger};
+
+/// Default events held in memory while the database is unavailable
+pub const DEFAULT_BUFFER_EVENTS: usize = 10_000;
+
+/// Default spool file
+pub const DEFAULT_SPOOL_PATH: &str = "/var/db/yori/audit-spool.jsonl";
+
+/// Default spool file size cap (64 MiB)
+pub const DEFAULT_SPOOL_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Time between replay attempts while events are queued
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Audit buffering settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpoolConfig {
+    /// Events held in memory before spilling to the file
+    pub memory_events: usize,
+
+    /// Spool file (None = memory only)
+    pub path: Option<PathBuf>,
+
+    /// Largest the spool file may grow
+    pub max_bytes: u64,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        SpoolConfig {
+            memory_events: DEFAULT_BUFFER_EVENTS,
+            path: Some(PathBuf::from(DEFAULT_SPOOL_PATH)),
+            max_bytes: DEFAULT_SPOOL_MAX_BYTES,
+        }
+    }
+}
+
+/// Queue counters, for health output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SpoolStats {
+    /// Events queued in memory
+    pub buffered: usize,
+
+    /// Events queued in the spool file
+    pub spilled: u64,
+
+    /// Queued events written to the database since start
+    pub replayed: u64,
+
+    /// Events lost to full buffers since start
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct State {
+    memory: VecDeque<AuditEvent>,
+    spilled: u64,
+    spill_bytes: u64,
+    replayed: u64,
+    dropped: u64,
+    /// A drop was already reported during the current outage
+    warned_drop: bool,
+}
+
+impl State {
+    fn is_empty(&self) -> bool {
+        self.memory.is_empty() && self.spilled == 0
+    }
+}
+
+/// Outcome of writing one event
+enum Outcome {
+    Written,
+    Unavailable,
+    Failed(anyhow::Error),
+}
+
+/// Buffers audit events while the database is unavailable
+pub struct AuditSpool {
+    config: SpoolConfig,
+    state: Mutex<State>,
+}
+
+impl std::fmt::Debug for AuditSpool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditSpool")
+            .field("config", &self.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Default for AuditSpool {
+    fn default() -> Self {
+        AuditSpool::new(SpoolConfig::default())
+    }
+}
+
+impl AuditSpool {
+    /// Create a spool, picking up a spool file left by an earlier run
+    pub fn new(config: SpoolConfig) -> Self {
+        let mut state = State::default();
+        if let Some(path) = &config.path {
+            match count_lines(path) {
+                Ok((0, _)) => {}
+                Ok((lines, bytes)) => {
+                    tracing::info!(
+                        "{} audit events waiting in {} from an earlier run",
+                        lines,
+                        path.display()
+                    );
+                    state.spilled = lines;
+                    state.spill_bytes = bytes;
+                }
+                Err(e) => tracing::warn!("Cannot read audit spool {}: {:#}", path.display(), e),
+            }
+        }
+        AuditSpool {
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Write `event`, or queue it while the database is unavailable
+    ///
+    /// Returns the row id when written, None when queued (or dropped).
+    /// Errors other than the database being unavailable are returned, not
+    /// queued.
+    pub fn record(&self, logger: &AuditLogger, event: &AuditEvent) -> Result<Option<i64>> {
+        let mut state = self.state.lock().unwrap();
+        // Keep order: nothing overtakes queued events
+        if state.is_empty() {
+            match logger.log(event) {
+                Ok(id) => return Ok(Some(id)),
+                Err(e) if is_unavailable(&e) => {
+                    tracing::warn!("Audit database unavailable, buffering events: {:#}", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.push(&mut state, event);
+        Ok(None)
+    }
+
+    fn push(&self, state: &mut State, event: &AuditEvent) {
+        if state.spilled == 0 && state.memory.len() < self.config.memory_events {
+            state.memory.push_back(event.clone());
+            return;
+        }
+        if let Some(path) = &self.config.path {
+            match self.spill(state, path, event) {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => tracing::debug!("Audit spool write failed: {:#}", e),
+            }
+        }
+        if state.spilled == 0 && self.config.memory_events > 0 {
+            // Ring buffer: the newest events are the ones worth keeping
+            state.memory.pop_front();
+            state.memory.push_back(event.clone());
+        }
+        state.dropped += 1;
+        if !state.warned_drop {
+            state.warned_drop = true;
+            tracing::warn!("Audit buffers full, dropping events until the database recovers");
+        }
+    }
+
+    /// Append to the spool file; false if it's at `max_bytes`
+    fn spill(&self, state: &mut State, path: &Path, event: &AuditEvent) -> Result<bool> {
+        let line = format!("{}\n", event.to_json());
+        if state.spill_bytes + line.len() as u64 > self.config.max_bytes {
+            return Ok(false);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        file.write_all(line.as_bytes())?;
+        state.spilled += 1;
+        state.spill_bytes += line.len() as u64;
+        Ok(true)
+    }
+
+    /// Write queued events to the database, oldest first, stopping at the
+    /// first one it still refuses
+    ///
+    /// Returns the number of events written.
+    pub fn replay(&self, logger: &AuditLogger) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut written = 0;
+        while let Some(event) = state.memory.front() {
+            match write(logger, event) {
+                Outcome::Unavailable => {
+                    state.replayed += written;
+                    return Ok(written);
+                }
+                Outcome::Written => written += 1,
+                Outcome::Failed(e) => {
+                    tracing::warn!("Dropping buffered audit event: {:#}", e);
+                    state.dropped += 1;
+                }
+            }
+            state.memory.pop_front();
+        }
+
+        if let Some(path) = self.config.path.clone().filter(|_| state.spilled > 0) {
+            let lines = read_lines(&path)?;
+            for (done, line) in lines.iter().enumerate() {
+                let result = serde_json::from_str(line)
+                    .map_err(anyhow::Error::from)
+                    .and_then(AuditEvent::from_json);
+                let outcome = match result {
+                    Ok(event) => write(logger, &event),
+                    Err(e) => Outcome::Failed(e.context("unreadable spooled event")),
+                };
+                match outcome {
+                    Outcome::Unavailable => {
+                        let rest = &lines[done..];
+                        rewrite(&path, rest)?;
+                        state.spilled = rest.len() as u64;
+                        state.spill_bytes = rest.iter().map(|l| l.len() as u64 + 1).sum();
+                        state.replayed += written;
+                        return Ok(written);
+                    }
+                    Outcome::Written => written += 1,
+                    Outcome::Failed(e) => {
+                        tracing::warn!("Dropping spooled audit event: {:#}", e);
+                        state.dropped += 1;
+                    }
+                }
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+            state.spilled = 0;
+            state.spill_bytes = 0;
+        }
+
+        state.replayed += written;
+        if written > 0 {
+            tracing::info!(
+                "Audit database recovered: {} buffered events written",
+                written
+            );
+        }
+        state.warned_drop = false;
+        Ok(written)
+    }
+
+    /// Replay what the database takes, then move events still in memory to
+    /// the spool file so they survive the restart
+    pub fn flush(&self, logger: Option<&AuditLogger>) -> Result<()> {
+        if let Some(logger) = logger {
+            self.replay(logger)?;
+        }
+        let mut state = self.state.lock().unwrap();
+        let Some(path) = &self.config.path else {
+            return Ok(());
+        };
+        if state.memory.is_empty() {
+            return Ok(());
+        }
+        // Memory holds the oldest events, so they go first
+        let mut lines: Vec<String> = state
+            .memory
+            .iter()
+            .map(|e| e.to_json().to_string())
+            .collect();
+        if state.spilled > 0 {
+            lines.extend(read_lines(path)?);
+        }
+        rewrite(path, &lines)?;
+        state.memory.clear();
+        state.spilled = lines.len() as u64;
+        state.spill_bytes = lines.iter().map(|l| l.len() as u64 + 1).sum();
+        Ok(())
+    }
+
+    /// Queue sizes and counters
+    pub fn stats(&self) -> SpoolStats {
+        let state = self.state.lock().unwrap();
+        SpoolStats {
+            buffered: state.memory.len(),
+            spilled: state.spilled,
+            replayed: state.replayed,
+            dropped: state.dropped,
+        }
+    }
+
+    /// Replay queued events every few seconds on the current Tokio runtime;
+    /// `logger` returns the audit logger currently attached
+    pub fn spawn<F>(self: Arc<Self>, logger: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn() -> Option<Arc<AuditLogger>> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RETRY_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(logger) = logger() else {
+                    continue;
+                };
+                if self.state.lock().unwrap().is_empty() {
+                    continue;
+                }
+                let spool = Arc::clone(&self);
+                // SQLite work is blocking; keep it off the proxy's reactor
+                let result = tokio::task::spawn_blocking(move || spool.replay(&logger)).await;
+                if let Ok(Err(e)) = result {
+                    tracing::warn!("Audit spool replay failed: {:#}", e);
+                }
+            }
+        })
+    }
+}
+
+fn write(logger: &AuditLogger, event: &AuditEvent) -> Outcome {
+    match logger.log(event) {
+        Ok(_) => Outcome::Written,
+        // Written before the error was reported; nothing to redo
+        Err(e) if sqlite_code(&e) == Some(ErrorCode::ConstraintViolation) => Outcome::Written,
+        Err(e) if is_unavailable(&e) => Outcome::Unavailable,
+        Err(e) => Outcome::Failed(e),
+    }
+}
+
+fn sqlite_code(error: &anyhow::Error) -> Option<ErrorCode> {
+    error
+        .chain()
+        .find_map(|e| match e.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::SqliteFailure(failure, _)) => Some(failure.code),
+            _ => None,
+        })
+}
+
+/// The database refused the write for now, not because of the event
+fn is_unavailable(error: &anyhow::Error) -> bool {
+    matches!(
+        sqlite_code(error),
+        Some(
+            ErrorCode::DatabaseBusy
+                | ErrorCode::DatabaseLocked
+                | ErrorCode::DiskFull
+                | ErrorCode::SystemIoFailure
+                | ErrorCode::CannotOpen
+                | ErrorCode::ReadOnly
+        )
+    ) || error.chain().any(|e| e.is::<std::io::Error>())
+}
+
+fn count_lines(path: &Path) -> Result<(u64, u64)> {
+    match File::open(path) {
+        Ok(file) => {
+            let bytes = file.metadata()?.len();
+            let lines = BufReader::new(file).lines().count() as u64;
+            Ok((lines, bytes))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok((0, 0)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let lines = BufReader::new(file)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(lines.into_iter().filter(|l| !l.trim().is_empty()).collect())
+}
+
+/// Replace the spool file's contents (via a temporary file and rename)
+fn rewrite(path: &Path, lines: &[String]) -> Result<()> {
+    let tmp = path.with_extension("jsonl.tmp");
+    {
+        let mut file =
+            File::create(&tmp).with_context(|| format!("failed to create {}", tmp.display()))?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::tests::{memory_logger, request, set_read_only};
+    use crate::audit::AuditQuery;
+
+    fn spool(dir: &tempfile::TempDir, memory_events: usize) -> AuditSpool {
+        AuditSpool::new(SpoolConfig {
+            memory_events,
+            path: Some(dir.path().join("spool.jsonl")),
+            max_bytes: DEFAULT_SPOOL_MAX_BYTES,
+        })
+    }
+
+    #[test]
+    fn test_buffers_while_unavailable_and_replays_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = memory_logger();
+        let spool = spool(&dir, 2);
+        let events: Vec<AuditEvent> = (0..4).map(|i| request(&format!("prompt {i}"))).collect();
+
+        set_read_only(&logger, true);
+        for event in &events {
+            assert_eq!(spool.record(&logger, event).unwrap(), None);
+        }
+        let stats = spool.stats();
+        assert_eq!((stats.buffered, stats.spilled, stats.dropped), (2, 2, 0));
+        assert_eq!(spool.replay(&logger).unwrap(), 0);
+
+        set_read_only(&logger, false);
+        // Still queued events go first
+        assert_eq!(spool.record(&logger, &request("late")).unwrap(), None);
+        assert_eq!(spool.replay(&logger).unwrap(), 5);
+        assert_eq!(spool.stats().replayed, 5);
+        assert!(!dir.path().join("spool.jsonl").exists());
+
+        let stored: Vec<String> = logger.query(&AuditQuery::default()).unwrap()[1..]
+            .iter()
+            .rev()
+            .map(|row| row["request_id"].as_str().unwrap().to_string())
+            .collect();
+        let expected: Vec<&str> = events.iter().map(|e| e.request_id.as_str()).collect();
+        assert_eq!(stored, expected);
+        assert!(spool.record(&logger, &request("direct")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_memory_only_ring_drops_oldest() {
+        let logger = memory_logger();
+        let spool = AuditSpool::new(SpoolConfig {
+            memory_events: 2,
+            path: None,
+            max_bytes: 0,
+        });
+        set_read_only(&logger, true);
+        for i in 0..3 {
+            spool
+                .record(&logger, &request(&format!("prompt {i}")))
+                .unwrap();
+        }
+        assert_eq!(spool.stats().dropped, 1);
+
+        set_read_only(&logger, false);
+        assert_eq!(spool.replay(&logger).unwrap(), 2);
+        assert_eq!(logger.count(&AuditQuery::default()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_flush_keeps_events_for_next_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = memory_logger();
+        let first = spool(&dir, 10);
+        set_read_only(&logger, true);
+        first.record(&logger, &request("unsaved")).unwrap();
+        first.flush(Some(&logger)).unwrap();
+        assert_eq!(first.stats().spilled, 1);
+
+        set_read_only(&logger, false);
+        let second = spool(&dir, 10);
+        assert_eq!(second.stats().spilled, 1);
+        assert_eq!(second.replay(&logger).unwrap(), 1);
+        assert_eq!(logger.count(&AuditQuery::default()).unwrap(), 1);
+    }
+}
@@ -0,0 +1,380 @@
+//! Server-sent event passthrough for streamed LLM responses
+//!
+//! Chat APIs stream completions as `text/event-stream`. Buffering those
+//! would hold every token until generation finishes, so streamed responses
+//! are forwarded frame by frame as they arrive. [`StreamingBody`] wraps the
+//! upstream body, passes each chunk through untouched and feeds a copy to an
+//! [`SseAccumulator`], which picks the served model, finish reason, safety
+//! flags and token usage out of the events for the `ResponseContext`.
+//!
+//! Usage is taken from the provider when it reports it in the stream
+//! (Anthropic always, Gemini per chunk, OpenAI-compatible APIs with
+//! `stream_options.include_usage`); otherwise completion tokens are
+//! estimated from the streamed text. Bedrock's binary event-stream
+//! encoding is not SSE and is passed through without accounting.
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use crate::providers::{
+    metadata_from_json, usage_from_json, ParsedUsage, Provider, ResponseMetadata,
+};
+
+/// Rough characters-per-token ratio used when a stream reports no usage
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Whether a response `Content-Type` is a server-sent event stream
+pub fn is_event_stream(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|ct| {
+        ct.split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+    })
+}
+
+/// Incremental SSE parser collecting usage and metadata from stream events
+///
+/// Chunks may split events (or lines) anywhere; partial lines are kept
+/// until the rest arrives.
+#[derive(Debug)]
+pub struct SseAccumulator {
+    provider: Provider,
+    line: Vec<u8>,
+    data: String,
+    events: usize,
+    content_chars: usize,
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    cost_usd: Option<f64>,
+    metadata: ResponseMetadata,
+}
+
+impl SseAccumulator {
+    /// Create an accumulator for a provider's event format
+    pub fn new(provider: Provider) -> Self {
+        SseAccumulator {
+            provider,
+            line: Vec::new(),
+            data: String::new(),
+            events: 0,
+            content_chars: 0,
+            prompt_tokens: None,
+            completion_tokens: None,
+            cost_usd: None,
+            metadata: ResponseMetadata::default(),
+        }
+    }
+
+    /// Feed the next chunk of the stream
+    pub fn push(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            if byte == b'\n' {
+                let mut line = std::mem::take(&mut self.line);
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                self.process_line(&String::from_utf8_lossy(&line));
+            } else {
+                self.line.push(byte);
+            }
+        }
+    }
+
+    /// Number of complete events seen
+    pub fn events(&self) -> usize {
+        self.events
+    }
+
+    /// Token usage reported by the provider, if the stream included it
+    pub fn usage(&self) -> Option<ParsedUsage> {
+        if self.prompt_tokens.is_none() && self.completion_tokens.is_none() {
+            return None;
+        }
+        Some(ParsedUsage {
+            prompt_tokens: self.prompt_tokens.unwrap_or(0),
+            completion_tokens: self.completion_tokens.unwrap_or(0),
+            cost_usd: self.cost_usd,
+        })
+    }
+
+    /// Total tokens: reported usage, or an estimate from the streamed text
+    pub fn tokens(&self) -> usize {
+        match self.usage() {
+            Some(usage) => usage.total_tokens() as usize,
+            None => self.content_chars.div_ceil(CHARS_PER_TOKEN),
+        }
+    }
+
+    /// Served model, finish reason and safety flags seen so far
+    pub fn metadata(&self) -> &ResponseMetadata {
+        &self.metadata
+    }
+
+    /// Consume the accumulator, flushing an unterminated final event
+    pub fn finish(mut self) -> Self {
+        if !self.line.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.line)).into_owned();
+            self.process_line(&line);
+        }
+        self.dispatch();
+        self
+    }
+
+    fn process_line(&mut self, line: &str) {
+        if line.is_empty() {
+            self.dispatch();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            if !self.data.is_empty() {
+                self.data.push('\n');
+            }
+            self.data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        }
+        // "event:", "id:", "retry:" and ":" comments carry nothing we need
+    }
+
+    fn dispatch(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return;
+        }
+        self.events += 1;
+        if data == "[DONE]" {
+            return;
+        }
+        if let Ok(event) = serde_json::from_str::<Value>(&data) {
+            self.handle_event(&event);
+        }
+    }
+
+    fn handle_event(&mut self, event: &Value) {
+        match self.provider {
+            Provider::Anthropic => match event["type"].as_str() {
+                Some("message_start") => {
+                    let message = &event["message"];
+                    self.merge_metadata(metadata_from_json(self.provider, message));
+                    self.merge_usage(usage_from_json(self.provider, message));
+                }
+                Some("content_block_delta") => {
+                    self.count_text(&event["delta"]["text"]);
+                }
+                Some("message_delta") => {
+                    self.merge_metadata(metadata_from_json(self.provider, &event["delta"]));
+                    if let Some(output) = event["usage"]["output_tokens"].as_u64() {
+                        self.completion_tokens = Some(output);
+                    }
+                }
+                _ => {}
+            },
+            Provider::Gemini => {
+                for candidate in event["candidates"].as_array().into_iter().flatten() {
+                    for part in candidate["content"]["parts"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                    {
+                        self.count_text(&part["text"]);
+                    }
+                }
+                self.merge_metadata(metadata_from_json(self.provider, event));
+                self.merge_usage(usage_from_json(self.provider, event));
+            }
+            _ => {
+                for choice in event["choices"].as_array().into_iter().flatten() {
+                    self.count_text(&choice["delta"]["content"]);
+                }
+                self.merge_metadata(metadata_from_json(self.provider, event));
+                self.merge_usage(usage_from_json(self.provider, event));
+            }
+        }
+    }
+
+    fn count_text(&mut self, text: &Value) {
+        if let Some(text) = text.as_str() {
+            self.content_chars += text.chars().count();
+        }
+    }
+
+    /// Later events override earlier ones; usage in a stream is cumulative
+    fn merge_usage(&mut self, usage: Option<ParsedUsage>) {
+        if let Some(usage) = usage {
+            self.prompt_tokens = Some(usage.prompt_tokens);
+            self.completion_tokens = Some(usage.completion_tokens);
+            self.cost_usd = usage.cost_usd.or(self.cost_usd);
+        }
+    }
+
+    fn merge_metadata(&mut self, metadata: ResponseMetadata) {
+        if metadata.model.is_some() {
+            self.metadata.model = metadata.model;
+        }
+        if metadata.finish_reason.is_some() {
+            self.metadata.finish_reason = metadata.finish_reason;
+        }
+        for flag in metadata.safety_flags {
+            if !self.metadata.safety_flags.contains(&flag) {
+                self.metadata.safety_flags.push(flag);
+            }
+        }
+    }
+}
+
+/// Callback run once a stream ends, errors or is dropped by the client
+type OnComplete = Box<dyn FnOnce(SseAccumulator) + Send>;
+
+/// Response body that forwards upstream frames as they arrive while
+/// accumulating usage and metadata
+///
+/// The completion callback receives the accumulator exactly once: at end
+/// of stream, on an upstream error, or when the client disconnects and the
+/// body is dropped early (in which case usage covers what was sent).
+pub struct StreamingBody<B> {
+    inner: B,
+    accumulator: Option<SseAccumulator>,
+    on_complete: Option<OnComplete>,
+}
+
+impl<B> StreamingBody<B> {
+    /// Wrap an upstream body
+    pub fn new(
+        inner: B,
+        provider: Provider,
+        on_complete: impl FnOnce(SseAccumulator) + Send + 'static,
+    ) -> Self {
+        StreamingBody {
+            inner,
+            accumulator: Some(SseAccumulator::new(provider)),
+            on_complete: Some(Box::new(on_complete)),
+        }
+    }
+
+    fn complete(&mut self) {
+        if let (Some(accumulator), Some(on_complete)) =
+            (self.accumulator.take(), self.on_complete.take())
+        {
+            on_complete(accumulator.finish());
+        }
+    }
+}
+
+impl<B> Body for StreamingBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let this = self.get_mut();
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let (Some(data), Some(accumulator)) = (frame.data_ref(), &mut this.accumulator) {
+                    accumulator.push(data);
+                }
+            }
+            Some(Err(_)) | None => this.complete(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for StreamingBody<B> {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    const OPENAI_STREAM: &str = concat!(
+        "data: {\"model\":\"gpt-4o-mini-2024-07-18\",\"choices\":[{\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"model\":\"gpt-4o-mini-2024-07-18\",\"choices\":[{\"delta\":{\"content\":\" there, friend\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"model\":\"gpt-4o-mini-2024-07-18\",\"choices\":[{\"delta\":{},\"finish_reason\":\"length\"}]}\n\n",
+        "data: [DONE]\n\n",
+    );
+
+    #[test]
+    fn test_openai_chunks_split_anywhere() {
+        let mut whole = SseAccumulator::new(Provider::OpenAI);
+        whole.push(OPENAI_STREAM.as_bytes());
+
+        let mut split = SseAccumulator::new(Provider::OpenAI);
+        for chunk in OPENAI_STREAM.as_bytes().chunks(7) {
+            split.push(chunk);
+        }
+
+        for acc in [whole, split] {
+            assert_eq!(acc.events(), 4);
+            assert!(acc.usage().is_none());
+            // "Hello there, friend" = 19 chars
+            assert_eq!(acc.tokens(), 5);
+            assert_eq!(
+                acc.metadata().model.as_deref(),
+                Some("gpt-4o-mini-2024-07-18")
+            );
+            assert_eq!(acc.metadata().finish_reason.as_deref(), Some("length"));
+        }
+    }
+
+    #[test]
+    fn test_anthropic_reported_usage() {
+        let mut acc = SseAccumulator::new(Provider::Anthropic);
+        acc.push(
+            concat!(
+                "event: message_start\r\n",
+                "data: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-3-5-haiku-20241022\",\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\r\n\r\n",
+                "event: content_block_delta\r\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi!\"}}\r\n\r\n",
+                "event: message_delta\r\n",
+                "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":12}}\r\n\r\n",
+            )
+            .as_bytes(),
+        );
+        let usage = acc.usage().unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (25, 12));
+        assert_eq!(acc.tokens(), 37);
+        assert_eq!(
+            acc.metadata().model.as_deref(),
+            Some("claude-3-5-haiku-20241022")
+        );
+        assert_eq!(acc.metadata().finish_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_body_passes_chunks_through() {
+        let chunks: Vec<Result<Frame<Bytes>, Infallible>> = OPENAI_STREAM
+            .as_bytes()
+            .chunks(40)
+            .map(|c| Ok(Frame::data(Bytes::copy_from_slice(c))))
+            .collect();
+        let done = Arc::new(Mutex::new(None));
+        let sink = Arc::clone(&done);
+        let body = StreamingBody::new(
+            StreamBody::new(futures::stream::iter(chunks)),
+            Provider::OpenAI,
+            move |acc| *sink.lock().unwrap() = Some(acc.tokens()),
+        );
+
+        let forwarded = body.collect().await.unwrap().to_bytes();
+        assert_eq!(forwarded, OPENAI_STREAM.as_bytes());
+        assert_eq!(*done.lock().unwrap(), Some(5));
+    }
+}
@@ -0,0 +1,225 @@
+//! Multi-tenant household support
+//!
+//! One YORI instance on a shared router (duplex, co-op, shared house) can
+//! govern several families. Each household is a *tenant* identified by the
+//! networks its devices live on; the tenant id is attached to every
+//! request, audit event and usage series so data and policies stay
+//! isolated.
+//!
+//! Policies for a tenant live in `<policy_dir>/tenants/<tenant_id>/`. They
+//! are compiled together with the shared policies in `<policy_dir>` into a
+//! policy set of their own, used for requests whose `input.tenant` is that
+//! tenant; other households' requests never see them.
+
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::netaddr::parse_client_ip;
+
+/// Tenant id used for traffic that matches no configured household
+pub const DEFAULT_TENANT: &str = "default";
+
+/// A household sharing the router
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    /// Stable identifier (used in audit rows and policy paths)
+    pub id: String,
+
+    /// Display name (e.g., "Upstairs unit")
+    pub name: String,
+
+    /// Networks whose clients belong to this tenant
+    pub networks: Vec<IpNet>,
+}
+
+/// Resolves client addresses to tenants
+///
+/// When networks overlap, the most specific (longest prefix) match wins,
+/// so a shared /16 can be carved into per-household /24s.
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// tenants = yori_core.TenantRegistry()
+/// tenants.add_tenant("upstairs", "Upstairs unit", ["192.168.10.0/24"])
+/// tenants.add_tenant("downstairs", "Downstairs unit", ["192.168.20.0/24"])
+///
+/// assert tenants.resolve("192.168.20.14") == "downstairs"
+/// ```
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct TenantRegistry {
+    tenants: Vec<Tenant>,
+}
+
+impl TenantRegistry {
+    /// Register a tenant
+    pub fn register(&mut self, tenant: Tenant) -> Result<()> {
+        if !is_valid_id(&tenant.id) {
+            bail!(
+                "invalid tenant id '{}': use letters, digits, '-' or '_'",
+                tenant.id
+            );
+        }
+        if self.tenants.iter().any(|t| t.id == tenant.id) {
+            bail!("tenant '{}' is already registered", tenant.id);
+        }
+        self.tenants.push(tenant);
+        Ok(())
+    }
+
+    /// Look up a tenant by id
+    pub fn get(&self, id: &str) -> Option<&Tenant> {
+        self.tenants.iter().find(|t| t.id == id)
+    }
+
+    /// All registered tenants
+    pub fn tenants(&self) -> &[Tenant] {
+        &self.tenants
+    }
+
+    /// Resolve a client address to a tenant id
+    ///
+    /// Returns [`DEFAULT_TENANT`] if no tenant network contains the address.
+    pub fn resolve_ip(&self, ip: IpAddr) -> &str {
+        let ip = ip.to_canonical();
+        self.tenants
+            .iter()
+            .flat_map(|t| t.networks.iter().map(move |n| (t, n)))
+            .filter(|(_, net)| net.contains(&ip))
+            .max_by_key(|(_, net)| net.prefix_len())
+            .map(|(t, _)| t.id.as_str())
+            .unwrap_or(DEFAULT_TENANT)
+    }
+
+    /// Resolve a client address given as a string
+    ///
+    /// Unparseable addresses resolve to [`DEFAULT_TENANT`].
+    pub fn resolve_str(&self, ip: &str) -> &str {
+        parse_client_ip(ip)
+            .map(|ip| self.resolve_ip(ip))
+            .unwrap_or(DEFAULT_TENANT)
+    }
+}
+
+/// Subdirectory of the policy directory holding per-tenant policies
+pub const TENANT_POLICY_DIR: &str = "tenants";
+
+/// Directory holding a tenant's own policies
+pub fn tenant_policy_dir(policy_dir: &Path, tenant_id: &str) -> PathBuf {
+    policy_dir.join(TENANT_POLICY_DIR).join(tenant_id)
+}
+
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[pymethods]
+impl TenantRegistry {
+    /// Create an empty tenant registry
+    #[new]
+    fn new() -> Self {
+        TenantRegistry::default()
+    }
+
+    /// Register a household
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Stable tenant identifier
+    /// * `name` - Display name
+    /// * `networks` - CIDR networks belonging to the tenant
+    fn add_tenant(&mut self, id: String, name: String, networks: Vec<String>) -> PyResult<()> {
+        let networks = networks
+            .iter()
+            .map(|n| {
+                n.parse::<IpNet>()
+                    .map_err(|e| PyValueError::new_err(format!("invalid network '{}': {}", n, e)))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        self.register(Tenant { id, name, networks })
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Resolve a client IP to a tenant id
+    fn resolve(&self, client_ip: String) -> String {
+        self.resolve_str(&client_ip).to_string()
+    }
+
+    /// List registered tenants
+    ///
+    /// # Returns
+    ///
+    /// List of dictionaries with `id`, `name` and `networks`
+    fn list(&self, py: Python) -> PyResult<PyObject> {
+        let list = PyList::empty_bound(py);
+        for tenant in &self.tenants {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", &tenant.id)?;
+            dict.set_item("name", &tenant.name)?;
+            dict.set_item(
+                "networks",
+                tenant
+                    .networks
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>(),
+            )?;
+            list.append(dict)?;
+        }
+        Ok(list.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(id: &str, networks: &[&str]) -> Tenant {
+        Tenant {
+            id: id.to_string(),
+            name: id.to_string(),
+            networks: networks.iter().map(|n| n.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_longest_prefix() {
+        let mut registry = TenantRegistry::default();
+        registry
+            .register(tenant("building", &["10.0.0.0/16"]))
+            .unwrap();
+        registry
+            .register(tenant("unit-a", &["10.0.1.0/24", "fd00:a::/64"]))
+            .unwrap();
+
+        assert_eq!(registry.resolve_str("10.0.1.20"), "unit-a");
+        assert_eq!(registry.resolve_str("10.0.7.3"), "building");
+        assert_eq!(registry.resolve_str("fd00:a::1234"), "unit-a");
+        assert_eq!(registry.resolve_str("192.168.1.5"), DEFAULT_TENANT);
+        assert_eq!(registry.resolve_str("not-an-ip"), DEFAULT_TENANT);
+    }
+
+    #[test]
+    fn test_register_rejects_bad_or_duplicate_ids() {
+        let mut registry = TenantRegistry::default();
+        registry.register(tenant("unit-a", &[])).unwrap();
+        assert!(registry.register(tenant("unit-a", &[])).is_err());
+        assert!(registry.register(tenant("../etc", &[])).is_err());
+        assert_eq!(
+            tenant_policy_dir(Path::new("/usr/local/etc/yori/policies"), "unit-a"),
+            PathBuf::from("/usr/local/etc/yori/policies/tenants/unit-a")
+        );
+    }
+}
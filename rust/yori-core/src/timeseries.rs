@@ -0,0 +1,331 @@
+//! Usage time series export for Grafana and other TSDB tooling
+//!
+//! YORI already knows every request, token count and block decision; this
+//! module rolls those up into fixed-width time buckets per tenant and
+//! device so an existing home Grafana instance can chart them without
+//! scraping SQLite.
+//!
+//! Two output formats are supported:
+//!
+//! - **InfluxDB line protocol** — push to InfluxDB/VictoriaMetrics/Telegraf
+//! - **JSON rows** — for the Grafana JSON/Infinity datasource plugins
+
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+use crate::tenant::DEFAULT_TENANT;
+
+/// Default bucket width (one minute)
+const DEFAULT_BUCKET_SECS: u64 = 60;
+
+/// Default retention for in-memory buckets (two days)
+const DEFAULT_RETENTION_SECS: u64 = 2 * 24 * 3600;
+
+/// Measurement name used in line protocol output
+const MEASUREMENT: &str = "yori_usage";
+
+/// Aggregated usage for one device within one time bucket
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsagePoint {
+    /// Number of requests seen
+    pub requests: u64,
+
+    /// Total tokens (prompt + completion) seen
+    pub tokens: u64,
+
+    /// Number of requests blocked by policy
+    pub blocks: u64,
+
+    /// Estimated cost in USD
+    pub cost_usd: f64,
+}
+
+/// A usage point tagged with its bucket time, tenant and device, as exported
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRow {
+    /// Bucket start time (ISO 8601)
+    pub time: DateTime<Utc>,
+
+    /// Tenant (household) the device belongs to
+    pub tenant: String,
+
+    /// Device identifier (name if known, otherwise client IP)
+    pub device: String,
+
+    #[serde(flatten)]
+    pub point: UsagePoint,
+}
+
+/// Bucketed per-device usage counters
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// series = yori_core.UsageSeries(bucket_seconds=60)
+/// series.record("kids-ipad", tokens=850, blocked=False, cost_usd=0.0012, tenant="default")
+///
+/// # Push to InfluxDB / Telegraf
+/// body = series.to_line_protocol(since_seconds=3600)
+/// ```
+#[pyclass]
+pub struct UsageSeries {
+    buckets: Mutex<BTreeMap<(i64, String, String), UsagePoint>>,
+    bucket_seconds: i64,
+    retention: ChronoDuration,
+}
+
+impl UsageSeries {
+    /// Create a series with the given bucket width and retention window
+    pub fn with_settings(bucket_seconds: u64, retention_seconds: u64) -> Self {
+        UsageSeries {
+            buckets: Mutex::new(BTreeMap::new()),
+            bucket_seconds: bucket_seconds.max(1) as i64,
+            retention: ChronoDuration::seconds(retention_seconds as i64),
+        }
+    }
+
+    /// Add one request's usage at the given time
+    pub fn record_at(
+        &self,
+        timestamp: DateTime<Utc>,
+        tenant: &str,
+        device: &str,
+        tokens: u64,
+        blocked: bool,
+        cost_usd: f64,
+    ) {
+        let ts = timestamp.timestamp();
+        let bucket = ts - ts.rem_euclid(self.bucket_seconds);
+        let cutoff = (Utc::now() - self.retention).timestamp();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let point = buckets
+            .entry((bucket, tenant.to_string(), device.to_string()))
+            .or_default();
+        point.requests += 1;
+        point.tokens += tokens;
+        point.blocks += u64::from(blocked);
+        point.cost_usd += cost_usd;
+
+        // Keys sort by bucket start, so everything older splits off the front
+        let kept = buckets.split_off(&(cutoff, String::new(), String::new()));
+        *buckets = kept;
+    }
+
+    /// Add one request's usage now
+    pub fn record_usage(
+        &self,
+        tenant: &str,
+        device: &str,
+        tokens: u64,
+        blocked: bool,
+        cost_usd: f64,
+    ) {
+        self.record_at(Utc::now(), tenant, device, tokens, blocked, cost_usd);
+    }
+
+    /// All rows with a bucket start at or after `since`, oldest first
+    pub fn rows_since(&self, since: DateTime<Utc>) -> Vec<UsageRow> {
+        let since = since.timestamp();
+        self.buckets
+            .lock()
+            .unwrap()
+            .range(
+                (
+                    since - since.rem_euclid(self.bucket_seconds),
+                    String::new(),
+                    String::new(),
+                )..,
+            )
+            .map(|((start, tenant, device), point)| UsageRow {
+                time: Utc.timestamp_opt(*start, 0).unwrap(),
+                tenant: tenant.clone(),
+                device: device.clone(),
+                point: point.clone(),
+            })
+            .collect()
+    }
+
+    /// Usage of one device since `since`, summed over buckets
+    pub fn device_totals_since(
+        &self,
+        since: DateTime<Utc>,
+        tenant: &str,
+        device: &str,
+    ) -> UsagePoint {
+        self.rows_since(since)
+            .into_iter()
+            .filter(|row| row.tenant == tenant && row.device == device)
+            .fold(UsagePoint::default(), |mut total, row| {
+                total.requests += row.point.requests;
+                total.tokens += row.point.tokens;
+                total.blocks += row.point.blocks;
+                total.cost_usd += row.point.cost_usd;
+                total
+            })
+    }
+
+    /// Render rows as InfluxDB line protocol (nanosecond precision)
+    pub fn line_protocol_since(&self, since: DateTime<Utc>) -> String {
+        let mut out = String::new();
+        for row in self.rows_since(since) {
+            let _ = writeln!(
+                out,
+                "{},tenant={},device={} requests={}i,tokens={}i,blocks={}i,cost_usd={} {}",
+                MEASUREMENT,
+                escape_tag(&row.tenant),
+                escape_tag(&row.device),
+                row.point.requests,
+                row.point.tokens,
+                row.point.blocks,
+                row.point.cost_usd,
+                row.time.timestamp() * 1_000_000_000,
+            );
+        }
+        out
+    }
+}
+
+impl Default for UsageSeries {
+    fn default() -> Self {
+        UsageSeries::with_settings(DEFAULT_BUCKET_SECS, DEFAULT_RETENTION_SECS)
+    }
+}
+
+#[pymethods]
+impl UsageSeries {
+    /// Create a new usage series
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_seconds` - Width of each time bucket (default: 60)
+    /// * `retention_seconds` - How long buckets are kept (default: 2 days)
+    #[new]
+    #[pyo3(signature = (bucket_seconds=DEFAULT_BUCKET_SECS, retention_seconds=DEFAULT_RETENTION_SECS))]
+    fn new(bucket_seconds: u64, retention_seconds: u64) -> Self {
+        UsageSeries::with_settings(bucket_seconds, retention_seconds)
+    }
+
+    /// Record one request's usage
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Device name or client IP
+    /// * `tokens` - Tokens consumed (default: 0)
+    /// * `blocked` - Whether the request was blocked (default: False)
+    /// * `cost_usd` - Estimated cost (default: 0.0)
+    /// * `tenant` - Tenant the device belongs to (default: "default")
+    #[pyo3(signature = (device, tokens=0, blocked=false, cost_usd=0.0, tenant=DEFAULT_TENANT.to_string()))]
+    fn record(&self, device: String, tokens: u64, blocked: bool, cost_usd: f64, tenant: String) {
+        self.record_usage(&tenant, &device, tokens, blocked, cost_usd);
+    }
+
+    /// Export recent buckets as InfluxDB line protocol
+    ///
+    /// # Arguments
+    ///
+    /// * `since_seconds` - How far back to export (default: 1 hour)
+    ///
+    /// # Returns
+    ///
+    /// Newline-separated line protocol, ready to POST to `/api/v2/write`
+    #[pyo3(signature = (since_seconds=3600))]
+    fn to_line_protocol(&self, since_seconds: u64) -> String {
+        self.line_protocol_since(Utc::now() - ChronoDuration::seconds(since_seconds as i64))
+    }
+
+    /// Export recent buckets as JSON rows for Grafana JSON datasources
+    ///
+    /// # Arguments
+    ///
+    /// * `since_seconds` - How far back to export (default: 1 hour)
+    ///
+    /// # Returns
+    ///
+    /// JSON array of `{time, tenant, device, requests, tokens, blocks, cost_usd}`
+    #[pyo3(signature = (since_seconds=3600))]
+    fn to_json(&self, since_seconds: u64) -> PyResult<String> {
+        let rows = self.rows_since(Utc::now() - ChronoDuration::seconds(since_seconds as i64));
+        serde_json::to_string(&rows)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+}
+
+/// Escape a tag value for line protocol (commas, spaces, equals signs)
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | ' ' | '=' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucketing_and_line_protocol() {
+        let series = UsageSeries::with_settings(60, 3600);
+        let base = Utc::now() - ChronoDuration::minutes(10);
+        let base = base - ChronoDuration::seconds(base.timestamp().rem_euclid(60));
+
+        series.record_at(base, "unit-a", "Timmy's iPad", 100, false, 0.01);
+        series.record_at(
+            base + ChronoDuration::seconds(30),
+            "unit-a",
+            "Timmy's iPad",
+            50,
+            true,
+            0.0,
+        );
+        series.record_at(
+            base + ChronoDuration::seconds(90),
+            "unit-a",
+            "laptop",
+            10,
+            false,
+            0.0,
+        );
+
+        let rows = series.rows_since(base);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].point.requests, 2);
+        assert_eq!(rows[0].point.tokens, 150);
+        assert_eq!(rows[0].point.blocks, 1);
+
+        let lines = series.line_protocol_since(base);
+        let first = lines.lines().next().unwrap();
+        assert!(first.starts_with(
+            "yori_usage,tenant=unit-a,device=Timmy's\\ iPad requests=2i,tokens=150i,blocks=1i"
+        ));
+        assert!(first.ends_with(&format!("{}", base.timestamp() * 1_000_000_000)));
+    }
+
+    #[test]
+    fn test_old_buckets_are_dropped() {
+        let series = UsageSeries::with_settings(60, 600);
+        series.record_at(
+            Utc::now() - ChronoDuration::hours(1),
+            DEFAULT_TENANT,
+            "old",
+            1,
+            false,
+            0.0,
+        );
+        series.record_usage(DEFAULT_TENANT, "new", 1, false, 0.0);
+
+        let rows = series.rows_since(Utc::now() - ChronoDuration::hours(2));
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].device, "new");
+    }
+}
@@ -0,0 +1,449 @@
+//! Conversation transcripts (opt-in)
+//!
+//! Audit events keep a short prompt preview. A household that wants the
+//! whole conversation, say to read back what a child asked a homework
+//! helper, can opt in to transcripts: the full prompt and completion of
+//! each request, grouped by session ([`crate::session`]).
+//!
+//! Only tenants listed in `transcripts.tenants` are recorded. Prompts and
+//! completions go through the audit PII redaction rules first, then each
+//! turn is sealed with ChaCha20-Poly1305 under a key derived from the
+//! [`crate::vault`] master key, its session and request id bound as
+//! associated data. The store is a SQLite file of its own
+//! (`/var/db/yori/transcripts.db` by default), so nothing readable reaches
+//! the disk with or without a SQLCipher build.
+//!
+//! Turns are deleted after `transcripts.retention_days`, independently of
+//! audit retention: a household can keep a year of audit metadata but
+//! only a week of transcripts.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::YoriConfig;
+use crate::policy::json_to_py;
+use crate::redact::{RedactionConfig, Redactor};
+use crate::vault::{Vault, DEFAULT_VAULT_PATH, TRANSCRIPTS_PURPOSE};
+
+/// Default location of the transcript database
+pub const DEFAULT_TRANSCRIPT_DB: &str = "/var/db/yori/transcripts.db";
+
+/// Default number of days transcripts are kept
+pub const DEFAULT_TRANSCRIPT_RETENTION_DAYS: u32 = 7;
+
+const NONCE_LEN: usize = 12;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transcript_turns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    request_id TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    tenant TEXT NOT NULL,
+    client_ip TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    model TEXT,
+    nonce BLOB NOT NULL,
+    sealed BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_transcript_session ON transcript_turns(session_id, id);
+CREATE INDEX IF NOT EXISTS idx_transcript_timestamp ON transcript_turns(timestamp);
+";
+
+/// Transcript store configuration
+#[derive(Debug, Clone)]
+pub struct TranscriptConfig {
+    /// SQLite database path (":memory:" for an ephemeral store)
+    pub database: PathBuf,
+
+    /// Vault key file the transcript key is derived from
+    pub vault_key: PathBuf,
+
+    /// Tenants (households) whose conversations are recorded
+    pub tenants: Vec<String>,
+
+    /// How long to keep transcripts
+    pub retention_days: u32,
+
+    /// PII detectors run on prompts and completions before sealing
+    pub redaction: RedactionConfig,
+
+    /// How often turns past `retention_days` are deleted
+    pub prune_interval: Duration,
+}
+
+impl Default for TranscriptConfig {
+    fn default() -> Self {
+        TranscriptConfig {
+            database: PathBuf::from(DEFAULT_TRANSCRIPT_DB),
+            vault_key: PathBuf::from(DEFAULT_VAULT_PATH),
+            tenants: Vec::new(),
+            retention_days: DEFAULT_TRANSCRIPT_RETENTION_DAYS,
+            redaction: RedactionConfig::default(),
+            prune_interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// One request and its answer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TranscriptTurn {
+    pub session_id: String,
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub tenant: String,
+    pub client_ip: String,
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub prompt: String,
+
+    /// None when the request was blocked or the answer couldn't be read
+    pub completion: Option<String>,
+}
+
+/// The sealed part of a turn
+#[derive(Serialize, Deserialize)]
+struct TurnText {
+    prompt: String,
+    completion: Option<String>,
+}
+
+/// Encrypted store of full conversations
+pub struct TranscriptStore {
+    config: TranscriptConfig,
+    conn: Mutex<Connection>,
+    cipher: ChaCha20Poly1305,
+    redactor: Redactor,
+}
+
+impl std::fmt::Debug for TranscriptStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranscriptStore")
+            .field("database", &self.config.database)
+            .field("tenants", &self.config.tenants)
+            .finish()
+    }
+}
+
+impl TranscriptStore {
+    /// Open (or create) the transcript database
+    pub fn open(config: TranscriptConfig) -> Result<Self> {
+        if let Some(dir) = config.database.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+            }
+        }
+        let conn = Connection::open(&config.database).with_context(|| {
+            format!(
+                "failed to open transcript database {}",
+                config.database.display()
+            )
+        })?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        conn.execute_batch(SCHEMA)
+            .context("failed to initialize transcript schema")?;
+
+        let vault = Vault::open_or_create(&config.vault_key)?;
+        let key = vault.derive_key(TRANSCRIPTS_PURPOSE);
+        Ok(TranscriptStore {
+            redactor: Redactor::new(&config.redaction)?,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key.as_ref())),
+            conn: Mutex::new(conn),
+            config,
+        })
+    }
+
+    /// Whether a tenant opted in to transcripts
+    pub fn records(&self, tenant: &str) -> bool {
+        self.config.tenants.iter().any(|t| t == tenant)
+    }
+
+    /// Redact, seal and store a turn
+    ///
+    /// Returns false (storing nothing) if its tenant hasn't opted in.
+    pub fn record(&self, turn: &TranscriptTurn) -> Result<bool> {
+        if !self.records(&turn.tenant) {
+            return Ok(false);
+        }
+        let text = TurnText {
+            prompt: self.redactor.redact(&turn.prompt).text,
+            completion: turn
+                .completion
+                .as_deref()
+                .map(|c| self.redactor.redact(c).text),
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| anyhow!("failed to generate nonce: {}", e))?;
+        let sealed = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &serde_json::to_vec(&text)?,
+                    aad: associated_data(&turn.session_id, &turn.request_id).as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("failed to seal transcript turn"))?;
+
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO transcript_turns (
+                session_id, request_id, timestamp, tenant, client_ip, endpoint, model, nonce, sealed
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                turn.session_id,
+                turn.request_id,
+                turn.timestamp.to_rfc3339(),
+                turn.tenant,
+                turn.client_ip,
+                turn.endpoint,
+                turn.model,
+                &nonce[..],
+                sealed,
+            ],
+        )?;
+        Ok(true)
+    }
+
+    /// Every stored turn of a session, oldest first
+    pub fn session(&self, session_id: &str) -> Result<Vec<TranscriptTurn>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT request_id, timestamp, tenant, client_ip, endpoint, model, nonce, sealed
+             FROM transcript_turns
+             WHERE session_id = ?1
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map([session_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Vec<u8>>(6)?,
+                row.get::<_, Vec<u8>>(7)?,
+            ))
+        })?;
+
+        let mut turns = Vec::new();
+        for row in rows {
+            let (request_id, timestamp, tenant, client_ip, endpoint, model, nonce, sealed) = row?;
+            if nonce.len() != NONCE_LEN {
+                bail!("transcript turn {} has a corrupt nonce", request_id);
+            }
+            let plain = self
+                .cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &sealed,
+                        aad: associated_data(session_id, &request_id).as_bytes(),
+                    },
+                )
+                .map_err(|_| {
+                    anyhow!(
+                        "transcript turn {} can't be opened: wrong vault key or altered row",
+                        request_id
+                    )
+                })?;
+            let text: TurnText = serde_json::from_slice(&plain)?;
+            turns.push(TranscriptTurn {
+                session_id: session_id.to_string(),
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                request_id,
+                tenant,
+                client_ip,
+                endpoint,
+                model,
+                prompt: text.prompt,
+                completion: text.completion,
+            });
+        }
+        Ok(turns)
+    }
+
+    /// Delete turns older than `retention_days`, returning how many
+    pub fn prune_expired(&self) -> Result<u64> {
+        let cutoff = Utc::now() - chrono::Duration::days(i64::from(self.config.retention_days));
+        self.prune_before(cutoff)
+    }
+
+    /// Delete turns before `cutoff`
+    pub fn prune_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let deleted = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM transcript_turns WHERE timestamp < ?1",
+                [cutoff.to_rfc3339()],
+            )
+            .context("failed to prune transcripts")?;
+        Ok(deleted as u64)
+    }
+
+    /// Prune expired turns every `prune_interval` on the current Tokio
+    /// runtime
+    pub fn spawn_pruning(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.prune_interval);
+            loop {
+                interval.tick().await;
+                let store = Arc::clone(&self);
+                match tokio::task::spawn_blocking(move || store.prune_expired()).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(deleted)) => {
+                        tracing::info!("Transcript retention: {} turns deleted", deleted)
+                    }
+                    Ok(Err(e)) => tracing::warn!("Transcript pruning failed: {:#}", e),
+                    Err(_) => {}
+                }
+            }
+        })
+    }
+}
+
+fn associated_data(session_id: &str, request_id: &str) -> String {
+    format!("{}\0{}", session_id, request_id)
+}
+
+/// Read access to the transcript store for the dashboard
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// transcripts = yori_core.TranscriptStore()
+/// for turn in transcripts.session(session_id):
+///     print(turn["prompt"], "->", turn["completion"])
+/// ```
+#[pyclass(name = "TranscriptStore")]
+pub struct PyTranscriptStore {
+    store: Arc<TranscriptStore>,
+}
+
+#[pymethods]
+impl PyTranscriptStore {
+    /// Open the transcript store
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration file with a `[transcripts]` section
+    ///   (default: the gateway's yori.toml)
+    #[new]
+    #[pyo3(signature = (config=None))]
+    fn new(config: Option<String>) -> PyResult<Self> {
+        let store = match config {
+            Some(path) => YoriConfig::load(Path::new(&path)),
+            None => YoriConfig::load_default(),
+        }
+        .and_then(|c| c.transcript_config())
+        .and_then(TranscriptStore::open)
+        .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        Ok(PyTranscriptStore {
+            store: Arc::new(store),
+        })
+    }
+
+    /// Turns of one session, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - Session id from an audit event
+    ///
+    /// # Returns
+    ///
+    /// List of dicts with `session_id`, `request_id`, `timestamp`,
+    /// `tenant`, `client_ip`, `endpoint`, `model`, `prompt` and
+    /// `completion`
+    fn session(&self, py: Python, session_id: &str) -> PyResult<PyObject> {
+        let turns = py
+            .allow_threads(|| self.store.session(session_id))
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))?;
+        let turns =
+            serde_json::to_value(&turns).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        json_to_py(py, &turns)
+    }
+
+    /// Delete turns past the retention period
+    ///
+    /// # Returns
+    ///
+    /// Number of turns deleted
+    fn prune_expired(&self, py: Python) -> PyResult<u64> {
+        py.allow_threads(|| self.store.prune_expired())
+            .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::PiiKind;
+
+    fn turn(tenant: &str, prompt: &str) -> TranscriptTurn {
+        TranscriptTurn {
+            session_id: "chat-1".to_string(),
+            request_id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            tenant: tenant.to_string(),
+            client_ip: "192.168.1.50".to_string(),
+            endpoint: "api.openai.com".to_string(),
+            model: Some("gpt-4o".to_string()),
+            prompt: prompt.to_string(),
+            completion: Some("Photosynthesis turns light into sugar.".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_opted_in_turns_sealed_and_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TranscriptStore::open(TranscriptConfig {
+            database: dir.path().join("transcripts.db"),
+            vault_key: dir.path().join("vault.key"),
+            tenants: vec!["upstairs".to_string()],
+            redaction: RedactionConfig::detectors(&[PiiKind::Email]),
+            ..TranscriptConfig::default()
+        })
+        .unwrap();
+
+        let prompt = "Explain photosynthesis, then email it to timmy@example.com";
+        assert!(store.record(&turn("upstairs", prompt)).unwrap());
+        assert!(!store.record(&turn("downstairs", "not recorded")).unwrap());
+
+        let turns = store.session("chat-1").unwrap();
+        assert_eq!(turns.len(), 1);
+        assert!(!turns[0].prompt.contains("timmy@example.com"));
+        assert!(turns[0].prompt.starts_with("Explain photosynthesis"));
+        assert_eq!(
+            store
+                .prune_before(Utc::now() + chrono::Duration::minutes(1))
+                .unwrap(),
+            1
+        );
+        assert!(store.session("chat-1").unwrap().is_empty());
+
+        // Nothing readable on disk
+        drop(store);
+        let raw = std::fs::read(dir.path().join("transcripts.db")).unwrap();
+        let wal = std::fs::read(dir.path().join("transcripts.db-wal")).unwrap_or_default();
+        for bytes in [raw, wal] {
+            assert!(!bytes
+                .windows(b"photosynthesis".len())
+                .any(|w| w == b"photosynthesis"));
+        }
+    }
+}
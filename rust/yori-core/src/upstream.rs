@@ -0,0 +1,336 @@
+//! Upstream failure handling: retries with backoff and circuit breaking
+//!
+//! When a provider has an outage, every request to it hangs until the
+//! connection times out, and a household's worth of retrying apps can tie
+//! up the whole worker pool. Two mechanisms keep the proxy responsive:
+//!
+//! - Idempotent requests (GET, HEAD, OPTIONS, PUT, DELETE) that fail with
+//!   a connection error or a 502/503/504 are retried with exponential
+//!   backoff ([`RetryPolicy`]). Completions are POSTs and are never
+//!   retried by the proxy: the client decides whether to pay twice.
+//! - Each endpoint has a circuit breaker ([`CircuitBreaker`]). After
+//!   `failure_threshold` consecutive upstream errors it opens, and requests
+//!   to that endpoint are answered straight away with a 502 JSON error
+//!   ([`upstream_error_body`]) until the cooldown passes. Then a single
+//!   probe request is let through; success closes the circuit, failure
+//!   opens it for another cooldown.
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default attempts per idempotent request (1 = no retries)
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+
+/// Default consecutive failures that open a circuit
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Default time a circuit stays open before a probe is allowed
+pub const DEFAULT_COOLDOWN_SECS: u64 = 30;
+
+/// Retry schedule for idempotent upstream requests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first (1 disables retries)
+    pub max_attempts: u32,
+
+    /// Wait before the first retry
+    pub initial_backoff: Duration,
+
+    /// Upper bound on the wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_RETRY_ATTEMPTS,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (1-based), doubling each time
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32 << retry.saturating_sub(1).min(16);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Whether a request with this method may be retried at all
+    pub fn allows(&self, method: &str) -> bool {
+        self.max_attempts > 1 && is_idempotent(method)
+    }
+
+    /// Run `attempt` until it succeeds, fails with a non-retryable outcome
+    /// or runs out of attempts
+    ///
+    /// `retryable` decides whether an outcome is worth another try (e.g.
+    /// a connection error or a 503). Non-idempotent methods get one
+    /// attempt.
+    pub async fn run<T, F, Fut>(
+        &self,
+        method: &str,
+        mut attempt: F,
+        retryable: impl Fn(&T) -> bool,
+    ) -> T
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let attempts = if self.allows(method) {
+            self.max_attempts
+        } else {
+            1
+        };
+        let mut n = 1;
+        loop {
+            let outcome = attempt(n).await;
+            if n >= attempts || !retryable(&outcome) {
+                return outcome;
+            }
+            tokio::time::sleep(self.backoff(n)).await;
+            n += 1;
+        }
+    }
+}
+
+/// Whether an HTTP method is idempotent (safe to send again)
+pub fn is_idempotent(method: &str) -> bool {
+    matches!(
+        method.to_ascii_uppercase().as_str(),
+        "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE"
+    )
+}
+
+/// Whether an upstream status counts as an upstream failure
+pub fn is_upstream_failure(status: u16) -> bool {
+    matches!(status, 502..=504)
+}
+
+/// Circuit breaker settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that open the circuit (0 disables the breaker)
+    pub failure_threshold: u32,
+
+    /// Time the circuit stays open before a probe request is let through
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+        }
+    }
+}
+
+/// State of one endpoint's circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow; counts consecutive failures
+    Closed { failures: u32 },
+
+    /// Requests are refused until `until`
+    Open { until: Instant },
+
+    /// One probe request is in flight
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// State name for status APIs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed { .. } => "closed",
+            CircuitState::Open { .. } => "open",
+            CircuitState::HalfOpen => "half-open",
+        }
+    }
+}
+
+/// Per-endpoint circuit breakers
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<String, CircuitState>>,
+}
+
+impl CircuitBreaker {
+    /// Create breakers with the given settings
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether a request to `endpoint` may be sent
+    ///
+    /// Returns the seconds until the circuit allows a probe when it's open.
+    /// After the cooldown, the first caller becomes the probe and later
+    /// callers are refused until it reports back.
+    pub fn check(&self, endpoint: &str, now: Instant) -> Result<(), u64> {
+        if self.config.failure_threshold == 0 {
+            return Ok(());
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(state) = circuits.get_mut(endpoint) else {
+            return Ok(());
+        };
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } if now >= until => {
+                *state = CircuitState::HalfOpen;
+                Ok(())
+            }
+            CircuitState::Open { until } => Err(until.duration_since(now).as_secs().max(1)),
+            CircuitState::HalfOpen => Err(1),
+        }
+    }
+
+    /// Record a successful upstream response, closing the circuit
+    pub fn record_success(&self, endpoint: &str) {
+        self.circuits.lock().unwrap().remove(endpoint);
+    }
+
+    /// Record an upstream failure, returning true if it opened the circuit
+    pub fn record_failure(&self, endpoint: &str, now: Instant) -> bool {
+        if self.config.failure_threshold == 0 {
+            return false;
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let state = circuits
+            .entry(endpoint.to_string())
+            .or_insert(CircuitState::Closed { failures: 0 });
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            // A failed probe (or a straggler from before opening) reopens
+            CircuitState::HalfOpen | CircuitState::Open { .. } => self.config.failure_threshold,
+        };
+        if failures >= self.config.failure_threshold {
+            let reopened = matches!(*state, CircuitState::Open { .. });
+            *state = CircuitState::Open {
+                until: now + self.config.cooldown,
+            };
+            !reopened
+        } else {
+            *state = CircuitState::Closed { failures };
+            false
+        }
+    }
+
+    /// Endpoints whose circuit isn't closed, with their state
+    pub fn tripped(&self) -> Vec<(String, CircuitState)> {
+        let mut tripped: Vec<_> = self
+            .circuits
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| !matches!(state, CircuitState::Closed { .. }))
+            .map(|(endpoint, state)| (endpoint.clone(), *state))
+            .collect();
+        tripped.sort_by(|a, b| a.0.cmp(&b.0));
+        tripped
+    }
+}
+
+/// JSON body of the 502 sent when an upstream is failing
+pub fn upstream_error_body(
+    endpoint: &str,
+    reason: &str,
+    retry_after_secs: Option<u64>,
+) -> serde_json::Value {
+    json!({
+        "error": {
+            "type": "upstream_unavailable",
+            "message": format!("{} is not responding: {}", endpoint, reason),
+            "endpoint": endpoint,
+            "retry_after_seconds": retry_after_secs,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(2));
+        assert!(policy.allows("get"));
+        assert!(!policy.allows("POST"));
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests_only() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let counter = AtomicU32::new(0);
+        let calls = &counter;
+        let status = policy
+            .run(
+                "GET",
+                move |_| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    503u16
+                },
+                |status| is_upstream_failure(*status),
+            )
+            .await;
+        assert_eq!((status, calls.load(Ordering::SeqCst)), (503, 3));
+
+        calls.store(0, Ordering::SeqCst);
+        policy
+            .run(
+                "POST",
+                move |_| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    503u16
+                },
+                |status| is_upstream_failure(*status),
+            )
+            .await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_circuit_opens_probes_and_closes() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+        });
+        let t0 = Instant::now();
+        assert!(!breaker.record_failure("api.openai.com", t0));
+        assert!(!breaker.record_failure("api.openai.com", t0));
+        assert!(breaker.record_failure("api.openai.com", t0));
+        assert_eq!(breaker.check("api.openai.com", t0), Err(30));
+        assert_eq!(breaker.check("api.anthropic.com", t0), Ok(()));
+
+        // After the cooldown one probe goes through; a failed probe reopens
+        let t1 = t0 + Duration::from_secs(31);
+        assert_eq!(breaker.check("api.openai.com", t1), Ok(()));
+        assert_eq!(breaker.check("api.openai.com", t1), Err(1));
+        breaker.record_failure("api.openai.com", t1);
+        assert!(breaker.check("api.openai.com", t1).is_err());
+
+        let t2 = t1 + Duration::from_secs(31);
+        assert_eq!(breaker.check("api.openai.com", t2), Ok(()));
+        breaker.record_success("api.openai.com");
+        assert_eq!(breaker.check("api.openai.com", t2), Ok(()));
+        assert!(breaker.tripped().is_empty());
+    }
+}
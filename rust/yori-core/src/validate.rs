@@ -0,0 +1,221 @@
+//! Policy validation without loading
+//!
+//! The web policy editor checks a policy before saving it, so a typo never
+//! reaches the policy directory (where the watcher would reject the whole
+//! reload). [`crate::PolicyEngine::validate_policy`] compiles the edited
+//! source together with the other policies on disk, replacing the file of
+//! the same name, and reports problems as editor-friendly diagnostics:
+//!
+//! ```python
+//! result = engine.validate_policy("bedtime", source)
+//! for d in result["diagnostics"]:
+//!     print(f'{d["line"]}:{d["column"]} {d["severity"]}: {d["message"]}')
+//! ```
+//!
+//! Nothing is loaded: the running policies and decision cache are left
+//! alone whatever the outcome.
+
+use serde::Serialize;
+
+use crate::clock;
+use crate::explain::RuleIndex;
+use crate::policytest::is_test_file;
+
+/// Rules that make a package take part in decisions
+pub(crate) const DECISION_RULES: &[&str] = &["allow", "deny", "reason", "mode", "obligations"];
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The policy would fail to load
+    Error,
+
+    /// The policy loads but probably doesn't do what was meant
+    Warning,
+}
+
+/// One problem found in a policy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// Position in the validated source (1-based); None when the problem
+    /// has no position or lies in another file
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+
+    pub message: String,
+}
+
+/// Outcome of validating one policy source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyValidation {
+    /// True if the policy would load (warnings allowed)
+    pub valid: bool,
+
+    /// Package the source declares, once it parses
+    pub package: Option<String>,
+
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compile `source` as the policy file `file` alongside `others` (the
+/// other `(file, source)` pairs of the policy directory)
+pub(crate) fn validate(file: &str, source: &str, others: &[(String, String)]) -> PolicyValidation {
+    let mut validation = PolicyValidation {
+        valid: false,
+        package: None,
+        diagnostics: Vec::new(),
+    };
+    let mut engine = regorus::Engine::new();
+    if let Err(e) = clock::register(&mut engine) {
+        validation
+            .diagnostics
+            .push(error(file, &format!("{:#}", e)));
+        return validation;
+    }
+    // Files that don't compile on their own are already rejected by reloads
+    for (other, other_source) in others.iter().filter(|(other, _)| other != file) {
+        let _ = add_policy(&mut engine, other, other_source);
+    }
+
+    let package = match add_policy(&mut engine, file, source) {
+        Ok(package) => package.trim_start_matches("data.").to_string(),
+        Err(e) => {
+            validation.diagnostics.push(error(file, &e));
+            return validation;
+        }
+    };
+    if let Err(e) = engine.eval_query("true".to_string(), false) {
+        validation.diagnostics.push(error(file, &e.to_string()));
+        validation.package = Some(package);
+        return validation;
+    }
+
+    let path = std::path::Path::new(file);
+    if !is_test_file(path) {
+        let mut index = RuleIndex::default();
+        index.add_source(file, source);
+        for (other, other_source) in others.iter().filter(|(other, _)| other != file) {
+            index.add_source(other, other_source);
+        }
+        if DECISION_RULES
+            .iter()
+            .all(|rule| index.locate(&package, rule).is_empty())
+        {
+            validation.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                line: package_line(source),
+                column: Some(1),
+                message: format!(
+                    "package {} defines none of {}, so it takes no part in decisions",
+                    package,
+                    DECISION_RULES.join(", ")
+                ),
+            });
+        }
+    }
+    validation.valid = true;
+    validation.package = Some(package);
+    validation
+}
+
+/// Add a source as Rego v1, falling back to v0; the v1 error is returned
+/// if both fail
+fn add_policy(engine: &mut regorus::Engine, file: &str, source: &str) -> Result<String, String> {
+    engine.set_rego_v0(false);
+    match engine.add_policy(file.to_string(), source.to_string()) {
+        Ok(package) => Ok(package),
+        Err(v1_error) => {
+            engine.set_rego_v0(true);
+            engine
+                .add_policy(file.to_string(), source.to_string())
+                .map_err(|_| v1_error.to_string())
+        }
+    }
+}
+
+/// Diagnostic for a Regorus error, positioned if it points into `file`
+///
+/// Regorus errors read `--> file:line:col`, a source excerpt and a final
+/// `error: message` line.
+fn error(file: &str, text: &str) -> Diagnostic {
+    let location = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("--> "))
+        .and_then(|location| {
+            let mut parts = location.rsplitn(3, ':');
+            let column = parts.next()?.trim().parse::<usize>().ok()?;
+            let line = parts.next()?.trim().parse::<usize>().ok()?;
+            Some((parts.next()?.to_string(), line, column))
+        });
+    let last = text.trim_end().lines().last().unwrap_or_default().trim();
+    let message = last.strip_prefix("error: ").unwrap_or(last).to_string();
+    match location {
+        Some((location_file, line, column)) if location_file == file => Diagnostic {
+            severity: Severity::Error,
+            line: Some(line),
+            column: Some(column),
+            message,
+        },
+        Some((location_file, line, column)) => Diagnostic {
+            severity: Severity::Error,
+            line: None,
+            column: None,
+            message: format!("{}:{}:{}: {}", location_file, line, column, message),
+        },
+        None => Diagnostic {
+            severity: Severity::Error,
+            line: None,
+            column: None,
+            message: text.trim().to_string(),
+        },
+    }
+}
+
+/// Line of the `package` statement
+fn package_line(source: &str) -> Option<usize> {
+    source
+        .lines()
+        .position(|line| line.trim_start().starts_with("package "))
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_position_of_errors() {
+        let valid = validate(
+            "bedtime.rego",
+            "package yori.bedtime\n\ndefault allow := true\n",
+            &[],
+        );
+        assert!(valid.valid);
+        assert_eq!(valid.package.as_deref(), Some("yori.bedtime"));
+        assert!(valid.diagnostics.is_empty());
+
+        let broken = validate(
+            "bedtime.rego",
+            "package yori.bedtime\n\nallow if {\n    input.hour <\n}\n",
+            &[],
+        );
+        assert!(!broken.valid);
+        let diagnostic = &broken.diagnostics[0];
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert!(diagnostic.line.is_some_and(|line| line >= 3));
+        assert!(diagnostic.column.is_some());
+        assert!(!diagnostic.message.contains("-->"));
+
+        let helpers = validate(
+            "helpers.rego",
+            "package yori.helpers\n\nis_late if input.hour >= 21\n",
+            &[],
+        );
+        assert!(helpers.valid);
+        assert_eq!(helpers.diagnostics[0].severity, Severity::Warning);
+        assert_eq!(helpers.diagnostics[0].line, Some(1));
+    }
+}
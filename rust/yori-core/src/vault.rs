@@ -0,0 +1,172 @@
+//! Local key vault
+//!
+//! Holds the router's master secret and derives purpose-specific keys from
+//! it, so encrypted stores (the audit database, API keys, backups) never
+//! share a key and never need their own passphrase prompts on a headless
+//! router.
+//!
+//! The master key is 32 random bytes stored hex-encoded in a root-only file
+//! (`/var/db/yori/vault.key` by default). Purpose keys are derived with
+//! HKDF-SHA256 using `yori:<purpose>` as the info string.
+//!
+//! Keep the key file off the data partition (or on removable media) if the
+//! threat model includes someone walking off with the whole router.
+
+use anyhow::{bail, Context, Result};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Default location of the master key file
+pub const DEFAULT_VAULT_PATH: &str = "/var/db/yori/vault.key";
+
+/// Purpose string for the audit database key
+pub const AUDIT_DB_PURPOSE: &str = "audit-db";
+
+/// Purpose string for the provider API key store
+pub const API_KEYS_PURPOSE: &str = "api-keys";
+
+/// Purpose string for the conversation transcript store
+pub const TRANSCRIPTS_PURPOSE: &str = "transcripts";
+
+const KEY_LEN: usize = 32;
+
+/// Master key holder
+pub struct Vault {
+    path: PathBuf,
+    master: Zeroizing<[u8; KEY_LEN]>,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print key material
+        f.debug_struct("Vault").field("path", &self.path).finish()
+    }
+}
+
+impl Vault {
+    /// Load the master key from `path`, generating a new one if absent
+    pub fn open_or_create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return Self::open(path);
+        }
+
+        let mut master = Zeroizing::new([0u8; KEY_LEN]);
+        getrandom::getrandom(master.as_mut())
+            .map_err(|e| anyhow::anyhow!("failed to generate vault key: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let encoded = Zeroizing::new(hex::encode(master.as_ref()));
+        let mut file = create_private(path)
+            .with_context(|| format!("failed to create vault key {}", path.display()))?;
+        file.write_all(encoded.as_bytes())?;
+        file.sync_all()?;
+
+        tracing::info!("Generated new vault key at {}", path.display());
+        Ok(Vault {
+            path: path.to_path_buf(),
+            master,
+        })
+    }
+
+    /// Load an existing master key
+    pub fn open(path: &Path) -> Result<Self> {
+        let encoded = Zeroizing::new(
+            fs::read_to_string(path)
+                .with_context(|| format!("failed to read vault key {}", path.display()))?,
+        );
+        let mut master = Zeroizing::new([0u8; KEY_LEN]);
+        hex::decode_to_slice(encoded.trim(), master.as_mut())
+            .with_context(|| format!("vault key {} is corrupt", path.display()))?;
+        if master.iter().all(|b| *b == 0) {
+            bail!("vault key {} is empty", path.display());
+        }
+
+        Ok(Vault {
+            path: path.to_path_buf(),
+            master,
+        })
+    }
+
+    /// Path of the master key file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Derive a 256-bit key for a specific purpose
+    pub fn derive_key(&self, purpose: &str) -> Zeroizing<[u8; KEY_LEN]> {
+        let hkdf = Hkdf::<Sha256>::new(None, self.master.as_ref());
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        hkdf.expand(format!("yori:{}", purpose).as_bytes(), key.as_mut())
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        key
+    }
+
+    /// Raw-key literal for SQLCipher's `PRAGMA key` (`x'<64 hex digits>'`)
+    ///
+    /// Passing a raw key skips SQLCipher's PBKDF2 step, which is pointless
+    /// for an already random key and slow on router CPUs.
+    pub fn sqlcipher_key(&self, purpose: &str) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "x'{}'",
+            hex::encode(self.derive_key(purpose).as_ref())
+        ))
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_persists_and_derives_distinct_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.key");
+
+        let vault = Vault::open_or_create(&path).unwrap();
+        let audit = vault.derive_key(AUDIT_DB_PURPOSE);
+        assert_ne!(*audit, *vault.derive_key("backup"));
+
+        let reopened = Vault::open_or_create(&path).unwrap();
+        assert_eq!(*reopened.derive_key(AUDIT_DB_PURPOSE), *audit);
+        assert!(reopened.sqlcipher_key(AUDIT_DB_PURPOSE).starts_with("x'"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_key_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.key");
+        fs::write(&path, "not hex").unwrap();
+        assert!(Vault::open(&path).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Policy directory watcher for hot-reload
+//!
+//! Editors and `scp` tend to produce a burst of filesystem events per save
+//! (truncate, write, rename, chmod), so changes to `.rego`/`.wasm` files and
+//! `.json`/`.yaml` data documents (and bundle signatures) are debounced: the reload runs once the
+//! directory has been quiet for a short while. The reload itself is left to
+//! the caller, which builds the new policy set completely before swapping it
+//! in.
+
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::data::DATA_EXTENSIONS;
+use crate::signing::is_bundle_signature;
+
+/// Policy file extensions that trigger a reload
+const POLICY_EXTENSIONS: &[&str] = &["rego", "wasm"];
+
+/// Watches a policy directory and calls back after changes settle
+///
+/// Watching stops when the watcher is dropped.
+pub struct PolicyWatcher {
+    // Held for its Drop; closing it ends the debounce thread
+    _watcher: RecommendedWatcher,
+}
+
+impl PolicyWatcher {
+    /// Start watching `policy_dir` (recursively)
+    ///
+    /// `on_change` runs on a background thread with the policy files that
+    /// changed during the burst.
+    pub fn new(
+        policy_dir: &Path,
+        debounce: Duration,
+        mut on_change: impl FnMut(Vec<PathBuf>) + Send + 'static,
+    ) -> Result<Self> {
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher
+            .watch(policy_dir, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch {}", policy_dir.display()))?;
+
+        std::thread::Builder::new()
+            .name("yori-policy-watch".to_string())
+            .spawn(move || {
+                while let Ok(event) = rx.recv() {
+                    let mut changed = Vec::new();
+                    collect_policy_paths(event, &mut changed);
+                    if changed.is_empty() {
+                        continue;
+                    }
+                    // Keep absorbing events until the burst is over
+                    loop {
+                        match rx.recv_timeout(debounce) {
+                            Ok(event) => collect_policy_paths(event, &mut changed),
+                            Err(mpsc::RecvTimeoutError::Timeout) => break,
+                            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                        }
+                    }
+                    changed.sort();
+                    changed.dedup();
+                    on_change(changed);
+                }
+            })?;
+
+        Ok(PolicyWatcher { _watcher: watcher })
+    }
+}
+
+fn collect_policy_paths(event: notify::Result<notify::Event>, out: &mut Vec<PathBuf>) {
+    let Ok(event) = event else {
+        return;
+    };
+    // Reads and metadata-only changes don't alter policy content
+    if event.kind.is_access() {
+        return;
+    }
+    out.extend(event.paths.into_iter().filter(|path| is_policy_file(path)));
+}
+
+/// Whether a path is a policy source, data document or bundle signature
+/// the engine loads
+pub fn is_policy_file(path: &Path) -> bool {
+    is_bundle_signature(path)
+        || path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| POLICY_EXTENSIONS.contains(&e) || DATA_EXTENSIONS.contains(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_are_debounced() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let _watcher = PolicyWatcher::new(dir.path(), Duration::from_millis(100), move |paths| {
+            tx.send(paths).unwrap();
+        })
+        .unwrap();
+
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+        for i in 0..3 {
+            std::fs::write(
+                dir.path().join("bedtime.rego"),
+                format!("package yori.v{i}\n"),
+            )
+            .unwrap();
+        }
+
+        let changed = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(changed.iter().all(|p| is_policy_file(p)));
+        assert!(changed.iter().any(|p| p.ends_with("bedtime.rego")));
+        // One burst, one callback
+        assert!(rx.recv_timeout(Duration::from_millis(400)).is_err());
+    }
+}
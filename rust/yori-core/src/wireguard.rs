@@ -0,0 +1,397 @@
+//! WireGuard peer identity
+//!
+//! Kids' phones on cellular reach the home network through the family
+//! WireGuard tunnel, so their requests arrive from tunnel addresses rather
+//! than LAN DHCP leases. This module parses WireGuard configuration and
+//! live status (`wg show all dump`) to map a tunnel IP back to a named
+//! peer, keeping policies and audit records accurate for off-LAN usage.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use ipnet::IpNet;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::process::Command;
+
+/// A WireGuard peer and the addresses it may originate traffic from
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireGuardPeer {
+    /// Tunnel interface (e.g., "wg0")
+    pub interface: String,
+
+    /// Peer public key (base64)
+    pub public_key: String,
+
+    /// Human-readable name (e.g., "Timmy's phone"), if known
+    pub name: Option<String>,
+
+    /// Tunnel addresses routed to this peer
+    pub allowed_ips: Vec<IpNet>,
+
+    /// Last seen outer endpoint (e.g., "203.0.113.5:51820")
+    pub endpoint: Option<String>,
+
+    /// Time of the most recent handshake
+    pub latest_handshake: Option<DateTime<Utc>>,
+}
+
+/// Tunnel address to peer lookup table
+///
+/// # Example (Python)
+///
+/// ```python
+/// import yori_core
+///
+/// peers = yori_core.WireGuardPeers()
+/// peers.load_config(open("/usr/local/etc/wireguard/wg0.conf").read(), "wg0")
+/// peers.refresh()  # merge live status from `wg show all dump`
+///
+/// peer = peers.lookup("10.8.0.3")
+/// if peer is not None:
+///     print(peer["name"])  # "Timmy's phone"
+/// ```
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct WireGuardPeers {
+    peers: Vec<WireGuardPeer>,
+    names: HashMap<String, String>,
+}
+
+impl WireGuardPeers {
+    /// Parse a wg-quick style config and merge its `[Peer]` sections
+    ///
+    /// Names are taken from a `# Name = ...` (or `# Name: ...`) comment
+    /// directly above the `[Peer]` header or at the top of the section, the
+    /// convention used by most WireGuard GUIs.
+    pub fn merge_config(&mut self, config: &str, interface: &str) -> Result<usize> {
+        let mut parsed = Vec::new();
+        let mut pending_name: Option<String> = None;
+        let mut current: Option<WireGuardPeer> = None;
+
+        for raw in config.lines() {
+            let line = raw.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(name) = parse_name_comment(comment) {
+                    match current.as_mut() {
+                        Some(peer) if peer.name.is_none() && peer.public_key.is_empty() => {
+                            peer.name = Some(name)
+                        }
+                        _ => pending_name = Some(name),
+                    }
+                }
+                continue;
+            }
+
+            if line.starts_with('[') {
+                parsed.extend(current.take());
+                if line.eq_ignore_ascii_case("[peer]") {
+                    current = Some(WireGuardPeer {
+                        interface: interface.to_string(),
+                        public_key: String::new(),
+                        name: pending_name.take(),
+                        allowed_ips: Vec::new(),
+                        endpoint: None,
+                        latest_handshake: None,
+                    });
+                }
+                pending_name = None;
+                continue;
+            }
+
+            let (Some(peer), Some((key, value))) = (current.as_mut(), line.split_once('=')) else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "publickey" => peer.public_key = value.to_string(),
+                "allowedips" => {
+                    for net in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                        peer.allowed_ips.push(
+                            net.parse()
+                                .with_context(|| format!("invalid AllowedIPs entry '{}'", net))?,
+                        );
+                    }
+                }
+                "endpoint" => peer.endpoint = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        parsed.extend(current.take());
+
+        if let Some(peer) = parsed.iter().find(|p| p.public_key.is_empty()) {
+            bail!(
+                "[Peer] section{} has no PublicKey",
+                peer.name
+                    .as_ref()
+                    .map(|n| format!(" '{}'", n))
+                    .unwrap_or_default()
+            );
+        }
+
+        let count = parsed.len();
+        for peer in parsed {
+            self.upsert(peer);
+        }
+        Ok(count)
+    }
+
+    /// Parse `wg show all dump` output and merge live peer status
+    pub fn merge_dump(&mut self, dump: &str) -> Result<usize> {
+        let mut count = 0;
+        for line in dump.lines().filter(|l| !l.trim().is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            // Interface lines have 5 fields, peer lines 9
+            if fields.len() != 9 {
+                continue;
+            }
+
+            let allowed_ips = match fields[4] {
+                "(none)" => Vec::new(),
+                list => list
+                    .split(',')
+                    .map(|n| n.parse())
+                    .collect::<Result<Vec<IpNet>, _>>()
+                    .with_context(|| format!("invalid allowed-ips '{}'", list))?,
+            };
+            let handshake: i64 = fields[5].parse().unwrap_or(0);
+
+            self.upsert(WireGuardPeer {
+                interface: fields[0].to_string(),
+                public_key: fields[1].to_string(),
+                name: None,
+                allowed_ips,
+                endpoint: (fields[3] != "(none)").then(|| fields[3].to_string()),
+                latest_handshake: (handshake > 0)
+                    .then(|| Utc.timestamp_opt(handshake, 0).single())
+                    .flatten(),
+            });
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Merge live status from the local `wg` tool
+    pub fn refresh_from_system(&mut self) -> Result<usize> {
+        let output = Command::new("wg")
+            .args(["show", "all", "dump"])
+            .output()
+            .context("failed to run `wg show all dump`")?;
+        if !output.status.success() {
+            bail!(
+                "`wg show all dump` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        self.merge_dump(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Assign a name to a peer by public key (overrides config comments)
+    pub fn set_peer_name(&mut self, public_key: &str, name: &str) {
+        self.names.insert(public_key.to_string(), name.to_string());
+        if let Some(peer) = self.peers.iter_mut().find(|p| p.public_key == public_key) {
+            peer.name = Some(name.to_string());
+        }
+    }
+
+    /// Find the peer owning a tunnel address (most specific AllowedIPs wins)
+    pub fn peer_for_ip(&self, ip: IpAddr) -> Option<&WireGuardPeer> {
+        let ip = ip.to_canonical();
+        self.peers
+            .iter()
+            .flat_map(|p| p.allowed_ips.iter().map(move |n| (p, n)))
+            .filter(|(_, net)| net.prefix_len() > 0 && net.contains(&ip))
+            .max_by_key(|(_, net)| net.prefix_len())
+            .map(|(p, _)| p)
+    }
+
+    /// Name of the peer owning a tunnel address, if known
+    pub fn name_for_ip(&self, ip: IpAddr) -> Option<&str> {
+        self.peer_for_ip(ip).and_then(|p| p.name.as_deref())
+    }
+
+    /// All known peers
+    pub fn peers(&self) -> &[WireGuardPeer] {
+        &self.peers
+    }
+
+    /// Insert or update a peer, keeping known names and merging status
+    fn upsert(&mut self, mut peer: WireGuardPeer) {
+        if let Some(name) = self.names.get(&peer.public_key) {
+            peer.name = Some(name.clone());
+        }
+
+        match self
+            .peers
+            .iter_mut()
+            .find(|p| p.public_key == peer.public_key)
+        {
+            Some(existing) => {
+                existing.interface = peer.interface;
+                if peer.name.is_some() {
+                    existing.name = peer.name;
+                }
+                if !peer.allowed_ips.is_empty() {
+                    existing.allowed_ips = peer.allowed_ips;
+                }
+                if peer.endpoint.is_some() {
+                    existing.endpoint = peer.endpoint;
+                }
+                if peer.latest_handshake.is_some() {
+                    existing.latest_handshake = peer.latest_handshake;
+                }
+            }
+            None => self.peers.push(peer),
+        }
+    }
+}
+
+fn parse_name_comment(comment: &str) -> Option<String> {
+    let comment = comment.trim();
+    let rest = comment
+        .get(..4)
+        .filter(|p| p.eq_ignore_ascii_case("name"))
+        .map(|_| comment[4..].trim_start())?;
+    let name = rest
+        .strip_prefix('=')
+        .or_else(|| rest.strip_prefix(':'))?
+        .trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[pymethods]
+impl WireGuardPeers {
+    /// Create an empty peer table
+    #[new]
+    fn new() -> Self {
+        WireGuardPeers::default()
+    }
+
+    /// Load `[Peer]` sections from a wg-quick style config
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Config file contents
+    /// * `interface` - Interface the config belongs to (default: "wg0")
+    ///
+    /// # Returns
+    ///
+    /// Number of peers parsed
+    #[pyo3(signature = (config, interface="wg0".to_string()))]
+    fn load_config(&mut self, config: String, interface: String) -> PyResult<usize> {
+        self.merge_config(&config, &interface)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Merge `wg show all dump` output
+    ///
+    /// # Returns
+    ///
+    /// Number of peers parsed
+    fn load_dump(&mut self, dump: String) -> PyResult<usize> {
+        self.merge_dump(&dump)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Refresh live peer status by running `wg show all dump`
+    fn refresh(&mut self) -> PyResult<usize> {
+        self.refresh_from_system()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Name a peer by its public key
+    fn set_name(&mut self, public_key: String, name: String) {
+        self.set_peer_name(&public_key, &name);
+    }
+
+    /// Look up the peer owning a tunnel address
+    ///
+    /// # Returns
+    ///
+    /// Dictionary with `interface`, `public_key`, `name`, `endpoint` and
+    /// `latest_handshake` (ISO 8601), or None
+    fn lookup(&self, py: Python, client_ip: String) -> PyResult<Option<PyObject>> {
+        let Some(peer) = client_ip.parse().ok().and_then(|ip| self.peer_for_ip(ip)) else {
+            return Ok(None);
+        };
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("interface", &peer.interface)?;
+        dict.set_item("public_key", &peer.public_key)?;
+        dict.set_item("name", &peer.name)?;
+        dict.set_item("endpoint", &peer.endpoint)?;
+        dict.set_item(
+            "latest_handshake",
+            peer.latest_handshake.map(|t| t.to_rfc3339()),
+        )?;
+        Ok(Some(dict.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+[Interface]
+PrivateKey = aGVsbG8=
+Address = 10.8.0.1/24
+
+# Name = Timmy's phone
+[Peer]
+PublicKey = dGltbXk=
+AllowedIPs = 10.8.0.3/32
+
+[Peer]
+# Name: Mum's laptop
+PublicKey = bXVt
+AllowedIPs = 10.8.0.4/32, fd08::4/128
+";
+
+    #[test]
+    fn test_config_names_and_lookup() {
+        let mut peers = WireGuardPeers::default();
+        assert_eq!(peers.merge_config(CONFIG, "wg0").unwrap(), 2);
+
+        assert_eq!(
+            peers.name_for_ip("10.8.0.3".parse().unwrap()),
+            Some("Timmy's phone")
+        );
+        assert_eq!(
+            peers.name_for_ip("fd08::4".parse().unwrap()),
+            Some("Mum's laptop")
+        );
+        assert!(peers.peer_for_ip("10.8.0.9".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_dump_merges_status_and_keeps_names() {
+        let mut peers = WireGuardPeers::default();
+        peers.merge_config(CONFIG, "wg0").unwrap();
+        peers.set_peer_name("bmV3", "Guest tablet");
+
+        let dump = "wg0\taGVsbG8=\tcHVi\t51820\toff\n\
+                    wg0\tdGltbXk=\t(none)\t203.0.113.5:4500\t10.8.0.3/32\t1700000000\t100\t200\t25\n\
+                    wg0\tbmV3\t(none)\t(none)\t10.8.0.7/32\t0\t0\t0\toff\n";
+        assert_eq!(peers.merge_dump(dump).unwrap(), 2);
+
+        let timmy = peers.peer_for_ip("10.8.0.3".parse().unwrap()).unwrap();
+        assert_eq!(timmy.name.as_deref(), Some("Timmy's phone"));
+        assert_eq!(timmy.endpoint.as_deref(), Some("203.0.113.5:4500"));
+        assert!(timmy.latest_handshake.is_some());
+        assert_eq!(
+            peers.name_for_ip("10.8.0.7".parse().unwrap()),
+            Some("Guest tablet")
+        );
+    }
+
+    #[test]
+    fn test_peer_without_key_is_rejected() {
+        let mut peers = WireGuardPeers::default();
+        assert!(peers
+            .merge_config("[Peer]\nAllowedIPs = 10.8.0.5/32\n", "wg0")
+            .is_err());
+    }
+}
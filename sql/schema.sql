@@ -4,7 +4,7 @@
 CREATE TABLE IF NOT EXISTS audit_events (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     timestamp TEXT NOT NULL,           -- ISO 8601
-    event_type TEXT NOT NULL,          -- 'request' | 'response' | 'block'
+    event_type TEXT NOT NULL,          -- 'request' | 'response' | 'block' | 'error' | 'policy_reload' | 'rate_limited' | 'response_block' | 'sensitive_data'
 
     -- Request details
     client_ip TEXT NOT NULL,
@@ -0,0 +1,52 @@
+"""
+Unit tests for the awaitable Rust core wrappers
+"""
+
+import asyncio
+import time
+
+from yori import aio
+
+
+class SlowEngine:
+    """Stands in for PolicyEngine: blocks like a long evaluation"""
+
+    def evaluate(self, input_data, explain=False):
+        time.sleep(0.2)
+        return {"allow": True, "user": input_data["user"], "explain": explain}
+
+    def load_policies(self):
+        return 3
+
+
+class FakeLogger:
+    def query(self, **filters):
+        return {"events": [], "total": 0, **filters}
+
+    def count(self, **filters):
+        return 7
+
+
+async def test_evaluate_does_not_block_event_loop():
+    ticks = 0
+
+    async def ticker():
+        nonlocal ticks
+        while True:
+            await asyncio.sleep(0.01)
+            ticks += 1
+
+    task = asyncio.create_task(ticker())
+    result = await aio.evaluate(SlowEngine(), {"user": "alice"}, explain=True)
+    task.cancel()
+
+    assert result == {"allow": True, "user": "alice", "explain": True}
+    assert ticks >= 5
+
+
+async def test_passthrough_wrappers():
+    assert await aio.load_policies(SlowEngine()) == 3
+    page = await aio.query_audit(FakeLogger(), client_ip="192.168.1.20", limit=10)
+    assert page["client_ip"] == "192.168.1.20"
+    assert page["limit"] == 10
+    assert await aio.count_audit(FakeLogger(), event_type="block") == 7
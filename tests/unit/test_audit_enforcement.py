@@ -8,7 +8,7 @@ import tempfile
 from pathlib import Path
 from datetime import datetime
 
-from yori.audit_enforcement import EnforcementAuditLogger
+from yori.audit_enforcement import GENESIS_HASH, EnforcementAuditLogger, event_hash
 
 
 @pytest.fixture
@@ -304,3 +304,50 @@ class TestEnforcementAuditLogger:
 
         assert row["event_type"] == "custom_event"
         assert row["enforcement_action"] == "alert"
+
+    def test_event_hash_matches_core(self):
+        """Test the chain hash is the one yori_core computes"""
+        # Same record and digest as yori-core's integrity tests
+        record = {
+            "id": 7,
+            "timestamp": "2026-03-01T12:00:00+00:00",
+            "prompt_preview": 'say "hi" – naïve\n',
+            "response_tokens": 300,
+            "policy_name": None,
+            "event_hash": "x",
+            "cost": 0.25,
+        }
+        assert (
+            event_hash(GENESIS_HASH, record)
+            == "64f85dda841e6570655b85b9378b98f974865f11b6adf166df38865e4f596c5a"
+        )
+
+    def test_hash_chain_links_events(self, temp_db):
+        """Test chained events reference the previous event's hash"""
+        logger = EnforcementAuditLogger(temp_db, hash_chain=True)
+
+        first = logger.log_block_event(
+            policy_name="bedtime.rego",
+            client_ip="192.168.1.100",
+            endpoint="api.openai.com",
+            reason="After bedtime",
+        )
+        second = logger.log_block_event(
+            policy_name="homework.rego",
+            client_ip="192.168.1.101",
+            endpoint="api.anthropic.com",
+            reason="Homework hours",
+        )
+
+        conn = sqlite3.connect(str(temp_db))
+        conn.row_factory = sqlite3.Row
+        rows = {
+            row["id"]: dict(row)
+            for row in conn.execute("SELECT * FROM audit_events ORDER BY id")
+        }
+        conn.close()
+
+        assert rows[first]["prev_hash"] == GENESIS_HASH
+        assert rows[second]["prev_hash"] == rows[first]["event_hash"]
+        for row in rows.values():
+            assert row["event_hash"] == event_hash(row["prev_hash"], row)